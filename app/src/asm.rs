@@ -0,0 +1,144 @@
+use crate::options::Options;
+
+use asm6502::Program;
+
+/// What the `asm` subcommand writes, `--format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// The memory image alone, from the lowest address of the program.
+    Bin,
+    /// The load address, then the memory image, like a C64 `.prg` file.
+    Prg,
+    /// Intel HEX records.
+    Ihex,
+}
+
+/// Settings of the `asm` subcommand.
+struct AsmOptions {
+    /// Source file to assemble.
+    source: String,
+    /// File the program is written to, `-o`.
+    output: Option<String>,
+    format: Format,
+    /// The emulator options after `--run`, if the program runs once assembled.
+    run: Option<Vec<String>>,
+}
+
+impl AsmOptions {
+    /// Parses `source.s -o out.prg [--format prg|bin|ihex] [--run [options...]]`, the
+    /// arguments after `asm`.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<AsmOptions, String> {
+        let mut source: Option<String> = None;
+        let mut output: Option<String> = None;
+        let mut format: Format = Format::Bin;
+        let mut run: Option<Vec<String>> = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for `{}`", name))
+            };
+            match arg.as_str() {
+                "-o" | "--out" => output = Some(value(&arg)?),
+                "--format" => {
+                    format = match value(&arg)?.as_str() {
+                        "bin" => Format::Bin,
+                        "prg" => Format::Prg,
+                        "ihex" => Format::Ihex,
+                        other => return Err(format!("Unknown format `{}`", other)),
+                    }
+                }
+                // The rest are for the emulator
+                "--run" => {
+                    run = Some(args.by_ref().collect());
+                    break;
+                }
+                _ if arg.starts_with('-') => return Err(format!("Unknown option `{}`", arg)),
+                _ if source.is_none() => source = Some(arg),
+                _ => return Err(format!("Unexpected argument `{}`", arg)),
+            }
+        }
+
+        if output.is_none() && run.is_none() {
+            return Err("No output file, give `-o <path>` or `--run`".to_string());
+        }
+        Ok(AsmOptions {
+            source: source.ok_or_else(|| "No source file to assemble".to_string())?,
+            output,
+            format,
+            run,
+        })
+    }
+}
+
+/// Runs `asm`: assembles a source file, see `asm6502::assemble()`, and writes the
+/// program in the format asked for.
+///
+/// # Returns
+/// With `--run`, the options of the emulator and the memory image it runs: the bare
+/// machine with the image loaded at its lowest address, which the reset vector points
+/// at unless the program sets it, followed by the options given after `--run`.
+pub fn assemble<I: Iterator<Item = String>>(args: I) -> Result<Option<(Options, Vec<u8>)>, String> {
+    let options: AsmOptions = AsmOptions::parse(args)?;
+    let source: String = std::fs::read_to_string(&options.source)
+        .map_err(|error| format!("cannot read `{}`: {}", options.source, error))?;
+    let program: Program =
+        asm6502::assemble(&source).map_err(|error| format!("{}: {}", options.source, error))?;
+
+    if let Some(path) = &options.output {
+        let bytes: Vec<u8> = match options.format {
+            Format::Bin => program.image(),
+            Format::Prg => program.to_prg(),
+            Format::Ihex => program.to_ihex().into_bytes(),
+        };
+        std::fs::write(path, bytes)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?;
+    }
+
+    let Some(run) = options.run else {
+        return Ok(None);
+    };
+    let args: Vec<String> = [
+        "--load-addr".to_string(),
+        format!("{:#06x}", program.start()),
+        "--auto-vectors".to_string(),
+    ]
+    .into_iter()
+    .chain(run)
+    .collect();
+    let emulator: Options = Options::parse(args.into_iter())?;
+    if let Some(path) = &emulator.rom_path {
+        return Err(format!("`--run` runs the program, not `{}`", path));
+    }
+    Ok(Some((emulator, program.image())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<AsmOptions, String> {
+        AsmOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_format_and_emulator_options() {
+        let options: AsmOptions = parse(&["source.s", "-o", "out.prg", "--format", "prg"]).unwrap();
+        assert_eq!(options.source, "source.s");
+        assert_eq!(options.output.as_deref(), Some("out.prg"));
+        assert_eq!(options.format, Format::Prg);
+        assert!(options.run.is_none());
+
+        let options: AsmOptions = parse(&["source.s", "--run", "--ci", "-o"]).unwrap();
+        assert_eq!(options.format, Format::Bin);
+        assert_eq!(options.output, None);
+        assert_eq!(
+            options.run,
+            Some(vec!["--ci".to_string(), "-o".to_string()])
+        );
+
+        assert!(parse(&["source.s"]).is_err());
+        assert!(parse(&["-o", "out.bin"]).is_err());
+        assert!(parse(&["source.s", "-o", "out.bin", "--format", "elf"]).is_err());
+    }
+}
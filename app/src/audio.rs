@@ -0,0 +1,71 @@
+use machine::AudioSink;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+/// Size of the RIFF/WAVE header written before the samples.
+const HEADER_SIZE: u32 = 44;
+
+/// Writes the audio of the machine to a 16 bit mono WAV file.
+/// The header is completed when the writer is dropped.
+/// This is a recording only: the sound is not played while the machine runs.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<Self> {
+        let mut writer: WavWriter = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_size: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let file: &mut BufWriter<File> = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&self.sample_rate.to_le_bytes())?;
+        file.write_all(&(self.sample_rate * 2).to_le_bytes())?;
+        // 2 bytes per frame, 16 bits per sample
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&self.data_size.to_le_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn write(&mut self, samples: &[f32]) {
+        for sample in samples {
+            let value: i16 = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if self.file.write_all(&value.to_le_bytes()).is_ok() {
+                self.data_size += 2;
+            }
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            println!("Error: cannot finish the audio file: {}", error);
+        }
+    }
+}
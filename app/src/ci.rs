@@ -0,0 +1,105 @@
+use crate::options::Options;
+
+use machine::Machine;
+use mos6502::differential::{compare, Divergence};
+use mos6502::reference::Reference;
+use mos6502::state_hash::StateHash;
+use mos6502::HaltReason;
+
+/// The expected value was found at the result address.
+pub const EXIT_PASS: i32 = 0;
+/// The program stopped but the result address holds another value.
+pub const EXIT_FAIL: i32 = 1;
+/// The program did not stop within the instruction budget.
+pub const EXIT_TIMEOUT: i32 = 2;
+/// The command line could not be parsed.
+pub const EXIT_USAGE: i32 = 3;
+
+/// Runs the program without user interaction until the CPU halts, e.g. at a `JMP` or
+/// branch to itself, or the timeout expires.
+///
+/// # Returns
+/// The process exit code, see `EXIT_PASS`, `EXIT_FAIL` and `EXIT_TIMEOUT`.
+/// Without `--ci` only the state hash is printed, and reaching the timeout is not a failure.
+pub fn run(machine: &mut Machine, options: &Options) -> i32 {
+    machine.cpu_mut().set_trap_detection(true);
+    let mut hash: StateHash = StateHash::new();
+    let mut executed: u64 = 0;
+    while !machine.cpu().is_halted() && executed < options.timeout_instr {
+        machine.step();
+        executed += 1;
+        if let Some(every) = options.state_hash {
+            if executed.is_multiple_of(every) {
+                hash.update(machine.cpu());
+            }
+        }
+    }
+
+    if options.state_hash.is_some() {
+        println!(
+            "State hash: {:#018x} ({} samples over {} instructions)",
+            hash.digest(),
+            hash.samples(),
+            executed
+        );
+    }
+
+    let ci = match &options.ci {
+        Some(ci) => ci,
+        None => return EXIT_PASS,
+    };
+
+    if !machine.cpu().is_halted() {
+        println!("Timed out after {} instructions", executed);
+        return EXIT_TIMEOUT;
+    }
+
+    let result: u8 = machine.memory().borrow().read(ci.result_addr);
+    if let Some(pc) = machine.cpu().trapped_at() {
+        println!("Trapped at {:#06x} after {} instructions", pc, executed);
+    }
+    if machine.cpu().halt_reason() == Some(HaltReason::Jam) {
+        println!(
+            "Jammed at {:#06x} after {} instructions, last ones:\n{}",
+            machine.cpu().pc(),
+            executed,
+            machine.cpu().format_pc_history()
+        );
+    }
+
+    if result == ci.expect {
+        println!("PASS: {:#06x} = {:#04x}", ci.result_addr, result);
+        EXIT_PASS
+    } else {
+        println!(
+            "FAIL: {:#06x} = {:#04x}, expected {:#04x}",
+            ci.result_addr, result, ci.expect
+        );
+        EXIT_FAIL
+    }
+}
+
+/// Runs the CPU of `machine` and `mos6502::reference::Reference` side by side from the
+/// same state, see `mos6502::differential::compare()`, only the CPU: the devices of the
+/// machine are not stepped, and the reference writes their addresses as RAM.
+///
+/// # Returns
+/// `EXIT_PASS` if both ran `timeout_instr` instructions alike or stopped together,
+/// `EXIT_FAIL` at the first difference.
+pub fn differential(machine: &mut Machine, timeout_instr: u64) -> i32 {
+    let ram: Vec<u8> = machine.memory().borrow().as_slice().to_vec();
+    let mut reference: Reference = Reference::new(ram, machine.cpu().snapshot());
+    reference.cycles = machine.cpu().cycles();
+    let compared: Result<u64, Divergence> =
+        compare(&mut reference, machine.cpu_mut(), timeout_instr);
+    match compared {
+        Ok(executed) => {
+            println!("PASS: the reference agreed for {} instructions", executed);
+            EXIT_PASS
+        }
+        Err(divergence) => {
+            println!("FAIL: {}", divergence);
+            EXIT_FAIL
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::screen::Screen;
+
+use devices::video::FrameBuffer;
+use machine::Machine;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Appended to the key of the client to prove the server speaks WebSocket, see RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+/// How long a client may take to accept an update before it is dropped, so a stalled
+/// browser does not slow the machine down.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Streams the state of the running machine to WebSocket clients, for browser dashboards.
+///
+/// Each update is a text message with a JSON object of the registers: `pc`, `a`, `x`,
+/// `y`, `sp`, `status`, `flags` as text (`NV-BDIZC`), `cycles` and `halted`. With
+/// frames on, machines with a video chip follow it with a binary message: the width
+/// and the height as little-endian 16-bit numbers, then the RGBA bytes of every pixel.
+pub struct Dashboard {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    /// The time between updates.
+    interval: Duration,
+    last: Option<Instant>,
+    frames: bool,
+}
+
+impl Dashboard {
+    /// Listens for clients on the local TCP `port`, sending them `rate` updates per
+    /// second at most, with the frame buffer if `frames`.
+    pub fn listen(port: u16, rate: f64, frames: bool) -> Result<Self, String> {
+        let listener: TcpListener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|error| format!("cannot listen on port {}: {}", port, error))?;
+        let clients: Arc<Mutex<Vec<TcpStream>>> = Arc::new(Mutex::new(Vec::new()));
+        let accepted: Arc<Mutex<Vec<TcpStream>>> = clients.clone();
+        std::thread::spawn(move || {
+            for client in listener.incoming().map_while(Result::ok) {
+                // Clients that are not WebSocket clients are turned away
+                if let Ok(client) = handshake(client) {
+                    accepted.lock().unwrap().push(client);
+                }
+            }
+        });
+        println!("Dashboard streaming on ws://127.0.0.1:{}", port);
+        Ok(Dashboard {
+            clients,
+            interval: Duration::from_secs_f64(1.0 / rate),
+            last: None,
+            frames,
+        })
+    }
+
+    /// Sends `messages` to every client, dropping those that fail to take them.
+    fn broadcast(&self, messages: &[Vec<u8>]) {
+        self.clients.lock().unwrap().retain_mut(|client| {
+            messages
+                .iter()
+                .all(|message| client.write_all(message).is_ok())
+        });
+    }
+}
+
+impl Screen for Dashboard {
+    fn present(&mut self, machine: &Machine) {
+        let now: Instant = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return;
+        }
+        self.last = Some(now);
+        if self.clients.lock().unwrap().is_empty() {
+            return;
+        }
+
+        let mut messages: Vec<Vec<u8>> = vec![encode_frame(OPCODE_TEXT, state(machine).as_bytes())];
+        if let (true, Some(frame)) = (self.frames, machine.frame_buffer()) {
+            messages.push(encode_frame(OPCODE_BINARY, &frame_message(frame)));
+        }
+        self.broadcast(&messages);
+    }
+}
+
+/// # Returns
+/// The registers of `machine` as a JSON object.
+fn state(machine: &Machine) -> String {
+    let cpu = machine.cpu();
+    format!(
+        "{{\"pc\":{},\"a\":{},\"x\":{},\"y\":{},\"sp\":{},\"status\":{},\"flags\":\"{}\",\"cycles\":{},\"halted\":{}}}",
+        cpu.pc(),
+        cpu.a(),
+        cpu.x(),
+        cpu.y(),
+        cpu.sp(),
+        cpu.status().bits(),
+        cpu.status(),
+        cpu.cycles(),
+        cpu.is_halted()
+    )
+}
+
+/// # Returns
+/// The size of `frame` followed by its pixels.
+fn frame_message(frame: &FrameBuffer) -> Vec<u8> {
+    let mut message: Vec<u8> = Vec::with_capacity(4 + frame.pixels().len());
+    message.extend_from_slice(&(frame.width() as u16).to_le_bytes());
+    message.extend_from_slice(&(frame.height() as u16).to_le_bytes());
+    message.extend_from_slice(frame.pixels());
+    message
+}
+
+/// Reads the HTTP upgrade request of `client` and accepts it.
+///
+/// # Returns
+/// The client, ready for messages, or an error if it did not ask for a WebSocket.
+fn handshake(client: TcpStream) -> io::Result<TcpStream> {
+    let mut key: Option<String> = None;
+    let mut reader: BufReader<&TcpStream> = BufReader::new(&client);
+    loop {
+        let mut line: String = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key: String =
+        key.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a WebSocket"))?;
+
+    let mut client: TcpStream = client;
+    write!(
+        client,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    client.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    client.set_nodelay(true)?;
+    Ok(client)
+}
+
+/// # Returns
+/// The `Sec-WebSocket-Accept` answering the `Sec-WebSocket-Key` of a client.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, HANDSHAKE_GUID).as_bytes()))
+}
+
+/// # Returns
+/// An unmasked, unfragmented WebSocket frame carrying `payload`, as servers send them.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    // FIN set, a single frame per message
+    let mut frame: Vec<u8> = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// # Returns
+/// The SHA-1 digest of `data`, which the handshake requires.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message: Vec<u8> = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w: [u32; 80] = [0; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k): (u32, u32) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp: u32 = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest: [u8; 20] = [0; 20];
+    for (bytes, state) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&state.to_be_bytes());
+    }
+    digest
+}
+
+/// # Returns
+/// `data` in standard, padded base64.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text: String = String::new();
+    for chunk in data.chunks(3) {
+        let bits: u32 = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_handshake_and_frames_messages() {
+        // The example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"ab"), "YWI=");
+
+        assert_eq!(encode_frame(OPCODE_TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        let frame: Vec<u8> = encode_frame(OPCODE_BINARY, &[0; 300]);
+        assert_eq!(frame[..4], [0x82, 126, 0x01, 0x2c]);
+        assert_eq!(frame.len(), 304);
+    }
+}
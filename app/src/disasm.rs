@@ -0,0 +1,126 @@
+use crate::ci;
+
+/// Settings of the `disasm` subcommand.
+struct DisasmOptions {
+    /// Binary file to disassemble.
+    input: String,
+    /// Where the binary is loaded, `--org`, in hexadecimal.
+    origin: u16,
+    /// Where the code starts, `--entry`, in hexadecimal, as many as given.
+    entries: Vec<u16>,
+    /// File the listing is written to, `--out`, instead of stdout.
+    output: Option<String>,
+}
+
+impl DisasmOptions {
+    /// Parses `input.bin [--org C000] [--entry C000]... [--out listing.asm]`, the
+    /// arguments after `disasm`.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<DisasmOptions, String> {
+        let mut input: Option<String> = None;
+        let mut origin: u16 = 0;
+        let mut entries: Vec<u16> = Vec::new();
+        let mut output: Option<String> = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for `{}`", name))
+            };
+            match arg.as_str() {
+                "--org" => origin = parse_hex(&value("--org")?)?,
+                "--entry" => entries.push(parse_hex(&value("--entry")?)?),
+                "--out" => output = Some(value("--out")?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option `{}`", arg)),
+                _ if input.is_none() => input = Some(arg),
+                _ => return Err(format!("Unexpected argument `{}`", arg)),
+            }
+        }
+
+        Ok(DisasmOptions {
+            input: input.ok_or_else(|| "No binary file to disassemble".to_string())?,
+            origin,
+            entries,
+            output,
+        })
+    }
+}
+
+/// Runs `disasm`: writes the disassembly of a binary, code and data apart, with labels
+/// for its branch, `JMP` and `JSR` targets, see `asm6502::disasm::disassemble()`.
+///
+/// # Returns
+/// The process exit code, `ci::EXIT_USAGE` if the arguments or the files are wrong.
+pub fn run<I: Iterator<Item = String>>(args: I) -> i32 {
+    match disassemble(args) {
+        Ok(()) => 0,
+        Err(error) => {
+            println!("Error: {}", error);
+            ci::EXIT_USAGE
+        }
+    }
+}
+
+fn disassemble<I: Iterator<Item = String>>(args: I) -> Result<(), String> {
+    let options: DisasmOptions = DisasmOptions::parse(args)?;
+    let bytes: Vec<u8> = std::fs::read(&options.input)
+        .map_err(|error| format!("cannot read `{}`: {}", options.input, error))?;
+    if options.origin as usize + bytes.len() > 0x10000 {
+        return Err(format!(
+            "`{}` does not fit in memory from {:#06x}",
+            options.input, options.origin
+        ));
+    }
+
+    let listing: String = asm6502::disasm::disassemble(&bytes, options.origin, &options.entries);
+    match &options.output {
+        Some(path) => std::fs::write(path, listing)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error)),
+        None => {
+            print!("{}", listing);
+            Ok(())
+        }
+    }
+}
+
+/// # Returns
+/// The address `text` is, in hexadecimal, with or without a `$` or `0x` prefix.
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits: &str = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address `{}`", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<DisasmOptions, String> {
+        DisasmOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_origin_in_hexadecimal() {
+        let options: DisasmOptions =
+            parse(&["input.bin", "--org", "C000", "--out", "listing.asm"]).unwrap();
+        assert_eq!(options.input, "input.bin");
+        assert_eq!(options.origin, 0xc000);
+        assert_eq!(options.output.as_deref(), Some("listing.asm"));
+
+        assert_eq!(
+            parse(&["input.bin", "--org", "$0800"]).unwrap().origin,
+            0x0800
+        );
+        assert_eq!(
+            parse(&["input.bin", "--entry", "C000", "--entry", "0xc100"])
+                .unwrap()
+                .entries,
+            [0xc000, 0xc100]
+        );
+        assert!(parse(&["input.bin", "--org", "10000"]).is_err());
+        assert!(parse(&["--org", "C000"]).is_err());
+        assert!(parse(&["input.bin", "--org"]).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ci;
+
+use asm6502::Program;
+use memory::Memory;
+use mos6502::state_hash::StateHash;
+use mos6502::{Mos6502, StatusFlags};
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Where the bundled programs and their traces are, from the root of the repository.
+pub const GOLDEN_DIRECTORY: &str = "roms/golden";
+
+/// The longest a program may run, in instructions.
+const MAX_INSTRUCTIONS: u64 = 100_000;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// # Returns
+/// The trace of the program assembled from `source`, run on a bare CPU from its lowest
+/// address until it halts: a line per instruction with the registers before it, then
+/// how it ended, with the hash of the registers after every instruction and of the
+/// memory at the end.
+pub fn record(source: &str) -> Result<String, String> {
+    let program: Program = asm6502::assemble(source)?;
+    let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+    program.load_into(cpu.memory().borrow_mut().as_mut_slice());
+    cpu.set_pc(program.start());
+    cpu.set_sp(0xfd);
+    cpu.set_status(StatusFlags::UNUSED | StatusFlags::INTERRUPT_DISABLE);
+    cpu.set_trap_detection(true);
+
+    let mut trace: String = String::new();
+    let mut hash: StateHash = StateHash::new();
+    let mut executed: u64 = 0;
+    while !cpu.is_halted() && executed < MAX_INSTRUCTIONS {
+        // Writing to a String cannot fail
+        let _ = writeln!(
+            trace,
+            "{:04X}  {:<14} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            cpu.pc(),
+            cpu.next_instruction(),
+            cpu.a(),
+            cpu.x(),
+            cpu.y(),
+            cpu.status().bits(),
+            cpu.sp(),
+            cpu.cycles()
+        );
+        cpu.step();
+        executed += 1;
+        hash.update(&cpu);
+    }
+
+    let end: String = match cpu.halt_reason() {
+        Some(reason) => format!("{} at {:#06x}", reason, cpu.pc()),
+        None => "timeout".to_string(),
+    };
+    let memory: u64 = cpu
+        .memory()
+        .borrow()
+        .as_slice()
+        .iter()
+        .fold(FNV_OFFSET_BASIS, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        });
+    let _ = writeln!(
+        trace,
+        "END {} after {} instructions, {} cycles, state hash {:#018x}, memory hash {:#018x}",
+        end,
+        executed,
+        cpu.cycles(),
+        hash.digest(),
+        memory
+    );
+    Ok(trace)
+}
+
+/// Records the trace of every `.s` program of `directory`, see `record()`, and compares
+/// it with the golden one next to it, `.trace`, or writes it there if `bless`.
+///
+/// # Returns
+/// How many programs were checked, or the differences, one line per program.
+pub fn check(directory: &Path, bless: bool) -> Result<usize, String> {
+    let mut sources: Vec<PathBuf> = std::fs::read_dir(directory)
+        .map_err(|error| format!("cannot read `{}`: {}", directory.display(), error))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "s"))
+        .collect();
+    sources.sort();
+
+    let mut differences: Vec<String> = Vec::new();
+    for source in &sources {
+        let golden: PathBuf = source.with_extension("trace");
+        let text: String = std::fs::read_to_string(source)
+            .map_err(|error| format!("cannot read `{}`: {}", source.display(), error))?;
+        let trace: String =
+            record(&text).map_err(|error| format!("{}: {}", source.display(), error))?;
+        if bless {
+            std::fs::write(&golden, &trace)
+                .map_err(|error| format!("cannot write `{}`: {}", golden.display(), error))?;
+            continue;
+        }
+        let expected: String = std::fs::read_to_string(&golden).unwrap_or_default();
+        let mismatch = expected
+            .lines()
+            .map(Some)
+            .chain(std::iter::repeat(None))
+            .zip(trace.lines().map(Some).chain(std::iter::repeat(None)))
+            .take(expected.lines().count().max(trace.lines().count()))
+            .enumerate()
+            .find(|(_, (expected, actual))| expected != actual);
+        if let Some((line, (expected, actual))) = mismatch {
+            differences.push(format!(
+                "{} line {}: expected `{}`, got `{}`",
+                golden.display(),
+                line + 1,
+                expected.unwrap_or("<end>"),
+                actual.unwrap_or("<end>")
+            ));
+        }
+    }
+
+    match differences.is_empty() {
+        true => Ok(sources.len()),
+        false => Err(differences.join("\n")),
+    }
+}
+
+/// Runs `golden`: checks the traces of the programs of a directory,
+/// `GOLDEN_DIRECTORY` by default, or records them with `--bless`.
+///
+/// # Returns
+/// The process exit code, `ci::EXIT_FAIL` if a trace differs.
+pub fn run<I: Iterator<Item = String>>(args: I) -> i32 {
+    let mut directory: Option<String> = None;
+    let mut bless: bool = false;
+    for arg in args {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            _ if arg.starts_with('-') || directory.is_some() => {
+                println!("Error: Unexpected argument `{}`", arg);
+                return ci::EXIT_USAGE;
+            }
+            _ => directory = Some(arg),
+        }
+    }
+    let directory: String = directory.unwrap_or_else(|| GOLDEN_DIRECTORY.to_string());
+    match check(Path::new(&directory), bless) {
+        Ok(count) if bless => {
+            println!("Recorded {} traces in `{}`", count, directory);
+            ci::EXIT_PASS
+        }
+        Ok(count) => {
+            println!("{} traces match", count);
+            ci::EXIT_PASS
+        }
+        Err(error) => {
+            println!("{}", error);
+            ci::EXIT_FAIL
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `BLESS_GOLDEN=1 cargo test -p app golden` records the traces anew.
+    #[test]
+    fn matches_the_golden_traces() {
+        let directory: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join(GOLDEN_DIRECTORY);
+        let bless: bool = std::env::var_os("BLESS_GOLDEN").is_some();
+        match check(&directory, bless) {
+            Ok(count) => assert!(count > 0, "no programs in `{}`", directory.display()),
+            Err(error) => panic!(
+                "{}\nIf the change is intended, run with BLESS_GOLDEN=1 to update the traces",
+                error
+            ),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// How many lines `History::list()` shows.
+const MAX_LISTED: usize = 20;
+
+/// The lines typed in the monitor, kept in a file across sessions with `--history`.
+///
+/// Lines starting with `!` recall earlier ones, see `expand()`: `!!` the last one, `!<n>`
+/// the one numbered `n` in `list()` and `!<prefix>` the last one starting with `prefix`.
+pub struct History {
+    lines: Vec<String>,
+    /// File each new line is appended to.
+    path: Option<String>,
+}
+
+impl History {
+    /// # Returns
+    /// The lines of the file `path`, oldest first, which new lines are appended to. None
+    /// if there is no file, or it does not exist yet.
+    pub fn load(path: Option<&str>) -> History {
+        let lines: Vec<String> = path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map_or_else(Vec::new, |text| {
+                text.lines()
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            });
+        History {
+            lines,
+            path: path.map(str::to_string),
+        }
+    }
+
+    /// # Returns
+    /// The earlier line `line` recalls, if it starts with `!`, `line` itself otherwise,
+    /// or an error if no line matches.
+    pub fn expand(&self, line: &str) -> Result<String, String> {
+        let Some(event) = line.strip_prefix('!') else {
+            return Ok(line.to_string());
+        };
+        let found: Option<&String> = match event {
+            "!" => self.lines.last(),
+            _ => match event.parse::<usize>() {
+                Ok(number) => number
+                    .checked_sub(1)
+                    .and_then(|index| self.lines.get(index)),
+                Err(_) => self
+                    .lines
+                    .iter()
+                    .rev()
+                    .find(|line| !event.is_empty() && line.starts_with(event)),
+            },
+        };
+        found
+            .cloned()
+            .ok_or_else(|| format!("No line in the history for `{}`", line))
+    }
+
+    /// Keeps `line` unless it is empty or the same as the last one, appending it to the
+    /// file.
+    ///
+    /// # Returns
+    /// An error if the file cannot be written.
+    pub fn add(&mut self, line: &str) -> Result<(), String> {
+        if line.is_empty() || self.lines.last().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+        self.lines.push(line.to_string());
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))
+    }
+
+    /// # Returns
+    /// The last lines, numbered for `!<n>`, one per line.
+    pub fn list(&self) -> String {
+        let start: usize = self.lines.len().saturating_sub(MAX_LISTED);
+        let listed: Vec<String> = self.lines[start..]
+            .iter()
+            .enumerate()
+            .map(|(index, line)| format!("{:5}  {}", start + index + 1, line))
+            .collect();
+        listed.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recalls_and_keeps_lines() {
+        let path: std::path::PathBuf = std::env::temp_dir().join("monitor_history.txt");
+        let name: &str = path.to_str().unwrap();
+        std::fs::write(&path, "step\nread $1000 4\n").unwrap();
+
+        let mut history: History = History::load(Some(name));
+        history.add("break $c000").unwrap();
+        history.add("break $c000").unwrap();
+        assert_eq!(history.expand("!!"), Ok("break $c000".to_string()));
+        assert_eq!(history.expand("!1"), Ok("step".to_string()));
+        assert_eq!(history.expand("!re"), Ok("read $1000 4".to_string()));
+        assert_eq!(history.expand("go"), Ok("go".to_string()));
+        assert!(history.expand("!4").is_err());
+        assert!(history.expand("!jump").is_err());
+        assert_eq!(
+            history.list(),
+            "    1  step\n    2  read $1000 4\n    3  break $c000"
+        );
+
+        let history: History = History::load(Some(name));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(history.expand("!!"), Ok("break $c000".to_string()));
+    }
+}
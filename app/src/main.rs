@@ -1,61 +1,706 @@
-use memory::Memory;
-use mos6502::Mos6502;
+mod asm;
+mod audio;
+mod ci;
+mod dashboard;
+mod disasm;
+mod golden;
+mod history;
+mod media;
+mod monitor;
+mod options;
+mod remote;
+mod screen;
+mod script;
+mod serial;
+mod speed;
+mod test_roms;
 
-use std::io;
+use asm6502::debug_info::DebugInfo;
+use dashboard::Dashboard;
+use devices::charset::{Charset, Translated};
+use devices::dma::{self, Dma};
+use devices::joypad;
+use devices::random::Random;
+use devices::timer::{self, Timer};
+use history::History;
+use machine::kernal;
+use machine::media::psid::Psid;
+use machine::media::vsf::Vsf;
+use machine::preset::Roms;
+use machine::Machine;
+use monitor::Monitor;
+use mos6502::input_log::{InputEvent, InputLog};
+use mos6502::logging::StdoutSink;
+use mos6502::trace::TraceRecord;
+use mos6502::{HaltReason, Mos6502};
+use options::Options;
+use screen::{PixelScreen, PpmScreen, Screen, TextScreen, TEXT_SCREEN_ADDRESS};
+use speed::Throttle;
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::process::exit;
 use std::rc::Rc;
-use std::{cell::RefCell, process::exit};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 
 fn main() {
-    // Initialize memory
-    let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("disasm") {
+        exit(disasm::run(args.into_iter().skip(1)));
+    }
+    if args.first().map(String::as_str) == Some("golden") {
+        exit(golden::run(args.into_iter().skip(1)));
+    }
+    if args.first().map(String::as_str) == Some("test-roms") {
+        exit(test_roms::run(args.into_iter().skip(1)));
+    }
+    if args.first().map(String::as_str) == Some("asm") {
+        match asm::assemble(args.into_iter().skip(1)) {
+            Ok(Some((options, image))) => emulate(options, Some(image)),
+            Ok(None) => {}
+            Err(error) => {
+                println!("Error: {}", error);
+                exit(ci::EXIT_USAGE);
+            }
+        }
+        return;
+    }
+
+    let options: Options = match Options::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Error: {}", error);
+            exit(ci::EXIT_USAGE);
+        }
+    };
 
     // Load ROMs
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() == 2 {
-        let rom_file_path: String = args[1].clone();
-        mem.borrow_mut().load_rom(rom_file_path.as_str(), 0x0000);
+    // With the KERNAL emulated, a PRG can run without any ROM, as can an executable
+    let rom: Option<Vec<u8>> = if let Some(rom_file_path) = &options.rom_path {
+        Some(read_rom(rom_file_path))
+    } else if options.kernal_hle
+        || options.sid_path.is_some()
+        || options.xex_path.is_some()
+        || options.dsk_path.is_some()
+    {
+        None
     } else {
         println!("No ROM or binary file given. Use `path/to/exe <path/to/rom>`");
+        println!("Headless mode: `path/to/exe <path/to/rom> --ci --result-addr <addr> --expect <value> [--timeout-instr <count>] [--state-hash <every>]`");
+        println!("Test suites: `path/to/exe test-roms <path/to/dir> [--status-addr <addr> [--pass-value <value>]] [--pass-text <text>] [--fail-text <text>] [--pass-pc <addr>] [options...]`");
+        println!("Golden traces: `path/to/exe golden [<path/to/dir>] [--bless]`");
+        println!("Differential testing: `path/to/exe <path/to/rom> --differential [--timeout-instr <count>]`");
+        println!("Remote control: `path/to/exe <path/to/rom> --listen 127.0.0.1:6502`");
+        println!("Monitor history: `--history <path/to/file>`");
+        println!("Breakpoints kept across sessions: `--breakpoints <path/to/file>`");
+        println!("Monitor scripts: `path/to/exe <path/to/rom> --script <path/to/commands>`");
+        println!("CPU log: `--log fetch|exec|bus|irq=off|info|debug|trace[,...]`");
+        println!("Source-level debugging: `--dbg <path/to/dbg>`, from ld65 `--dbgfile`");
+        println!("Inputs: `--record <path/to/log>` or `--replay <path/to/log>`");
+        println!("Machines: `--machine bare|c64-lite|apple1|nes-lite|atari2600`");
+        println!("Raw binaries: `path/to/exe <path/to/bin> --load-addr <addr> --auto-vectors [--entry <addr>]`");
+        println!(
+            "Console programs: `path/to/exe --machine c64-lite --kernal-hle --prg <path/to/prg>`"
+        );
+        println!("Assembler: `path/to/exe asm <path/to/source> -o <path/to/output> [--format prg|bin|ihex] [--run [options...]]`");
+        println!(
+            "Disassembler: `path/to/exe disasm <path/to/bin> [--org <hex>] [--entry <hex>]... [--out <path/to/asm>]`"
+        );
+        println!("SID tunes: `path/to/exe --machine c64-lite --play-sid <path/to/sid> --audio <path/to/wav> [--song <number>] [--seconds <length>]`");
         exit(0);
+    };
+    emulate(options, rom);
+}
+
+/// Builds the machine of `options` with `rom` as its program, its devices and media
+/// attached, then resets it.
+///
+/// # Arguments
+/// * `console` - Where the KERNAL console of `--kernal-hle` prints.
+fn build_machine(
+    options: &Options,
+    rom: Option<Vec<u8>>,
+    console: Box<dyn Write>,
+) -> Result<Machine, String> {
+    // A binary loaded elsewhere than $0000 is copied into RAM once the machine is built
+    let (rom, raw): (Option<Vec<u8>>, Option<Vec<u8>>) = match options.load_address {
+        Some(_) => (None, rom),
+        None => (rom, None),
+    };
+    let roms: Roms = Roms {
+        program: rom,
+        character: options.char_rom_path.as_deref().map(read_rom),
+        basic: options.basic_rom_path.as_deref().map(read_rom),
+    };
+    let mut machine: Machine = options.machine.build(roms, options.charset)?;
+
+    if let (Some(address), Some(raw)) = (options.load_address, &raw) {
+        let start: usize = address as usize;
+        if start + raw.len() > memory::MEMORY_SIZE {
+            return Err(format!(
+                "binary of {} bytes does not fit in memory at {:#06x}",
+                raw.len(),
+                address
+            ));
+        }
+        machine.memory().borrow_mut().as_mut_slice()[start..start + raw.len()].copy_from_slice(raw);
+    }
+    if options.auto_vectors {
+        let entry: u16 = options.entry.or(options.load_address).unwrap_or(0x0000);
+        machine.install_vectors(entry);
     }
+    machine.cpu_mut().set_trap_detection(options.stop_on_trap);
 
-    // Initialize CPU and load created memory
-    let mut cpu: Mos6502 = Mos6502::new(mem);
-    cpu.reset();
-    #[cfg(debug_assertions)]
-    {
-        cpu.print_state();
+    if let Some((address, host)) = options.serial {
+        serial::attach(&mut machine, address, host, options.charset)?;
+    }
+
+    if let Some(address) = options.timer {
+        let timer: Rc<RefCell<Timer>> = Rc::new(RefCell::new(Timer::new()));
+        machine.map_device(address, address + timer::STATUS, timer);
+    }
+
+    if let Some(address) = options.dma {
+        let dma: Rc<RefCell<Dma>> = Rc::new(RefCell::new(Dma::new()));
+        machine.map_device(address, address + dma::PAGE, dma);
+    }
+    if let Some(address) = options.random {
+        let random: Rc<RefCell<Random>> = Rc::new(RefCell::new(Random::new(options.random_seed)));
+        machine.map_device(address, address, random);
+    }
+
+    if options.kernal_hle {
+        kernal::install_console(&mut machine, Translated::new(console, options.charset));
+    }
+
+    media::insert_cartridge(&mut machine, options)?;
+    let entry: Option<u16> = media::load(&mut machine, options)?;
+
+    machine.reset();
+    if let (true, Some(entry)) = (options.kernal_hle, entry) {
+        kernal::start_program(&mut machine, entry);
+    } else if let (true, Some(entry)) = (
+        options.xex_path.is_some() || options.dsk_path.is_some(),
+        entry,
+    ) {
+        machine.cpu_mut().set_pc(entry);
+    }
+    Ok(machine)
+}
+
+/// Builds the machine of `options`, with `rom` as its program, and runs it.
+fn emulate(options: Options, rom: Option<Vec<u8>>) {
+    let mut machine: Machine =
+        build_machine(&options, rom, Box::new(io::stdout())).unwrap_or_else(|error| {
+            println!("Error: {}", error);
+            exit(ci::EXIT_USAGE);
+        });
+
+    if let Some(path) = &options.sid_path {
+        let installed: Result<u16, String> = Psid::parse(&read_rom(path)).and_then(|psid| {
+            let song: u16 = options.song.unwrap_or(psid.start_song);
+            println!(
+                "Playing \"{}\" by {} ({}), song {} of {}",
+                psid.name, psid.author, psid.released, song, psid.songs
+            );
+            psid.install(&mut machine.memory().borrow_mut(), song)
+        });
+        match installed {
+            Ok(driver) => machine.cpu_mut().set_pc(driver),
+            Err(error) => {
+                println!("Error: {}", error);
+                exit(ci::EXIT_USAGE);
+            }
+        }
+    }
+
+    if let Some(path) = &options.vsf_path {
+        match Vsf::parse(&read_rom(path)) {
+            Ok(vsf) => {
+                vsf.restore(&mut machine);
+                println!(
+                    "Resumed {} snapshot at {:#06x}",
+                    vsf.machine, vsf.registers.pc
+                );
+            }
+            Err(error) => {
+                println!("Error: {}", error);
+                exit(ci::EXIT_USAGE);
+            }
+        }
+    }
+
+    let debug_info: Option<Rc<DebugInfo>> = options.debug_info_path.as_deref().map(|path| {
+        let text: String = std::fs::read_to_string(path).unwrap_or_else(|error| {
+            println!("Error: cannot read `{}`: {}", path, error);
+            exit(ci::EXIT_USAGE);
+        });
+        Rc::new(DebugInfo::parse(&text).unwrap_or_else(|error| {
+            println!("Error: `{}`: {}", path, error);
+            exit(ci::EXIT_USAGE);
+        }))
+    });
+
+    if let Some(path) = &options.trace_json {
+        // Line by line, the process may exit without dropping the machine
+        let mut trace: LineWriter<File> = match File::create(path) {
+            Ok(file) => LineWriter::new(file),
+            Err(error) => {
+                println!("Error: cannot create `{}`: {}", path, error);
+                exit(ci::EXIT_USAGE);
+            }
+        };
+        let debug_info: Option<Rc<DebugInfo>> = debug_info.clone();
+        machine.cpu_mut().set_tracer(move |record: &TraceRecord| {
+            let mut json: String = record.to_json();
+            if let Some(location) = debug_info
+                .as_ref()
+                .and_then(|info| info.location(record.pc))
+            {
+                let source: String = location
+                    .to_string()
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"");
+                // Last, inside the closing brace
+                json.pop();
+                json.push_str(&format!(",\"source\":\"{}\"}}", source));
+            }
+            // Tracing is best effort, like the console
+            let _ = writeln!(trace, "{}", json);
+        });
+    }
+
+    if !options.log_levels.is_empty() {
+        machine.cpu_mut().set_log_sink(StdoutSink);
+        for &(category, level) in &options.log_levels {
+            machine.cpu_mut().set_log_level(category, level);
+        }
+    }
+
+    if let Some(path) = &options.audio_path {
+        let sample_rate: u32 = machine.audio_sample_rate().unwrap_or_else(|| {
+            println!(
+                "Error: the {} machine has no sound chip",
+                options.machine.name()
+            );
+            exit(ci::EXIT_USAGE);
+        });
+        match audio::WavWriter::create(path, sample_rate) {
+            Ok(writer) => machine.set_audio_sink(Box::new(writer)),
+            Err(error) => {
+                println!("Error: cannot write `{}`: {}", path, error);
+                exit(ci::EXIT_USAGE);
+            }
+        }
+    }
+
+    if let Some(path) = &options.replay {
+        let text: String = std::fs::read_to_string(path).unwrap_or_else(|error| {
+            println!("Error: cannot read `{}`: {}", path, error);
+            exit(ci::EXIT_USAGE);
+        });
+        let log: InputLog = InputLog::parse(&text).unwrap_or_else(|error| {
+            println!("Error: {}", error);
+            exit(ci::EXIT_USAGE);
+        });
+        machine.replay(log);
+    }
+
+    let mut screens: Vec<Box<dyn Screen>> = Vec::new();
+    if (options.frame_path.is_some() || options.pixel_screen) && machine.frame_buffer().is_none() {
+        println!(
+            "Error: the {} machine has no video chip",
+            options.machine.name()
+        );
+        exit(ci::EXIT_USAGE);
+    }
+    if let Some(path) = &options.frame_path {
+        screens.push(Box::new(PpmScreen::new(path)));
+    }
+    if options.pixel_screen {
+        screens.push(Box::new(PixelScreen::new()));
+    }
+    if options.text_screen {
+        screens.push(Box::new(TextScreen::new(TEXT_SCREEN_ADDRESS)));
+    }
+    if let Some(dashboard) = &options.dashboard {
+        match Dashboard::listen(dashboard.port, dashboard.rate, dashboard.frames) {
+            Ok(dashboard) => screens.push(Box::new(dashboard)),
+            Err(error) => {
+                println!("Error: {}", error);
+                exit(ci::EXIT_USAGE);
+            }
+        }
+    }
+
+    if options.sid_path.is_some() {
+        let frames: u64 = (options.play_seconds as f64 * options.machine.frame_rate()) as u64;
+        for _ in 0..frames {
+            machine.run_frame();
+        }
+        // Finishes the audio file
+        drop(machine);
+        exit(0);
+    }
+
+    let mut monitor: Monitor = Monitor::new(options.timeout_instr);
+    monitor.set_progress(Box::new(|report: &str| println!("{}", report)));
+    let mut history: History = History::load(options.history.as_deref());
+    if let Some(debug_info) = debug_info {
+        monitor.set_debug_info(debug_info);
+    }
+    if let Some(path) = &options.breakpoints {
+        if let Err(error) = monitor.set_breakpoints_file(path) {
+            println!("Error: {}", error);
+            exit(ci::EXIT_USAGE);
+        }
+    }
+
+    if let Some(path) = &options.script {
+        let code: i32 = script::run(&mut machine, &mut monitor, path);
+        // Finishes the audio file
+        drop(machine);
+        exit(code);
+    }
+
+    if let Some(address) = &options.listen {
+        if let Err(error) = remote::serve(&mut machine, address, &mut monitor) {
+            println!("Error: {}", error);
+            exit(ci::EXIT_USAGE);
+        }
+        // Finishes the audio file
+        drop(machine);
+        exit(0);
+    }
+
+    if options.differential {
+        exit(ci::differential(&mut machine, options.timeout_instr));
+    }
+    if options.headless() {
+        let code: i32 = ci::run(&mut machine, &options);
+        // Show where the program stopped
+        machine.render();
+        for screen in &mut screens {
+            screen.present(&machine);
+        }
+        // Finishes the audio file
+        drop(machine);
+        exit(code);
     }
 
+    if options.record.is_some() {
+        machine.start_recording();
+    }
+    let mut irq_asserted: bool = false;
+    let mut throttle: Throttle =
+        Throttle::new(options.clock_hz.unwrap_or(machine.clock_hz()), options.warp);
+
+    // Read stdin on its own thread so a running program can still be controlled
+    let (input_tx, input_rx) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if input_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    machine.cpu().print_state();
+
     // Emulation loop
     loop {
         println!("Select: ");
         println!("'s': Step");
+        println!("'c': Continue until trap");
+        println!(
+            "'w': Toggle warp (currently {})",
+            if throttle.is_warp() { "on" } else { "off" }
+        );
         println!("'r': Reset");
+        println!("'i': Toggle IRQ line");
+        println!("'n': Trigger NMI");
+        println!("'m': Monitor");
         println!("'q': Quit");
 
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(_) => {
+        match input_rx.recv() {
+            Ok(input) => {
                 // Assuming the user enters only one character
                 if let Some(c) = input.chars().next() {
+                    let event: Option<InputEvent> = match c {
+                        'r' => Some(InputEvent::Reset),
+                        'i' => {
+                            irq_asserted = !irq_asserted;
+                            Some(InputEvent::Irq(irq_asserted))
+                        }
+                        'n' => Some(InputEvent::Nmi),
+                        _ => None,
+                    };
+                    if let Some(event) = event {
+                        machine.input(event);
+                        save_recording(&machine, options.record.as_deref());
+                    }
+
                     match c {
                         's' => {
-                            cpu.step();
-                            cpu.print_state();
+                            machine.step();
+                            machine.cpu().print_state();
+                        }
+                        'c' => {
+                            run(
+                                &mut machine,
+                                &mut throttle,
+                                &mut screens,
+                                options.charset,
+                                options.record.as_deref(),
+                                &input_rx,
+                            );
+                            println!("{}", stop_report(machine.cpu()));
+                            machine.cpu().print_state();
                         }
-                        'r' => {
-                            cpu.reset();
-                            cpu.print_state();
+                        'w' => throttle.toggle_warp(machine.cycles()),
+                        'm' => {
+                            if !monitor_session(&mut machine, &mut monitor, &mut history, &input_rx)
+                            {
+                                return;
+                            }
                         }
-                        'q' => exit(0),
+                        'r' | 'i' | 'n' => machine.cpu().print_state(),
+                        'q' => return,
                         _ => println!("Invalid option."),
                     }
                 } else {
                     println!("No character entered.");
                 }
             }
+            Err(_) => return,
+        }
+    }
+}
+
+/// Runs the commands of `monitor` typed on stdin, until `x`, keeping them in `history`.
+/// `history` lists the last ones and a line ending with a tab lists what its last word
+/// can be completed to, see `Monitor::complete()`.
+///
+/// # Returns
+/// `false` if stdin was closed.
+fn monitor_session(
+    machine: &mut Machine,
+    monitor: &mut Monitor,
+    history: &mut History,
+    input_rx: &Receiver<String>,
+) -> bool {
+    println!("Monitor: the commands of --listen, 'a <address>' to assemble, 'x' to go back.");
+    println!("'history' and '!!', '!<n>' or '!<prefix>' recall lines, a tab then Enter completes.");
+    loop {
+        match monitor.assembling() {
+            Some(address) => print!("a {:#06x} ", address),
+            None => print!("> "),
+        }
+        let _ = io::stdout().flush();
+        let line: String = match input_rx.recv() {
+            Ok(line) => line,
+            Err(_) => return false,
+        };
+        if let Some(prefix) = line.strip_suffix('\t') {
+            println!("{}", monitor.complete(prefix.trim_start()).join(" "));
+            continue;
+        }
+        let line: String = match history.expand(line.trim()) {
+            Ok(recalled) if recalled != line.trim() => {
+                println!("{}", recalled);
+                recalled
+            }
+            Ok(line) => line,
+            Err(error) => {
+                println!("Error: {}", error);
+                continue;
+            }
+        };
+        if let Err(error) = history.add(&line) {
+            println!("Error: {}", error);
+        }
+        if monitor.assembling().is_none() && line == "x" {
+            return true;
+        }
+        if monitor.assembling().is_none() && line == "history" {
+            println!("{}", history.list());
+            continue;
+        }
+        match monitor.execute(machine, &line) {
+            Ok(text) => println!("{}", text),
             Err(error) => println!("Error: {}", error),
         }
     }
 }
+
+/// Reads a ROM image, exiting if it cannot be read.
+fn read_rom(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|error| {
+        println!("Error: cannot read `{}`: {}", path, error);
+        exit(ci::EXIT_USAGE);
+    })
+}
+
+/// Writes the inputs recorded so far to `path`, if recording.
+fn save_recording(machine: &Machine, path: Option<&str>) {
+    if let (Some(path), Some(recording)) = (path, machine.recording()) {
+        if let Err(error) = std::fs::write(path, recording.to_string()) {
+            println!("Error: cannot write `{}`: {}", path, error);
+        }
+    }
+}
+
+/// Runs frame by frame until the CPU halts or the user stops it, throttled to the
+/// configured clock. Each frame is shown on all `screens`.
+/// While running, 'p' pauses/resumes, 'w' toggles warp and 'h' stops.
+/// Any other line is typed on the keyboard of the machine, if it has one, in `charset`.
+/// On machines with game controllers it gives the buttons held instead, see
+/// `joypad_input()`, recorded to `record` if given.
+fn run(
+    machine: &mut Machine,
+    throttle: &mut Throttle,
+    screens: &mut [Box<dyn Screen>],
+    charset: Charset,
+    record: Option<&str>,
+    input_rx: &Receiver<String>,
+) {
+    println!(
+        "Running at {} Hz. 'p': Pause/resume, 'w': Toggle warp, 'h': Stop",
+        throttle.hz()
+    );
+    if machine.keyboard().is_some() {
+        println!("Other lines are typed on the keyboard.");
+    }
+    if machine.has_joypad() {
+        println!(
+            "Other lines hold controller buttons until the next line, e.g. `a start` or `2: left`. Buttons: {}",
+            joypad::BUTTON_NAMES.join(", ")
+        );
+    }
+    throttle.restart(machine.cycles());
+    while !machine.cpu().is_halted() {
+        machine.run_frame();
+        for screen in screens.iter_mut() {
+            screen.present(machine);
+        }
+        throttle.sync(machine.cycles());
+
+        loop {
+            let input: Result<String, TryRecvError> = if throttle.is_paused() {
+                input_rx.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                input_rx.try_recv()
+            };
+            match input.as_deref().map(str::trim) {
+                Ok("p") => {
+                    throttle.toggle_pause(machine.cycles());
+                    println!(
+                        "{}",
+                        if throttle.is_paused() {
+                            "Paused."
+                        } else {
+                            "Resumed."
+                        }
+                    );
+                }
+                Ok("w") => {
+                    throttle.toggle_warp(machine.cycles());
+                    println!("Warp {}.", if throttle.is_warp() { "on" } else { "off" });
+                }
+                Ok("h") | Err(TryRecvError::Disconnected) => {
+                    if throttle.is_paused() {
+                        throttle.toggle_pause(machine.cycles());
+                    }
+                    return;
+                }
+                Ok(line) if machine.has_joypad() => match joypad_input(line) {
+                    Ok(event) => {
+                        machine.input(event);
+                        save_recording(machine, record);
+                    }
+                    Err(error) => println!("Error: {}", error),
+                },
+                Ok(line) => match machine.keyboard() {
+                    Some(keys) => type_line(keys, line, charset),
+                    None => println!("Invalid option."),
+                },
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+    }
+}
+
+/// # Returns
+/// Why `run()` came back, for the menu: where the CPU trapped, why it halted, or that
+/// the user stopped it.
+fn stop_report(cpu: &Mos6502) -> String {
+    match cpu.trapped_at() {
+        Some(pc) => format!("Trapped at {:#06x}", pc),
+        None => match cpu.halt_reason() {
+            Some(HaltReason::Jam) => {
+                format!("CPU is halted: jam, after:\n{}", cpu.format_pc_history())
+            }
+            Some(reason) => format!("CPU is halted: {}.", reason),
+            None => "Stopped.".to_string(),
+        },
+    }
+}
+
+/// Parses a line giving the buttons now held on a controller, `2:` selecting the second
+/// one. An empty line releases the buttons of the first controller.
+fn joypad_input(line: &str) -> Result<InputEvent, String> {
+    let (port, buttons): (u8, &str) = match line.split_once(':') {
+        Some(("1", buttons)) => (0, buttons),
+        Some(("2", buttons)) => (1, buttons),
+        Some((port, _)) => return Err(format!("Unknown controller `{}`, expected 1 or 2", port)),
+        None => (0, line),
+    };
+    Ok(InputEvent::Joypad {
+        port,
+        buttons: joypad::parse_buttons(buttons)?,
+    })
+}
+
+/// Sends `line` to the keyboard followed by a carriage return, the Enter key of most
+/// 6502 machines. Characters `charset` does not have are left out.
+fn type_line(keys: &Sender<u8>, line: &str, charset: Charset) {
+    for byte in line.chars().chain(['\r']).filter_map(|c| charset.encode(c)) {
+        // Only fails if the machine is gone, then nobody is reading keys anyway
+        let _ = keys.send(byte);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// # Returns
+    /// The bare machine of `args`, running `JMP $0000` from $0000.
+    fn jump_to_itself(args: &[&str]) -> Machine {
+        let options: Options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        build_machine(&options, Some(vec![0x4c, 0x00, 0x00]), Box::new(io::sink())).unwrap()
+    }
+
+    #[test]
+    fn continue_stops_at_a_jump_to_itself() {
+        let mut machine: Machine = jump_to_itself(&["jmp.bin"]);
+        let mut throttle: Throttle = Throttle::new(machine.clock_hz(), true);
+        // Kept open, or the run stops as if the terminal was closed
+        let (_input_tx, input_rx) = mpsc::channel::<String>();
+        run(
+            &mut machine,
+            &mut throttle,
+            &mut [],
+            Charset::Raw,
+            None,
+            &input_rx,
+        );
+        assert_eq!(stop_report(machine.cpu()), "Trapped at 0x0000");
+
+        let mut machine: Machine = jump_to_itself(&["jmp.bin", "--stop-on-trap", "off"]);
+        machine.run_frame();
+        assert!(!machine.cpu().is_halted());
+        assert_eq!(stop_report(machine.cpu()), "Stopped.");
+    }
+}
@@ -0,0 +1,137 @@
+use crate::options::Options;
+
+use devices::charset::Charset;
+use devices::reu::Reu;
+use machine::media::dsk::{self, Dsk, FileType};
+use machine::media::{self, crt::Crt, d64::D64, t64::T64, tap::Tap, xex::Xex, TapeFile};
+use machine::Machine;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How long the init routine of an Atari executable may run.
+const XEX_INIT_CYCLES: u64 = 10_000_000;
+
+/// The I/O 2 page of the expansion port, where the REU has its registers.
+const IO2_START: u16 = 0xdf00;
+
+/// # Returns
+/// `name`, PETSCII on disk or tape, as host text.
+fn host_name(name: &[u8]) -> String {
+    let mut host: Vec<u8> = Vec::new();
+    for c in name {
+        Charset::Petscii.decode(*c, &mut host);
+    }
+    String::from_utf8_lossy(&host).into_owned()
+}
+
+fn read(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|error| format!("cannot read `{}`: {}", path, error))
+}
+
+/// Plugs the cartridge and REU given in `options` into the expansion port of
+/// `machine`, if any.
+pub fn insert_cartridge(machine: &mut Machine, options: &Options) -> Result<(), String> {
+    if let Some(path) = &options.cart_path {
+        let crt: Crt = Crt::parse(&read(path)?)?;
+        crt.map(&mut machine.memory().borrow_mut());
+        println!("Inserted cartridge \"{}\" ({:?})", crt.name, crt.mode);
+    }
+    if let Some(size_kb) = options.reu_kb {
+        // The REC registers repeat across the I/O 2 page
+        machine.map_device(
+            IO2_START,
+            IO2_START + 0xff,
+            Rc::new(RefCell::new(Reu::new(size_kb))),
+        );
+    }
+    Ok(())
+}
+
+/// Loads the binary file selected by `options` from an Apple DOS 3.3 disk into
+/// `machine`, whose CPU then reads and writes the disk through RWTS.
+///
+/// # Returns
+/// The address the file is loaded at, where it starts like with `BRUN`.
+fn load_dsk(machine: &mut Machine, options: &Options, path: &str) -> Result<u16, String> {
+    let disk: Dsk = Dsk::parse(read(path)?)?;
+    let (entry, content) = disk.read_file(options.load_name.to_ascii_uppercase().as_bytes())?;
+    let name: String = String::from_utf8_lossy(&entry.name).into_owned();
+    if entry.file_type != FileType::Binary {
+        return Err(format!(
+            "`{}` is a {} file, only binary files can be run",
+            name, entry.file_type
+        ));
+    }
+    let (address, data) = dsk::binary_file(&content)?;
+    let end: usize = address as usize + data.len();
+    machine.memory().borrow_mut().as_mut_slice()[address as usize..end].copy_from_slice(data);
+    println!(
+        "Loaded \"{}\" from \"{}\" at {:#06x}-{:#06x}",
+        name,
+        path,
+        address,
+        end - 1
+    );
+    dsk::install_rwts(machine.cpu_mut(), Rc::new(RefCell::new(disk)));
+    Ok(address)
+}
+
+/// Loads into `machine` the program selected by `options` from a disk or tape image,
+/// or the PRG file or Atari executable given, if any.
+///
+/// # Returns
+/// Where the program starts: the address called by its BASIC loader if it has one,
+/// the run address of an Atari executable, else its load address.
+pub fn load(machine: &mut Machine, options: &Options) -> Result<Option<u16>, String> {
+    if let Some(path) = &options.dsk_path {
+        return load_dsk(machine, options, path).map(Some);
+    }
+    if let Some(path) = &options.xex_path {
+        let xex: Xex = Xex::parse(&read(path)?)?;
+        let entry: u16 = xex.load(machine, XEX_INIT_CYCLES)?;
+        println!(
+            "Loaded \"{}\", {} segments, running at {:#06x}",
+            path,
+            xex.segments.len(),
+            entry
+        );
+        return Ok(Some(entry));
+    }
+
+    let pattern: Vec<u8> = options
+        .load_name
+        .chars()
+        .filter_map(|c| Charset::Petscii.encode(c))
+        .collect();
+
+    let (name, medium, prg): (String, String, Vec<u8>) = if let Some(path) = &options.d64_path {
+        let disk: D64 = D64::parse(read(path)?)?;
+        let (entry, content) = disk.read_file(&pattern)?;
+        (
+            host_name(&entry.name),
+            host_name(&disk.disk_name()),
+            content,
+        )
+    } else if let Some(path) = &options.tape_path {
+        let data: Vec<u8> = read(path)?;
+        // Told apart by their signature rather than their extension
+        let (tape_name, files): (Vec<u8>, Vec<TapeFile>) = match T64::parse(&data) {
+            Ok(tape) => (tape.name, tape.files),
+            Err(_) => (Vec::new(), Tap::parse(&data)?.files()),
+        };
+        let file: &TapeFile = media::find_file(&files, &pattern)?;
+        (host_name(&file.name), host_name(&tape_name), file.prg())
+    } else if let Some(path) = &options.prg_path {
+        (path.clone(), "the host".to_string(), read(path)?)
+    } else {
+        return Ok(None);
+    };
+
+    let (start, end) = media::load_prg(&mut machine.memory().borrow_mut(), &prg)?;
+    println!(
+        "Loaded \"{}\" from \"{}\" at {:#06x}-{:#06x}",
+        name, medium, start, end
+    );
+    Ok(Some(media::sys_address(&prg).unwrap_or(start)))
+}
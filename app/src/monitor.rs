@@ -0,0 +1,1373 @@
+use crate::options::parse_number;
+
+use asm6502::debug_info::{DebugInfo, Location};
+use asm6502::expr::Expr;
+use devices::charset::Charset;
+use machine::Machine;
+use mos6502::{HaltReason, Mos6502};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The commands of `execute()`, which `complete()` completes.
+const COMMANDS: [&str; 19] = [
+    "state", "step", "go", "run", "read", "write", "fill", "compare", "hunt", "save", "watch",
+    "unwatch", "break", "bk", "delete", "stack", "reset", "a", "assert",
+];
+
+/// How often long runs report their progress, in cycles, see `set_progress()`.
+const PROGRESS_CYCLES: u64 = 1_000_000;
+
+/// Where long runs report their progress, see `Monitor::set_progress()`.
+pub type Progress = Box<dyn FnMut(&str)>;
+
+/// The most bytes `read` shows at once.
+const MAX_READ: usize = 256;
+
+/// The most differences `compare`, or matches `hunt`, lists.
+const MAX_LISTED: usize = 16;
+
+/// The debugger commands shared by the front-ends driving a machine, each run with
+/// `execute()`:
+///
+/// - `state`: the registers, cycle count and whether the CPU is halted.
+/// - `step [count]`: executes `count` instructions, 1 by default.
+/// - `step line`: executes until the source line changes, with debug information.
+/// - `go [count]`: runs until a breakpoint, the CPU halting or `count` instructions.
+/// - `run cycles <count>`: runs until a breakpoint, the CPU halting or `count` cycles,
+///   rounded up to the end of an instruction.
+/// - `read <address> [length]`: the bytes from `address` on, 1 by default.
+/// - `write <address> <byte>...`, or `> <address> <byte>...`: writes the bytes from
+///   `address` on.
+/// - `fill <start> <end> <byte>`: writes `byte` from `start` to `end`, included.
+/// - `compare <start> <end> <other>`: lists the bytes from `start` to `end` that differ
+///   from as many from `other` on.
+/// - `hunt <start> <end> <pattern>`: lists where `pattern` is from `start` to `end`.
+///   The pattern is bytes, `?` for any byte, `"text"` for ASCII and `p"TEXT"` for
+///   PETSCII, e.g. `hunt $e000 $ffff $20 ? ? "OK"`.
+/// - `save <file> <start> <end> [bin|prg]`: writes the bytes from `start` to `end` to
+///   `file`, quoted if it has spaces, after their load address for `prg`.
+/// - `watch [signed] [byte|word] <address> [as <name>]`: shows the byte, or little-endian
+///   word, at `address` after each stop, called `name` or the address. Without
+///   arguments, shows them all.
+/// - `unwatch <name>`: removes a watch.
+/// - `break [address]`: sets a breakpoint, or lists them.
+/// - `delete <address>`: removes a breakpoint.
+/// - `bk [list]`: lists the breakpoints, with how many times `go` stopped at them.
+/// - `bk add|temp <address>`: sets a breakpoint, deleted once hit for `temp`.
+/// - `bk enable|disable|delete <address>`: turns a breakpoint on or off, or removes it.
+/// - `bk save|load <file>`: writes the breakpoints to `file`, or sets those it has.
+/// - `stack`: the bytes pushed on the stack, from the top on.
+/// - `reset`: presses the reset button.
+/// - `a <address> [instruction]`: assembles `instruction` at `address`, echoing its
+///   disassembly. Lines after it are assembled one after the other, until an empty one.
+/// - `assert <condition>`: fails unless `condition`, an expression which can have spaces
+///   here, is not 0, e.g. `assert a == $00 && mem[$0200] == $55`.
+///
+/// Numbers and addresses are expressions without spaces, like in the assembler, see
+/// `asm6502::expr::Expr`: decimal, or hexadecimal with `0x` or `$` in front, combined
+/// with arithmetic, e.g. `table+3*2`. The registers are named `pc`, `a`, `x`, `y`, `sp`
+/// and `p`, `mem[address]` is the byte at `address`, and `,x` or `,y` after an
+/// expression adds that register, e.g. `table,x`. Comparisons and `&&`, `||` and `!`
+/// give 1 if they hold and 0 otherwise.
+/// With debug information, see `set_debug_info()`, labels and C symbols can be used
+/// too, and where the machine stops is told in the source.
+pub struct Monitor {
+    breakpoints: BTreeMap<u16, Breakpoint>,
+    /// File the breakpoints are written to whenever they change, see
+    /// `set_breakpoints_file()`.
+    breakpoints_file: Option<String>,
+    /// What `watch` shows, in the order the watches were set.
+    watches: Vec<Watch>,
+    /// How many instructions `go` runs at most unless told otherwise.
+    max_instructions: u64,
+    /// Where the next line is assembled, while assembling.
+    assembling: Option<u16>,
+    /// How many times `assert` failed.
+    failed_assertions: u64,
+    /// Where long runs report their progress, see `set_progress()`.
+    progress: Option<Progress>,
+    debug_info: Option<Rc<DebugInfo>>,
+}
+
+impl Monitor {
+    pub fn new(max_instructions: u64) -> Self {
+        Monitor {
+            breakpoints: BTreeMap::new(),
+            breakpoints_file: None,
+            watches: Vec::new(),
+            max_instructions,
+            assembling: None,
+            failed_assertions: 0,
+            progress: None,
+            debug_info: None,
+        }
+    }
+
+    /// Names the addresses and source lines of the program from `debug_info`.
+    pub fn set_debug_info(&mut self, debug_info: Rc<DebugInfo>) {
+        self.debug_info = Some(debug_info);
+    }
+
+    /// Sets the breakpoints of `path`, if it exists, and writes them back to it whenever
+    /// they change, to keep them from one session to the next.
+    ///
+    /// # Returns
+    /// An error if `path` cannot be read or is not a breakpoint file, see `bk save`.
+    pub fn set_breakpoints_file(&mut self, path: &str) -> Result<(), String> {
+        if std::path::Path::new(path).exists() {
+            self.load_breakpoints(path)?;
+        }
+        self.breakpoints_file = Some(path.to_string());
+        Ok(())
+    }
+
+    /// Reports where `step`, `go` and `run` are to `progress` every million cycles or so,
+    /// for front-ends to show that long runs advance.
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = Some(progress);
+    }
+
+    /// # Returns
+    /// How many times `assert` failed, for scripts to fail with it.
+    pub fn failed_assertions(&self) -> u64 {
+        self.failed_assertions
+    }
+
+    /// # Returns
+    /// Where the next line is assembled, if `a` is assembling, for front-ends to
+    /// prompt with.
+    pub fn assembling(&self) -> Option<u16> {
+        self.assembling
+    }
+
+    /// # Returns
+    /// What the last word of `line` can be completed to, in order: the commands starting
+    /// with it if it is the first one, the symbols of the debug information otherwise.
+    pub fn complete(&self, line: &str) -> Vec<String> {
+        let start: usize = line.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+        let word: &str = &line[start..];
+        let candidates: Vec<&str> = if line[..start].trim().is_empty() {
+            COMMANDS.to_vec()
+        } else {
+            self.debug_info
+                .as_ref()
+                .map_or_else(Vec::new, |info| info.symbols().collect())
+        };
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Runs the command `line` on `machine`.
+    ///
+    /// # Returns
+    /// What the command reports, on one line, or an error if the command is unknown
+    /// or its arguments are invalid.
+    pub fn execute(&mut self, machine: &mut Machine, line: &str) -> Result<String, String> {
+        if let Some(address) = self.assembling {
+            return self.assemble(machine, address, line);
+        }
+        let mut words = line.split_whitespace();
+        let command: &str = words.next().unwrap_or("");
+        let arguments: Vec<&str> = words.collect();
+        match (command, arguments.as_slice()) {
+            ("state", []) => Ok(self.located(machine, state(machine))),
+            ("step", ["line"]) if self.debug_info.is_some() => {
+                let info: Rc<DebugInfo> = self.debug_info.clone().unwrap();
+                let line = |machine: &Machine| info.location(machine.cpu().pc()).cloned();
+                let start: Option<Location> = line(machine);
+                for _ in 0..self.max_instructions {
+                    if machine.step() == 0 {
+                        break;
+                    }
+                    let current: Option<Location> = line(machine);
+                    if current.is_some() && current != start {
+                        break;
+                    }
+                }
+                Ok(self.located(machine, state(machine)))
+            }
+            ("step", ["line"]) => Err("`step line` needs debug information".to_string()),
+            ("step", []) | ("step", [_]) => {
+                let count: u64 = arguments
+                    .first()
+                    .map_or(Ok(1), |count| self.value(machine, count))?;
+                let (start, mut reported): (u64, u64) = (machine.cycles(), 0);
+                for _ in 0..count {
+                    if machine.step() == 0 {
+                        break;
+                    }
+                    self.report_progress(machine, start, &mut reported);
+                }
+                Ok(self.located(machine, state(machine)))
+            }
+            ("go", []) | ("go", [_]) => {
+                let count: u64 = arguments
+                    .first()
+                    .map_or(Ok(self.max_instructions), |count| {
+                        self.value(machine, count)
+                    })?;
+                let stop: String = self.go(machine, Limit::Instructions(count));
+                self.breakpoints_changed()?;
+                Ok(self.located(machine, stop))
+            }
+            ("run", ["cycles", count]) => {
+                let count: u64 = self.value(machine, count)?;
+                let stop: String = self.go(machine, Limit::Cycles(count));
+                self.breakpoints_changed()?;
+                Ok(self.located(machine, stop))
+            }
+            ("read", [address]) | ("read", [address, _]) => {
+                let address: u16 = self.value(machine, address)?;
+                let length: usize = arguments
+                    .get(1)
+                    .map_or(Ok(1), |length| self.value(machine, length))?;
+                if length == 0 || length > MAX_READ {
+                    return Err(format!("Length must be 1 to {}", MAX_READ));
+                }
+                let mem = machine.memory().borrow();
+                let bytes: Vec<String> = (0..length)
+                    .map(|offset| format!("{:02x}", mem.read(address.wrapping_add(offset as u16))))
+                    .collect();
+                Ok(format!("{:#06x}: {}", address, bytes.join(" ")))
+            }
+            ("write" | ">", [address, bytes @ ..]) if !bytes.is_empty() => {
+                let address: u16 = self.value(machine, address)?;
+                let bytes: Vec<u8> = bytes
+                    .iter()
+                    .map(|byte| self.value(machine, byte))
+                    .collect::<Result<_, _>>()?;
+                let mut mem = machine.memory().borrow_mut();
+                for (offset, byte) in bytes.iter().enumerate() {
+                    mem.write(address.wrapping_add(offset as u16), *byte);
+                }
+                Ok(format!("Wrote {} bytes at {:#06x}", bytes.len(), address))
+            }
+            ("fill", [start, end, value]) => {
+                let (start, end): (u16, u16) = self.range(machine, start, end)?;
+                let value: u8 = self.value(machine, value)?;
+                machine.memory().borrow_mut().fill(start, end, value);
+                Ok(format!(
+                    "Filled {:#06x}-{:#06x} with {:02x}",
+                    start, end, value
+                ))
+            }
+            ("compare", [start, end, other]) => {
+                let (start, end): (u16, u16) = self.range(machine, start, end)?;
+                let other: u16 = self.value(machine, other)?;
+                let differences: Vec<(u16, u8, u8)> =
+                    machine.memory().borrow().compare(start, end, other);
+                if differences.is_empty() {
+                    return Ok(format!(
+                        "{:#06x}-{:#06x} matches {:#06x} on",
+                        start, end, other
+                    ));
+                }
+                let mut listed: Vec<String> = differences
+                    .iter()
+                    .take(MAX_LISTED)
+                    .map(|(address, value, other)| {
+                        format!("{:#06x}: {:02x}/{:02x}", address, value, other)
+                    })
+                    .collect();
+                if differences.len() > MAX_LISTED {
+                    listed.push("...".to_string());
+                }
+                Ok(format!(
+                    "{} bytes differ: {}",
+                    differences.len(),
+                    listed.join(", ")
+                ))
+            }
+            ("hunt", [start, end, _, ..]) => {
+                let (start, end): (u16, u16) = self.range(machine, start, end)?;
+                let pattern: Vec<Option<u8>> =
+                    parse_pattern(after_words(line, 3), |word| self.value(machine, word))?;
+                let matches: Vec<u16> = machine.memory().borrow().find(start, end, &pattern);
+                if matches.is_empty() {
+                    return Ok(format!("No match in {:#06x}-{:#06x}", start, end));
+                }
+                let mut listed: Vec<String> = matches
+                    .iter()
+                    .take(MAX_LISTED)
+                    .map(|address| format!("{:#06x}", address))
+                    .collect();
+                if matches.len() > MAX_LISTED {
+                    listed.push("...".to_string());
+                }
+                Ok(format!("{} matches: {}", matches.len(), listed.join(" ")))
+            }
+            ("save", [_, ..]) => {
+                let (path, rest): (&str, &str) = parse_file_name(after_words(line, 1))?;
+                let (start, end, prg): (u16, u16, bool) =
+                    match rest.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                        [start, end] | [start, end, "bin"] => {
+                            let (start, end): (u16, u16) = self.range(machine, start, end)?;
+                            (start, end, false)
+                        }
+                        [start, end, "prg"] => {
+                            let (start, end): (u16, u16) = self.range(machine, start, end)?;
+                            (start, end, true)
+                        }
+                        [_, _, format] => return Err(format!("Unknown format `{}`", format)),
+                        _ => return Err("Wrong arguments for `save`".to_string()),
+                    };
+                let mut bytes: Vec<u8> = if prg {
+                    start.to_le_bytes().to_vec()
+                } else {
+                    Vec::new()
+                };
+                let mem = machine.memory().borrow();
+                bytes.extend((start..=end).map(|address| mem.read(address)));
+                std::fs::write(path, &bytes)
+                    .map_err(|error| format!("Cannot write `{}`: {}", path, error))?;
+                Ok(format!("Saved {:#06x}-{:#06x} to `{}`", start, end, path))
+            }
+            ("watch", []) if self.watches.is_empty() => Ok("No watches".to_string()),
+            ("watch", []) => Ok(self.watched(machine)),
+            ("watch", [..]) => {
+                let mut words: &[&str] = arguments.as_slice();
+                let signed: bool = words.first() == Some(&"signed");
+                if signed {
+                    words = &words[1..];
+                }
+                let word: bool = words.first() == Some(&"word");
+                if word || words.first() == Some(&"byte") {
+                    words = &words[1..];
+                }
+                let (address, name): (&str, Option<&str>) = match words {
+                    [address] => (address, None),
+                    [address, "as", name] => (address, Some(name)),
+                    _ => return Err("Wrong arguments for `watch`".to_string()),
+                };
+                let address: u16 = self.value(machine, address)?;
+                let name: String = name.map_or_else(|| format!("{:#06x}", address), str::to_string);
+                self.watches.retain(|watch| watch.name != name);
+                self.watches.push(Watch {
+                    name,
+                    address,
+                    word,
+                    signed,
+                });
+                Ok(format!(
+                    "Watching {}",
+                    self.watches.last().unwrap().show(machine)
+                ))
+            }
+            ("unwatch", [name]) => {
+                let count: usize = self.watches.len();
+                self.watches.retain(|watch| watch.name != *name);
+                if self.watches.len() == count {
+                    return Err(format!("No watch `{}`", name));
+                }
+                Ok(format!("Removed watch `{}`", name))
+            }
+            ("break", []) => {
+                let list: Vec<String> = self
+                    .breakpoints
+                    .keys()
+                    .map(|address| format!("{:#06x}", address))
+                    .collect();
+                Ok(format!("Breakpoints: {}", list.join(" ")))
+            }
+            ("break", [address]) | ("bk", ["add", address]) => {
+                let address: u16 = self.value(machine, address)?;
+                self.breakpoints.insert(address, Breakpoint::new(false));
+                self.breakpoints_changed()?;
+                Ok(format!("Breakpoint at {:#06x}", address))
+            }
+            ("bk", ["temp", address]) => {
+                let address: u16 = self.value(machine, address)?;
+                self.breakpoints.insert(address, Breakpoint::new(true));
+                self.breakpoints_changed()?;
+                Ok(format!("Temporary breakpoint at {:#06x}", address))
+            }
+            ("delete", [address]) | ("bk", ["delete", address]) => {
+                let address: u16 = self.value(machine, address)?;
+                if self.breakpoints.remove(&address).is_none() {
+                    return Err(format!("No breakpoint at {:#06x}", address));
+                }
+                self.breakpoints_changed()?;
+                Ok(format!("Deleted breakpoint at {:#06x}", address))
+            }
+            ("bk", []) | ("bk", ["list"]) if self.breakpoints.is_empty() => {
+                Ok("No breakpoints".to_string())
+            }
+            ("bk", []) | ("bk", ["list"]) => {
+                let list: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|(address, breakpoint)| format!("{:#06x} ({})", address, breakpoint))
+                    .collect();
+                Ok(format!("Breakpoints: {}", list.join(", ")))
+            }
+            ("bk", [action @ ("enable" | "disable"), address]) => {
+                let address: u16 = self.value(machine, address)?;
+                let breakpoint: &mut Breakpoint = self
+                    .breakpoints
+                    .get_mut(&address)
+                    .ok_or_else(|| format!("No breakpoint at {:#06x}", address))?;
+                breakpoint.enabled = *action == "enable";
+                self.breakpoints_changed()?;
+                Ok(format!(
+                    "{}d breakpoint at {:#06x}",
+                    capitalized(action),
+                    address
+                ))
+            }
+            ("bk", ["save", _, ..]) => {
+                let (path, _): (&str, &str) = parse_file_name(after_words(line, 2))?;
+                self.save_breakpoints(path)?;
+                Ok(format!(
+                    "Saved {} breakpoints to `{}`",
+                    self.breakpoints.len(),
+                    path
+                ))
+            }
+            ("bk", ["load", _, ..]) => {
+                let (path, _): (&str, &str) = parse_file_name(after_words(line, 2))?;
+                let count: usize = self.load_breakpoints(path)?;
+                self.breakpoints_changed()?;
+                Ok(format!("Loaded {} breakpoints from `{}`", count, path))
+            }
+            ("stack", []) => {
+                let top: u16 = 0x0100 + machine.cpu().sp() as u16 + 1;
+                if top > 0x01ff {
+                    return Ok("Stack is empty".to_string());
+                }
+                let mem = machine.memory().borrow();
+                let bytes: Vec<String> = (top..=0x01ff)
+                    .map(|address| format!("{:02x}", mem.read(address)))
+                    .collect();
+                Ok(format!("{:#06x}: {}", top, bytes.join(" ")))
+            }
+            ("reset", []) => {
+                machine.reset();
+                Ok(self.located(machine, state(machine)))
+            }
+            ("a", [address, ..]) => {
+                let instruction: &str = after_words(line, 2);
+                let address: u16 = self.value(machine, address)?;
+                self.assembling = Some(address);
+                if instruction.is_empty() {
+                    return Ok(format!(
+                        "Assembling at {:#06x}, an empty line stops",
+                        address
+                    ));
+                }
+                self.assemble(machine, address, instruction)
+            }
+            ("assert", [_, ..]) => {
+                let condition: &str = after_words(line, 1);
+                if self.value::<i64>(machine, condition)? == 0 {
+                    self.failed_assertions += 1;
+                    return Err(format!("Assertion failed: {}", condition));
+                }
+                Ok(format!("Assertion holds: {}", condition))
+            }
+            ("", _) => Err("No command given".to_string()),
+            (
+                "state" | "step" | "go" | "run" | "read" | "write" | ">" | "fill" | "compare"
+                | "hunt" | "save" | "unwatch" | "break" | "bk" | "delete" | "stack" | "reset" | "a"
+                | "assert",
+                _,
+            ) => Err(format!("Wrong arguments for `{}`", command)),
+            _ => Err(format!("Unknown command `{}`", command)),
+        }
+    }
+
+    /// # Returns
+    /// The value of the expression `text` on `machine`, see `Monitor`, or an error if
+    /// it is invalid or does not fit a `T`.
+    fn value<T: TryFrom<i64>>(&self, machine: &Machine, text: &str) -> Result<T, String> {
+        let cpu: &Mos6502 = machine.cpu();
+        let (expression, index): (&str, u8) = match text.rsplit_once(',') {
+            Some((base, "x" | "X")) => (base, cpu.x()),
+            Some((base, "y" | "Y")) => (base, cpu.y()),
+            _ => (text, 0),
+        };
+        let symbol = |name: &str, index: Option<i64>| -> Option<i64> {
+            if let Some(address) = index {
+                let address: u16 = u16::try_from(address).ok().filter(|_| name == "mem")?;
+                return Some(machine.memory().borrow().read(address) as i64);
+            }
+            match name {
+                "pc" => Some(cpu.pc() as i64),
+                "a" => Some(cpu.a() as i64),
+                "x" => Some(cpu.x() as i64),
+                "y" => Some(cpu.y() as i64),
+                "sp" => Some(cpu.sp() as i64),
+                "p" => Some(cpu.status().bits() as i64),
+                _ => self.debug_info.as_ref()?.symbol(name).map(i64::from),
+            }
+        };
+        let value: i64 = Expr::parse(expression)?.evaluate_with(&symbol, cpu.pc())? + index as i64;
+        T::try_from(value).map_err(|_| format!("`{}` is {}, out of range", text, value))
+    }
+
+    /// # Returns
+    /// The addresses `start` and `end` are, see `value()`, or an error if `start` is
+    /// after `end`.
+    fn range(&self, machine: &Machine, start: &str, end: &str) -> Result<(u16, u16), String> {
+        let (start, end): (u16, u16) = (self.value(machine, start)?, self.value(machine, end)?);
+        if start > end {
+            return Err(format!("{:#06x} is after {:#06x}", start, end));
+        }
+        Ok((start, end))
+    }
+
+    /// # Returns
+    /// The source line `address` was built from and the symbol it is in, if known,
+    /// e.g. `hello.c:4 main+3`.
+    fn location(&self, address: u16) -> Option<String> {
+        let info: &DebugInfo = self.debug_info.as_ref()?;
+        let line: String = info.location(address)?.to_string();
+        Some(match info.symbol_at(address) {
+            Some((name, 0)) => format!("{} {}", line, name),
+            Some((name, offset)) => format!("{} {}+{}", line, name, offset),
+            None => line,
+        })
+    }
+
+    /// Writes the breakpoints to `path`, a line per breakpoint: its address, then
+    /// `disabled` and `temporary` if it is.
+    ///
+    /// # Returns
+    /// An error if `path` cannot be written.
+    fn save_breakpoints(&self, path: &str) -> Result<(), String> {
+        let lines: String = self
+            .breakpoints
+            .iter()
+            .map(|(address, breakpoint)| {
+                let mut line: String = format!("{:#06x}", address);
+                if !breakpoint.enabled {
+                    line.push_str(" disabled");
+                }
+                if breakpoint.temporary {
+                    line.push_str(" temporary");
+                }
+                line + "\n"
+            })
+            .collect();
+        std::fs::write(path, lines).map_err(|error| format!("Cannot write `{}`: {}", path, error))
+    }
+
+    /// Sets the breakpoints of the file `path`, see `save_breakpoints()`.
+    ///
+    /// # Returns
+    /// How many there are, or an error if `path` cannot be read or has an invalid line, in
+    /// which case none is set.
+    fn load_breakpoints(&mut self, path: &str) -> Result<usize, String> {
+        let text: String = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read `{}`: {}", path, error))?;
+        let mut loaded: Vec<(u16, Breakpoint)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let invalid = || format!("{}:{}: invalid breakpoint `{}`", path, number + 1, line);
+            let mut words = line.split_whitespace();
+            let Some(address) = words.next() else {
+                continue;
+            };
+            let address: u16 = parse_number(address).map_err(|_| invalid())?;
+            let mut breakpoint: Breakpoint = Breakpoint::new(false);
+            for word in words {
+                match word {
+                    "disabled" => breakpoint.enabled = false,
+                    "temporary" => breakpoint.temporary = true,
+                    _ => return Err(invalid()),
+                }
+            }
+            loaded.push((address, breakpoint));
+        }
+        let count: usize = loaded.len();
+        self.breakpoints.extend(loaded);
+        Ok(count)
+    }
+
+    /// Writes the breakpoints to the file of `set_breakpoints_file()`, if any.
+    ///
+    /// # Returns
+    /// An error if the file cannot be written.
+    fn breakpoints_changed(&self) -> Result<(), String> {
+        match &self.breakpoints_file {
+            Some(path) => self.save_breakpoints(path),
+            None => Ok(()),
+        }
+    }
+
+    /// # Returns
+    /// `reply`, followed by where in the source the machine is, if known, and by the
+    /// watches.
+    fn located(&self, machine: &Machine, reply: String) -> String {
+        let reply: String = match self.location(machine.cpu().pc()) {
+            Some(location) => format!("{} at {}", reply, location),
+            None => reply,
+        };
+        if self.watches.is_empty() {
+            return reply;
+        }
+        format!("{}; {}", reply, self.watched(machine))
+    }
+
+    /// # Returns
+    /// The values of the watches, e.g. `raster=0x3f ptr=0xc000`.
+    fn watched(&self, machine: &Machine) -> String {
+        let values: Vec<String> = self
+            .watches
+            .iter()
+            .map(|watch| watch.show(machine))
+            .collect();
+        values.join(" ")
+    }
+
+    /// Assembles `line` at `address` into the memory of `machine`, the next line going
+    /// after it, or stops assembling if `line` is empty. A line that does not assemble
+    /// can be typed again.
+    ///
+    /// # Returns
+    /// The address, the bytes and the disassembly of the instruction.
+    fn assemble(
+        &mut self,
+        machine: &mut Machine,
+        address: u16,
+        line: &str,
+    ) -> Result<String, String> {
+        if line.trim().is_empty() {
+            self.assembling = None;
+            return Ok("Stopped assembling".to_string());
+        }
+        let bytes: Vec<u8> = asm6502::assemble_line(line, address)?;
+        let mut mem = machine.memory().borrow_mut();
+        for (offset, byte) in bytes.iter().enumerate() {
+            mem.write(address.wrapping_add(offset as u16), *byte);
+        }
+        drop(mem);
+        self.assembling = Some(address.wrapping_add(bytes.len() as u16));
+
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!(
+            "{:#06x}: {:<8}  {}",
+            address,
+            hex.join(" "),
+            machine.cpu().disassemble(address)
+        ))
+    }
+
+    /// Runs up to `limit`, stopping before an enabled breakpoint other than the one at
+    /// PC, or when the CPU halts. The breakpoint stopped at counts the hit, and is
+    /// deleted if temporary.
+    ///
+    /// # Returns
+    /// Why and where the machine stopped.
+    fn go(&mut self, machine: &mut Machine, limit: Limit) -> String {
+        let (start, mut reported): (u64, u64) = (machine.cycles(), 0);
+        let mut executed: u64 = 0;
+        loop {
+            let pc: u16 = machine.cpu().pc();
+            let elapsed: u64 = machine.cycles() - start;
+            match limit {
+                Limit::Instructions(count) if executed >= count => {
+                    return format!("Stopped at {:#06x} after {} instructions", pc, count);
+                }
+                Limit::Cycles(count) if elapsed >= count => {
+                    return format!("Stopped at {:#06x} after {} cycles", pc, elapsed);
+                }
+                _ => {}
+            }
+            if let Some(breakpoint) = self.breakpoints.get_mut(&pc) {
+                if executed > 0 && breakpoint.enabled {
+                    breakpoint.hits += 1;
+                    if !breakpoint.temporary {
+                        return format!("Break at {:#06x}", pc);
+                    }
+                    self.breakpoints.remove(&pc);
+                    return format!("Break at {:#06x}, deleting its temporary breakpoint", pc);
+                }
+            }
+            if machine.step() == 0 {
+                let cpu: &Mos6502 = machine.cpu();
+                return match (cpu.trapped_at(), cpu.halt_reason()) {
+                    (Some(pc), _) => format!("Trapped at {:#06x}", pc),
+                    (None, Some(HaltReason::Jam)) => format!(
+                        "Halted at {:#06x} (jam) after {}",
+                        cpu.pc(),
+                        instructions_before(cpu)
+                    ),
+                    (None, reason) => format!(
+                        "Halted at {:#06x} ({})",
+                        cpu.pc(),
+                        reason.map_or("stopped".to_string(), |reason| reason.to_string())
+                    ),
+                };
+            }
+            executed += 1;
+            self.report_progress(machine, start, &mut reported);
+        }
+    }
+
+    /// Tells the progress sink, if any, where a run that started at cycle `start` is,
+    /// once `PROGRESS_CYCLES` have passed since the last report, `reported` cycles in.
+    fn report_progress(&mut self, machine: &Machine, start: u64, reported: &mut u64) {
+        let elapsed: u64 = machine.cycles() - start;
+        if elapsed - *reported < PROGRESS_CYCLES {
+            return;
+        }
+        *reported = elapsed;
+        if let Some(progress) = &mut self.progress {
+            progress(&format!(
+                "Running at {:#06x}, {} cycles in",
+                machine.cpu().pc(),
+                elapsed
+            ));
+        }
+    }
+}
+
+/// How far `go` runs at most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Limit {
+    Instructions(u64),
+    /// Cycles, finishing the instruction the last one is in.
+    Cycles(u64),
+}
+
+/// A place `go` stops before, see `break` and `bk`.
+struct Breakpoint {
+    /// Whether `go` stops there, `bk disable` keeping it for later.
+    enabled: bool,
+    /// Whether the breakpoint is deleted once hit.
+    temporary: bool,
+    /// How many times `go` stopped there.
+    hits: u64,
+}
+
+impl Breakpoint {
+    fn new(temporary: bool) -> Self {
+        Breakpoint {
+            enabled: true,
+            temporary,
+            hits: 0,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    /// Formats the state of the breakpoint, e.g. `disabled, 2 hits`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.enabled {
+            write!(f, "disabled, ")?;
+        }
+        if self.temporary {
+            write!(f, "temporary, ")?;
+        }
+        match self.hits {
+            1 => write!(f, "1 hit"),
+            hits => write!(f, "{} hits", hits),
+        }
+    }
+}
+
+/// A memory location the monitor shows after each stop, see `watch`.
+struct Watch {
+    name: String,
+    address: u16,
+    /// Whether the value is the little-endian word at `address`, not the byte.
+    word: bool,
+    /// Whether the value is shown as a signed decimal number, not in hexadecimal.
+    signed: bool,
+}
+
+impl Watch {
+    /// # Returns
+    /// The name and value of the watch on `machine`, e.g. `ptr=0xc000` or `delta=-3`.
+    fn show(&self, machine: &Machine) -> String {
+        let mem = machine.memory().borrow();
+        let low: u8 = mem.read(self.address);
+        let value: String = match (self.word, self.signed) {
+            (false, false) => format!("{:#04x}", low),
+            (false, true) => (low as i8).to_string(),
+            (true, signed) => {
+                let word: u16 = u16::from_le_bytes([low, mem.read(self.address.wrapping_add(1))]);
+                if signed {
+                    (word as i16).to_string()
+                } else {
+                    format!("{:#06x}", word)
+                }
+            }
+        };
+        format!("{}={}", self.name, value)
+    }
+}
+
+/// # Returns
+/// The registers of the CPU of `machine`, its cycle count and whether it is halted.
+pub fn state(machine: &Machine) -> String {
+    let cpu = machine.cpu();
+    format!(
+        "pc={:#06x} a={:#04x} x={:#04x} y={:#04x} sp={:#04x} p={:#04x} flags={} cycles={} halted={}",
+        cpu.pc(),
+        cpu.a(),
+        cpu.x(),
+        cpu.y(),
+        cpu.sp(),
+        cpu.status().bits(),
+        cpu.status(),
+        cpu.cycles(),
+        cpu.is_halted()
+    )
+}
+
+/// # Returns
+/// `word` with its first letter in uppercase, e.g. `Enable` for `enable`.
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// # Returns
+/// `line` without its first `count` words, trimmed, e.g. the instruction of `a <address>
+/// <instruction>`, which keeps its spaces.
+fn after_words(line: &str, count: usize) -> &str {
+    let mut rest: &str = line.trim_start();
+    for _ in 0..count {
+        rest = rest
+            .trim_start_matches(|c: char| !c.is_whitespace())
+            .trim_start();
+    }
+    rest.trim_end()
+}
+
+/// # Returns
+/// The file name `text` starts with, between double quotes if it has spaces, and the
+/// text after it.
+fn parse_file_name(text: &str) -> Result<(&str, &str), String> {
+    let (name, rest): (&str, &str) = match text.strip_prefix('"') {
+        Some(quoted) => quoted
+            .split_once('"')
+            .ok_or_else(|| format!("Unterminated file name in `{}`", text))?,
+        None => text.split_at(text.find(char::is_whitespace).unwrap_or(text.len())),
+    };
+    if name.is_empty() {
+        return Err("No file name given".to_string());
+    }
+    Ok((name, rest))
+}
+
+/// # Returns
+/// The bytes `hunt` looks for, `None` for those matching any value, or an error if
+/// `text` is not a pattern, see `Monitor`. `byte` gives the value of the other words.
+fn parse_pattern(
+    text: &str,
+    byte: impl Fn(&str) -> Result<u8, String>,
+) -> Result<Vec<Option<u8>>, String> {
+    let mut pattern: Vec<Option<u8>> = Vec::new();
+    let mut rest: &str = text.trim_start();
+    while !rest.is_empty() {
+        let string: Option<(bool, &str)> = match rest.strip_prefix("p\"") {
+            Some(quoted) => Some((true, quoted)),
+            None => rest.strip_prefix('"').map(|quoted| (false, quoted)),
+        };
+        if let Some((petscii, quoted)) = string {
+            let (string, after): (&str, &str) = quoted
+                .split_once('"')
+                .ok_or_else(|| format!("Unterminated string in `{}`", text))?;
+            for c in string.chars() {
+                let byte: Option<u8> = if petscii {
+                    Charset::Petscii.encode(c)
+                } else {
+                    c.is_ascii().then_some(c as u8)
+                };
+                let charset: &str = if petscii { "PETSCII" } else { "ASCII" };
+                pattern.push(Some(
+                    byte.ok_or_else(|| format!("`{}` is not {}", c, charset))?,
+                ));
+            }
+            rest = after.trim_start();
+            continue;
+        }
+        let (word, after): (&str, &str) =
+            rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        pattern.push(match word {
+            "?" | "??" => None,
+            _ => Some(byte(word)?),
+        });
+        rest = after.trim_start();
+    }
+    if pattern.is_empty() {
+        return Err("Empty pattern".to_string());
+    }
+    Ok(pattern)
+}
+
+/// # Returns
+/// The addresses of the last `MAX_LISTED` instructions before the one at PC, from the
+/// PC history of `cpu`, oldest first.
+fn instructions_before(cpu: &Mos6502) -> String {
+    let history: Vec<u16> = cpu.pc_history().map(|(pc, _)| pc).collect();
+    let end: usize = history.len().saturating_sub(1);
+    let listed: Vec<String> = history[end.saturating_sub(MAX_LISTED)..end]
+        .iter()
+        .map(|pc| format!("{:#06x}", pc))
+        .collect();
+    listed.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    #[test]
+    fn breakpoints_stop_go() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // INX, INX, JMP $0000
+        monitor
+            .execute(&mut machine, "write 0 0xe8 0xe8 0x4c 0 0")
+            .unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "read $0000 3"),
+            Ok("0x0000: e8 e8 4c".to_string())
+        );
+        monitor.execute(&mut machine, "break 1").unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "go"),
+            Ok("Break at 0x0001".to_string())
+        );
+        // Going on from a breakpoint passes it
+        assert_eq!(
+            monitor.execute(&mut machine, "go 2"),
+            Ok("Stopped at 0x0000 after 2 instructions".to_string())
+        );
+        monitor.execute(&mut machine, "delete 1").unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "go 3"),
+            Ok("Stopped at 0x0000 after 3 instructions".to_string())
+        );
+        assert!(monitor
+            .execute(&mut machine, "state")
+            .unwrap()
+            .starts_with("pc=0x0000 a=0x00 x=0x04"));
+
+        machine.cpu_mut().set_sp(0xfd);
+        monitor
+            .execute(&mut machine, "write $01fe $34 $12")
+            .unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "stack"),
+            Ok("0x01fe: 34 12".to_string())
+        );
+
+        assert!(monitor.execute(&mut machine, "delete 1").is_err());
+        assert!(monitor.execute(&mut machine, "read").is_err());
+        assert!(monitor.execute(&mut machine, "jump 0").is_err());
+    }
+
+    #[test]
+    fn lists_the_instructions_before_a_jam() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // INX, INX, then an undocumented opcode
+        monitor
+            .execute(&mut machine, "write 0 0xe8 0xe8 0x02")
+            .unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "go"),
+            Ok("Halted at 0x0002 (jam) after 0x0000 0x0001".to_string())
+        );
+    }
+
+    #[test]
+    fn hunts_bytes_strings_and_wildcards() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // LDA #$01, STA $d020, then "HI THERE" twice, the second time in PETSCII
+        monitor
+            .execute(&mut machine, "write $1000 $a9 1 $8d $20 $d0")
+            .unwrap();
+        monitor
+            .execute(&mut machine, "write $2000 $48 $49 $20 $54 $48 $45 $52 $45")
+            .unwrap();
+        monitor
+            .execute(&mut machine, "write $3000 $48 $49 $20 $54 $48 $45 $52 $45")
+            .unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "hunt 0 $ffff $a9 ? $8d ?? $d0"),
+            Ok("1 matches: 0x1000".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, r#"hunt 0 $ffff "HI THERE""#),
+            Ok("2 matches: 0x2000 0x3000".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, r#"hunt $2001 $ffff p"hi there""#),
+            Ok("1 matches: 0x3000".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, r#"hunt 0 $ffff "hi there""#),
+            Ok("No match in 0x0000-0xffff".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, r#"hunt 0 $ffff "HI"#),
+            Err(r#"Unterminated string in `"HI`"#.to_string())
+        );
+    }
+
+    #[test]
+    fn saves_binaries_and_prgs() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        monitor
+            .execute(&mut machine, "write $c000 $a9 1 $60")
+            .unwrap();
+        let path: std::path::PathBuf = std::env::temp_dir().join("monitor save.prg");
+        let name: &str = path.to_str().unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, &format!(r#"save "{}" $c000 $c002"#, name)),
+            Ok(format!("Saved 0xc000-0xc002 to `{}`", name))
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), [0xa9, 0x01, 0x60]);
+        monitor
+            .execute(&mut machine, &format!(r#"save "{}" $c000 $c002 prg"#, name))
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            [0x00, 0xc0, 0xa9, 0x01, 0x60]
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "save out.bin $c000 $c002 hex"),
+            Err("Unknown format `hex`".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "save out.bin $c002 $c000"),
+            Err("0xc002 is after 0xc000".to_string())
+        );
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // LDX #$02, LDA #$10
+        monitor
+            .execute(&mut machine, "write 0 $a2 2 $a9 $10")
+            .unwrap();
+        monitor.execute(&mut machine, "step 2").unwrap();
+        monitor
+            .execute(&mut machine, "write $1000+a a*2 x<<4 pc")
+            .unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "read $1010 3"),
+            Ok("0x1010: 20 20 04".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "read $100e,x 0x4-1"),
+            Ok("0x1010: 20 20 04".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "read $10000-1"),
+            Ok("0xffff: 00".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "read $ffff,x"),
+            Err("`$ffff,x` is 65537, out of range".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "read nowhere+1"),
+            Err("Undefined symbol `nowhere`".to_string())
+        );
+    }
+
+    #[test]
+    fn completes_commands_and_symbols() {
+        let mut monitor: Monitor = Monitor::new(1000);
+        assert_eq!(monitor.complete("s"), ["state", "step", "save", "stack"]);
+        assert_eq!(monitor.complete("  re"), ["read", "reset"]);
+        assert!(monitor.complete("break ma").is_empty());
+
+        let info: DebugInfo = DebugInfo::parse(
+            "seg\tid=0,name=\"CODE\",start=0x0200,size=6
+sym\tid=0,name=\"main\",scope=0,def=0,val=0x200,seg=0,type=lab
+sym\tid=1,name=\"loop\",scope=0,def=0,val=0x203,seg=0,type=lab",
+        )
+        .unwrap();
+        monitor.set_debug_info(Rc::new(info));
+        assert_eq!(monitor.complete("break ma"), ["main"]);
+        assert_eq!(monitor.complete("compare main "), ["loop", "main"]);
+    }
+
+    #[test]
+    fn manages_and_keeps_breakpoints() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        let path: std::path::PathBuf = std::env::temp_dir().join("monitor_breakpoints.txt");
+        let name: &str = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+        monitor.set_breakpoints_file(name).unwrap();
+        // INX, INX, JMP $0000
+        monitor
+            .execute(&mut machine, "write 0 0xe8 0xe8 0x4c 0 0")
+            .unwrap();
+        monitor.execute(&mut machine, "bk add 1").unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "bk temp 2"),
+            Ok("Temporary breakpoint at 0x0002".to_string())
+        );
+
+        assert_eq!(
+            monitor.execute(&mut machine, "go"),
+            Ok("Break at 0x0001".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "go"),
+            Ok("Break at 0x0002, deleting its temporary breakpoint".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "bk disable 1"),
+            Ok("Disabled breakpoint at 0x0001".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "go 4"),
+            Ok("Stopped at 0x0000 after 4 instructions".to_string())
+        );
+        monitor.execute(&mut machine, "bk temp 0").unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "bk list"),
+            Ok("Breakpoints: 0x0000 (temporary, 0 hits), 0x0001 (disabled, 1 hit)".to_string())
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "0x0000 temporary\n0x0001 disabled\n"
+        );
+
+        let mut restored: Monitor = Monitor::new(1000);
+        restored.set_breakpoints_file(name).unwrap();
+        assert_eq!(
+            restored.execute(&mut machine, "bk delete 0"),
+            Ok("Deleted breakpoint at 0x0000".to_string())
+        );
+        assert_eq!(
+            restored.execute(&mut machine, "bk"),
+            Ok("Breakpoints: 0x0001 (disabled, 0 hits)".to_string())
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "0x0001 disabled\n");
+        std::fs::write(&path, "0x0001 sometimes\n").unwrap();
+        assert_eq!(
+            Monitor::new(1000).set_breakpoints_file(name),
+            Err(format!("{}:1: invalid breakpoint `0x0001 sometimes`", name))
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(restored.execute(&mut machine, "bk enable 2").is_err());
+    }
+
+    #[test]
+    fn asserts_conditions() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // LDA #$00
+        monitor.execute(&mut machine, "write 0 $a9 0").unwrap();
+        monitor.execute(&mut machine, "write $0200 $55").unwrap();
+        monitor.execute(&mut machine, "step").unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "assert a == $00 && mem[$0200] == $55"),
+            Ok("Assertion holds: a == $00 && mem[$0200] == $55".to_string())
+        );
+        assert_eq!(monitor.failed_assertions(), 0);
+        assert_eq!(
+            monitor.execute(&mut machine, "assert pc != 2 || !mem[$0200]"),
+            Err("Assertion failed: pc != 2 || !mem[$0200]".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "assert mem[$10000]"),
+            Err("Invalid index 65536 of `mem`".to_string())
+        );
+        assert_eq!(monitor.failed_assertions(), 1);
+    }
+
+    #[test]
+    fn runs_for_cycles_reporting_progress() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        let reports: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+        let sink: Rc<RefCell<Vec<String>>> = reports.clone();
+        monitor.set_progress(Box::new(move |report: &str| {
+            sink.borrow_mut().push(report.to_string())
+        }));
+        // NOP, JMP $0000, 5 cycles
+        monitor
+            .execute(&mut machine, "write 0 $ea $4c 0 0")
+            .unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "run cycles 11"),
+            Ok("Stopped at 0x0001 after 12 cycles".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "run cycles 2500000"),
+            Ok("Stopped at 0x0001 after 2500000 cycles".to_string())
+        );
+        assert_eq!(
+            *reports.borrow(),
+            [
+                "Running at 0x0001, 1000000 cycles in",
+                "Running at 0x0001, 2000000 cycles in"
+            ]
+        );
+        monitor.execute(&mut machine, "break 0").unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "run cycles 100"),
+            Ok("Break at 0x0000".to_string())
+        );
+        assert!(monitor.execute(&mut machine, "run 100").is_err());
+    }
+
+    #[test]
+    fn watches_are_shown_after_each_stop() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        // INC $10, DEC $fb
+        monitor
+            .execute(&mut machine, "write 0 $e6 $10 $c6 $fb")
+            .unwrap();
+        monitor.execute(&mut machine, "write $fb $ff $c0").unwrap();
+
+        assert_eq!(
+            monitor.execute(&mut machine, "watch $10 as count"),
+            Ok("Watching count=0x00".to_string())
+        );
+        monitor
+            .execute(&mut machine, "watch word $fb as ptr")
+            .unwrap();
+        monitor.execute(&mut machine, "watch signed $fb").unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "go 2"),
+            Ok(
+                "Stopped at 0x0004 after 2 instructions; count=0x01 ptr=0xc0fe 0x00fb=-2"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "unwatch ptr"),
+            Ok("Removed watch `ptr`".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "watch"),
+            Ok("count=0x01 0x00fb=-2".to_string())
+        );
+        assert!(monitor.execute(&mut machine, "unwatch ptr").is_err());
+        assert!(monitor.execute(&mut machine, "watch word").is_err());
+    }
+
+    #[test]
+    fn modifies_fills_and_compares_memory() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+
+        assert_eq!(
+            monitor.execute(&mut machine, "fill $1000 $10ff $ea"),
+            Ok("Filled 0x1000-0x10ff with ea".to_string())
+        );
+        monitor
+            .execute(&mut machine, "fill $2000 $20ff $ea")
+            .unwrap();
+        assert_eq!(
+            monitor.execute(&mut machine, "compare $1000 $10ff $2000"),
+            Ok("0x1000-0x10ff matches 0x2000 on".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "> $2010 1 2"),
+            Ok("Wrote 2 bytes at 0x2010".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, "compare $1000 $10ff $2000"),
+            Ok("2 bytes differ: 0x1010: ea/01, 0x1011: ea/02".to_string())
+        );
+        monitor.execute(&mut machine, "fill $2000 $20ff 0").unwrap();
+        assert!(monitor
+            .execute(&mut machine, "compare $1000 $10ff $2000")
+            .unwrap()
+            .starts_with("256 bytes differ: 0x1000: ea/00, "));
+
+        assert!(monitor.execute(&mut machine, "fill $10ff $1000 0").is_err());
+        assert!(monitor
+            .execute(&mut machine, "fill $1000 $10ff $100")
+            .is_err());
+        assert!(monitor.execute(&mut machine, ">").is_err());
+    }
+
+    #[test]
+    fn reports_source_lines() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+        let info: DebugInfo = DebugInfo::parse(
+            "file\tid=0,name=\"main.c\"
+seg\tid=0,name=\"CODE\",start=0x0200,size=6
+span\tid=0,seg=0,start=0,size=2
+span\tid=1,seg=0,start=2,size=4
+line\tid=0,file=0,line=3,type=1,span=0
+line\tid=1,file=0,line=4,type=1,span=1
+sym\tid=0,name=\"_main\",scope=0,def=0,val=0x200,seg=0,type=lab
+csym\tid=0,name=\"main\",scope=0,type=0,sc=ext,sym=0",
+        )
+        .unwrap();
+        monitor.set_debug_info(Rc::new(info));
+        // INX, INX, INX, JMP $0200
+        monitor
+            .execute(&mut machine, "write main 0xe8 0xe8 0xe8 0x4c 0 2")
+            .unwrap();
+        machine.cpu_mut().set_pc(0x0200);
+
+        assert_eq!(
+            monitor.execute(&mut machine, "break main"),
+            Ok("Breakpoint at 0x0200".to_string())
+        );
+        assert!(monitor
+            .execute(&mut machine, "step line")
+            .unwrap()
+            .ends_with(" at main.c:4 main+2"));
+        assert_eq!(
+            monitor.execute(&mut machine, "go"),
+            Ok("Break at 0x0200 at main.c:3 main".to_string())
+        );
+        assert!(monitor.execute(&mut machine, "read nowhere").is_err());
+    }
+
+    #[test]
+    fn assembles_lines_into_memory() {
+        let mut machine: Machine = Machine::new();
+        let mut monitor: Monitor = Monitor::new(1000);
+
+        assert_eq!(
+            monitor.execute(&mut machine, "a $1000 lda #$01"),
+            Ok("0x1000: a9 01     LDA #$01".to_string())
+        );
+        assert_eq!(monitor.assembling(), Some(0x1002));
+        assert!(monitor.execute(&mut machine, "lda #$1234").is_err());
+        assert_eq!(
+            monitor.execute(&mut machine, "bne $1000"),
+            Ok("0x1002: d0 fc     BNE $1000".to_string())
+        );
+        assert_eq!(
+            monitor.execute(&mut machine, ""),
+            Ok("Stopped assembling".to_string())
+        );
+        assert_eq!(monitor.assembling(), None);
+        assert_eq!(
+            monitor.execute(&mut machine, "read $1000 4"),
+            Ok("0x1000: a9 01 d0 fc".to_string())
+        );
+    }
+}
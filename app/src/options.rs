@@ -0,0 +1,550 @@
+/// Settings for the headless CI mode.
+pub struct CiOptions {
+    /// Address checked once the program stops.
+    pub result_addr: u16,
+    /// Value expected at `result_addr` for the run to pass.
+    pub expect: u8,
+}
+
+/// Settings of the WebSocket dashboard stream, see `dashboard::Dashboard`.
+pub struct DashboardOptions {
+    /// Local TCP port clients connect to.
+    pub port: u16,
+    /// Updates sent per second at most.
+    pub rate: f64,
+    /// Send the frame buffer along with the registers.
+    pub frames: bool,
+}
+
+use crate::serial::SerialHost;
+use crate::speed;
+
+use devices::charset::Charset;
+use devices::dma;
+use devices::random;
+use devices::timer;
+use machine::preset::Preset;
+use mos6502::logging::{Category, LogLevel};
+
+/// Command line options.
+pub struct Options {
+    pub rom_path: Option<String>,
+    /// Machine configuration the ROM is loaded into.
+    pub machine: Preset,
+    /// Where the bare machine loads the binary, instead of $0000.
+    pub load_address: Option<u16>,
+    /// Point the reset vector at the entry and the other vectors at a halt handler,
+    /// where the binary left them at zero.
+    pub auto_vectors: bool,
+    /// Halt the CPU at any `JMP` or branch to itself, see `Mos6502::set_trap_detection()`.
+    /// On by default for raw binaries on the bare machine, off for the other machines,
+    /// whose programs wait in such loops for interrupts.
+    pub stop_on_trap: bool,
+    /// Where the installed reset vector points, defaults to the load address.
+    pub entry: Option<u16>,
+    /// Character generator ROM for machines with a VIC-II.
+    pub char_rom_path: Option<String>,
+    /// BASIC ROM for machines that have one.
+    pub basic_rom_path: Option<String>,
+    pub ci: Option<CiOptions>,
+    /// File to write a newline-delimited JSON record of every instruction to.
+    pub trace_json: Option<String>,
+    /// The ld65 debug information of the program, `.dbg`, naming its source lines.
+    pub debug_info_path: Option<String>,
+    /// The CPU log levels, printed to stdout, e.g. from `--log exec=debug,irq=info`.
+    pub log_levels: Vec<(Category, LogLevel)>,
+    /// Hash the CPU state every this many instructions and print the digest.
+    pub state_hash: Option<u64>,
+    /// Run the program through the reference core as well, stopping at the first
+    /// difference, see `ci::differential()`.
+    pub differential: bool,
+    /// Maximum number of instructions to execute in headless mode before giving up.
+    pub timeout_instr: u64,
+    /// Address to serve the remote-control protocol on, see `remote::serve()`.
+    pub listen: Option<String>,
+    /// File keeping the lines typed in the monitor across sessions, see `History`.
+    pub history: Option<String>,
+    /// File keeping the breakpoints of the monitor across sessions, see
+    /// `Monitor::set_breakpoints_file()`.
+    pub breakpoints: Option<String>,
+    /// File of monitor commands to run instead of the interactive menu, see
+    /// `script::run()`.
+    pub script: Option<String>,
+    /// File to record external inputs to.
+    pub record: Option<String>,
+    /// File to replay external inputs from.
+    pub replay: Option<String>,
+    /// Clock frequency interactive runs are throttled to, in Hz.
+    /// Defaults to the clock of the machine.
+    pub clock_hz: Option<f64>,
+    /// Start interactive runs unthrottled.
+    pub warp: bool,
+    /// CRT cartridge image to plug in.
+    pub cart_path: Option<String>,
+    /// Expansion RAM of the REU to plug in, in KiB.
+    pub reu_kb: Option<usize>,
+    /// D64 disk image to load a program from.
+    pub d64_path: Option<String>,
+    /// Apple DOS 3.3 disk image to run a binary file from.
+    pub dsk_path: Option<String>,
+    /// T64 or TAP tape image to load a program from.
+    pub tape_path: Option<String>,
+    /// Name of the program to load from the image, CBM DOS wildcards allowed.
+    pub load_name: String,
+    /// PRG file to load into RAM.
+    pub prg_path: Option<String>,
+    /// Atari 8-bit executable to load into RAM and start.
+    pub xex_path: Option<String>,
+    /// Emulate the KERNAL console routines on the host and start the loaded program.
+    pub kernal_hle: bool,
+    /// PSID tune to play instead of running a program.
+    pub sid_path: Option<String>,
+    /// Song of the tune to play, defaults to its start song.
+    pub song: Option<u16>,
+    /// How long the tune is played for, in seconds.
+    pub play_seconds: u64,
+    /// VICE snapshot to resume the session of.
+    pub vsf_path: Option<String>,
+    /// Image file to show the screen of the machine in.
+    pub frame_path: Option<String>,
+    /// Draw the screen RAM as text in the terminal.
+    pub text_screen: bool,
+    /// Draw the frames of the video chip in the terminal.
+    pub pixel_screen: bool,
+    /// WAV file to write the audio of the machine to.
+    pub audio_path: Option<String>,
+    /// Character set the console and serial text is translated from.
+    /// Defaults to the charset of the machine.
+    pub charset: Charset,
+    /// Stream the state of the running machine to WebSocket clients.
+    pub dashboard: Option<DashboardOptions>,
+    /// Address to map a 6551 ACIA at, and where it is connected on the host.
+    pub serial: Option<(u16, SerialHost)>,
+    /// Address to map a programmable interval timer at, see `devices::timer`.
+    pub timer: Option<u16>,
+    /// Address to map a DMA controller at, see `devices::dma`.
+    pub dma: Option<u16>,
+    /// Address to map a random number register at, see `devices::random`.
+    pub random: Option<u16>,
+    /// Seed of the random number register.
+    pub random_seed: u64,
+}
+
+impl Options {
+    /// Parses the command line arguments, excluding the executable name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut rom_path: Option<String> = None;
+        let mut machine: Preset = Preset::Bare;
+        let mut load_address: Option<u16> = None;
+        let mut auto_vectors: bool = false;
+        let mut stop_on_trap: Option<bool> = None;
+        let mut entry: Option<u16> = None;
+        let mut char_rom_path: Option<String> = None;
+        let mut basic_rom_path: Option<String> = None;
+        let mut ci: bool = false;
+        let mut result_addr: Option<u16> = None;
+        let mut expect: Option<u8> = None;
+        let mut trace_json: Option<String> = None;
+        let mut debug_info_path: Option<String> = None;
+        let mut log_levels: Vec<(Category, LogLevel)> = Vec::new();
+        let mut state_hash: Option<u64> = None;
+        let mut differential: bool = false;
+        let mut timeout_instr: u64 = 50_000_000;
+        let mut listen: Option<String> = None;
+        let mut history: Option<String> = None;
+        let mut breakpoints: Option<String> = None;
+        let mut script: Option<String> = None;
+        let mut record: Option<String> = None;
+        let mut replay: Option<String> = None;
+        let mut clock_hz: Option<f64> = None;
+        let mut warp: bool = false;
+        let mut cart_path: Option<String> = None;
+        let mut reu_kb: Option<usize> = None;
+        let mut d64_path: Option<String> = None;
+        let mut dsk_path: Option<String> = None;
+        let mut tape_path: Option<String> = None;
+        let mut load_name: Option<String> = None;
+        let mut prg_path: Option<String> = None;
+        let mut xex_path: Option<String> = None;
+        let mut kernal_hle: bool = false;
+        let mut sid_path: Option<String> = None;
+        let mut song: Option<u16> = None;
+        let mut play_seconds: Option<u64> = None;
+        let mut vsf_path: Option<String> = None;
+        let mut frame_path: Option<String> = None;
+        let mut text_screen: bool = false;
+        let mut pixel_screen: bool = false;
+        let mut audio_path: Option<String> = None;
+        let mut dashboard_port: Option<u16> = None;
+        let mut dashboard_rate: Option<f64> = None;
+        let mut dashboard_frames: bool = false;
+        let mut serial_address: Option<u16> = None;
+        let mut serial_host: SerialHost = SerialHost::Stdio;
+        let mut charset: Option<Charset> = None;
+        let mut timer: Option<u16> = None;
+        let mut dma: Option<u16> = None;
+        let mut random: Option<u16> = None;
+        let mut random_seed: u64 = random::DEFAULT_SEED;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--machine" => machine = Preset::parse(&value(&mut args, &arg)?)?,
+                "--load-addr" => load_address = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--auto-vectors" => auto_vectors = true,
+                "--stop-on-trap" => {
+                    stop_on_trap = match value(&mut args, &arg)?.as_str() {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        other => {
+                            return Err(format!(
+                                "`--stop-on-trap` takes `on` or `off`, not `{}`",
+                                other
+                            ))
+                        }
+                    }
+                }
+                "--entry" => entry = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--char-rom" => char_rom_path = Some(value(&mut args, &arg)?),
+                "--basic-rom" => basic_rom_path = Some(value(&mut args, &arg)?),
+                "--ci" => ci = true,
+                "--result-addr" => result_addr = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--expect" => expect = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--timeout-instr" => timeout_instr = parse_number(&value(&mut args, &arg)?)?,
+                "--state-hash" => {
+                    let every: u64 = parse_number(&value(&mut args, &arg)?)?;
+                    if every == 0 {
+                        return Err("`--state-hash` interval must be at least 1".to_string());
+                    }
+                    state_hash = Some(every);
+                }
+                "--differential" => differential = true,
+                "--trace-json" => trace_json = Some(value(&mut args, &arg)?),
+                "--dbg" => debug_info_path = Some(value(&mut args, &arg)?),
+                "--log" => {
+                    for setting in value(&mut args, &arg)?.split(',') {
+                        let (category, level): (&str, &str) =
+                            setting.split_once('=').ok_or_else(|| {
+                                format!("`--log` expects `category=level`, not `{}`", setting)
+                            })?;
+                        log_levels.push((Category::parse(category)?, LogLevel::parse(level)?));
+                    }
+                }
+                "--listen" => listen = Some(value(&mut args, &arg)?),
+                "--history" => history = Some(value(&mut args, &arg)?),
+                "--breakpoints" => breakpoints = Some(value(&mut args, &arg)?),
+                "--script" => script = Some(value(&mut args, &arg)?),
+                "--record" => record = Some(value(&mut args, &arg)?),
+                "--replay" => replay = Some(value(&mut args, &arg)?),
+                "--clock" => clock_hz = Some(speed::parse_clock(&value(&mut args, &arg)?)?),
+                "--warp" => warp = true,
+                "--cart" => cart_path = Some(value(&mut args, &arg)?),
+                "--reu" => reu_kb = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--d64" => d64_path = Some(value(&mut args, &arg)?),
+                "--dsk" => dsk_path = Some(value(&mut args, &arg)?),
+                "--tape" => tape_path = Some(value(&mut args, &arg)?),
+                "--load" => load_name = Some(value(&mut args, &arg)?),
+                "--prg" => prg_path = Some(value(&mut args, &arg)?),
+                "--xex" => xex_path = Some(value(&mut args, &arg)?),
+                "--kernal-hle" => kernal_hle = true,
+                "--play-sid" => sid_path = Some(value(&mut args, &arg)?),
+                "--song" => song = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--seconds" => play_seconds = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--vsf" => vsf_path = Some(value(&mut args, &arg)?),
+                "--frame-out" => frame_path = Some(value(&mut args, &arg)?),
+                "--text-screen" => text_screen = true,
+                "--pixel-screen" => pixel_screen = true,
+                "--charset" => charset = Some(Charset::parse(&value(&mut args, &arg)?)?),
+                "--audio" => audio_path = Some(value(&mut args, &arg)?),
+                "--dashboard" => dashboard_port = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--dashboard-rate" => {
+                    let text: String = value(&mut args, &arg)?;
+                    match text.parse::<f64>() {
+                        Ok(rate) if rate > 0.0 && rate.is_finite() => dashboard_rate = Some(rate),
+                        _ => return Err(format!("Invalid rate `{}`", text)),
+                    }
+                }
+                "--dashboard-frames" => dashboard_frames = true,
+                "--serial" => serial_address = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--timer" => timer = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--dma" => dma = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--random" => random = Some(parse_number(&value(&mut args, &arg)?)?),
+                "--random-seed" => random_seed = parse_number(&value(&mut args, &arg)?)?,
+                "--serial-tcp" => {
+                    serial_host = SerialHost::Tcp(parse_number(&value(&mut args, &arg)?)?)
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown option `{}`", arg)),
+                _ if rom_path.is_none() => rom_path = Some(arg),
+                _ => return Err(format!("Unexpected argument `{}`", arg)),
+            }
+        }
+
+        let ci = if ci {
+            Some(CiOptions {
+                result_addr: result_addr.ok_or("`--ci` requires `--result-addr`")?,
+                expect: expect.ok_or("`--ci` requires `--expect`")?,
+            })
+        } else {
+            None
+        };
+
+        if timer.is_some_and(|address| address > 0xffff - timer::STATUS) {
+            return Err("Timer registers do not fit in memory".to_string());
+        }
+        if dma.is_some_and(|address| address > 0xffff - dma::PAGE) {
+            return Err("DMA registers do not fit in memory".to_string());
+        }
+        if (load_address.is_some() || auto_vectors) && machine != Preset::Bare {
+            return Err("`--load-addr` and `--auto-vectors` require `--machine bare`".to_string());
+        }
+        if entry.is_some() && !auto_vectors {
+            return Err("`--entry` requires `--auto-vectors`".to_string());
+        }
+        if load_name.is_some() && d64_path.is_none() && dsk_path.is_none() && tape_path.is_none() {
+            return Err("`--load` requires `--d64`, `--dsk` or `--tape`".to_string());
+        }
+        if cart_path.is_some() && machine != Preset::C64Lite {
+            return Err("`--cart` requires `--machine c64-lite`".to_string());
+        }
+        if reu_kb.is_some() && machine != Preset::C64Lite {
+            return Err("`--reu` requires `--machine c64-lite`".to_string());
+        }
+        if reu_kb.is_some_and(|size| !matches!(size, 128 | 256 | 512)) {
+            return Err("REU size must be 128, 256 or 512 KiB".to_string());
+        }
+        let media: usize = [&d64_path, &dsk_path, &tape_path, &prg_path, &xex_path]
+            .iter()
+            .filter(|path| path.is_some())
+            .count();
+        if media > 1 {
+            return Err(
+                "Only one of `--d64`, `--dsk`, `--tape`, `--prg` and `--xex` can be used"
+                    .to_string(),
+            );
+        }
+        if kernal_hle && machine != Preset::C64Lite {
+            return Err("`--kernal-hle` requires `--machine c64-lite`".to_string());
+        }
+        if sid_path.is_none() && (song.is_some() || play_seconds.is_some()) {
+            return Err("`--song` and `--seconds` require `--play-sid`".to_string());
+        }
+        if sid_path.is_some() && machine != Preset::C64Lite {
+            return Err("`--play-sid` requires `--machine c64-lite`".to_string());
+        }
+        if sid_path.is_some() && audio_path.is_none() {
+            return Err("`--play-sid` requires `--audio`, the tune is written there".to_string());
+        }
+        if vsf_path.is_some() && machine != Preset::C64Lite {
+            return Err("`--vsf` requires `--machine c64-lite`".to_string());
+        }
+        // The reference core has no devices
+        if differential && (machine != Preset::Bare || ci.is_some() || state_hash.is_some()) {
+            return Err(
+                "`--differential` requires `--machine bare`, without `--ci` or `--state-hash`"
+                    .to_string(),
+            );
+        }
+        if listen.is_some() && (ci.is_some() || state_hash.is_some() || sid_path.is_some()) {
+            return Err(
+                "`--listen` cannot be used with `--ci`, `--state-hash` or `--play-sid`".to_string(),
+            );
+        }
+        if script.is_some()
+            && (listen.is_some() || ci.is_some() || state_hash.is_some() || sid_path.is_some())
+        {
+            return Err(
+                "`--script` cannot be used with `--listen`, `--ci`, `--state-hash` or `--play-sid`"
+                    .to_string(),
+            );
+        }
+        if dashboard_port.is_none() && (dashboard_rate.is_some() || dashboard_frames) {
+            return Err(
+                "`--dashboard-rate` and `--dashboard-frames` require `--dashboard`".to_string(),
+            );
+        }
+        if serial_address.is_none() && serial_host != SerialHost::Stdio {
+            return Err("`--serial-tcp` requires `--serial`".to_string());
+        }
+
+        Ok(Options {
+            rom_path,
+            machine,
+            load_address,
+            auto_vectors,
+            stop_on_trap: stop_on_trap.unwrap_or(machine == Preset::Bare),
+            entry,
+            char_rom_path,
+            basic_rom_path,
+            ci,
+            trace_json,
+            debug_info_path,
+            log_levels,
+            state_hash,
+            differential,
+            timeout_instr,
+            listen,
+            history,
+            breakpoints,
+            script,
+            record,
+            replay,
+            clock_hz,
+            warp,
+            cart_path,
+            reu_kb,
+            d64_path,
+            dsk_path,
+            tape_path,
+            load_name: load_name.unwrap_or_else(|| "*".to_string()),
+            prg_path,
+            xex_path,
+            kernal_hle,
+            sid_path,
+            song,
+            // A typical song
+            play_seconds: play_seconds.unwrap_or(180),
+            vsf_path,
+            frame_path,
+            text_screen,
+            pixel_screen,
+            audio_path,
+            charset: charset.unwrap_or(machine.charset()),
+            dashboard: dashboard_port.map(|port| DashboardOptions {
+                port,
+                rate: dashboard_rate.unwrap_or(10.0),
+                frames: dashboard_frames,
+            }),
+            serial: serial_address.map(|address| (address, serial_host)),
+            timer,
+            dma,
+            random,
+            random_seed,
+        })
+    }
+
+    /// # Returns
+    /// `true` if the program should run without the interactive menu.
+    pub fn headless(&self) -> bool {
+        self.ci.is_some()
+            || self.state_hash.is_some()
+            || self.sid_path.is_some()
+            || self.differential
+    }
+}
+
+/// # Returns
+/// The value following the option `name`.
+fn value<I: Iterator<Item = String>>(args: &mut I, name: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Option `{}` requires a value", name))
+}
+
+/// Parses a decimal number, or a hexadecimal one when prefixed with `0x` or `$`.
+pub fn parse_number<T: TryFrom<u64>>(text: &str) -> Result<T, String> {
+    let value: Result<u64, _> = if let Some(hex) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        u64::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u64>()
+    };
+
+    value
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("Invalid number `{}`", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_ci_options() {
+        let options = parse(&[
+            "test.bin",
+            "--ci",
+            "--result-addr",
+            "0x0200",
+            "--expect",
+            "$55",
+            "--timeout-instr",
+            "1000",
+        ])
+        .unwrap();
+
+        assert!(options.headless());
+        assert_eq!(options.rom_path.as_deref(), Some("test.bin"));
+        assert_eq!(options.timeout_instr, 1000);
+        assert_eq!(options.state_hash, None);
+        assert_eq!(options.machine, Preset::Bare);
+        assert_eq!(options.clock_hz, None);
+        assert!(!options.warp);
+        assert_eq!(options.charset, Charset::Raw);
+
+        let ci = options.ci.unwrap();
+        assert_eq!(ci.result_addr, 0x0200);
+        assert_eq!(ci.expect, 0x55);
+    }
+
+    #[test]
+    fn parse_state_hash() {
+        let options = parse(&["test.bin", "--state-hash", "100"]).unwrap();
+
+        assert!(options.headless());
+        assert!(options.ci.is_none());
+        assert_eq!(options.state_hash, Some(100));
+        assert!(parse(&["test.bin", "--state-hash", "0"]).is_err());
+        assert!(parse(&["test.bin", "--differential"]).unwrap().headless());
+        assert!(parse(&["test.bin", "--differential", "--state-hash", "100"]).is_err());
+    }
+
+    #[test]
+    fn parse_stop_on_trap() {
+        assert!(parse(&["test.bin"]).unwrap().stop_on_trap);
+        assert!(
+            !parse(&["test.bin", "--stop-on-trap", "off"])
+                .unwrap()
+                .stop_on_trap
+        );
+        assert!(!parse(&["--machine", "nes-lite"]).unwrap().stop_on_trap);
+        let options = parse(&["--machine", "nes-lite", "--stop-on-trap", "on"]).unwrap();
+        assert!(options.stop_on_trap);
+        assert!(parse(&["--stop-on-trap", "yes"]).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_bad_values() {
+        assert!(parse(&["--ci", "--expect", "0x55"]).is_err());
+        assert!(parse(&["--ci", "--result-addr", "0x10000", "--expect", "1"]).is_err());
+        assert!(parse(&["--expect"]).is_err());
+        assert!(parse(&["--log", "exec=debug,bus"]).is_err());
+        assert!(parse(&["--log", "exec=loud"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--record"]).is_err());
+        assert!(parse(&["--dashboard-frames"]).is_err());
+        assert!(parse(&["--dashboard", "8080", "--dashboard-rate", "0"]).is_err());
+        assert!(parse(&["--listen", "127.0.0.1:6502", "--state-hash", "10"]).is_err());
+        assert!(parse(&["--script", "check.txt", "--listen", "127.0.0.1:6502"]).is_err());
+        assert!(parse(&["--clock", "fast"]).is_err());
+        assert!(parse(&["--machine", "c128"]).is_err());
+        assert!(parse(&["--serial-tcp", "6551"]).is_err());
+        assert!(parse(&["--timer", "0xfffe"]).is_err());
+        assert!(parse(&["--charset", "ebcdic"]).is_err());
+        assert!(parse(&["--load", "GAME"]).is_err());
+        assert!(parse(&["--prg", "a.prg", "--tape", "a.tap"]).is_err());
+        assert!(parse(&["--xex", "a.xex", "--prg", "a.prg"]).is_err());
+        assert!(parse(&["--dsk", "a.dsk", "--load", "HELLO"]).is_ok());
+        assert!(parse(&["--kernal-hle"]).is_err());
+        assert!(parse(&["--machine", "c64-lite", "--play-sid", "a.sid"]).is_err());
+        assert!(parse(&["--song", "2"]).is_err());
+        assert!(parse(&["--vsf", "a.vsf"]).is_err());
+        assert!(parse(&["--machine", "apple1", "--auto-vectors"]).is_err());
+        assert!(parse(&["--entry", "0x0400"]).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::monitor::Monitor;
+
+use machine::Machine;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Lets a client on `address` drive `machine` with the commands of `Monitor`, one per
+/// line. Each command gets one line back, `ok` and what it reports, or `error` and
+/// why it failed. `quit` closes the connection and `shutdown` stops the server.
+///
+/// Clients are served one at a time, sharing the breakpoints, and the machine only
+/// runs when told to.
+///
+pub fn serve(machine: &mut Machine, address: &str, monitor: &mut Monitor) -> Result<(), String> {
+    let listener: TcpListener = TcpListener::bind(address)
+        .map_err(|error| format!("cannot listen on {}: {}", address, error))?;
+    println!("Remote control listening on {}", address);
+
+    for client in listener.incoming() {
+        let client: TcpStream = match client {
+            Ok(client) => client,
+            Err(_) => continue,
+        };
+        // A client going away only ends its session
+        if let Ok(true) = serve_client(machine, monitor, client) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the commands of `client` until it quits or disconnects.
+///
+/// # Returns
+/// `true` if the client asked for the server to stop.
+fn serve_client(
+    machine: &mut Machine,
+    monitor: &mut Monitor,
+    client: TcpStream,
+) -> std::io::Result<bool> {
+    let mut output: TcpStream = client.try_clone()?;
+    for line in BufReader::new(client).lines() {
+        let line: String = line?;
+        let reply: String = match line.trim() {
+            "quit" => return Ok(false),
+            "shutdown" => {
+                writeln!(output, "ok")?;
+                return Ok(true);
+            }
+            command => match monitor.execute(machine, command) {
+                Ok(text) => format!("ok {}", text),
+                Err(error) => format!("error {}", error),
+            },
+        };
+        writeln!(output, "{}", reply)?;
+    }
+    Ok(false)
+}
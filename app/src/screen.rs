@@ -0,0 +1,233 @@
+use devices::charset;
+use devices::video::FrameBuffer;
+use machine::Machine;
+
+use std::io::{self, Write};
+
+/// Shows the screen of the machine on the host.
+pub trait Screen {
+    /// Called after every frame the machine ran.
+    fn present(&mut self, machine: &Machine);
+}
+
+/// Writes each new frame rendered by the video chip to a binary PPM image, replacing
+/// the previous one. Image viewers that reload on change then follow the emulated screen.
+/// It is a diagnostic output, not a window.
+pub struct PpmScreen {
+    path: String,
+    /// The last frame written, to skip rewriting the file while the screen is still.
+    last: Option<FrameBuffer>,
+}
+
+impl PpmScreen {
+    pub fn new(path: &str) -> Self {
+        PpmScreen {
+            path: path.to_string(),
+            last: None,
+        }
+    }
+
+    fn write(&self, frame: &FrameBuffer) -> io::Result<()> {
+        let mut image: Vec<u8> =
+            format!("P6\n{} {}\n255\n", frame.width(), frame.height()).into_bytes();
+        for rgba in frame.pixels().chunks_exact(4) {
+            image.extend_from_slice(&rgba[..3]);
+        }
+        // Write then rename, so a viewer never loads half a frame
+        let temporary: String = format!("{}.tmp", self.path);
+        std::fs::write(&temporary, image)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+impl Screen for PpmScreen {
+    fn present(&mut self, machine: &Machine) {
+        let frame: &FrameBuffer = match machine.frame_buffer() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if self.last.as_ref() == Some(frame) {
+            return;
+        }
+        if let Err(error) = self.write(frame) {
+            println!("Error: cannot write `{}`: {}", self.path, error);
+        }
+        self.last = Some(frame.clone());
+    }
+}
+
+/// How many columns `PixelScreen` draws a frame in at most.
+pub const PIXEL_COLUMNS: usize = 160;
+
+/// Draws each new frame rendered by the video chip in the terminal, two pixels per
+/// character: a `▀` in the color of the upper one on the color of the lower one, with
+/// 24-bit ANSI colors. Frames wider than `PIXEL_COLUMNS` are scaled down by a whole
+/// factor. With the pacing of the run loop and the keys typed on its input, this is a
+/// frontend that needs no platform library.
+pub struct PixelScreen {
+    /// The last frame drawn, to skip redrawing while the screen is still.
+    last: Option<FrameBuffer>,
+}
+
+impl PixelScreen {
+    pub fn new() -> Self {
+        PixelScreen { last: None }
+    }
+
+    /// # Returns
+    /// `frame` as lines of colored half blocks.
+    fn draw(frame: &FrameBuffer) -> String {
+        let scale: usize = frame.width().div_ceil(PIXEL_COLUMNS).max(1);
+        let mut text: String = String::new();
+        for y in (0..frame.height()).step_by(2 * scale) {
+            let mut colors: Option<([u8; 4], [u8; 4])> = None;
+            for x in (0..frame.width()).step_by(scale) {
+                let top: [u8; 4] = frame.pixel(x, y);
+                let bottom: [u8; 4] = if y + scale < frame.height() {
+                    frame.pixel(x, y + scale)
+                } else {
+                    [0, 0, 0, 0xff]
+                };
+                // Colors are only sent when they change along the line
+                if colors != Some((top, bottom)) {
+                    text.push_str(&format!(
+                        "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                        top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                    ));
+                    colors = Some((top, bottom));
+                }
+                text.push('▀');
+            }
+            text.push_str("\x1b[0m\r\n");
+        }
+        text
+    }
+}
+
+impl Screen for PixelScreen {
+    fn present(&mut self, machine: &Machine) {
+        let frame: &FrameBuffer = match machine.frame_buffer() {
+            Some(frame) => frame,
+            None => return,
+        };
+        if self.last.as_ref() == Some(frame) {
+            return;
+        }
+
+        // As for `TextScreen`, drawn from the top left corner, the cursor kept below
+        let mut out = io::stdout().lock();
+        if self.last.is_none() {
+            let rows: usize = Self::draw(frame).matches('\n').count();
+            let _ = write!(out, "\x1b[2J\x1b[{}H", rows + 2);
+        }
+        let _ = write!(out, "\x1b7\x1b[H{}\x1b8", Self::draw(frame));
+        let _ = out.flush();
+        self.last = Some(frame.clone());
+    }
+}
+
+/// Where the C64 KERNAL puts the screen RAM.
+pub const TEXT_SCREEN_ADDRESS: u16 = 0x0400;
+pub const TEXT_COLUMNS: usize = 40;
+pub const TEXT_ROWS: usize = 25;
+
+/// Draws a 40x25 screen RAM of C64 screen codes as text in the terminal, using ANSI
+/// escapes. It needs no video chip, so it works on any machine and over SSH.
+pub struct TextScreen {
+    address: u16,
+    /// The screen RAM last drawn, to skip redrawing while the screen is still.
+    last: Option<Vec<u8>>,
+}
+
+impl TextScreen {
+    pub fn new(address: u16) -> Self {
+        TextScreen {
+            address,
+            last: None,
+        }
+    }
+
+    /// # Returns
+    /// The screen as lines of text, reverse characters in reverse video.
+    fn draw(codes: &[u8]) -> String {
+        let mut text: String = String::new();
+        for row in codes.chunks(TEXT_COLUMNS) {
+            text.push_str("\x1b[K");
+            let mut reversed: bool = false;
+            for &code in row {
+                if (code & 0x80 != 0) != reversed {
+                    reversed = !reversed;
+                    text.push_str(if reversed { "\x1b[7m" } else { "\x1b[27m" });
+                }
+                text.push(charset::screen_code_char(code));
+            }
+            if reversed {
+                text.push_str("\x1b[27m");
+            }
+            text.push_str("\r\n");
+        }
+        text
+    }
+}
+
+impl Screen for TextScreen {
+    fn present(&mut self, machine: &Machine) {
+        let start: usize = self.address as usize;
+        let codes: Vec<u8> = {
+            let mem = machine.memory().borrow();
+            let ram: &[u8] = mem.as_slice();
+            let end: usize = (start + TEXT_COLUMNS * TEXT_ROWS).min(ram.len());
+            ram[start.min(end)..end].to_vec()
+        };
+        if self.last.as_ref() == Some(&codes) {
+            return;
+        }
+
+        // Save the cursor, draw from the top left corner, then restore it so the menu
+        // output keeps going below, after clearing the terminal the first time
+        let mut out = io::stdout().lock();
+        if self.last.is_none() {
+            let _ = write!(out, "\x1b[2J\x1b[{}H", TEXT_ROWS + 2);
+        }
+        let _ = write!(out, "\x1b7\x1b[H{}\x1b8", Self::draw(&codes));
+        let _ = out.flush();
+        self.last = Some(codes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_draw_as_half_blocks() {
+        let mut frame: FrameBuffer = FrameBuffer::new(2, 3);
+        frame.set_pixel(0, 0, [0xff, 0x00, 0x00, 0xff]);
+        frame.set_pixel(1, 0, [0xff, 0x00, 0x00, 0xff]);
+        frame.set_pixel(1, 1, [0x00, 0x00, 0xff, 0xff]);
+
+        assert_eq!(
+            PixelScreen::draw(&frame),
+            "\x1b[38;2;255;0;0;48;2;0;0;0m▀\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[0m\r\n\
+             \x1b[38;2;0;0;0;48;2;0;0;0m▀▀\x1b[0m\r\n"
+        );
+        // Scaled down to fit
+        let frame: FrameBuffer = FrameBuffer::new(2 * PIXEL_COLUMNS, 8);
+        let text: String = PixelScreen::draw(&frame);
+        assert_eq!(text.matches('▀').count(), PIXEL_COLUMNS * 2);
+    }
+
+    #[test]
+    fn screen_codes_draw_as_text() {
+        let mut codes: Vec<u8> = vec![0x20; TEXT_COLUMNS];
+        // "HI" then a reversed "!"
+        codes[0] = 0x08;
+        codes[1] = 0x09;
+        codes[2] = 0x21 | 0x80;
+
+        let text: String = TextScreen::draw(&codes);
+
+        assert!(text.starts_with("\x1b[KHI\x1b[7m!\x1b[27m  "));
+        assert!(text.ends_with(" \r\n"));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::ci;
+use crate::monitor::Monitor;
+
+use machine::Machine;
+
+/// Runs `--script`: the monitor commands of the file `path` on `machine`, a line each,
+/// echoing every command before what it reports. Empty lines and those starting with
+/// `#` are skipped, except while assembling, where an empty line stops.
+///
+/// # Returns
+/// The exit code: `ci::EXIT_PASS` once every command ran, `ci::EXIT_FAIL` at the first
+/// `assert` that fails, or `ci::EXIT_USAGE` if the file cannot be read or another
+/// command fails.
+pub fn run(machine: &mut Machine, monitor: &mut Monitor, path: &str) -> i32 {
+    let text: String = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            println!("Error: cannot read `{}`: {}", path, error);
+            return ci::EXIT_USAGE;
+        }
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line: &str = line.trim();
+        if monitor.assembling().is_none() && (line.is_empty() || line.starts_with('#')) {
+            continue;
+        }
+        println!("> {}", line);
+        let failed: u64 = monitor.failed_assertions();
+        match monitor.execute(machine, line) {
+            Ok(reply) => println!("{}", reply),
+            Err(error) => {
+                println!("Error: {}:{}: {}", path, number + 1, error);
+                return if monitor.failed_assertions() > failed {
+                    ci::EXIT_FAIL
+                } else {
+                    ci::EXIT_USAGE
+                };
+            }
+        }
+    }
+    ci::EXIT_PASS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_on_assertions_and_errors() {
+        let path: std::path::PathBuf = std::env::temp_dir().join("monitor_script.txt");
+        let name: &str = path.to_str().unwrap();
+        let run_script = |script: &str| {
+            std::fs::write(&path, script).unwrap();
+            run(&mut Machine::new(), &mut Monitor::new(1000), name)
+        };
+
+        // LDX #$05, then DEX until 0
+        let program: &str = "a 0 ldx #5\ndex\nbne 2\n\n# Counts down\ngo 11\n";
+        assert_eq!(
+            run_script(&format!("{}assert x == 0 && pc == 5\n", program)),
+            ci::EXIT_PASS
+        );
+        assert_eq!(
+            run_script(&format!("{}assert x == 1\nstate\n", program)),
+            ci::EXIT_FAIL
+        );
+        assert_eq!(run_script("jump 0\n"), ci::EXIT_USAGE);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            run(&mut Machine::new(), &mut Monitor::new(1000), name),
+            ci::EXIT_USAGE
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use devices::acia::Acia;
+use devices::charset::{Charset, Translated};
+use machine::Machine;
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+/// Where the serial port of the machine is connected on the host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialHost {
+    /// Transmit to stdout, receive the lines typed while running.
+    Stdio,
+    /// Transmit to and receive from the client connected to this local TCP port.
+    Tcp(u16),
+}
+
+/// The client currently connected to the TCP bridge, shared with the accepting thread.
+/// Bytes sent while nobody is connected are dropped, like on an unplugged serial line.
+struct TcpOutput {
+    client: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Write for TcpOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(client) = self.client.lock().unwrap().as_mut() {
+            client.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.client.lock().unwrap().as_mut() {
+            Some(client) => client.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Maps a 6551 ACIA at `address..=address + 3` on `machine`, bridged to `host` with
+/// the text translated from `charset`.
+pub fn attach(
+    machine: &mut Machine,
+    address: u16,
+    host: SerialHost,
+    charset: Charset,
+) -> Result<(), String> {
+    let end: u16 = address
+        .checked_add(3)
+        .ok_or_else(|| format!("Serial port at {:#06x} does not fit in memory", address))?;
+
+    match host {
+        SerialHost::Stdio => {
+            let (acia, received) = Acia::new(Translated::new(io::stdout(), charset));
+            machine.map_device(address, end, Rc::new(RefCell::new(acia)));
+            // Console input goes to the serial port instead of the keyboard
+            machine.connect_keyboard(received);
+        }
+        SerialHost::Tcp(port) => {
+            let listener: TcpListener = TcpListener::bind(("127.0.0.1", port))
+                .map_err(|error| format!("cannot listen on port {}: {}", port, error))?;
+            let client: Arc<Mutex<Option<TcpStream>>> = Arc::new(Mutex::new(None));
+            let output: TcpOutput = TcpOutput {
+                client: client.clone(),
+            };
+            let (acia, received) = Acia::new(Translated::new(output, charset));
+            machine.map_device(address, end, Rc::new(RefCell::new(acia)));
+            println!("Serial port listening on 127.0.0.1:{}", port);
+            std::thread::spawn(move || accept_clients(listener, client, received, charset));
+        }
+    }
+    Ok(())
+}
+
+/// Serves one client at a time, forwarding what it sends to the ACIA in `charset`.
+/// Clients are expected to send ASCII unless the charset is raw.
+fn accept_clients(
+    listener: TcpListener,
+    client: Arc<Mutex<Option<TcpStream>>>,
+    received: Sender<u8>,
+    charset: Charset,
+) {
+    for stream in listener.incoming() {
+        let mut stream: TcpStream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        *client.lock().unwrap() = stream.try_clone().ok();
+
+        let mut buffer: [u8; 256] = [0; 256];
+        while let Ok(count @ 1..) = stream.read(&mut buffer) {
+            let bytes = buffer[..count]
+                .iter()
+                .filter_map(|byte| charset.encode(*byte as char));
+            for byte in bytes {
+                if received.send(byte).is_err() {
+                    // The machine is gone
+                    return;
+                }
+            }
+        }
+        *client.lock().unwrap() = None;
+    }
+}
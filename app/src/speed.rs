@@ -0,0 +1,78 @@
+use machine::clock::Clock;
+
+/// Clock of a PAL C64, in Hz.
+pub const PAL_HZ: f64 = 985_248.0;
+/// Clock of an NTSC C64, in Hz.
+pub const NTSC_HZ: f64 = 1_022_727.0;
+
+/// Keeps emulated time in step with wall-clock time.
+pub struct Throttle {
+    warp: bool,
+    paused: bool,
+    /// Anchored where the current throttling period started.
+    clock: Clock,
+}
+
+impl Throttle {
+    /// Creates a throttle running at `hz`, or unthrottled if `warp` is set.
+    pub fn new(hz: f64, warp: bool) -> Self {
+        Throttle {
+            warp,
+            paused: false,
+            clock: Clock::new(hz),
+        }
+    }
+
+    pub fn hz(&self) -> f64 {
+        self.clock.hz()
+    }
+
+    pub fn is_warp(&self) -> bool {
+        self.warp
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Switches between throttled and unthrottled speed.
+    pub fn toggle_warp(&mut self, cycles: u64) {
+        self.warp = !self.warp;
+        self.restart(cycles);
+    }
+
+    /// Pauses or resumes emulation.
+    pub fn toggle_pause(&mut self, cycles: u64) {
+        self.paused = !self.paused;
+        self.restart(cycles);
+    }
+
+    /// Starts a new throttling period at `cycles`, forgetting any time spent before,
+    /// e.g. while paused or waiting for the user.
+    pub fn restart(&mut self, cycles: u64) {
+        self.clock.restart(cycles);
+    }
+
+    /// Sleeps until wall-clock time catches up with `cycles`.
+    pub fn sync(&mut self, cycles: u64) {
+        if self.warp || self.paused {
+            return;
+        }
+        self.clock.sleep_until_cycle(cycles);
+    }
+}
+
+/// Parses a clock frequency: `pal`, `ntsc` or a number of Hz.
+pub fn parse_clock(text: &str) -> Result<f64, String> {
+    match text {
+        "pal" => Ok(PAL_HZ),
+        "ntsc" => Ok(NTSC_HZ),
+        _ => match text.parse::<f64>() {
+            Ok(hz) if hz > 0.0 => Ok(hz),
+            _ => Err(format!(
+                "Invalid clock `{}`, expected `pal`, `ntsc` or Hz",
+                text
+            )),
+        },
+    }
+}
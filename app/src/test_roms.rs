@@ -0,0 +1,408 @@
+use crate::build_machine;
+use crate::ci;
+use crate::options::{parse_number, Options};
+
+use devices::char_output::{self, CharOutput};
+use devices::charset::Translated;
+use machine::Machine;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Where blargg's test ROMs keep their status, `0x80` while running and the result
+/// code once done, `0x00` being a pass.
+const BLARGG_STATUS: u16 = 0x6000;
+/// What follows the status once the ROM wrote it.
+const BLARGG_SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+/// The NUL-terminated text of the result, after the signature.
+const BLARGG_TEXT: u16 = 0x6004;
+
+/// How often the output and the memory are looked at for a result, in instructions.
+const CHECK_INTERVAL: u64 = 10_000;
+
+/// Settings of the `test-roms` subcommand.
+struct SuiteOptions {
+    /// Directory of the test ROMs, each file one ROM.
+    directory: String,
+    /// Address of a byte holding the result once the ROM stopped, `--status-addr`.
+    status_address: Option<u16>,
+    /// The value of a pass there, `--pass-value`.
+    pass_value: u8,
+    /// Output meaning a pass, `--pass-text`.
+    pass_text: Option<String>,
+    /// Output meaning a failure, `--fail-text`, looked for first.
+    fail_text: Option<String>,
+    /// Where a ROM jumping to itself passed, `--pass-pc`.
+    pass_pc: Option<u16>,
+    /// The options of the emulator, the same for every ROM.
+    emulator: Vec<String>,
+}
+
+impl SuiteOptions {
+    /// Parses `directory [--status-addr <addr> [--pass-value <value>]] [--pass-text <text>]
+    /// [--fail-text <text>] [--pass-pc <addr>] [options...]`, the arguments after
+    /// `test-roms`, the other options being the emulator's.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<SuiteOptions, String> {
+        let directory: String = args
+            .next()
+            .filter(|arg| !arg.starts_with('-'))
+            .ok_or_else(|| "No directory of test ROMs".to_string())?;
+        let mut status_address: Option<u16> = None;
+        let mut pass_value: Option<u8> = None;
+        let mut pass_text: Option<String> = None;
+        let mut fail_text: Option<String> = None;
+        let mut pass_pc: Option<u16> = None;
+        let mut emulator: Vec<String> = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Option `{}` requires a value", name))
+            };
+            match arg.as_str() {
+                "--status-addr" => status_address = Some(parse_number(&value(&arg)?)?),
+                "--pass-value" => pass_value = Some(parse_number(&value(&arg)?)?),
+                "--pass-text" => pass_text = Some(value(&arg)?),
+                "--fail-text" => fail_text = Some(value(&arg)?),
+                "--pass-pc" => pass_pc = Some(parse_number(&value(&arg)?)?),
+                "--ci" | "--listen" | "--prg" => {
+                    return Err(format!("`{}` cannot be used with `test-roms`", arg))
+                }
+                _ => emulator.push(arg),
+            }
+        }
+
+        if status_address.is_none() && pass_value.is_some() {
+            return Err("`--pass-value` requires `--status-addr`".to_string());
+        }
+        Ok(SuiteOptions {
+            directory,
+            status_address,
+            pass_value: pass_value.unwrap_or(0x00),
+            pass_text,
+            fail_text,
+            pass_pc,
+            emulator,
+        })
+    }
+}
+
+/// How a ROM ended, see `Outcome`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Verdict {
+    Pass,
+    Fail,
+    Timeout,
+    /// The ROM stopped, or could not run, without telling how it went.
+    Unknown,
+}
+
+impl Verdict {
+    fn name(self) -> &'static str {
+        match self {
+            Verdict::Pass => "PASS",
+            Verdict::Fail => "FAIL",
+            Verdict::Timeout => "TIMEOUT",
+            Verdict::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// What running a ROM gave.
+struct Outcome {
+    verdict: Verdict,
+    /// Why, e.g. the value of the status byte.
+    detail: String,
+    instructions: u64,
+}
+
+/// The console output of a ROM, kept to look for the pass and fail texts.
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `test-roms`: every file of a directory as a test ROM, in name order, then
+/// prints a table of the results.
+///
+/// # Returns
+/// The process exit code, `ci::EXIT_PASS` if every ROM passed, `ci::EXIT_FAIL` if not.
+pub fn run<I: Iterator<Item = String>>(args: I) -> i32 {
+    let options: SuiteOptions = match SuiteOptions::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("Error: {}", error);
+            return ci::EXIT_USAGE;
+        }
+    };
+    let paths: Vec<PathBuf> = match roms(Path::new(&options.directory)) {
+        Ok(paths) if paths.is_empty() => {
+            println!("Error: no test ROMs in `{}`", options.directory);
+            return ci::EXIT_USAGE;
+        }
+        Ok(paths) => paths,
+        Err(error) => {
+            println!("Error: cannot read `{}`: {}", options.directory, error);
+            return ci::EXIT_USAGE;
+        }
+    };
+
+    let mut outcomes: Vec<(String, Outcome)> = Vec::new();
+    for path in &paths {
+        let name: String = path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let outcome: Outcome = run_rom(path, &options).unwrap_or_else(|error| Outcome {
+            verdict: Verdict::Unknown,
+            detail: error,
+            instructions: 0,
+        });
+        outcomes.push((name, outcome));
+    }
+
+    let width: usize = outcomes
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    println!(
+        "{:<width$}  {:<7}  {:>12}  Detail",
+        "ROM",
+        "Result",
+        "Instructions",
+        width = width
+    );
+    for (name, outcome) in &outcomes {
+        let row: String = format!(
+            "{:<width$}  {:<7}  {:>12}  {}",
+            name,
+            outcome.verdict.name(),
+            outcome.instructions,
+            outcome.detail,
+            width = width
+        );
+        println!("{}", row.trim_end());
+    }
+    let count = |verdict: Verdict| {
+        outcomes
+            .iter()
+            .filter(|(_, outcome)| outcome.verdict == verdict)
+            .count()
+    };
+    println!(
+        "{} passed, {} failed, {} timed out, {} unknown",
+        count(Verdict::Pass),
+        count(Verdict::Fail),
+        count(Verdict::Timeout),
+        count(Verdict::Unknown)
+    );
+    if count(Verdict::Pass) == outcomes.len() {
+        ci::EXIT_PASS
+    } else {
+        ci::EXIT_FAIL
+    }
+}
+
+/// # Returns
+/// The files of `directory`, hidden ones left out, sorted by name.
+fn roms(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let path: PathBuf = entry?.path();
+        let hidden: bool = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_file() && !hidden {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Runs the ROM at `path` up to `--timeout-instr` instructions, a `.prg` file with
+/// `--prg`, until one of the conventions of `verdict()` tells how it went.
+fn run_rom(path: &Path, options: &SuiteOptions) -> Result<Outcome, String> {
+    let path: String = path.to_string_lossy().into_owned();
+    let prg: bool = path.to_ascii_lowercase().ends_with(".prg");
+    let args: Vec<String> = if prg {
+        vec!["--prg".to_string(), path.clone()]
+    } else {
+        vec![path.clone()]
+    };
+    let emulator: Options = Options::parse(args.into_iter().chain(options.emulator.clone()))?;
+    let rom: Option<Vec<u8>> = match prg {
+        true => None,
+        false => Some(
+            std::fs::read(&path).map_err(|error| format!("cannot read `{}`: {}", path, error))?,
+        ),
+    };
+
+    let output: Captured = Captured::default();
+    let mut machine: Machine = build_machine(&emulator, rom, Box::new(output.clone()))?;
+    if emulator.machine == machine::preset::Preset::Bare {
+        // In place of the port printing to stdout
+        let port: Rc<RefCell<CharOutput<_>>> = Rc::new(RefCell::new(CharOutput::new(
+            Translated::new(output.clone(), emulator.charset),
+        )));
+        machine.map_device(
+            char_output::DEFAULT_ADDRESS,
+            char_output::DEFAULT_ADDRESS,
+            port,
+        );
+    }
+
+    // Test ROMs report their result by jumping to themselves
+    machine.cpu_mut().set_trap_detection(true);
+    let mut executed: u64 = 0;
+    while !machine.cpu().is_halted() && executed < emulator.timeout_instr {
+        machine.step();
+        executed += 1;
+        if executed.is_multiple_of(CHECK_INTERVAL) {
+            if let Some((verdict, detail)) = verdict(&machine, &output, options, false) {
+                return Ok(Outcome {
+                    verdict,
+                    detail,
+                    instructions: executed,
+                });
+            }
+        }
+    }
+
+    let stopped: bool = machine.cpu().is_halted();
+    let (verdict, detail): (Verdict, String) = verdict(&machine, &output, options, stopped)
+        .unwrap_or_else(|| match stopped {
+            true => (
+                Verdict::Unknown,
+                match machine.cpu().trapped_at() {
+                    Some(pc) => format!("trapped at {:#06x}", pc),
+                    None => "halted".to_string(),
+                },
+            ),
+            false => (Verdict::Timeout, String::new()),
+        });
+    Ok(Outcome {
+        verdict,
+        detail,
+        instructions: executed,
+    })
+}
+
+/// Looks for the result of a ROM, in order: the fail then the pass text in its output,
+/// blargg's status at `$6000` once a ROM signed it, and, once the ROM `stopped`, the
+/// status byte and where it trapped.
+///
+/// # Returns
+/// The verdict and why, or `None` if the ROM did not tell yet.
+fn verdict(
+    machine: &Machine,
+    output: &Captured,
+    options: &SuiteOptions,
+    stopped: bool,
+) -> Option<(Verdict, String)> {
+    let text: String = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    let found = |needle: &Option<String>| {
+        needle
+            .clone()
+            .filter(|needle| text.contains(needle.as_str()))
+    };
+    if let Some(fail) = found(&options.fail_text) {
+        return Some((Verdict::Fail, format!("printed \"{}\"", fail)));
+    }
+    if let Some(pass) = found(&options.pass_text) {
+        return Some((Verdict::Pass, format!("printed \"{}\"", pass)));
+    }
+
+    let memory = machine.memory().borrow();
+    let signed: bool = (0..BLARGG_SIGNATURE.len())
+        .all(|index| memory.read(BLARGG_STATUS + 1 + index as u16) == BLARGG_SIGNATURE[index]);
+    let status: u8 = memory.read(BLARGG_STATUS);
+    if signed && status < 0x80 {
+        let message: String = (BLARGG_TEXT..BLARGG_TEXT + 0x100)
+            .map(|address| memory.read(address))
+            .take_while(|&byte| byte != 0)
+            .map(char::from)
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let verdict: Verdict = if status == 0x00 {
+            Verdict::Pass
+        } else {
+            Verdict::Fail
+        };
+        return Some((
+            verdict,
+            format!("{:#04x} {}", status, message).trim().to_string(),
+        ));
+    }
+
+    if !stopped {
+        return None;
+    }
+    if let Some(address) = options.status_address {
+        let status: u8 = memory.read(address);
+        let verdict: Verdict = if status == options.pass_value {
+            Verdict::Pass
+        } else {
+            Verdict::Fail
+        };
+        return Some((verdict, format!("{:#06x} = {:#04x}", address, status)));
+    }
+    match (options.pass_pc, machine.cpu().trapped_at()) {
+        (Some(pass), Some(pc)) if pc == pass => {
+            Some((Verdict::Pass, format!("trapped at {:#06x}", pc)))
+        }
+        (Some(_), Some(pc)) => Some((Verdict::Fail, format!("trapped at {:#06x}", pc))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<SuiteOptions, String> {
+        SuiteOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_conventions_and_emulator_options() {
+        let options: SuiteOptions = parse(&[
+            "roms",
+            "--status-addr",
+            "0x0200",
+            "--machine",
+            "nes-lite",
+            "--fail-text",
+            "FAILED",
+            "--pass-pc",
+            "$3469",
+        ])
+        .unwrap();
+        assert_eq!(options.directory, "roms");
+        assert_eq!(options.status_address, Some(0x0200));
+        assert_eq!(options.pass_value, 0x00);
+        assert_eq!(options.fail_text.as_deref(), Some("FAILED"));
+        assert_eq!(options.pass_text, None);
+        assert_eq!(options.pass_pc, Some(0x3469));
+        assert_eq!(options.emulator, vec!["--machine", "nes-lite"]);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--machine", "bare"]).is_err());
+        assert!(parse(&["roms", "--pass-value", "1"]).is_err());
+        assert!(parse(&["roms", "--ci"]).is_err());
+    }
+}
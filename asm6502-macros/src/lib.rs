@@ -0,0 +1,207 @@
+use mos6502::instruction_info;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// A token of the input, the parentheses of groups being tokens of their own.
+struct Piece {
+    text: String,
+    span: Span,
+}
+
+/// Assembles 6502 source at compile time into a `[u8; N]`, the `image()` of the
+/// program, see `asm6502::assemble()`:
+///
+/// ```text
+/// cpu.load(0x0200, &m6502_asm! { ldx #$10 loop: dex bne loop });
+/// ```
+///
+/// A statement starts on a new line, or before a mnemonic, a label, a constant or a
+/// directive. Comments are those of Rust, `//`. A few numbers do not make Rust tokens,
+/// e.g. `$1e`, read as an exponent, so the source can also be a string:
+/// `m6502_asm!("lda #$1e")`.
+///
+/// Errors are reported at the statement they are about.
+#[proc_macro]
+pub fn m6502_asm(input: TokenStream) -> TokenStream {
+    let (source, lines): (String, Vec<Span>) = match string_input(&input) {
+        Some(source) => (source, Vec::new()),
+        None => {
+            let mut pieces: Vec<Piece> = Vec::new();
+            flatten(input, &mut pieces);
+            to_source(&pieces)
+        }
+    };
+
+    match asm6502::assemble(&source) {
+        Ok(program) => {
+            let bytes: Vec<String> = program
+                .image()
+                .iter()
+                .map(|byte| format!("{:#04x}_u8", byte))
+                .collect();
+            let array: String = if bytes.is_empty() {
+                "[0_u8; 0]".to_string()
+            } else {
+                format!("[{}]", bytes.join(", "))
+            };
+            // Only numbers, which always parse
+            array.parse().unwrap()
+        }
+        Err(error) => {
+            // Errors are `line N: message`, N counting from 1
+            let line: Option<usize> = error
+                .strip_prefix("line ")
+                .and_then(|rest| rest.split_once(':'))
+                .and_then(|(line, _)| line.parse::<usize>().ok())
+                .filter(|&line| line >= 1 && line <= lines.len());
+            match line {
+                Some(line) => {
+                    let message: &str = error
+                        .split_once(": ")
+                        .map_or(&error, |(_, message)| message);
+                    let statement: &str = source.lines().nth(line - 1).unwrap_or("");
+                    compile_error(&format!("{} in `{}`", message, statement), lines[line - 1])
+                }
+                None => compile_error(&error, Span::call_site()),
+            }
+        }
+    }
+}
+
+/// # Returns
+/// The value of `input` if it is a single string literal, normal or raw.
+fn string_input(input: &TokenStream) -> Option<String> {
+    let mut trees = input.clone().into_iter();
+    let (Some(TokenTree::Literal(literal)), None) = (trees.next(), trees.next()) else {
+        return None;
+    };
+    let text: String = literal.to_string();
+    if let Some(raw) = text.strip_prefix('r') {
+        let hashes: usize = raw.len() - raw.trim_start_matches('#').len();
+        return raw
+            .get(hashes + 1..raw.len() - hashes - 1)
+            .map(str::to_string);
+    }
+    let inner: &str = text.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut source: String = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            source.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => source.push('\n'),
+            Some('r') => source.push('\r'),
+            Some('t') => source.push('\t'),
+            Some('0') => source.push('\0'),
+            // A line continuation skips the indentation of the next line
+            Some('\n') => chars = chars.as_str().trim_start().chars(),
+            Some(c) => source.push(c),
+            None => {}
+        }
+    }
+    Some(source)
+}
+
+/// Adds the tokens of `stream` to `pieces`, with the tokens of its groups in between
+/// their delimiters.
+fn flatten(stream: TokenStream, pieces: &mut Vec<Piece>) {
+    for tree in stream {
+        let TokenTree::Group(group) = tree else {
+            pieces.push(Piece {
+                text: tree.to_string(),
+                span: tree.span(),
+            });
+            continue;
+        };
+        let (open, close): (&str, &str) = match group.delimiter() {
+            Delimiter::Parenthesis => ("(", ")"),
+            Delimiter::Bracket => ("[", "]"),
+            Delimiter::Brace => ("{", "}"),
+            Delimiter::None => ("", ""),
+        };
+        if !open.is_empty() {
+            pieces.push(Piece {
+                text: open.to_string(),
+                span: group.span_open(),
+            });
+        }
+        flatten(group.stream(), pieces);
+        if !close.is_empty() {
+            pieces.push(Piece {
+                text: close.to_string(),
+                span: group.span_close(),
+            });
+        }
+    }
+}
+
+/// # Returns
+/// The source `pieces` were written as, a statement per line, each piece spaced like
+/// in the input, and the span of the first piece of each line.
+fn to_source(pieces: &[Piece]) -> (String, Vec<Span>) {
+    let mut source: String = String::new();
+    let mut lines: Vec<Span> = Vec::new();
+    for (index, piece) in pieces.iter().enumerate() {
+        let previous: Option<Span> = index.checked_sub(1).map(|index| pieces[index].span.end());
+        let start: Span = piece.span.start();
+        let same_line: bool = previous.is_some_and(|previous| previous.line() == start.line());
+        let adjacent: bool =
+            same_line && previous.is_some_and(|previous| previous.column() == start.column());
+
+        if !same_line || (!adjacent && starts_statement(pieces, index)) {
+            if previous.is_some() {
+                source.push('\n');
+            }
+            lines.push(piece.span);
+        } else if !adjacent {
+            source.push(' ');
+        }
+        source.push_str(&piece.text);
+    }
+    (source, lines)
+}
+
+/// # Returns
+/// `true` if the piece at `index` starts a statement: a mnemonic, a label followed by
+/// `:`, a constant followed by `=` or the `.` of a directive.
+fn starts_statement(pieces: &[Piece], index: usize) -> bool {
+    let text = |index: usize| pieces.get(index).map_or("", |piece| piece.text.as_str());
+    let is_word = |text: &str| text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    let word: &str = text(index);
+    match word {
+        "." => is_word(text(index + 1)),
+        "@" => is_word(text(index + 1)) && text(index + 2) == ":",
+        _ if is_word(word) => {
+            matches!(text(index + 1), ":" | "=")
+                || (0..=0xff_u8).any(|op_code| {
+                    instruction_info(op_code)
+                        .is_some_and(|info| info.mnemonic.eq_ignore_ascii_case(word))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// # Returns
+/// `compile_error!(message)`, reported at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut literal: Literal = Literal::string(message);
+    literal.set_span(span);
+    let mut group: Group = Group::new(
+        Delimiter::Parenthesis,
+        TokenStream::from(TokenTree::Literal(literal)),
+    );
+    group.set_span(span);
+    let mut bang: Punct = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(group),
+    ]
+    .into_iter()
+    .collect()
+}
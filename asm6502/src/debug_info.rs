@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A line of a source file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// The debug information ld65 writes with `--dbgfile`: which source line every
+/// address was built from, and where the labels and C symbols are.
+pub struct DebugInfo {
+    locations: Vec<Location>,
+    /// The index in `locations` of the line each address was built from.
+    lines: BTreeMap<u16, usize>,
+    symbols: BTreeMap<String, u16>,
+    /// The name of each address a symbol is at, a C one if there is one.
+    names: BTreeMap<u16, String>,
+}
+
+impl DebugInfo {
+    /// Parses the text of a `.dbg` file, version 2: a record per line, its type then
+    /// `key=value` pairs separated by commas. Records and keys this does not use are
+    /// skipped.
+    pub fn parse(text: &str) -> Result<DebugInfo, String> {
+        let mut files: BTreeMap<u64, String> = BTreeMap::new();
+        let mut segments: BTreeMap<u64, u64> = BTreeMap::new();
+        let mut spans: BTreeMap<u64, (u64, u64, u64)> = BTreeMap::new();
+        let mut lines: Vec<(u64, u32, bool, Vec<u64>)> = Vec::new();
+        let mut labels: BTreeMap<u64, (String, u16)> = BTreeMap::new();
+        let mut c_symbols: Vec<(String, u64)> = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", index + 1, message);
+            let (kind, fields): (&str, &str) = match line.trim().split_once(char::is_whitespace) {
+                Some((kind, fields)) => (kind, fields.trim()),
+                None => continue,
+            };
+            let fields: BTreeMap<&str, &str> = parse_fields(fields).map_err(error)?;
+            let number = |key: &str| {
+                let value: &str = fields
+                    .get(key)
+                    .ok_or_else(|| error(format!("`{}` has no `{}`", kind, key)))?;
+                parse_number(value).ok_or_else(|| error(format!("Invalid number `{}`", value)))
+            };
+            let name = || {
+                fields
+                    .get("name")
+                    .map(|name| name.trim_matches('"').to_string())
+                    .ok_or_else(|| error(format!("`{}` has no `name`", kind)))
+            };
+            match kind {
+                "version" if number("major")? != 2 => {
+                    return Err(error(
+                        "Only version 2 debug information is read".to_string(),
+                    ));
+                }
+                "file" => {
+                    files.insert(number("id")?, name()?);
+                }
+                "seg" => {
+                    segments.insert(number("id")?, number("start")?);
+                }
+                "span" => {
+                    spans.insert(
+                        number("id")?,
+                        (number("seg")?, number("start")?, number("size")?),
+                    );
+                }
+                // Lines without a span built nothing
+                "line" if fields.contains_key("span") => {
+                    let line_spans: Vec<u64> = fields["span"]
+                        .split('+')
+                        .map(|span| {
+                            parse_number(span)
+                                .ok_or_else(|| error(format!("Invalid span `{}`", span)))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    // Type 1 is a line of C, 0 of assembly and 2 of a macro
+                    let c: bool = fields.contains_key("type") && number("type")? == 1;
+                    lines.push((number("file")?, number("line")? as u32, c, line_spans));
+                }
+                "sym" if fields.get("type") == Some(&"lab") => {
+                    labels.insert(number("id")?, (name()?, number("val")? as u16));
+                }
+                // Locals live on the C stack instead
+                "csym" if fields.contains_key("sym") => {
+                    c_symbols.push((name()?, number("sym")?));
+                }
+                _ => {}
+            }
+        }
+
+        let mut info: DebugInfo = DebugInfo {
+            locations: Vec::new(),
+            lines: BTreeMap::new(),
+            symbols: BTreeMap::new(),
+            names: BTreeMap::new(),
+        };
+
+        // Assembly first and larger spans first, for C lines and the lines within
+        // others to win
+        let mut covered: Vec<(bool, u16, u16, usize)> = Vec::new();
+        for (file, line, c, line_spans) in lines {
+            let file: &String = files
+                .get(&file)
+                .ok_or_else(|| format!("Unknown file {}", file))?;
+            info.locations.push(Location {
+                file: file.clone(),
+                line,
+            });
+            for span in line_spans {
+                let (segment, start, size): (u64, u64, u64) = *spans
+                    .get(&span)
+                    .ok_or_else(|| format!("Unknown span {}", span))?;
+                let segment: u64 = *segments
+                    .get(&segment)
+                    .ok_or_else(|| format!("Span {} is in unknown segment {}", span, segment))?;
+                covered.push((
+                    c,
+                    size as u16,
+                    (segment + start) as u16,
+                    info.locations.len() - 1,
+                ));
+            }
+        }
+        covered.sort_by_key(|&(c, size, _, _)| (c, std::cmp::Reverse(size)));
+        for (_, size, start, location) in covered {
+            for offset in 0..size {
+                info.lines.insert(start.wrapping_add(offset), location);
+            }
+        }
+
+        for (name, value) in labels.values() {
+            info.symbols.insert(name.clone(), *value);
+            info.names.insert(*value, name.clone());
+        }
+        for (name, symbol) in c_symbols {
+            if let Some((_, value)) = labels.get(&symbol) {
+                info.symbols.insert(name.clone(), *value);
+                info.names.insert(*value, name);
+            }
+        }
+        Ok(info)
+    }
+
+    /// # Returns
+    /// The source line `address` was built from, a line of C over one of assembly.
+    pub fn location(&self, address: u16) -> Option<&Location> {
+        self.lines
+            .get(&address)
+            .map(|&location| &self.locations[location])
+    }
+
+    /// # Returns
+    /// The address of the label or C symbol `name`, e.g. `_main` or `main`.
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// # Returns
+    /// The names of the labels and C symbols, in order.
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+
+    /// # Returns
+    /// The closest symbol at or before `address`, and how far after it `address` is.
+    pub fn symbol_at(&self, address: u16) -> Option<(&str, u16)> {
+        self.names
+            .range(..=address)
+            .next_back()
+            .map(|(start, name)| (name.as_str(), address - start))
+    }
+}
+
+/// # Returns
+/// The `key=value` pairs of `fields`, values keeping their quotes.
+fn parse_fields(fields: &str) -> Result<BTreeMap<&str, &str>, String> {
+    let mut pairs: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut rest: &str = fields;
+    while !rest.is_empty() {
+        let (key, after): (&str, &str) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("Expected `key=value` in `{}`", rest))?;
+        // Strings can hold commas
+        let end: usize = if let Some(string) = after.strip_prefix('"') {
+            string
+                .find('"')
+                .map(|quote| quote + 2)
+                .ok_or_else(|| format!("Unterminated string in `{}`", after))?
+        } else {
+            after.find(',').unwrap_or(after.len())
+        };
+        pairs.insert(key.trim(), &after[..end]);
+        rest = after[end..].strip_prefix(',').unwrap_or(&after[end..]);
+    }
+    Ok(pairs)
+}
+
+/// # Returns
+/// The value of a decimal or `0x` hexadecimal number.
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_addresses_to_lines_and_symbols() {
+        let info: DebugInfo = DebugInfo::parse(
+            "version\tmajor=2,minor=0
+info\tcsym=2,file=2,lib=0,line=3,mod=1,scope=2,seg=1,span=3,sym=2,type=2
+file\tid=0,name=\"hello.c\",size=120,mtime=0x6500A1B2,mod=0
+file\tid=1,name=\"hello, world.s\",size=300,mtime=0x6500A1B3,mod=0
+line\tid=0,file=0,line=3,type=1,count=1,span=2
+line\tid=1,file=0,line=4,type=1,count=1,span=1
+line\tid=2,file=1,line=10,span=0
+line\tid=3,file=1,line=1
+seg\tid=0,name=\"CODE\",start=0x000800,size=0x000010,addrsize=absolute,type=ro,oname=\"hello\",ooffs=2
+span\tid=0,seg=0,start=0,size=3,type=0
+span\tid=1,seg=0,start=3,size=6
+span\tid=2,seg=0,start=0,size=10
+sym\tid=0,name=\"_main\",addrsize=absolute,size=10,scope=0,def=3,ref=5,val=0x800,seg=0,type=lab
+sym\tid=1,name=\"ptr1\",addrsize=zeropage,scope=0,def=4,type=imp,exp=0
+csym\tid=0,name=\"main\",scope=1,type=0,sc=ext,sym=0
+csym\tid=1,name=\"i\",scope=1,type=1,sc=auto,offs=-2
+",
+        )
+        .unwrap();
+
+        let line = |address: u16| info.location(address).map(Location::to_string);
+        assert_eq!(line(0x0800).as_deref(), Some("hello.c:3"));
+        assert_eq!(line(0x0803).as_deref(), Some("hello.c:4"));
+        assert_eq!(line(0x0809).as_deref(), Some("hello.c:3"));
+        assert_eq!(line(0x080a), None);
+        assert_eq!(info.symbol("main"), Some(0x0800));
+        assert_eq!(info.symbol("_main"), Some(0x0800));
+        assert_eq!(info.symbol("i"), None);
+        assert_eq!(info.symbol_at(0x0805), Some(("main", 5)));
+        assert_eq!(info.symbol_at(0x07ff), None);
+
+        assert!(DebugInfo::parse("version\tmajor=3,minor=0").is_err());
+        assert!(DebugInfo::parse(
+            "file\tid=0,name=\"a.s\"\nspan\tid=0,seg=5,start=0,size=1\nline\tid=0,file=0,line=1,span=0"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::{hex_bytes, op_code};
+
+use mos6502::opcodes::AddressingMode;
+use mos6502::{instruction_info, InstructionInfo};
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Where the NMI, reset and IRQ vectors are, in that order.
+const VECTORS: u16 = 0xfffa;
+
+/// How many bytes a `.byte` line holds at most.
+const DATA_BYTES: usize = 8;
+
+/// What a byte of the program was found to be.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Data,
+    /// The first byte of an instruction.
+    Instruction,
+    /// The first byte of an address, written `.word`.
+    Word,
+    /// A byte after the first of an instruction or address.
+    Rest,
+}
+
+/// What a line of the disassembly is.
+enum Item {
+    Instruction { info: InstructionInfo, operand: u16 },
+    Word(u16),
+    Bytes(Vec<u8>),
+}
+
+impl Item {
+    fn length(&self) -> usize {
+        match self {
+            Item::Instruction { info, .. } => info.length as usize,
+            Item::Word(_) => 2,
+            Item::Bytes(bytes) => bytes.len(),
+        }
+    }
+
+    /// # Returns
+    /// Where the instruction at `address` branches, jumps or calls to, or the address
+    /// the word is.
+    fn target(&self, address: u16) -> Option<u16> {
+        match self {
+            Item::Instruction { info, operand } => jump_target(info, *operand, address),
+            Item::Word(value) => Some(*value),
+            Item::Bytes(_) => None,
+        }
+    }
+}
+
+/// Disassembles `bytes`, loaded at `origin`, into source `assemble()` reads back to the
+/// same bytes, each line commented with its address and bytes.
+///
+/// What is code is what the control flow reaches from `entries` and from the vectors at
+/// `$FFFA`-`$FFFF` if `bytes` covers them, which are written `.word`, as are the
+/// pointers of `JMP ($nnnn)`. The rest is data, written `.byte`. Without any entry or
+/// vector everything that decodes is code.
+///
+/// Branch, `JMP` and `JSR` targets and the addresses of words get a label, `L` and
+/// their address, which is a constant if they are outside of `bytes`.
+///
+/// Bytes past `$FFFF` are left out.
+pub fn disassemble(bytes: &[u8], origin: u16, entries: &[u16]) -> String {
+    let bytes: &[u8] = &bytes[..bytes.len().min(0x10000 - origin as usize)];
+    let items: Vec<(u16, Item)> = items(bytes, origin, &classify(bytes, origin, entries));
+    let end: u32 = origin as u32 + bytes.len() as u32;
+
+    // Targets inside the program can only be labelled at the start of a line
+    let starts: BTreeSet<u16> = items.iter().map(|(address, _)| *address).collect();
+    let targets: BTreeSet<u16> = items
+        .iter()
+        .filter_map(|(address, item)| item.target(*address))
+        .collect();
+    let outside = |target: &u16| (*target as u32) < origin as u32 || *target as u32 >= end;
+    let labels: BTreeSet<u16> = targets
+        .iter()
+        .copied()
+        .filter(|target| outside(target) || starts.contains(target))
+        .collect();
+    let operand = |target: u16| labels.contains(&target).then(|| format!("L{:04X}", target));
+
+    let mut source: String = String::new();
+    for target in targets.iter().filter(|target| outside(target)) {
+        let _ = writeln!(source, "L{:04X} = ${:04X}", target, target);
+    }
+    if !source.is_empty() {
+        source.push('\n');
+    }
+    let _ = writeln!(source, "{:8}.org ${:04X}", "", origin);
+
+    let mut offset: usize = 0;
+    for (address, item) in &items {
+        let label: String = if labels.contains(address) {
+            format!("L{:04X}:", address)
+        } else {
+            String::new()
+        };
+        let text: String = match item {
+            Item::Instruction {
+                info,
+                operand: value,
+            } => {
+                let operand: String = item
+                    .target(*address)
+                    .and_then(operand)
+                    .unwrap_or_else(|| format_operand(info, *value, *address));
+                format!("{} {}", info.mnemonic, operand)
+                    .trim_end()
+                    .to_string()
+            }
+            Item::Word(value) => {
+                format!(
+                    ".word {}",
+                    operand(*value).unwrap_or(format!("${:04X}", value))
+                )
+            }
+            Item::Bytes(data) => {
+                let data: Vec<String> = data.iter().map(|byte| format!("${:02X}", byte)).collect();
+                format!(".byte {}", data.join(", "))
+            }
+        };
+        let line: String = match item {
+            // The bytes are in the line already
+            Item::Bytes(_) => format!("{:<8}{:<23} ; {:04X}", label, text, address),
+            _ => format!(
+                "{:<8}{:<23} ; {:04X}  {}",
+                label,
+                text,
+                address,
+                hex_bytes(&bytes[offset..offset + item.length()])
+            ),
+        };
+        let _ = writeln!(source, "{}", line);
+        offset += item.length();
+    }
+    source
+}
+
+/// # Returns
+/// What every byte of `bytes`, loaded at `origin`, is: the vectors it covers are words,
+/// then what the control flow from the entries and vectors reaches is code, or, without
+/// any, every instruction that decodes one after the other.
+fn classify(bytes: &[u8], origin: u16, entries: &[u16]) -> Vec<Class> {
+    let mut classes: Vec<Class> = vec![Class::Data; bytes.len()];
+    let mut pending: Vec<u16> = entries.to_vec();
+    let offset_of = |address: u16| {
+        (address as usize)
+            .checked_sub(origin as usize)
+            .filter(|&offset| offset < bytes.len())
+    };
+
+    for vector in (VECTORS..=0xfffe).step_by(2) {
+        if let (Some(low), Some(high)) = (offset_of(vector), offset_of(vector + 1)) {
+            classes[low] = Class::Word;
+            classes[high] = Class::Rest;
+            pending.push(u16::from_le_bytes([bytes[low], bytes[high]]));
+        }
+    }
+
+    // Claims the instruction at `offset` if it decodes and its bytes are free
+    let claim = |classes: &mut Vec<Class>, offset: usize| {
+        let info: InstructionInfo = instruction_info(bytes[offset])?;
+        let length: usize = info.length as usize;
+        let free: bool = classes
+            .get(offset..offset + length)
+            .is_some_and(|classes| classes.iter().all(|&class| class == Class::Data));
+        if !free {
+            return None;
+        }
+        classes[offset] = Class::Instruction;
+        classes[offset + 1..offset + length].fill(Class::Rest);
+        Some((info, operand(&bytes[offset..offset + length])))
+    };
+
+    if pending.is_empty() {
+        let mut offset: usize = 0;
+        while offset < bytes.len() {
+            offset += claim(&mut classes, offset).map_or(1, |(info, _)| info.length as usize);
+        }
+        return classes;
+    }
+
+    let mut pointers: Vec<u16> = Vec::new();
+    while let Some(address) = pending.pop() {
+        let Some(mut offset) = offset_of(address) else {
+            continue;
+        };
+        while let Some((info, operand)) = claim(&mut classes, offset) {
+            let address: u16 = origin + offset as u16;
+            pending.extend(jump_target(&info, operand, address));
+            match (info.mnemonic, info.addressing_mode) {
+                ("JMP", AddressingMode::Indirect) => {
+                    pointers.push(operand);
+                    break;
+                }
+                ("JMP" | "RTS" | "RTI" | "BRK", _) => break,
+                _ => offset += info.length as usize,
+            }
+            if offset >= bytes.len() {
+                break;
+            }
+        }
+    }
+
+    for pointer in pointers {
+        if let (Some(low), Some(high)) = (
+            offset_of(pointer),
+            pointer.checked_add(1).and_then(offset_of),
+        ) {
+            if classes[low] == Class::Data && classes[high] == Class::Data {
+                classes[low] = Class::Word;
+                classes[high] = Class::Rest;
+            }
+        }
+    }
+    classes
+}
+
+/// # Returns
+/// The lines of `bytes` loaded at `origin`, as `classes` tells, data split before the
+/// targets of the code so that they can be labelled.
+fn items(bytes: &[u8], origin: u16, classes: &[Class]) -> Vec<(u16, Item)> {
+    let mut items: Vec<(u16, Item)> = Vec::new();
+    let mut offset: usize = 0;
+    while offset < bytes.len() {
+        let address: u16 = origin + offset as u16;
+        let item: Item = match classes[offset] {
+            Class::Instruction => {
+                let info: InstructionInfo = instruction_info(bytes[offset]).unwrap();
+                Item::Instruction {
+                    info,
+                    operand: operand(&bytes[offset..offset + info.length as usize]),
+                }
+            }
+            Class::Word => Item::Word(u16::from_le_bytes([bytes[offset], bytes[offset + 1]])),
+            Class::Data | Class::Rest => {
+                let mut length: usize = 1;
+                while length < DATA_BYTES
+                    && offset + length < bytes.len()
+                    && classes[offset + length] == Class::Data
+                {
+                    length += 1;
+                }
+                Item::Bytes(bytes[offset..offset + length].to_vec())
+            }
+        };
+        offset += item.length();
+        items.push((address, item));
+    }
+
+    // Words and jumps into data, split off so that they start a line of their own
+    let targets: BTreeSet<u16> = items
+        .iter()
+        .filter_map(|(address, item)| item.target(*address))
+        .collect();
+    items
+        .into_iter()
+        .flat_map(|(address, item)| match item {
+            Item::Bytes(data) => split(address, data, &targets),
+            item => vec![(address, item)],
+        })
+        .collect()
+}
+
+/// # Returns
+/// The bytes `data` at `address` as lines, a new one at each of `targets`.
+fn split(address: u16, data: Vec<u8>, targets: &BTreeSet<u16>) -> Vec<(u16, Item)> {
+    let mut lines: Vec<(u16, Item)> = Vec::new();
+    let mut start: usize = 0;
+    for index in 1..=data.len() {
+        if index == data.len() || targets.contains(&(address + index as u16)) {
+            lines.push((
+                address + start as u16,
+                Item::Bytes(data[start..index].to_vec()),
+            ));
+            start = index;
+        }
+    }
+    lines
+}
+
+/// # Returns
+/// The operand of the whole instruction `bytes`, zero without one.
+fn operand(bytes: &[u8]) -> u16 {
+    match bytes.len() {
+        1 => 0,
+        2 => bytes[1] as u16,
+        _ => u16::from_le_bytes([bytes[1], bytes[2]]),
+    }
+}
+
+/// # Returns
+/// Where the instruction `info` at `address` branches, jumps or calls to, if it does.
+fn jump_target(info: &InstructionInfo, operand: u16, address: u16) -> Option<u16> {
+    match info.addressing_mode {
+        AddressingMode::Relative => Some(
+            address
+                .wrapping_add(2)
+                .wrapping_add(operand as u8 as i8 as u16),
+        ),
+        AddressingMode::Absolute if matches!(info.mnemonic, "JMP" | "JSR") => Some(operand),
+        _ => None,
+    }
+}
+
+/// # Returns
+/// The operand as `assemble()` reads it back to the same instruction: an absolute
+/// address that fits in a byte is written `a:`, not to be taken for a zero-page one.
+fn format_operand(info: &InstructionInfo, operand: u16, address: u16) -> String {
+    let text: String = info.addressing_mode.format_operand(operand, address);
+    let zero_page: Option<AddressingMode> = match info.addressing_mode {
+        AddressingMode::Absolute => Some(AddressingMode::ZeroPage),
+        AddressingMode::AbsoluteX => Some(AddressingMode::ZeroPageX),
+        AddressingMode::AbsoluteY => Some(AddressingMode::ZeroPageY),
+        _ => None,
+    };
+    match zero_page {
+        Some(mode) if operand <= 0xff && op_code(info.mnemonic, mode).is_some() => {
+            format!("a:{}", text)
+        }
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assemble;
+
+    #[test]
+    fn labels_targets_and_assembles_back() {
+        // loop: JSR $FFD2, DEX, BNE loop, JMP (vector), then an undocumented opcode
+        let bytes: [u8; 10] = [0x20, 0xd2, 0xff, 0xca, 0xd0, 0xfa, 0x6c, 0x34, 0x12, 0x02];
+        let source: String = disassemble(&bytes, 0xc000, &[]);
+
+        assert_eq!(
+            source.lines().collect::<Vec<&str>>(),
+            [
+                "LFFD2 = $FFD2",
+                "",
+                "        .org $C000",
+                "LC000:  JSR LFFD2               ; C000  20 D2 FF",
+                "        DEX                     ; C003  CA",
+                "        BNE LC000               ; C004  D0 FA",
+                "        JMP ($1234)             ; C006  6C 34 12",
+                "        .byte $02               ; C009",
+            ]
+        );
+        assert_eq!(assemble(&source).unwrap().image(), bytes);
+    }
+
+    #[test]
+    fn follows_vectors_and_keeps_data() {
+        let mut bytes: Vec<u8> = vec![
+            0xad, 0x10, 0x00, // LDA a:$0010
+            0xf0, 0x03, // BEQ done
+            0x4c, 0xe0, 0xff, // JMP reset
+            0x60, // done: RTS
+            0xa9, 0x00,
+        ];
+        bytes.extend_from_slice(b"hello, world!\x00\x02");
+        bytes.extend_from_slice(&[0xe8, 0xff, 0xe0, 0xff, 0x00, 0x03]);
+        let source: String = disassemble(&bytes, 0xffe0, &[]);
+
+        assert_eq!(
+            source.lines().collect::<Vec<&str>>(),
+            [
+                "L0300 = $0300",
+                "",
+                "        .org $FFE0",
+                "LFFE0:  LDA a:$0010             ; FFE0  AD 10 00",
+                "        BEQ LFFE8               ; FFE3  F0 03",
+                "        JMP LFFE0               ; FFE5  4C E0 FF",
+                "LFFE8:  RTS                     ; FFE8  60",
+                "        .byte $A9, $00, $68, $65, $6C, $6C, $6F, $2C ; FFE9",
+                "        .byte $20, $77, $6F, $72, $6C, $64, $21, $00 ; FFF1",
+                "        .byte $02               ; FFF9",
+                "        .word LFFE8             ; FFFA  E8 FF",
+                "        .word LFFE0             ; FFFC  E0 FF",
+                "        .word L0300             ; FFFE  00 03",
+            ]
+        );
+        assert_eq!(assemble(&source).unwrap().image(), bytes);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+
+/// An operator taking one value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    /// `-`
+    Negate,
+    /// `~`, bitwise.
+    Not,
+    /// `<`, the low byte.
+    Low,
+    /// `>`, the high byte.
+    High,
+    /// `!`, 1 for 0 and 0 otherwise.
+    LogicalNot,
+}
+
+/// An operator taking two values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    /// The comparisons give 1 if they hold and 0 otherwise.
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// `&&`, only evaluating the right value if the left one is not 0.
+    LogicalAnd,
+    /// `||`, only evaluating the right value if the left one is 0.
+    LogicalOr,
+}
+
+/// The binary operators by precedence, lowest first, like in C.
+const LEVELS: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::LogicalOr)],
+    &[("&&", BinaryOp::LogicalAnd)],
+    &[("|", BinaryOp::Or)],
+    &[("^", BinaryOp::Xor)],
+    &[("&", BinaryOp::And)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Mod),
+    ],
+];
+
+/// A value in the source, computed once every symbol it uses is defined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    /// `name[index]`, e.g. `mem[$0200]` in the monitor, which the assembler has none of.
+    Index(String, Box<Expr>),
+    /// `*`, the address of the line.
+    Pc,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses `text`: numbers (`$` or `0x` hexadecimal, `%` binary, decimal, `'c'`
+    /// characters), symbols, `name[index]` and `*`, combined with `( )`, the unary `-`,
+    /// `~`, `!`, `<` and `>`, and the binary `*`, `/`, `%`, `+`, `-`, `<<`, `>>`, the
+    /// comparisons, `&`, `^`, `|`, `&&` and `||`, by the precedence of C.
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser: Parser = Parser {
+            text,
+            chars: text.chars().collect(),
+            position: 0,
+        };
+        let expr: Expr = parser.binary(0)?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(expr),
+            Some(c) => Err(format!("Unexpected `{}` in `{}`", c, text.trim())),
+        }
+    }
+
+    /// # Arguments
+    /// * `symbols` - The values of the symbols defined so far.
+    /// * `pc` - The address of the line, the value of `*`.
+    ///
+    /// # Returns
+    /// The value, or an error naming the first symbol that is not defined.
+    pub fn evaluate(&self, symbols: &BTreeMap<String, i64>, pc: u16) -> Result<i64, String> {
+        self.evaluate_with(
+            &|name: &str, index: Option<i64>| match index {
+                None => symbols.get(name).copied(),
+                Some(_) => None,
+            },
+            pc,
+        )
+    }
+
+    /// Like `evaluate()`, with `symbol` giving the value of each symbol, if defined, and
+    /// of `name[index]` for the name and the value of the index.
+    pub fn evaluate_with(
+        &self,
+        symbol: &dyn Fn(&str, Option<i64>) -> Option<i64>,
+        pc: u16,
+    ) -> Result<i64, String> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Symbol(name) => {
+                symbol(name, None).ok_or_else(|| format!("Undefined symbol `{}`", name))
+            }
+            Expr::Index(name, index) => {
+                let index: i64 = index.evaluate_with(symbol, pc)?;
+                symbol(name, Some(index))
+                    .ok_or_else(|| format!("Invalid index {} of `{}`", index, name))
+            }
+            Expr::Pc => Ok(pc as i64),
+            Expr::Unary(op, value) => {
+                let value: i64 = value.evaluate_with(symbol, pc)?;
+                Ok(match op {
+                    UnaryOp::Negate => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                    UnaryOp::Low => value & 0xff,
+                    UnaryOp::High => (value >> 8) & 0xff,
+                    UnaryOp::LogicalNot => (value == 0) as i64,
+                })
+            }
+            Expr::Binary(op, left, right) => {
+                let left: i64 = left.evaluate_with(symbol, pc)?;
+                match op {
+                    BinaryOp::LogicalAnd if left == 0 => return Ok(0),
+                    BinaryOp::LogicalOr if left != 0 => return Ok(1),
+                    _ => {}
+                }
+                let right: i64 = right.evaluate_with(symbol, pc)?;
+                let shift = |shift: fn(i64, u32) -> Option<i64>| {
+                    u32::try_from(right)
+                        .ok()
+                        .and_then(|right| shift(left, right))
+                        .ok_or_else(|| format!("Invalid shift by {}", right))
+                };
+                match op {
+                    BinaryOp::Add => Ok(left.wrapping_add(right)),
+                    BinaryOp::Sub => Ok(left.wrapping_sub(right)),
+                    BinaryOp::Mul => Ok(left.wrapping_mul(right)),
+                    BinaryOp::Div => left
+                        .checked_div(right)
+                        .ok_or_else(|| "Division by zero".to_string()),
+                    BinaryOp::Mod => left
+                        .checked_rem(right)
+                        .ok_or_else(|| "Division by zero".to_string()),
+                    BinaryOp::And => Ok(left & right),
+                    BinaryOp::Or => Ok(left | right),
+                    BinaryOp::Xor => Ok(left ^ right),
+                    BinaryOp::Shl => shift(i64::checked_shl),
+                    BinaryOp::Shr => shift(i64::checked_shr),
+                    BinaryOp::Eq => Ok((left == right) as i64),
+                    BinaryOp::Ne => Ok((left != right) as i64),
+                    BinaryOp::Lt => Ok((left < right) as i64),
+                    BinaryOp::Le => Ok((left <= right) as i64),
+                    BinaryOp::Gt => Ok((left > right) as i64),
+                    BinaryOp::Ge => Ok((left >= right) as i64),
+                    BinaryOp::LogicalAnd | BinaryOp::LogicalOr => Ok((right != 0) as i64),
+                }
+            }
+        }
+    }
+
+    /// # Returns
+    /// `true` if the value is a byte whatever the symbols, i.e. `<` or `>` of something.
+    pub fn is_byte(&self) -> bool {
+        matches!(self, Expr::Unary(UnaryOp::Low | UnaryOp::High, _))
+    }
+
+    /// # Returns
+    /// The names of the symbols used, in order.
+    pub fn symbols(&self) -> Vec<&str> {
+        match self {
+            Expr::Symbol(name) => vec![name.as_str()],
+            Expr::Index(_, index) => index.symbols(),
+            Expr::Unary(_, value) => value.symbols(),
+            Expr::Binary(_, left, right) => {
+                let mut names: Vec<&str> = left.symbols();
+                names.extend(right.symbols());
+                names
+            }
+            Expr::Number(_) | Expr::Pc => Vec::new(),
+        }
+    }
+}
+
+/// # Returns
+/// `true` if `text` can name a label: a letter, `_` or `@`, then letters, digits, `_`
+/// and `@`.
+pub fn is_symbol(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+}
+
+/// A recursive descent parser over the characters of an expression.
+struct Parser<'a> {
+    text: &'a str,
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Moves past the binary operator `token` if it comes next, but not past the first
+    /// half of `&&` or `||` for `&` or `|`.
+    fn eat_operator(&mut self, token: &str) -> bool {
+        let doubled: bool = matches!(token, "&" | "|")
+            && self.chars.get(self.position + 1).copied() == token.chars().next();
+        if doubled && self.chars.get(self.position).copied() == token.chars().next() {
+            return false;
+        }
+        self.eat(token)
+    }
+
+    /// Moves past `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        let matches: bool = token
+            .chars()
+            .enumerate()
+            .all(|(offset, c)| self.chars.get(self.position + offset) == Some(&c));
+        if matches {
+            self.position += token.chars().count();
+        }
+        matches
+    }
+
+    /// # Returns
+    /// The characters from here on that satisfy `accept`.
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let start: usize = self.position;
+        while self.peek().is_some_and(&accept) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    /// Parses the operators of precedence `level` and above.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left: Expr = self.binary(level + 1)?;
+        'operators: loop {
+            self.skip_whitespace();
+            for &(token, op) in LEVELS[level] {
+                if self.eat_operator(token) {
+                    let right: Expr = self.binary(level + 1)?;
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        let op: UnaryOp = if self.eat("-") {
+            UnaryOp::Negate
+        } else if self.eat("~") {
+            UnaryOp::Not
+        } else if self.eat("<") {
+            UnaryOp::Low
+        } else if self.eat(">") {
+            UnaryOp::High
+        } else if self.eat("!") {
+            UnaryOp::LogicalNot
+        } else if self.eat("+") {
+            return self.unary();
+        } else {
+            return self.primary();
+        };
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        let number = |digits: String, radix: u32| {
+            i64::from_str_radix(&digits, radix)
+                .map(Expr::Number)
+                .map_err(|_| format!("Invalid number `{}`", digits))
+        };
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expr: Expr = self.binary(0)?;
+                self.skip_whitespace();
+                if !self.eat(")") {
+                    return Err(format!("Missing `)` in `{}`", self.text.trim()));
+                }
+                Ok(expr)
+            }
+            Some('*') => {
+                self.position += 1;
+                Ok(Expr::Pc)
+            }
+            Some('$') => {
+                self.position += 1;
+                number(self.take_while(|c| c.is_ascii_alphanumeric()), 16)
+            }
+            Some('%') => {
+                self.position += 1;
+                number(self.take_while(|c| c.is_ascii_alphanumeric()), 2)
+            }
+            Some('0') if matches!(self.chars.get(self.position + 1), Some('x' | 'X')) => {
+                self.position += 2;
+                number(self.take_while(|c| c.is_ascii_alphanumeric()), 16)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                number(self.take_while(|c| c.is_ascii_alphanumeric()), 10)
+            }
+            Some(quote @ ('\'' | '"')) => {
+                let c: Option<char> = self.chars.get(self.position + 1).copied();
+                match c {
+                    Some(c) if self.chars.get(self.position + 2) == Some(&quote) => {
+                        self.position += 3;
+                        Ok(Expr::Number(c as i64))
+                    }
+                    _ => Err(format!("Invalid character in `{}`", self.text.trim())),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '@' => {
+                let name: String =
+                    self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@');
+                if !self.eat("[") {
+                    return Ok(Expr::Symbol(name));
+                }
+                let index: Expr = self.binary(0)?;
+                self.skip_whitespace();
+                if !self.eat("]") {
+                    return Err(format!("Missing `]` in `{}`", self.text.trim()));
+                }
+                Ok(Expr::Index(name, Box::new(index)))
+            }
+            _ => Err(format!("Invalid expression `{}`", self.text.trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_operators_by_precedence() {
+        let symbols: BTreeMap<String, i64> = BTreeMap::from([("table".to_string(), 0x1234)]);
+        let value = |text: &str| Expr::parse(text).and_then(|expr| expr.evaluate(&symbols, 0x0200));
+
+        assert_eq!(value("1 + 2 * 3"), Ok(7));
+        assert_eq!(value("(1 + 2) * 3"), Ok(9));
+        assert_eq!(value("<table"), Ok(0x34));
+        assert_eq!(value(">(table + $100)"), Ok(0x13));
+        assert_eq!(value("1 << 4 | %11 & ~1"), Ok(0x12));
+        assert_eq!(value("* + 3"), Ok(0x0203));
+        assert_eq!(value("'A' - -1"), Ok(0x42));
+        assert_eq!(value("0x10 + $10"), Ok(0x20));
+        assert_eq!(value("1 + 1 == 2 && 3 > 2 || 0"), Ok(1));
+        assert_eq!(value("!(2 <= 1) & 3 != 3"), Ok(0));
+        assert_eq!(value("6 & 3 | 8"), Ok(10));
+        assert_eq!(value("0 && 1 / 0"), Ok(0));
+        assert_eq!(
+            value("table[1]"),
+            Err("Invalid index 1 of `table`".to_string())
+        );
+        assert_eq!(value("table / 0"), Err("Division by zero".to_string()));
+        assert_eq!(
+            value("missing + 1"),
+            Err("Undefined symbol `missing`".to_string())
+        );
+        assert!(Expr::parse("1 +").is_err());
+        assert!(Expr::parse("(1").is_err());
+        assert!(Expr::parse("1 2").is_err());
+    }
+}
@@ -0,0 +1,966 @@
+pub mod debug_info;
+pub mod disasm;
+pub mod expr;
+mod macros;
+
+use expr::{is_symbol, Expr};
+use macros::{Role, SourceLine};
+use mos6502::instruction_info;
+use mos6502::opcodes::AddressingMode;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The size of the address space, which programs cannot run past.
+const ADDRESS_SPACE: u32 = 0x10000;
+
+/// How many bytes a row of the listing shows, longer lines taking several rows.
+const LISTING_BYTES: usize = 3;
+
+/// How many data bytes an Intel HEX record holds at most.
+const IHEX_RECORD_BYTES: usize = 16;
+
+/// Bytes assembled at consecutive addresses, see `Program::segments()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub start: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// A line of the source and what it assembled to, see `Program::listing()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListingLine {
+    /// The number of the line in the source, that of the call for macro expansions.
+    pub line: usize,
+    /// Where the line goes, `None` in macro definitions.
+    pub address: Option<u16>,
+    pub bytes: Vec<u8>,
+    /// How many macro calls deep the line is, `0` in the source itself.
+    pub depth: usize,
+    pub text: String,
+}
+
+/// An assembled program, see `assemble()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    segments: Vec<Segment>,
+    symbols: BTreeMap<String, u16>,
+    listing: Vec<ListingLine>,
+}
+
+impl Program {
+    /// # Returns
+    /// The bytes of the program, a segment per run of consecutive addresses, in the
+    /// order of the source.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// # Returns
+    /// The labels and constants of the program and their values, constants cut to
+    /// 16 bits.
+    pub fn symbols(&self) -> &BTreeMap<String, u16> {
+        &self.symbols
+    }
+
+    /// # Returns
+    /// The value of the label or constant `name`, if the program defines it.
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// # Returns
+    /// The lowest address the program fills, `0x0000` if it is empty.
+    pub fn start(&self) -> u16 {
+        self.segments
+            .iter()
+            .map(|segment| segment.start)
+            .min()
+            .unwrap_or(0x0000)
+    }
+
+    /// # Returns
+    /// The memory image of the program, from `start()` to its last byte, with the gaps
+    /// between segments left at zero.
+    pub fn image(&self) -> Vec<u8> {
+        let start: usize = self.start() as usize;
+        let end: usize = self
+            .segments
+            .iter()
+            .map(|segment| segment.start as usize + segment.bytes.len())
+            .max()
+            .unwrap_or(start);
+        let mut image: Vec<u8> = vec![0x00; end - start];
+        for segment in &self.segments {
+            let offset: usize = segment.start as usize - start;
+            image[offset..offset + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        image
+    }
+
+    /// # Returns
+    /// The program as a C64 `.prg` file: the load address, `start()`, then `image()`.
+    pub fn to_prg(&self) -> Vec<u8> {
+        let mut prg: Vec<u8> = self.start().to_le_bytes().to_vec();
+        prg.extend_from_slice(&self.image());
+        prg
+    }
+
+    /// # Returns
+    /// The program as Intel HEX: a data record per 16 bytes of each segment, then the
+    /// end of file record.
+    pub fn to_ihex(&self) -> String {
+        let mut hex: String = String::new();
+        for segment in &self.segments {
+            for (index, chunk) in segment.bytes.chunks(IHEX_RECORD_BYTES).enumerate() {
+                let address: u16 = segment.start + (index * IHEX_RECORD_BYTES) as u16;
+                let mut record: Vec<u8> = vec![chunk.len() as u8];
+                record.extend_from_slice(&address.to_be_bytes());
+                record.push(0x00);
+                record.extend_from_slice(chunk);
+                let sum: u8 = record
+                    .iter()
+                    .fold(0, |sum: u8, byte| sum.wrapping_add(*byte));
+                record.push(sum.wrapping_neg());
+                let _ = writeln!(hex, ":{}", hex_bytes(&record).replace(' ', ""));
+            }
+        }
+        hex.push_str(":00000001FF\n");
+        hex
+    }
+
+    /// # Returns
+    /// Every line of the source, with the lines macros expand to after their call.
+    pub fn listing_lines(&self) -> &[ListingLine] {
+        &self.listing
+    }
+
+    /// # Returns
+    /// The listing of the program: the address, the bytes in hexadecimal and the text
+    /// of each line, marked with a `+` per macro call it is expanded from.
+    pub fn listing(&self) -> String {
+        let mut listing: String = String::new();
+        for line in &self.listing {
+            let mut rows = line.bytes.chunks(LISTING_BYTES);
+            let address: String = line
+                .address
+                .map_or_else(|| " ".repeat(4), |address| format!("{:04X}", address));
+            let row: String = format!(
+                "{}  {:<width$}  {}{}",
+                address,
+                hex_bytes(rows.next().unwrap_or(&[])),
+                "+".repeat(line.depth),
+                line.text,
+                width = 3 * LISTING_BYTES - 1
+            );
+            let _ = writeln!(listing, "{}", row.trim_end());
+            for (index, bytes) in rows.enumerate() {
+                // Only lines with an address have bytes
+                let address: usize = line.address.unwrap() as usize + (index + 1) * LISTING_BYTES;
+                let _ = writeln!(listing, "{:04X}  {}", address, hex_bytes(bytes));
+            }
+        }
+        listing
+    }
+
+    /// # Returns
+    /// The symbols as VICE labels, which its monitor loads with `ll "file"`: a line
+    /// `al C:0200 .start` each. Local labels of macros are left out, VICE does not
+    /// take names with an `@`.
+    pub fn vice_labels(&self) -> String {
+        let mut labels: String = String::new();
+        for (name, value) in &self.symbols {
+            if !name.contains('@') {
+                let _ = writeln!(labels, "al C:{:04x} .{}", value, name);
+            }
+        }
+        labels
+    }
+
+    /// Copies the segments into `ram`, the whole address space, e.g. that of
+    /// `Memory::as_mut_slice()`.
+    pub fn load_into(&self, ram: &mut [u8]) {
+        for segment in &self.segments {
+            let start: usize = segment.start as usize;
+            ram[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+    }
+}
+
+/// What a line of source assembles to.
+enum Kind {
+    Instruction {
+        mnemonic: String,
+        mode: AddressingMode,
+        operand: Option<Expr>,
+    },
+    Bytes(Vec<Item>),
+    Words(Vec<Expr>),
+}
+
+/// An element of a `.byte` list.
+enum Item {
+    Value(Expr),
+    Text(Vec<u8>),
+}
+
+impl Kind {
+    /// # Returns
+    /// How many bytes the line assembles to.
+    fn size(&self) -> u32 {
+        match self {
+            Kind::Instruction { mode, .. } => 1 + mode.operand_bytes() as u32,
+            Kind::Bytes(items) => items
+                .iter()
+                .map(|item| match item {
+                    Item::Value(_) => 1,
+                    Item::Text(text) => text.len() as u32,
+                })
+                .sum(),
+            Kind::Words(values) => 2 * values.len() as u32,
+        }
+    }
+}
+
+/// A line of source that assembles to bytes, placed by the first pass.
+struct Statement {
+    line: usize,
+    address: u16,
+    kind: Kind,
+    /// Where the line is in the listing.
+    listed: usize,
+}
+
+/// What a line of source does, besides defining its labels.
+enum Line {
+    Nothing,
+    Origin(Expr),
+    Constant(String, Expr),
+    Code(Kind),
+}
+
+/// A constant using symbols defined further down, computed after the first pass.
+struct Pending {
+    line: usize,
+    name: String,
+    value: Expr,
+    address: u16,
+}
+
+/// Assembles `source`, in the usual 6502 syntax: one instruction or directive per line,
+/// optionally after labels ending with `:`, and comments from `;` on. Mnemonics and
+/// directives are case-insensitive, symbols are not.
+///
+/// - Operands: `#value`, `value`, `value,X`, `value,Y`, `(value)`, `(value,X)`,
+///   `(value),Y` and `A`. The zero-page form is used when the value is known to fit
+///   by then, so forward references get the absolute form, unless they are the `<` or
+///   `>` byte of something.
+/// - Values: expressions of numbers, symbols and `*`, the address of the line, see
+///   `Expr::parse()`. Symbols can be used before they are defined.
+/// - `NAME = value` or `NAME .equ value`: defines a constant.
+/// - `.org address`: where the next lines go, `$0000` at first. It must only use
+///   symbols defined above it.
+/// - `.byte values`: bytes and `"text"`, separated by commas, and `.asciiz` the same
+///   followed by a zero. `.word values`: 16-bit values, little-endian.
+/// - `.macro name param, ...` to `.endmacro`: a macro, see `macros::expand()`.
+///
+/// # Returns
+/// The program, or the first error, prefixed with its line number.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut symbols: BTreeMap<String, i64> = BTreeMap::new();
+    let mut pending: Vec<Pending> = Vec::new();
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut listing: Vec<ListingLine> = Vec::new();
+    let mut address: u32 = 0x0000;
+
+    // First pass: where every line and label goes
+    for source_line in macros::expand(source)? {
+        let SourceLine {
+            line,
+            text,
+            depth,
+            role,
+        } = source_line;
+        let listed: usize = listing.len();
+        listing.push(ListingLine {
+            line,
+            address: (role != Role::Definition).then_some(address as u16),
+            bytes: Vec::new(),
+            depth,
+            text: text.clone(),
+        });
+        if role == Role::Definition {
+            continue;
+        }
+        let at = |error: String| format!("line {}: {}", line, error);
+        let rest: &str = strip_labels(&text, |label| {
+            define(&mut symbols, &pending, label, address as i64)
+        })
+        .map_err(at)?;
+        if role == Role::Call {
+            continue;
+        }
+        match parse_statement(rest, &symbols, address as u16).map_err(at)? {
+            Line::Nothing => {}
+            Line::Origin(origin) => {
+                let origin: i64 = origin.evaluate(&symbols, address as u16).map_err(at)?;
+                address = u16::try_from(origin)
+                    .map_err(|_| at(format!("Invalid origin {}", origin)))?
+                    as u32;
+                listing[listed].address = Some(address as u16);
+            }
+            Line::Constant(name, value) => match value.evaluate(&symbols, address as u16) {
+                Ok(value) => define(&mut symbols, &pending, &name, value).map_err(at)?,
+                Err(_) => {
+                    check_new(&symbols, &pending, &name).map_err(at)?;
+                    pending.push(Pending {
+                        line,
+                        name,
+                        value,
+                        address: address as u16,
+                    });
+                }
+            },
+            Line::Code(Kind::Bytes(items)) if items.is_empty() => {}
+            Line::Code(kind) => {
+                let size: u32 = kind.size();
+                statements.push(Statement {
+                    line,
+                    address: address as u16,
+                    kind,
+                    listed,
+                });
+                address += size;
+                if address > ADDRESS_SPACE {
+                    return Err(at("past the end of memory".to_string()));
+                }
+            }
+        }
+    }
+
+    // Constants using symbols defined after them, in as many rounds as they need
+    while !pending.is_empty() {
+        let count: usize = pending.len();
+        pending.retain(
+            |constant| match constant.value.evaluate(&symbols, constant.address) {
+                Ok(value) => {
+                    symbols.insert(constant.name.clone(), value);
+                    false
+                }
+                Err(_) => true,
+            },
+        );
+        if pending.len() == count {
+            let is_pending = |name: &str| pending.iter().any(|other| other.name == name);
+            // Report a symbol that is missing or wrong first, before blaming a cycle
+            return Err(
+                match pending
+                    .iter()
+                    .find(|constant| !constant.value.symbols().into_iter().any(is_pending))
+                {
+                    Some(constant) => format!(
+                        "line {}: {}",
+                        constant.line,
+                        constant
+                            .value
+                            .evaluate(&symbols, constant.address)
+                            .unwrap_err()
+                    ),
+                    None => format!(
+                        "line {}: `{}` is defined in terms of itself",
+                        pending[0].line, pending[0].name
+                    ),
+                },
+            );
+        }
+    }
+
+    // Second pass: the bytes, every symbol being known
+    let mut segments: Vec<Segment> = Vec::new();
+    for statement in &statements {
+        let bytes: Vec<u8> = encode(statement, &symbols)
+            .map_err(|error| format!("line {}: {}", statement.line, error))?;
+        listing[statement.listed].bytes = bytes.clone();
+        match segments.last_mut() {
+            Some(segment)
+                if segment.start as usize + segment.bytes.len() == statement.address as usize =>
+            {
+                segment.bytes.extend_from_slice(&bytes)
+            }
+            _ => segments.push(Segment {
+                start: statement.address,
+                bytes,
+            }),
+        }
+    }
+    let symbols: BTreeMap<String, u16> = symbols
+        .into_iter()
+        .map(|(name, value)| (name, value as u16))
+        .collect();
+    Ok(Program {
+        segments,
+        symbols,
+        listing,
+    })
+}
+
+/// Assembles the single line `text` at `address`, e.g. typed in a monitor: an
+/// instruction or a `.byte`, `.asciiz` or `.word` directive, without labels. Values
+/// can use numbers and `*`, there being no symbols.
+///
+/// # Returns
+/// The bytes of the line.
+pub fn assemble_line(text: &str, address: u16) -> Result<Vec<u8>, String> {
+    let symbols: BTreeMap<String, i64> = BTreeMap::new();
+    match parse_statement(strip_comment(text).trim(), &symbols, address)? {
+        Line::Code(kind) => encode(
+            &Statement {
+                line: 1,
+                address,
+                kind,
+                listed: 0,
+            },
+            &symbols,
+        ),
+        _ => Err("Expected an instruction or data".to_string()),
+    }
+}
+
+/// Fails if `name` is already defined, or waiting to be.
+fn check_new(
+    symbols: &BTreeMap<String, i64>,
+    pending: &[Pending],
+    name: &str,
+) -> Result<(), String> {
+    if symbols.contains_key(name) || pending.iter().any(|constant| constant.name == name) {
+        return Err(format!("Symbol `{}` is defined twice", name));
+    }
+    Ok(())
+}
+
+/// Defines the symbol `name`, see `check_new()`.
+fn define(
+    symbols: &mut BTreeMap<String, i64>,
+    pending: &[Pending],
+    name: &str,
+    value: i64,
+) -> Result<(), String> {
+    check_new(symbols, pending, name)?;
+    symbols.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// Parses `text`, a line without its labels and comment, at `address`.
+fn parse_statement(
+    text: &str,
+    symbols: &BTreeMap<String, i64>,
+    address: u16,
+) -> Result<Line, String> {
+    if text.is_empty() {
+        return Ok(Line::Nothing);
+    }
+    if let Some((name, value)) = text.split_once('=') {
+        if is_symbol(name.trim()) {
+            return Ok(Line::Constant(name.trim().to_string(), Expr::parse(value)?));
+        }
+    }
+
+    let (word, operand): (&str, &str) = split_word(text);
+    let (directive, value): (&str, &str) = split_word(operand);
+    if is_symbol(word) && directive.eq_ignore_ascii_case(".equ") {
+        return Ok(Line::Constant(word.to_string(), Expr::parse(value)?));
+    }
+    match word.to_ascii_lowercase().as_str() {
+        ".org" => Ok(Line::Origin(Expr::parse(operand)?)),
+        ".byte" => Ok(Line::Code(Kind::Bytes(parse_items(operand)?))),
+        ".asciiz" => {
+            let mut items: Vec<Item> = parse_items(operand)?;
+            items.push(Item::Value(Expr::Number(0)));
+            Ok(Line::Code(Kind::Bytes(items)))
+        }
+        ".word" => {
+            let values: Result<Vec<Expr>, String> =
+                split_list(operand).into_iter().map(Expr::parse).collect();
+            Ok(Line::Code(Kind::Words(values?)))
+        }
+        directive if directive.starts_with('.') => Err(format!("Unknown directive `{}`", word)),
+        _ => parse_instruction(word, operand, symbols, address).map(Line::Code),
+    }
+}
+
+/// # Returns
+/// The elements of a `.byte` list.
+fn parse_items(text: &str) -> Result<Vec<Item>, String> {
+    split_list(text)
+        .into_iter()
+        .map(|item| {
+            match item
+                .strip_prefix('"')
+                .and_then(|text| text.strip_suffix('"'))
+                .filter(|text| !text.contains('"'))
+            {
+                Some(text) => Ok(Item::Text(text.as_bytes().to_vec())),
+                None => Expr::parse(item).map(Item::Value),
+            }
+        })
+        .collect()
+}
+
+/// Passes the labels at the start of `text` to `define`.
+///
+/// # Returns
+/// The rest of the line, without its comment.
+fn strip_labels(
+    text: &str,
+    mut define: impl FnMut(&str) -> Result<(), String>,
+) -> Result<&str, String> {
+    let mut rest: &str = strip_comment(text).trim();
+    while let Some((label, after)) = rest.split_once(':') {
+        let label: &str = label.trim();
+        if !is_symbol(label) {
+            break;
+        }
+        define(label)?;
+        rest = after.trim();
+    }
+    Ok(rest)
+}
+
+/// # Returns
+/// The characters of `text` outside of quotes, `"text"` or `'c'`, with their positions.
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote: Option<char> = None;
+    text.char_indices().filter(move |&(_, c)| match quote {
+        Some(open) => {
+            if c == open {
+                quote = None;
+            }
+            false
+        }
+        None if c == '"' || c == '\'' => {
+            quote = Some(c);
+            false
+        }
+        None => true,
+    })
+}
+
+/// # Returns
+/// `text` up to its comment, a `;` outside of quotes.
+fn strip_comment(text: &str) -> &str {
+    match unquoted(text).find(|&(_, c)| c == ';') {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+/// # Returns
+/// The first word of `text` and the rest, trimmed.
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// # Returns
+/// The elements of the comma-separated `text`, trimmed, commas in quotes included.
+fn split_list(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut items: Vec<&str> = Vec::new();
+    let mut start: usize = 0;
+    for (index, _) in unquoted(text).filter(|&(_, c)| c == ',') {
+        items.push(text[start..index].trim());
+        start = index + 1;
+    }
+    items.push(text[start..].trim());
+    items
+}
+
+/// # Returns
+/// `true` if `text` is in parentheses from its first character to its last, unlike
+/// e.g. `(1 + 2) * 3`.
+fn is_enclosed(text: &str) -> bool {
+    if !text.starts_with('(') {
+        return false;
+    }
+    let mut depth: usize = 0;
+    for (index, c) in unquoted(text) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return index == text.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// # Returns
+/// The opcode of `mnemonic` in `mode`, if the 6502 has it.
+fn op_code(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    (0..=0xff_u8).find(|&op_code| {
+        instruction_info(op_code)
+            .is_some_and(|info| info.mnemonic == mnemonic && info.addressing_mode == mode)
+    })
+}
+
+/// Works out the addressing mode of an instruction at `address` from its `operand`.
+fn parse_instruction(
+    word: &str,
+    operand: &str,
+    symbols: &BTreeMap<String, i64>,
+    address: u16,
+) -> Result<Kind, String> {
+    let mnemonic: String = word.to_ascii_uppercase();
+    if !(0..=0xff_u8)
+        .any(|op_code| instruction_info(op_code).is_some_and(|info| info.mnemonic == mnemonic))
+    {
+        return Err(format!("Unknown instruction `{}`", word));
+    }
+    let has = |mode: AddressingMode| op_code(&mnemonic, mode).is_some();
+
+    let blanks: Vec<usize> = unquoted(operand)
+        .filter(|&(_, c)| c.is_whitespace())
+        .map(|(index, _)| index)
+        .collect();
+    let text: String = operand
+        .char_indices()
+        .filter(|(index, _)| !blanks.contains(index))
+        .map(|(_, c)| c)
+        .collect();
+    // `a:` keeps the absolute form of an address that fits in a byte, like in ca65
+    let (forced, text): (bool, String) = match text.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("a:") => (true, text[2..].to_string()),
+        _ => (false, text),
+    };
+    let upper: String = text.to_ascii_uppercase();
+    // The zero-page form when the value is known to fit, or the only one there is
+    let zero_page_or = |zero_page: AddressingMode, absolute: AddressingMode, value: &Expr| {
+        let fits: bool = value.is_byte()
+            || value
+                .evaluate(symbols, address)
+                .is_ok_and(|value| (0x00..=0xff).contains(&value));
+        if has(zero_page) && !forced && (fits || !has(absolute)) {
+            zero_page
+        } else {
+            absolute
+        }
+    };
+    let inner = |prefix: usize, suffix: usize| Expr::parse(&text[prefix..text.len() - suffix]);
+    let (mode, operand): (AddressingMode, Option<Expr>) = if text.is_empty() {
+        if has(AddressingMode::Implied) {
+            (AddressingMode::Implied, None)
+        } else {
+            (AddressingMode::Accumulator, None)
+        }
+    } else if upper == "A" && has(AddressingMode::Accumulator) {
+        (AddressingMode::Accumulator, None)
+    } else if text.starts_with('#') {
+        (AddressingMode::Immediate, Some(inner(1, 0)?))
+    } else if upper.ends_with(",X)") && is_enclosed(&text) {
+        (AddressingMode::IndirectX, Some(inner(1, 3)?))
+    } else if upper.ends_with(",Y") && is_enclosed(&text[..text.len() - 2]) {
+        (AddressingMode::IndirectY, Some(inner(1, 3)?))
+    } else if is_enclosed(&text) {
+        (AddressingMode::Indirect, Some(inner(1, 1)?))
+    } else if upper.ends_with(",X") {
+        let value: Expr = inner(0, 2)?;
+        (
+            zero_page_or(AddressingMode::ZeroPageX, AddressingMode::AbsoluteX, &value),
+            Some(value),
+        )
+    } else if upper.ends_with(",Y") {
+        let value: Expr = inner(0, 2)?;
+        (
+            zero_page_or(AddressingMode::ZeroPageY, AddressingMode::AbsoluteY, &value),
+            Some(value),
+        )
+    } else if has(AddressingMode::Relative) {
+        (AddressingMode::Relative, Some(inner(0, 0)?))
+    } else {
+        let value: Expr = inner(0, 0)?;
+        (
+            zero_page_or(AddressingMode::ZeroPage, AddressingMode::Absolute, &value),
+            Some(value),
+        )
+    };
+
+    if !has(mode) {
+        return Err(format!("`{}` has no {:?} addressing mode", mnemonic, mode));
+    }
+    if forced
+        && !matches!(
+            mode,
+            AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY
+        )
+    {
+        return Err(format!("`a:` needs an absolute address, not {:?}", mode));
+    }
+    Ok(Kind::Instruction {
+        mnemonic,
+        mode,
+        operand,
+    })
+}
+
+/// # Returns
+/// The bytes of `statement`, with the symbols of the whole program.
+fn encode(statement: &Statement, symbols: &BTreeMap<String, i64>) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    match &statement.kind {
+        Kind::Instruction {
+            mnemonic,
+            mode,
+            operand,
+        } => {
+            // Checked by the first pass
+            bytes.push(op_code(mnemonic, *mode).unwrap());
+            let value: i64 = match operand {
+                Some(operand) => operand.evaluate(symbols, statement.address)?,
+                None => 0,
+            };
+            match mode {
+                AddressingMode::Relative => {
+                    // The program counter wraps around, so do branches
+                    let target: u16 = u16::from_le_bytes(word(value)?);
+                    let offset: i16 = target.wrapping_sub(statement.address.wrapping_add(2)) as i16;
+                    let offset: i8 = i8::try_from(offset)
+                        .map_err(|_| format!("Branch target {:#06x} is out of range", value))?;
+                    bytes.push(offset as u8);
+                }
+                _ if mode.operand_bytes() == 1 => bytes.push(byte(value)?),
+                _ if mode.operand_bytes() == 2 => bytes.extend_from_slice(&word(value)?),
+                _ => {}
+            }
+        }
+        Kind::Bytes(items) => {
+            for item in items {
+                match item {
+                    Item::Value(value) => {
+                        bytes.push(byte(value.evaluate(symbols, statement.address)?)?)
+                    }
+                    Item::Text(text) => bytes.extend_from_slice(text),
+                }
+            }
+        }
+        Kind::Words(values) => {
+            for value in values {
+                bytes.extend_from_slice(&word(value.evaluate(symbols, statement.address)?)?);
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// # Returns
+/// `bytes` in hexadecimal, separated by spaces.
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// # Returns
+/// `value` as a byte, negative values in two's complement.
+fn byte(value: i64) -> Result<u8, String> {
+    match value {
+        -0x80..=0xff => Ok(value as u8),
+        _ => Err(format!("Value {} does not fit in a byte", value)),
+    }
+}
+
+/// # Returns
+/// `value` as a little-endian word, negative values in two's complement.
+fn word(value: i64) -> Result<[u8; 2], String> {
+    match value {
+        -0x8000..=0xffff => Ok((value as u16).to_le_bytes()),
+        _ => Err(format!("Value {} does not fit in a word", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_program_with_labels() {
+        let program: Program = assemble(
+            "
+            .org $0200
+            start:  ldx #$03        ; count down
+            loop:   dex
+                    bne loop
+                    lda $10,X
+                    sta table,Y
+                    jmp (vector)
+                    asl
+            table:  .byte 1, \"ab\", $ff
+            vector: .word start
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.symbol("loop"), Some(0x0202));
+        assert_eq!(
+            program.image(),
+            [
+                0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xb5, 0x10, 0x99, 0x0e, 0x02, 0x6c, 0x12, 0x02, 0x0a,
+                0x01, b'a', b'b', 0xff, 0x00, 0x02,
+            ]
+        );
+        assert_eq!(program.to_prg()[..3], [0x00, 0x02, 0xa2]);
+    }
+
+    #[test]
+    fn places_segments_and_reports_errors() {
+        let program: Program = assemble(".org $10\nnop\n.org $20\nrts").unwrap();
+        assert_eq!(program.segments().len(), 2);
+        assert_eq!(program.image().len(), 0x11);
+        let mut ram: Vec<u8> = vec![0x00; 0x10000];
+        program.load_into(&mut ram);
+        assert_eq!((ram[0x10], ram[0x20]), (0xea, 0x60));
+        assert_eq!(
+            program.to_ihex(),
+            ":01001000EA05\n:01002000607F\n:00000001FF\n"
+        );
+
+        assert_eq!(
+            assemble("nop\nfoo #1").unwrap_err(),
+            "line 2: Unknown instruction `foo`"
+        );
+        assert_eq!(
+            assemble("jmp nowhere").unwrap_err(),
+            "line 1: Undefined symbol `nowhere`"
+        );
+        assert!(assemble("ldx $1234,X").is_err());
+        assert!(assemble("here: bne $0200").is_err());
+        assert!(assemble("a: nop\na: nop").is_err());
+
+        assert_eq!(assemble_line("lda ($10),y", 0x1000), Ok(vec![0xb1, 0x10]));
+        assert_eq!(assemble_line("bne $0ff0", 0x1000), Ok(vec![0xd0, 0xee]));
+        assert_eq!(assemble_line("bne $fff0", 0x0000), Ok(vec![0xd0, 0xee]));
+        assert!(assemble_line("start: nop", 0x1000).is_err());
+    }
+
+    #[test]
+    fn resolves_constants_and_forward_references() {
+        let program: Program = assemble(
+            "
+            PORTB = $6000
+            ptr .equ $10
+                    .org $8000
+            reset:  lda #<message
+                    sta ptr
+                    lda #>message
+                    sta ptr + 1
+                    lda PORTB
+                    lda >table,X
+                    jmp end
+            table:  .byte <(end - reset), ';', \"hi\"
+            message: .asciiz \"ok\"
+            end = * + LENGTH
+            LENGTH = 2 * 2
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.symbol("end"), Some(0x801b));
+        assert_eq!(
+            program.image(),
+            [
+                0xa9, 0x14, 0x85, 0x10, 0xa9, 0x80, 0x85, 0x11, 0xad, 0x00, 0x60, 0xb5, 0x80, 0x4c,
+                0x1b, 0x80, 0x1b, b';', b'h', b'i', b'o', b'k', 0x00,
+            ]
+        );
+
+        assert_eq!(
+            assemble("a = b\nb = a + 1").unwrap_err(),
+            "line 1: `a` is defined in terms of itself"
+        );
+        assert_eq!(
+            assemble("a = b\nb = c").unwrap_err(),
+            "line 2: Undefined symbol `c`"
+        );
+        assert!(assemble("x = 1\nx: nop").is_err());
+    }
+
+    #[test]
+    fn expands_macros_with_local_labels() {
+        let program: Program = assemble(
+            "
+            .macro delay count
+                    ldx #count
+            @loop:  dex             ; local to each expansion
+                    bne @loop
+            .endmacro
+            .macro inc16 addr
+                    inc addr
+                    bne @done
+                    inc addr+1
+            @done:
+            .endmacro
+                    .org $0200
+            start:  delay 3
+                    delay $10
+                    inc16 $fe
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(program.symbol("start"), Some(0x0200));
+        assert_eq!(program.symbol("@loop@2"), Some(0x0207));
+        assert_eq!(
+            program.image(),
+            [
+                0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xa2, 0x10, 0xca, 0xd0, 0xfd, 0xe6, 0xfe, 0xd0, 0x02,
+                0xe6, 0xff,
+            ]
+        );
+
+        let source: &str = ".macro twice a, b\n.byte a, b\n.endm\ntwice 1\n";
+        assert_eq!(
+            assemble(source).unwrap_err(),
+            "line 4: Macro `twice` takes 2 arguments, not 1"
+        );
+        assert!(assemble(".macro forever\nforever\n.endmacro\nforever").is_err());
+        assert!(assemble(".macro open\nnop").is_err());
+        assert!(assemble(".endmacro").is_err());
+    }
+
+    #[test]
+    fn lists_lines_and_symbols() {
+        let program: Program = assemble(
+            ".macro pause n\n    ldx #n\n@l: dex\n    bne @l\n.endmacro\n\
+             .org $c000\nstart: pause 2 ; wait\nmsg: .byte \"hello\", 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.listing().lines().collect::<Vec<&str>>(),
+            [
+                "                .macro pause n",
+                "                    ldx #n",
+                "                @l: dex",
+                "                    bne @l",
+                "                .endmacro",
+                "C000            .org $c000",
+                "C000            start: pause 2 ; wait",
+                "C000  A2 02     +    ldx #2",
+                "C002  CA        +@l@1: dex",
+                "C003  D0 FD     +    bne @l@1",
+                "C005  68 65 6C  msg: .byte \"hello\", 0",
+                "C008  6C 6F 00",
+            ]
+        );
+        assert_eq!(program.vice_labels(), "al C:c005 .msg\nal C:c000 .start\n");
+    }
+}
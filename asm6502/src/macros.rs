@@ -0,0 +1,255 @@
+use crate::expr::is_symbol;
+use crate::{split_list, split_word, strip_comment, strip_labels};
+
+use mos6502::instruction_info;
+
+use std::collections::BTreeMap;
+
+/// How deep macros can call each other, which stops a macro calling itself forever.
+const MAX_DEPTH: usize = 16;
+
+/// What a line of the expanded source is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Role {
+    /// Assembled as is.
+    Code,
+    /// A line from `.macro` to `.endmacro`, which assembles to nothing.
+    Definition,
+    /// A macro call, followed by its expansion. Only its labels are defined.
+    Call,
+}
+
+/// A line of the source with its macros expanded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SourceLine {
+    /// The number of the line in the source, that of the outermost call in expansions.
+    pub line: usize,
+    pub text: String,
+    /// How many macro calls deep the line is, `0` in the source itself.
+    pub depth: usize,
+    pub role: Role,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// Expands the macros of `source`:
+///
+/// ```text
+/// .macro name param, ...
+///     ...
+/// .endmacro
+/// ```
+///
+/// defines `name`, which lines further down call with `name argument, ...`. The
+/// parameters in the body are replaced with the text of the arguments, and labels
+/// starting with `@` are local to each expansion. `.endm` also ends a macro.
+///
+/// # Returns
+/// The lines of the source and of the expansions, or the first error, prefixed with
+/// its line number.
+pub(crate) fn expand(source: &str) -> Result<Vec<SourceLine>, String> {
+    let mut expander: Expander = Expander {
+        macros: BTreeMap::new(),
+        lines: Vec::new(),
+        calls: 0,
+    };
+    // The macro being defined, with its name and the line it starts at
+    let mut definition: Option<(usize, String, Macro)> = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let line: usize = index + 1;
+        let at = |error: String| format!("line {}: {}", line, error);
+        let (word, operand): (&str, &str) = split_word(strip_comment(text).trim());
+        let word: String = word.to_ascii_lowercase();
+        let role: Role = match (&mut definition, word.as_str()) {
+            (Some(_), ".macro") => {
+                return Err(at("Macros cannot be defined inside macros".to_string()))
+            }
+            (Some(_), ".endmacro" | ".endm") => {
+                // Checked when the definition started
+                let (_, name, body): (usize, String, Macro) = definition.take().unwrap();
+                expander.macros.insert(name, body);
+                Role::Definition
+            }
+            (Some((_, _, body)), _) => {
+                body.body.push(text.to_string());
+                Role::Definition
+            }
+            (None, ".macro") => {
+                let (name, params): (String, Macro) = parse_definition(operand).map_err(at)?;
+                if expander.macros.contains_key(&name) {
+                    return Err(at(format!("Macro `{}` is defined twice", name)));
+                }
+                definition = Some((line, name, params));
+                Role::Definition
+            }
+            (None, ".endmacro" | ".endm") => {
+                return Err(at(format!("`{}` without `.macro`", word)))
+            }
+            (None, _) => {
+                expander.emit(line, text.to_string(), 0).map_err(at)?;
+                continue;
+            }
+        };
+        expander.lines.push(SourceLine {
+            line,
+            text: text.to_string(),
+            depth: 0,
+            role,
+        });
+    }
+
+    match definition {
+        Some((line, name, _)) => Err(format!(
+            "line {}: Macro `{}` has no `.endmacro`",
+            line, name
+        )),
+        None => Ok(expander.lines),
+    }
+}
+
+/// # Returns
+/// The name and the parameters of `.macro name param, ...`, with an empty body.
+fn parse_definition(text: &str) -> Result<(String, Macro), String> {
+    let (name, params): (&str, &str) = split_word(text);
+    if !is_symbol(name) {
+        return Err(format!("Invalid macro name `{}`", name));
+    }
+    if (0..=0xff_u8).any(|op_code| {
+        instruction_info(op_code).is_some_and(|info| info.mnemonic.eq_ignore_ascii_case(name))
+    }) {
+        return Err(format!("`{}` is an instruction", name));
+    }
+    let params: Vec<String> = split_list(params).into_iter().map(str::to_string).collect();
+    for (index, param) in params.iter().enumerate() {
+        if !is_symbol(param) || param.starts_with('@') {
+            return Err(format!("Invalid parameter `{}`", param));
+        }
+        if params[..index].contains(param) {
+            return Err(format!("Parameter `{}` is given twice", param));
+        }
+    }
+    Ok((
+        name.to_string(),
+        Macro {
+            params,
+            body: Vec::new(),
+        },
+    ))
+}
+
+struct Expander {
+    macros: BTreeMap<String, Macro>,
+    lines: Vec<SourceLine>,
+    /// The expansions so far, which tell their local labels apart.
+    calls: usize,
+}
+
+impl Expander {
+    /// Adds the line `text`, expanding it if it calls a macro.
+    fn emit(&mut self, line: usize, text: String, depth: usize) -> Result<(), String> {
+        let rest: &str = strip_labels(&text, |_| Ok(()))?;
+        let (name, operand): (&str, &str) = split_word(rest);
+        let Some(called) = self.macros.get(name) else {
+            self.lines.push(SourceLine {
+                line,
+                text,
+                depth,
+                role: Role::Code,
+            });
+            return Ok(());
+        };
+
+        if depth == MAX_DEPTH {
+            return Err(format!("Macros nested more than {} deep", MAX_DEPTH));
+        }
+        let args: Vec<&str> = split_list(operand);
+        if args.len() != called.params.len() {
+            return Err(format!(
+                "Macro `{}` takes {} arguments, not {}",
+                name,
+                called.params.len(),
+                args.len()
+            ));
+        }
+        self.calls += 1;
+        let suffix: String = format!("@{}", self.calls);
+        let body: Vec<String> = called
+            .body
+            .iter()
+            .map(|body_line| {
+                substitute(body_line, |word| {
+                    if word.starts_with('@') {
+                        return Some(format!("{}{}", word, suffix));
+                    }
+                    called
+                        .params
+                        .iter()
+                        .position(|param| param == word)
+                        .map(|index| args[index].to_string())
+                })
+            })
+            .collect();
+
+        self.lines.push(SourceLine {
+            line,
+            text,
+            depth,
+            role: Role::Call,
+        });
+        for body_line in body {
+            self.emit(line, body_line, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// # Returns
+/// `text` with the words `replace` gives a replacement for replaced, leaving quotes,
+/// comments, numbers and directives alone.
+fn substitute(text: &str, replace: impl Fn(&str) -> Option<String>) -> String {
+    let code: &str = strip_comment(text);
+    let chars: Vec<char> = code.chars().collect();
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '@';
+    let mut result: String = String::new();
+    let mut quote: Option<char> = None;
+    let mut index: usize = 0;
+    while index < chars.len() {
+        let c: char = chars[index];
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            // The start of a word, not the digits of `$ff`, `10` or the name of `.byte`
+            None if is_word(c) && !c.is_ascii_digit() => {
+                let start: usize = index;
+                while index < chars.len() && is_word(chars[index]) {
+                    index += 1;
+                }
+                let word: String = chars[start..index].iter().collect();
+                let prefixed: bool = start > 0 && matches!(chars[start - 1], '$' | '.');
+                match replace(&word) {
+                    Some(replacement) if !prefixed => result.push_str(&replacement),
+                    _ => result.push_str(&word),
+                }
+                continue;
+            }
+            None if is_word(c) => {
+                // A number, and whatever letters it has
+                while index < chars.len() && is_word(chars[index]) {
+                    result.push(chars[index]);
+                    index += 1;
+                }
+                continue;
+            }
+            None => {}
+        }
+        result.push(c);
+        index += 1;
+    }
+    result.push_str(&text[code.len()..]);
+    result
+}
@@ -0,0 +1,165 @@
+use memory::device::{Device, IrqState};
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// Register offsets, the chip decodes the low 2 address bits.
+pub const DATA: u16 = 0x0;
+pub const STATUS: u16 = 0x1;
+pub const COMMAND: u16 = 0x2;
+pub const CONTROL: u16 = 0x3;
+
+// Status register bits
+pub const STATUS_OVERRUN: u8 = 0b0000_0100;
+pub const STATUS_RX_FULL: u8 = 0b0000_1000;
+pub const STATUS_TX_EMPTY: u8 = 0b0001_0000;
+pub const STATUS_IRQ: u8 = 0b1000_0000;
+
+// Command register bits
+/// Data terminal ready, the chip is disabled while clear.
+const COMMAND_DTR: u8 = 0b0000_0001;
+/// Disables the receive interrupt while set.
+const COMMAND_RX_IRQ_DISABLE: u8 = 0b0000_0010;
+/// Transmitter control, `0b01` enables the transmit interrupt.
+const COMMAND_TX_CONTROL: u8 = 0b0000_1100;
+const COMMAND_TX_IRQ: u8 = 0b0000_0100;
+
+/// A MOS 6551 Asynchronous Communications Interface Adapter, bridged to the host.
+///
+/// Transmitted bytes go straight to a host writer and received bytes come from the
+/// `Sender` returned by `new()`, so any host stream can be the other end, e.g. stdio or
+/// a TCP socket. There is no baud rate timing: transmitting is instant and a received
+/// byte is taken as soon as the previous one was read.
+pub struct Acia<W: Write> {
+    output: W,
+    received: Receiver<u8>,
+    queue: VecDeque<u8>,
+
+    rx_data: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+}
+
+impl<W: Write> Acia<W> {
+    /// # Returns
+    /// The ACIA transmitting to `output` and the sender feeding its receiver, which can
+    /// be moved to another thread, e.g. one reading a socket.
+    pub fn new(output: W) -> (Self, Sender<u8>) {
+        let (sender, received) = mpsc::channel::<u8>();
+        let acia: Acia<W> = Acia {
+            output,
+            received,
+            queue: VecDeque::new(),
+            rx_data: 0x00,
+            status: STATUS_TX_EMPTY,
+            command: 0x00,
+            control: 0x00,
+        };
+        (acia, sender)
+    }
+
+    /// # Returns
+    /// Where the transmitted bytes go.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Programmed reset, as done by writing the status register.
+    fn reset(&mut self) {
+        self.status &= !STATUS_OVERRUN;
+        self.command &= !(COMMAND_DTR | COMMAND_RX_IRQ_DISABLE | COMMAND_TX_CONTROL);
+    }
+
+    fn rx_irq_enabled(&self) -> bool {
+        self.command & COMMAND_DTR != 0 && self.command & COMMAND_RX_IRQ_DISABLE == 0
+    }
+
+    fn tx_irq_enabled(&self) -> bool {
+        self.command & COMMAND_DTR != 0 && self.command & COMMAND_TX_CONTROL == COMMAND_TX_IRQ
+    }
+}
+
+impl<W: Write> Device for Acia<W> {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x03 {
+            DATA => {
+                self.status &= !(STATUS_RX_FULL | STATUS_OVERRUN);
+                self.rx_data
+            }
+            STATUS => {
+                // Reading the status acknowledges the interrupt
+                let value: u8 = self.status;
+                self.status &= !STATUS_IRQ;
+                value
+            }
+            COMMAND => self.command,
+            _ => self.control,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x03 {
+            DATA => {
+                // The emulated program cannot react to host errors, so they are dropped
+                let _ = self.output.write_all(&[value]);
+                let _ = self.output.flush();
+                if self.tx_irq_enabled() {
+                    self.status |= STATUS_IRQ;
+                }
+            }
+            STATUS => self.reset(),
+            COMMAND => self.command = value,
+            _ => self.control = value,
+        }
+    }
+
+    fn tick(&mut self, _cycles: u32) -> IrqState {
+        self.queue.extend(self.received.try_iter());
+        if self.status & STATUS_RX_FULL == 0 {
+            if let Some(byte) = self.queue.pop_front() {
+                self.rx_data = byte;
+                self.status |= STATUS_RX_FULL;
+                if self.rx_irq_enabled() {
+                    self.status |= STATUS_IRQ;
+                }
+            }
+        }
+
+        if self.status & STATUS_IRQ != 0 {
+            IrqState::IRQ
+        } else {
+            IrqState::NONE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receive_interrupt_and_transmit() {
+        let (mut acia, sender) = Acia::new(Vec::new());
+        acia.write(COMMAND, COMMAND_DTR);
+
+        assert_eq!(acia.tick(1), IrqState::NONE);
+        sender.send(b'A').unwrap();
+        sender.send(b'B').unwrap();
+        assert_eq!(acia.tick(1), IrqState::IRQ);
+
+        assert_eq!(
+            acia.read(STATUS),
+            STATUS_IRQ | STATUS_TX_EMPTY | STATUS_RX_FULL
+        );
+        assert_eq!(acia.read(DATA), b'A');
+        assert_eq!(acia.tick(1), IrqState::IRQ);
+        acia.read(STATUS);
+        assert_eq!(acia.read(DATA), b'B');
+        assert_eq!(acia.tick(1), IrqState::NONE);
+
+        acia.write(DATA, b'C');
+        assert_eq!(acia.output(), b"C");
+    }
+}
@@ -0,0 +1,68 @@
+use memory::device::Device;
+
+use std::io::{self, Write};
+
+/// Address the `bare` machine maps the character output at, like py65 and other
+/// simulators do.
+pub const DEFAULT_ADDRESS: u16 = 0xf001;
+
+/// A one-register output port: each byte written to it is sent as is to the host,
+/// e.g. an ASCII character to stdout. Reads return `0`.
+pub struct CharOutput<W: Write> {
+    output: W,
+}
+
+impl CharOutput<io::Stdout> {
+    /// Creates a port printing to stdout.
+    pub fn stdout() -> Self {
+        CharOutput::new(io::stdout())
+    }
+}
+
+impl<W: Write> CharOutput<W> {
+    pub fn new(output: W) -> Self {
+        CharOutput { output }
+    }
+
+    /// # Returns
+    /// Where the written bytes go.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+}
+
+impl<W: Write> Device for CharOutput<W> {
+    fn read(&mut self, _offset: u16) -> u8 {
+        0x00
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        // Flush every byte since prompts have no newline. The program cannot react to
+        // host errors, e.g. a closed stdout, so they are dropped.
+        let _ = self.output.write_all(&[value]);
+        let _ = self.output.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn writes_reach_output() {
+        let output: Rc<RefCell<CharOutput<Vec<u8>>>> =
+            Rc::new(RefCell::new(CharOutput::new(Vec::new())));
+        let mut mem: Memory = Memory::new();
+        mem.map_device(DEFAULT_ADDRESS, DEFAULT_ADDRESS, output.clone());
+
+        for byte in b"Hi\n" {
+            mem.write(DEFAULT_ADDRESS, *byte);
+        }
+
+        assert_eq!(output.borrow().output(), b"Hi\n");
+        assert_eq!(mem.read(DEFAULT_ADDRESS), 0x00);
+    }
+}
@@ -0,0 +1,291 @@
+//! Translation between the character sets of 8-bit machines and the host terminal.
+
+use std::io::{self, Write};
+
+/// The character set a program prints and reads its text in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// Bytes go to and come from the host unchanged, fine for ASCII programs.
+    Raw,
+    /// Commodore PETSCII, in the uppercase/graphics set the machines start in.
+    Petscii,
+    /// Atari ATASCII.
+    Atascii,
+}
+
+/// ANSI colors closest to the 16 Commodore colors, in palette order.
+const PETSCII_ANSI_COLORS: [u8; 16] = [
+    30, 97, 31, 96, 35, 32, 34, 93, 33, 33, 91, 90, 37, 92, 94, 37,
+];
+
+/// ATASCII graphics characters `0x00` to `0x1a`.
+const ATASCII_GRAPHICS: [char; 27] = [
+    '♥', '├', '▕', '┘', '┤', '┐', '╱', '╲', '◢', '▗', '◣', '▝', '▘', '▔', '▁', '▖', '♣', '┌', '─',
+    '┼', '●', '▄', '▎', '┬', '┴', '▌', '└',
+];
+
+impl Charset {
+    pub const ALL: [Charset; 3] = [Charset::Raw, Charset::Petscii, Charset::Atascii];
+
+    /// # Returns
+    /// The name of the charset on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Raw => "raw",
+            Charset::Petscii => "petscii",
+            Charset::Atascii => "atascii",
+        }
+    }
+
+    /// # Returns
+    /// The charset called `name`, see `name()`.
+    pub fn parse(name: &str) -> Result<Charset, String> {
+        Charset::ALL
+            .into_iter()
+            .find(|charset| charset.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Charset::ALL.iter().map(Charset::name).collect();
+                format!(
+                    "Unknown charset `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// Appends to `host` the UTF-8 text, or ANSI escape sequence for control codes,
+    /// that shows `byte` on a terminal. Control codes without an equivalent add nothing.
+    pub fn decode(&self, byte: u8, host: &mut Vec<u8>) {
+        match self {
+            Charset::Raw => host.push(byte),
+            Charset::Petscii => decode_petscii(byte, host),
+            Charset::Atascii => decode_atascii(byte, host),
+        }
+    }
+
+    /// # Returns
+    /// The byte a program expects for the key typing `c` on the host, `None` if the
+    /// charset has no such character. Both `\r` and `\n` are the Return key.
+    pub fn encode(&self, c: char) -> Option<u8> {
+        match self {
+            Charset::Raw => u8::try_from(c).ok(),
+            Charset::Petscii => match c {
+                '\r' | '\n' => Some(0x0d),
+                '\x08' | '\x7f' => Some(0x14),
+                // Letters are unshifted in the uppercase set, whatever their host case
+                'a'..='z' => Some(c.to_ascii_uppercase() as u8),
+                ' '..='_' => Some(c as u8),
+                '£' => Some(0x5c),
+                '↑' => Some(0x5e),
+                '←' => Some(0x5f),
+                'π' => Some(0xff),
+                _ => None,
+            },
+            Charset::Atascii => match c {
+                '\r' | '\n' => Some(0x9b),
+                '\x08' | '\x7f' => Some(0x7e),
+                '\t' => Some(0x7f),
+                ' '..='_' | 'a'..='z' | '|' => Some(c as u8),
+                '♦' => Some(0x60),
+                '♠' => Some(0x7b),
+                _ => None,
+            },
+        }
+    }
+}
+
+fn decode_petscii(byte: u8, host: &mut Vec<u8>) {
+    let escape: &str = match byte {
+        0x0d | 0x8d => "\r\n",
+        0x11 => "\x1b[B",
+        0x91 => "\x1b[A",
+        0x1d => "\x1b[C",
+        0x9d => "\x1b[D",
+        0x12 => "\x1b[7m",
+        0x92 => "\x1b[27m",
+        0x13 => "\x1b[H",
+        0x93 => "\x1b[2J\x1b[H",
+        0x14 => "\x08 \x08",
+        0x07 => "\x07",
+        _ => "",
+    };
+    if !escape.is_empty() {
+        host.extend_from_slice(escape.as_bytes());
+        return;
+    }
+
+    let color: Option<usize> = match byte {
+        0x90 => Some(0),
+        0x05 => Some(1),
+        0x1c => Some(2),
+        0x9f => Some(3),
+        0x9c => Some(4),
+        0x1e => Some(5),
+        0x1f => Some(6),
+        0x9e => Some(7),
+        0x81 => Some(8),
+        0x95 => Some(9),
+        0x96 => Some(10),
+        0x97 => Some(11),
+        0x98 => Some(12),
+        0x99 => Some(13),
+        0x9a => Some(14),
+        0x9b => Some(15),
+        _ => None,
+    };
+    if let Some(color) = color {
+        host.extend_from_slice(format!("\x1b[{}m", PETSCII_ANSI_COLORS[color]).as_bytes());
+        return;
+    }
+
+    let code: u8 = match byte {
+        0x00..=0x1f | 0x80..=0x9f => return,
+        0x20..=0x3f => byte,
+        0x40..=0x5f | 0xa0..=0xbf => byte - 0x40,
+        0x60..=0x7f => byte - 0x20,
+        0xc0..=0xfe => byte - 0x80,
+        0xff => 0x5e,
+    };
+    let mut utf8: [u8; 4] = [0; 4];
+    host.extend_from_slice(screen_code_char(code).encode_utf8(&mut utf8).as_bytes());
+}
+
+fn decode_atascii(byte: u8, host: &mut Vec<u8>) {
+    let escape: &str = match byte {
+        0x9b => "\r\n",
+        0x1c => "\x1b[A",
+        0x1d => "\x1b[B",
+        0x1e => "\x1b[D",
+        0x1f => "\x1b[C",
+        0x7d => "\x1b[2J\x1b[H",
+        0x7e => "\x08 \x08",
+        0x7f => "\t",
+        0xfd => "\x07",
+        0x1b | 0x9c..=0x9f | 0xfe | 0xff => return,
+        _ => "",
+    };
+    if !escape.is_empty() {
+        host.extend_from_slice(escape.as_bytes());
+        return;
+    }
+
+    let c: char = match byte & 0x7f {
+        code @ 0x00..=0x1a => ATASCII_GRAPHICS[code as usize],
+        0x60 => '♦',
+        0x7b => '♠',
+        code => code as char,
+    };
+    let mut utf8: [u8; 4] = [0; 4];
+    // The high bit selects inverse video
+    if byte & 0x80 != 0 {
+        host.extend_from_slice(b"\x1b[7m");
+    }
+    host.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+    if byte & 0x80 != 0 {
+        host.extend_from_slice(b"\x1b[27m");
+    }
+}
+
+/// # Returns
+/// The character closest to the Commodore screen code `code`, in the
+/// uppercase/graphics set. Bit 7, reverse video, is ignored.
+pub fn screen_code_char(code: u8) -> char {
+    match code & 0x7f {
+        0x00 => '@',
+        code @ 0x01..=0x1a => (b'A' + code - 1) as char,
+        0x1b => '[',
+        0x1c => '£',
+        0x1d => ']',
+        0x1e => '↑',
+        0x1f => '←',
+        code @ 0x20..=0x3f => code as char,
+        0x40 => '─',
+        0x5b => '┼',
+        0x5d => '│',
+        0x5e => 'π',
+        0x60 => ' ',
+        0x61 => '▌',
+        0x62 => '▄',
+        0x63 => '▔',
+        0x64 => '▁',
+        0x65 => '▏',
+        0x66 => '▒',
+        0x67 => '▕',
+        0x6b => '├',
+        0x6d => '└',
+        0x6e => '┐',
+        0x70 => '┌',
+        0x71 => '┴',
+        0x72 => '┬',
+        0x73 => '┤',
+        0x7d => '┘',
+        0x7e => '▘',
+        0x7f => '▚',
+        _ => '▒',
+    }
+}
+
+/// A host writer the text of a program goes through, translated from its charset.
+pub struct Translated<W: Write> {
+    output: W,
+    charset: Charset,
+}
+
+impl<W: Write> Translated<W> {
+    pub fn new(output: W, charset: Charset) -> Self {
+        Translated { output, charset }
+    }
+
+    /// # Returns
+    /// Where the translated text goes.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+}
+
+impl<W: Write> Write for Translated<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut host: Vec<u8> = Vec::with_capacity(buf.len());
+        for byte in buf {
+            self.charset.decode(*byte, &mut host);
+        }
+        self.output.write_all(&host)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn petscii_round_trips_through_the_host() {
+        let mut translated: Translated<Vec<u8>> = Translated::new(Vec::new(), Charset::Petscii);
+        // Clear, "HI", a shifted space, a horizontal line and Return
+        translated
+            .write_all(&[0x93, 0x48, 0x49, 0xa0, 0xc0, 0x0d])
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(translated.output().clone()).unwrap(),
+            "\x1b[2J\x1b[HHI ─\r\n"
+        );
+        let typed: Vec<Option<u8>> = "hi\n".chars().map(|c| Charset::Petscii.encode(c)).collect();
+        assert_eq!(typed, [Some(0x48), Some(0x49), Some(0x0d)]);
+    }
+
+    #[test]
+    fn atascii_inverse_and_end_of_line() {
+        let mut host: Vec<u8> = Vec::new();
+        for byte in [0x00, 0xc1, 0x9b] {
+            Charset::Atascii.decode(byte, &mut host);
+        }
+
+        assert_eq!(String::from_utf8(host).unwrap(), "♥\x1b[7mA\x1b[27m\r\n");
+        assert_eq!(Charset::Atascii.encode('\r'), Some(0x9b));
+    }
+}
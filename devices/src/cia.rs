@@ -0,0 +1,436 @@
+use memory::device::{Device, IrqState};
+
+// Register offsets, the chip decodes the low 4 address bits.
+pub const PRA: u16 = 0x0;
+pub const PRB: u16 = 0x1;
+pub const DDRA: u16 = 0x2;
+pub const DDRB: u16 = 0x3;
+pub const TA_LO: u16 = 0x4;
+pub const TA_HI: u16 = 0x5;
+pub const TB_LO: u16 = 0x6;
+pub const TB_HI: u16 = 0x7;
+pub const TOD_10THS: u16 = 0x8;
+pub const TOD_SEC: u16 = 0x9;
+pub const TOD_MIN: u16 = 0xa;
+pub const TOD_HR: u16 = 0xb;
+pub const SDR: u16 = 0xc;
+pub const ICR: u16 = 0xd;
+pub const CRA: u16 = 0xe;
+pub const CRB: u16 = 0xf;
+
+// Interrupt control register bits
+pub const INT_TA: u8 = 0b0000_0001;
+pub const INT_TB: u8 = 0b0000_0010;
+pub const INT_ALARM: u8 = 0b0000_0100;
+pub const INT_SDR: u8 = 0b0000_1000;
+pub const INT_FLAG: u8 = 0b0001_0000;
+/// Set in ICR reads while any enabled interrupt is flagged. In ICR writes, set to enable.
+pub const INT_ANY: u8 = 0b1000_0000;
+
+// Control register bits
+const CR_START: u8 = 0b0000_0001;
+const CR_ONE_SHOT: u8 = 0b0000_1000;
+const CR_LOAD: u8 = 0b0001_0000;
+/// In CRB: timer B counts timer A underflows instead of cycles.
+const CRB_COUNT_TA: u8 = 0b0100_0000;
+/// In CRB: TOD writes set the alarm instead of the clock.
+const CRB_ALARM: u8 = 0b1000_0000;
+
+/// The CPU line a CIA drives. On the C64, CIA1 raises IRQ and CIA2 raises NMI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptLine {
+    Irq,
+    Nmi,
+}
+
+/// One interval timer of the CIA.
+struct Timer {
+    counter: u16,
+    latch: u16,
+    control: u8,
+}
+
+impl Timer {
+    fn new() -> Self {
+        Timer {
+            counter: 0xffff,
+            latch: 0xffff,
+            control: 0x00,
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        if value & CR_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        // The load bit is a strobe and always reads back as clear
+        self.control = value & !CR_LOAD;
+    }
+
+    fn write_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x00ff) | (value as u16) << 8;
+        if self.control & CR_START == 0 {
+            self.counter = self.latch;
+        }
+    }
+
+    /// Counts `pulses` down, reloading from the latch after every underflow, which
+    /// happens one pulse after reaching zero.
+    ///
+    /// # Returns
+    /// The number of underflows.
+    fn count(&mut self, pulses: u32) -> u32 {
+        let mut remaining: u32 = pulses;
+        let mut underflows: u32 = 0;
+        while self.control & CR_START != 0 && remaining > self.counter as u32 {
+            remaining -= self.counter as u32 + 1;
+            underflows += 1;
+            self.counter = self.latch;
+            if self.control & CR_ONE_SHOT != 0 {
+                self.control &= !CR_START;
+                return underflows;
+            }
+        }
+        if self.control & CR_START != 0 {
+            self.counter -= remaining as u16;
+        }
+        underflows
+    }
+}
+
+/// Time of day in BCD, as the TOD registers hold it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Tod {
+    tenths: u8,
+    seconds: u8,
+    minutes: u8,
+    /// `1`-`12` in BCD, with bit 7 set for PM.
+    hours: u8,
+}
+
+impl Tod {
+    fn bcd_increment(value: u8) -> u8 {
+        if value & 0x0f == 9 {
+            (value & 0xf0) + 0x10
+        } else {
+            value + 1
+        }
+    }
+
+    /// Advances the clock by a tenth of a second.
+    fn advance(&mut self) {
+        self.tenths += 1;
+        if self.tenths < 10 {
+            return;
+        }
+        self.tenths = 0;
+        self.seconds = Self::bcd_increment(self.seconds);
+        if self.seconds < 0x60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = Self::bcd_increment(self.minutes);
+        if self.minutes < 0x60 {
+            return;
+        }
+        self.minutes = 0;
+
+        let pm: u8 = self.hours & 0x80;
+        let hours: u8 = self.hours & 0x1f;
+        self.hours = match hours {
+            0x11 => 0x12 | (pm ^ 0x80),
+            0x12 => 0x01 | pm,
+            _ => Self::bcd_increment(hours) | pm,
+        };
+    }
+}
+
+/// A MOS 6526 Complex Interface Adapter.
+///
+/// Emulated: both ports with their data direction registers, timers A and B in
+/// one-shot and continuous mode, timer B counting timer A underflows, the time of day
+/// clock with its alarm and the FLAG input.
+/// Not emulated: counting CNT pulses, PB6/PB7 timer outputs and serial shifting, SDR
+/// only stores its value.
+pub struct Cia {
+    line: InterruptLine,
+
+    pra: u8,
+    prb: u8,
+    ddra: u8,
+    ddrb: u8,
+    /// Levels driven onto the port pins from outside, high where nothing pulls them down.
+    pins_a: u8,
+    pins_b: u8,
+
+    timer_a: Timer,
+    timer_b: Timer,
+
+    tod: Tod,
+    alarm: Tod,
+    /// Copy of the clock returned by reads between reading the hours and the tenths.
+    tod_latch: Option<Tod>,
+    /// The clock is stopped between writing the hours and the tenths.
+    tod_stopped: bool,
+    /// CPU cycles per tenth of a second, the TOD input is the mains frequency on the C64.
+    cycles_per_tenth: u32,
+    tod_cycles: u32,
+
+    sdr: u8,
+    icr_flags: u8,
+    icr_mask: u8,
+}
+
+impl Cia {
+    /// Creates a CIA in its power-on state.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The CPU interrupt line the chip drives.
+    /// * `clock_hz` - The CPU clock, to derive the time of day clock from.
+    pub fn new(line: InterruptLine, clock_hz: f64) -> Self {
+        Cia {
+            line,
+            pra: 0x00,
+            prb: 0x00,
+            ddra: 0x00,
+            ddrb: 0x00,
+            pins_a: 0xff,
+            pins_b: 0xff,
+            timer_a: Timer::new(),
+            timer_b: Timer::new(),
+            tod: Tod {
+                hours: 0x01,
+                ..Tod::default()
+            },
+            alarm: Tod::default(),
+            tod_latch: None,
+            tod_stopped: false,
+            cycles_per_tenth: (clock_hz / 10.0).round() as u32,
+            tod_cycles: 0,
+            sdr: 0x00,
+            icr_flags: 0x00,
+            icr_mask: 0x00,
+        }
+    }
+
+    /// # Returns
+    /// The levels of the port A pins: register bits where the pin is an output,
+    /// the external level where it is an input.
+    pub fn port_a(&self) -> u8 {
+        (self.pra & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    /// # Returns
+    /// The levels of the port B pins, see `port_a()`.
+    pub fn port_b(&self) -> u8 {
+        (self.prb & self.ddrb) | (self.pins_b & !self.ddrb)
+    }
+
+    /// Sets the levels driven from outside onto the port A pins.
+    /// Only the pins configured as inputs are affected.
+    pub fn set_port_a_input(&mut self, levels: u8) {
+        self.pins_a = levels;
+    }
+
+    /// Sets the levels driven from outside onto the port B pins.
+    pub fn set_port_b_input(&mut self, levels: u8) {
+        self.pins_b = levels;
+    }
+
+    /// Signals a falling edge on the FLAG input, e.g. from the cassette or serial port.
+    pub fn trigger_flag(&mut self) {
+        self.icr_flags |= INT_FLAG;
+    }
+
+    fn tod_register(tod: &Tod, offset: u16) -> u8 {
+        match offset {
+            TOD_10THS => tod.tenths,
+            TOD_SEC => tod.seconds,
+            TOD_MIN => tod.minutes,
+            _ => tod.hours,
+        }
+    }
+
+    fn tick_tod(&mut self, cycles: u32) {
+        if self.tod_stopped || self.cycles_per_tenth == 0 {
+            return;
+        }
+        self.tod_cycles += cycles;
+        while self.tod_cycles >= self.cycles_per_tenth {
+            self.tod_cycles -= self.cycles_per_tenth;
+            self.tod.advance();
+            if self.tod == self.alarm {
+                self.icr_flags |= INT_ALARM;
+            }
+        }
+    }
+
+    fn is_interrupting(&self) -> bool {
+        self.icr_flags & self.icr_mask != 0
+    }
+}
+
+impl Device for Cia {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x0f {
+            PRA => self.port_a(),
+            PRB => self.port_b(),
+            DDRA => self.ddra,
+            DDRB => self.ddrb,
+            TA_LO => self.timer_a.counter as u8,
+            TA_HI => (self.timer_a.counter >> 8) as u8,
+            TB_LO => self.timer_b.counter as u8,
+            TB_HI => (self.timer_b.counter >> 8) as u8,
+            register @ TOD_10THS..=TOD_HR => {
+                // Reading the hours freezes the registers until the tenths are read,
+                // so a time can be read without it rolling over halfway
+                if register == TOD_HR {
+                    self.tod_latch = Some(self.tod);
+                }
+                let tod: Tod = self.tod_latch.unwrap_or(self.tod);
+                if register == TOD_10THS {
+                    self.tod_latch = None;
+                }
+                Self::tod_register(&tod, register)
+            }
+            SDR => self.sdr,
+            ICR => {
+                // Reading acknowledges every interrupt
+                let value: u8 = if self.is_interrupting() {
+                    self.icr_flags | INT_ANY
+                } else {
+                    self.icr_flags
+                };
+                self.icr_flags = 0x00;
+                value
+            }
+            CRA => self.timer_a.control,
+            _ => self.timer_b.control,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x0f {
+            PRA => self.pra = value,
+            PRB => self.prb = value,
+            DDRA => self.ddra = value,
+            DDRB => self.ddrb = value,
+            TA_LO => self.timer_a.latch = (self.timer_a.latch & 0xff00) | value as u16,
+            TA_HI => self.timer_a.write_high(value),
+            TB_LO => self.timer_b.latch = (self.timer_b.latch & 0xff00) | value as u16,
+            TB_HI => self.timer_b.write_high(value),
+            register @ TOD_10THS..=TOD_HR => {
+                let tod: &mut Tod = if self.timer_b.control & CRB_ALARM != 0 {
+                    &mut self.alarm
+                } else {
+                    // Writing the hours stops the clock until the tenths are written
+                    match register {
+                        TOD_HR => self.tod_stopped = true,
+                        TOD_10THS => self.tod_stopped = false,
+                        _ => {}
+                    }
+                    &mut self.tod
+                };
+                match register {
+                    TOD_10THS => tod.tenths = value & 0x0f,
+                    TOD_SEC => tod.seconds = value & 0x7f,
+                    TOD_MIN => tod.minutes = value & 0x7f,
+                    _ => tod.hours = value & 0x9f,
+                }
+            }
+            SDR => self.sdr = value,
+            ICR => {
+                if value & INT_ANY != 0 {
+                    self.icr_mask |= value & !INT_ANY;
+                } else {
+                    self.icr_mask &= !value;
+                }
+            }
+            CRA => self.timer_a.write_control(value),
+            _ => self.timer_b.write_control(value),
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        let ta_underflows: u32 = self.timer_a.count(cycles);
+        if ta_underflows > 0 {
+            self.icr_flags |= INT_TA;
+        }
+
+        let tb_pulses: u32 = if self.timer_b.control & CRB_COUNT_TA != 0 {
+            ta_underflows
+        } else {
+            cycles
+        };
+        if self.timer_b.count(tb_pulses) > 0 {
+            self.icr_flags |= INT_TB;
+        }
+
+        self.tick_tod(cycles);
+
+        match (self.line, self.is_interrupting()) {
+            (_, false) => IrqState::NONE,
+            (InterruptLine::Irq, true) => IrqState::IRQ,
+            (InterruptLine::Nmi, true) => IrqState::NMI,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_a_continuous_underflow_irq() {
+        let mut cia = Cia::new(InterruptLine::Irq, 1_000_000.0);
+        cia.write(ICR, INT_ANY | INT_TA);
+        cia.write(TA_LO, 0x09);
+        cia.write(TA_HI, 0x00);
+        cia.write(CRA, CR_START);
+
+        assert_eq!(cia.tick(9), IrqState::NONE);
+        assert_eq!(cia.tick(1), IrqState::IRQ);
+        assert_eq!(cia.read(ICR), INT_ANY | INT_TA);
+        assert_eq!(cia.read(ICR), 0x00);
+
+        // Reloaded from the latch, a period of latch + 1
+        assert_eq!(cia.tick(9), IrqState::NONE);
+        assert_eq!(cia.tick(1), IrqState::IRQ);
+    }
+
+    #[test]
+    fn timer_b_counts_timer_a_on_nmi() {
+        let mut cia = Cia::new(InterruptLine::Nmi, 1_000_000.0);
+        cia.write(ICR, INT_ANY | INT_TB);
+        cia.write(TA_LO, 0x00);
+        cia.write(TA_HI, 0x00);
+        cia.write(TB_LO, 0x02);
+        cia.write(TB_HI, 0x00);
+        cia.write(CRA, CR_START);
+        cia.write(CRB, CR_START | CR_ONE_SHOT | CRB_COUNT_TA);
+
+        assert_eq!(cia.tick(2), IrqState::NONE);
+        assert_eq!(cia.tick(1), IrqState::NMI);
+        assert_eq!(cia.read(CRB) & CR_START, 0);
+    }
+
+    #[test]
+    fn tod_counts_and_latches() {
+        let mut cia = Cia::new(InterruptLine::Irq, 1_000.0);
+        cia.write(TOD_HR, 0x11);
+        cia.write(TOD_MIN, 0x59);
+        cia.write(TOD_SEC, 0x59);
+        cia.write(TOD_10THS, 0x09);
+
+        let hours: u8 = cia.read(TOD_HR);
+        cia.tick(100);
+        assert_eq!(cia.read(TOD_SEC), 0x59);
+        assert_eq!(cia.read(TOD_10THS), 0x09);
+
+        assert_eq!(hours, 0x11);
+        assert_eq!(cia.read(TOD_HR), 0x92);
+        assert_eq!(cia.read(TOD_MIN), 0x00);
+        assert_eq!(cia.read(TOD_10THS), 0x00);
+    }
+}
@@ -0,0 +1,122 @@
+use memory::device::{BusJob, Device, Transfer};
+
+// Register offsets, the device decodes the low 3 address bits.
+pub const SOURCE_LO: u16 = 0x0;
+pub const SOURCE_HI: u16 = 0x1;
+pub const DESTINATION_LO: u16 = 0x2;
+pub const DESTINATION_HI: u16 = 0x3;
+/// Bytes to copy, `0` for 65536.
+pub const LENGTH_LO: u16 = 0x4;
+pub const LENGTH_HI: u16 = 0x5;
+/// Writing any value starts the transfer.
+pub const START: u16 = 0x6;
+/// Writing a page number copies that page to the destination, like the OAM DMA
+/// register of the NES, without touching the source and length registers.
+pub const PAGE: u16 = 0x7;
+
+/// A DMA controller copying blocks of memory while the CPU waits on its RDY line.
+///
+/// A transfer starts as soon as it is triggered and the CPU is stalled for
+/// `memory::device::TRANSFER_CYCLES_PER_BYTE` cycles per byte, during which the other
+/// devices keep running.
+pub struct Dma {
+    source: u16,
+    destination: u16,
+    length: u16,
+    pending: Option<Transfer>,
+}
+
+impl Default for Dma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Dma {
+            source: 0x0000,
+            destination: 0x0000,
+            length: 0x0000,
+            pending: None,
+        }
+    }
+}
+
+fn set_lo(word: &mut u16, value: u8) {
+    *word = (*word & 0xff00) | value as u16;
+}
+
+fn set_hi(word: &mut u16, value: u8) {
+    *word = (*word & 0x00ff) | ((value as u16) << 8);
+}
+
+impl Device for Dma {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x07 {
+            SOURCE_LO => self.source as u8,
+            SOURCE_HI => (self.source >> 8) as u8,
+            DESTINATION_LO => self.destination as u8,
+            DESTINATION_HI => (self.destination >> 8) as u8,
+            LENGTH_LO => self.length as u8,
+            LENGTH_HI => (self.length >> 8) as u8,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x07 {
+            SOURCE_LO => set_lo(&mut self.source, value),
+            SOURCE_HI => set_hi(&mut self.source, value),
+            DESTINATION_LO => set_lo(&mut self.destination, value),
+            DESTINATION_HI => set_hi(&mut self.destination, value),
+            LENGTH_LO => set_lo(&mut self.length, value),
+            LENGTH_HI => set_hi(&mut self.length, value),
+            START => {
+                self.pending = Some(Transfer {
+                    source: self.source,
+                    destination: self.destination,
+                    length: self.length,
+                })
+            }
+            _ => {
+                self.pending = Some(Transfer {
+                    source: (value as u16) << 8,
+                    destination: self.destination,
+                    length: 0x100,
+                })
+            }
+        }
+    }
+
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        self.pending.take().map(Transfer::job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::device::TRANSFER_CYCLES_PER_BYTE;
+    use memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn copies_page_and_stalls() {
+        let mut mem: Memory = Memory::new();
+        mem.map_device(0x4000, 0x4007, Rc::new(RefCell::new(Dma::new())));
+        for index in 0..0x100u16 {
+            mem.write(0x0200 + index, index as u8);
+        }
+
+        mem.write(0x4000 + DESTINATION_HI, 0x30);
+        mem.write(0x4000 + PAGE, 0x02);
+        mem.tick_devices(4);
+
+        assert_eq!(mem.read(0x3000), 0x00);
+        assert_eq!(mem.read(0x30ff), 0xff);
+        assert_eq!(mem.take_stall_cycles(), 0x100 * TRANSFER_CYCLES_PER_BYTE);
+        assert_eq!(mem.take_stall_cycles(), 0);
+    }
+}
@@ -0,0 +1,138 @@
+use memory::device::Device;
+
+// Register offsets, at 0x4016 and 0x4017 on the NES.
+/// Reading shifts out the next button of controller 1, writing bit 0 sets the strobe
+/// of both controllers.
+pub const PORT_1: u16 = 0x0;
+/// Reading shifts out the next button of controller 2.
+pub const PORT_2: u16 = 0x1;
+
+// Button bits, in the order they are shifted out.
+pub const BUTTON_A: u8 = 0b0000_0001;
+pub const BUTTON_B: u8 = 0b0000_0010;
+pub const BUTTON_SELECT: u8 = 0b0000_0100;
+pub const BUTTON_START: u8 = 0b0000_1000;
+pub const BUTTON_UP: u8 = 0b0001_0000;
+pub const BUTTON_DOWN: u8 = 0b0010_0000;
+pub const BUTTON_LEFT: u8 = 0b0100_0000;
+pub const BUTTON_RIGHT: u8 = 0b1000_0000;
+
+/// The names of the buttons, bit 0 first, see `parse_buttons()`.
+pub const BUTTON_NAMES: [&str; 8] = ["a", "b", "select", "start", "up", "down", "left", "right"];
+
+/// Bits 5 to 7 are not driven and read back the high byte of the address, 0x40.
+const OPEN_BUS: u8 = 0x40;
+
+/// The two controller ports of the NES, standard controllers plugged into both.
+///
+/// While the strobe is high, the shift registers keep loading the buttons held, so
+/// reading returns the A button. Once it goes low, each read returns the next
+/// button, A, B, Select, Start, Up, Down, Left and Right, then 1s.
+pub struct Joypad {
+    /// Buttons held on each controller, see `BUTTON_A` and the others.
+    buttons: [u8; 2],
+    shifters: [u8; 2],
+    strobe: bool,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            buttons: [0x00; 2],
+            shifters: [0x00; 2],
+            strobe: false,
+        }
+    }
+
+    /// Holds `buttons` on controller `port`, `0` or `1`, releasing the others.
+    pub fn set_buttons(&mut self, port: usize, buttons: u8) {
+        self.buttons[port] = buttons;
+    }
+
+    /// # Returns
+    /// The buttons held on controller `port`.
+    pub fn buttons(&self, port: usize) -> u8 {
+        self.buttons[port]
+    }
+}
+
+/// Parses button names separated by spaces, `+` or `,`, e.g. `a+start`, see
+/// `BUTTON_NAMES`.
+///
+/// # Returns
+/// The button bits, or an error naming the unknown button.
+pub fn parse_buttons(text: &str) -> Result<u8, String> {
+    let mut buttons: u8 = 0x00;
+    for name in text
+        .split(|c: char| c.is_whitespace() || c == '+' || c == ',')
+        .filter(|name| !name.is_empty())
+    {
+        let bit: usize = BUTTON_NAMES
+            .iter()
+            .position(|known| known.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown button `{}`, expected {}",
+                    name,
+                    BUTTON_NAMES.join(", ")
+                )
+            })?;
+        buttons |= 1 << bit;
+    }
+    Ok(buttons)
+}
+
+impl Device for Joypad {
+    fn read(&mut self, offset: u16) -> u8 {
+        let port: usize = (offset & 0x01) as usize;
+        if self.strobe {
+            self.shifters[port] = self.buttons[port];
+        }
+        let bit: u8 = self.shifters[port] & 0x01;
+        // Official controllers shift in 1s
+        self.shifters[port] = (self.shifters[port] >> 1) | 0x80;
+        OPEN_BUS | bit
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        // Writes to 0x4017 go to the APU frame counter instead
+        if offset & 0x01 != PORT_1 {
+            return;
+        }
+        // The buttons are latched as long as the strobe is high, and when it goes low
+        if self.strobe || value & 0x01 != 0 {
+            self.shifters = self.buttons;
+        }
+        self.strobe = value & 0x01 != 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strobe_and_shift() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(0, parse_buttons("a+start right").unwrap());
+        joypad.set_buttons(1, BUTTON_B);
+
+        joypad.write(PORT_1, 1);
+        // The strobe is high, A is read over and over
+        assert_eq!(joypad.read(PORT_1), OPEN_BUS | 1);
+        assert_eq!(joypad.read(PORT_1), OPEN_BUS | 1);
+        joypad.write(PORT_1, 0);
+
+        let bits: Vec<u8> = (0..10).map(|_| joypad.read(PORT_1) & 1).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(joypad.read(PORT_2) & 1, 0);
+        assert_eq!(joypad.read(PORT_2) & 1, 1);
+        assert!(parse_buttons("turbo").is_err());
+    }
+}
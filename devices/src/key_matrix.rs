@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A key, at the crossing of a column driven by CIA1 port A and a row read on port B.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub column: u8,
+    pub row: u8,
+}
+
+impl Key {
+    pub const LEFT_SHIFT: Key = Key { column: 1, row: 7 };
+    pub const RIGHT_SHIFT: Key = Key { column: 6, row: 4 };
+    pub const CONTROL: Key = Key { column: 7, row: 2 };
+    pub const COMMODORE: Key = Key { column: 7, row: 5 };
+    pub const RUN_STOP: Key = Key { column: 7, row: 7 };
+}
+
+/// The PETSCII code each key types unshifted, indexed by column then row.
+/// `0` marks the modifiers, which type nothing by themselves.
+const LAYOUT: [[u8; 8]; 8] = [
+    [0x14, 0x0d, 0x1d, 0x88, 0x85, 0x86, 0x87, 0x11],
+    [b'3', b'W', b'A', b'4', b'Z', b'S', b'E', 0x00],
+    [b'5', b'R', b'D', b'6', b'C', b'F', b'T', b'X'],
+    [b'7', b'Y', b'G', b'8', b'B', b'H', b'U', b'V'],
+    [b'9', b'I', b'J', b'0', b'M', b'K', b'O', b'N'],
+    [b'+', b'P', b'L', b'-', b'.', b':', b'@', b','],
+    [0x5c, b'*', b';', 0x13, 0x00, b'=', 0x5e, b'/'],
+    [b'1', 0x5f, 0x00, b'2', b' ', 0x00, b'Q', 0x03],
+];
+
+/// PETSCII codes typed with shift, and the code of the same key unshifted.
+const SHIFTED: [(u8, u8); 23] = [
+    (b'!', b'1'),
+    (b'"', b'2'),
+    (b'#', b'3'),
+    (b'$', b'4'),
+    (b'%', b'5'),
+    (b'&', b'6'),
+    (b'\'', b'7'),
+    (b'(', b'8'),
+    (b')', b'9'),
+    (b'[', b':'),
+    (b']', b';'),
+    (b'<', b','),
+    (b'>', b'.'),
+    (b'?', b'/'),
+    (0x94, 0x14),
+    (0x9d, 0x1d),
+    (0x91, 0x11),
+    (0x93, 0x13),
+    (0x89, 0x85),
+    (0x8a, 0x86),
+    (0x8b, 0x87),
+    (0x8c, 0x88),
+    (0x83, 0x03),
+];
+
+/// Cycles a typed key is held down, then released before the next one. The KERNAL
+/// scans the keyboard every 1/60 s, this gives it two scans either way.
+pub const HOLD_CYCLES: u32 = 40_000;
+
+/// # Returns
+/// The key typing the PETSCII code `petscii` and whether shift is needed with it,
+/// `None` if no key types it.
+pub fn key_for(petscii: u8) -> Option<(Key, bool)> {
+    let (code, shift): (u8, bool) = match petscii {
+        // Shifted letters are the graphics characters in the uppercase set
+        0xc1..=0xda => (petscii - 0x80, true),
+        _ => match SHIFTED.iter().find(|(shifted, _)| *shifted == petscii) {
+            Some((_, code)) => (*code, true),
+            None => (petscii, false),
+        },
+    };
+    if code == 0x00 {
+        return None;
+    }
+    (0..8u8)
+        .flat_map(|column| (0..8u8).map(move |row| Key { column, row }))
+        .find(|key| LAYOUT[key.column as usize][key.row as usize] == code)
+        .map(|key| (key, shift))
+}
+
+/// The 8x8 keyboard matrix of the C64, scanned through the ports of CIA1.
+///
+/// Keys can be pressed directly, or typed as PETSCII codes through the `Sender` returned
+/// by `new()`: each is then held for `HOLD_CYCLES` with shift if needed, as `tick()`
+/// advances.
+pub struct KeyMatrix {
+    /// Rows pulled down by each column, one bit per pressed key.
+    pressed: [u8; 8],
+
+    typed: Receiver<u8>,
+    queue: VecDeque<u8>,
+    /// The key being typed, with whether shift is held along.
+    typing: Option<(Key, bool)>,
+    /// Cycles until the typed key is released, or the next one can be pressed.
+    countdown: u32,
+}
+
+impl KeyMatrix {
+    /// # Returns
+    /// The matrix with no key pressed and the sender typing on it, which can be moved to
+    /// another thread, e.g. one reading stdin.
+    pub fn new() -> (Self, Sender<u8>) {
+        let (sender, typed) = mpsc::channel::<u8>();
+        let matrix: KeyMatrix = KeyMatrix {
+            pressed: [0; 8],
+            typed,
+            queue: VecDeque::new(),
+            typing: None,
+            countdown: 0,
+        };
+        (matrix, sender)
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        let bit: u8 = 1 << key.row;
+        if pressed {
+            self.pressed[key.column as usize] |= bit;
+        } else {
+            self.pressed[key.column as usize] &= !bit;
+        }
+    }
+
+    /// # Returns
+    /// The levels of the row lines, low where a pressed key connects the row to a column
+    /// driven low in `columns`.
+    pub fn rows(&self, columns: u8) -> u8 {
+        let mut rows: u8 = 0xff;
+        for (column, pressed) in self.pressed.iter().enumerate() {
+            if columns & (1 << column) == 0 {
+                rows &= !pressed;
+            }
+        }
+        rows
+    }
+
+    /// # Returns
+    /// The levels of the column lines, low where a pressed key connects the column to a
+    /// row driven low in `rows`. Some games scan the matrix this way round.
+    pub fn columns(&self, rows: u8) -> u8 {
+        let mut columns: u8 = 0xff;
+        for (column, pressed) in self.pressed.iter().enumerate() {
+            if pressed & !rows != 0 {
+                columns &= !(1 << column);
+            }
+        }
+        columns
+    }
+
+    /// Advances the typing of queued keys by `cycles` CPU cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        self.queue.extend(self.typed.try_iter());
+        if self.countdown > cycles {
+            self.countdown -= cycles;
+            return;
+        }
+        self.countdown = 0;
+
+        if let Some((key, shift)) = self.typing.take() {
+            self.set_key(key, false);
+            if shift {
+                self.set_key(Key::LEFT_SHIFT, false);
+            }
+            self.countdown = HOLD_CYCLES;
+            return;
+        }
+        // Codes no key types are skipped
+        while let Some(petscii) = self.queue.pop_front() {
+            if let Some((key, shift)) = key_for(petscii) {
+                self.set_key(key, true);
+                if shift {
+                    self.set_key(Key::LEFT_SHIFT, true);
+                }
+                self.typing = Some((key, shift));
+                self.countdown = HOLD_CYCLES;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_key_is_scanned_with_shift() {
+        let (mut matrix, typed) = KeyMatrix::new();
+        typed.send(b'!').unwrap();
+
+        matrix.tick(1);
+        // "1" is on column 7, row 0, shift on column 1, row 7
+        assert_eq!(matrix.rows(!0b1000_0000), !0b0000_0001);
+        assert_eq!(matrix.rows(!0b0000_0010), !0b1000_0000);
+        assert_eq!(matrix.rows(0xff), 0xff);
+        assert_eq!(matrix.columns(!0b0000_0001), !0b1000_0000);
+
+        matrix.tick(HOLD_CYCLES);
+        assert_eq!(matrix.rows(0x00), 0xff);
+    }
+}
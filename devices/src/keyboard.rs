@@ -0,0 +1,73 @@
+use memory::device::Device;
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Address the `bare` machine maps the keyboard at, like py65 and other simulators do.
+pub const DEFAULT_ADDRESS: u16 = 0xf004;
+
+/// Offset of the data register. Reading it takes the next key, `0` if there is none.
+pub const DATA: u16 = 0;
+/// Offset of the status register. Bit 7 is set while a key is available.
+pub const STATUS: u16 = 1;
+
+const KEY_AVAILABLE: u8 = 0b1000_0000;
+
+/// A keyboard port fed by the host. Keys sent through the `Sender` returned by
+/// `new()` queue up until the program reads them.
+pub struct Keyboard {
+    keys: Receiver<u8>,
+    queue: VecDeque<u8>,
+}
+
+impl Keyboard {
+    /// # Returns
+    /// The keyboard and the sender feeding it, which can be moved to another thread,
+    /// e.g. one reading stdin.
+    pub fn new() -> (Self, Sender<u8>) {
+        let (sender, keys) = mpsc::channel::<u8>();
+        let keyboard: Keyboard = Keyboard {
+            keys,
+            queue: VecDeque::new(),
+        };
+        (keyboard, sender)
+    }
+
+    fn poll(&mut self) {
+        self.queue.extend(self.keys.try_iter());
+    }
+}
+
+impl Device for Keyboard {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.poll();
+        match offset {
+            DATA => self.queue.pop_front().unwrap_or(0x00),
+            STATUS if self.queue.is_empty() => 0x00,
+            STATUS => KEY_AVAILABLE,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, _offset: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_read_in_order() {
+        let (mut keyboard, sender) = Keyboard::new();
+        assert_eq!(keyboard.read(STATUS), 0x00);
+        assert_eq!(keyboard.read(DATA), 0x00);
+
+        sender.send(b'A').unwrap();
+        sender.send(b'\r').unwrap();
+
+        assert_eq!(keyboard.read(STATUS), KEY_AVAILABLE);
+        assert_eq!(keyboard.read(DATA), b'A');
+        assert_eq!(keyboard.read(DATA), b'\r');
+        assert_eq!(keyboard.read(STATUS), 0x00);
+    }
+}
@@ -0,0 +1,21 @@
+//! Peripheral chips that can be mapped into `memory::Memory`.
+
+pub mod acia;
+pub mod char_output;
+pub mod charset;
+pub mod cia;
+pub mod dma;
+pub mod joypad;
+pub mod key_matrix;
+pub mod keyboard;
+pub mod pia;
+pub mod ppu;
+pub mod random;
+pub mod reu;
+pub mod riot;
+pub mod sid;
+pub mod tia;
+pub mod timer;
+pub mod via;
+pub mod vic;
+pub mod video;
@@ -0,0 +1,214 @@
+use memory::device::{Device, IrqState};
+
+// Register offsets, the chip decodes the low 2 address bits.
+/// Port A data, or its data direction register while bit 2 of CRA is clear.
+pub const PRA: u16 = 0x0;
+pub const CRA: u16 = 0x1;
+/// Port B data, or its data direction register while bit 2 of CRB is clear.
+pub const PRB: u16 = 0x2;
+pub const CRB: u16 = 0x3;
+
+// Control register bits, the same for both ports
+/// Enables the interrupt of the C1 input.
+pub const CONTROL_C1_IRQ: u8 = 0b0000_0001;
+/// The C1 input is active on a rising edge instead of a falling one.
+pub const CONTROL_C1_POSITIVE_EDGE: u8 = 0b0000_0010;
+/// Selects the data register instead of the data direction register.
+pub const CONTROL_DATA: u8 = 0b0000_0100;
+/// Set on an active edge of C1, cleared by reading the data register.
+pub const CONTROL_C1_FLAG: u8 = 0b1000_0000;
+
+/// One side of the PIA.
+#[derive(Default)]
+struct Port {
+    output: u8,
+    ddr: u8,
+    /// Levels driven onto the pins from outside.
+    pins: u8,
+    control: u8,
+    c1: bool,
+}
+
+impl Port {
+    fn levels(&self) -> u8 {
+        (self.output & self.ddr) | (self.pins & !self.ddr)
+    }
+
+    fn read(&mut self, control: bool) -> u8 {
+        if control {
+            self.control
+        } else if self.control & CONTROL_DATA != 0 {
+            self.control &= !CONTROL_C1_FLAG;
+            self.levels()
+        } else {
+            self.ddr
+        }
+    }
+
+    /// # Returns
+    /// `true` if the data register was written.
+    fn write(&mut self, control: bool, value: u8) -> bool {
+        if control {
+            // The flags are read-only
+            self.control = (self.control & CONTROL_C1_FLAG) | (value & 0x3f);
+        } else if self.control & CONTROL_DATA != 0 {
+            self.output = value;
+            return true;
+        } else {
+            self.ddr = value;
+        }
+        false
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        let positive: bool = self.control & CONTROL_C1_POSITIVE_EDGE != 0;
+        if (positive && !self.c1 && level) || (!positive && self.c1 && !level) {
+            self.control |= CONTROL_C1_FLAG;
+        }
+        self.c1 = level;
+    }
+
+    fn irq(&self) -> bool {
+        self.control & CONTROL_C1_FLAG != 0 && self.control & CONTROL_C1_IRQ != 0
+    }
+}
+
+/// A Motorola 6821 Peripheral Interface Adapter, the parallel port of the Apple-1.
+///
+/// Emulated: both ports with their data direction registers and the CA1/CB1 edge
+/// interrupts on the IRQA/IRQB outputs, wired together to IRQ.
+/// Not emulated: the CA2/CB2 lines and their interrupts.
+#[derive(Default)]
+pub struct Pia {
+    a: Port,
+    b: Port,
+    /// Writes to the port B data register since the last `take_port_b_writes()`.
+    b_writes: Vec<u8>,
+}
+
+impl Pia {
+    pub fn new() -> Self {
+        Pia {
+            a: Port {
+                pins: 0xff,
+                ..Port::default()
+            },
+            b: Port {
+                pins: 0xff,
+                ..Port::default()
+            },
+            b_writes: Vec::new(),
+        }
+    }
+
+    /// # Returns
+    /// The levels of the port A pins: register bits where the pin is an output,
+    /// the external level where it is an input.
+    pub fn port_a(&self) -> u8 {
+        self.a.levels()
+    }
+
+    /// # Returns
+    /// The levels of the port B pins, see `port_a()`.
+    pub fn port_b(&self) -> u8 {
+        self.b.levels()
+    }
+
+    /// Sets the levels driven from outside onto the port A pins.
+    pub fn set_port_a_input(&mut self, levels: u8) {
+        self.a.pins = levels;
+    }
+
+    /// Sets the levels driven from outside onto the port B pins.
+    pub fn set_port_b_input(&mut self, levels: u8) {
+        self.b.pins = levels;
+    }
+
+    /// Sets the level of the CA1 input, flagging it on the edge selected in CRA.
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    /// Sets the level of the CB1 input, flagging it on the edge selected in CRB.
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    /// # Returns
+    /// `true` while CA1 is flagged, until port A is read.
+    pub fn ca1_flagged(&self) -> bool {
+        self.a.control & CONTROL_C1_FLAG != 0
+    }
+
+    /// # Returns
+    /// The values written to the port B data register since the last call, oldest
+    /// first. Devices strobed by those writes, like the Apple-1 display, read them here.
+    pub fn take_port_b_writes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.b_writes)
+    }
+}
+
+impl Device for Pia {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x03 {
+            PRA => self.a.read(false),
+            CRA => self.a.read(true),
+            PRB => self.b.read(false),
+            _ => self.b.read(true),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x03 {
+            PRA => {
+                self.a.write(false, value);
+            }
+            CRA => {
+                self.a.write(true, value);
+            }
+            PRB => {
+                if self.b.write(false, value) {
+                    self.b_writes.push(value);
+                }
+            }
+            _ => {
+                self.b.write(true, value);
+            }
+        }
+    }
+
+    fn tick(&mut self, _cycles: u32) -> IrqState {
+        if self.a.irq() || self.b.irq() {
+            IrqState::IRQ
+        } else {
+            IrqState::NONE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strobed_input_and_port_b_writes() {
+        let mut pia: Pia = Pia::new();
+        // As Wozmon sets it up: PB0-6 outputs, data registers, CA1 rising edge
+        pia.write(PRB, 0x7f);
+        pia.write(CRA, 0xa7);
+        pia.write(CRB, 0xa7);
+
+        pia.set_port_a_input(b'A' | 0x80);
+        pia.set_ca1(true);
+        assert_eq!(pia.read(CRA) & CONTROL_C1_FLAG, CONTROL_C1_FLAG);
+        assert_eq!(pia.tick(1), IrqState::IRQ);
+        assert_eq!(pia.read(PRA), b'A' | 0x80);
+        assert!(!pia.ca1_flagged());
+
+        pia.write(PRB, b'A' | 0x80);
+        assert_eq!(pia.take_port_b_writes(), [b'A' | 0x80]);
+        // PB7 is an input, high unless pulled down
+        assert_eq!(pia.port_b(), b'A' | 0x80);
+        assert_eq!(pia.read(CRB), 0x27);
+    }
+}
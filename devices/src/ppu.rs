@@ -0,0 +1,620 @@
+use crate::video::FrameBuffer;
+
+use memory::device::{BusJob, Device, IrqState};
+use memory::Memory;
+
+// Register offsets, the chip decodes the low 3 address bits.
+pub const PPUCTRL: u16 = 0x0;
+pub const PPUMASK: u16 = 0x1;
+pub const PPUSTATUS: u16 = 0x2;
+pub const OAMADDR: u16 = 0x3;
+pub const OAMDATA: u16 = 0x4;
+pub const PPUSCROLL: u16 = 0x5;
+pub const PPUADDR: u16 = 0x6;
+pub const PPUDATA: u16 = 0x7;
+
+/// In PPUCTRL: add 32 to the VRAM address after each PPUDATA access, instead of 1.
+const CTRL_INCREMENT_32: u8 = 0b0000_0100;
+/// In PPUCTRL: 8x8 sprites use the pattern table at 0x1000.
+const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+/// In PPUCTRL: the background uses the pattern table at 0x1000.
+const CTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CTRL_SPRITE_8X16: u8 = 0b0010_0000;
+/// In PPUCTRL: assert NMI at the start of vblank.
+const CTRL_NMI: u8 = 0b1000_0000;
+const MASK_GRAYSCALE: u8 = 0b0000_0001;
+/// In PPUMASK: show the background in the leftmost 8 pixels.
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const STATUS_SPRITE_0_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
+/// Size of the rendered frame, the whole picture including the overscan.
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+/// NTSC timing, the PPU runs 3 dots per CPU cycle.
+const DOTS_PER_CYCLE: u32 = 3;
+const DOTS_PER_LINE: u32 = 341;
+const LINES: u16 = 262;
+const VBLANK_LINE: u16 = 241;
+const PRE_RENDER_LINE: u16 = 261;
+
+/// Cycles the CPU is stalled by an OAM DMA.
+pub const OAM_DMA_CYCLES: u32 = 513;
+
+/// The 64 colors of the 2C02 as RGBA.
+pub const PALETTE: [[u8; 4]; 64] = [
+    [0x54, 0x54, 0x54, 0xff],
+    [0x00, 0x1e, 0x74, 0xff],
+    [0x08, 0x10, 0x90, 0xff],
+    [0x30, 0x00, 0x88, 0xff],
+    [0x44, 0x00, 0x64, 0xff],
+    [0x5c, 0x00, 0x30, 0xff],
+    [0x54, 0x04, 0x00, 0xff],
+    [0x3c, 0x18, 0x00, 0xff],
+    [0x20, 0x2a, 0x00, 0xff],
+    [0x08, 0x3a, 0x00, 0xff],
+    [0x00, 0x40, 0x00, 0xff],
+    [0x00, 0x3c, 0x00, 0xff],
+    [0x00, 0x32, 0x3c, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x98, 0x96, 0x98, 0xff],
+    [0x08, 0x4c, 0xc4, 0xff],
+    [0x30, 0x32, 0xec, 0xff],
+    [0x5c, 0x1e, 0xe4, 0xff],
+    [0x88, 0x14, 0xb0, 0xff],
+    [0xa0, 0x14, 0x64, 0xff],
+    [0x98, 0x22, 0x20, 0xff],
+    [0x78, 0x3c, 0x00, 0xff],
+    [0x54, 0x5a, 0x00, 0xff],
+    [0x28, 0x72, 0x00, 0xff],
+    [0x08, 0x7c, 0x00, 0xff],
+    [0x00, 0x76, 0x28, 0xff],
+    [0x00, 0x66, 0x78, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0xec, 0xee, 0xec, 0xff],
+    [0x4c, 0x9a, 0xec, 0xff],
+    [0x78, 0x7c, 0xec, 0xff],
+    [0xb0, 0x62, 0xec, 0xff],
+    [0xe4, 0x54, 0xec, 0xff],
+    [0xec, 0x58, 0xb4, 0xff],
+    [0xec, 0x6a, 0x64, 0xff],
+    [0xd4, 0x88, 0x20, 0xff],
+    [0xa0, 0xaa, 0x00, 0xff],
+    [0x74, 0xc4, 0x00, 0xff],
+    [0x4c, 0xd0, 0x20, 0xff],
+    [0x38, 0xcc, 0x6c, 0xff],
+    [0x38, 0xb4, 0xcc, 0xff],
+    [0x3c, 0x3c, 0x3c, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0xec, 0xee, 0xec, 0xff],
+    [0xa8, 0xcc, 0xec, 0xff],
+    [0xbc, 0xbc, 0xec, 0xff],
+    [0xd4, 0xb2, 0xec, 0xff],
+    [0xec, 0xae, 0xec, 0xff],
+    [0xec, 0xae, 0xd4, 0xff],
+    [0xec, 0xb4, 0xb0, 0xff],
+    [0xe4, 0xc4, 0x90, 0xff],
+    [0xcc, 0xd2, 0x78, 0xff],
+    [0xb4, 0xde, 0x78, 0xff],
+    [0xa8, 0xe2, 0x90, 0xff],
+    [0x98, 0xe2, 0xb4, 0xff],
+    [0xa0, 0xd6, 0xe4, 0xff],
+    [0xa0, 0xa2, 0xa0, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+    [0x00, 0x00, 0x00, 0xff],
+];
+
+/// How the cartridge wires the 2 KiB of VRAM to the 4 nametables.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    /// `0x2000` and `0x2400` share a nametable, for vertical scrolling.
+    Horizontal,
+    /// `0x2000` and `0x2800` share a nametable, for horizontal scrolling.
+    Vertical,
+}
+
+/// A Ricoh 2C02 PPU (NTSC), rendering the background scanline by scanline.
+///
+/// Emulated: the nametables, attribute and pattern tables, palettes, scrolling through
+/// the internal `v`/`t`/`x`/`w` registers (so scroll changes between scanlines split the
+/// screen), buffered PPUDATA reads, the vblank flag and its NMI, OAM and the sprite 0 hit
+/// flag.
+/// Not emulated: drawing sprites, sprite overflow, color emphasis, the short odd frames
+/// and changes of registers in the middle of a scanline.
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_address: u8,
+    oam: [u8; 0x100],
+    /// The PPU address space: CHR-ROM or RAM in the pattern tables, internal VRAM and
+    /// palette RAM.
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    vram: [u8; 0x800],
+    palette: [u8; 0x20],
+    mirroring: Mirroring,
+
+    /// Current VRAM address.
+    v: u16,
+    /// Temporary VRAM address, the top-left of the screen while rendering.
+    t: u16,
+    /// Fine horizontal scroll.
+    x: u8,
+    /// Toggles between the first and second write of PPUSCROLL and PPUADDR.
+    w: bool,
+    read_buffer: u8,
+    /// The last value written to a register, seen in the unused bits of PPUSTATUS.
+    latch: u8,
+
+    scanline: u16,
+    dots: u32,
+    /// Palette indices of the picture rendered so far.
+    picture: Vec<u8>,
+}
+
+/// The OAM DMA register of the NES, to map at `0x4014`: writing a page number copies
+/// that page to OAMDATA while the CPU waits.
+#[derive(Default)]
+pub struct OamDma {
+    pending: Option<u8>,
+}
+
+impl Ppu {
+    /// # Arguments
+    ///
+    /// * `chr` - The pattern tables from the cartridge CHR-ROM. When empty, the
+    ///   cartridge has 8 KiB of CHR-RAM instead.
+    /// * `mirroring` - The nametable layout wired by the cartridge.
+    pub fn new(chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram: bool = chr.is_empty();
+        Ppu {
+            ctrl: 0x00,
+            mask: 0x00,
+            status: 0x00,
+            oam_address: 0x00,
+            oam: [0; 0x100],
+            chr: if chr_is_ram { vec![0; 0x2000] } else { chr },
+            chr_is_ram,
+            vram: [0; 0x800],
+            palette: [0; 0x20],
+            mirroring,
+            v: 0x0000,
+            t: 0x0000,
+            x: 0,
+            w: false,
+            read_buffer: 0x00,
+            latch: 0x00,
+            scanline: 0,
+            dots: 0,
+            picture: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    /// # Returns
+    /// The current scanline, `0` to `261`, vblank starting at `241`.
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    /// # Returns
+    /// The sprite attribute memory.
+    pub fn oam(&self) -> &[u8; 0x100] {
+        &self.oam
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & (MASK_BACKGROUND | MASK_SPRITES) != 0
+    }
+
+    fn vram_index(&self, address: u16) -> usize {
+        let table: u16 = match self.mirroring {
+            Mirroring::Horizontal => (address >> 11) & 1,
+            Mirroring::Vertical => (address >> 10) & 1,
+        };
+        (table * 0x400 + (address & 0x03ff)) as usize
+    }
+
+    fn palette_index(address: u16) -> usize {
+        // The backdrop entries of the sprite palettes mirror those of the background
+        let index: u16 = address & 0x1f;
+        if index & 0x13 == 0x10 {
+            (index & 0x0f) as usize
+        } else {
+            index as usize
+        }
+    }
+
+    /// Reads a byte of the PPU address space.
+    fn fetch(&self, address: u16) -> u8 {
+        let address: u16 = address & 0x3fff;
+        match address {
+            0x0000..=0x1fff => self.chr[address as usize % self.chr.len()],
+            0x2000..=0x3eff => self.vram[self.vram_index(address)],
+            _ => self.palette[Self::palette_index(address)],
+        }
+    }
+
+    fn store(&mut self, address: u16, value: u8) {
+        let address: u16 = address & 0x3fff;
+        match address {
+            0x0000..=0x1fff if self.chr_is_ram => self.chr[address as usize] = value,
+            0x0000..=0x1fff => {}
+            0x2000..=0x3eff => self.vram[self.vram_index(address)] = value,
+            _ => self.palette[Self::palette_index(address)] = value & 0x3f,
+        }
+    }
+
+    fn increment(&mut self) {
+        let step: u16 = if self.ctrl & CTRL_INCREMENT_32 != 0 {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
+
+    /// Moves `v` to the next tile, into the next nametable after the 32nd.
+    fn increment_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v = (self.v & !0x001f) ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// Moves `v` to the next pixel row, into the next nametable after the 30th tile row.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y: u16 = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // Rows 30 and 31 hold the attributes, scrolling into them wraps without
+            // switching nametables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    /// # Returns
+    /// The 2-bit color of the sprite 0 pixel at `x` on the current scanline, `0` if it
+    /// is transparent or not there.
+    fn sprite_0_pixel(&self, x: usize) -> u8 {
+        let height: u16 = if self.ctrl & CTRL_SPRITE_8X16 != 0 {
+            16
+        } else {
+            8
+        };
+        // Sprites are drawn one line below their Y coordinate
+        let row: u16 = self.scanline.wrapping_sub(self.oam[0] as u16 + 1);
+        let column: usize = x.wrapping_sub(self.oam[3] as usize);
+        if row >= height || column >= 8 {
+            return 0;
+        }
+        let (tile, attributes): (u8, u8) = (self.oam[1], self.oam[2]);
+        let row: u16 = if attributes & 0x80 != 0 {
+            height - 1 - row
+        } else {
+            row
+        };
+        let column: usize = if attributes & 0x40 != 0 {
+            7 - column
+        } else {
+            column
+        };
+        let address: u16 = if height == 16 {
+            (tile as u16 & 1) * 0x1000 + (tile as u16 & 0xfe) * 16 + (row & 8) * 2 + (row & 7)
+        } else {
+            let table: u16 = if self.ctrl & CTRL_SPRITE_TABLE != 0 {
+                0x1000
+            } else {
+                0x0000
+            };
+            table + tile as u16 * 16 + row
+        };
+        let bit: usize = 7 - column;
+        ((self.fetch(address) >> bit) & 1) | (((self.fetch(address + 8) >> bit) & 1) << 1)
+    }
+
+    /// Draws the background of the current scanline from `v`, tile by tile.
+    fn render_scanline(&mut self) {
+        let line: usize = self.scanline as usize * WIDTH;
+        let backdrop: u8 = self.palette[0];
+        if self.mask & MASK_BACKGROUND == 0 {
+            self.picture[line..line + WIDTH].fill(backdrop);
+            return;
+        }
+
+        let table: u16 = if self.ctrl & CTRL_BACKGROUND_TABLE != 0 {
+            0x1000
+        } else {
+            0x0000
+        };
+        let fine_y: u16 = (self.v >> 12) & 0x07;
+        let mut sprite_0_hit: bool = false;
+        // 33 tiles cover the line when it is scrolled by a fraction of a tile
+        for tile in 0..33usize {
+            let name: u8 = self.fetch(0x2000 | (self.v & 0x0fff));
+            let attribute: u8 = self.fetch(
+                0x23c0 | (self.v & 0x0c00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07),
+            );
+            let shift: u16 = ((self.v >> 4) & 0x04) | (self.v & 0x02);
+            let palette: u8 = (attribute >> shift) & 0x03;
+            let pattern: u16 = table + name as u16 * 16 + fine_y;
+            let (low, high): (u8, u8) = (self.fetch(pattern), self.fetch(pattern + 8));
+
+            for bit in 0..8 {
+                let x: usize = (tile * 8 + bit).wrapping_sub(self.x as usize);
+                if x >= WIDTH {
+                    continue;
+                }
+                let color: u8 = ((low >> (7 - bit)) & 1) | (((high >> (7 - bit)) & 1) << 1);
+                let color: u8 = if x < 8 && self.mask & MASK_BACKGROUND_LEFT == 0 {
+                    0
+                } else {
+                    color
+                };
+                self.picture[line + x] = if color == 0 {
+                    backdrop
+                } else {
+                    self.palette[(palette * 4 + color) as usize]
+                };
+
+                let sprites_shown: bool =
+                    self.mask & MASK_SPRITES != 0 && (x >= 8 || self.mask & MASK_SPRITES_LEFT != 0);
+                if color != 0 && sprites_shown && x != 255 && self.sprite_0_pixel(x) != 0 {
+                    sprite_0_hit = true;
+                }
+            }
+            self.increment_x();
+        }
+        if sprite_0_hit {
+            self.status |= STATUS_SPRITE_0_HIT;
+        }
+    }
+
+    /// Finishes the current scanline and starts the next one.
+    fn next_scanline(&mut self) {
+        if self.scanline < HEIGHT as u16 {
+            let start: u16 = self.v;
+            self.render_scanline();
+            if self.rendering() {
+                // Back to the left edge, one pixel row down
+                self.v = start;
+                self.increment_y();
+                self.v = (self.v & !0x041f) | (self.t & 0x041f);
+            } else {
+                self.v = start;
+            }
+        }
+
+        self.scanline = (self.scanline + 1) % LINES;
+        match self.scanline {
+            VBLANK_LINE => self.status |= STATUS_VBLANK,
+            PRE_RENDER_LINE => {
+                self.status &= !(STATUS_VBLANK | STATUS_SPRITE_0_HIT);
+                if self.rendering() {
+                    // The whole scroll position is reloaded for the next frame
+                    self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+                    self.v = (self.v & !0x041f) | (self.t & 0x041f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Renders the last complete picture into `frame`, which must be `WIDTH` x `HEIGHT`.
+    pub fn render(&self, frame: &mut FrameBuffer) {
+        let gray: u8 = if self.mask & MASK_GRAYSCALE != 0 {
+            0x30
+        } else {
+            0x3f
+        };
+        for (index, color) in self.picture.iter().enumerate() {
+            frame.set_pixel(
+                index % WIDTH,
+                index / WIDTH,
+                PALETTE[(color & gray) as usize],
+            );
+        }
+    }
+}
+
+impl Device for Ppu {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x07 {
+            PPUSTATUS => {
+                let status: u8 = self.status | (self.latch & 0x1f);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                status
+            }
+            OAMDATA => self.oam[self.oam_address as usize],
+            PPUDATA => {
+                let address: u16 = self.v & 0x3fff;
+                let value: u8 = if address >= 0x3f00 {
+                    // Palette reads are not buffered, the buffer gets the nametable
+                    // underneath
+                    self.read_buffer = self.fetch(address - 0x1000);
+                    self.fetch(address)
+                } else {
+                    let buffered: u8 = self.read_buffer;
+                    self.read_buffer = self.fetch(address);
+                    buffered
+                };
+                self.increment();
+                value
+            }
+            // The other registers are write-only
+            _ => self.latch,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.latch = value;
+        match offset & 0x07 {
+            PPUCTRL => {
+                self.ctrl = value;
+                self.t = (self.t & !0x0c00) | ((value as u16 & 0x03) << 10);
+            }
+            PPUMASK => self.mask = value,
+            OAMADDR => self.oam_address = value,
+            OAMDATA => {
+                self.oam[self.oam_address as usize] = value;
+                self.oam_address = self.oam_address.wrapping_add(1);
+            }
+            PPUSCROLL if !self.w => {
+                self.t = (self.t & !0x001f) | (value as u16 >> 3);
+                self.x = value & 0x07;
+                self.w = true;
+            }
+            PPUSCROLL => {
+                self.t = (self.t & !0x73e0)
+                    | ((value as u16 & 0x07) << 12)
+                    | ((value as u16 & 0xf8) << 2);
+                self.w = false;
+            }
+            PPUADDR if !self.w => {
+                self.t = (self.t & 0x00ff) | ((value as u16 & 0x3f) << 8);
+                self.w = true;
+            }
+            PPUADDR => {
+                self.t = (self.t & 0xff00) | value as u16;
+                self.v = self.t;
+                self.w = false;
+            }
+            PPUDATA => {
+                self.store(self.v, value);
+                self.increment();
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.dots += cycles * DOTS_PER_CYCLE;
+        while self.dots >= DOTS_PER_LINE {
+            self.dots -= DOTS_PER_LINE;
+            self.next_scanline();
+        }
+
+        if self.status & STATUS_VBLANK != 0 && self.ctrl & CTRL_NMI != 0 {
+            IrqState::NMI
+        } else {
+            IrqState::NONE
+        }
+    }
+}
+
+impl Device for OamDma {
+    fn read(&mut self, _offset: u16) -> u8 {
+        0x00
+    }
+
+    fn write(&mut self, _offset: u16, value: u8) {
+        self.pending = Some(value);
+    }
+
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        let page: u16 = (self.pending.take()? as u16) << 8;
+        Some(Box::new(move |mem: &mut Memory| {
+            for index in 0..0x100 {
+                let value: u8 = mem.read(page + index);
+                mem.write(0x2000 + OAMDATA, value);
+            }
+            OAM_DMA_CYCLES
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CHR-ROM with tile 1 solid in color 1 and a nametable showing it top-left.
+    fn ppu_with_tile() -> Ppu {
+        let mut chr: Vec<u8> = vec![0x00; 0x2000];
+        chr[16..24].fill(0xff);
+        let mut ppu = Ppu::new(chr, Mirroring::Vertical);
+        ppu.write(PPUADDR, 0x20);
+        ppu.write(PPUADDR, 0x00);
+        ppu.write(PPUDATA, 0x01);
+        ppu.write(PPUADDR, 0x3f);
+        ppu.write(PPUADDR, 0x00);
+        for color in [0x0f, 0x30, 0x16, 0x27] {
+            ppu.write(PPUDATA, color);
+        }
+        ppu.write(PPUMASK, MASK_BACKGROUND | MASK_BACKGROUND_LEFT);
+        ppu
+    }
+
+    /// Sets the scroll position like a vblank handler and runs to the end of the next
+    /// picture.
+    fn run_frame(ppu: &mut Ppu, scroll_x: u8) {
+        ppu.read(PPUSTATUS);
+        ppu.write(PPUCTRL, 0x00);
+        ppu.write(PPUSCROLL, scroll_x);
+        ppu.write(PPUSCROLL, 0);
+        for line in [PRE_RENDER_LINE, VBLANK_LINE] {
+            while ppu.scanline() != line {
+                ppu.tick(1);
+            }
+        }
+    }
+
+    #[test]
+    fn renders_and_scrolls_background() {
+        let mut ppu = ppu_with_tile();
+        let mut frame = FrameBuffer::new(WIDTH, HEIGHT);
+
+        run_frame(&mut ppu, 0);
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(7, 7), PALETTE[0x30]);
+        assert_eq!(frame.pixel(8, 0), PALETTE[0x0f]);
+
+        // Scrolled 4 pixels left
+        run_frame(&mut ppu, 4);
+        ppu.render(&mut frame);
+        assert_eq!(frame.pixel(3, 7), PALETTE[0x30]);
+        assert_eq!(frame.pixel(4, 0), PALETTE[0x0f]);
+    }
+
+    #[test]
+    fn vblank_nmi_and_buffered_reads() {
+        let mut ppu = ppu_with_tile();
+        ppu.write(PPUCTRL, CTRL_NMI);
+        let cycles_to_vblank: u32 = VBLANK_LINE as u32 * DOTS_PER_LINE / DOTS_PER_CYCLE + 1;
+        assert_eq!(ppu.tick(cycles_to_vblank - 1), IrqState::NONE);
+        assert_eq!(ppu.tick(1), IrqState::NMI);
+        // Reading the status acknowledges vblank
+        assert_eq!(ppu.read(PPUSTATUS) & STATUS_VBLANK, STATUS_VBLANK);
+        assert_eq!(ppu.tick(1), IrqState::NONE);
+
+        ppu.write(PPUADDR, 0x20);
+        ppu.write(PPUADDR, 0x00);
+        ppu.read(PPUDATA);
+        assert_eq!(ppu.read(PPUDATA), 0x01);
+        // 0x2800 mirrors 0x2000
+        ppu.write(PPUADDR, 0x28);
+        ppu.write(PPUADDR, 0x00);
+        ppu.read(PPUDATA);
+        assert_eq!(ppu.read(PPUDATA), 0x01);
+    }
+}
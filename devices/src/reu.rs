@@ -0,0 +1,303 @@
+use memory::device::{BusJob, Device, IrqState};
+use memory::Memory;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Register offsets, repeated every 32 bytes across the I/O 2 page.
+pub const STATUS: u16 = 0x00;
+pub const COMMAND: u16 = 0x01;
+pub const C64_ADDRESS_LO: u16 = 0x02;
+pub const C64_ADDRESS_HI: u16 = 0x03;
+pub const REU_ADDRESS_LO: u16 = 0x04;
+pub const REU_ADDRESS_HI: u16 = 0x05;
+pub const REU_BANK: u16 = 0x06;
+/// Bytes to transfer, `0` for 65536.
+pub const LENGTH_LO: u16 = 0x07;
+pub const LENGTH_HI: u16 = 0x08;
+pub const INTERRUPT_MASK: u16 = 0x09;
+pub const ADDRESS_CONTROL: u16 = 0x0a;
+
+// Status bits, the top three are cleared by reading it
+pub const STATUS_IRQ: u8 = 0b1000_0000;
+pub const STATUS_END_OF_BLOCK: u8 = 0b0100_0000;
+pub const STATUS_VERIFY_ERROR: u8 = 0b0010_0000;
+/// Set on the units with 256 Kbit chips, the 1764 and 1750.
+pub const STATUS_SIZE: u8 = 0b0001_0000;
+
+// Command bits
+pub const COMMAND_EXECUTE: u8 = 0b1000_0000;
+/// Restore the address and length registers after the transfer.
+pub const COMMAND_AUTOLOAD: u8 = 0b0010_0000;
+/// Start right away instead of on the next write to $FF00.
+pub const COMMAND_NO_FF00: u8 = 0b0001_0000;
+pub const COMMAND_TYPE: u8 = 0b0000_0011;
+pub const TYPE_TO_REU: u8 = 0b00;
+pub const TYPE_FROM_REU: u8 = 0b01;
+pub const TYPE_SWAP: u8 = 0b10;
+pub const TYPE_VERIFY: u8 = 0b11;
+
+// Interrupt mask bits, on top of the status bits they enable
+pub const MASK_ENABLE: u8 = 0b1000_0000;
+
+// Address control bits
+pub const FIX_C64_ADDRESS: u8 = 0b1000_0000;
+pub const FIX_REU_ADDRESS: u8 = 0b0100_0000;
+
+/// The registers and expansion RAM, shared with the transfer running on the bus.
+struct State {
+    ram: Vec<u8>,
+    status: u8,
+    command: u8,
+    c64_address: u16,
+    /// Bank and address, 24 bits.
+    reu_address: u32,
+    length: u16,
+    interrupt_mask: u8,
+    address_control: u8,
+    /// The address and length registers as last written, restored by autoload.
+    reload: (u16, u32, u16),
+    pending: bool,
+}
+
+/// A Commodore RAM Expansion Unit, the 1700, 1764 or 1750, plugged in the expansion
+/// port with its REC DMA controller at $DF00.
+///
+/// Transfers run as soon as they are started, with the CPU stalled a cycle per byte
+/// and two when swapping. Waiting for a write to $FF00 is not emulated: a transfer
+/// without `COMMAND_NO_FF00` starts right away too.
+pub struct Reu {
+    state: Rc<RefCell<State>>,
+}
+
+impl Reu {
+    /// Creates an REU of `size_kb` KiB of expansion RAM: 128 for the 1700, 256 for the
+    /// 1764 and 512 for the 1750.
+    pub fn new(size_kb: usize) -> Self {
+        assert!(
+            size_kb >= 64 && size_kb.is_power_of_two(),
+            "Invalid REU size {} KiB",
+            size_kb
+        );
+        Reu {
+            state: Rc::new(RefCell::new(State {
+                ram: vec![0; size_kb * 1024],
+                status: if size_kb > 128 { STATUS_SIZE } else { 0x00 },
+                command: COMMAND_NO_FF00,
+                c64_address: 0x0000,
+                reu_address: 0,
+                length: 0xffff,
+                interrupt_mask: 0x00,
+                address_control: 0x00,
+                reload: (0x0000, 0, 0xffff),
+                pending: false,
+            })),
+        }
+    }
+}
+
+impl State {
+    fn interrupt(&mut self) {
+        if self.interrupt_mask & MASK_ENABLE != 0
+            && self.status & self.interrupt_mask & (STATUS_END_OF_BLOCK | STATUS_VERIFY_ERROR) != 0
+        {
+            self.status |= STATUS_IRQ;
+        }
+    }
+}
+
+/// Runs the transfer programmed in `state`.
+///
+/// # Returns
+/// The cycles the CPU was stalled.
+fn run(state: &RefCell<State>, mem: &mut Memory) -> u32 {
+    let (kind, length, fix_c64, fix_reu): (u8, u32, bool, bool) = {
+        let state = state.borrow();
+        let length: u32 = match state.length {
+            0 => 0x10000,
+            length => length as u32,
+        };
+        (
+            state.command & COMMAND_TYPE,
+            length,
+            state.address_control & FIX_C64_ADDRESS != 0,
+            state.address_control & FIX_REU_ADDRESS != 0,
+        )
+    };
+
+    let mut cycles: u32 = 0;
+    let mut done: u32 = 0;
+    let mut verify_error: bool = false;
+    while done < length && !verify_error {
+        let (c64_address, reu_index): (u16, usize) = {
+            let state = state.borrow();
+            let index: usize = state.reu_address as usize % state.ram.len();
+            (state.c64_address, index)
+        };
+        // The state is not borrowed across bus accesses, they may reach the registers
+        match kind {
+            TYPE_TO_REU => {
+                let value: u8 = mem.read(c64_address);
+                state.borrow_mut().ram[reu_index] = value;
+            }
+            TYPE_FROM_REU => {
+                let value: u8 = state.borrow().ram[reu_index];
+                mem.write(c64_address, value);
+            }
+            TYPE_SWAP => {
+                let value: u8 = mem.read(c64_address);
+                let old: u8 = std::mem::replace(&mut state.borrow_mut().ram[reu_index], value);
+                mem.write(c64_address, old);
+                cycles += 1;
+            }
+            _ => verify_error = mem.read(c64_address) != state.borrow().ram[reu_index],
+        }
+        cycles += 1;
+        done += 1;
+
+        let mut state = state.borrow_mut();
+        if !fix_c64 {
+            state.c64_address = state.c64_address.wrapping_add(1);
+        }
+        if !fix_reu {
+            state.reu_address = (state.reu_address + 1) & 0x00ff_ffff;
+        }
+    }
+
+    let mut state = state.borrow_mut();
+    state.status |= if verify_error {
+        STATUS_VERIFY_ERROR
+    } else {
+        STATUS_END_OF_BLOCK
+    };
+    if state.command & COMMAND_AUTOLOAD != 0 {
+        (state.c64_address, state.reu_address, state.length) = state.reload;
+    } else {
+        // Like the chip, the length counts down to 1
+        state.length = (length - done).max(1) as u16;
+    }
+    state.command = (state.command & !COMMAND_EXECUTE) | COMMAND_NO_FF00;
+    state.interrupt();
+    cycles
+}
+
+impl Device for Reu {
+    fn read(&mut self, offset: u16) -> u8 {
+        let mut state = self.state.borrow_mut();
+        match offset & 0x1f {
+            STATUS => {
+                let value: u8 = state.status;
+                state.status &= !(STATUS_IRQ | STATUS_END_OF_BLOCK | STATUS_VERIFY_ERROR);
+                value
+            }
+            COMMAND => state.command,
+            C64_ADDRESS_LO => state.c64_address as u8,
+            C64_ADDRESS_HI => (state.c64_address >> 8) as u8,
+            REU_ADDRESS_LO => state.reu_address as u8,
+            REU_ADDRESS_HI => (state.reu_address >> 8) as u8,
+            // Unused bits read high
+            REU_BANK => (state.reu_address >> 16) as u8 | 0xf8,
+            LENGTH_LO => state.length as u8,
+            LENGTH_HI => (state.length >> 8) as u8,
+            INTERRUPT_MASK => state.interrupt_mask | 0x1f,
+            ADDRESS_CONTROL => state.address_control | 0x3f,
+            _ => 0xff,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let mut state = self.state.borrow_mut();
+        match offset & 0x1f {
+            COMMAND => {
+                state.command = value;
+                state.pending = value & COMMAND_EXECUTE != 0;
+            }
+            C64_ADDRESS_LO => state.c64_address = (state.c64_address & 0xff00) | value as u16,
+            C64_ADDRESS_HI => {
+                state.c64_address = (state.c64_address & 0x00ff) | ((value as u16) << 8)
+            }
+            REU_ADDRESS_LO => state.reu_address = (state.reu_address & !0xff) | value as u32,
+            REU_ADDRESS_HI => {
+                state.reu_address = (state.reu_address & !0xff00) | ((value as u32) << 8)
+            }
+            REU_BANK => {
+                state.reu_address = (state.reu_address & 0xffff) | (((value & 0x07) as u32) << 16)
+            }
+            LENGTH_LO => state.length = (state.length & 0xff00) | value as u16,
+            LENGTH_HI => state.length = (state.length & 0x00ff) | ((value as u16) << 8),
+            INTERRUPT_MASK => {
+                state.interrupt_mask = value & 0xe0;
+                state.interrupt();
+            }
+            ADDRESS_CONTROL => state.address_control = value & 0xc0,
+            // The status is read-only
+            _ => return,
+        }
+        if (C64_ADDRESS_LO..=LENGTH_HI).contains(&(offset & 0x1f)) {
+            state.reload = (state.c64_address, state.reu_address, state.length);
+        }
+    }
+
+    fn tick(&mut self, _cycles: u32) -> IrqState {
+        if self.state.borrow().status & STATUS_IRQ != 0 {
+            IrqState::IRQ
+        } else {
+            IrqState::NONE
+        }
+    }
+
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        let mut state = self.state.borrow_mut();
+        if !state.pending {
+            return None;
+        }
+        state.pending = false;
+        let shared: Rc<RefCell<State>> = self.state.clone();
+        Some(Box::new(move |mem: &mut Memory| run(&shared, mem)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stash_then_fetch_with_autoload() {
+        let mut mem: Memory = Memory::new();
+        let reu: Rc<RefCell<Reu>> = Rc::new(RefCell::new(Reu::new(512)));
+        mem.map_device(0xdf00, 0xdfff, reu.clone());
+        mem.write(0x1000, 0x11);
+        mem.write(0x1001, 0x22);
+
+        for (register, value) in [
+            (C64_ADDRESS_HI, 0x10),
+            (REU_BANK, 0x07),
+            (LENGTH_LO, 0x02),
+            (LENGTH_HI, 0x00),
+        ] {
+            mem.write(0xdf00 + register, value);
+        }
+        mem.write(
+            0xdf00 + COMMAND,
+            COMMAND_EXECUTE | COMMAND_AUTOLOAD | TYPE_TO_REU,
+        );
+        mem.tick_devices(4);
+
+        assert_eq!(mem.take_stall_cycles(), 2);
+        assert_eq!(reu.borrow().state.borrow().ram[0x70001], 0x22);
+        assert_eq!(mem.read(0xdf00 + STATUS), STATUS_END_OF_BLOCK | STATUS_SIZE);
+        assert_eq!(mem.read(0xdf00 + LENGTH_LO), 0x02);
+
+        // Fetch back to another address, the REU address was restored
+        mem.write(0xdf00 + C64_ADDRESS_HI, 0x20);
+        mem.write(0xdf00 + INTERRUPT_MASK, MASK_ENABLE | STATUS_END_OF_BLOCK);
+        mem.write(0xdf00 + COMMAND, COMMAND_EXECUTE | TYPE_FROM_REU);
+        assert_eq!(mem.tick_devices(4), IrqState::NONE);
+        assert_eq!(mem.tick_devices(1), IrqState::IRQ);
+
+        assert_eq!(mem.read(0x2000), 0x11);
+        assert_eq!(mem.read(0x2001), 0x22);
+        assert_eq!(mem.read(0xdf00 + C64_ADDRESS_LO), 0x02);
+        assert_eq!(mem.read(0xdf00 + STATUS) & STATUS_IRQ, STATUS_IRQ);
+    }
+}
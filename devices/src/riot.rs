@@ -0,0 +1,242 @@
+use memory::device::{Device, IrqState};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// I/O register offsets, the chip decodes the low 5 address bits.
+pub const DRA: u16 = 0x00;
+pub const DDRA: u16 = 0x01;
+pub const DRB: u16 = 0x02;
+pub const DDRB: u16 = 0x03;
+/// Reads the timer. Written, sets the PA7 edge detection, see `EDGE_*`.
+pub const TIMER: u16 = 0x04;
+/// Reads the interrupt flags, see `FLAG_*`.
+pub const INTERRUPT_FLAGS: u16 = 0x05;
+/// Writing `TIMER_1T + prescaler` loads the timer, `0` to `3` for 1, 8, 64 and 1024
+/// cycles per count. Adding `TIMER_IRQ` enables its interrupt.
+pub const TIMER_1T: u16 = 0x14;
+pub const TIMER_IRQ: u16 = 0x08;
+
+// Edge detection writes, added to `TIMER` while bit 4 is clear
+pub const EDGE_POSITIVE: u16 = 0x01;
+pub const EDGE_IRQ: u16 = 0x02;
+
+pub const FLAG_PA7: u8 = 0b0100_0000;
+pub const FLAG_TIMER: u8 = 0b1000_0000;
+
+const PRESCALERS: [u32; 4] = [1, 8, 64, 1024];
+
+/// A MOS 6532 RAM-I/O-Timer, as in the Atari 2600 and the KIM-1.
+///
+/// This is the I/O half of the chip, mapped over its registers. The 128 bytes of RAM
+/// are selected by a separate pin, so they are mapped as their own device, see `ram()`.
+pub struct Riot {
+    ram: [u8; 128],
+
+    dra: u8,
+    drb: u8,
+    ddra: u8,
+    ddrb: u8,
+    /// Levels driven onto the port pins from outside, high where nothing pulls them down.
+    pins_a: u8,
+    pins_b: u8,
+
+    timer: u8,
+    prescaler: u32,
+    /// Cycles until the timer counts down next.
+    countdown: u32,
+    /// After passing zero, the timer counts every cycle until read or written.
+    underflowed: bool,
+    timer_irq: bool,
+
+    edge_positive: bool,
+    edge_irq: bool,
+    flags: u8,
+}
+
+/// The RAM half of a `Riot`, see `Riot::ram()`.
+pub struct RiotRam(Rc<RefCell<Riot>>);
+
+impl Default for Riot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Riot {
+    pub fn new() -> Self {
+        Riot {
+            ram: [0; 128],
+            dra: 0x00,
+            drb: 0x00,
+            ddra: 0x00,
+            ddrb: 0x00,
+            pins_a: 0xff,
+            pins_b: 0xff,
+            timer: 0x00,
+            prescaler: PRESCALERS[3],
+            countdown: PRESCALERS[3],
+            underflowed: false,
+            timer_irq: false,
+            edge_positive: false,
+            edge_irq: false,
+            flags: 0x00,
+        }
+    }
+
+    /// # Returns
+    /// A device giving access to the RAM of `riot`, to map at the RAM addresses.
+    pub fn ram(riot: &Rc<RefCell<Riot>>) -> RiotRam {
+        RiotRam(riot.clone())
+    }
+
+    /// # Returns
+    /// The levels of the port A pins: register bits where the pin is an output,
+    /// the external level where it is an input.
+    pub fn port_a(&self) -> u8 {
+        (self.dra & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    /// # Returns
+    /// The levels of the port B pins, see `port_a()`.
+    pub fn port_b(&self) -> u8 {
+        (self.drb & self.ddrb) | (self.pins_b & !self.ddrb)
+    }
+
+    /// Sets the levels driven from outside onto the port A pins, flagging the PA7
+    /// interrupt on the selected edge.
+    pub fn set_port_a_input(&mut self, levels: u8) {
+        let old: bool = self.port_a() & 0x80 != 0;
+        self.pins_a = levels;
+        let new: bool = self.port_a() & 0x80 != 0;
+        if old != new && new == self.edge_positive {
+            self.flags |= FLAG_PA7;
+        }
+    }
+
+    /// Sets the levels driven from outside onto the port B pins.
+    pub fn set_port_b_input(&mut self, levels: u8) {
+        self.pins_b = levels;
+    }
+
+    fn tick_timer(&mut self) {
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+        if self.timer == 0 {
+            self.underflowed = true;
+            self.flags |= FLAG_TIMER;
+        }
+        self.timer = self.timer.wrapping_sub(1);
+        self.countdown = if self.underflowed { 1 } else { self.prescaler };
+    }
+}
+
+impl Device for Riot {
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset & 0x04 == 0 {
+            return match offset & 0x03 {
+                DRA => self.port_a(),
+                DDRA => self.ddra,
+                DRB => self.port_b(),
+                _ => self.ddrb,
+            };
+        }
+
+        if offset & 0x01 == 0 {
+            // Reading the timer acknowledges its interrupt and restores the prescaler
+            self.timer_irq = offset & TIMER_IRQ != 0;
+            self.flags &= !FLAG_TIMER;
+            if self.underflowed {
+                self.underflowed = false;
+                self.countdown = self.prescaler;
+            }
+            self.timer
+        } else {
+            let value: u8 = self.flags;
+            self.flags &= !FLAG_PA7;
+            value
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset & 0x04 == 0 {
+            match offset & 0x03 {
+                DRA => self.dra = value,
+                DDRA => self.ddra = value,
+                DRB => self.drb = value,
+                _ => self.ddrb = value,
+            }
+        } else if offset & 0x10 != 0 {
+            self.prescaler = PRESCALERS[(offset & 0x03) as usize];
+            self.timer_irq = offset & TIMER_IRQ != 0;
+            self.timer = value;
+            self.countdown = self.prescaler;
+            self.underflowed = false;
+            self.flags &= !FLAG_TIMER;
+        } else {
+            self.edge_positive = offset & EDGE_POSITIVE != 0;
+            self.edge_irq = offset & EDGE_IRQ != 0;
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        for _ in 0..cycles {
+            self.tick_timer();
+        }
+
+        let timer: bool = self.timer_irq && self.flags & FLAG_TIMER != 0;
+        let pa7: bool = self.edge_irq && self.flags & FLAG_PA7 != 0;
+        IrqState {
+            irq: timer || pa7,
+            nmi: false,
+        }
+    }
+}
+
+impl Device for RiotRam {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.0.borrow().ram[(offset & 0x7f) as usize]
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.0.borrow_mut().ram[(offset & 0x7f) as usize] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_counts_with_prescaler_then_every_cycle() {
+        let mut riot = Riot::new();
+        // 8 cycles per count, interrupt enabled
+        riot.write(TIMER_1T + 1 + TIMER_IRQ, 2);
+
+        riot.tick(8);
+        assert_eq!(riot.read(TIMER + TIMER_IRQ), 1);
+        assert!(!riot.tick(8).irq);
+        assert!(riot.tick(8).irq);
+        assert_eq!(riot.read(INTERRUPT_FLAGS), FLAG_TIMER);
+
+        // Reading acknowledges it and restores the prescaler
+        assert_eq!(riot.read(TIMER + TIMER_IRQ), 0xff);
+        assert!(!riot.tick(1).irq);
+        assert_eq!(riot.read(TIMER + TIMER_IRQ), 0xff);
+        riot.tick(8);
+        assert_eq!(riot.read(TIMER + TIMER_IRQ), 0xfe);
+    }
+
+    #[test]
+    fn ram_is_shared_with_the_chip() {
+        let riot: Rc<RefCell<Riot>> = Rc::new(RefCell::new(Riot::new()));
+        let mut ram: RiotRam = Riot::ram(&riot);
+
+        ram.write(0x80 + 0x05, 0x42);
+
+        assert_eq!(ram.read(0x05), 0x42);
+        assert_eq!(riot.borrow().ram[0x05], 0x42);
+    }
+}
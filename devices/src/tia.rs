@@ -0,0 +1,171 @@
+use memory::device::{BusJob, Device, IrqState};
+
+// Write register offsets, the chip decodes the low 6 address bits when written.
+/// Bit 1 starts and ends the vertical sync, see `Tia::frames()`.
+pub const VSYNC: u16 = 0x00;
+pub const VBLANK: u16 = 0x01;
+/// Writing any value halts the CPU until the start of the next scanline.
+pub const WSYNC: u16 = 0x02;
+/// Writing any value restarts the scanline.
+pub const RSYNC: u16 = 0x03;
+pub const COLUP0: u16 = 0x06;
+pub const COLUP1: u16 = 0x07;
+pub const COLUPF: u16 = 0x08;
+pub const COLUBK: u16 = 0x09;
+pub const CTRLPF: u16 = 0x0a;
+pub const PF0: u16 = 0x0d;
+pub const PF1: u16 = 0x0e;
+pub const PF2: u16 = 0x0f;
+
+// Read register offsets, the chip decodes the low 4 address bits when read.
+/// The fire buttons of the joysticks, bit 7 low while pressed.
+pub const INPT4: u16 = 0x0c;
+pub const INPT5: u16 = 0x0d;
+
+/// In VSYNC and VBLANK: the signal is on.
+const SIGNAL_ON: u8 = 0b0000_0010;
+
+/// NTSC timing, 228 color clocks of 3 per CPU cycle.
+pub const CYCLES_PER_LINE: u32 = 76;
+pub const COLOR_CLOCKS_PER_CYCLE: u32 = 3;
+
+/// A register-level stub of the Atari TIA: the registers are stored and the beam is
+/// timed, so kernels run with the right timing and can be traced scanline by scanline.
+///
+/// Emulated: WSYNC halting the CPU on its RDY line until the end of the scanline,
+/// RSYNC, and counting scanlines and frames from the vertical sync.
+/// Not emulated: drawing anything, collisions (always clear), the paddles, sound, and
+/// the fire buttons, which read as released.
+pub struct Tia {
+    registers: [u8; 0x40],
+    /// CPU cycles into the current scanline.
+    line_cycles: u32,
+    scanline: u32,
+    frames: u64,
+    /// WSYNC was written, the CPU is stalled once the current bus cycle ends.
+    wsync: bool,
+}
+
+impl Default for Tia {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tia {
+    pub fn new() -> Self {
+        Tia {
+            registers: [0; 0x40],
+            line_cycles: 0,
+            scanline: 0,
+            frames: 0,
+            wsync: false,
+        }
+    }
+
+    /// # Returns
+    /// The scanlines started since the vertical sync was last turned on, which the
+    /// kernel uses as the top of the frame.
+    pub fn scanline(&self) -> u32 {
+        self.scanline
+    }
+
+    /// # Returns
+    /// The color clock of the beam in the current scanline, `0` to `227`, 68 of them
+    /// in the horizontal blank.
+    pub fn color_clock(&self) -> u32 {
+        self.line_cycles * COLOR_CLOCKS_PER_CYCLE
+    }
+
+    /// # Returns
+    /// The number of times the vertical sync was turned on.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// # Returns
+    /// Whether the kernel blanks the beam, as it does outside the visible picture.
+    pub fn vblank(&self) -> bool {
+        self.registers[VBLANK as usize] & SIGNAL_ON != 0
+    }
+
+    /// # Returns
+    /// The last value written to write register `register`.
+    pub fn register(&self, register: u16) -> u8 {
+        self.registers[(register & 0x3f) as usize]
+    }
+}
+
+impl Device for Tia {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x0f {
+            INPT4 | INPT5 => 0x80,
+            _ => 0x00,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let register: u16 = offset & 0x3f;
+        let was_on: bool = self.registers[register as usize] & SIGNAL_ON != 0;
+        match register {
+            VSYNC if value & SIGNAL_ON != 0 && !was_on => {
+                self.frames += 1;
+                self.scanline = 0;
+            }
+            WSYNC => self.wsync = true,
+            RSYNC => self.line_cycles = 0,
+            _ => {}
+        }
+        self.registers[register as usize] = value;
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.line_cycles += cycles;
+        while self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            self.scanline += 1;
+        }
+        IrqState::NONE
+    }
+
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        if !std::mem::take(&mut self.wsync) || self.line_cycles == 0 {
+            return None;
+        }
+        // The other devices keep running while the CPU waits, the TIA included
+        let remaining: u32 = CYCLES_PER_LINE - self.line_cycles;
+        Some(Box::new(move |_| remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use memory::Memory;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn wsync_stalls_to_next_scanline() {
+        let tia: Rc<RefCell<Tia>> = Rc::new(RefCell::new(Tia::new()));
+        let mut mem: Memory = Memory::new();
+        mem.map_device(0x0000, 0x007f, tia.clone());
+
+        mem.write(VSYNC, SIGNAL_ON);
+        mem.tick_devices(3 * CYCLES_PER_LINE);
+        mem.write(VSYNC, 0x00);
+        assert_eq!(tia.borrow().frames(), 1);
+        assert_eq!(tia.borrow().scanline(), 3);
+
+        // STA WSYNC, 3 cycles into the line
+        mem.write(WSYNC, 0x00);
+        mem.tick_devices(3);
+        assert_eq!(mem.take_stall_cycles(), CYCLES_PER_LINE - 3);
+        mem.tick_devices(CYCLES_PER_LINE - 3);
+        assert_eq!(mem.take_stall_cycles(), 0);
+        assert_eq!(tia.borrow().scanline(), 4);
+        assert_eq!(tia.borrow().color_clock(), 0);
+    }
+}
@@ -0,0 +1,130 @@
+use memory::device::{Device, IrqState};
+
+// Register offsets, the device decodes the low 2 address bits.
+/// Low byte of the period, in CPU cycles. `0` is a period of 65536 cycles.
+pub const PERIOD_LO: u16 = 0x0;
+pub const PERIOD_HI: u16 = 0x1;
+/// Written with `CONTROL_ENABLE`, (re)starts the timer from the period, see `CONTROL_*`.
+pub const CONTROL: u16 = 0x2;
+/// Bit 7 is set once the timer fired. Reading it acknowledges the interrupt.
+pub const STATUS: u16 = 0x3;
+
+pub const CONTROL_ENABLE: u8 = 0b0000_0001;
+/// Restart from the period after firing instead of stopping.
+pub const CONTROL_REPEAT: u8 = 0b0000_0010;
+/// Interrupt through NMI instead of IRQ.
+pub const CONTROL_NMI: u8 = 0b0000_0100;
+
+pub const STATUS_FIRED: u8 = 0b1000_0000;
+
+/// A programmable interval timer that fires an interrupt every period, for testing
+/// interrupt handlers and for minimal custom machines. It is not modeled after any
+/// chip, which keeps it the simplest complete `Device`.
+pub struct Timer {
+    period: u16,
+    control: u8,
+    /// Cycles until the timer fires next.
+    remaining: u32,
+    fired: bool,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    /// Creates a stopped timer.
+    pub fn new() -> Self {
+        Timer {
+            period: 0,
+            control: 0x00,
+            remaining: 0,
+            fired: false,
+        }
+    }
+
+    fn period_cycles(&self) -> u32 {
+        match self.period {
+            0 => 0x10000,
+            period => period as u32,
+        }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x03 {
+            PERIOD_LO => self.period as u8,
+            PERIOD_HI => (self.period >> 8) as u8,
+            CONTROL => self.control,
+            _ => {
+                let value: u8 = if self.fired { STATUS_FIRED } else { 0x00 };
+                self.fired = false;
+                value
+            }
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x03 {
+            PERIOD_LO => self.period = (self.period & 0xff00) | value as u16,
+            PERIOD_HI => self.period = (self.period & 0x00ff) | ((value as u16) << 8),
+            CONTROL => {
+                self.control = value;
+                self.remaining = self.period_cycles();
+            }
+            // The status is read-only
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, mut cycles: u32) -> IrqState {
+        // A period may end several times within a long instruction or `cycles` batch
+        while self.control & CONTROL_ENABLE != 0 && cycles >= self.remaining {
+            cycles -= self.remaining;
+            self.fired = true;
+            if self.control & CONTROL_REPEAT != 0 {
+                self.remaining = self.period_cycles();
+            } else {
+                self.control &= !CONTROL_ENABLE;
+            }
+        }
+        if self.control & CONTROL_ENABLE != 0 {
+            self.remaining -= cycles;
+        }
+
+        match (self.fired, self.control & CONTROL_NMI != 0) {
+            (false, _) => IrqState::NONE,
+            (true, false) => IrqState::IRQ,
+            (true, true) => IrqState::NMI,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_until_stopped() {
+        let mut timer: Timer = Timer::new();
+        timer.write(PERIOD_LO, 100);
+        timer.write(CONTROL, CONTROL_ENABLE | CONTROL_REPEAT);
+
+        assert_eq!(timer.tick(99), IrqState::NONE);
+        assert_eq!(timer.tick(1), IrqState::IRQ);
+        assert_eq!(timer.read(STATUS), STATUS_FIRED);
+        assert_eq!(timer.tick(99), IrqState::NONE);
+        assert_eq!(timer.tick(1), IrqState::IRQ);
+        timer.read(STATUS);
+
+        // One-shot on NMI
+        timer.write(CONTROL, CONTROL_ENABLE | CONTROL_NMI);
+        assert_eq!(timer.tick(250), IrqState::NMI);
+        assert_eq!(timer.read(STATUS), STATUS_FIRED);
+        assert_eq!(timer.tick(250), IrqState::NONE);
+        assert_eq!(timer.read(CONTROL), CONTROL_NMI);
+    }
+}
@@ -0,0 +1,442 @@
+use memory::device::{Device, IrqState};
+
+// Register offsets, the chip decodes the low 4 address bits.
+pub const ORB: u16 = 0x0;
+pub const ORA: u16 = 0x1;
+pub const DDRB: u16 = 0x2;
+pub const DDRA: u16 = 0x3;
+pub const T1C_L: u16 = 0x4;
+pub const T1C_H: u16 = 0x5;
+pub const T1L_L: u16 = 0x6;
+pub const T1L_H: u16 = 0x7;
+pub const T2C_L: u16 = 0x8;
+pub const T2C_H: u16 = 0x9;
+pub const SR: u16 = 0xa;
+pub const ACR: u16 = 0xb;
+pub const PCR: u16 = 0xc;
+pub const IFR: u16 = 0xd;
+pub const IER: u16 = 0xe;
+/// Port A without the CA1/CA2 handshake side effects.
+pub const ORA_NO_HANDSHAKE: u16 = 0xf;
+
+// Interrupt flag bits, shared by IFR and IER
+pub const IRQ_CA2: u8 = 0b0000_0001;
+pub const IRQ_CA1: u8 = 0b0000_0010;
+pub const IRQ_SR: u8 = 0b0000_0100;
+pub const IRQ_CB2: u8 = 0b0000_1000;
+pub const IRQ_CB1: u8 = 0b0001_0000;
+pub const IRQ_T2: u8 = 0b0010_0000;
+pub const IRQ_T1: u8 = 0b0100_0000;
+/// Set in IFR while any enabled interrupt is flagged. In IER writes, set to enable.
+pub const IRQ_ANY: u8 = 0b1000_0000;
+
+const ACR_T1_FREE_RUN: u8 = 0b0100_0000;
+const ACR_T2_PULSE_COUNT: u8 = 0b0010_0000;
+const ACR_SR_MODE_SHIFT: u8 = 2;
+const PCR_CA1_POSITIVE_EDGE: u8 = 0b0000_0001;
+const PCR_CB1_POSITIVE_EDGE: u8 = 0b0001_0000;
+
+/// A MOS 6522 Versatile Interface Adapter.
+///
+/// Emulated: both ports with their data direction registers, CA1/CB1 edge interrupts,
+/// timer 1 in one-shot and free-running mode, timer 2 in one-shot mode and the shift
+/// register clocked by timer 2 or the system clock.
+/// Not emulated: PB7 timer output, timer 2 pulse counting, CA2/CB2 handshake modes,
+/// input latching and shifting clocked by an external CB1.
+pub struct Via {
+    ora: u8,
+    orb: u8,
+    ddra: u8,
+    ddrb: u8,
+    /// Levels driven onto the port pins from outside, high where nothing pulls them down.
+    pins_a: u8,
+    pins_b: u8,
+    ca1: bool,
+    cb1: bool,
+
+    /// Counters can hold `0x10000` for the extra cycle a free-running reload takes.
+    t1_counter: u32,
+    t1_latch: u16,
+    /// Timer 1 interrupts at the next underflow. One-shot mode disarms it until reloaded.
+    t1_armed: bool,
+    t2_counter: u32,
+    t2_latch_low: u8,
+    t2_armed: bool,
+
+    sr: u8,
+    /// Bits left to shift in the current byte, `0` when idle.
+    sr_bits: u8,
+    /// Cycles until the next bit is shifted.
+    sr_countdown: u32,
+    /// Every byte shifted out so far, for the host to pick up, see `take_shifted_out()`.
+    shifted_out: Vec<u8>,
+    /// Level of CB2 shifted in, bit by bit.
+    cb2_in: bool,
+
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+}
+
+impl Default for Via {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Via {
+    /// Creates a VIA in its power-on state: all pins inputs, timers and interrupts off.
+    pub fn new() -> Self {
+        Via {
+            ora: 0x00,
+            orb: 0x00,
+            ddra: 0x00,
+            ddrb: 0x00,
+            pins_a: 0xff,
+            pins_b: 0xff,
+            ca1: true,
+            cb1: true,
+            t1_counter: 0xffff,
+            t1_latch: 0xffff,
+            t1_armed: false,
+            t2_counter: 0xffff,
+            t2_latch_low: 0xff,
+            t2_armed: false,
+            sr: 0x00,
+            sr_bits: 0,
+            sr_countdown: 0,
+            shifted_out: Vec::new(),
+            cb2_in: true,
+            acr: 0x00,
+            pcr: 0x00,
+            ifr: 0x00,
+            ier: 0x00,
+        }
+    }
+
+    /// # Returns
+    /// The levels of the port A pins: output register bits where the pin is an output,
+    /// the external level where it is an input.
+    pub fn port_a(&self) -> u8 {
+        (self.ora & self.ddra) | (self.pins_a & !self.ddra)
+    }
+
+    /// # Returns
+    /// The levels of the port B pins, see `port_a()`.
+    pub fn port_b(&self) -> u8 {
+        (self.orb & self.ddrb) | (self.pins_b & !self.ddrb)
+    }
+
+    /// Sets the levels driven from outside onto the port A pins.
+    /// Only the pins configured as inputs are affected.
+    pub fn set_port_a_input(&mut self, levels: u8) {
+        self.pins_a = levels;
+    }
+
+    /// Sets the levels driven from outside onto the port B pins.
+    pub fn set_port_b_input(&mut self, levels: u8) {
+        self.pins_b = levels;
+    }
+
+    /// Sets the level of the CA1 input, flagging an interrupt on the edge selected in PCR.
+    pub fn set_ca1(&mut self, level: bool) {
+        if Self::is_active_edge(self.ca1, level, self.pcr & PCR_CA1_POSITIVE_EDGE != 0) {
+            self.ifr |= IRQ_CA1;
+        }
+        self.ca1 = level;
+    }
+
+    /// Sets the level of the CB1 input, flagging an interrupt on the edge selected in PCR.
+    pub fn set_cb1(&mut self, level: bool) {
+        if Self::is_active_edge(self.cb1, level, self.pcr & PCR_CB1_POSITIVE_EDGE != 0) {
+            self.ifr |= IRQ_CB1;
+        }
+        self.cb1 = level;
+    }
+
+    /// Sets the level of CB2, sampled when shifting in.
+    pub fn set_cb2(&mut self, level: bool) {
+        self.cb2_in = level;
+    }
+
+    /// # Returns
+    /// The bytes shifted out since the last call, oldest first.
+    pub fn take_shifted_out(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.shifted_out)
+    }
+
+    fn is_active_edge(old: bool, new: bool, positive: bool) -> bool {
+        if positive {
+            !old && new
+        } else {
+            old && !new
+        }
+    }
+
+    fn read_ifr(&self) -> u8 {
+        if self.ifr & self.ier & !IRQ_ANY != 0 {
+            self.ifr | IRQ_ANY
+        } else {
+            self.ifr
+        }
+    }
+
+    /// Shift register mode, bits 2-4 of ACR.
+    /// `0` disabled, `1`-`3` shift in, `4`-`7` shift out; `3` and `7` use an external clock.
+    fn sr_mode(&self) -> u8 {
+        (self.acr >> ACR_SR_MODE_SHIFT) & 0b111
+    }
+
+    /// # Returns
+    /// The number of cycles between two shifted bits, `None` if clocked from outside.
+    fn sr_bit_cycles(&self) -> Option<u32> {
+        match self.sr_mode() {
+            // CB1 toggles once every T2 time-out, so a bit every two of them
+            1 | 4 | 5 => Some(2 * (self.t2_latch_low as u32 + 2)),
+            // CB1 toggles every cycle
+            2 | 6 => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Starts shifting a byte after SR is accessed.
+    fn start_shift(&mut self) {
+        self.ifr &= !IRQ_SR;
+        match self.sr_bit_cycles() {
+            Some(cycles) => {
+                self.sr_bits = 8;
+                self.sr_countdown = cycles;
+            }
+            None => self.sr_bits = 0,
+        }
+    }
+
+    fn tick_shift_register(&mut self, cycles: u32) {
+        let bit_cycles: u32 = match self.sr_bit_cycles() {
+            Some(bit_cycles) => bit_cycles,
+            None => return,
+        };
+        let mut remaining: u32 = cycles;
+        while self.sr_bits > 0 && remaining >= self.sr_countdown {
+            remaining -= self.sr_countdown;
+            self.sr_countdown = bit_cycles;
+
+            if self.sr_mode() < 4 {
+                self.sr = (self.sr << 1) | self.cb2_in as u8;
+            } else {
+                self.sr = self.sr.rotate_left(1);
+            }
+            self.sr_bits -= 1;
+
+            if self.sr_bits == 0 {
+                if self.sr_mode() >= 4 {
+                    self.shifted_out.push(self.sr);
+                }
+                if self.sr_mode() == 4 {
+                    // Free-running output recirculates the same byte forever
+                    self.sr_bits = 8;
+                } else {
+                    self.ifr |= IRQ_SR;
+                }
+            }
+        }
+        if self.sr_bits > 0 {
+            self.sr_countdown -= remaining;
+        }
+    }
+
+    fn tick_t1(&mut self, cycles: u32) {
+        let mut remaining: u32 = cycles;
+        while remaining > self.t1_counter {
+            // Counts down through 0 to 0xffff, which is when the interrupt is flagged
+            remaining -= self.t1_counter + 1;
+            if self.t1_armed {
+                self.ifr |= IRQ_T1;
+            }
+            if self.acr & ACR_T1_FREE_RUN != 0 {
+                // Spends one cycle at 0xffff before the reload, a period of N + 2 cycles
+                self.t1_counter = self.t1_latch as u32 + 1;
+            } else {
+                self.t1_armed = false;
+                self.t1_counter = 0xffff;
+            }
+        }
+        self.t1_counter -= remaining;
+    }
+
+    fn tick_t2(&mut self, cycles: u32) {
+        if self.acr & ACR_T2_PULSE_COUNT != 0 {
+            return;
+        }
+        let mut remaining: u32 = cycles;
+        while remaining > self.t2_counter {
+            remaining -= self.t2_counter + 1;
+            if self.t2_armed {
+                self.ifr |= IRQ_T2;
+                self.t2_armed = false;
+            }
+            self.t2_counter = 0xffff;
+        }
+        self.t2_counter -= remaining;
+    }
+}
+
+impl Device for Via {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x0f {
+            ORB => {
+                self.ifr &= !(IRQ_CB1 | IRQ_CB2);
+                self.port_b()
+            }
+            ORA => {
+                self.ifr &= !(IRQ_CA1 | IRQ_CA2);
+                self.port_a()
+            }
+            DDRB => self.ddrb,
+            DDRA => self.ddra,
+            T1C_L => {
+                self.ifr &= !IRQ_T1;
+                self.t1_counter as u8
+            }
+            T1C_H => (self.t1_counter >> 8) as u8,
+            T1L_L => self.t1_latch as u8,
+            T1L_H => (self.t1_latch >> 8) as u8,
+            T2C_L => {
+                self.ifr &= !IRQ_T2;
+                self.t2_counter as u8
+            }
+            T2C_H => (self.t2_counter >> 8) as u8,
+            SR => {
+                let value: u8 = self.sr;
+                self.start_shift();
+                value
+            }
+            ACR => self.acr,
+            PCR => self.pcr,
+            IFR => self.read_ifr(),
+            IER => self.ier | IRQ_ANY,
+            _ => self.port_a(),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x0f {
+            ORB => {
+                self.ifr &= !(IRQ_CB1 | IRQ_CB2);
+                self.orb = value;
+            }
+            ORA => {
+                self.ifr &= !(IRQ_CA1 | IRQ_CA2);
+                self.ora = value;
+            }
+            DDRB => self.ddrb = value,
+            DDRA => self.ddra = value,
+            T1C_L | T1L_L => self.t1_latch = (self.t1_latch & 0xff00) | value as u16,
+            T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | (value as u16) << 8;
+                self.t1_counter = self.t1_latch as u32;
+                self.t1_armed = true;
+                self.ifr &= !IRQ_T1;
+            }
+            T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | (value as u16) << 8;
+                self.ifr &= !IRQ_T1;
+            }
+            T2C_L => self.t2_latch_low = value,
+            T2C_H => {
+                self.t2_counter = (value as u32) << 8 | self.t2_latch_low as u32;
+                self.t2_armed = true;
+                self.ifr &= !IRQ_T2;
+            }
+            SR => {
+                self.sr = value;
+                self.start_shift();
+            }
+            ACR => self.acr = value,
+            PCR => self.pcr = value,
+            // Writing a 1 clears the flag
+            IFR => self.ifr &= !value,
+            IER => {
+                if value & IRQ_ANY != 0 {
+                    self.ier |= value & !IRQ_ANY;
+                } else {
+                    self.ier &= !value;
+                }
+            }
+            _ => self.ora = value,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.tick_t1(cycles);
+        self.tick_t2(cycles);
+        self.tick_shift_register(cycles);
+        IrqState {
+            irq: self.ifr & self.ier & !IRQ_ANY != 0,
+            nmi: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_mix_outputs_and_inputs() {
+        let mut via = Via::new();
+        via.write(DDRA, 0xf0);
+        via.write(ORA, 0xaa);
+        via.set_port_a_input(0x05);
+
+        assert_eq!(via.read(ORA), 0xa5);
+        assert_eq!(via.port_a(), 0xa5);
+        assert_eq!(via.port_b(), 0xff);
+    }
+
+    #[test]
+    fn t1_one_shot_interrupts_once() {
+        let mut via = Via::new();
+        via.write(IER, IRQ_ANY | IRQ_T1);
+        via.write(T1C_L, 0x10);
+        via.write(T1C_H, 0x00);
+
+        assert!(!via.tick(0x10).irq);
+        assert!(via.tick(1).irq);
+        assert_eq!(via.read(IFR), IRQ_ANY | IRQ_T1);
+
+        via.read(T1C_L);
+        assert!(!via.tick(0x20000).irq);
+    }
+
+    #[test]
+    fn t1_free_run_period() {
+        let mut via = Via::new();
+        via.write(ACR, ACR_T1_FREE_RUN);
+        via.write(T1C_L, 0x08);
+        via.write(T1C_H, 0x00);
+        via.tick(0x09);
+        assert_eq!(via.read(IFR) & IRQ_T1, IRQ_T1);
+        via.write(IFR, IRQ_T1);
+
+        // Period is latch + 2
+        via.tick(0x09);
+        assert_eq!(via.read(IFR) & IRQ_T1, 0);
+        via.tick(1);
+        assert_eq!(via.read(IFR) & IRQ_T1, IRQ_T1);
+    }
+
+    #[test]
+    fn shift_out_under_system_clock() {
+        let mut via = Via::new();
+        via.write(ACR, 6 << ACR_SR_MODE_SHIFT);
+        via.write(SR, 0x81);
+
+        via.tick(15);
+        assert_eq!(via.read(IFR) & IRQ_SR, 0);
+        via.tick(1);
+        assert_eq!(via.read(IFR) & IRQ_SR, IRQ_SR);
+        assert_eq!(via.take_shifted_out(), vec![0x81]);
+    }
+}
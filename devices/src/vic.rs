@@ -0,0 +1,376 @@
+use crate::video::FrameBuffer;
+
+use memory::device::{Device, IrqState};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Register offsets, the chip decodes the low 6 address bits.
+pub const RASTER: u16 = 0x12;
+pub const CONTROL_1: u16 = 0x11;
+pub const CONTROL_2: u16 = 0x16;
+pub const MEMORY_POINTERS: u16 = 0x18;
+pub const INTERRUPT: u16 = 0x19;
+pub const INTERRUPT_ENABLE: u16 = 0x1a;
+pub const BORDER_COLOR: u16 = 0x20;
+pub const BACKGROUND_COLOR_0: u16 = 0x21;
+pub const BACKGROUND_COLOR_1: u16 = 0x22;
+pub const BACKGROUND_COLOR_2: u16 = 0x23;
+
+/// In CONTROL_1: bit 8 of the raster line.
+const CONTROL_1_RASTER_8: u8 = 0b1000_0000;
+const CONTROL_1_ECM: u8 = 0b0100_0000;
+const CONTROL_1_BMM: u8 = 0b0010_0000;
+const CONTROL_1_DEN: u8 = 0b0001_0000;
+/// In CONTROL_1: 25 rows instead of 24.
+const CONTROL_1_RSEL: u8 = 0b0000_1000;
+/// In CONTROL_2: multicolor characters.
+const CONTROL_2_MCM: u8 = 0b0001_0000;
+/// In CONTROL_2: 40 columns instead of 38.
+const CONTROL_2_CSEL: u8 = 0b0000_1000;
+const IRQ_RASTER: u8 = 0b0000_0001;
+
+/// Size of the rendered frame: the 320x200 text window and the visible border around it.
+pub const WIDTH: usize = 384;
+pub const HEIGHT: usize = 272;
+const WINDOW_X: usize = 32;
+const WINDOW_Y: usize = 36;
+
+/// PAL timing.
+const CYCLES_PER_LINE: u32 = 63;
+const LINES: u16 = 312;
+
+pub const COLUMNS: usize = 40;
+pub const ROWS: usize = 25;
+
+/// The 16 colors as RGBA.
+pub const PALETTE: [[u8; 4]; 16] = [
+    [0x00, 0x00, 0x00, 0xff],
+    [0xff, 0xff, 0xff, 0xff],
+    [0x68, 0x37, 0x2b, 0xff],
+    [0x70, 0xa4, 0xb2, 0xff],
+    [0x6f, 0x3d, 0x86, 0xff],
+    [0x58, 0x8d, 0x43, 0xff],
+    [0x35, 0x28, 0x79, 0xff],
+    [0xb8, 0xc7, 0x6f, 0xff],
+    [0x6f, 0x4f, 0x25, 0xff],
+    [0x43, 0x39, 0x00, 0xff],
+    [0x9a, 0x67, 0x59, 0xff],
+    [0x44, 0x44, 0x44, 0xff],
+    [0x6c, 0x6c, 0x6c, 0xff],
+    [0x9a, 0xd2, 0x84, 0xff],
+    [0x6c, 0x5e, 0xb5, 0xff],
+    [0x95, 0x95, 0x95, 0xff],
+];
+
+/// A MOS 6569 VIC-II (PAL), rendering character modes.
+///
+/// Emulated: standard, multicolor and extended background color text, the 24/25 row
+/// and 38/40 column windows, fine scrolling, border and background colors, and the
+/// raster counter with its interrupt.
+/// Not emulated: bitmap modes (drawn as background), sprites, light pen, bad lines
+/// stealing CPU cycles and changes of registers in the middle of a frame.
+pub struct Vic {
+    registers: [u8; 0x40],
+    /// The 1 KiB x 4 bit color RAM, mapped separately, see `color_ram()`.
+    color_ram: [u8; 0x400],
+    /// The 4 KiB character generator ROM, seen by the VIC in banks 0 and 2.
+    char_rom: Option<Vec<u8>>,
+
+    raster: u16,
+    line_cycles: u32,
+    raster_compare: u16,
+    irq_flags: u8,
+}
+
+/// The color RAM of a `Vic`, see `Vic::color_ram()`.
+pub struct ColorRam(Rc<RefCell<Vic>>);
+
+impl Default for Vic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vic {
+    pub fn new() -> Self {
+        Vic {
+            registers: [0; 0x40],
+            color_ram: [0; 0x400],
+            char_rom: None,
+            raster: 0,
+            line_cycles: 0,
+            raster_compare: 0,
+            irq_flags: 0x00,
+        }
+    }
+
+    /// Sets the character generator ROM. Without one, characters in ROM are blank.
+    pub fn set_char_rom(&mut self, char_rom: Vec<u8>) {
+        self.char_rom = Some(char_rom);
+    }
+
+    /// # Returns
+    /// A device giving access to the color RAM of `vic`, to map at `0xd800`.
+    pub fn color_ram(vic: &Rc<RefCell<Vic>>) -> ColorRam {
+        ColorRam(vic.clone())
+    }
+
+    /// # Returns
+    /// The current raster line.
+    pub fn raster(&self) -> u16 {
+        self.raster
+    }
+
+    /// Reads a byte the way the VIC sees memory, through the 16 KiB window of `bank`.
+    fn fetch(&self, ram: &[u8], bank: u8, offset: u16) -> u8 {
+        let offset: u16 = offset & 0x3fff;
+        if bank & 1 == 0 && (0x1000..0x2000).contains(&offset) {
+            return match &self.char_rom {
+                Some(rom) => rom[(offset - 0x1000) as usize % rom.len()],
+                None => 0x00,
+            };
+        }
+        ram[bank as usize * 0x4000 + offset as usize]
+    }
+
+    fn color(&self, register: u16) -> [u8; 4] {
+        PALETTE[(self.registers[register as usize] & 0x0f) as usize]
+    }
+
+    /// # Returns
+    /// The color of the pixel at `x`, `y` of the 320x200 text window.
+    fn text_pixel(&self, ram: &[u8], bank: u8, x: usize, y: usize) -> [u8; 4] {
+        let control_1: u8 = self.registers[CONTROL_1 as usize];
+        let control_2: u8 = self.registers[CONTROL_2 as usize];
+        let pointers: u8 = self.registers[MEMORY_POINTERS as usize];
+        let background: [u8; 4] = self.color(BACKGROUND_COLOR_0);
+
+        // The default vertical scroll is 3, the text is not shifted then
+        let y_scroll: usize = (control_1 & 0x07) as usize;
+        let x_scroll: usize = (control_2 & 0x07) as usize;
+        if y + 3 < y_scroll || x < x_scroll || control_1 & CONTROL_1_BMM != 0 {
+            return background;
+        }
+        let text_y: usize = y + 3 - y_scroll;
+        let text_x: usize = x - x_scroll;
+        let (row, column): (usize, usize) = (text_y / 8, text_x / 8);
+        if row >= ROWS || column >= COLUMNS {
+            return background;
+        }
+
+        let cell: usize = row * COLUMNS + column;
+        let screen: u16 = (pointers >> 4) as u16 * 0x0400;
+        let chars: u16 = ((pointers >> 1) & 0x07) as u16 * 0x0800;
+        let mut code: u8 = self.fetch(ram, bank, screen + cell as u16);
+        let color: u8 = self.color_ram[cell] & 0x0f;
+
+        let mut background: [u8; 4] = background;
+        let ecm: bool = control_1 & CONTROL_1_ECM != 0;
+        let mcm: bool = control_2 & CONTROL_2_MCM != 0;
+        if ecm {
+            if mcm {
+                // Invalid mode, the chip outputs black
+                return PALETTE[0];
+            }
+            background = self.color(BACKGROUND_COLOR_0 + (code >> 6) as u16);
+            code &= 0x3f;
+        }
+
+        let glyph: u8 = self.fetch(ram, bank, chars + code as u16 * 8 + (text_y % 8) as u16);
+        let bit: usize = text_x % 8;
+        if mcm && color & 0x08 != 0 {
+            // Two bits per pixel, each pair twice as wide
+            match (glyph >> (6 - (bit & 0b110))) & 0b11 {
+                0b00 => background,
+                0b01 => self.color(BACKGROUND_COLOR_1),
+                0b10 => self.color(BACKGROUND_COLOR_2),
+                _ => PALETTE[(color & 0x07) as usize],
+            }
+        } else if glyph & (0x80 >> bit) != 0 {
+            PALETTE[color as usize]
+        } else {
+            background
+        }
+    }
+
+    /// Renders the current screen into `frame`, which must be `WIDTH` x `HEIGHT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ram` - The whole 64 KiB of RAM, as the VIC sees it without the I/O area.
+    /// * `bank` - The 16 KiB bank the VIC looks at, `0` to `3`. On the C64 it comes
+    ///   from the CIA2 port A.
+    /// * `frame` - The frame buffer to draw into.
+    pub fn render(&self, ram: &[u8], bank: u8, frame: &mut FrameBuffer) {
+        let control_1: u8 = self.registers[CONTROL_1 as usize];
+        let control_2: u8 = self.registers[CONTROL_2 as usize];
+        let border: [u8; 4] = self.color(BORDER_COLOR);
+
+        // 24 rows and 38 columns hide the edges of the window under the border
+        let (top, bottom): (usize, usize) = if control_1 & CONTROL_1_RSEL != 0 {
+            (0, 200)
+        } else {
+            (4, 196)
+        };
+        let (left, right): (usize, usize) = if control_2 & CONTROL_2_CSEL != 0 {
+            (0, 320)
+        } else {
+            (7, 311)
+        };
+        let display: bool = control_1 & CONTROL_1_DEN != 0;
+
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let window_y: usize = y.wrapping_sub(WINDOW_Y);
+                let window_x: usize = x.wrapping_sub(WINDOW_X);
+                let inside: bool = display
+                    && (top..bottom).contains(&window_y)
+                    && (left..right).contains(&window_x);
+                let rgba: [u8; 4] = if inside {
+                    self.text_pixel(ram, bank, window_x, window_y)
+                } else {
+                    border
+                };
+                frame.set_pixel(x, y, rgba);
+            }
+        }
+    }
+}
+
+impl Device for Vic {
+    fn read(&mut self, offset: u16) -> u8 {
+        let register: u16 = offset & 0x3f;
+        match register {
+            CONTROL_1 => {
+                let raster_8: u8 = if self.raster & 0x100 != 0 {
+                    CONTROL_1_RASTER_8
+                } else {
+                    0x00
+                };
+                (self.registers[CONTROL_1 as usize] & !CONTROL_1_RASTER_8) | raster_8
+            }
+            RASTER => self.raster as u8,
+            INTERRUPT => {
+                let any: u8 = if self.irq_flags & self.registers[INTERRUPT_ENABLE as usize] != 0 {
+                    0x80
+                } else {
+                    0x00
+                };
+                self.irq_flags | any | 0x70
+            }
+            INTERRUPT_ENABLE => self.registers[INTERRUPT_ENABLE as usize] | 0xf0,
+            CONTROL_2 => self.registers[CONTROL_2 as usize] | 0xc0,
+            MEMORY_POINTERS => self.registers[MEMORY_POINTERS as usize] | 0x01,
+            // Color registers are 4 bits wide
+            0x20..=0x2e => self.registers[register as usize] | 0xf0,
+            0x2f..=0x3f => 0xff,
+            _ => self.registers[register as usize],
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let register: u16 = offset & 0x3f;
+        match register {
+            CONTROL_1 => {
+                self.raster_compare =
+                    (self.raster_compare & 0x00ff) | ((value & CONTROL_1_RASTER_8) as u16) << 1;
+                self.registers[CONTROL_1 as usize] = value;
+            }
+            RASTER => self.raster_compare = (self.raster_compare & 0x0100) | value as u16,
+            // Writing a 1 acknowledges the interrupt
+            INTERRUPT => self.irq_flags &= !value & 0x0f,
+            _ => self.registers[register as usize] = value,
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.line_cycles += cycles;
+        while self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            self.raster = (self.raster + 1) % LINES;
+            if self.raster == self.raster_compare {
+                self.irq_flags |= IRQ_RASTER;
+            }
+        }
+
+        if self.irq_flags & self.registers[INTERRUPT_ENABLE as usize] & 0x0f != 0 {
+            IrqState::IRQ
+        } else {
+            IrqState::NONE
+        }
+    }
+}
+
+impl Device for ColorRam {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.0.borrow().color_ram[(offset & 0x03ff) as usize]
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.0.borrow_mut().color_ram[(offset & 0x03ff) as usize] = value & 0x0f;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Screen at 0x0400 and characters at 0x2000 in bank 0, 25 rows and 40 columns.
+    fn vic_with_glyph() -> (Vic, Vec<u8>) {
+        let mut vic = Vic::new();
+        vic.write(CONTROL_1, CONTROL_1_DEN | CONTROL_1_RSEL | 3);
+        vic.write(CONTROL_2, CONTROL_2_CSEL);
+        vic.write(MEMORY_POINTERS, 0x18);
+        vic.write(BORDER_COLOR, 14);
+        vic.write(BACKGROUND_COLOR_0, 6);
+
+        let mut ram: Vec<u8> = vec![0x00; 0x10000];
+        // Character 1 has its top-left pixel set, put it in the top-left cell
+        ram[0x2000 + 8] = 0x80;
+        ram[0x0400] = 0x01;
+        vic.color_ram[0] = 1;
+        (vic, ram)
+    }
+
+    #[test]
+    fn renders_text_and_border() {
+        let (vic, ram) = vic_with_glyph();
+        let mut frame = FrameBuffer::new(WIDTH, HEIGHT);
+
+        vic.render(&ram, 0, &mut frame);
+
+        assert_eq!(frame.pixel(0, 0), PALETTE[14]);
+        assert_eq!(frame.pixel(WINDOW_X, WINDOW_Y), PALETTE[1]);
+        assert_eq!(frame.pixel(WINDOW_X + 1, WINDOW_Y), PALETTE[6]);
+    }
+
+    #[test]
+    fn scrolling_and_38_columns() {
+        let (mut vic, ram) = vic_with_glyph();
+        let mut frame = FrameBuffer::new(WIDTH, HEIGHT);
+        vic.write(CONTROL_2, 1);
+
+        vic.render(&ram, 0, &mut frame);
+
+        // The first 7 columns are under the border, the glyph moved right by 1
+        assert_eq!(frame.pixel(WINDOW_X, WINDOW_Y), PALETTE[14]);
+        vic.write(CONTROL_2, CONTROL_2_CSEL | 1);
+        vic.render(&ram, 0, &mut frame);
+        assert_eq!(frame.pixel(WINDOW_X, WINDOW_Y), PALETTE[6]);
+        assert_eq!(frame.pixel(WINDOW_X + 1, WINDOW_Y), PALETTE[1]);
+    }
+
+    #[test]
+    fn raster_interrupt() {
+        let mut vic = Vic::new();
+        vic.write(RASTER, 2);
+        vic.write(INTERRUPT_ENABLE, IRQ_RASTER);
+
+        assert_eq!(vic.tick(CYCLES_PER_LINE), IrqState::NONE);
+        assert_eq!(vic.tick(CYCLES_PER_LINE), IrqState::IRQ);
+        assert_eq!(vic.read(RASTER), 2);
+        vic.write(INTERRUPT, IRQ_RASTER);
+        assert_eq!(vic.tick(1), IrqState::NONE);
+    }
+}
@@ -0,0 +1,49 @@
+/// An RGBA image produced by a video chip, 4 bytes per pixel, rows top to bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameBuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl FrameBuffer {
+    /// Creates a black frame buffer.
+    pub fn new(width: usize, height: usize) -> Self {
+        FrameBuffer {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// # Returns
+    /// The RGBA bytes of every pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// # Returns
+    /// The RGBA color of the pixel at column `x` of row `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let index: usize = (y * self.width + x) * 4;
+        [
+            self.pixels[index],
+            self.pixels[index + 1],
+            self.pixels[index + 2],
+            self.pixels[index + 3],
+        ]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        let index: usize = (y * self.width + x) * 4;
+        self.pixels[index..index + 4].copy_from_slice(&rgba);
+    }
+}
@@ -0,0 +1,163 @@
+#ifndef EMU6502_H
+#define EMU6502_H
+
+/* Written by hand as cbindgen generates it from src/lib.rs with cbindgen.toml.
+   The tests of src/lib.rs check it against the exports, edit both together. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An emulated machine: 64 KiB of RAM, a 6502 and the devices mapped by the host.
+typedef struct Emu Emu;
+
+// Called when the CPU reads a register of a host device, with the offset from the
+// start of its range.
+typedef uint8_t (*EmuReadFn)(void *user_data, uint16_t offset);
+
+// Called when the CPU writes a register of a host device.
+typedef void (*EmuWriteFn)(void *user_data, uint16_t offset, uint8_t value);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// # Returns
+// A new machine, to free with `emu_free()`. Like the CPU, it must be reset before
+// running.
+Emu *emu_new(void);
+
+// Frees `emu`. Does nothing if `emu` is null.
+//
+// # Safety
+//
+// `emu` must come from `emu_new()` and is not used afterwards.
+void emu_free(Emu *emu);
+
+// Resets the CPU, which starts at the reset vector.
+//
+// # Safety
+//
+// `emu` must come from `emu_new()`, here and in every function taking it.
+void emu_reset(Emu *emu);
+
+// Executes one instruction, or enters a pending interrupt, and advances the devices.
+//
+// # Returns
+// The cycles it took, `0` if the CPU is halted.
+//
+// # Safety
+//
+// See `emu_reset()`.
+uint32_t emu_step(Emu *emu);
+
+// # Returns
+// `true` if the CPU is halted, after jumping to itself with trap detection on or on an
+// opcode it cannot execute.
+//
+// # Safety
+//
+// See `emu_reset()`.
+bool emu_halted(const Emu *emu);
+
+// # Returns
+// The byte at `address`, as the CPU reads it, devices included.
+//
+// # Safety
+//
+// See `emu_reset()`.
+uint8_t emu_read(const Emu *emu, uint16_t address);
+
+// Writes `value` at `address`, as the CPU does, devices included.
+//
+// # Safety
+//
+// See `emu_reset()`.
+void emu_write(Emu *emu, uint16_t address, uint8_t value);
+
+// Copies the `length` bytes at `data` into RAM from `address` on, without touching
+// devices.
+//
+// # Returns
+// `true`, or `false` if they do not fit in memory.
+//
+// # Safety
+//
+// See `emu_reset()`. `data` must point to `length` readable bytes.
+bool emu_load(Emu *emu, uint16_t address, const uint8_t *data, uintptr_t length);
+
+// # Returns
+// The program counter.
+//
+// # Safety
+//
+// See `emu_reset()`.
+uint16_t emu_pc(const Emu *emu);
+
+// Sets the program counter, to start a program without going through the reset
+// vector.
+//
+// # Safety
+//
+// See `emu_reset()`.
+void emu_set_pc(Emu *emu, uint16_t pc);
+
+// Halts the CPU whenever a `JMP` or branch jumps to itself if `enabled`, see
+// `Mos6502::set_trap_detection()`. It is off after `emu_new()`.
+//
+// # Safety
+//
+// See `emu_reset()`.
+void emu_set_trap_detection(Emu *emu, bool enabled);
+
+// Reads the registers other than PC, any pointer may be null to skip the register.
+//
+// # Safety
+//
+// See `emu_reset()`. The pointers that are not null must be writable.
+void emu_registers(const Emu *emu,
+                   uint8_t *a,
+                   uint8_t *x,
+                   uint8_t *y,
+                   uint8_t *sp,
+                   uint8_t *status);
+
+// Sets the level of the IRQ line, for host devices that interrupt the CPU.
+//
+// # Safety
+//
+// See `emu_reset()`.
+void emu_set_irq(Emu *emu, bool asserted);
+
+// Triggers a non-maskable interrupt, entered at the next `emu_step()`.
+//
+// # Safety
+//
+// See `emu_reset()`.
+void emu_nmi(Emu *emu);
+
+// Maps a device of the host over the inclusive range `start..=end`: reads and writes
+// there call `read` and `write` with `user_data` and the offset from `start`,
+// instead of reaching RAM. Later mappings take precedence where ranges overlap.
+//
+// # Returns
+// `true`, or `false` if `end` is below `start`.
+//
+// # Safety
+//
+// See `emu_reset()`. The callbacks can be called with `user_data` until `emu` is
+// freed, and must not call back into `emu`.
+bool emu_map_io(Emu *emu,
+                uint16_t start,
+                uint16_t end,
+                EmuReadFn read,
+                EmuWriteFn write,
+                void *user_data);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* EMU6502_H */
@@ -0,0 +1,416 @@
+//! A C API to embed the emulator in C and C++ front-ends, or any language that can
+//! call C. The declarations are in `include/emu6502.h`. It is not generated:
+//! cbindgen cannot be fetched in this tree, so the header is written by hand as it
+//! would generate it with `cbindgen.toml`, and a test checks that it declares the
+//! exports of this file with the same signatures.
+//!
+//! A front-end creates an `Emu` with `emu_new()`, maps its own devices with
+//! `emu_map_io()`, loads a program and calls `emu_step()`. Every function taking an
+//! `Emu` expects one from `emu_new()` that was not freed yet, and the API is not
+//! thread-safe: an `Emu` is used from one thread at a time.
+
+use machine::Machine;
+use memory::device::Device;
+
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::rc::Rc;
+
+/// An emulated machine: 64 KiB of RAM, a 6502 and the devices mapped by the host.
+pub struct Emu {
+    machine: Machine,
+}
+
+/// Called when the CPU reads a register of a host device, with the offset from the
+/// start of its range.
+pub type EmuReadFn = extern "C" fn(user_data: *mut c_void, offset: u16) -> u8;
+
+/// Called when the CPU writes a register of a host device.
+pub type EmuWriteFn = extern "C" fn(user_data: *mut c_void, offset: u16, value: u8);
+
+/// A device of the host, reached through its callbacks.
+struct HostDevice {
+    read: EmuReadFn,
+    write: EmuWriteFn,
+    user_data: *mut c_void,
+}
+
+impl Device for HostDevice {
+    fn read(&mut self, offset: u16) -> u8 {
+        (self.read)(self.user_data, offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        (self.write)(self.user_data, offset, value);
+    }
+}
+
+/// # Returns
+/// A new machine, to free with `emu_free()`. Like the CPU, it must be reset before
+/// running.
+#[no_mangle]
+pub extern "C" fn emu_new() -> *mut Emu {
+    Box::into_raw(Box::new(Emu {
+        machine: Machine::new(),
+    }))
+}
+
+/// Frees `emu`. Does nothing if `emu` is null.
+///
+/// # Safety
+///
+/// `emu` must come from `emu_new()` and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn emu_free(emu: *mut Emu) {
+    if !emu.is_null() {
+        drop(Box::from_raw(emu));
+    }
+}
+
+/// Resets the CPU, which starts at the reset vector.
+///
+/// # Safety
+///
+/// `emu` must come from `emu_new()`, here and in every function taking it.
+#[no_mangle]
+pub unsafe extern "C" fn emu_reset(emu: *mut Emu) {
+    (*emu).machine.reset();
+}
+
+/// Executes one instruction, or enters a pending interrupt, and advances the devices.
+///
+/// # Returns
+/// The cycles it took, `0` if the CPU is halted.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_step(emu: *mut Emu) -> u32 {
+    (*emu).machine.step()
+}
+
+/// # Returns
+/// `true` if the CPU is halted, after jumping to itself with trap detection on or on an
+/// opcode it cannot execute.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_halted(emu: *const Emu) -> bool {
+    (*emu).machine.cpu().is_halted()
+}
+
+/// # Returns
+/// The byte at `address`, as the CPU reads it, devices included.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_read(emu: *const Emu, address: u16) -> u8 {
+    (*emu).machine.memory().borrow().read(address)
+}
+
+/// Writes `value` at `address`, as the CPU does, devices included.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_write(emu: *mut Emu, address: u16, value: u8) {
+    (*emu).machine.memory().borrow_mut().write(address, value);
+}
+
+/// Copies the `length` bytes at `data` into RAM from `address` on, without touching
+/// devices.
+///
+/// # Returns
+/// `true`, or `false` if they do not fit in memory.
+///
+/// # Safety
+///
+/// See `emu_reset()`. `data` must point to `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn emu_load(
+    emu: *mut Emu,
+    address: u16,
+    data: *const u8,
+    length: usize,
+) -> bool {
+    let start: usize = address as usize;
+    if start + length > memory::MEMORY_SIZE {
+        return false;
+    }
+    let data: &[u8] = std::slice::from_raw_parts(data, length);
+    (*emu).machine.memory().borrow_mut().as_mut_slice()[start..start + length]
+        .copy_from_slice(data);
+    true
+}
+
+/// # Returns
+/// The program counter.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_pc(emu: *const Emu) -> u16 {
+    (*emu).machine.cpu().pc()
+}
+
+/// Sets the program counter, to start a program without going through the reset
+/// vector.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_pc(emu: *mut Emu, pc: u16) {
+    (*emu).machine.cpu_mut().set_pc(pc);
+}
+
+/// Halts the CPU whenever a `JMP` or branch jumps to itself if `enabled`, see
+/// `Mos6502::set_trap_detection()`. It is off after `emu_new()`.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_trap_detection(emu: *mut Emu, enabled: bool) {
+    (*emu).machine.cpu_mut().set_trap_detection(enabled);
+}
+
+/// Reads the registers other than PC, any pointer may be null to skip the register.
+///
+/// # Safety
+///
+/// See `emu_reset()`. The pointers that are not null must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn emu_registers(
+    emu: *const Emu,
+    a: *mut u8,
+    x: *mut u8,
+    y: *mut u8,
+    sp: *mut u8,
+    status: *mut u8,
+) {
+    let cpu = (*emu).machine.cpu();
+    for (register, value) in [
+        (a, cpu.a()),
+        (x, cpu.x()),
+        (y, cpu.y()),
+        (sp, cpu.sp()),
+        (status, cpu.status().bits()),
+    ] {
+        if !register.is_null() {
+            *register = value;
+        }
+    }
+}
+
+/// Sets the level of the IRQ line, for host devices that interrupt the CPU.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_irq(emu: *mut Emu, asserted: bool) {
+    (*emu).machine.cpu_mut().set_irq(asserted);
+}
+
+/// Triggers a non-maskable interrupt, entered at the next `emu_step()`.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_nmi(emu: *mut Emu) {
+    (*emu).machine.cpu_mut().nmi();
+}
+
+/// Maps a device of the host over the inclusive range `start..=end`: reads and writes
+/// there call `read` and `write` with `user_data` and the offset from `start`,
+/// instead of reaching RAM. Later mappings take precedence where ranges overlap.
+///
+/// # Returns
+/// `true`, or `false` if `end` is below `start`.
+///
+/// # Safety
+///
+/// See `emu_reset()`. The callbacks can be called with `user_data` until `emu` is
+/// freed, and must not call back into `emu`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_map_io(
+    emu: *mut Emu,
+    start: u16,
+    end: u16,
+    read: EmuReadFn,
+    write: EmuWriteFn,
+    user_data: *mut c_void,
+) -> bool {
+    if end < start {
+        return false;
+    }
+    let device: HostDevice = HostDevice {
+        read,
+        write,
+        user_data,
+    };
+    (*emu)
+        .machine
+        .map_device(start, end, Rc::new(RefCell::new(device)));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stores the last value written, reads return it plus the offset.
+    extern "C" fn read_latch(user_data: *mut c_void, offset: u16) -> u8 {
+        unsafe { *(user_data as *mut u8) + offset as u8 }
+    }
+
+    extern "C" fn write_latch(user_data: *mut c_void, _offset: u16, value: u8) {
+        unsafe { *(user_data as *mut u8) = value }
+    }
+
+    /// # Returns
+    /// The C type cbindgen writes for the Rust type `rust`.
+    fn c_type(rust: &str) -> String {
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        match rust {
+            "u8" => "uint8_t",
+            "u16" => "uint16_t",
+            "u32" => "uint32_t",
+            "usize" => "uintptr_t",
+            "c_void" => "void",
+            other => other,
+        }
+        .to_string()
+    }
+
+    /// # Returns
+    /// `c_type` followed by `name`, as cbindgen spaces them, e.g. `const Emu *emu`.
+    fn declare(c_type: &str, name: &str) -> String {
+        if c_type.ends_with('*') {
+            format!("{}{}", c_type, name)
+        } else {
+            format!("{} {}", c_type, name)
+        }
+    }
+
+    /// # Returns
+    /// The C declarations of the `extern "C"` functions of this file, as in the header,
+    /// e.g. `uint8_t emu_read(const Emu *emu, uint16_t address)`.
+    fn exports() -> Vec<String> {
+        let source: &str = include_str!("lib.rs");
+        let mut declarations: Vec<String> = Vec::new();
+        for (start, prefix) in source.match_indices("extern \"C\" fn emu_") {
+            let signature: &str = &source[start + prefix.len() - "emu_".len()..];
+            let signature: &str = &signature[..signature.find('{').unwrap()];
+            let (name, rest): (&str, &str) = signature.split_once('(').unwrap();
+            let (parameters, result): (&str, &str) = rest.rsplit_once(')').unwrap();
+            let parameters: Vec<String> = parameters
+                .split(',')
+                .map(str::trim)
+                .filter(|parameter| !parameter.is_empty())
+                .map(|parameter| {
+                    let (name, rust): (&str, &str) = parameter.split_once(": ").unwrap();
+                    declare(&c_type(rust), name)
+                })
+                .collect();
+            let result: String = match result.trim().strip_prefix("-> ") {
+                Some(rust) => c_type(rust),
+                None => "void".to_string(),
+            };
+            let parameters: String = match parameters.is_empty() {
+                true => "void".to_string(),
+                false => parameters.join(", "),
+            };
+            declarations.push(format!("{}({})", declare(&result, name), parameters));
+        }
+        declarations
+    }
+
+    #[test]
+    fn header_declares_the_exports() {
+        let header: String = include_str!("../include/emu6502.h")
+            .lines()
+            .filter(|line| !line.starts_with("//") && !line.starts_with('#'))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let declarations: Vec<String> = header
+            .split(';')
+            .filter(|declaration| declaration.contains("emu_"))
+            // After `extern "C" {` for the first one
+            .map(|declaration| declaration.rsplit('{').next().unwrap())
+            .map(|declaration| {
+                declaration
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .map(|declaration| declaration.replace("( ", "("))
+            .collect();
+        let exports: Vec<String> = exports();
+        assert_eq!(exports.len(), 15);
+        assert_eq!(declarations, exports);
+    }
+
+    #[test]
+    fn runs_program_with_host_device() {
+        // LDA #$40, STA $D000, LDA $D001, JMP $0208
+        let program: [u8; 11] = [
+            0xa9, 0x40, 0x8d, 0x00, 0xd0, 0xad, 0x01, 0xd0, 0x4c, 0x08, 0x02,
+        ];
+        let mut latch: u8 = 0;
+        unsafe {
+            let emu: *mut Emu = emu_new();
+            assert!(emu_load(emu, 0x0200, program.as_ptr(), program.len()));
+            let user_data: *mut c_void = &mut latch as *mut u8 as *mut c_void;
+            assert!(emu_map_io(
+                emu,
+                0xd000,
+                0xd00f,
+                read_latch,
+                write_latch,
+                user_data
+            ));
+            assert!(!emu_map_io(
+                emu,
+                0xd00f,
+                0xd000,
+                read_latch,
+                write_latch,
+                user_data
+            ));
+            emu_set_pc(emu, 0x0200);
+            emu_set_trap_detection(emu, true);
+            while !emu_halted(emu) {
+                emu_step(emu);
+            }
+
+            let mut a: u8 = 0;
+            emu_registers(
+                emu,
+                &mut a,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            assert_eq!(a, 0x41);
+            assert_eq!(emu_pc(emu), 0x0208);
+            emu_free(emu);
+        }
+        assert_eq!(latch, 0x40);
+    }
+}
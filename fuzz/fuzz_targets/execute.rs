@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Enough for a few thousand instructions
+fuzz_target!(|data: &[u8]| {
+    mos6502::fuzz::run(data, 20_000);
+});
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+/// Do not sleep for less than this, the OS cannot wake us up that precisely anyway.
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+/// The master clock of a machine: counts cycles and keeps them in step with wall-clock
+/// time, for frontends pacing the emulation.
+///
+/// Wall-clock time is measured from an anchor, the cycle count and the instant of the
+/// last `restart()`, so time spent paused is not caught up on.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    hz: f64,
+    cycles: u64,
+    anchor: Instant,
+    anchor_cycles: u64,
+}
+
+impl Clock {
+    /// Creates a clock at `hz`, at cycle `0` and anchored now.
+    pub fn new(hz: f64) -> Self {
+        Clock {
+            hz,
+            cycles: 0,
+            anchor: Instant::now(),
+            anchor_cycles: 0,
+        }
+    }
+
+    pub fn hz(&self) -> f64 {
+        self.hz
+    }
+
+    /// # Returns
+    /// The cycles counted by `advance()` and `set_cycles()`.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Counts `cycles` more.
+    pub fn advance(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+    }
+
+    /// Sets the cycle count, e.g. to that of the CPU, without moving the anchor.
+    pub fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    /// # Returns
+    /// How long `cycles` take at the frequency of the clock.
+    pub fn cycles_to_duration(&self, cycles: u64) -> Duration {
+        Duration::from_secs_f64(cycles as f64 / self.hz)
+    }
+
+    /// # Returns
+    /// How many whole cycles fit in `duration`.
+    pub fn duration_to_cycles(&self, duration: Duration) -> u64 {
+        (duration.as_secs_f64() * self.hz) as u64
+    }
+
+    /// # Returns
+    /// The emulated time elapsed since cycle `0`.
+    pub fn elapsed(&self) -> Duration {
+        self.cycles_to_duration(self.cycles)
+    }
+
+    /// Anchors wall-clock time at `cycles` now, also setting the count to it,
+    /// forgetting any time spent before, e.g. while paused or waiting for the user.
+    pub fn restart(&mut self, cycles: u64) {
+        self.cycles = cycles;
+        self.anchor = Instant::now();
+        self.anchor_cycles = cycles;
+    }
+
+    /// # Returns
+    /// The cycle wall-clock time has reached, which the emulation should catch up to.
+    pub fn cycles_due(&self) -> u64 {
+        self.anchor_cycles + self.duration_to_cycles(self.anchor.elapsed())
+    }
+
+    /// # Returns
+    /// How long until wall-clock time reaches `cycle`, zero if it already did.
+    pub fn time_until_cycle(&self, cycle: u64) -> Duration {
+        let offset: Duration = self.cycles_to_duration(cycle.saturating_sub(self.anchor_cycles));
+        (self.anchor + offset).saturating_duration_since(Instant::now())
+    }
+
+    /// Sleeps until wall-clock time reaches `cycle`, not at all if it is less than
+    /// a millisecond away.
+    pub fn sleep_until_cycle(&self, cycle: u64) {
+        let wait: Duration = self.time_until_cycle(cycle);
+        if wait > MIN_SLEEP {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Sleeps until wall-clock time reaches the cycle count, see `sleep_until_cycle()`.
+    pub fn sync(&self) {
+        self.sleep_until_cycle(self.cycles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_cycles_and_time() {
+        let mut clock: Clock = Clock::new(1_000_000.0);
+        clock.advance(500_000);
+        assert_eq!(clock.elapsed(), Duration::from_millis(500));
+        assert_eq!(clock.duration_to_cycles(Duration::from_millis(2)), 2000);
+
+        clock.restart(1_000_000);
+        assert_eq!(clock.cycles(), 1_000_000);
+        assert_eq!(clock.time_until_cycle(999_000), Duration::ZERO);
+        assert!(clock.time_until_cycle(1_100_000) > Duration::from_millis(50));
+        assert!(clock.cycles_due() >= 1_000_000);
+    }
+}
@@ -0,0 +1,141 @@
+//! High-level emulation of the console routines of the C64 KERNAL, so programs that
+//! only print and read characters run without the KERNAL ROM, BASIC or a screen.
+
+use crate::Machine;
+
+use mos6502::{Mos6502, StatusFlags, TrapAction};
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver};
+
+/// Reads the next character of the input line into A.
+pub const CHRIN: u16 = 0xffcf;
+/// Prints the character in A.
+pub const CHROUT: u16 = 0xffd2;
+/// Reads the next key pressed into A, `0` if there is none.
+pub const GETIN: u16 = 0xffe4;
+
+/// Where a program started by `start_program()` returns to, in RAM the KERNAL does not
+/// use. A `JMP` to itself is placed there, so the CPU stays there, and stops as a trap
+/// if traps are detected, see `Mos6502::set_trap_detection()`.
+pub const EXIT_ADDRESS: u16 = 0x02a7;
+
+/// Sets A with the zero and negative flags, clearing carry to report no error, as the
+/// KERNAL routines do.
+fn set_result(cpu: &mut Mos6502, value: u8) {
+    let mut status: StatusFlags = cpu.status();
+    status.set_carry(false);
+    status.set_zero(value == 0);
+    status.set_negative(value & 0x80 != 0);
+    cpu.set_status(status);
+    cpu.set_a(value);
+}
+
+/// Traps `CHROUT`, `CHRIN` and `GETIN` of `machine`, printing to `output` and reading
+/// the keys sent to `Machine::keyboard()`, which replaces the keyboard the machine had.
+///
+/// `CHRIN` waits until a key arrives, the CPU spinning meanwhile. Characters are
+/// passed as they are, so `output` translates them from PETSCII if needed.
+pub fn install_console(machine: &mut Machine, output: impl Write + 'static) {
+    let (sender, keys) = mpsc::channel::<u8>();
+    machine.connect_keyboard(sender);
+    let keys: Rc<Receiver<u8>> = Rc::new(keys);
+    let output: Rc<RefCell<dyn Write>> = Rc::new(RefCell::new(output));
+
+    let cpu: &mut Mos6502 = machine.cpu_mut();
+    cpu.add_trap(CHROUT, move |cpu: &mut Mos6502| {
+        let mut output = output.borrow_mut();
+        // Console output is best effort, like the screen of the real machine
+        let _ = output.write_all(&[cpu.a()]).and_then(|()| output.flush());
+        cpu.set_status(cpu.status() & !StatusFlags::CARRY);
+        TrapAction::Return
+    });
+
+    let chrin_keys: Rc<Receiver<u8>> = keys.clone();
+    cpu.add_trap(CHRIN, move |cpu: &mut Mos6502| {
+        match chrin_keys.try_recv() {
+            Ok(key) => {
+                set_result(cpu, key);
+                TrapAction::Return
+            }
+            Err(_) => TrapAction::Wait,
+        }
+    });
+
+    cpu.add_trap(GETIN, move |cpu: &mut Mos6502| {
+        set_result(cpu, keys.try_recv().unwrap_or(0x00));
+        TrapAction::Return
+    });
+}
+
+/// Starts the machine code at `entry` as if called with `SYS` from BASIC: it runs with
+/// a fresh stack and returning from it goes to `EXIT_ADDRESS`.
+pub fn start_program(machine: &mut Machine, entry: u16) {
+    {
+        let mut mem = machine.memory().borrow_mut();
+        let [low, high]: [u8; 2] = EXIT_ADDRESS.to_le_bytes();
+        for (offset, byte) in [0x4c, low, high].into_iter().enumerate() {
+            mem.write(EXIT_ADDRESS + offset as u16, byte);
+        }
+        // The return address as pushed by `JSR`, less 1 and high byte first
+        let [low, high]: [u8; 2] = EXIT_ADDRESS.wrapping_sub(1).to_le_bytes();
+        mem.write(0x01ff, high);
+        mem.write(0x01fe, low);
+    }
+    let cpu: &mut Mos6502 = machine.cpu_mut();
+    cpu.set_sp(0xfd);
+    cpu.set_pc(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+
+    /// Collects the console output where the test can see it.
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn console_program_runs_without_kernal() {
+        // LDA #'H', JSR CHROUT, JSR CHRIN, JSR CHROUT, JSR GETIN, STA $10, RTS
+        let program: [u8; 17] = [
+            0xa9, b'H', 0x20, 0xd2, 0xff, 0x20, 0xcf, 0xff, 0x20, 0xd2, 0xff, 0x20, 0xe4, 0xff,
+            0x85, 0x10, 0x60,
+        ];
+        let mut machine: Machine = Machine::new();
+        machine.memory().borrow_mut().as_mut_slice()[0xc000..0xc011].copy_from_slice(&program);
+        let output: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        install_console(&mut machine, Shared(output.clone()));
+        start_program(&mut machine, 0xc000);
+        machine.cpu_mut().set_trap_detection(true);
+
+        // CHRIN waits for the key
+        for _ in 0..10 {
+            machine.step();
+        }
+        assert_eq!(*output.borrow(), b"H");
+        machine.keyboard().unwrap().send(b'I').unwrap();
+        while !machine.cpu().is_halted() {
+            machine.step();
+        }
+
+        assert_eq!(*output.borrow(), b"HI");
+        // No key left for GETIN
+        assert_eq!(machine.memory().borrow().read(0x0010), 0x00);
+        assert_eq!(machine.cpu().trapped_at(), Some(EXIT_ADDRESS));
+    }
+}
@@ -0,0 +1,455 @@
+pub mod clock;
+pub mod kernal;
+pub mod media;
+pub mod preset;
+#[cfg(feature = "serialize")]
+mod serialize;
+pub mod worker;
+
+use devices::joypad::Joypad;
+use devices::video::FrameBuffer;
+use memory::device::Device;
+use memory::Memory;
+use mos6502::input_log::{InputEvent, InputLog};
+use mos6502::Mos6502;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+/// Clock of the bare machine, in Hz.
+pub const DEFAULT_CLOCK_HZ: f64 = 1_000_000.0;
+/// Frame rate of the bare machine, in Hz.
+pub const DEFAULT_FRAME_RATE: f64 = 60.0;
+
+/// Where `Machine::install_vectors()` places the `JMP` to itself that interrupts run.
+pub const HALT_HANDLER: u16 = 0xfff7;
+const NMI_VECTOR: usize = 0xfffa;
+const RESET_VECTOR: usize = 0xfffc;
+const IRQ_VECTOR: usize = 0xfffe;
+
+/// A video chip, turning memory into a picture once per frame.
+pub trait Video {
+    /// Draws the current screen into `frame`.
+    fn render(&mut self, mem: &Memory, frame: &mut FrameBuffer);
+}
+
+/// A sound chip, whose samples are collected by the machine, see `Machine::set_audio_sink()`.
+pub trait Audio {
+    /// # Returns
+    /// The samples generated since the last call, from `-1.0` to `1.0`.
+    fn take_samples(&mut self) -> Vec<f32>;
+}
+
+/// Where the machine sends the audio it generates, e.g. a sound card or a file.
+pub trait AudioSink {
+    fn write(&mut self, samples: &[f32]);
+}
+
+/// A saved machine state, see `Machine::snapshot()`.
+/// Devices are not included, only the CPU and the RAM.
+#[derive(Clone)]
+pub struct Snapshot {
+    cpu: Mos6502,
+    ram: Vec<u8>,
+    frame: u64,
+}
+
+/// A complete computer: CPU, memory map, devices and master clock.
+/// Frontends drive this instead of wiring the CPU and memory together themselves.
+pub struct Machine {
+    cpu: Mos6502,
+    mem: Rc<RefCell<Memory>>,
+
+    clock_hz: f64,
+    cycles_per_frame: u32,
+    /// Number of frames completed by `run_frame()`.
+    frame: u64,
+
+    /// Inputs fed back into the machine when their cycle is reached.
+    replay: Option<InputLog>,
+    /// Inputs applied through `input()` so far, if recording.
+    recording: Option<InputLog>,
+    /// Feeds the keyboard device, if the machine has one.
+    keyboard: Option<Sender<u8>>,
+    /// The game controller ports, if the machine has them.
+    joypad: Option<Rc<RefCell<Joypad>>>,
+    /// The video chip and the last frame it rendered, if the machine has one.
+    video: Option<(Box<dyn Video>, FrameBuffer)>,
+    /// The sound chip and its sample rate, if the machine has one.
+    audio: Option<(Box<dyn Audio>, u32)>,
+    audio_sink: Option<Box<dyn AudioSink>>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    /// Creates a bare machine: 64 KiB of RAM and no devices, at `DEFAULT_CLOCK_HZ`.
+    /// Like the CPU, the machine must be reset before running.
+    pub fn new() -> Self {
+        Self::with_memory(Memory::new(), DEFAULT_CLOCK_HZ, DEFAULT_FRAME_RATE)
+    }
+
+    /// Creates a machine around an existing memory map.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - The memory map, with any devices already mapped.
+    /// * `clock_hz` - The CPU clock, in Hz.
+    /// * `frame_rate` - How many times per second `run_frame()` is meant to be called.
+    pub fn with_memory(mem: Memory, clock_hz: f64, frame_rate: f64) -> Self {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(mem));
+        Machine {
+            cpu: Mos6502::new(mem.clone()),
+            mem,
+            clock_hz,
+            cycles_per_frame: (clock_hz / frame_rate).round() as u32,
+            frame: 0,
+            replay: None,
+            recording: None,
+            keyboard: None,
+            joypad: None,
+            video: None,
+            audio: None,
+            audio_sink: None,
+        }
+    }
+
+    pub fn cpu(&self) -> &Mos6502 {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Mos6502 {
+        &mut self.cpu
+    }
+
+    /// # Returns
+    /// The memory map shared with the CPU.
+    pub fn memory(&self) -> &Rc<RefCell<Memory>> {
+        &self.mem
+    }
+
+    /// Maps `device` over the inclusive range `start..=end`. See `Memory::map_device()`.
+    pub fn map_device(&mut self, start: u16, end: u16, device: Rc<RefCell<dyn Device>>) {
+        self.mem.borrow_mut().map_device(start, end, device);
+    }
+
+    /// Makes `keys` the way host keystrokes reach the machine, see `keyboard()`.
+    pub fn connect_keyboard(&mut self, keys: Sender<u8>) {
+        self.keyboard = Some(keys);
+    }
+
+    /// # Returns
+    /// The sender feeding the keyboard device, if the machine has one.
+    pub fn keyboard(&self) -> Option<&Sender<u8>> {
+        self.keyboard.as_ref()
+    }
+
+    /// Makes `joypad` receive the `InputEvent::Joypad` inputs, see `has_joypad()`.
+    pub fn connect_joypad(&mut self, joypad: Rc<RefCell<Joypad>>) {
+        self.joypad = Some(joypad);
+    }
+
+    /// # Returns
+    /// Whether the machine has game controller ports.
+    pub fn has_joypad(&self) -> bool {
+        self.joypad.is_some()
+    }
+
+    /// Makes `video` render a `width` x `height` frame at the end of every frame,
+    /// see `frame_buffer()`.
+    pub fn connect_video(&mut self, video: Box<dyn Video>, width: usize, height: usize) {
+        self.video = Some((video, FrameBuffer::new(width, height)));
+    }
+
+    /// # Returns
+    /// The last frame rendered, if the machine has a video chip.
+    pub fn frame_buffer(&self) -> Option<&FrameBuffer> {
+        self.video.as_ref().map(|(_, frame)| frame)
+    }
+
+    /// Renders the current screen now, without waiting for the end of the frame.
+    pub fn render(&mut self) {
+        if let Some((video, frame)) = &mut self.video {
+            video.render(&self.mem.borrow(), frame);
+        }
+    }
+
+    /// Makes `audio`, generating `sample_rate` samples per second, the sound of the machine.
+    pub fn connect_audio(&mut self, audio: Box<dyn Audio>, sample_rate: u32) {
+        self.audio = Some((audio, sample_rate));
+    }
+
+    /// # Returns
+    /// The sample rate of the sound chip, if the machine has one.
+    pub fn audio_sample_rate(&self) -> Option<u32> {
+        self.audio.as_ref().map(|(_, sample_rate)| *sample_rate)
+    }
+
+    /// Sends the generated audio to `sink` from now on, as the machine runs.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    /// Loads a ROM into memory starting at the given address.
+    pub fn load_rom(&mut self, path: &str, start_address: u16) {
+        self.mem.borrow_mut().load_rom(path, start_address);
+    }
+
+    pub fn clock_hz(&self) -> f64 {
+        self.clock_hz
+    }
+
+    pub fn cycles_per_frame(&self) -> u32 {
+        self.cycles_per_frame
+    }
+
+    /// # Returns
+    /// The number of cycles executed since the machine was created.
+    pub fn cycles(&self) -> u64 {
+        self.cpu.cycles()
+    }
+
+    /// # Returns
+    /// The number of frames completed by `run_frame()`.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Fills the vectors a raw program left at zero in RAM: reset points at `entry`
+    /// and NMI and IRQ/BRK at a `JMP` to itself at `HALT_HANDLER`, so a stray `BRK`
+    /// or interrupt stays there instead of running whatever is at $0000, and stops the
+    /// CPU as a trap if traps are detected, see `Mos6502::set_trap_detection()`.
+    ///
+    /// Takes effect at the next reset.
+    pub fn install_vectors(&mut self, entry: u16) {
+        let mut mem = self.mem.borrow_mut();
+        let ram: &mut [u8] = mem.as_mut_slice();
+        let mut halting: bool = false;
+        for (vector, target) in [
+            (RESET_VECTOR, entry),
+            (NMI_VECTOR, HALT_HANDLER),
+            (IRQ_VECTOR, HALT_HANDLER),
+        ] {
+            if ram[vector..vector + 2] == [0x00, 0x00] {
+                ram[vector..vector + 2].copy_from_slice(&target.to_le_bytes());
+                halting |= target == HALT_HANDLER;
+            }
+        }
+        if halting {
+            let [low, high]: [u8; 2] = HALT_HANDLER.to_le_bytes();
+            ram[HALT_HANDLER as usize..NMI_VECTOR].copy_from_slice(&[0x4c, low, high]);
+        }
+    }
+
+    /// Presses the reset button. Same as `input(InputEvent::Reset)`.
+    pub fn reset(&mut self) {
+        self.input(InputEvent::Reset);
+    }
+
+    /// Applies an external input, recording it if `start_recording()` was called.
+    pub fn input(&mut self, event: InputEvent) {
+        self.apply(event);
+        if let Some(recording) = &mut self.recording {
+            recording.record(self.cpu.cycles(), event);
+        }
+    }
+
+    fn apply(&mut self, event: InputEvent) {
+        match (event, &self.joypad) {
+            (InputEvent::Joypad { port, buttons }, Some(joypad)) => {
+                // Ports the machine does not have are left unplugged
+                if port < 2 {
+                    joypad.borrow_mut().set_buttons(port as usize, buttons);
+                }
+            }
+            _ => event.apply(&mut self.cpu),
+        }
+    }
+
+    /// Records every input from now on, see `recording()`.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(InputLog::new());
+    }
+
+    /// # Returns
+    /// The inputs recorded since `start_recording()`, if recording.
+    pub fn recording(&self) -> Option<&InputLog> {
+        self.recording.as_ref()
+    }
+
+    /// Feeds the inputs of `log` back into the machine as their cycle is reached.
+    pub fn replay(&mut self, log: InputLog) {
+        self.replay = Some(log);
+    }
+
+    /// Applies due replayed inputs, then executes one instruction or interrupt entry.
+    ///
+    /// # Returns
+    /// The number of cycles consumed, `0` if the CPU is halted.
+    pub fn step(&mut self) -> u32 {
+        let cycle: u64 = self.cpu.cycles();
+        while let Some(event) = self.replay.as_mut().and_then(|log| log.next_due(cycle)) {
+            self.apply(event);
+        }
+        let cycles: u32 = self.cpu.step();
+        if let (Some((audio, _)), Some(sink)) = (&mut self.audio, &mut self.audio_sink) {
+            let samples: Vec<f32> = audio.take_samples();
+            if !samples.is_empty() {
+                sink.write(&samples);
+            }
+        }
+        cycles
+    }
+
+    /// Runs for one frame worth of cycles, or until the CPU halts.
+    /// A completed frame is rendered, see `frame_buffer()`.
+    ///
+    /// # Returns
+    /// The number of cycles executed. The last instruction may run past the end of the frame.
+    pub fn run_frame(&mut self) -> u32 {
+        let mut cycles: u32 = 0;
+        while cycles < self.cycles_per_frame {
+            let step_cycles: u32 = self.step();
+            if step_cycles == 0 {
+                return cycles;
+            }
+            cycles += step_cycles;
+        }
+        self.frame += 1;
+        self.render();
+        cycles
+    }
+
+    /// # Returns
+    /// A copy of the CPU state and RAM contents. Devices are not included.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            ram: self.mem.borrow().as_slice().to_vec(),
+            frame: self.frame,
+        }
+    }
+
+    /// Puts the CPU and RAM back into the state saved in `snapshot`.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu = snapshot.cpu.clone();
+        self.mem
+            .borrow_mut()
+            .as_mut_slice()
+            .copy_from_slice(&snapshot.ram);
+        self.frame = snapshot.frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LDX #$FF, TXS, loop: INC $10, JMP loop
+    const PROGRAM: [u8; 8] = [0xA2, 0xFF, 0x9A, 0xE6, 0x10, 0x4C, 0x03, 0x00];
+
+    fn looping_machine() -> Machine {
+        let mut machine = Machine::new();
+        for (address, byte) in PROGRAM.iter().enumerate() {
+            machine.memory().borrow_mut().write(address as u16, *byte);
+        }
+        machine.reset();
+        machine
+    }
+
+    #[test]
+    fn run_frame_runs_a_frame_of_cycles() {
+        let mut machine = looping_machine();
+
+        let cycles: u32 = machine.run_frame();
+
+        assert!(cycles >= machine.cycles_per_frame());
+        assert!(cycles < machine.cycles_per_frame() + 7);
+        assert_eq!(machine.frame(), 1);
+        assert_eq!(machine.cycles(), cycles as u64);
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let mut machine = looping_machine();
+        machine.run_frame();
+        let snapshot: Snapshot = machine.snapshot();
+        let counter: u8 = machine.memory().borrow().read(0x0010);
+
+        machine.run_frame();
+        assert_ne!(machine.memory().borrow().read(0x0010), counter);
+
+        machine.restore(&snapshot);
+        assert_eq!(machine.memory().borrow().read(0x0010), counter);
+        assert_eq!(machine.frame(), 1);
+
+        // The restored CPU still drives the shared memory
+        machine.step();
+        machine.step();
+        assert_eq!(
+            machine.memory().borrow().read(0x0010),
+            counter.wrapping_add(1)
+        );
+    }
+
+    #[test]
+    fn recorded_inputs_replay() {
+        let mut machine = looping_machine();
+        machine.start_recording();
+        machine.run_frame();
+        machine.reset();
+        machine.step();
+
+        let log: InputLog = InputLog::parse(&machine.recording().unwrap().to_string()).unwrap();
+        let expected: u8 = machine.memory().borrow().read(0x0010);
+
+        let mut replayed = looping_machine();
+        replayed.replay(log);
+        while replayed.cycles() < machine.cycles() {
+            replayed.step();
+        }
+
+        assert_eq!(replayed.memory().borrow().read(0x0010), expected);
+        assert_eq!(replayed.cpu().trapped_at(), None);
+    }
+
+    #[test]
+    fn joypad_inputs_replay() {
+        let mut machine = looping_machine();
+        let joypad: Rc<RefCell<Joypad>> = Rc::new(RefCell::new(Joypad::new()));
+        machine.connect_joypad(joypad.clone());
+        let log: InputLog = InputLog::parse("20 joypad 1 0x81\n").unwrap();
+        machine.replay(log);
+
+        machine.run_frame();
+
+        assert_eq!(joypad.borrow().buttons(0), 0x00);
+        assert_eq!(joypad.borrow().buttons(1), 0x81);
+    }
+
+    #[test]
+    fn installed_vectors_start_program_and_halt_on_brk() {
+        let mut machine: Machine = Machine::new();
+        // LDX #$FF, TXS, LDA #$01, BRK
+        machine.memory().borrow_mut().as_mut_slice()[0x0400..0x0406]
+            .copy_from_slice(&[0xa2, 0xff, 0x9a, 0xa9, 0x01, 0x00]);
+        machine.memory().borrow_mut().as_mut_slice()[0xfffa] = 0x34;
+        machine.install_vectors(0x0400);
+        machine.reset();
+        machine.cpu_mut().set_trap_detection(true);
+        assert_eq!(machine.cpu().pc(), 0x0400);
+
+        for _ in 0..6 {
+            machine.step();
+        }
+        assert_eq!(machine.cpu().a(), 0x01);
+        assert_eq!(machine.cpu().trapped_at(), Some(HALT_HANDLER));
+        // A vector the program set is kept
+        assert_eq!(machine.memory().borrow().get_nmi_vector(), 0x0034);
+    }
+}
@@ -0,0 +1,165 @@
+use memory::rom::Rom;
+use memory::Memory;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SIGNATURE: &[u8] = b"C64 CARTRIDGE   ";
+const CHIP_SIGNATURE: &[u8] = b"CHIP";
+/// Hardware type of plain cartridges, without bank switching.
+const NORMAL_CARTRIDGE: u16 = 0;
+
+pub const ROML_START: u16 = 0x8000;
+/// Where ROMH is seen in the 16K configuration, it moves to `ULTIMAX_ROMH_START` in the
+/// Ultimax one.
+pub const ROMH_START: u16 = 0xa000;
+pub const ULTIMAX_ROMH_START: u16 = 0xe000;
+pub const BANK_SIZE: u16 = 0x2000;
+
+/// How the cartridge sets the EXROM and GAME lines, selecting the memory configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// EXROM low: ROML at $8000.
+    Rom8K,
+    /// EXROM and GAME low: ROML at $8000 and ROMH at $A000.
+    Rom16K,
+    /// GAME low: ROML at $8000 and ROMH at $E000, replacing the KERNAL, as on the MAX Machine.
+    Ultimax,
+    /// Both lines high, the cartridge is invisible.
+    Off,
+}
+
+/// A C64 cartridge in the CRT format: a header giving the hardware type and the
+/// EXROM/GAME lines, followed by CHIP packets with the ROM data.
+///
+/// Only normal cartridges, of 8K, 16K or Ultimax, are supported. Bank switched ones
+/// need their mapper emulated.
+pub struct Crt {
+    /// The name of the cartridge, in ASCII.
+    pub name: String,
+    pub mode: Mode,
+    roml: Option<Vec<u8>>,
+    romh: Option<Vec<u8>>,
+}
+
+impl Crt {
+    /// # Returns
+    /// The cartridge read from `data`, or an error if it is not a CRT file or not a
+    /// normal cartridge.
+    pub fn parse(data: &[u8]) -> Result<Crt, String> {
+        if data.len() < 0x40 || !data.starts_with(SIGNATURE) {
+            return Err("not a CRT cartridge image".to_string());
+        }
+        let header_size: usize =
+            u32::from_be_bytes([data[0x10], data[0x11], data[0x12], data[0x13]]) as usize;
+        let hardware: u16 = u16::from_be_bytes([data[0x16], data[0x17]]);
+        if hardware != NORMAL_CARTRIDGE {
+            return Err(format!(
+                "Cartridge hardware type {} is not supported, only normal cartridges are",
+                hardware
+            ));
+        }
+        // The lines are active low
+        let mode: Mode = match (data[0x18] != 0, data[0x19] != 0) {
+            (false, true) => Mode::Rom8K,
+            (false, false) => Mode::Rom16K,
+            (true, false) => Mode::Ultimax,
+            (true, true) => Mode::Off,
+        };
+        let name: String = String::from_utf8_lossy(&data[0x20..0x40])
+            .trim_end_matches('\0')
+            .to_string();
+
+        let mut crt: Crt = Crt {
+            name,
+            mode,
+            roml: None,
+            romh: None,
+        };
+        let mut offset: usize = header_size.max(0x40);
+        while offset < data.len() {
+            let chip: &[u8] = &data[offset..];
+            if chip.len() < 0x10 || !chip.starts_with(CHIP_SIGNATURE) {
+                return Err(format!("Invalid CHIP packet at offset {:#x}", offset));
+            }
+            let packet_size: usize =
+                u32::from_be_bytes([chip[4], chip[5], chip[6], chip[7]]) as usize;
+            let address: u16 = u16::from_be_bytes([chip[0x0c], chip[0x0d]]);
+            let size: usize = u16::from_be_bytes([chip[0x0e], chip[0x0f]]) as usize;
+            let rom: Vec<u8> = chip
+                .get(0x10..0x10 + size)
+                .ok_or_else(|| format!("CHIP packet at offset {:#x} is truncated", offset))?
+                .to_vec();
+            if rom.is_empty() || packet_size < 0x10 {
+                return Err(format!("Empty CHIP packet at offset {:#x}", offset));
+            }
+
+            match address {
+                // A 16K chip spans both halves
+                ROML_START if rom.len() > BANK_SIZE as usize => {
+                    crt.romh = Some(rom[BANK_SIZE as usize..].to_vec());
+                    crt.roml = Some(rom[..BANK_SIZE as usize].to_vec());
+                }
+                ROML_START => crt.roml = Some(rom),
+                ROMH_START | ULTIMAX_ROMH_START => crt.romh = Some(rom),
+                _ => {
+                    return Err(format!(
+                        "CHIP packet loads at {:#06x}, not a cartridge slot",
+                        address
+                    ))
+                }
+            }
+            offset += packet_size;
+        }
+        Ok(crt)
+    }
+
+    /// Maps the ROMs of the cartridge into `mem` as the EXROM and GAME lines select.
+    pub fn map(&self, mem: &mut Memory) {
+        let romh_start: u16 = match self.mode {
+            Mode::Rom8K | Mode::Off => return self.map_roml(mem),
+            Mode::Rom16K => ROMH_START,
+            Mode::Ultimax => ULTIMAX_ROMH_START,
+        };
+        self.map_roml(mem);
+        if let Some(romh) = &self.romh {
+            let rom: Rc<RefCell<Rom>> = Rc::new(RefCell::new(Rom::new(romh.clone())));
+            mem.map_device(romh_start, romh_start + (BANK_SIZE - 1), rom);
+        }
+    }
+
+    fn map_roml(&self, mem: &mut Memory) {
+        if let (Some(roml), false) = (&self.roml, self.mode == Mode::Off) {
+            let rom: Rc<RefCell<Rom>> = Rc::new(RefCell::new(Rom::new(roml.clone())));
+            mem.map_device(ROML_START, ROML_START + (BANK_SIZE - 1), rom);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_16k_chip_over_both_slots() {
+        let mut data: Vec<u8> = SIGNATURE.to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0x40, 1, 0, 0, NORMAL_CARTRIDGE as u8]);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        data.resize(0x40, 0);
+        data[0x20..0x24].copy_from_slice(b"TEST");
+        data.extend_from_slice(b"CHIP");
+        data.extend_from_slice(&(0x4010u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 0x80, 0x00, 0x40, 0x00]);
+        data.extend((0..0x4000).map(|index: usize| (index >> 13) as u8 + 1));
+
+        let crt: Crt = Crt::parse(&data).unwrap();
+        let mut mem: Memory = Memory::new();
+        crt.map(&mut mem);
+
+        assert_eq!(crt.name, "TEST");
+        assert_eq!(crt.mode, Mode::Rom16K);
+        assert_eq!(mem.read(0x8000), 1);
+        assert_eq!(mem.read(0xbfff), 2);
+        assert_eq!(mem.read(0xc000), 0);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::media;
+
+use std::fmt;
+
+/// Size of a sector, the unit the 1541 reads and writes.
+pub const SECTOR_SIZE: usize = 256;
+/// Track holding the BAM, disk name and directory.
+pub const DIRECTORY_TRACK: u8 = 18;
+
+/// Padding of names on disk, a shifted space.
+const NAME_PADDING: u8 = 0xa0;
+
+/// The kind of a directory entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Del,
+    Seq,
+    Prg,
+    Usr,
+    Rel,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name: &str = match self {
+            FileType::Del => "DEL",
+            FileType::Seq => "SEQ",
+            FileType::Prg => "PRG",
+            FileType::Usr => "USR",
+            FileType::Rel => "REL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A file listed in the directory of a disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name in PETSCII, without its padding.
+    pub name: Vec<u8>,
+    pub file_type: FileType,
+    /// Size in blocks of 254 data bytes, as listed by the drive.
+    pub blocks: u16,
+    /// Track and sector of the first block.
+    first: (u8, u8),
+}
+
+/// A 1541 disk image in the D64 format: every sector of the disk in track order, with
+/// 35 or 40 tracks and optionally an error byte per sector.
+pub struct D64 {
+    data: Vec<u8>,
+    tracks: u8,
+}
+
+impl D64 {
+    /// # Returns
+    /// The image read from `data`, or an error if its size is not one of a D64.
+    pub fn parse(data: Vec<u8>) -> Result<D64, String> {
+        let tracks: u8 = match data.len() {
+            174_848 | 175_531 => 35,
+            196_608 | 197_376 => 40,
+            size => return Err(format!("{} bytes is not the size of a D64 image", size)),
+        };
+        Ok(D64 { data, tracks })
+    }
+
+    fn sectors_in(track: u8) -> u8 {
+        match track {
+            1..=17 => 21,
+            18..=24 => 19,
+            25..=30 => 18,
+            _ => 17,
+        }
+    }
+
+    /// # Returns
+    /// The sector at `track` and `sector`, or `None` if the disk has no such sector.
+    pub fn sector(&self, track: u8, sector: u8) -> Option<&[u8]> {
+        if track == 0 || track > self.tracks || sector >= Self::sectors_in(track) {
+            return None;
+        }
+        let index: usize = (1..track)
+            .map(|track| Self::sectors_in(track) as usize)
+            .sum::<usize>()
+            + sector as usize;
+        Some(&self.data[index * SECTOR_SIZE..(index + 1) * SECTOR_SIZE])
+    }
+
+    /// # Returns
+    /// The sectors of the chain starting at `first`, in order, each cut to the bytes it
+    /// uses after its link. Stops at a broken link or a loop.
+    fn chain(&self, first: (u8, u8)) -> Vec<&[u8]> {
+        let mut sectors: Vec<&[u8]> = Vec::new();
+        let mut next: (u8, u8) = first;
+        // A disk has fewer than 800 sectors, more means the chain loops
+        while sectors.len() <= 800 {
+            let sector: &[u8] = match self.sector(next.0, next.1) {
+                Some(sector) => sector,
+                None => break,
+            };
+            if sector[0] == 0 {
+                // The last sector links to the index of its last byte
+                let last: usize = (sector[1] as usize).max(1);
+                sectors.push(&sector[2..=last]);
+                break;
+            }
+            sectors.push(&sector[2..]);
+            next = (sector[0], sector[1]);
+        }
+        sectors
+    }
+
+    /// # Returns
+    /// The name of the disk in PETSCII, without its padding.
+    pub fn disk_name(&self) -> Vec<u8> {
+        let bam: &[u8] = self.sector(DIRECTORY_TRACK, 0).unwrap();
+        unpad(&bam[0x90..0xa0])
+    }
+
+    /// # Returns
+    /// The files listed in the directory, deleted entries left out.
+    pub fn directory(&self) -> Vec<DirEntry> {
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for sector in self.chain((DIRECTORY_TRACK, 1)) {
+            // With the link of the sector cut, each entry starts with its file type
+            for entry in sector.chunks(32).filter(|entry| entry.len() >= 30) {
+                // Bit 7 marks a closed file, unclosed ones are unreadable
+                let file_type: FileType = match entry[0] {
+                    0x81 => FileType::Seq,
+                    0x82 => FileType::Prg,
+                    0x83 => FileType::Usr,
+                    0x84 => FileType::Rel,
+                    0x80 => FileType::Del,
+                    _ => continue,
+                };
+                entries.push(DirEntry {
+                    name: unpad(&entry[3..19]),
+                    file_type,
+                    blocks: u16::from_le_bytes([entry[28], entry[29]]),
+                    first: (entry[1], entry[2]),
+                });
+            }
+        }
+        entries.retain(|entry| entry.file_type != FileType::Del);
+        entries
+    }
+
+    /// # Returns
+    /// The content of the first file whose name matches `pattern`, with the CBM DOS
+    /// `?` and `*` wildcards, and its directory entry.
+    pub fn read_file(&self, pattern: &[u8]) -> Result<(DirEntry, Vec<u8>), String> {
+        let entry: DirEntry = self
+            .directory()
+            .into_iter()
+            .find(|entry| media::matches_name(pattern, &entry.name))
+            .ok_or_else(|| {
+                format!(
+                    "File `{}` not found on the disk",
+                    String::from_utf8_lossy(pattern)
+                )
+            })?;
+        let content: Vec<u8> = self.chain(entry.first).concat();
+        Ok((entry, content))
+    }
+}
+
+fn unpad(name: &[u8]) -> Vec<u8> {
+    let length: usize = name
+        .iter()
+        .position(|&c| c == NAME_PADDING)
+        .unwrap_or(name.len());
+    name[..length].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(track: u8, sector: u8) -> usize {
+        ((1..track)
+            .map(|track| D64::sectors_in(track) as usize)
+            .sum::<usize>()
+            + sector as usize)
+            * SECTOR_SIZE
+    }
+
+    #[test]
+    fn reads_file_through_directory() {
+        let mut data: Vec<u8> = vec![0; 174_848];
+        let bam: usize = offset(18, 0);
+        data[bam + 0x90..bam + 0xa0].copy_from_slice(b"TEST DISK\xa0\xa0\xa0\xa0\xa0\xa0\xa0");
+
+        // One PRG entry whose data spans two sectors: 254 then 2 bytes
+        let directory: usize = offset(18, 1);
+        data[directory + 1] = 0xff;
+        let entry: &mut [u8] = &mut data[directory + 2..directory + 32];
+        entry[0] = 0x82;
+        entry[1..3].copy_from_slice(&[17, 0]);
+        entry[3..19].copy_from_slice(b"HELLO\xa0\xa0\xa0\xa0\xa0\xa0\xa0\xa0\xa0\xa0\xa0");
+        entry[28] = 2;
+        // And an empty SEQ file in the second entry
+        data[directory + 0x22] = 0x81;
+        data[directory + 0x25..directory + 0x35].fill(NAME_PADDING);
+        data[directory + 0x25..directory + 0x2a].copy_from_slice(b"NOTES");
+        let first: usize = offset(17, 0);
+        data[first..first + 2].copy_from_slice(&[17, 1]);
+        data[first + 2..first + 256].fill(0x11);
+        let second: usize = offset(17, 1);
+        data[second..second + 4].copy_from_slice(&[0, 3, 0x22, 0x33]);
+
+        let disk: D64 = D64::parse(data).unwrap();
+        assert_eq!(disk.disk_name(), b"TEST DISK");
+        assert_eq!(disk.directory()[1].name, b"NOTES");
+
+        let (entry, content) = disk.read_file(b"HEL*").unwrap();
+        assert_eq!(entry.file_type, FileType::Prg);
+        assert_eq!(entry.blocks, 2);
+        assert_eq!(content.len(), 256);
+        assert_eq!(&content[253..], [0x11, 0x22, 0x33]);
+        assert!(disk.read_file(b"HELLO?").is_err());
+        assert!(D64::parse(vec![0; 1000]).is_err());
+    }
+}
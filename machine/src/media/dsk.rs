@@ -0,0 +1,345 @@
+use crate::media;
+
+use mos6502::{Mos6502, StatusFlags, TrapAction};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub const TRACKS: u8 = 35;
+pub const SECTORS_PER_TRACK: u8 = 16;
+pub const SECTOR_SIZE: usize = 256;
+/// The size of a 16-sector image of 35 tracks.
+pub const IMAGE_SIZE: usize = TRACKS as usize * SECTORS_PER_TRACK as usize * SECTOR_SIZE;
+/// Track holding the VTOC and the catalog.
+pub const CATALOG_TRACK: u8 = 17;
+
+/// Where programs call RWTS through, with the address of an IOB in Y (low) and A
+/// (high). DOS places a `JMP` to the real routine there.
+pub const RWTS_ENTRY: u16 = 0x03d9;
+
+/// Offsets in a catalog sector: the link, then 7 entries of 35 bytes.
+const FIRST_ENTRY: usize = 0x0b;
+const ENTRY_SIZE: usize = 35;
+/// Offsets in a track/sector list: the link, then 122 pairs.
+const FIRST_PAIR: usize = 0x0c;
+/// Marks a deleted entry in place of the track of its track/sector list.
+const DELETED: u8 = 0xff;
+/// Bit 7 of the file type locks the file.
+const LOCKED: u8 = 0x80;
+
+// Offsets in an IOB, the block of parameters RWTS is called with.
+const IOB_TRACK: u16 = 0x04;
+const IOB_SECTOR: u16 = 0x05;
+const IOB_BUFFER: u16 = 0x08;
+const IOB_COMMAND: u16 = 0x0c;
+const IOB_ERROR: u16 = 0x0d;
+const IOB_VOLUME: u16 = 0x0e;
+
+// RWTS commands and errors.
+const COMMAND_SEEK: u8 = 0x00;
+const COMMAND_READ: u8 = 0x01;
+const COMMAND_WRITE: u8 = 0x02;
+const ERROR_DRIVE: u8 = 0x40;
+/// The volume reported by RWTS, the one disks are initialized with by default.
+const VOLUME: u8 = 254;
+
+/// The kind of a catalog entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Text,
+    Integer,
+    Applesoft,
+    Binary,
+    S,
+    Relocatable,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name: &str = match self {
+            FileType::Text => "T",
+            FileType::Integer => "I",
+            FileType::Applesoft => "A",
+            FileType::Binary => "B",
+            FileType::S => "S",
+            FileType::Relocatable => "R",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A file listed in the catalog of a disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogEntry {
+    /// The name in ASCII, without its padding.
+    pub name: Vec<u8>,
+    pub file_type: FileType,
+    pub locked: bool,
+    /// Size in sectors, the track/sector lists included, as listed by `CATALOG`.
+    pub sectors: u16,
+    /// Track and sector of the first track/sector list.
+    first_list: (u8, u8),
+}
+
+/// An Apple II disk image in the 16-sector DSK format: the 35 tracks of the disk in
+/// order, the sectors of each in the logical order of DOS 3.3.
+pub struct Dsk {
+    data: Vec<u8>,
+}
+
+impl Dsk {
+    /// # Returns
+    /// The image read from `data`, or an error if its size is not one of a DSK.
+    pub fn parse(data: Vec<u8>) -> Result<Dsk, String> {
+        if data.len() != IMAGE_SIZE {
+            return Err(format!(
+                "{} bytes is not the size of a DSK image",
+                data.len()
+            ));
+        }
+        Ok(Dsk { data })
+    }
+
+    fn offset(track: u8, sector: u8) -> Option<usize> {
+        if track >= TRACKS || sector >= SECTORS_PER_TRACK {
+            return None;
+        }
+        Some((track as usize * SECTORS_PER_TRACK as usize + sector as usize) * SECTOR_SIZE)
+    }
+
+    /// # Returns
+    /// The sector at `track` and `sector`, or `None` if the disk has no such sector.
+    pub fn sector(&self, track: u8, sector: u8) -> Option<&[u8]> {
+        let offset: usize = Self::offset(track, sector)?;
+        Some(&self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    /// # Returns
+    /// The sector at `track` and `sector` to write, or `None` if the disk has no such
+    /// sector.
+    pub fn sector_mut(&mut self, track: u8, sector: u8) -> Option<&mut [u8]> {
+        let offset: usize = Self::offset(track, sector)?;
+        Some(&mut self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    /// # Returns
+    /// The sectors of the chain of catalog or track/sector list sectors starting at
+    /// `first`, which link to the next one at offsets 1 and 2. Stops at a broken link
+    /// or a loop.
+    fn chain(&self, first: (u8, u8)) -> Vec<&[u8]> {
+        let mut sectors: Vec<&[u8]> = Vec::new();
+        let mut next: (u8, u8) = first;
+        while sectors.len() < IMAGE_SIZE / SECTOR_SIZE {
+            let sector: &[u8] = match self.sector(next.0, next.1) {
+                // Track 0 holds DOS, a link there ends the chain
+                Some(sector) if next.0 != 0 => sector,
+                _ => break,
+            };
+            sectors.push(sector);
+            next = (sector[1], sector[2]);
+        }
+        sectors
+    }
+
+    /// # Returns
+    /// The files listed in the catalog, deleted entries left out.
+    pub fn catalog(&self) -> Vec<CatalogEntry> {
+        let vtoc: &[u8] = self.sector(CATALOG_TRACK, 0).unwrap();
+        let mut entries: Vec<CatalogEntry> = Vec::new();
+        for sector in self.chain((vtoc[1], vtoc[2])) {
+            for entry in sector[FIRST_ENTRY..].chunks_exact(ENTRY_SIZE) {
+                // Entries are used in order, a track of 0 ends the catalog
+                if entry[0] == 0x00 {
+                    return entries;
+                }
+                let file_type: FileType = match entry[2] & !LOCKED {
+                    0x00 => FileType::Text,
+                    0x01 => FileType::Integer,
+                    0x02 => FileType::Applesoft,
+                    0x04 => FileType::Binary,
+                    0x08 => FileType::S,
+                    0x10 => FileType::Relocatable,
+                    _ => continue,
+                };
+                if entry[0] == DELETED {
+                    continue;
+                }
+                entries.push(CatalogEntry {
+                    name: unpad(&entry[3..33]),
+                    file_type,
+                    locked: entry[2] & LOCKED != 0,
+                    sectors: u16::from_le_bytes([entry[33], entry[34]]),
+                    first_list: (entry[0], entry[1]),
+                });
+            }
+        }
+        entries
+    }
+
+    /// # Returns
+    /// The content of the first file whose name matches `pattern`, with the `?` and
+    /// `*` wildcards of CBM DOS, and its catalog entry. The content is the data
+    /// sectors up to the first hole, so it is padded to a whole sector.
+    pub fn read_file(&self, pattern: &[u8]) -> Result<(CatalogEntry, Vec<u8>), String> {
+        let entry: CatalogEntry = self
+            .catalog()
+            .into_iter()
+            .find(|entry| media::matches_name(pattern, &entry.name))
+            .ok_or_else(|| {
+                format!(
+                    "File `{}` not found on the disk",
+                    String::from_utf8_lossy(pattern)
+                )
+            })?;
+        let mut content: Vec<u8> = Vec::new();
+        'lists: for list in self.chain(entry.first_list) {
+            for pair in list[FIRST_PAIR..].chunks_exact(2) {
+                match self.sector(pair[0], pair[1]) {
+                    Some(sector) if pair[0] != 0 => content.extend_from_slice(sector),
+                    _ => break 'lists,
+                }
+            }
+        }
+        Ok((entry, content))
+    }
+}
+
+/// # Returns
+/// Where a binary file loads and its data, read from the address and length that
+/// start its content, or an error if it is shorter than its length.
+pub fn binary_file(content: &[u8]) -> Result<(u16, &[u8]), String> {
+    if content.len() < 4 {
+        return Err("Binary file has no header".to_string());
+    }
+    let address: u16 = u16::from_le_bytes([content[0], content[1]]);
+    let length: usize = u16::from_le_bytes([content[2], content[3]]) as usize;
+    let data: &[u8] = content
+        .get(4..4 + length)
+        .ok_or("Binary file is shorter than its length")?;
+    if address as usize + length > memory::MEMORY_SIZE {
+        return Err(format!(
+            "Binary file of {} bytes at {:#06x} does not fit in memory",
+            length, address
+        ));
+    }
+    Ok((address, data))
+}
+
+/// # Returns
+/// `name` in ASCII, without the high bit DOS sets or the spaces it is padded with.
+fn unpad(name: &[u8]) -> Vec<u8> {
+    let name: Vec<u8> = name.iter().map(|c| c & 0x7f).collect();
+    let length: usize = name
+        .iter()
+        .rposition(|&c| c != b' ')
+        .map_or(0, |last| last + 1);
+    name[..length].to_vec()
+}
+
+/// Traps `RWTS_ENTRY` of `cpu` to read and write the sectors of `disk` on the host,
+/// so programs loading their data through RWTS run without DOS or a disk controller.
+///
+/// Seek, read and write are emulated, whatever the slot, drive or volume of the IOB.
+/// Other commands, like format, fail with a drive error.
+pub fn install_rwts(cpu: &mut Mos6502, disk: Rc<RefCell<Dsk>>) {
+    cpu.add_trap(RWTS_ENTRY, move |cpu: &mut Mos6502| {
+        let iob: u16 = u16::from_le_bytes([cpu.y(), cpu.a()]);
+        let memory = cpu.memory().clone();
+        let mut mem = memory.borrow_mut();
+        let track: u8 = mem.read(iob + IOB_TRACK);
+        let sector: u8 = mem.read(iob + IOB_SECTOR);
+        let buffer: u16 =
+            u16::from_le_bytes([mem.read(iob + IOB_BUFFER), mem.read(iob + IOB_BUFFER + 1)]);
+
+        let mut disk = disk.borrow_mut();
+        let done: bool = match (mem.read(iob + IOB_COMMAND), disk.sector_mut(track, sector)) {
+            (COMMAND_SEEK, _) => track < TRACKS,
+            (COMMAND_READ, Some(data)) => {
+                for (offset, byte) in data.iter().enumerate() {
+                    mem.write(buffer.wrapping_add(offset as u16), *byte);
+                }
+                true
+            }
+            (COMMAND_WRITE, Some(data)) => {
+                for (offset, byte) in data.iter_mut().enumerate() {
+                    *byte = mem.read(buffer.wrapping_add(offset as u16));
+                }
+                true
+            }
+            _ => false,
+        };
+
+        mem.write(iob + IOB_VOLUME, VOLUME);
+        let error: u8 = if done { 0x00 } else { ERROR_DRIVE };
+        mem.write(iob + IOB_ERROR, error);
+        drop(mem);
+        cpu.set_a(error);
+        let mut status: StatusFlags = cpu.status();
+        status.set_carry(!done);
+        cpu.set_status(status);
+        TrapAction::Return
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use memory::Memory;
+
+    fn offset(track: u8, sector: u8) -> usize {
+        Dsk::offset(track, sector).unwrap()
+    }
+
+    #[test]
+    fn reads_binary_file_and_sectors_through_rwts() {
+        let mut data: Vec<u8> = vec![0; IMAGE_SIZE];
+        data[offset(17, 0) + 1..offset(17, 0) + 3].copy_from_slice(&[17, 15]);
+        // A deleted entry, then a binary file with its list at 19/15
+        let catalog: usize = offset(17, 15) + FIRST_ENTRY;
+        data[catalog] = DELETED;
+        data[catalog + 2] = 0x04;
+        let entry: &mut [u8] = &mut data[catalog + ENTRY_SIZE..catalog + 2 * ENTRY_SIZE];
+        entry[..3].copy_from_slice(&[19, 15, LOCKED | 0x04]);
+        entry[3..33].fill(0xa0);
+        entry[3..8].copy_from_slice(b"\xc8\xc5\xcc\xcc\xcf");
+        entry[33] = 2;
+        // One data sector at 19/14: $0300, 3 bytes
+        data[offset(19, 15) + FIRST_PAIR..offset(19, 15) + FIRST_PAIR + 2]
+            .copy_from_slice(&[19, 14]);
+        data[offset(19, 14)..offset(19, 14) + 7]
+            .copy_from_slice(&[0x00, 0x03, 0x03, 0x00, 0xea, 0xea, 0x60]);
+
+        let disk: Dsk = Dsk::parse(data).unwrap();
+        let (entry, content) = disk.read_file(b"H*").unwrap();
+        assert_eq!(entry.name, b"HELLO");
+        assert_eq!((entry.file_type, entry.locked), (FileType::Binary, true));
+        assert_eq!(content.len(), SECTOR_SIZE);
+        assert_eq!(binary_file(&content), Ok((0x0300, &[0xea, 0xea, 0x60][..])));
+        assert!(disk.read_file(b"GOODBYE").is_err());
+
+        // JSR RWTS with the IOB at $0380, reading track 19 sector 14 into $2000
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        {
+            let mut mem = mem.borrow_mut();
+            let iob: [u8; 13] = [1, 0x60, 1, 0, 19, 14, 0, 0, 0x00, 0x20, 0, 0, COMMAND_READ];
+            mem.as_mut_slice()[0x0380..0x038d].copy_from_slice(&iob);
+            mem.as_mut_slice()[0x0200..0x0205].copy_from_slice(&[0x20, 0xd9, 0x03, 0x4c, 0x03]);
+            mem.as_mut_slice()[0x0205] = 0x02;
+        }
+        let mut cpu: Mos6502 = Mos6502::new(mem.clone());
+        install_rwts(&mut cpu, Rc::new(RefCell::new(disk)));
+        cpu.set_sp(0xff);
+        cpu.set_pc(0x0200);
+        cpu.set_a(0x03);
+        cpu.set_y(0x80);
+        cpu.set_status(StatusFlags::CARRY);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0203);
+        assert!(!cpu.status().carry());
+        assert_eq!(mem.borrow().read(0x2004), 0xea);
+        assert_eq!(mem.borrow().read(0x038e), VOLUME);
+    }
+}
@@ -0,0 +1,94 @@
+use devices::ppu::Mirroring;
+
+pub const SIGNATURE: &[u8] = b"NES\x1a";
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x2000;
+/// In flags 6: vertical mirroring instead of horizontal.
+const FLAGS_6_VERTICAL: u8 = 0b0000_0001;
+/// In flags 6: 512 bytes of trainer before the PRG-ROM.
+const FLAGS_6_TRAINER: u8 = 0b0000_0100;
+/// NROM, without bank switching.
+const MAPPER_NROM: u8 = 0;
+
+/// A NES cartridge in the iNES format: a header giving the ROM sizes, the mapper and
+/// the nametable mirroring, followed by the PRG-ROM and the CHR-ROM.
+///
+/// Only mapper 0 (NROM) cartridges are supported, others need their bank switching
+/// emulated.
+pub struct INes {
+    /// 16 or 32 KiB of program, seen by the CPU at `0x8000`.
+    pub prg: Vec<u8>,
+    /// The pattern tables seen by the PPU, empty for cartridges with CHR-RAM.
+    pub chr: Vec<u8>,
+    pub mirroring: Mirroring,
+}
+
+impl INes {
+    /// # Returns
+    /// The cartridge read from `data`, or an error if it is not an iNES file or not a
+    /// mapper 0 cartridge.
+    pub fn parse(data: &[u8]) -> Result<INes, String> {
+        if data.len() < HEADER_SIZE || !data.starts_with(SIGNATURE) {
+            return Err("not an iNES cartridge image".to_string());
+        }
+        let (flags_6, flags_7): (u8, u8) = (data[6], data[7]);
+        let mapper: u8 = (flags_7 & 0xf0) | (flags_6 >> 4);
+        if mapper != MAPPER_NROM {
+            return Err(format!(
+                "Cartridge mapper {} is not supported, only mapper 0 (NROM) is",
+                mapper
+            ));
+        }
+
+        let prg_size: usize = data[4] as usize * PRG_BANK_SIZE;
+        let chr_size: usize = data[5] as usize * CHR_BANK_SIZE;
+        let mut offset: usize = HEADER_SIZE;
+        if flags_6 & FLAGS_6_TRAINER != 0 {
+            offset += TRAINER_SIZE;
+        }
+        let prg: &[u8] = data
+            .get(offset..offset + prg_size)
+            .ok_or("iNES PRG-ROM is truncated")?;
+        let chr: &[u8] = data
+            .get(offset + prg_size..offset + prg_size + chr_size)
+            .ok_or("iNES CHR-ROM is truncated")?;
+        let mirroring: Mirroring = if flags_6 & FLAGS_6_VERTICAL != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        Ok(INes {
+            prg: prg.to_vec(),
+            chr: chr.to_vec(),
+            mirroring,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_nrom_cartridge() {
+        let mut data: Vec<u8> = SIGNATURE.to_vec();
+        data.extend_from_slice(&[1, 1, FLAGS_6_VERTICAL, 0]);
+        data.resize(HEADER_SIZE, 0);
+        data.extend(std::iter::repeat_n(0xea, PRG_BANK_SIZE));
+        data.extend(std::iter::repeat_n(0x55, CHR_BANK_SIZE));
+
+        let cartridge: INes = INes::parse(&data).unwrap();
+        assert_eq!(cartridge.prg.len(), PRG_BANK_SIZE);
+        assert_eq!(cartridge.chr, vec![0x55; CHR_BANK_SIZE]);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+
+        // Mapper 1, MMC1
+        data[6] |= 0x10;
+        assert!(INes::parse(&data).is_err());
+        data[6] &= 0x0f;
+        data.truncate(HEADER_SIZE + 0x100);
+        assert!(INes::parse(&data).is_err());
+    }
+}
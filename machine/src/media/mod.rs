@@ -0,0 +1,147 @@
+//! Software distribution formats: disk and tape images and the programs inside them.
+
+pub mod crt;
+pub mod d64;
+pub mod dsk;
+pub mod ines;
+pub mod psid;
+pub mod t64;
+pub mod tap;
+pub mod vsf;
+pub mod xex;
+
+use memory::Memory;
+
+/// Where C64 BASIC programs start.
+pub const BASIC_START: u16 = 0x0801;
+/// Zero page pointers BASIC keeps at the end of the program: the start of the
+/// variables, of the arrays and the end of the arrays.
+const BASIC_END_POINTERS: [u16; 3] = [0x002d, 0x002f, 0x0031];
+
+/// Copies a PRG file, a little-endian load address followed by the data, into RAM
+/// without touching devices, like the KERNAL LOAD does. For a BASIC program the
+/// pointers to its end are set too, so `RUN` works right away.
+///
+/// # Returns
+/// The first and last address loaded, or an error if the file has no data or does not
+/// fit in memory.
+pub fn load_prg(mem: &mut Memory, prg: &[u8]) -> Result<(u16, u16), String> {
+    if prg.len() < 3 {
+        return Err("PRG file has no data".to_string());
+    }
+    let start: u16 = u16::from_le_bytes([prg[0], prg[1]]);
+    let data: &[u8] = &prg[2..];
+    let end: usize = start as usize + data.len();
+    if end > memory::MEMORY_SIZE {
+        return Err(format!(
+            "PRG file of {} bytes at {:#06x} does not fit in memory",
+            data.len(),
+            start
+        ));
+    }
+
+    mem.as_mut_slice()[start as usize..end].copy_from_slice(data);
+    if start == BASIC_START {
+        for pointer in BASIC_END_POINTERS {
+            mem.as_mut_slice()[pointer as usize..pointer as usize + 2]
+                .copy_from_slice(&(end as u16).to_le_bytes());
+        }
+    }
+    Ok((start, (end - 1) as u16))
+}
+
+/// The BASIC token of `SYS`.
+const SYS_TOKEN: u8 = 0x9e;
+
+/// # Returns
+/// The address called by the `SYS` statement that starts the first line of a BASIC
+/// PRG file, the usual loader of machine code programs, if it has one.
+pub fn sys_address(prg: &[u8]) -> Option<u16> {
+    if prg.get(..2)? != BASIC_START.to_le_bytes() {
+        return None;
+    }
+    // After the link to the next line and the line number
+    let mut line = prg.get(6..)?.iter().take_while(|&&c| c != 0x00).peekable();
+    while line.next_if(|&&c| c == b' ').is_some() {}
+    line.next_if_eq(&&SYS_TOKEN)?;
+    while line.next_if(|&&c| c == b' ' || c == b'(').is_some() {}
+    let digits: String = line
+        .take_while(|c| c.is_ascii_digit())
+        .map(|&c| c as char)
+        .collect();
+    digits.parse().ok()
+}
+
+/// A program found on a tape image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TapeFile {
+    /// The name in PETSCII, without its padding.
+    pub name: Vec<u8>,
+    /// Where the data is loaded.
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+impl TapeFile {
+    /// # Returns
+    /// The file in the PRG format, see `load_prg()`.
+    pub fn prg(&self) -> Vec<u8> {
+        [&self.start.to_le_bytes()[..], &self.data].concat()
+    }
+}
+
+/// # Returns
+/// The first file of `files` whose name matches `pattern`, see `matches_name()`.
+pub fn find_file<'a>(files: &'a [TapeFile], pattern: &[u8]) -> Result<&'a TapeFile, String> {
+    files
+        .iter()
+        .find(|file| matches_name(pattern, &file.name))
+        .ok_or_else(|| {
+            format!(
+                "File `{}` not found on the tape",
+                String::from_utf8_lossy(pattern)
+            )
+        })
+}
+
+/// # Returns
+/// `name` without the spaces or shifted spaces it is padded with.
+fn trim_name(name: &[u8]) -> Vec<u8> {
+    let length: usize = name
+        .iter()
+        .rposition(|&c| c != b' ' && c != 0xa0)
+        .map_or(0, |last| last + 1);
+    name[..length].to_vec()
+}
+
+/// # Returns
+/// `true` if the CBM DOS file name `name` matches `pattern`, where `?` matches any
+/// character and `*` the rest of the name.
+pub fn matches_name(pattern: &[u8], name: &[u8]) -> bool {
+    for (index, &c) in pattern.iter().enumerate() {
+        match c {
+            b'*' => return true,
+            b'?' if index < name.len() => {}
+            _ if name.get(index) == Some(&c) => {}
+            _ => return false,
+        }
+    }
+    pattern.len() == name.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_sys_address_of_loader() {
+        // 10 SYS 2061
+        let mut prg: Vec<u8> = vec![0x01, 0x08, 0x0b, 0x08, 0x0a, 0x00, SYS_TOKEN];
+        prg.extend_from_slice(b" 2061\0\0\0");
+        assert_eq!(sys_address(&prg), Some(2061));
+
+        prg[6] = 0x99;
+        assert_eq!(sys_address(&prg), None);
+        assert_eq!(sys_address(&[0x00, 0xc0, 0x60]), None);
+    }
+}
@@ -0,0 +1,309 @@
+use devices::{cia, vic};
+
+use memory::Memory;
+
+const SIGNATURE: &[u8] = b"PSID";
+/// Real C64 tunes, which need the KERNAL and full hardware to run.
+const RSID_SIGNATURE: &[u8] = b"RSID";
+const HEADER_V1_SIZE: usize = 0x76;
+const TEXT_SIZE: usize = 32;
+/// The driver fits in one page of free memory.
+const DRIVER_SIZE: usize = 0x100;
+
+const CIA1: u16 = 0xdc00;
+const CIA2: u16 = 0xdd00;
+const VIC: u16 = 0xd000;
+/// CIA1 timer A latch giving 60 Hz on a PAL machine, as the KERNAL sets it up.
+const CIA_60HZ: u16 = 0x4025;
+/// The raster line vertical blank tunes are called at.
+const PLAY_LINE: u8 = 0x00;
+
+/// A SID tune in the PSID format: a header giving where the tune loads and the
+/// routines that start and play it, followed by the C64 code and data.
+///
+/// Only PSID files are supported: RSID tunes need the whole machine, KERNAL included.
+pub struct Psid {
+    pub name: String,
+    pub author: String,
+    pub released: String,
+    pub load_address: u16,
+    /// Called with the song number minus 1 in A to start a song.
+    pub init_address: u16,
+    /// Called at the rate of the song to play it.
+    pub play_address: u16,
+    pub songs: u16,
+    /// The song played unless another is asked for, from `1`.
+    pub start_song: u16,
+    /// Bit `n` set: song `n + 1` is played from CIA1 timer A instead of the vertical
+    /// blank, see `uses_cia_timer()`.
+    speed: u32,
+    /// A page the tune leaves free for the driver, if the header gives one.
+    free_page: Option<u8>,
+    /// The tune, loaded at `load_address`.
+    pub data: Vec<u8>,
+}
+
+impl Psid {
+    /// # Returns
+    /// The tune read from `data`, or an error if it is not a PSID file or can only be
+    /// played by the KERNAL.
+    pub fn parse(data: &[u8]) -> Result<Psid, String> {
+        if data.starts_with(RSID_SIGNATURE) {
+            return Err(
+                "RSID tunes need the real KERNAL, only PSID tunes can be played".to_string(),
+            );
+        }
+        if data.len() < HEADER_V1_SIZE || !data.starts_with(SIGNATURE) {
+            return Err("not a PSID tune".to_string());
+        }
+        let word = |offset: usize| -> u16 { u16::from_be_bytes([data[offset], data[offset + 1]]) };
+        let version: u16 = word(0x04);
+        let data_offset: usize = word(0x06) as usize;
+        let mut load_address: u16 = word(0x08);
+        let mut init_address: u16 = word(0x0a);
+        let play_address: u16 = word(0x0c);
+        let songs: u16 = word(0x0e).max(1);
+        let start_song: u16 = word(0x10).clamp(1, songs);
+        let speed: u32 = u32::from_be_bytes([data[0x12], data[0x13], data[0x14], data[0x15]]);
+
+        let mut tune: &[u8] = data
+            .get(data_offset..)
+            .ok_or("PSID data offset is past the end of the file")?;
+        // A load address of 0 means the data starts with it, like a PRG file
+        if load_address == 0x0000 {
+            if tune.len() < 2 {
+                return Err("PSID tune has no load address".to_string());
+            }
+            load_address = u16::from_le_bytes([tune[0], tune[1]]);
+            tune = &tune[2..];
+        }
+        if tune.is_empty() || load_address as usize + tune.len() > 0x10000 {
+            return Err("PSID tune does not fit in memory".to_string());
+        }
+        if init_address == 0x0000 {
+            init_address = load_address;
+        }
+        if play_address == 0x0000 {
+            return Err(
+                "The tune installs its own interrupt handler, which needs the KERNAL".to_string(),
+            );
+        }
+        // 0 means anywhere outside the tune, 0xff no page at all
+        let free_page: Option<u8> = match data.get(0x78) {
+            Some(&page) if version >= 2 && page != 0x00 && page != 0xff => Some(page),
+            _ => None,
+        };
+
+        Ok(Psid {
+            name: text(&data[0x16..0x16 + TEXT_SIZE]),
+            author: text(&data[0x36..0x36 + TEXT_SIZE]),
+            released: text(&data[0x56..0x56 + TEXT_SIZE]),
+            load_address,
+            init_address,
+            play_address,
+            songs,
+            start_song,
+            speed,
+            free_page,
+            data: tune.to_vec(),
+        })
+    }
+
+    /// # Returns
+    /// `true` if `song`, from `1`, is played at the rate of CIA1 timer A, 60 Hz unless
+    /// the tune changes it, `false` if once per frame at the vertical blank.
+    pub fn uses_cia_timer(&self, song: u16) -> bool {
+        // Songs past 32 share the last bit
+        let bit: u16 = song.clamp(1, 32) - 1;
+        self.speed & (1 << bit) != 0
+    }
+
+    /// Loads the tune into RAM with a driver that starts `song` and plays it from a
+    /// CIA1 or raster interrupt, with the KERNAL banked out and the I/O chips in.
+    ///
+    /// # Returns
+    /// The address of the driver, to start the CPU at, or an error if `song` is not
+    /// in the tune or no page is free for the driver.
+    pub fn install(&self, mem: &mut Memory, song: u16) -> Result<u16, String> {
+        if !(1..=self.songs).contains(&song) {
+            return Err(format!(
+                "The tune has songs 1 to {}, not {}",
+                self.songs, song
+            ));
+        }
+        let start: usize = self.load_address as usize;
+        let end: usize = start + self.data.len();
+        let driver: u16 = match self.free_page {
+            Some(page) => (page as u16) << 8,
+            // After the zero page and the stack, below the I/O
+            None => (0x02..0xd0)
+                .map(|page: usize| page << 8)
+                .find(|&page| page + DRIVER_SIZE <= start || page >= end)
+                .ok_or("No page of memory is free for the driver")? as u16,
+        };
+
+        mem.as_mut_slice()[start..end].copy_from_slice(&self.data);
+        let (code, irq, nmi): (Vec<u8>, u16, u16) = self.driver(driver, song);
+        let ram: &mut [u8] = mem.as_mut_slice();
+        ram[driver as usize..driver as usize + code.len()].copy_from_slice(&code);
+        // In the RAM under the KERNAL
+        ram[0xfffa..0xfffc].copy_from_slice(&nmi.to_le_bytes());
+        ram[0xfffc..0xfffe].copy_from_slice(&driver.to_le_bytes());
+        ram[0xfffe..].copy_from_slice(&irq.to_le_bytes());
+        Ok(driver)
+    }
+
+    /// # Returns
+    /// The code of the driver assembled at `base`: it sets up the interrupt, starts
+    /// `song` and waits, the interrupt handler calling the play routine. Then the
+    /// addresses of the IRQ and NMI handlers.
+    fn driver(&self, base: u16, song: u16) -> (Vec<u8>, u16, u16) {
+        let mut code: Vec<u8> = Vec::new();
+        let absolute = |code: &mut Vec<u8>, op_code: u8, address: u16| {
+            code.push(op_code);
+            code.extend_from_slice(&address.to_le_bytes());
+        };
+        // SEI, CLD, LDX #$ff, TXS
+        code.extend_from_slice(&[0x78, 0xd8, 0xa2, 0xff, 0x9a]);
+        // RAM everywhere but the I/O: LDA #$2f, STA $00, LDA #$35, STA $01
+        code.extend_from_slice(&[0xa9, 0x2f, 0x85, 0x00, 0xa9, 0x35, 0x85, 0x01]);
+        // Both CIAs quiet: LDA #$7f, STA ICR, LDA ICR to acknowledge
+        code.extend_from_slice(&[0xa9, 0x7f]);
+        absolute(&mut code, 0x8d, CIA1 + cia::ICR);
+        absolute(&mut code, 0x8d, CIA2 + cia::ICR);
+        absolute(&mut code, 0xad, CIA1 + cia::ICR);
+        if self.uses_cia_timer(song) {
+            let [low, high]: [u8; 2] = CIA_60HZ.to_le_bytes();
+            code.extend_from_slice(&[0xa9, low]);
+            absolute(&mut code, 0x8d, CIA1 + cia::TA_LO);
+            code.extend_from_slice(&[0xa9, high]);
+            absolute(&mut code, 0x8d, CIA1 + cia::TA_HI);
+            code.extend_from_slice(&[0xa9, cia::INT_ANY | cia::INT_TA]);
+            absolute(&mut code, 0x8d, CIA1 + cia::ICR);
+            // Started, reloading from the latch
+            code.extend_from_slice(&[0xa9, 0x11]);
+            absolute(&mut code, 0x8d, CIA1 + cia::CRA);
+        } else {
+            // Screen on with bit 8 of the raster compare clear
+            code.extend_from_slice(&[0xa9, 0x1b]);
+            absolute(&mut code, 0x8d, VIC + vic::CONTROL_1);
+            code.extend_from_slice(&[0xa9, PLAY_LINE]);
+            absolute(&mut code, 0x8d, VIC + vic::RASTER);
+            code.extend_from_slice(&[0xa9, 0x01]);
+            absolute(&mut code, 0x8d, VIC + vic::INTERRUPT_ENABLE);
+        }
+        // LDA #song, JSR init, CLI
+        code.extend_from_slice(&[0xa9, (song - 1) as u8]);
+        absolute(&mut code, 0x20, self.init_address);
+        code.push(0x58);
+        // Wait with NOP, JMP back, which is not a trap even where traps are detected
+        let idle: u16 = base + code.len() as u16;
+        code.push(0xea);
+        absolute(&mut code, 0x4c, idle);
+
+        // The handler: PHA, TXA, PHA, TYA, PHA, JSR play, acknowledge both chips
+        let irq: u16 = base + code.len() as u16;
+        code.extend_from_slice(&[0x48, 0x8a, 0x48, 0x98, 0x48]);
+        absolute(&mut code, 0x20, self.play_address);
+        absolute(&mut code, 0xad, CIA1 + cia::ICR);
+        code.extend_from_slice(&[0xa9, 0xff]);
+        absolute(&mut code, 0x8d, VIC + vic::INTERRUPT);
+        // PLA, TAY, PLA, TAX, PLA, RTI
+        code.extend_from_slice(&[0x68, 0xa8, 0x68, 0xaa, 0x68, 0x40]);
+        // NMIs, e.g. RESTORE, are ignored
+        let nmi: u16 = base + code.len() as u16;
+        code.push(0x40);
+
+        debug_assert!(code.len() <= DRIVER_SIZE);
+        (code, irq, nmi)
+    }
+}
+
+/// # Returns
+/// A text field of the header, padded with zeros.
+fn text(field: &[u8]) -> String {
+    field
+        .iter()
+        .take_while(|&&c| c != 0x00)
+        .map(|&c| c as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::preset::{Preset, Roms};
+    use crate::Machine;
+
+    use devices::charset::Charset;
+
+    /// # Returns
+    /// A tune at `0x1000` whose init stores the song at `0xc000` and whose play
+    /// routine counts its calls at `0xc001`.
+    fn counting_tune(speed: u32) -> Vec<u8> {
+        let mut file: Vec<u8> = SIGNATURE.to_vec();
+        file.extend_from_slice(&[0x00, 0x02, 0x00, 0x7c]);
+        // Load address in the data, init at 0x1000, play at 0x1004, 2 songs, song 2
+        file.extend_from_slice(&[0x00, 0x00, 0x10, 0x00, 0x10, 0x04, 0x00, 0x02, 0x00, 0x02]);
+        file.extend_from_slice(&speed.to_be_bytes());
+        file.extend_from_slice(b"Counter");
+        file.resize(0x36, 0x00);
+        file.extend_from_slice(b"Tester");
+        file.resize(0x7c, 0x00);
+        // STA $c000, RTS, INC $c001, RTS
+        file.extend_from_slice(&[0x00, 0x10, 0x8d, 0x00, 0xc0, 0x60, 0xee, 0x01, 0xc0, 0x60]);
+        file
+    }
+
+    fn play(psid: &Psid, song: u16, frames: u32) -> Machine {
+        let mut machine: Machine = Preset::C64Lite
+            .build(Roms::default(), Charset::Petscii)
+            .unwrap();
+        machine.reset();
+        let driver: u16 = psid
+            .install(&mut machine.memory().borrow_mut(), song)
+            .unwrap();
+        machine.cpu_mut().set_pc(driver);
+        for _ in 0..frames {
+            machine.run_frame();
+        }
+        machine
+    }
+
+    #[test]
+    fn plays_at_frame_and_timer_rate() {
+        let psid: Psid = Psid::parse(&counting_tune(0b10)).unwrap();
+        assert_eq!(
+            (psid.name.as_str(), psid.author.as_str()),
+            ("Counter", "Tester")
+        );
+        assert_eq!((psid.load_address, psid.start_song), (0x1000, 2));
+        assert!(!psid.uses_cia_timer(1));
+        assert!(psid.uses_cia_timer(2));
+
+        // One play call per frame at 50 Hz
+        let machine: Machine = play(&psid, 1, 50);
+        let mem = machine.memory().borrow();
+        assert_eq!(mem.read(0xc000), 0x00);
+        assert!(
+            (48..=50).contains(&mem.read(0xc001)),
+            "{}",
+            mem.read(0xc001)
+        );
+        drop(mem);
+
+        // 60 Hz from the timer
+        let machine: Machine = play(&psid, 2, 50);
+        let mem = machine.memory().borrow();
+        assert_eq!(mem.read(0xc000), 0x01);
+        assert!(
+            (58..=60).contains(&mem.read(0xc001)),
+            "{}",
+            mem.read(0xc001)
+        );
+
+        assert!(psid.install(&mut Memory::new(), 3).is_err());
+        assert!(Psid::parse(b"RSID").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::media::{self, TapeFile};
+
+/// Size of the header, before the directory.
+const HEADER_SIZE: usize = 0x40;
+/// Size of a directory entry.
+const ENTRY_SIZE: usize = 0x20;
+
+/// A T64 tape archive: a directory of programs with their load addresses, as stored by
+/// early C64 emulators. It holds no tape signal, only the files.
+pub struct T64 {
+    /// The name of the tape, without its padding.
+    pub name: Vec<u8>,
+    pub files: Vec<TapeFile>,
+}
+
+impl T64 {
+    /// # Returns
+    /// The archive read from `data`, or an error if it is not a T64 archive.
+    pub fn parse(data: &[u8]) -> Result<T64, String> {
+        if data.len() < HEADER_SIZE || !data.starts_with(b"C64") {
+            return Err("not a T64 tape archive".to_string());
+        }
+        let entries: usize = u16::from_le_bytes([data[0x22], data[0x23]]) as usize;
+
+        let mut files: Vec<TapeFile> = Vec::new();
+        for index in 0..entries.max(1) {
+            let entry: &[u8] = match data
+                .get(HEADER_SIZE + index * ENTRY_SIZE..)
+                .and_then(|rest| rest.get(..ENTRY_SIZE))
+            {
+                Some(entry) => entry,
+                None => break,
+            };
+            // 1 is a normal tape file, 0 a free entry and 3 a memory snapshot
+            if entry[0] != 1 {
+                continue;
+            }
+            let start: u16 = u16::from_le_bytes([entry[2], entry[3]]);
+            let end: u16 = u16::from_le_bytes([entry[4], entry[5]]);
+            let offset: usize =
+                u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as usize;
+            // Many archives have a wrong end address, the data then runs to the end of the
+            // file, like other emulators read it
+            let length: usize =
+                (end.wrapping_sub(start) as usize).min(data.len().saturating_sub(offset));
+            files.push(TapeFile {
+                name: media::trim_name(&entry[16..32]),
+                start,
+                data: data[offset.min(data.len())..][..length].to_vec(),
+            });
+        }
+        Ok(T64 {
+            name: media::trim_name(&data[0x28..0x40]),
+            files,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_entry_clamped_to_file() {
+        let mut data: Vec<u8> = vec![0x20; HEADER_SIZE + ENTRY_SIZE];
+        data[..19].copy_from_slice(b"C64 tape image file");
+        data[0x22..0x24].copy_from_slice(&[1, 0]);
+        data[0x28..0x2c].copy_from_slice(b"DEMO");
+        let entry: &mut [u8] = &mut data[HEADER_SIZE..];
+        entry[..6].copy_from_slice(&[1, 0x82, 0x01, 0x08, 0xff, 0xff]);
+        entry[8..12].copy_from_slice(&((HEADER_SIZE + ENTRY_SIZE) as u32).to_le_bytes());
+        entry[16..20].copy_from_slice(b"GAME");
+        data.extend_from_slice(&[0xa9, 0x00, 0x60]);
+
+        let tape: T64 = T64::parse(&data).unwrap();
+
+        assert_eq!(tape.name, b"DEMO");
+        assert_eq!(tape.files.len(), 1);
+        assert_eq!(tape.files[0].name, b"GAME");
+        assert_eq!(tape.files[0].prg(), [0x01, 0x08, 0xa9, 0x00, 0x60]);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::media::{self, TapeFile};
+
+/// Magic at the start of a TAP image.
+const SIGNATURE: &[u8] = b"C64-TAPE-RAW";
+const HEADER_SIZE: usize = 20;
+
+// Pulse lengths from the KERNAL tape routines, in units of 8 cycles as stored in TAP
+// images: short, medium and long are about 0x30, 0x42 and 0x56.
+const SHORT_MEDIUM: u8 = 0x37;
+const MEDIUM_LONG: u8 = 0x4a;
+
+/// The countdown before the first copy of a block, the second copy has bit 7 clear.
+const COUNTDOWN: [u8; 9] = [0x89, 0x88, 0x87, 0x86, 0x85, 0x84, 0x83, 0x82, 0x81];
+
+/// Header block types holding a program, relocatable or not.
+const HEADER_TYPES: [u8; 2] = [0x01, 0x03];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pulse {
+    Short,
+    Medium,
+    Long,
+    /// Too long to be data, e.g. the gap between blocks.
+    Pause,
+}
+
+/// A TAP image: the pulses read by the datasette, as recorded from a real tape.
+///
+/// Programs saved by the KERNAL are decoded to their files directly, an instant load
+/// instead of feeding the signal to the CIA. Turbo loaders use their own encodings and
+/// are not recognized.
+pub struct Tap {
+    pulses: Vec<Pulse>,
+}
+
+impl Tap {
+    /// # Returns
+    /// The image read from `data`, or an error if it is not a TAP image.
+    pub fn parse(data: &[u8]) -> Result<Tap, String> {
+        if data.len() < HEADER_SIZE || !data.starts_with(SIGNATURE) {
+            return Err("not a TAP tape image".to_string());
+        }
+        let version: u8 = data[12];
+
+        let mut pulses: Vec<Pulse> = Vec::new();
+        let mut bytes = data[HEADER_SIZE..].iter();
+        while let Some(&value) = bytes.next() {
+            pulses.push(match value {
+                // Version 1 stores long pauses as 3 bytes of cycles, version 0 as a lone 0
+                0 => {
+                    if version >= 1 {
+                        bytes.nth(2);
+                    }
+                    Pulse::Pause
+                }
+                1..SHORT_MEDIUM => Pulse::Short,
+                SHORT_MEDIUM..MEDIUM_LONG => Pulse::Medium,
+                MEDIUM_LONG..=0x7f => Pulse::Long,
+                _ => Pulse::Pause,
+            });
+        }
+        Ok(Tap { pulses })
+    }
+
+    /// # Returns
+    /// The bytes of every block on the tape, their countdown and checksum included.
+    fn blocks(&self) -> Vec<Vec<u8>> {
+        let pulses: &[Pulse] = &self.pulses;
+        let mut blocks: Vec<Vec<u8>> = Vec::new();
+        let mut block: Vec<u8> = Vec::new();
+        let mut index: usize = 0;
+        while index + 1 < pulses.len() {
+            match (pulses[index], pulses[index + 1]) {
+                // Byte marker, then 8 data bits and the check bit as pulse pairs
+                (Pulse::Long, Pulse::Medium) => {
+                    if let Some(byte) = read_byte(pulses.get(index + 2..index + 20)) {
+                        block.push(byte);
+                        index += 20;
+                        continue;
+                    }
+                }
+                (Pulse::Long, Pulse::Short) | (Pulse::Short | Pulse::Pause, _)
+                    if !block.is_empty() =>
+                {
+                    blocks.push(std::mem::take(&mut block));
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+        if !block.is_empty() {
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    /// # Returns
+    /// The programs on the tape: each header block with the data block following it.
+    /// The second copy of a block is only used if the first is damaged.
+    pub fn files(&self) -> Vec<TapeFile> {
+        let mut files: Vec<TapeFile> = Vec::new();
+        let mut header: Option<(Vec<u8>, u16, u16)> = None;
+        for block in self.blocks() {
+            let payload: &[u8] = match block_payload(&block) {
+                Some(payload) => payload,
+                None => continue,
+            };
+            if payload.len() >= 21 && HEADER_TYPES.contains(&payload[0]) {
+                let start: u16 = u16::from_le_bytes([payload[1], payload[2]]);
+                let end: u16 = u16::from_le_bytes([payload[3], payload[4]]);
+                header = Some((media::trim_name(&payload[5..21]), start, end));
+            } else if let Some((name, start, end)) = header.take() {
+                let length: usize = (end.wrapping_sub(start) as usize).min(payload.len());
+                files.push(TapeFile {
+                    name,
+                    start,
+                    data: payload[..length].to_vec(),
+                });
+            }
+        }
+        files
+    }
+}
+
+/// # Returns
+/// The byte encoded by `pulses`, 9 pairs of short-medium for a 0 bit or medium-short
+/// for a 1 bit, least significant first, then the check bit, or `None` if the pulses
+/// are not a byte.
+fn read_byte(pulses: Option<&[Pulse]>) -> Option<u8> {
+    let mut bits: u16 = 0;
+    for (index, pair) in pulses?.chunks_exact(2).enumerate() {
+        match (pair[0], pair[1]) {
+            (Pulse::Short, Pulse::Medium) => {}
+            (Pulse::Medium, Pulse::Short) => bits |= 1 << index,
+            _ => return None,
+        }
+    }
+    // The check bit makes the count of 1 bits odd
+    if bits.count_ones().is_multiple_of(2) {
+        return None;
+    }
+    Some(bits as u8)
+}
+
+/// # Returns
+/// The data of `block` without its countdown and checksum, or `None` if it is damaged.
+fn block_payload(block: &[u8]) -> Option<&[u8]> {
+    if block.len() < COUNTDOWN.len() + 1 {
+        return None;
+    }
+    let (countdown, rest) = block.split_at(COUNTDOWN.len());
+    let first: bool = countdown == COUNTDOWN;
+    let repeat: bool = countdown.iter().zip(COUNTDOWN).all(|(c, d)| *c == d & 0x7f);
+    let (payload, checksum) = rest.split_at(rest.len() - 1);
+    let valid: bool = payload.iter().fold(0, |sum, byte| sum ^ byte) == checksum[0];
+    ((first || repeat) && valid).then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const S: u8 = 0x30;
+    const M: u8 = 0x42;
+    const L: u8 = 0x56;
+
+    /// Appends `bytes` as a KERNAL block: pilot, countdown, bytes, checksum and end.
+    fn write_block(tap: &mut Vec<u8>, countdown: &[u8], bytes: &[u8]) {
+        tap.extend_from_slice(&[S; 16]);
+        let checksum: u8 = bytes.iter().fold(0, |sum, byte| sum ^ byte);
+        for &byte in countdown.iter().chain(bytes).chain([&checksum]) {
+            tap.extend_from_slice(&[L, M]);
+            for bit in 0..8 {
+                tap.extend_from_slice(if byte & (1 << bit) != 0 {
+                    &[M, S]
+                } else {
+                    &[S, M]
+                });
+            }
+            let check: bool = byte.count_ones().is_multiple_of(2);
+            tap.extend_from_slice(if check { &[M, S] } else { &[S, M] });
+        }
+        tap.extend_from_slice(&[L, S, 0]);
+    }
+
+    #[test]
+    fn decodes_kernal_program() {
+        let mut tap: Vec<u8> = SIGNATURE.to_vec();
+        tap.extend_from_slice(&[0; 8]);
+
+        let mut header: Vec<u8> = vec![0x01, 0x01, 0x08, 0x04, 0x08];
+        header.extend_from_slice(b"HELLO           ");
+        write_block(&mut tap, &COUNTDOWN, &header);
+        // A damaged first copy of the data, then the good second copy
+        let damaged: usize = tap.len() + 16 + COUNTDOWN.len() * 20 + 2;
+        write_block(&mut tap, &COUNTDOWN, &[0xaa, 0xbb, 0xcc]);
+        tap.swap(damaged, damaged + 1);
+        let repeat: Vec<u8> = COUNTDOWN.iter().map(|c| c & 0x7f).collect();
+        write_block(&mut tap, &repeat, &[0xaa, 0xbb, 0xcc]);
+
+        let files: Vec<TapeFile> = Tap::parse(&tap).unwrap().files();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, b"HELLO");
+        assert_eq!(files[0].prg(), [0x01, 0x08, 0xaa, 0xbb, 0xcc]);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::Machine;
+
+use mos6502::{Mos6502, StatusFlags};
+
+const SIGNATURE: &[u8] = b"VICE Snapshot File\x1a";
+/// Follows the machine name in the snapshots of VICE 3 and later.
+const VERSION_SIGNATURE: &[u8] = b"VICE Version\x1a";
+const NAME_SIZE: usize = 16;
+/// The name, the major and minor version and the size of the module, header included.
+const MODULE_HEADER_SIZE: usize = NAME_SIZE + 2 + 4;
+/// GUI and tool versions of VICE save a 4 byte version and a 4 byte revision.
+const VERSION_SIZE: usize = 8;
+const RAM_SIZE: usize = 0x10000;
+
+/// Where the 6510 has its I/O port, the data direction first.
+const PORT_DIRECTION: u16 = 0x0000;
+const PORT_DATA: u16 = 0x0001;
+
+/// The CPU registers saved in a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub status: u8,
+}
+
+/// A snapshot of a C64 saved by VICE: a header naming the machine, followed by one
+/// module for each chip.
+///
+/// Only the MAINCPU module, with the registers, and the C64MEM one, with the RAM and
+/// the 6510 port, are read. The state of the other chips is lost, so a frozen program
+/// resumes with the devices as they are after a reset.
+pub struct Vsf {
+    /// The machine the snapshot was saved from, e.g. `C64SC`.
+    pub machine: String,
+    pub registers: Registers,
+    /// The CPU cycle the snapshot was saved at.
+    pub clock: u32,
+    pub port_direction: u8,
+    pub port_data: u8,
+    pub ram: Vec<u8>,
+}
+
+/// # Returns
+/// `name` without the NUL bytes padding it.
+fn text(name: &[u8]) -> String {
+    String::from_utf8_lossy(name)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+impl Vsf {
+    /// # Returns
+    /// The snapshot read from `data`, or an error if it is not a VICE snapshot of a
+    /// C64 or it lacks the MAINCPU or C64MEM module.
+    pub fn parse(data: &[u8]) -> Result<Vsf, String> {
+        let name_start: usize = SIGNATURE.len() + 2;
+        if data.len() < name_start + NAME_SIZE || !data.starts_with(SIGNATURE) {
+            return Err("not a VICE snapshot".to_string());
+        }
+        let machine: String = text(&data[name_start..name_start + NAME_SIZE]);
+        if !machine.starts_with("C64") {
+            return Err(format!("VICE snapshot of a {}, not of a C64", machine));
+        }
+        let mut offset: usize = name_start + NAME_SIZE;
+        if data[offset..].starts_with(VERSION_SIGNATURE) {
+            offset += VERSION_SIGNATURE.len() + VERSION_SIZE;
+        }
+
+        let mut cpu: Option<(u32, Registers)> = None;
+        let mut memory: Option<(u8, u8, Vec<u8>)> = None;
+        while offset + MODULE_HEADER_SIZE <= data.len() {
+            let header: &[u8] = &data[offset..offset + MODULE_HEADER_SIZE];
+            let size: usize =
+                u32::from_le_bytes([header[18], header[19], header[20], header[21]]) as usize;
+            let module: &[u8] = data
+                .get(offset + MODULE_HEADER_SIZE..offset + size.max(MODULE_HEADER_SIZE))
+                .ok_or("VICE snapshot module is past the end of the file")?;
+            match text(&header[..NAME_SIZE]).as_str() {
+                "MAINCPU" => {
+                    // The clock, A, X, Y, SP, PC and the status
+                    if module.len() < 11 {
+                        return Err("VICE snapshot MAINCPU module is too short".to_string());
+                    }
+                    let registers: Registers = Registers {
+                        a: module[4],
+                        x: module[5],
+                        y: module[6],
+                        sp: module[7],
+                        pc: u16::from_le_bytes([module[8], module[9]]),
+                        status: module[10],
+                    };
+                    let clock: u32 =
+                        u32::from_le_bytes([module[0], module[1], module[2], module[3]]);
+                    cpu = Some((clock, registers));
+                }
+                "C64MEM" => {
+                    // The port data and direction, EXROM, GAME and the RAM
+                    if module.len() < 4 + RAM_SIZE {
+                        return Err("VICE snapshot C64MEM module is too short".to_string());
+                    }
+                    memory = Some((module[1], module[0], module[4..4 + RAM_SIZE].to_vec()));
+                }
+                _ => {}
+            }
+            offset += size.max(MODULE_HEADER_SIZE);
+        }
+
+        let (clock, registers) = cpu.ok_or("VICE snapshot has no MAINCPU module")?;
+        let (port_direction, port_data, ram) =
+            memory.ok_or("VICE snapshot has no C64MEM module")?;
+        Ok(Vsf {
+            machine,
+            registers,
+            clock,
+            port_direction,
+            port_data,
+            ram,
+        })
+    }
+
+    /// Copies the RAM and registers of the snapshot into `machine`, and sets the 6510
+    /// port, banking the ROMs as they were.
+    pub fn restore(&self, machine: &mut Machine) {
+        {
+            let mut mem = machine.memory().borrow_mut();
+            mem.as_mut_slice().copy_from_slice(&self.ram);
+            mem.write(PORT_DIRECTION, self.port_direction);
+            mem.write(PORT_DATA, self.port_data);
+        }
+        let cpu: &mut Mos6502 = machine.cpu_mut();
+        cpu.set_a(self.registers.a);
+        cpu.set_x(self.registers.x);
+        cpu.set_y(self.registers.y);
+        cpu.set_sp(self.registers.sp);
+        cpu.set_pc(self.registers.pc);
+        cpu.set_status(StatusFlags::from_bits(self.registers.status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::preset::{Preset, Roms};
+
+    use devices::charset::Charset;
+
+    fn module(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut module: Vec<u8> = name.as_bytes().to_vec();
+        module.resize(NAME_SIZE, 0x00);
+        module.extend_from_slice(&[1, 1]);
+        module.extend_from_slice(&((MODULE_HEADER_SIZE + content.len()) as u32).to_le_bytes());
+        module.extend_from_slice(content);
+        module
+    }
+
+    #[test]
+    fn restores_cpu_and_memory() {
+        let mut file: Vec<u8> = SIGNATURE.to_vec();
+        file.extend_from_slice(&[2, 0]);
+        file.extend_from_slice(b"C64SC\0\0\0\0\0\0\0\0\0\0\0");
+        file.extend_from_slice(VERSION_SIGNATURE);
+        file.extend_from_slice(&[3, 7, 0, 0, 0, 0, 0, 0]);
+        // CLK, AC, XR, YR, SP, PC, ST, then state that is not read
+        file.extend(module(
+            "MAINCPU",
+            &[
+                0x10, 0x00, 0x00, 0x00, 0x42, 0x01, 0x02, 0xf0, 0x00, 0xc0, 0x24, 0xee,
+            ],
+        ));
+        file.extend(module("CIA1", &[0x00; 8]));
+        let mut ram: Vec<u8> = vec![0x00; RAM_SIZE];
+        // INX, JMP $C001
+        ram[0xc000..0xc004].copy_from_slice(&[0xe8, 0x4c, 0x01, 0xc0]);
+        ram[0xe000] = 0xaa;
+        let mut memory: Vec<u8> = vec![0x35, 0x2f, 0x01, 0x01];
+        memory.extend_from_slice(&ram);
+        file.extend(module("C64MEM", &memory));
+
+        let vsf: Vsf = Vsf::parse(&file).unwrap();
+        assert_eq!(vsf.machine, "C64SC");
+        assert_eq!(vsf.clock, 0x10);
+        assert_eq!((vsf.port_direction, vsf.port_data), (0x2f, 0x35));
+
+        let roms: Roms = Roms {
+            program: Some(vec![0x00; 0x2000]),
+            character: None,
+            basic: None,
+        };
+        let mut machine: Machine = Preset::C64Lite.build(roms, Charset::Petscii).unwrap();
+        machine.reset();
+        vsf.restore(&mut machine);
+        assert_eq!(machine.cpu().pc(), 0xc000);
+        assert_eq!(machine.cpu().sp(), 0xf0);
+        machine.step();
+        assert_eq!(machine.cpu().x(), 0x02);
+        assert_eq!(machine.cpu().a(), 0x42);
+        // The KERNAL is banked out, the RAM below it shows
+        assert_eq!(machine.memory().borrow().read(0xe000), 0xaa);
+        assert_eq!(machine.memory().borrow().read(0x0001) & 0x07, 0x05);
+
+        let name_start: usize = SIGNATURE.len() + 2;
+        file[name_start..name_start + 3].copy_from_slice(b"VIC");
+        assert!(Vsf::parse(&file).is_err());
+    }
+}
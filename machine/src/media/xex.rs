@@ -0,0 +1,146 @@
+use crate::Machine;
+
+use mos6502::Mos6502;
+
+/// Starts the file, and may start any segment.
+const SIGNATURE: u16 = 0xffff;
+/// Where a segment may store the address of a routine to call once it is loaded.
+pub const INITAD: u16 = 0x02e2;
+/// Where a segment may store the address the program starts at once loaded.
+pub const RUNAD: u16 = 0x02e0;
+
+/// A block of the program, loaded from `start` on.
+pub struct Segment {
+    pub start: u16,
+    pub data: Vec<u8>,
+}
+
+/// An Atari 8-bit executable in the DOS binary load format: segments, each with the
+/// first and last address it loads at, the file and any segment starting with $FFFF.
+pub struct Xex {
+    pub segments: Vec<Segment>,
+}
+
+impl Xex {
+    /// # Returns
+    /// The executable read from `data`, or an error if it does not start with $FFFF
+    /// or a segment is past the end of the file.
+    pub fn parse(data: &[u8]) -> Result<Xex, String> {
+        let word = |offset: usize| -> Option<u16> {
+            Some(u16::from_le_bytes([
+                *data.get(offset)?,
+                *data.get(offset + 1)?,
+            ]))
+        };
+        if word(0) != Some(SIGNATURE) {
+            return Err("not an Atari executable".to_string());
+        }
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut offset: usize = 2;
+        while offset < data.len() {
+            if word(offset) == Some(SIGNATURE) {
+                offset += 2;
+            }
+            let (start, end): (u16, u16) = match (word(offset), word(offset + 2)) {
+                (Some(start), Some(end)) => (start, end),
+                _ => return Err(format!("Segment header at {} is cut short", offset)),
+            };
+            if end < start {
+                return Err(format!(
+                    "Segment at {:#06x} ends before it starts, at {:#06x}",
+                    start, end
+                ));
+            }
+            let data_start: usize = offset + 4;
+            let data_end: usize = data_start + (end - start) as usize + 1;
+            let content: &[u8] = data
+                .get(data_start..data_end)
+                .ok_or_else(|| format!("Segment at {:#06x} is past the end of the file", start))?;
+            segments.push(Segment {
+                start,
+                data: content.to_vec(),
+            });
+            offset = data_end;
+        }
+        if segments.is_empty() {
+            return Err("Atari executable has no segments".to_string());
+        }
+        Ok(Xex { segments })
+    }
+
+    /// Loads the segments into the RAM of `machine` one after the other, like DOS
+    /// does. When a segment sets `INITAD`, the routine is called before the next
+    /// segment loads, on a fresh stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `machine` - Where the program is loaded and its init routines run.
+    /// * `max_cycles` - How long each init routine may run before giving up on it.
+    ///
+    /// # Returns
+    ///
+    /// The address the program starts at: the one in `RUNAD` if a segment set it,
+    /// else the start of the first segment, as most loaders do. Or an error if an
+    /// init routine did not return.
+    pub fn load(&self, machine: &mut Machine, max_cycles: u64) -> Result<u16, String> {
+        let set_word = |machine: &mut Machine, address: u16, value: u16| {
+            let mut mem = machine.memory().borrow_mut();
+            let ram: &mut [u8] = mem.as_mut_slice();
+            ram[address as usize..address as usize + 2].copy_from_slice(&value.to_le_bytes());
+        };
+        let word = |machine: &Machine, address: u16| -> u16 {
+            let mem = machine.memory().borrow();
+            let ram: &[u8] = mem.as_slice();
+            u16::from_le_bytes([ram[address as usize], ram[address as usize + 1]])
+        };
+
+        set_word(machine, RUNAD, 0x0000);
+        for segment in &self.segments {
+            set_word(machine, INITAD, 0x0000);
+            let start: usize = segment.start as usize;
+            machine.memory().borrow_mut().as_mut_slice()[start..start + segment.data.len()]
+                .copy_from_slice(&segment.data);
+
+            let init: u16 = word(machine, INITAD);
+            if init != 0x0000 {
+                let cpu: &mut Mos6502 = machine.cpu_mut();
+                cpu.set_sp(0xff);
+                cpu.call(init, max_cycles)?;
+            }
+        }
+
+        Ok(match word(machine, RUNAD) {
+            0x0000 => self.segments[0].start,
+            run => run,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_segments_and_calls_init() {
+        let mut file: Vec<u8> = vec![0xff, 0xff];
+        // INC $80, RTS
+        file.extend_from_slice(&[0x00, 0x30, 0x02, 0x30, 0xe6, 0x80, 0x60]);
+        // INITAD -> $3000, the routine runs before the next segment
+        file.extend_from_slice(&[0xe2, 0x02, 0xe3, 0x02, 0x00, 0x30]);
+        // LDA $80, with its own $FFFF header
+        file.extend_from_slice(&[0xff, 0xff, 0x00, 0x40, 0x01, 0x40, 0xa5, 0x80]);
+        // RUNAD -> $4000
+        file.extend_from_slice(&[0xe0, 0x02, 0xe1, 0x02, 0x00, 0x40]);
+
+        let xex: Xex = Xex::parse(&file).unwrap();
+        assert_eq!(xex.segments.len(), 4);
+        let mut machine: Machine = Machine::new();
+        assert_eq!(xex.load(&mut machine, 1000), Ok(0x4000));
+        assert_eq!(machine.memory().borrow().read(0x0080), 0x01);
+        assert_eq!(machine.memory().borrow().read(0x4001), 0x80);
+
+        assert!(Xex::parse(&file[2..]).is_err());
+        assert!(Xex::parse(&file[..file.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,813 @@
+use crate::media::ines::{self, INes};
+use crate::{Audio, Machine, Video};
+
+use devices::char_output::{self, CharOutput};
+use devices::charset::{Charset, Translated};
+use devices::cia::{self, Cia, InterruptLine};
+use devices::joypad::{self, Joypad};
+use devices::key_matrix::KeyMatrix;
+use devices::keyboard::{self, Keyboard};
+use devices::pia::Pia;
+use devices::ppu::{self, Mirroring, OamDma, Ppu};
+use devices::riot::Riot;
+use devices::sid::{self, Sid, SidModel};
+use devices::tia::Tia;
+use devices::vic::{self, Vic};
+use devices::video::FrameBuffer;
+
+use memory::device::{BusJob, Device, IrqState};
+use memory::ram::Ram;
+use memory::rom::Rom;
+use memory::Memory;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The ROM images to build a machine with.
+#[derive(Clone, Debug, Default)]
+pub struct Roms {
+    /// Mapped in the ROM slot of the preset, see `Preset::rom_slot()`.
+    pub program: Option<Vec<u8>>,
+    /// The character generator, used by machines with a VIC-II, or the CHR-ROM of a
+    /// NES cartridge given as a bare PRG-ROM.
+    pub character: Option<Vec<u8>>,
+    /// The BASIC interpreter, mapped at `0xa000..=0xbfff` on machines that have one.
+    pub basic: Option<Vec<u8>>,
+}
+
+// Bits of the 6510 I/O port the PLA of the C64 decodes, see `C64Banking`.
+/// In the I/O port: BASIC is seen at `0xa000`, if HIRAM is set too.
+const LORAM: u8 = 0b0000_0001;
+/// In the I/O port: the KERNAL is seen at `0xe000`.
+const HIRAM: u8 = 0b0000_0010;
+/// In the I/O port: the I/O chips are seen at `0xd000` instead of the character ROM.
+const CHAREN: u8 = 0b0000_0100;
+
+/// The VIC-II as wired in the C64, looking at the bank selected by CIA2.
+struct C64Video {
+    vic: Rc<RefCell<Vic>>,
+    cia2: Rc<RefCell<Cia>>,
+}
+
+/// CIA1 as wired in the C64, its ports scanning the keyboard matrix.
+struct C64Cia1 {
+    cia: Cia,
+    keys: KeyMatrix,
+}
+
+impl Device for C64Cia1 {
+    fn read(&mut self, offset: u16) -> u8 {
+        if matches!(offset & 0x0f, cia::PRA | cia::PRB) {
+            // Port A drives the columns and port B reads the rows, or the other way round
+            let rows: u8 = self.keys.rows(self.cia.port_a());
+            self.cia.set_port_b_input(rows);
+            let columns: u8 = self.keys.columns(self.cia.port_b());
+            self.cia.set_port_a_input(columns);
+        }
+        self.cia.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.cia.write(offset, value);
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.keys.tick(cycles);
+        self.cia.tick(cycles)
+    }
+}
+
+/// The on-chip I/O port of the 6510, its direction register at `0x0000` and its data
+/// register at `0x0001`, with the PLA switching what the CPU sees from its low bits.
+///
+/// Pins set as inputs are pulled up, so at power on everything is banked in. Without a
+/// ROM image, its range shows the RAM below. Cartridge lines are not decoded.
+struct C64Banking {
+    direction: u8,
+    output: u8,
+    /// The port was written, the banks are switched once the write is done.
+    changed: bool,
+    basic: Option<Rc<RefCell<dyn Device>>>,
+    character: Option<Rc<RefCell<dyn Device>>>,
+    kernal: Option<Rc<RefCell<dyn Device>>>,
+    /// The chips mapped in `0xd000..=0xdfff`, switched all together.
+    io: Vec<Rc<RefCell<dyn Device>>>,
+}
+
+impl C64Banking {
+    /// # Returns
+    /// The level of the port pins.
+    fn lines(&self) -> u8 {
+        (self.output & self.direction) | !self.direction
+    }
+}
+
+impl Device for C64Banking {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            0 => self.direction,
+            _ => self.lines(),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset {
+            0 => self.direction = value,
+            _ => self.output = value,
+        }
+        self.changed = true;
+    }
+
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        let lines: u8 = self.lines();
+        let any_rom: bool = lines & (LORAM | HIRAM) != 0;
+        let mut banks: Vec<(Rc<RefCell<dyn Device>>, bool)> = Vec::new();
+        banks.extend(
+            self.basic
+                .iter()
+                .map(|rom| (rom.clone(), lines & (LORAM | HIRAM) == LORAM | HIRAM)),
+        );
+        banks.extend(
+            self.character
+                .iter()
+                .map(|rom| (rom.clone(), any_rom && lines & CHAREN == 0)),
+        );
+        banks.extend(
+            self.io
+                .iter()
+                .map(|chip| (chip.clone(), any_rom && lines & CHAREN != 0)),
+        );
+        banks.extend(
+            self.kernal
+                .iter()
+                .map(|rom| (rom.clone(), lines & HIRAM != 0)),
+        );
+        // Switching takes no time, the CPU is not held
+        Some(Box::new(move |mem: &mut Memory| {
+            for (device, visible) in &banks {
+                mem.set_visible(device, *visible);
+            }
+            0
+        }))
+    }
+}
+
+/// Maps `data` as a ROM over `start..=end` for reads only, as the C64 does.
+///
+/// # Returns
+/// The ROM, or an error naming it if it is empty or does not fit.
+fn map_banked_rom(
+    mem: &mut Memory,
+    start: u16,
+    end: u16,
+    name: &str,
+    data: Vec<u8>,
+) -> Result<Rc<RefCell<dyn Device>>, String> {
+    let size: usize = (end - start) as usize + 1;
+    if data.is_empty() || data.len() > size {
+        return Err(format!(
+            "{} ROM of {} bytes does not fit its slot of {} bytes at {:#06x}",
+            name,
+            data.len(),
+            size,
+            start
+        ));
+    }
+    let rom: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(Rom::new(data)));
+    mem.map_rom(start, end, rom.clone());
+    Ok(rom)
+}
+
+/// The PIA of the Apple-1, port A reading the keyboard and port B writing the display.
+struct Apple1Pia<W: Write> {
+    pia: Pia,
+    display: W,
+    keys: Receiver<u8>,
+    queue: VecDeque<u8>,
+}
+
+impl<W: Write> Apple1Pia<W> {
+    fn new(display: W) -> (Self, Sender<u8>) {
+        let (sender, keys) = mpsc::channel::<u8>();
+        let mut pia: Pia = Pia::new();
+        // The display is always ready, it pulls PB7 low
+        pia.set_port_b_input(0x7f);
+        let apple1: Apple1Pia<W> = Apple1Pia {
+            pia,
+            display,
+            keys,
+            queue: VecDeque::new(),
+        };
+        (apple1, sender)
+    }
+}
+
+impl<W: Write> Device for Apple1Pia<W> {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.pia.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.pia.write(offset, value);
+        for character in self.pia.take_port_b_writes() {
+            // 7-bit uppercase ASCII, with a carriage return ending the line
+            let _ = match character & 0x7f {
+                b'\r' => self.display.write_all(b"\n"),
+                printable @ 0x20..=0x5f => self.display.write_all(&[printable]),
+                _ => Ok(()),
+            };
+            let _ = self.display.flush();
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.queue.extend(self.keys.try_iter());
+        // The next key is strobed in once the program read the previous one
+        if !self.pia.ca1_flagged() {
+            if let Some(key) = self.queue.pop_front() {
+                let key: u8 = match key {
+                    b'\n' => b'\r',
+                    // Wozmon rubs out with an underscore
+                    0x08 | 0x7f => b'_',
+                    _ => key.to_ascii_uppercase(),
+                };
+                // The keyboard drives bit 7 high
+                self.pia.set_port_a_input(key | 0x80);
+                self.pia.set_ca1(false);
+                self.pia.set_ca1(true);
+            }
+        }
+        // IRQA and IRQB are not connected
+        self.pia.tick(cycles);
+        IrqState::NONE
+    }
+}
+
+/// The RIOT of the 2600, whose interrupt output goes nowhere since the 6507 has no
+/// IRQ pin.
+struct Atari2600Riot(Rc<RefCell<Riot>>);
+
+impl Device for Atari2600Riot {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.0.borrow_mut().read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        self.0.borrow_mut().write(offset, value);
+    }
+
+    fn tick(&mut self, cycles: u32) -> IrqState {
+        self.0.borrow_mut().tick(cycles);
+        IrqState::NONE
+    }
+}
+
+/// The PPU of the NES, which has the picture ready by the end of the frame.
+struct NesVideo(Rc<RefCell<Ppu>>);
+
+impl Video for NesVideo {
+    fn render(&mut self, _mem: &Memory, frame: &mut FrameBuffer) {
+        self.0.borrow().render(frame);
+    }
+}
+
+impl Video for C64Video {
+    fn render(&mut self, mem: &Memory, frame: &mut FrameBuffer) {
+        // The bank bits are inverted, the pull-ups select bank 0 at power on
+        let bank: u8 = 3 - (self.cia2.borrow().port_a() & 0b11);
+        self.vic.borrow().render(mem.as_slice(), bank, frame);
+    }
+}
+
+impl Audio for Rc<RefCell<Sid>> {
+    fn take_samples(&mut self) -> Vec<f32> {
+        self.borrow_mut().take_samples()
+    }
+}
+
+/// A ready-made machine configuration: memory map, ROM slot and clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// 64 KiB of RAM with a character output port at `0xf001` and a keyboard at `0xf004`.
+    /// The ROM is copied into RAM at `0x0000`.
+    Bare,
+    /// The memory map of a C64 with the KERNAL slot at `0xe000..=0xffff`, the VIC-II at
+    /// `0xd000`, a 6581 SID at `0xd400`, color RAM at `0xd800`, CIA1 at `0xdc00` driving
+    /// IRQ and CIA2 at `0xdd00` driving NMI.
+    /// The 6510 I/O port at `0x0000` banks BASIC, the character ROM, the I/O chips and
+    /// the KERNAL in and out, writes always reaching the RAM below the ROMs.
+    C64Lite,
+    /// An Apple-1 with the monitor ROM slot at `0xff00..=0xffff`.
+    Apple1,
+    /// A NES: 2 KiB of RAM mirrored up to `0x1fff`, the PPU at `0x2000` driving NMI, OAM
+    /// DMA at `0x4014`, the controller ports at `0x4016` and the cartridge PRG-ROM at
+    /// `0x8000..=0xffff`.
+    /// The ROM is either an iNES file of a mapper 0 cartridge or a bare PRG-ROM, with
+    /// the CHR-ROM given as the character ROM and vertical mirroring.
+    NesLite,
+    /// An Atari 2600: a 6507 seeing only 13 address lines, the TIA at `0x0000`, the RIOT
+    /// RAM at `0x0080` and its I/O at `0x0280`, all repeated across `0x0000..=0x0fff`,
+    /// and the 4 KiB cartridge slot at `0x1000..=0x1fff`.
+    Atari2600,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Bare,
+        Preset::C64Lite,
+        Preset::Apple1,
+        Preset::NesLite,
+        Preset::Atari2600,
+    ];
+
+    /// # Returns
+    /// The name used to select the preset on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Bare => "bare",
+            Preset::C64Lite => "c64-lite",
+            Preset::Apple1 => "apple1",
+            Preset::NesLite => "nes-lite",
+            Preset::Atari2600 => "atari2600",
+        }
+    }
+
+    /// Parses a preset name, see `name()`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Preset::ALL.iter().map(Preset::name).collect();
+                format!(
+                    "Unknown machine `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+
+    /// # Returns
+    /// The CPU clock of the machine, in Hz.
+    pub fn clock_hz(&self) -> f64 {
+        match self {
+            Preset::Bare => crate::DEFAULT_CLOCK_HZ,
+            Preset::C64Lite => 985_248.0,
+            Preset::Apple1 => 1_022_727.0,
+            Preset::NesLite => 1_789_773.0,
+            Preset::Atari2600 => 1_193_182.0,
+        }
+    }
+
+    /// # Returns
+    /// The video frame rate of the machine, in Hz.
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Preset::Bare => crate::DEFAULT_FRAME_RATE,
+            Preset::C64Lite => 50.0,
+            Preset::Apple1 => 60.0,
+            Preset::NesLite => 60.0988,
+            // 262 scanlines of 76 cycles
+            Preset::Atari2600 => 59.923,
+        }
+    }
+
+    /// # Returns
+    /// The character set programs for the machine print in.
+    pub fn charset(&self) -> Charset {
+        match self {
+            Preset::C64Lite => Charset::Petscii,
+            _ => Charset::Raw,
+        }
+    }
+
+    /// # Returns
+    /// The inclusive address range the ROM is mapped at.
+    /// A ROM smaller than the slot is mirrored across it.
+    pub fn rom_slot(&self) -> (u16, u16) {
+        match self {
+            Preset::Bare => (0x0000, 0xffff),
+            Preset::C64Lite => (0xe000, 0xffff),
+            Preset::Apple1 => (0xff00, 0xffff),
+            Preset::NesLite => (0x8000, 0xffff),
+            Preset::Atari2600 => (0x1000, 0x1fff),
+        }
+    }
+
+    /// Builds the machine with `roms` in their slots, translating the text of its
+    /// console from `charset`. The machine is not reset.
+    ///
+    /// # Returns
+    /// The machine, or an error if the program ROM does not fit the slot or is a
+    /// cartridge image that cannot be used.
+    pub fn build(&self, roms: Roms, charset: Charset) -> Result<Machine, String> {
+        let (start, end): (u16, u16) = self.rom_slot();
+        let slot_size: usize = (end - start) as usize + 1;
+
+        let mut mem: Memory = match self {
+            Preset::Atari2600 => Memory::with_address_bits(13),
+            _ => Memory::new(),
+        };
+        let mut program: Option<Vec<u8>> = roms.program;
+        let mut keys: Option<Sender<u8>> = None;
+        let mut joypads: Option<Rc<RefCell<Joypad>>> = None;
+        let mut video: Option<(Box<dyn Video>, usize, usize)> = None;
+        let mut audio: Option<Box<dyn Audio>> = None;
+        let mut banking: Option<C64Banking> = None;
+        match self {
+            Preset::Bare => {
+                let output: Rc<RefCell<CharOutput<_>>> = Rc::new(RefCell::new(CharOutput::new(
+                    Translated::new(io::stdout(), charset),
+                )));
+                mem.map_device(
+                    char_output::DEFAULT_ADDRESS,
+                    char_output::DEFAULT_ADDRESS,
+                    output,
+                );
+
+                let (device, sender) = Keyboard::new();
+                keys = Some(sender);
+                mem.map_device(
+                    keyboard::DEFAULT_ADDRESS,
+                    keyboard::DEFAULT_ADDRESS + keyboard::STATUS,
+                    Rc::new(RefCell::new(device)),
+                );
+            }
+            Preset::NesLite => {
+                let ram: Rc<RefCell<Ram>> = Rc::new(RefCell::new(Ram::new(0x0800)));
+                mem.map_device(0x0000, 0x1fff, ram);
+
+                let (chr, mirroring): (Vec<u8>, Mirroring) = match program.take() {
+                    Some(rom) if rom.starts_with(ines::SIGNATURE) => {
+                        let cartridge: INes = INes::parse(&rom)?;
+                        program = Some(cartridge.prg);
+                        (cartridge.chr, cartridge.mirroring)
+                    }
+                    rom => {
+                        program = rom;
+                        (roms.character.unwrap_or_default(), Mirroring::Vertical)
+                    }
+                };
+                // The PPU decodes 3 address bits, its registers repeat up to 0x3fff
+                let ppu: Rc<RefCell<Ppu>> = Rc::new(RefCell::new(Ppu::new(chr, mirroring)));
+                mem.map_device(0x2000, 0x3fff, ppu.clone());
+                mem.map_device(0x4014, 0x4014, Rc::new(RefCell::new(OamDma::default())));
+                let joypad: Rc<RefCell<Joypad>> = Rc::new(RefCell::new(Joypad::new()));
+                mem.map_device(0x4016, 0x4016 + joypad::PORT_2, joypad.clone());
+                joypads = Some(joypad);
+                video = Some((Box::new(NesVideo(ppu)), ppu::WIDTH, ppu::HEIGHT));
+            }
+            Preset::C64Lite => {
+                let mut c64_banking: C64Banking = C64Banking {
+                    direction: 0x00,
+                    output: 0x00,
+                    // Applied once the whole map is built
+                    changed: true,
+                    basic: None,
+                    character: None,
+                    kernal: None,
+                    io: Vec::new(),
+                };
+                if let Some(basic) = roms.basic {
+                    c64_banking.basic =
+                        Some(map_banked_rom(&mut mem, 0xa000, 0xbfff, "BASIC", basic)?);
+                }
+                // The VIC-II always sees the character ROM, the CPU only when banked in
+                if let Some(character) = &roms.character {
+                    c64_banking.character = Some(map_banked_rom(
+                        &mut mem,
+                        0xd000,
+                        0xdfff,
+                        "Character",
+                        character.clone(),
+                    )?);
+                }
+
+                // Both CIAs decode only 4 address bits, so they repeat across their page
+                let (matrix, sender) = KeyMatrix::new();
+                keys = Some(sender);
+                let cia1: Rc<RefCell<C64Cia1>> = Rc::new(RefCell::new(C64Cia1 {
+                    cia: Cia::new(InterruptLine::Irq, self.clock_hz()),
+                    keys: matrix,
+                }));
+                mem.map_device(0xdc00, 0xdcff, cia1.clone());
+                let cia2: Rc<RefCell<Cia>> =
+                    Rc::new(RefCell::new(Cia::new(InterruptLine::Nmi, self.clock_hz())));
+                mem.map_device(0xdd00, 0xddff, cia2.clone());
+
+                let vic: Rc<RefCell<Vic>> = Rc::new(RefCell::new(Vic::new()));
+                if let Some(character) = roms.character {
+                    vic.borrow_mut().set_char_rom(character);
+                }
+                mem.map_device(0xd000, 0xd3ff, vic.clone());
+                let color_ram: Rc<RefCell<dyn Device>> =
+                    Rc::new(RefCell::new(Vic::color_ram(&vic)));
+                mem.map_device(0xd800, 0xdbff, color_ram.clone());
+
+                let sid: Rc<RefCell<Sid>> =
+                    Rc::new(RefCell::new(Sid::new(SidModel::Mos6581, self.clock_hz())));
+                mem.map_device(0xd400, 0xd7ff, sid.clone());
+
+                c64_banking.io = vec![cia1, cia2.clone(), vic.clone(), color_ram, sid.clone()];
+                banking = Some(c64_banking);
+                video = Some((Box::new(C64Video { vic, cia2 }), vic::WIDTH, vic::HEIGHT));
+                audio = Some(Box::new(sid));
+            }
+            Preset::Atari2600 => {
+                // The TIA is selected by A7 low, the RIOT by A7 high with A9 choosing
+                // between its RAM and I/O, in every page below the cartridge
+                let tia: Rc<RefCell<Tia>> = Rc::new(RefCell::new(Tia::new()));
+                let riot: Rc<RefCell<Riot>> = Rc::new(RefCell::new(Riot::new()));
+                let riot_ram: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(Riot::ram(&riot)));
+                let riot_io: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(Atari2600Riot(riot)));
+                for page in (0x0000..0x1000).step_by(0x100) {
+                    mem.map_device(page, page + 0x7f, tia.clone());
+                    let riot_half: &Rc<RefCell<dyn Device>> = if page & 0x0200 == 0 {
+                        &riot_ram
+                    } else {
+                        &riot_io
+                    };
+                    mem.map_device(page + 0x80, page + 0xff, riot_half.clone());
+                }
+            }
+            Preset::Apple1 => {
+                let (pia, sender) = Apple1Pia::new(Translated::new(io::stdout(), charset));
+                keys = Some(sender);
+                mem.map_device(0xd010, 0xd013, Rc::new(RefCell::new(pia)));
+            }
+        }
+
+        if let Some(rom) = program {
+            if rom.len() > slot_size {
+                return Err(format!(
+                    "ROM of {} bytes does not fit the {} slot of {} bytes at {:#06x}",
+                    rom.len(),
+                    self.name(),
+                    slot_size,
+                    start
+                ));
+            }
+            match self {
+                // No ROM chip, the program lives in RAM and may modify itself
+                Preset::Bare => mem.as_mut_slice()[..rom.len()].copy_from_slice(&rom),
+                _ if rom.is_empty() => return Err("ROM is empty".to_string()),
+                _ => match &mut banking {
+                    Some(banking) => {
+                        banking.kernal = Some(map_banked_rom(&mut mem, start, end, "KERNAL", rom)?)
+                    }
+                    None => mem.map_device(start, end, Rc::new(RefCell::new(Rom::new(rom)))),
+                },
+            }
+        }
+        if let Some(banking) = banking {
+            let banking: Rc<RefCell<C64Banking>> = Rc::new(RefCell::new(banking));
+            mem.map_device(0x0000, 0x0001, banking.clone());
+            let job: Option<BusJob> = banking.borrow_mut().take_bus_job();
+            if let Some(job) = job {
+                job(&mut mem);
+            }
+        }
+
+        let mut machine: Machine = Machine::with_memory(mem, self.clock_hz(), self.frame_rate());
+        if let Some(keys) = keys {
+            machine.connect_keyboard(keys);
+        }
+        if let Some(joypad) = joypads {
+            machine.connect_joypad(joypad);
+        }
+        if let Some((video, width, height)) = video {
+            machine.connect_video(video, width, height);
+        }
+        if let Some(audio) = audio {
+            machine.connect_audio(audio, sid::SAMPLE_RATE);
+        }
+        Ok(machine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_is_mirrored_and_read_only() {
+        // 16 KiB PRG-ROM whose reset vector points at 0xc000
+        let mut rom: Vec<u8> = vec![0xea; 0x4000];
+        rom[0x3ffc] = 0x00;
+        rom[0x3ffd] = 0xc0;
+        let machine: Machine = Preset::NesLite
+            .build(
+                Roms {
+                    program: Some(rom),
+                    ..Roms::default()
+                },
+                Charset::Raw,
+            )
+            .unwrap();
+
+        let mut mem = machine.memory().borrow_mut();
+        assert_eq!(mem.get_reset_vector(), 0xc000);
+        assert_eq!(mem.read(0x8000), 0xea);
+        mem.write(0x8000, 0x00);
+        assert_eq!(mem.read(0x8000), 0xea);
+
+        // Internal RAM is mirrored every 2 KiB
+        mem.write(0x0001, 0x42);
+        assert_eq!(mem.read(0x1801), 0x42);
+    }
+
+    #[test]
+    fn nes_cartridge_drives_ppu() {
+        let mut rom: Vec<u8> = ines::SIGNATURE.to_vec();
+        rom.extend_from_slice(&[1, 1, 0, 0]);
+        rom.resize(16, 0);
+        rom.extend(std::iter::repeat_n(0xea, 0x4000));
+        rom.extend(std::iter::repeat_n(0x00, 0x2000));
+        let machine: Machine = Preset::NesLite
+            .build(
+                Roms {
+                    program: Some(rom),
+                    ..Roms::default()
+                },
+                Charset::Raw,
+            )
+            .unwrap();
+
+        assert_eq!(
+            machine.frame_buffer().map(FrameBuffer::width),
+            Some(ppu::WIDTH)
+        );
+        let mut mem = machine.memory().borrow_mut();
+        assert_eq!(mem.read(0xc000), 0xea);
+        // Nametable data written through the mirrored PPU registers
+        mem.write(0x3ffe, 0x20);
+        mem.write(0x2006, 0x00);
+        mem.write(0x2007, 0x42);
+        mem.write(0x2006, 0x20);
+        mem.write(0x2006, 0x00);
+        mem.read(0x2007);
+        assert_eq!(mem.read(0x2007), 0x42);
+    }
+
+    #[test]
+    fn atari2600_bus_has_13_lines() {
+        // 2 KiB cartridge whose reset vector points at 0xf000
+        let mut rom: Vec<u8> = vec![0xea; 0x0800];
+        rom[0x07fc] = 0x00;
+        rom[0x07fd] = 0xf0;
+        let machine: Machine = Preset::Atari2600
+            .build(
+                Roms {
+                    program: Some(rom),
+                    ..Roms::default()
+                },
+                Charset::Raw,
+            )
+            .unwrap();
+
+        let mut mem = machine.memory().borrow_mut();
+        assert_eq!(mem.get_reset_vector(), 0xf000);
+        assert_eq!(mem.read(0xf800), 0xea);
+        // Zero page and stack share the RIOT RAM
+        mem.write(0x0080, 0x42);
+        assert_eq!(mem.read(0x01ff - 0x7f), 0x42);
+        assert_eq!(mem.read(0x2080), 0x42);
+        // The fire buttons of the TIA read as released
+        assert_eq!(mem.read(0x003c), 0x80);
+    }
+
+    #[test]
+    fn c64_io_port_banks_roms() {
+        let roms: Roms = Roms {
+            program: Some(vec![0x33; 0x2000]),
+            character: Some(vec![0x22; 0x1000]),
+            basic: Some(vec![0x11; 0x2000]),
+        };
+        let machine: Machine = Preset::C64Lite.build(roms, Charset::Petscii).unwrap();
+        let mut mem = machine.memory().borrow_mut();
+        assert_eq!(mem.read(0xa000), 0x11);
+        assert_eq!(mem.read(0xe000), 0x33);
+        // The I/O chips hide the character ROM, the border color of the VIC-II
+        mem.write(0xd020, 0x06);
+        assert_eq!(mem.read(0xd020) & 0x0f, 0x06);
+        mem.write(0xa000, 0x44);
+        mem.write(0xe000, 0x55);
+
+        // As the KERNAL sets it up, then with LORAM low, CHAREN low and all low
+        mem.write(0x0000, 0x2f);
+        mem.write(0x0001, 0x36);
+        mem.tick_devices(1);
+        assert_eq!(mem.read(0xa000), 0x44);
+        assert_eq!(mem.read(0xe000), 0x33);
+        mem.write(0x0001, 0x33);
+        mem.tick_devices(1);
+        assert_eq!(mem.read(0xa000), 0x11);
+        assert_eq!(mem.read(0xd020), 0x22);
+        mem.write(0x0001, 0x30);
+        mem.tick_devices(1);
+        assert_eq!(mem.read(0xe000), 0x55);
+        assert_eq!(mem.read(0xd020), 0x00);
+        assert_eq!(mem.read(0x0001) & 0x07, 0x00);
+    }
+
+    /// # Returns
+    /// Whether the text screen at `0x0400` shows `text`, in uppercase.
+    fn screen_shows(machine: &Machine, text: &str) -> bool {
+        let codes: Vec<u8> = text
+            .bytes()
+            .map(|c| match c {
+                b'A'..=b'Z' => c - b'A' + 1,
+                _ => c,
+            })
+            .collect();
+        machine
+            .memory()
+            .borrow()
+            .slice(0x0400, 0x07e7)
+            .windows(codes.len())
+            .any(|window| window == codes)
+    }
+
+    fn run_until_shown(machine: &mut Machine, text: &str, max_frames: u32) {
+        for _ in 0..max_frames {
+            machine.run_frame();
+            if screen_shows(machine, text) {
+                return;
+            }
+        }
+        panic!("`{}` never showed on the screen", text);
+    }
+
+    /// Boots the miniature KERNAL and BASIC of `roms/` to the `READY.` prompt and runs
+    /// a program typed on the keyboard, through the I/O port banking, the interrupts of
+    /// CIA1 scanning the keyboard matrix, and the `RTS` dispatch of commands.
+    #[test]
+    fn c64_mini_basic_runs_typed_program() {
+        let assemble =
+            |source: &str| -> Option<Vec<u8>> { Some(asm6502::assemble(source).unwrap().image()) };
+        let roms: Roms = Roms {
+            program: assemble(include_str!("../../roms/c64_mini_kernal.s")),
+            character: None,
+            basic: assemble(include_str!("../../roms/c64_mini_basic.s")),
+        };
+        let mut machine: Machine = Preset::C64Lite.build(roms, Charset::Petscii).unwrap();
+        // The RAM below BASIC, which the KERNAL clears
+        machine.memory().borrow_mut().write(0xa000, 0xaa);
+        machine.reset();
+
+        run_until_shown(&mut machine, "READY.", 50);
+        for key in b"10 PRINT 6*7\rRUN\r" {
+            machine.keyboard().unwrap().send(*key).unwrap();
+        }
+        run_until_shown(&mut machine, " 42", 400);
+
+        let mut mem = machine.memory().borrow_mut();
+        assert_eq!(mem.read(0x0001) & 0x07, 0x07);
+        assert_ne!(mem.read(0xa000), 0x00);
+        mem.write(0x0001, 0x36);
+        mem.tick_devices(1);
+        assert_eq!(mem.read(0xa000), 0x00);
+    }
+
+    /// Boots real C64 ROMs to the `READY.` prompt and runs a BASIC program typed on
+    /// the keyboard. The ROMs are not shipped: `C64_ROMS` names a directory with
+    /// `basic`, `chargen` and `kernal` in it, e.g. from VICE.
+    #[test]
+    #[ignore = "needs the C64 ROMs, see the comment"]
+    fn c64_basic_runs_typed_program() {
+        let directory: String = std::env::var("C64_ROMS").expect("C64_ROMS is not set");
+        let read = |name: &str| -> Option<Vec<u8>> {
+            Some(std::fs::read(format!("{}/{}", directory, name)).expect("cannot read ROM"))
+        };
+        let roms: Roms = Roms {
+            program: read("kernal"),
+            character: read("chargen"),
+            basic: read("basic"),
+        };
+        let mut machine: Machine = Preset::C64Lite.build(roms, Charset::Petscii).unwrap();
+        machine.reset();
+
+        run_until_shown(&mut machine, "READY.", 200);
+        for key in b"10 PRINT 6*7\rRUN\r" {
+            machine.keyboard().unwrap().send(*key).unwrap();
+        }
+        run_until_shown(&mut machine, " 42", 400);
+    }
+
+    #[test]
+    fn rom_must_fit_slot() {
+        let too_big: Roms = Roms {
+            program: Some(vec![0; 0x0200]),
+            ..Roms::default()
+        };
+        assert!(Preset::Apple1.build(too_big, Charset::Raw).is_err());
+        assert!(Preset::parse("c64-lite").is_ok());
+        assert!(Preset::parse("c128").is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! The state of a `Machine` as bytes, with `memory::serialize`, behind the
+//! `serialize` feature.
+
+use crate::Machine;
+use memory::serialize::{take, Deserialize, Serialize};
+use memory::MEMORY_SIZE;
+use mos6502::w65c816::Registers65816;
+use mos6502::{Mos6502, Registers, Variant};
+
+impl Machine {
+    /// # Returns
+    /// The CPU variant and registers, the RAM and the frame count as bytes, for
+    /// `load_state()`. Like `snapshot()`, the state of the devices is not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        self.cpu().variant().serialize(&mut out);
+        self.cpu().snapshot().serialize(&mut out);
+        self.cpu().registers_65816().serialize(&mut out);
+        self.memory().borrow().serialize(&mut out);
+        self.frame.serialize(&mut out);
+        out
+    }
+
+    /// Puts the machine back into the state `save_state()` gave as `bytes`. The
+    /// cycle count, the halt state and the interrupt lines are left alone.
+    ///
+    /// # Returns
+    /// An error if `bytes` do not hold a state, then nothing is changed.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut input: &[u8] = bytes;
+        let variant: Variant = Variant::deserialize(&mut input)?;
+        let registers: Registers = Registers::deserialize(&mut input)?;
+        let registers_65816: Registers65816 = Registers65816::deserialize(&mut input)?;
+        let ram: &[u8] = take(&mut input, MEMORY_SIZE)?;
+        let frame: u64 = u64::from_bytes(input)?;
+
+        let cpu: &mut Mos6502 = self.cpu_mut();
+        cpu.set_variant(variant);
+        cpu.restore(registers);
+        cpu.set_registers_65816(registers_65816);
+        self.memory()
+            .borrow_mut()
+            .as_mut_slice()
+            .copy_from_slice(ram);
+        self.frame = frame;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_loads_back() {
+        let mut machine: Machine = Machine::new();
+        // INX, JMP $0000
+        machine.memory().borrow_mut().as_mut_slice()[..4]
+            .copy_from_slice(&[0xe8, 0x4c, 0x00, 0x00]);
+        machine.reset();
+        machine.run_frame();
+        let state: Vec<u8> = machine.save_state();
+        let (x, frame): (u8, u64) = (machine.cpu().x(), machine.frame());
+
+        machine.run_frame();
+        machine.memory().borrow_mut().write(0x0000, 0x00);
+        assert!(machine.load_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(machine.frame(), frame + 1);
+        machine.load_state(&state).unwrap();
+        assert_eq!((machine.cpu().x(), machine.frame()), (x, frame));
+        assert_eq!(machine.memory().borrow().read(0x0000), 0xe8);
+        assert_eq!(machine.save_state(), state);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::clock::Clock;
+use crate::Machine;
+
+use devices::video::FrameBuffer;
+use mos6502::input_log::InputEvent;
+use mos6502::StatusFlags;
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// What a `Worker` is told to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// Runs frames in real time, sending an update after each of them.
+    Run,
+    /// Stops running, sending an update.
+    Pause,
+    /// Executes one instruction while paused, sending an update.
+    Step,
+    /// Applies an input, see `Machine::input()`.
+    Input(InputEvent),
+    /// Types a key on the keyboard of the machine, if it has one.
+    Key(u8),
+    /// Ends the thread.
+    Stop,
+}
+
+/// The state of the machine after a frame or a command, sent by a `Worker`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Update {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: StatusFlags,
+    pub cycles: u64,
+    pub frame: u64,
+    /// The CPU halted; the worker pauses when it does.
+    pub halted: bool,
+    /// A copy of the last frame rendered, if the machine has a video chip.
+    pub frame_buffer: Option<FrameBuffer>,
+}
+
+impl Update {
+    fn new(machine: &Machine) -> Self {
+        let cpu = machine.cpu();
+        Update {
+            pc: cpu.pc(),
+            a: cpu.a(),
+            x: cpu.x(),
+            y: cpu.y(),
+            sp: cpu.sp(),
+            status: cpu.status(),
+            cycles: cpu.cycles(),
+            frame: machine.frame(),
+            halted: cpu.is_halted(),
+            frame_buffer: machine.frame_buffer().cloned(),
+        }
+    }
+}
+
+/// A machine running on a thread of its own, so a GUI thread can stay responsive.
+///
+/// The machine shares its memory through `Rc`, so it cannot be moved across threads.
+/// Instead, the worker builds it on its thread, then takes `Command`s and sends
+/// `Update`s over channels. The worker starts paused.
+pub struct Worker {
+    commands: Sender<Command>,
+    updates: Receiver<Update>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Starts a thread running the machine made by `build`.
+    pub fn spawn(build: impl FnOnce() -> Machine + Send + 'static) -> Self {
+        let (commands, command_receiver) = mpsc::channel::<Command>();
+        let (update_sender, updates) = mpsc::channel::<Update>();
+        let thread: JoinHandle<()> =
+            std::thread::spawn(move || run(build(), command_receiver, update_sender));
+        Worker {
+            commands,
+            updates,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends `command` to the machine.
+    pub fn send(&self, command: Command) -> Result<(), String> {
+        self.commands
+            .send(command)
+            .map_err(|_| "The emulation thread has stopped".to_string())
+    }
+
+    /// # Returns
+    /// The latest update sent, if any since the last call, skipping older ones.
+    pub fn latest_update(&self) -> Option<Update> {
+        self.updates.try_iter().last()
+    }
+
+    /// # Returns
+    /// The next update, waiting `timeout` at most.
+    pub fn wait_update(&self, timeout: Duration) -> Option<Update> {
+        self.updates.recv_timeout(timeout).ok()
+    }
+
+    /// Stops the thread and waits for it to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.commands.send(Command::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The loop of the emulation thread, until `Command::Stop` or the worker goes away.
+fn run(mut machine: Machine, commands: Receiver<Command>, updates: Sender<Update>) {
+    let mut clock: Clock = Clock::new(machine.clock_hz());
+    let mut running: bool = false;
+    loop {
+        // Running, commands are taken until the next frame is due
+        let command: Option<Command> = if running {
+            match commands.recv_timeout(clock.time_until_cycle(machine.cycles())) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        } else {
+            // Paused, nothing happens until the next command
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            }
+        };
+
+        match command {
+            Some(Command::Run) => {
+                running = true;
+                clock.restart(machine.cycles());
+            }
+            Some(Command::Pause) => {
+                running = false;
+                let _ = updates.send(Update::new(&machine));
+            }
+            Some(Command::Step) if !running => {
+                machine.step();
+                let _ = updates.send(Update::new(&machine));
+            }
+            Some(Command::Step) => {}
+            Some(Command::Input(event)) => machine.input(event),
+            Some(Command::Key(key)) => {
+                if let Some(keyboard) = machine.keyboard() {
+                    let _ = keyboard.send(key);
+                }
+            }
+            Some(Command::Stop) => return,
+            None => {
+                machine.run_frame();
+                running = !machine.cpu().is_halted();
+                if updates.send(Update::new(&machine)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LDX #$FF, TXS, loop: INC $10, JMP loop
+    const PROGRAM: [u8; 8] = [0xA2, 0xFF, 0x9A, 0xE6, 0x10, 0x4C, 0x03, 0x00];
+
+    #[test]
+    fn runs_machine_on_its_thread() {
+        let worker: Worker = Worker::spawn(|| {
+            let mut machine: Machine = Machine::new();
+            machine.cpu_mut().load(0x0000, &PROGRAM);
+            machine.reset();
+            machine
+        });
+        let timeout: Duration = Duration::from_secs(5);
+
+        worker.send(Command::Step).unwrap();
+        assert_eq!(
+            worker
+                .wait_update(timeout)
+                .map(|update| (update.pc, update.x)),
+            Some((0x0002, 0xff))
+        );
+
+        worker.send(Command::Run).unwrap();
+        let update: Update = worker.wait_update(timeout).unwrap();
+        assert_eq!(update.frame, 1);
+        worker.send(Command::Pause).unwrap();
+        worker.stop();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::Memory;
+
+use std::ops::BitOr;
+
+/// Interrupt lines driven by a device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IrqState {
+    /// The IRQ line is asserted.
+    pub irq: bool,
+    /// The NMI line is asserted. The CPU reacts to it going from released to asserted.
+    pub nmi: bool,
+}
+
+impl IrqState {
+    /// No interrupt requested.
+    pub const NONE: IrqState = IrqState {
+        irq: false,
+        nmi: false,
+    };
+    /// Only the IRQ line asserted.
+    pub const IRQ: IrqState = IrqState {
+        irq: true,
+        nmi: false,
+    };
+    /// Only the NMI line asserted.
+    pub const NMI: IrqState = IrqState {
+        irq: false,
+        nmi: true,
+    };
+}
+
+/// Lines are open-collector, so any device asserting one asserts it for the whole bus.
+impl BitOr for IrqState {
+    type Output = IrqState;
+
+    fn bitor(self, other: IrqState) -> IrqState {
+        IrqState {
+            irq: self.irq || other.irq,
+            nmi: self.nmi || other.nmi,
+        }
+    }
+}
+
+/// Work a device does as bus master while RDY holds the CPU off the bus, e.g. a DMA
+/// transfer. It gets the whole memory, the device itself no longer borrowed, and
+/// returns the cycles the CPU waited.
+pub type BusJob = Box<dyn FnOnce(&mut Memory) -> u32>;
+
+/// A block copy within the address space, the simplest `BusJob`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub source: u16,
+    pub destination: u16,
+    /// Bytes to copy, `0` for 65536. Addresses wrap around at the end of memory.
+    pub length: u16,
+}
+
+/// CPU cycles a transfer takes per byte: one to read it and one to write it.
+pub const TRANSFER_CYCLES_PER_BYTE: u32 = 2;
+
+impl Transfer {
+    /// Copies the block through `Memory::read()` and `Memory::write()`, so devices see it.
+    ///
+    /// # Returns
+    /// The cycles the copy took, see `TRANSFER_CYCLES_PER_BYTE`.
+    pub fn run(self, mem: &mut Memory) -> u32 {
+        let length: u32 = match self.length {
+            0 => 0x10000,
+            length => length as u32,
+        };
+        for index in 0..length {
+            let value: u8 = mem.read(self.source.wrapping_add(index as u16));
+            mem.write(self.destination.wrapping_add(index as u16), value);
+        }
+        length * TRANSFER_CYCLES_PER_BYTE
+    }
+
+    /// # Returns
+    /// The transfer as a job for `Device::take_bus_job()`.
+    pub fn job(self) -> BusJob {
+        Box::new(move |mem: &mut Memory| self.run(mem))
+    }
+}
+
+/// A peripheral chip mapped into the address space.
+/// See `Memory::map_device()`.
+pub trait Device {
+    /// Reads the register at `offset` from the start of the mapped range.
+    /// Unlike RAM, a read may have side effects, e.g. acknowledging an interrupt.
+    fn read(&mut self, offset: u16) -> u8;
+
+    /// Writes the register at `offset` from the start of the mapped range.
+    fn write(&mut self, offset: u16, value: u8);
+
+    /// Advances the device by `cycles` CPU cycles.
+    ///
+    /// # Returns
+    /// The state of the interrupt lines the device drives.
+    fn tick(&mut self, _cycles: u32) -> IrqState {
+        IrqState::NONE
+    }
+
+    /// # Returns
+    /// Work the device wants to do as bus master, checked after every `tick()`.
+    fn take_bus_job(&mut self) -> Option<BusJob> {
+        None
+    }
+}
@@ -1,26 +1,402 @@
+pub mod device;
+pub mod ram;
+pub mod rom;
+#[cfg(feature = "serialize")]
+pub mod serialize;
+
+use device::{BusJob, Device, IrqState};
+use std::cell::RefCell;
+use std::rc::Rc;
+
 pub const MEMORY_SIZE: usize = 0x10000;
 
+/// A device mapped over the inclusive address range `start..=end`.
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: Rc<RefCell<dyn Device>>,
+    /// Writes go to the RAM below, see `map_rom()`.
+    read_only: bool,
+    /// Hidden mappings are skipped for reads and writes, see `set_visible()`.
+    visible: bool,
+}
+
+/// A read or write on the bus, see `Memory::set_logging()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
 pub struct Memory {
     data: [u8; MEMORY_SIZE],
+
+    mappings: Vec<Mapping>,
+    /// Every mapped device, once even if it is mapped at several ranges.
+    devices: Vec<Rc<RefCell<dyn Device>>>,
+    /// Number of visible mappings touching each 256 byte page, so unmapped pages skip
+    /// the lookup.
+    mapped_pages: [u8; 0x100],
+    /// Interrupt lines of all devices after the last `tick_devices()`.
+    irq_state: IrqState,
+    /// Cycles the CPU must wait for the bus jobs run since the last `take_stall_cycles()`.
+    stall_cycles: u32,
+    /// The address lines that are connected, see `with_address_bits()`.
+    address_mask: u16,
+    /// The bytes of RAM from `$0000` on, see `with_ram_size()`.
+    ram_size: usize,
+    /// The reads and writes since the last `take_accesses()`, while logging.
+    log: Option<RefCell<Vec<Access>>>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A memory holding `image` from `$0000` on, panicking if it is larger than
+/// `MEMORY_SIZE`.
+impl From<&[u8]> for Memory {
+    fn from(image: &[u8]) -> Self {
+        assert!(
+            image.len() <= MEMORY_SIZE,
+            "Memory image of {} bytes is too large",
+            image.len()
+        );
+        let mut mem: Memory = Memory::new();
+        mem.data[..image.len()].copy_from_slice(image);
+        mem
+    }
 }
 
 impl Memory {
     pub fn new() -> Self {
-        Memory { data: [0; MEMORY_SIZE] }
+        Memory {
+            data: [0; MEMORY_SIZE],
+            mappings: Vec::new(),
+            devices: Vec::new(),
+            mapped_pages: [0; 0x100],
+            irq_state: IrqState::NONE,
+            stall_cycles: 0,
+            address_mask: 0xffff,
+            ram_size: MEMORY_SIZE,
+            log: None,
+        }
+    }
+
+    /// Creates a memory whose address bus only has its low `bits` lines connected,
+    /// like the 13 of the 6507. The upper address bits are ignored, so the space
+    /// repeats every `2^bits` bytes, the vectors included.
+    pub fn with_address_bits(bits: u32) -> Self {
+        Self::with_layout(bits, MEMORY_SIZE)
+    }
+
+    /// Creates a memory with the ROM file at `path` loaded from `start_address` on.
+    ///
+    /// # Returns
+    /// The memory, or an error if the file cannot be read or does not fit.
+    pub fn with_rom(path: &str, start_address: u16) -> Result<Self, String> {
+        let rom: Vec<u8> =
+            std::fs::read(path).map_err(|error| format!("cannot read `{}`: {}", path, error))?;
+        if start_address as usize + rom.len() > MEMORY_SIZE {
+            return Err(format!(
+                "`{}` does not fit at {:#06x}, it has {} bytes",
+                path,
+                start_address,
+                rom.len()
+            ));
+        }
+        let mut mem: Memory = Memory::new();
+        mem.data[start_address as usize..start_address as usize + rom.len()].copy_from_slice(&rom);
+        Ok(mem)
+    }
+
+    /// Creates a memory with only `size` bytes of RAM from `$0000` on, like the 32 KiB
+    /// of many single-board computers. Writes above it are ignored unless a device is
+    /// mapped there, so ROMs go above with `map_rom()`.
+    pub fn with_ram_size(size: usize) -> Self {
+        Self::with_layout(16, size)
+    }
+
+    /// Creates a memory with `bits` address lines, see `with_address_bits()`, and
+    /// `ram_size` bytes of RAM, see `with_ram_size()`.
+    pub fn with_layout(bits: u32, ram_size: usize) -> Self {
+        assert!(
+            (1..=16).contains(&bits),
+            "Invalid address bus width {}",
+            bits
+        );
+        assert!(ram_size <= MEMORY_SIZE, "Invalid RAM size {:#x}", ram_size);
+        Memory {
+            address_mask: (0xffff_u32 >> (16 - bits)) as u16,
+            ram_size,
+            ..Self::new()
+        }
+    }
+
+    /// # Returns
+    /// The mask of the connected address lines, `0xffff` for a full 16-bit bus.
+    pub fn address_mask(&self) -> u16 {
+        self.address_mask
+    }
+
+    /// # Returns
+    /// The bytes of RAM from `$0000` on, `MEMORY_SIZE` unless built with
+    /// `with_ram_size()`.
+    pub fn ram_size(&self) -> usize {
+        self.ram_size
     }
 
     /// Reads a byte from memory at the given address.
+    /// If a device is mapped there, the device is read instead of RAM.
     pub fn read(&self, address: u16) -> u8 {
-        self.data[address as usize]
+        let address: u16 = address & self.address_mask;
+        let value: u8 = match self.mapping(address) {
+            Some(mapping) => mapping.device.borrow_mut().read(address - mapping.start),
+            None => self.data[address as usize],
+        };
+        if let Some(log) = &self.log {
+            log.borrow_mut().push(Access {
+                address,
+                value,
+                write: false,
+            });
+        }
+        value
     }
 
     /// Writes a byte to memory at the given address.
+    /// If a device is mapped there, the device is written instead of RAM, unless it is
+    /// mapped read-only. Writes past the end of the RAM are lost.
     pub fn write(&mut self, address: u16, value: u8) {
-        self.data[address as usize] = value;
+        let address: u16 = address & self.address_mask;
+        if let Some(log) = &mut self.log {
+            log.get_mut().push(Access {
+                address,
+                value,
+                write: true,
+            });
+        }
+        match self.mapping(address) {
+            Some(mapping) if !mapping.read_only => mapping
+                .device
+                .borrow_mut()
+                .write(address - mapping.start, value),
+            _ if (address as usize) < self.ram_size => self.data[address as usize] = value,
+            _ => {}
+        }
+    }
+
+    /// Writes `value` to every address of the inclusive range `start..=end` through
+    /// `write()`, devices included.
+    ///
+    /// Panics if `start` is after `end`.
+    pub fn fill(&mut self, start: u16, end: u16, value: u8) {
+        assert!(start <= end, "Invalid range {:#06x}-{:#06x}", start, end);
+        for address in start..=end {
+            self.write(address, value);
+        }
+    }
+
+    /// Compares the inclusive range `start..=end` with as many bytes from `other` on,
+    /// which wrap at `$FFFF`, all read through `read()`.
+    ///
+    /// # Returns
+    /// The addresses of the first range that hold another value than their counterpart,
+    /// with both values.
+    ///
+    /// Panics if `start` is after `end`.
+    pub fn compare(&self, start: u16, end: u16, other: u16) -> Vec<(u16, u8, u8)> {
+        assert!(start <= end, "Invalid range {:#06x}-{:#06x}", start, end);
+        (start..=end)
+            .map(|address| {
+                let value: u8 = self.read(address);
+                (
+                    address,
+                    value,
+                    self.read(other.wrapping_add(address - start)),
+                )
+            })
+            .filter(|(_, value, other)| value != other)
+            .collect()
+    }
+
+    /// Searches the inclusive range `start..=end` for `pattern`, whose `None` bytes match
+    /// any value, all read through `read()`.
+    ///
+    /// # Returns
+    /// The addresses, in order, each occurrence of `pattern` lying in the range starts at.
+    /// Occurrences may overlap.
+    ///
+    /// Panics if `start` is after `end`.
+    pub fn find(&self, start: u16, end: u16, pattern: &[Option<u8>]) -> Vec<u16> {
+        assert!(start <= end, "Invalid range {:#06x}-{:#06x}", start, end);
+        let range: Vec<u8> = (start..=end).map(|address| self.read(address)).collect();
+        if pattern.is_empty() || pattern.len() > range.len() {
+            return Vec::new();
+        }
+        range
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, bytes)| {
+                bytes
+                    .iter()
+                    .zip(pattern)
+                    .all(|(byte, wanted)| wanted.is_none_or(|wanted| wanted == *byte))
+            })
+            .map(|(offset, _)| start + offset as u16)
+            .collect()
+    }
+
+    /// Starts or stops logging the reads and writes made through `read()` and `write()`,
+    /// see `take_accesses()`. Device bus jobs are logged too.
+    pub fn set_logging(&mut self, enabled: bool) {
+        self.log = enabled.then(|| RefCell::new(Vec::new()));
+    }
+
+    /// # Returns
+    /// The reads and writes logged since the last call, oldest first, none if logging
+    /// is off.
+    pub fn take_accesses(&mut self) -> Vec<Access> {
+        self.log
+            .as_mut()
+            .map_or_else(Vec::new, |log| std::mem::take(log.get_mut()))
+    }
+
+    /// # Returns
+    /// The whole RAM, including the parts hidden by devices, all `MEMORY_SIZE` bytes
+    /// even with a smaller `ram_size()`.
+    /// Unlike `read()`, this never touches a device.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// # Returns
+    /// The whole RAM, including the parts hidden by devices.
+    /// Unlike `write()`, this never touches a device.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// # Returns
+    /// The RAM of the inclusive range `start..=end`, e.g. `0x0400..=0x07e7` for the
+    /// text screen of the C64. Like `as_slice()`, this never touches a device.
+    ///
+    /// Panics if `start` is after `end`.
+    pub fn slice(&self, start: u16, end: u16) -> &[u8] {
+        assert!(start <= end, "Invalid range {:#06x}-{:#06x}", start, end);
+        &self.data[start as usize..=end as usize]
+    }
+
+    /// # Returns
+    /// The RAM of the inclusive range `start..=end`, see `slice()`. Like
+    /// `as_mut_slice()`, this never touches a device.
+    pub fn slice_mut(&mut self, start: u16, end: u16) -> &mut [u8] {
+        assert!(start <= end, "Invalid range {:#06x}-{:#06x}", start, end);
+        &mut self.data[start as usize..=end as usize]
+    }
+
+    /// Maps `device` over the inclusive range `start..=end`, hiding the RAM below it.
+    /// The device sees addresses as offsets from `start`.
+    /// The same device can be mapped at several ranges, e.g. to mirror its registers.
+    /// Later mappings take precedence where ranges overlap.
+    pub fn map_device(&mut self, start: u16, end: u16, device: Rc<RefCell<dyn Device>>) {
+        self.map(start, end, device, false);
+    }
+
+    /// Maps `device` over the inclusive range `start..=end` for reads only: writes go to
+    /// the RAM below it, as they do under the ROMs of the C64.
+    pub fn map_rom(&mut self, start: u16, end: u16, device: Rc<RefCell<dyn Device>>) {
+        self.map(start, end, device, true);
+    }
+
+    /// Shows or hides every mapping of `device`, as a bank switch would. A hidden device
+    /// no longer sees reads and writes, but keeps being ticked.
+    pub fn set_visible(&mut self, device: &Rc<RefCell<dyn Device>>, visible: bool) {
+        for mapping in self
+            .mappings
+            .iter_mut()
+            .filter(|mapping| Rc::ptr_eq(&mapping.device, device) && mapping.visible != visible)
+        {
+            mapping.visible = visible;
+            for page in (mapping.start >> 8)..=(mapping.end >> 8) {
+                if visible {
+                    self.mapped_pages[page as usize] += 1;
+                } else {
+                    self.mapped_pages[page as usize] -= 1;
+                }
+            }
+        }
+    }
+
+    fn map(&mut self, start: u16, end: u16, device: Rc<RefCell<dyn Device>>, read_only: bool) {
+        assert!(
+            start <= end,
+            "Invalid device range {:#06x}-{:#06x}",
+            start,
+            end
+        );
+        for page in (start >> 8)..=(end >> 8) {
+            self.mapped_pages[page as usize] += 1;
+        }
+        if !self.devices.iter().any(|known| Rc::ptr_eq(known, &device)) {
+            self.devices.push(device.clone());
+        }
+        self.mappings.push(Mapping {
+            start,
+            end,
+            device,
+            read_only,
+            visible: true,
+        });
+    }
+
+    /// Advances every mapped device by `cycles` CPU cycles.
+    ///
+    /// # Returns
+    /// The combined interrupt lines of all devices, also available from `irq_state()`.
+    pub fn tick_devices(&mut self, cycles: u32) -> IrqState {
+        self.irq_state = self.devices.iter().fold(IrqState::NONE, |state, device| {
+            state | device.borrow_mut().tick(cycles)
+        });
+
+        for index in 0..self.devices.len() {
+            // Released before running, the job may touch the device itself
+            let job: Option<BusJob> = self.devices[index].borrow_mut().take_bus_job();
+            if let Some(job) = job {
+                self.stall_cycles += job(self);
+            }
+        }
+        self.irq_state
+    }
+
+    /// # Returns
+    /// The cycles the CPU must be stalled for the bus jobs of devices since the
+    /// last call, which resets the count.
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    /// # Returns
+    /// The combined interrupt lines of all devices after the last `tick_devices()`.
+    pub fn irq_state(&self) -> IrqState {
+        self.irq_state
+    }
+
+    fn mapping(&self, address: u16) -> Option<&Mapping> {
+        if self.mapped_pages[(address >> 8) as usize] == 0 {
+            return None;
+        }
+        self.mappings
+            .iter()
+            .rev()
+            .find(|mapping| mapping.visible && mapping.start <= address && address <= mapping.end)
     }
 
     /// Helper function for the CPU only.
-    /// 
+    ///
     /// # Returns
     /// A 16-bit address at location `0xfffc` and `0xfffd`.
     pub fn get_reset_vector(&self) -> u16 {
@@ -31,7 +407,18 @@ impl Memory {
     }
 
     /// Helper function for the CPU only.
-    /// 
+    ///
+    /// # Returns
+    /// A 16-bit address at location `0xfffa` and `0xfffb`.
+    pub fn get_nmi_vector(&self) -> u16 {
+        let low_byte: u8 = self.read(0xfffa);
+        let high_byte: u8 = self.read(0xfffb);
+
+        (high_byte as u16) << 8 | (low_byte as u16)
+    }
+
+    /// Helper function for the CPU only.
+    ///
     /// # Returns
     /// A 16-bit address at location `0xfffe` and `0xffff`.
     pub fn get_interrupt_vector(&self) -> u16 {
@@ -41,12 +428,188 @@ impl Memory {
         (high_byte as u16) << 8 | (low_byte as u16)
     }
 
-    /// Loads a ROM into memory starting at the given address.
+    /// Loads a ROM into memory starting at the given address, wrapping at the end of
+    /// memory.
     pub fn load_rom(&mut self, path: &str, start_address: u16) {
         let rom: Vec<u8> = std::fs::read(path).unwrap();
         for (i, byte) in rom.iter().enumerate() {
-            let address: u16 = start_address + i as u16;
+            let address: u16 = start_address.wrapping_add(i as u16);
             self.write(address, *byte);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Latches writes and raises IRQ once `countdown` cycles have passed.
+    struct TestDevice {
+        latch: u8,
+        countdown: u32,
+    }
+
+    impl Device for TestDevice {
+        fn read(&mut self, offset: u16) -> u8 {
+            self.latch.wrapping_add(offset as u8)
+        }
+
+        fn write(&mut self, _offset: u16, value: u8) {
+            self.latch = value;
+        }
+
+        fn tick(&mut self, cycles: u32) -> IrqState {
+            self.countdown = self.countdown.saturating_sub(cycles);
+            if self.countdown == 0 {
+                IrqState::IRQ
+            } else {
+                IrqState::NONE
+            }
+        }
+    }
+
+    #[test]
+    fn device_hides_ram() {
+        let mut mem: Memory = Memory::new();
+        let device = Rc::new(RefCell::new(TestDevice {
+            latch: 0,
+            countdown: 10,
+        }));
+        mem.write(0xd000, 0x11);
+        mem.write(0xd010, 0x22);
+        mem.map_device(0xd000, 0xd00f, device.clone());
+
+        mem.write(0xd000, 0x40);
+
+        assert_eq!(mem.read(0xd000), 0x40);
+        assert_eq!(mem.read(0xd003), 0x43);
+        assert_eq!(mem.read(0xd010), 0x22);
+        assert_eq!(device.borrow().latch, 0x40);
+    }
+
+    #[test]
+    fn fills_and_compares_ranges() {
+        let mut mem: Memory = Memory::new();
+        mem.fill(0x1000, 0x10ff, 0xea);
+        mem.fill(0x2000, 0x20ff, 0xea);
+        mem.write(0x2010, 0x00);
+
+        assert_eq!(
+            mem.slice(0x0fff, 0x1100),
+            [[0x00].as_slice(), &[0xea; 0x100], &[0x00]].concat()
+        );
+        assert_eq!(
+            mem.compare(0x1000, 0x10ff, 0x2000),
+            vec![(0x1010, 0xea, 0x00)]
+        );
+        assert_eq!(mem.compare(0x1000, 0x100f, 0x2000), Vec::new());
+    }
+
+    #[test]
+    fn finds_patterns_with_wildcards() {
+        let mut mem: Memory = Memory::new();
+        mem.slice_mut(0x1000, 0x1005)
+            .copy_from_slice(&[0xa9, 0x01, 0x8d, 0xa9, 0x02, 0x8d]);
+
+        assert_eq!(
+            mem.find(0x0000, 0xffff, &[Some(0xa9), None, Some(0x8d)]),
+            vec![0x1000, 0x1003]
+        );
+        assert_eq!(
+            mem.find(0x1000, 0x1004, &[Some(0xa9), None, Some(0x8d)]),
+            vec![0x1000]
+        );
+        assert_eq!(
+            mem.find(0x1001, 0x1001, &[Some(0x01), Some(0x8d)]),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn narrow_address_bus_repeats() {
+        let mut mem: Memory = Memory::with_address_bits(13);
+        mem.write(0x1ffc, 0x34);
+        mem.write(0xfffd, 0x12);
+
+        assert_eq!(mem.read(0x1ffd), 0x12);
+        assert_eq!(mem.get_reset_vector(), 0x1234);
+        assert_eq!(mem.as_slice()[0xfffd], 0x00);
+    }
+
+    #[test]
+    fn builds_from_image_and_rom_file() {
+        let mem: Memory = Memory::from(&[0xa9, 0x01][..]);
+        assert_eq!((mem.read(0x0000), mem.read(0x0001)), (0xa9, 0x01));
+
+        let path: std::path::PathBuf = std::env::temp_dir().join("memory_with_rom.bin");
+        std::fs::write(&path, [0x4c, 0x00, 0xe0]).unwrap();
+        let mem: Memory = Memory::with_rom(path.to_str().unwrap(), 0xe000).unwrap();
+        assert_eq!(mem.read(0xe002), 0xe0);
+        assert!(Memory::with_rom(path.to_str().unwrap(), 0xfffe).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(Memory::with_rom(path.to_str().unwrap(), 0x0000).is_err());
+    }
+
+    #[test]
+    fn writes_past_small_ram_are_lost() {
+        let mut mem: Memory = Memory::with_ram_size(0x8000);
+        let rom: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(rom::Rom::new(vec![0xea])));
+        mem.map_rom(0xc000, 0xc000, rom);
+        mem.write(0x7fff, 0x12);
+        mem.write(0x8000, 0x34);
+        mem.write(0xc000, 0x56);
+
+        assert_eq!(mem.ram_size(), 0x8000);
+        assert_eq!(mem.read(0x7fff), 0x12);
+        assert_eq!(mem.read(0x8000), 0x00);
+        assert_eq!(mem.read(0xc000), 0xea);
+    }
+
+    #[test]
+    fn banked_rom_and_hidden_device() {
+        let mut mem: Memory = Memory::new();
+        let rom: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(rom::Rom::new(vec![0xea])));
+        let device = Rc::new(RefCell::new(TestDevice {
+            latch: 0,
+            countdown: 10,
+        }));
+        mem.map_rom(0xe000, 0xffff, rom.clone());
+        mem.map_device(0xd000, 0xd0ff, device.clone());
+
+        // Writes go under the ROM
+        mem.write(0xe000, 0x42);
+        assert_eq!(mem.read(0xe000), 0xea);
+        mem.set_visible(&rom, false);
+        assert_eq!(mem.read(0xe000), 0x42);
+
+        let hidden: Rc<RefCell<dyn Device>> = device.clone();
+        mem.set_visible(&hidden, false);
+        mem.set_visible(&hidden, false);
+        mem.write(0xd000, 0x11);
+        assert_eq!(device.borrow().latch, 0x00);
+        assert_eq!(mem.tick_devices(10), IrqState::IRQ);
+        mem.set_visible(&hidden, true);
+        assert_eq!(mem.read(0xd000), 0x00);
+        assert_eq!(mem.as_slice()[0xd000], 0x11);
+        assert_eq!(mem.slice(0xcfff, 0xd001), [0x00, 0x11, 0x00]);
+        mem.slice_mut(0xd001, 0xd002).copy_from_slice(&[0x22, 0x33]);
+        assert_eq!(mem.slice(0xd000, 0xd002), [0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn mirrored_device_ticks_once() {
+        let mut mem: Memory = Memory::new();
+        let device = Rc::new(RefCell::new(TestDevice {
+            latch: 0,
+            countdown: 10,
+        }));
+        mem.map_device(0xd000, 0xd00f, device.clone());
+        mem.map_device(0xd010, 0xd01f, device.clone());
+
+        assert_eq!(mem.read(0xd012), 0x02);
+        assert_eq!(mem.tick_devices(6), IrqState::NONE);
+        assert_eq!(mem.tick_devices(6), IrqState::IRQ);
+        assert_eq!(mem.irq_state(), IrqState::IRQ);
+        assert_eq!(device.borrow().countdown, 0);
+    }
+}
@@ -0,0 +1,28 @@
+use crate::device::Device;
+
+/// A RAM chip smaller than the range it is mapped over, e.g. the 2 KiB of the NES
+/// mirrored across `0x0000..=0x1fff`. Addresses wrap around every `size` bytes.
+pub struct Ram {
+    data: Vec<u8>,
+}
+
+impl Ram {
+    /// Creates `size` bytes of zeroed RAM. `size` must not be zero.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "RAM size must not be zero");
+        Ram {
+            data: vec![0; size],
+        }
+    }
+}
+
+impl Device for Ram {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.data[offset as usize % self.data.len()]
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let index: usize = offset as usize % self.data.len();
+        self.data[index] = value;
+    }
+}
@@ -0,0 +1,32 @@
+use crate::device::Device;
+
+/// Read-only memory. Writes are ignored.
+/// Reads past the end of the image wrap around, so a small ROM mapped over a larger
+/// range is mirrored across it.
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    /// Creates a ROM holding `data`, which must not be empty.
+    pub fn new(data: Vec<u8>) -> Self {
+        assert!(!data.is_empty(), "ROM image is empty");
+        Rom { data }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl Device for Rom {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.data[offset as usize % self.data.len()]
+    }
+
+    fn write(&mut self, _offset: u16, _value: u8) {}
+}
@@ -0,0 +1,134 @@
+//! Serialization of the emulator state, behind the `serialize` feature.
+//!
+//! serde cannot be a dependency of this std-only tree, so these traits stand in for
+//! its `Serialize` and `Deserialize`, with one format: the fields in order, integers
+//! little-endian, `bool` as a byte. The crates of the CPU and the machine implement
+//! them for their state, and snapshots, golden states or a network link can share
+//! the bytes.
+
+use crate::{Memory, MEMORY_SIZE};
+
+/// A value that can be written as bytes, see the module documentation.
+pub trait Serialize {
+    /// Appends the bytes of the value to `out`.
+    fn serialize(&self, out: &mut Vec<u8>);
+
+    /// # Returns
+    /// The bytes of the value.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::new();
+        self.serialize(&mut out);
+        out
+    }
+}
+
+/// A value that can be read back from the bytes `Serialize` wrote.
+pub trait Deserialize: Sized {
+    /// Reads the value from the start of `input`, which is moved past its bytes.
+    ///
+    /// # Returns
+    /// The value, or an error if `input` is too short or holds an invalid value.
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String>;
+
+    /// # Returns
+    /// The value of `bytes`, or an error if they hold anything else, or more.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut input: &[u8] = bytes;
+        let value: Self = Self::deserialize(&mut input)?;
+        if !input.is_empty() {
+            return Err(format!("{} bytes left over", input.len()));
+        }
+        Ok(value)
+    }
+}
+
+/// # Returns
+/// The next `count` bytes of `input`, which is moved past them, or an error if it is
+/// shorter.
+pub fn take<'a>(input: &mut &'a [u8], count: usize) -> Result<&'a [u8], String> {
+    if input.len() < count {
+        return Err(format!(
+            "{} bytes expected, only {} left",
+            count,
+            input.len()
+        ));
+    }
+    let (taken, rest): (&[u8], &[u8]) = input.split_at(count);
+    *input = rest;
+    Ok(taken)
+}
+
+macro_rules! integer {
+    ($($type:ty),*) => {
+        $(
+            impl Serialize for $type {
+                fn serialize(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Deserialize for $type {
+                fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+                    let bytes: &[u8] = take(input, std::mem::size_of::<$type>())?;
+                    Ok(<$type>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+integer!(u8, u16, u32, u64);
+
+impl Serialize for bool {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Deserialize for bool {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        match u8::deserialize(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(format!("{:#04x} is not a bool", value)),
+        }
+    }
+}
+
+/// The whole RAM, `MEMORY_SIZE` bytes. Devices are not included: they are mapped
+/// again by whoever builds the memory.
+impl Serialize for Memory {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_slice());
+    }
+}
+
+impl Deserialize for Memory {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(Memory::from(take(input, MEMORY_SIZE)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_wrote() {
+        let mut mem: Memory = Memory::new();
+        mem.write(0x1234, 0x56);
+        let mut bytes: Vec<u8> = 0x0102_u16.to_bytes();
+        true.serialize(&mut bytes);
+        mem.serialize(&mut bytes);
+        assert_eq!(bytes[..3], [0x02, 0x01, 0x01]);
+
+        let mut input: &[u8] = &bytes;
+        assert_eq!(u16::deserialize(&mut input), Ok(0x0102));
+        assert_eq!(bool::deserialize(&mut input), Ok(true));
+        assert_eq!(Memory::from_bytes(input).unwrap().read(0x1234), 0x56);
+
+        assert!(u32::from_bytes(&[0x01, 0x02]).is_err());
+        assert!(bool::from_bytes(&[0x02]).is_err());
+        assert!(u8::from_bytes(&[0x01, 0x02]).is_err());
+    }
+}
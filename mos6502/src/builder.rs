@@ -0,0 +1,139 @@
+use crate::{Mos6502, StatusFlags, Variant};
+
+use memory::Memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Sets up a `Mos6502` in the state a test or a host program wants it in, see
+/// `Mos6502::builder()`.
+///
+/// The built CPU is reset, then given the registers that were set, so it can run
+/// right away. Registers left alone keep their value after a reset, PC that of the
+/// reset vector.
+pub struct Mos6502Builder {
+    mem: Option<Rc<RefCell<Memory>>>,
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    status: StatusFlags,
+    pc: Option<u16>,
+    variant: Variant,
+    trap_detection: bool,
+}
+
+impl Mos6502Builder {
+    pub(crate) fn new() -> Self {
+        Mos6502Builder {
+            mem: None,
+            a: 0x00,
+            x: 0x00,
+            y: 0x00,
+            sp: 0x00,
+            status: StatusFlags::empty(),
+            pc: None,
+            variant: Variant::Nmos,
+            trap_detection: false,
+        }
+    }
+
+    /// Connects the CPU to `mem`, shared with devices or other CPUs. By default it
+    /// gets 64 KiB of RAM of its own.
+    pub fn memory(mut self, mem: Rc<RefCell<Memory>>) -> Self {
+        self.mem = Some(mem);
+        self
+    }
+
+    pub fn a(mut self, a: u8) -> Self {
+        self.a = a;
+        self
+    }
+
+    pub fn x(mut self, x: u8) -> Self {
+        self.x = x;
+        self
+    }
+
+    pub fn y(mut self, y: u8) -> Self {
+        self.y = y;
+        self
+    }
+
+    pub fn sp(mut self, sp: u8) -> Self {
+        self.sp = sp;
+        self
+    }
+
+    /// Sets the processor status register.
+    pub fn status(mut self, status: StatusFlags) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Starts the CPU at `pc` instead of the reset vector.
+    pub fn pc(mut self, pc: u16) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    /// Emulates `variant` instead of the NMOS 6502.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Halts the CPU on a `JMP` or branch to itself, see `Mos6502::set_trap_detection()`.
+    pub fn trap_detection(mut self, enabled: bool) -> Self {
+        self.trap_detection = enabled;
+        self
+    }
+
+    /// # Returns
+    /// The CPU, reset and with the registers set.
+    pub fn build(self) -> Mos6502 {
+        let mem: Rc<RefCell<Memory>> = self
+            .mem
+            .unwrap_or_else(|| Rc::new(RefCell::new(Memory::new())));
+        let mut cpu: Mos6502 = Mos6502::new(mem);
+        cpu.reset();
+        cpu.a = self.a;
+        cpu.x = self.x;
+        cpu.y = self.y;
+        cpu.sp = self.sp;
+        cpu.ps = self.status;
+        cpu.variant = self.variant;
+        cpu.trap_detection = self.trap_detection;
+        if let Some(pc) = self.pc {
+            cpu.pc = pc;
+        }
+        cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_cpu_with_registers() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        mem.borrow_mut().write(0xfffc, 0x00);
+        mem.borrow_mut().write(0xfffd, 0x80);
+        let cpu: Mos6502 = Mos6502::builder().memory(mem.clone()).x(0x12).build();
+        assert_eq!(cpu.pc(), 0x8000);
+        assert_eq!(cpu.x(), 0x12);
+
+        let cpu: Mos6502 = Mos6502::builder()
+            .a(0x34)
+            .y(0x56)
+            .sp(0xff)
+            .status(StatusFlags::CARRY)
+            .pc(0x0200)
+            .build();
+        assert_eq!(
+            (cpu.a(), cpu.y(), cpu.sp(), cpu.status(), cpu.pc()),
+            (0x34, 0x56, 0xff, StatusFlags::CARRY, 0x0200)
+        );
+        assert!(!Rc::ptr_eq(cpu.memory(), &mem));
+    }
+}
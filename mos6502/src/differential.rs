@@ -0,0 +1,247 @@
+use crate::reference::{normalized, Reference};
+use crate::{instruction_info, Mos6502, Registers};
+
+use std::fmt;
+use std::ops::Deref;
+
+/// A CPU `compare()` can run, with the memory it runs in.
+pub trait Core {
+    /// What the core is called in a `Divergence`.
+    fn name(&self) -> &str;
+
+    /// Executes one instruction.
+    ///
+    /// # Returns
+    /// `false` if the core cannot go on, e.g. at an op code it does not run.
+    fn step(&mut self) -> bool;
+
+    fn registers(&self) -> Registers;
+
+    /// # Returns
+    /// The cycles executed so far.
+    fn cycles(&self) -> u64;
+
+    /// # Returns
+    /// The whole 64 KiB memory.
+    fn memory(&self) -> Box<dyn Deref<Target = [u8]> + '_>;
+}
+
+impl Core for Mos6502 {
+    fn name(&self) -> &str {
+        "mos6502"
+    }
+
+    fn step(&mut self) -> bool {
+        // Stopping at a jump to itself, whether traps are detected or not
+        let pc: u16 = self.pc();
+        !self.is_halted() && Mos6502::step(self) > 0 && !self.is_halted() && self.pc() != pc
+    }
+
+    fn registers(&self) -> Registers {
+        self.snapshot()
+    }
+
+    fn cycles(&self) -> u64 {
+        Mos6502::cycles(self)
+    }
+
+    fn memory(&self) -> Box<dyn Deref<Target = [u8]> + '_> {
+        Box::new(std::cell::Ref::map(self.memory().borrow(), |memory| {
+            memory.as_slice()
+        }))
+    }
+}
+
+impl Core for Reference {
+    fn name(&self) -> &str {
+        "reference"
+    }
+
+    fn step(&mut self) -> bool {
+        // Stopping at a jump to itself, like `Mos6502` at a trap
+        let pc: u16 = self.registers.pc;
+        Reference::step(self) && self.registers.pc != pc
+    }
+
+    fn registers(&self) -> Registers {
+        self.registers
+    }
+
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn memory(&self) -> Box<dyn Deref<Target = [u8]> + '_> {
+        Box::new(self.ram.as_slice())
+    }
+}
+
+/// Where two cores first disagreed, see `compare()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// How many instructions both executed alike before.
+    pub instructions: u64,
+    /// Where the instruction they disagree on is.
+    pub pc: u16,
+    pub op_code: u8,
+    /// What differs, `name: expected, got`, the first core being the expected one.
+    pub differences: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic: &str = instruction_info(self.op_code).map_or("???", |info| info.mnemonic);
+        write!(
+            f,
+            "Diverged after {} instructions, at {:#06x} ({} {:#04x}): {}",
+            self.instructions,
+            self.pc,
+            mnemonic,
+            self.op_code,
+            self.differences.join(", ")
+        )
+    }
+}
+
+/// Runs `expected` and `actual` side by side, an instruction at a time, comparing
+/// the registers, the cycle count and the whole memory after each. The B bit and
+/// bit 5 of the status register are left out, they only exist on the stack.
+///
+/// # Returns
+/// How many instructions ran, up to `max_instructions`, once both cores stopped
+/// together, or the first difference.
+pub fn compare(
+    expected: &mut impl Core,
+    actual: &mut impl Core,
+    max_instructions: u64,
+) -> Result<u64, Divergence> {
+    for executed in 0..max_instructions {
+        let pc: u16 = expected.registers().pc;
+        let op_code: u8 = expected.memory()[pc as usize];
+        let stepped: (bool, bool) = (expected.step(), actual.step());
+        let mut differences: Vec<String> = Vec::new();
+        match stepped {
+            (false, false) => return Ok(executed),
+            (true, false) => differences.push(format!("{} stopped", actual.name())),
+            (false, true) => differences.push(format!("{} stopped", expected.name())),
+            (true, true) => differences = difference(expected, actual),
+        }
+        if !differences.is_empty() {
+            return Err(Divergence {
+                instructions: executed,
+                pc,
+                op_code,
+                differences,
+            });
+        }
+    }
+    Ok(max_instructions)
+}
+
+/// # Returns
+/// What differs between the state of `expected` and that of `actual`.
+fn difference(expected: &impl Core, actual: &impl Core) -> Vec<String> {
+    let (left, right): (Registers, Registers) = (expected.registers(), actual.registers());
+    let mut differences: Vec<String> = Vec::new();
+    let mut differ = |name: &str, left: u64, right: u64, width: usize| {
+        if left != right {
+            differences.push(format!(
+                "{}: {:#0width$x}, got {:#0width$x}",
+                name,
+                left,
+                right,
+                width = width
+            ));
+        }
+    };
+    differ("pc", left.pc as u64, right.pc as u64, 6);
+    differ("a", left.a as u64, right.a as u64, 4);
+    differ("x", left.x as u64, right.x as u64, 4);
+    differ("y", left.y as u64, right.y as u64, 4);
+    differ("sp", left.sp as u64, right.sp as u64, 4);
+    differ(
+        "p",
+        normalized(left.status).bits() as u64,
+        normalized(right.status).bits() as u64,
+        4,
+    );
+    differ("cycles", expected.cycles(), actual.cycles(), 0);
+
+    // Comparing whole slices first, which is much faster
+    let (left, right) = (expected.memory(), actual.memory());
+    let (left, right): (&[u8], &[u8]) = (&left, &right);
+    let first = || (0..left.len()).find(|&address| left[address] != right[address]);
+    if let Some(address) = (left != right).then(first).flatten() {
+        differences.push(format!(
+            "{:#06x}: {:#04x}, got {:#04x}",
+            address, left[address], right[address]
+        ));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::micro_test::Random;
+    use crate::{StatusFlags, Variant};
+
+    use memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// # Returns
+    /// `program` loaded at `$0200` in both the reference and a `Mos6502`, starting there.
+    fn cores(program: &[u8]) -> (Reference, Mos6502) {
+        let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+        cpu.load(0x0200, program);
+        cpu.set_pc(0x0200);
+        cpu.set_sp(0xff);
+        cpu.set_status(StatusFlags::UNUSED);
+        let ram: Vec<u8> = cpu.memory().borrow().as_slice().to_vec();
+        (Reference::new(ram, cpu.snapshot()), cpu)
+    }
+
+    #[test]
+    fn agrees_on_random_instructions() {
+        let mut random: Random = Random(0x6502);
+        for _ in 0..200 {
+            let mut program: Vec<u8> = Vec::new();
+            while program.len() < 0x100 {
+                let op_code: u8 = random.byte();
+                let Some(info) = instruction_info(op_code) else {
+                    continue;
+                };
+                program.push(op_code);
+                for _ in 1..info.length {
+                    program.push(random.byte());
+                }
+            }
+            // Branches out of the program meet an op code that stops both, as
+            // does the first undocumented one within
+            let (mut reference, mut cpu) = cores(&program);
+            for range in [0x0180..0x0200, 0x0200 + program.len()..0x0380] {
+                reference.ram[range.clone()].fill(0x02);
+                cpu.load(range.start as u16, &vec![0x02; range.len()]);
+            }
+            if let Err(divergence) = compare(&mut reference, &mut cpu, 1000) {
+                panic!("{}", divergence);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_the_first_divergence() {
+        // SED, BRK: the 65C02 clears decimal mode on entering the handler
+        let (mut reference, mut cpu) = cores(&[0xf8, 0x00]);
+        cpu.set_variant(Variant::Cmos);
+        let divergence: Divergence = compare(&mut reference, &mut cpu, 10).unwrap_err();
+        assert_eq!(divergence.instructions, 1);
+        assert_eq!(divergence.pc, 0x0201);
+        assert_eq!(
+            divergence.to_string(),
+            "Diverged after 1 instructions, at 0x0201 (BRK 0x00): p: 0x2c, got 0x24"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{Mos6502, Registers, StatusFlags};
+
+use memory::Memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How many bytes of a fuzz input set the registers before the program, see `run()`.
+pub const HEADER_BYTES: usize = 8;
+
+/// The most cycles one step can take: 7 for the longest instructions, and 7 more for
+/// entering an interrupt handler after it.
+const MAX_STEP_CYCLES: u32 = 14;
+
+/// Runs arbitrary bytes as code, for fuzzers, checking what holds whatever the program:
+/// no step panics, a step takes between 2 and 14 cycles, and the cycle count grows by
+/// exactly those.
+///
+/// The input begins with `HEADER_BYTES` bytes, missing ones being zero: PC, low byte
+/// first, A, X, Y, SP, the status register, and the interrupt lines, bit 0 holding IRQ
+/// asserted and bit 1 raising NMI. The rest is loaded from PC on, wrapping at `$FFFF`,
+/// into otherwise zeroed memory. An op code the CPU does not execute, or a jump to
+/// itself, is stepped over so that the rest of the input still runs.
+///
+/// # Arguments
+/// * `data` - The input of the fuzzer.
+/// * `max_cycles` - How long the program runs, at least.
+///
+/// # Returns
+/// The cycles executed.
+pub fn run(data: &[u8], max_cycles: u64) -> u64 {
+    let header = |index: usize| data.get(index).copied().unwrap_or(0);
+    let registers: Registers = Registers {
+        pc: u16::from_le_bytes([header(0), header(1)]),
+        a: header(2),
+        x: header(3),
+        y: header(4),
+        sp: header(5),
+        status: StatusFlags::from_bits(header(6)),
+    };
+    let lines: u8 = header(7);
+
+    let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+    for (offset, &byte) in data.iter().skip(HEADER_BYTES).enumerate() {
+        cpu.poke(registers.pc.wrapping_add(offset as u16), byte);
+    }
+    cpu.restore(registers);
+    cpu.set_irq(lines & 0x01 != 0);
+    if lines & 0x02 != 0 {
+        cpu.nmi();
+    }
+
+    while cpu.cycles() < max_cycles {
+        if cpu.is_halted() {
+            cpu.set_pc(cpu.pc().wrapping_add(1));
+            cpu.resume();
+        }
+        // Decoding must hold up too
+        let _ = cpu.next_instruction();
+        let before: u64 = cpu.cycles();
+        let cycles: u32 = cpu.step();
+        if cycles == 0 && cpu.is_halted() {
+            continue;
+        }
+        assert!(
+            (2..=MAX_STEP_CYCLES).contains(&cycles),
+            "a step took {} cycles, before {:#06x}",
+            cycles,
+            cpu.pc()
+        );
+        assert_eq!(cpu.cycles() - before, cycles as u64);
+    }
+    cpu.cycles()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::micro_test::Random;
+
+    #[test]
+    fn survives_random_programs() {
+        let mut random: Random = Random(0x6502);
+        for length in 0..300 {
+            let data: Vec<u8> = (0..length).map(|_| random.byte()).collect();
+            assert!(run(&data, 2_000) >= 2_000);
+        }
+        // Code running off the end of memory
+        assert!(run(&[0xfd, 0xff, 0, 0, 0, 0xff, 0, 0, 0xea, 0xea, 0xea], 100) >= 100);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::opcodes::OpCode;
+use crate::StatusFlags;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// An instruction and the registers around it, given to the hooks of
+/// `Mos6502::add_pre_hook()` and `Mos6502::add_post_hook()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionState {
+    /// Where the instruction starts.
+    pub pc: u16,
+    pub op_code: OpCode,
+    // The registers before the instruction for pre-hooks, after it for post-hooks.
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: StatusFlags,
+}
+
+/// What the CPU does once a pre-hook returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookAction {
+    /// Executes the instruction.
+    Continue,
+    /// Halts the CPU before the instruction, which runs once it is resumed.
+    Stop,
+}
+
+/// A host function run before every instruction, see `Mos6502::add_pre_hook()`.
+pub type PreHook = Rc<RefCell<dyn FnMut(&InstructionState) -> HookAction>>;
+
+/// A host function run after every instruction, see `Mos6502::add_post_hook()`.
+pub type PostHook = Rc<RefCell<dyn FnMut(&InstructionState)>>;
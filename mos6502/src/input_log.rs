@@ -0,0 +1,201 @@
+use crate::Mos6502;
+
+use std::fmt;
+
+/// An input coming from outside the emulated machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    /// The IRQ line was asserted (`true`) or released (`false`).
+    Irq(bool),
+    /// A non-maskable interrupt was signalled.
+    Nmi,
+    /// The reset button was pressed.
+    Reset,
+    /// The buttons held on the game controller plugged into `port` changed to `buttons`,
+    /// one bit per button.
+    Joypad { port: u8, buttons: u8 },
+}
+
+impl InputEvent {
+    /// Applies the event to `cpu`.
+    /// Controller events do not reach the CPU, the machine owning the controller ports
+    /// applies them.
+    pub fn apply(&self, cpu: &mut Mos6502) {
+        match self {
+            InputEvent::Irq(asserted) => cpu.set_irq(*asserted),
+            InputEvent::Nmi => cpu.nmi(),
+            InputEvent::Reset => cpu.reset(),
+            InputEvent::Joypad { .. } => {}
+        }
+    }
+}
+
+/// An event together with the CPU cycle count at which it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub cycle: u64,
+    pub event: InputEvent,
+}
+
+/// A recording of the external inputs of a run.
+/// Replaying it into a machine started from the same state reproduces the run exactly.
+///
+/// The text form has one event per line, `<cycle> irq on|off`, `<cycle> nmi`, `<cycle> reset`
+/// or `<cycle> joypad <port> <buttons>`, the buttons in hexadecimal.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Default)]
+pub struct InputLog {
+    events: Vec<TimedEvent>,
+    next: usize,
+}
+
+impl InputLog {
+    pub fn new() -> Self {
+        InputLog {
+            events: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Appends `event`, which happened at `cycle`.
+    /// Cycles must not go backwards.
+    pub fn record(&mut self, cycle: u64, event: InputEvent) {
+        debug_assert!(self.events.last().is_none_or(|last| last.cycle <= cycle));
+        self.events.push(TimedEvent { cycle, event });
+    }
+
+    /// # Returns
+    /// The next event that is due at `cycle`, if any. Call repeatedly until `None`
+    /// before each step, since several events can share a cycle.
+    pub fn next_due(&mut self, cycle: u64) -> Option<InputEvent> {
+        let timed: &TimedEvent = self.events.get(self.next)?;
+        if timed.cycle <= cycle {
+            self.next += 1;
+            Some(timed.event)
+        } else {
+            None
+        }
+    }
+
+    /// Applies every event due at the current cycle of `cpu`.
+    pub fn replay_due(&mut self, cpu: &mut Mos6502) {
+        while let Some(event) = self.next_due(cpu.cycles()) {
+            event.apply(cpu);
+        }
+    }
+
+    /// # Returns
+    /// All recorded events.
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+
+    /// Parses the text form of a log.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut log: InputLog = InputLog::new();
+        for (index, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = || format!("Invalid input log entry on line {}: `{}`", index + 1, line);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let cycle: u64 = fields[0].parse().map_err(|_| error())?;
+            let event: InputEvent = match fields[1..] {
+                ["irq", "on"] => InputEvent::Irq(true),
+                ["irq", "off"] => InputEvent::Irq(false),
+                ["nmi"] => InputEvent::Nmi,
+                ["reset"] => InputEvent::Reset,
+                ["joypad", port, buttons] => InputEvent::Joypad {
+                    port: port.parse().map_err(|_| error())?,
+                    buttons: u8::from_str_radix(buttons.trim_start_matches("0x"), 16)
+                        .map_err(|_| error())?,
+                },
+                _ => return Err(error()),
+            };
+            if log.events.last().is_some_and(|last| last.cycle > cycle) {
+                return Err(error());
+            }
+            log.record(cycle, event);
+        }
+        Ok(log)
+    }
+}
+
+impl fmt::Display for InputLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for timed in &self.events {
+            match timed.event {
+                InputEvent::Irq(true) => writeln!(f, "{} irq on", timed.cycle)?,
+                InputEvent::Irq(false) => writeln!(f, "{} irq off", timed.cycle)?,
+                InputEvent::Nmi => writeln!(f, "{} nmi", timed.cycle)?,
+                InputEvent::Reset => writeln!(f, "{} reset", timed.cycle)?,
+                InputEvent::Joypad { port, buttons } => {
+                    writeln!(f, "{} joypad {} {:#04x}", timed.cycle, port, buttons)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::OpCode;
+    use memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn text_round_trip() {
+        let mut log = InputLog::new();
+        log.record(10, InputEvent::Irq(true));
+        log.record(10, InputEvent::Nmi);
+        log.record(25, InputEvent::Irq(false));
+        log.record(40, InputEvent::Reset);
+        log.record(
+            52,
+            InputEvent::Joypad {
+                port: 1,
+                buttons: 0x09,
+            },
+        );
+
+        let parsed = InputLog::parse(&log.to_string()).unwrap();
+
+        assert_eq!(parsed.events(), log.events());
+        assert!(InputLog::parse("5 irq\n").is_err());
+        assert!(InputLog::parse("5 nmi\n2 nmi\n").is_err());
+    }
+
+    #[test]
+    fn replay_at_recorded_cycle() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.sp = 0xff;
+        for address in 0x0000..0x0004 {
+            cpu.poke(address, OpCode::Nop.into());
+        }
+        cpu.poke(0xfffa, 0x00);
+        cpu.poke(0xfffb, 0x80);
+
+        let mut log = InputLog::parse("4 nmi\n").unwrap();
+        log.replay_due(&mut cpu);
+        cpu.step();
+        log.replay_due(&mut cpu);
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x0002);
+
+        log.replay_due(&mut cpu);
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x8000);
+        assert_eq!(cpu.cycles(), 11);
+        assert!(cpu.status().interrupt_disable());
+    }
+}
@@ -1,30 +1,204 @@
+pub mod builder;
+pub mod differential;
+pub mod fuzz;
+pub mod hooks;
+pub mod input_log;
+pub mod logging;
+pub mod micro_test;
 pub mod opcodes;
-
-use memory::Memory;
-use opcodes::OpCode;
+#[cfg(feature = "perfect6502")]
+pub mod perfect6502;
+pub mod reference;
+#[cfg(feature = "serialize")]
+pub mod serialize;
+pub mod stack;
+pub mod state_hash;
+pub mod status;
+pub mod steps;
+pub mod ticks;
+pub mod trace;
+pub mod w65c816;
+
+use builder::Mos6502Builder;
+use hooks::{HookAction, InstructionState, PostHook, PreHook};
+use logging::{Category, LogLevel, LogSink};
+use memory::device::IrqState;
+use memory::{Access, Memory};
+use opcodes::{AddressingMode, OpCode};
+use stack::{StackWrap, StackWrapKind, CALL_TRACE_DEPTH};
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::rc::Rc;
+use steps::Steps;
+use ticks::Tick;
+use trace::TraceRecord;
+use w65c816::Registers65816;
+
+pub use opcodes::{instruction_info, InstructionInfo};
+pub use status::StatusFlags;
+
+/// What the CPU does once a trap returned, see `Mos6502::add_trap()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapAction {
+    /// Executes the instruction at PC, which the trap may have changed.
+    Continue,
+    /// Returns from the subroutine the trap replaces, as if it ran `RTS`.
+    Return,
+    /// Spends the 2 cycles of a `NOP` and runs the trap again at the next step, for
+    /// routines waiting on input.
+    Wait,
+}
+
+/// A host function run when PC reaches its address, see `Mos6502::add_trap()`.
+pub type Trap = Rc<RefCell<dyn FnMut(&mut Mos6502) -> TrapAction>>;
+
+/// A host function given every instruction executed, see `Mos6502::set_tracer()`.
+pub type Tracer = Rc<RefCell<dyn FnMut(&TraceRecord)>>;
+
+/// A host function told when SP wraps around, see `Mos6502::set_stack_watch()`.
+pub type StackWatch = Rc<RefCell<dyn FnMut(&StackWrap)>>;
+
+/// The registers of the CPU once a routine called with `Mos6502::call()` returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallResult {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: StatusFlags,
+    /// Cycles the routine took, its `RTS` included.
+    pub cycles: u64,
+}
+
+/// Why the CPU stopped executing instructions, see `Mos6502::halt_reason()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HaltReason {
+    /// The host called `Mos6502::halt()`.
+    UserRequest,
+    /// An opcode the CPU cannot execute, left at PC. Undocumented opcodes jam for now.
+    Jam,
+    /// Waiting for an interrupt, for hosts emulating `WAI`.
+    Wai,
+    /// A pre-hook stopped the instruction at PC, see `Mos6502::add_pre_hook()`.
+    Breakpoint,
+    /// A `JMP` or branch jumped to itself, see `Mos6502::set_trap_detection()`.
+    Trap,
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason: &str = match self {
+            HaltReason::UserRequest => "user request",
+            HaltReason::Jam => "jam",
+            HaltReason::Wai => "WAI",
+            HaltReason::Breakpoint => "breakpoint",
+            HaltReason::Trap => "trap",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// The chip a `Mos6502` behaves as, see `Mos6502::set_variant()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// The original NMOS 6502.
+    #[default]
+    Nmos,
+    /// The CMOS 65C02, which only differs in decimal mode, its flags, results and extra
+    /// cycle, and in clearing it on interrupts and `BRK`, and in the dummy accesses of
+    /// indexed and read-modify-write instructions, for now.
+    Cmos,
+    /// The 65816 in emulation mode, with the extra registers and opcodes of `w65c816`.
+    /// Decimal mode is that of the 65C02 without its extra cycle, the dummy accesses
+    /// those of the NMOS 6502.
+    W65c816,
+}
+
+/// The registers of the CPU, see `Mos6502::snapshot()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub pc: u16,
+    pub status: StatusFlags,
+}
+
+/// In how many cycles an NMI takes over `BRK` or an IRQ entry, before they fetch the
+/// vector.
+const HIJACK_CYCLES: u32 = 4;
 
-const CARRY_FLAG: u8 = 0b0000_0001;
-const ZERO_FLAG: u8 = 0b0000_0010;
-const INTERRUPT_DISABLE_FLAG: u8 = 0b0000_0100;
-const DECIMAL_MODE_FLAG: u8 = 0b0000_1000;
-const BREAK_FLAG: u8 = 0b0001_0000;
-const OVERFLOW_FLAG: u8 = 0b0100_0000;
-const NEGATIVE_FLAG: u8 = 0b1000_0000;
+/// How many instructions `Mos6502::pc_history()` remembers.
+pub const PC_HISTORY_DEPTH: usize = 32;
+
+/// Where `Mos6502::call()` has the called routine return to.
+const CALL_RETURN_ADDRESS: u16 = 0xffff;
 
 /// A MOS 6502 CPU.
-/// Decimal mode is not yet supported.
+///
+/// Cloning copies the registers and shares the memory and the traps.
+#[derive(Clone)]
 pub struct Mos6502 {
     a: u8,
     x: u8,
     y: u8,
 
     sp: u8,
-    ps: u8,
+    ps: StatusFlags,
     pc: u16,
 
-    halted: bool,
+    variant: Variant,
+    halt_reason: Option<HaltReason>,
+    /// Address of the last instruction that jumped or branched to itself.
+    trap_pc: Option<u16>,
+    /// Halt on a `JMP` or branch to itself, see `set_trap_detection()`.
+    trap_detection: bool,
+
+    /// Total number of cycles executed since the CPU was created. Not cleared by a reset.
+    cycles: u64,
+    /// Level of the IRQ line, `true` while asserted.
+    irq_line: bool,
+    /// An NMI edge was seen and has not been serviced yet.
+    nmi_pending: bool,
+    /// Level of the NMI line driven by devices at the last step, to detect edges.
+    device_nmi: bool,
+    /// An NMI or an IRQ seen when the last instruction polled for interrupts, taken
+    /// before the next one, see `step()`.
+    nmi_polled: bool,
+    irq_polled: bool,
+    /// The last instruction cleared the interrupt disable flag, which only applies
+    /// after the next one.
+    irq_delayed: bool,
+    /// Host functions by the address they intercept.
+    traps: HashMap<u16, Trap>,
+    tracer: Option<Tracer>,
+    pre_hooks: Vec<PreHook>,
+    post_hooks: Vec<PostHook>,
+    log_sink: Option<Rc<RefCell<dyn LogSink>>>,
+    stack_watch: Option<StackWatch>,
+    /// Where the calls the CPU is in were made, while the stack is watched.
+    call_trace: Vec<u16>,
+    /// Address and opcode of the last instructions fetched, oldest first.
+    pc_history: VecDeque<(u16, u8)>,
+    /// Where the current instruction or interrupt entry starts.
+    instruction_pc: u16,
+    /// The level of each `Category`, by its index.
+    log_levels: [LogLevel; 4],
+    /// Bytes of the current instruction fetched so far.
+    fetched: u8,
+    /// The instruction the last step executed, `None` if it entered an interrupt or
+    /// returned from a trap.
+    executed: Option<OpCode>,
+    /// The registers of the 65816, only used by `Variant::W65c816`.
+    registers_65816: Registers65816,
+    /// The cycles of the last step not handed out by `tick()` yet.
+    ticks: VecDeque<Tick>,
+    /// A step is run for `tick()`, which needs all its accesses.
+    ticking: bool,
+    /// The accesses of the instruction the step for `tick()` executed.
+    tick_accesses: Vec<Access>,
 
     mem: Rc<RefCell<Memory>>,
 }
@@ -37,7 +211,7 @@ impl Mos6502 {
     /// # Arguments
     ///
     /// * `mem` - A shared pointer to a `Memory` instance. Memory must be initialized first.
-    ///          See `memory::Memory::new()`.
+    ///   See `memory::Memory::new()`.
     ///
     /// # Returns
     ///
@@ -48,13 +222,46 @@ impl Mos6502 {
             x: 0x00,
             y: 0x00,
             sp: 0x00,
-            ps: 0x00,
+            ps: StatusFlags::empty(),
             pc: 0x00,
-            halted: false,
-            mem: mem,
+            variant: Variant::Nmos,
+            halt_reason: None,
+            trap_pc: None,
+            trap_detection: false,
+            cycles: 0,
+            irq_line: false,
+            nmi_pending: false,
+            device_nmi: false,
+            nmi_polled: false,
+            irq_polled: false,
+            irq_delayed: false,
+            traps: HashMap::new(),
+            tracer: None,
+            pre_hooks: Vec::new(),
+            post_hooks: Vec::new(),
+            log_sink: None,
+            stack_watch: None,
+            call_trace: Vec::new(),
+            pc_history: VecDeque::with_capacity(PC_HISTORY_DEPTH),
+            instruction_pc: 0x00,
+            log_levels: [LogLevel::Off; 4],
+            fetched: 0,
+            executed: None,
+            registers_65816: Registers65816::RESET,
+            ticks: VecDeque::new(),
+            ticking: false,
+            tick_accesses: Vec::new(),
+            mem,
         }
     }
 
+    /// # Returns
+    /// A builder to set the memory and the registers of a new CPU, e.g.
+    /// `Mos6502::builder().pc(0x0200).sp(0xff).build()`.
+    pub fn builder() -> Mos6502Builder {
+        Mos6502Builder::new()
+    }
+
     /// Resets the CPU to its initial state.
     pub fn reset(&mut self) {
         self.a = 0x00;
@@ -65,92 +272,811 @@ impl Mos6502 {
         // it will be set automatically when we load the c64 kernal rom
         self.sp = 0x00;
 
-        self.ps = 0x00;
+        self.ps = StatusFlags::empty();
         self.pc = self.mem.borrow().get_reset_vector();
+
+        self.halt_reason = None;
+        self.trap_pc = None;
+
+        self.irq_line = false;
+        self.nmi_pending = false;
+        self.device_nmi = false;
+        self.nmi_polled = false;
+        self.irq_polled = false;
+        self.irq_delayed = false;
+        self.registers_65816 = Registers65816::RESET;
+        self.ticks.clear();
+    }
+
+    /// Halts the CPU for `reason`, replacing the previous reason if it was halted.
+    /// A halted CPU does not execute any instructions until `resume()` or a reset.
+    pub fn halt(&mut self, reason: HaltReason) {
+        self.halt_reason = Some(reason);
+        if reason != HaltReason::Trap {
+            self.trap_pc = None;
+        }
     }
 
-    /// Halts/resumes the CPU.
-    /// If the CPU is halted, it will not execute any instructions.
-    /// halted => !halted
-    pub fn halt_resume(&mut self) {
-        self.halted = !self.halted;
+    /// Lets a halted CPU execute instructions again, from PC.
+    pub fn resume(&mut self) {
+        self.halt_reason = None;
+        self.trap_pc = None;
     }
 
-    pub fn step(&mut self) {
-        if !self.halted {
-            let op_code: u8 = self.fetch();
-            #[cfg(debug_assertions)]
-            {
-                println!(
-                    "== Executing {}({:#04x}) at {:#06x} ==",
-                    OpCode::from(op_code),
-                    op_code,
-                    self.pc - 1
-                );
+    /// # Returns
+    /// `true` if the CPU is halted, see `halt_reason()`.
+    pub fn is_halted(&self) -> bool {
+        self.halt_reason.is_some()
+    }
+
+    /// # Returns
+    /// Why the CPU is halted, `None` if it runs.
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        self.halt_reason
+    }
+
+    /// # Returns
+    /// The address of the `JMP` or branch instruction that jumped to itself, if the CPU
+    /// stopped because of one, see `set_trap_detection()`.
+    pub fn trapped_at(&self) -> Option<u16> {
+        self.trap_pc
+    }
+
+    /// Halts the CPU with `HaltReason::Trap` whenever a `JMP` or branch jumps to itself,
+    /// if `enabled`. Test ROMs use this idiom to signal success or failure, but
+    /// programs also wait in such a loop for interrupts, so it is off by default.
+    pub fn set_trap_detection(&mut self, enabled: bool) {
+        self.trap_detection = enabled;
+    }
+
+    /// # Returns
+    /// The address and the opcode of the last `PC_HISTORY_DEPTH` instructions fetched,
+    /// oldest first, ending with the one that jammed, if any. Kept across resets, to see
+    /// how a program got lost.
+    pub fn pc_history(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.pc_history.iter().copied()
+    }
+
+    /// # Returns
+    /// `pc_history()` a line each, e.g. `0x0200  a9  LDA`, with the mnemonic of the
+    /// opcode fetched then, or `???` if it is not implemented.
+    pub fn format_pc_history(&self) -> String {
+        let lines: Vec<String> = self
+            .pc_history()
+            .map(|(pc, op_code)| {
+                let mnemonic: &str = OpCode::from_byte(op_code).map_or("???", |op| op.mnemonic());
+                format!("{:#06x}  {:02x}  {}", pc, op_code, mnemonic)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// # Returns
+    /// The number of cycles executed since the CPU was created.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// # Returns
+    /// The memory the CPU is connected to.
+    pub fn memory(&self) -> &Rc<RefCell<Memory>> {
+        &self.mem
+    }
+
+    /// # Returns
+    /// The byte at `address`, read through the bus as the CPU would, devices included.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.mem.borrow().read(address)
+    }
+
+    /// # Returns
+    /// The little-endian word at `address`, e.g. a vector. The high byte comes from
+    /// `address + 1`, wrapping at the end of memory.
+    pub fn peek_word(&self, address: u16) -> u16 {
+        let mem = self.mem.borrow();
+        u16::from_le_bytes([mem.read(address), mem.read(address.wrapping_add(1))])
+    }
+
+    /// Writes `value` at `address` through the bus, as the CPU would.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.mem.borrow_mut().write(address, value);
+    }
+
+    /// Writes `bytes` through the bus from `address` on, wrapping at the end of memory,
+    /// e.g. to put a program in place before setting PC.
+    pub fn load(&mut self, address: u16, bytes: &[u8]) {
+        let mut mem = self.mem.borrow_mut();
+        for (offset, &byte) in bytes.iter().enumerate() {
+            mem.write(address.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn set_a(&mut self, a: u8) {
+        self.a = a;
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    /// # Returns
+    /// The processor status register.
+    pub fn status(&self) -> StatusFlags {
+        self.ps
+    }
+
+    pub fn set_status(&mut self, status: StatusFlags) {
+        self.ps = status;
+    }
+
+    /// # Returns
+    /// The chip emulated, `Variant::Nmos` by default.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Makes the CPU behave as `variant` from the next instruction on.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    /// # Returns
+    /// The registers the 65816 adds, which only `Variant::W65c816` uses.
+    pub fn registers_65816(&self) -> Registers65816 {
+        self.registers_65816
+    }
+
+    /// Sets the registers the 65816 adds, see `registers_65816()`.
+    pub fn set_registers_65816(&mut self, registers: Registers65816) {
+        self.registers_65816 = registers;
+    }
+
+    /// # Returns
+    /// A copy of the registers, without the memory, e.g. to compare two CPUs.
+    pub fn snapshot(&self) -> Registers {
+        Registers {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            pc: self.pc,
+            status: self.ps,
+        }
+    }
+
+    /// Puts the registers back as saved by `snapshot()`. The cycle count, the halt
+    /// state and the interrupt lines are left alone.
+    pub fn restore(&mut self, registers: Registers) {
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.sp = registers.sp;
+        self.pc = registers.pc;
+        self.ps = registers.status;
+    }
+
+    /// Runs `trap` instead of fetching an instruction whenever PC reaches `address`,
+    /// replacing any trap already there. The trap can read and change the CPU state,
+    /// and its memory through `memory()`, then either continue with the instruction at
+    /// PC or return from the subroutine, so firmware routines can be emulated on the
+    /// host without patching ROMs.
+    ///
+    /// Traps are not checked for interrupt entries, only for instruction fetches.
+    /// A trap returning costs the 6 cycles of `RTS` and a waiting one those of `NOP`,
+    /// a continuing one takes no cycles.
+    pub fn add_trap(
+        &mut self,
+        address: u16,
+        trap: impl FnMut(&mut Mos6502) -> TrapAction + 'static,
+    ) {
+        self.traps.insert(address, Rc::new(RefCell::new(trap)));
+    }
+
+    /// Removes the trap at `address`, if any.
+    pub fn remove_trap(&mut self, address: u16) {
+        self.traps.remove(&address);
+    }
+
+    /// Gives every instruction executed from now on to `tracer`, with the registers
+    /// before it ran and the bus accesses it made. Interrupt entries and traps are
+    /// not traced. Replaces the previous tracer, if any.
+    ///
+    /// Memory logs its accesses while traced, see `Memory::set_logging()`.
+    pub fn set_tracer(&mut self, tracer: impl FnMut(&TraceRecord) + 'static) {
+        self.tracer = Some(Rc::new(RefCell::new(tracer)));
+        self.update_bus_logging();
+    }
+
+    /// Stops tracing, see `set_tracer()`.
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+        self.update_bus_logging();
+    }
+
+    /// Tells `watch` whenever SP wraps around page one, with the calls the CPU is in
+    /// to find the runaway recursion or the unbalanced pull. Replaces the previous
+    /// watch, if any.
+    pub fn set_stack_watch(&mut self, watch: impl FnMut(&StackWrap) + 'static) {
+        self.stack_watch = Some(Rc::new(RefCell::new(watch)));
+        self.call_trace.clear();
+    }
+
+    /// Stops watching the stack, see `set_stack_watch()`.
+    pub fn clear_stack_watch(&mut self) {
+        self.stack_watch = None;
+        self.call_trace.clear();
+    }
+
+    /// Sends the log messages of the categories enabled with `set_log_level()` to
+    /// `sink`, replacing the previous sink, if any. Nothing is logged without a sink,
+    /// and messages are only formatted for enabled levels.
+    pub fn set_log_sink(&mut self, sink: impl LogSink + 'static) {
+        self.log_sink = Some(Rc::new(RefCell::new(sink)));
+        self.update_bus_logging();
+    }
+
+    /// Stops logging, see `set_log_sink()`.
+    pub fn clear_log_sink(&mut self) {
+        self.log_sink = None;
+        self.update_bus_logging();
+    }
+
+    /// Logs the messages of `category` up to `level`, all categories being off by
+    /// default.
+    ///
+    /// Memory logs its accesses while `Category::Bus` is traced, see
+    /// `Memory::set_logging()`.
+    pub fn set_log_level(&mut self, category: Category, level: LogLevel) {
+        self.log_levels[category as usize] = level;
+        self.update_bus_logging();
+    }
+
+    /// # Returns
+    /// The level of `category`, see `set_log_level()`.
+    pub fn log_level(&self, category: Category) -> LogLevel {
+        self.log_levels[category as usize]
+    }
+
+    /// # Returns
+    /// `true` if messages of `category` at `level` go to a sink.
+    fn logs(&self, category: Category, level: LogLevel) -> bool {
+        self.log_sink.is_some() && self.log_levels[category as usize] >= level
+    }
+
+    /// Sends the message made by `message` to the sink, if `category` logs `level`.
+    fn log(&self, category: Category, level: LogLevel, message: impl FnOnce() -> String) {
+        if let (true, Some(sink)) = (self.logs(category, level), &self.log_sink) {
+            sink.borrow_mut().log(category, level, &message());
+        }
+    }
+
+    /// # Returns
+    /// `true` if the accesses of each instruction are needed, by a tracer, the log or
+    /// `tick()`.
+    fn watches_bus(&self) -> bool {
+        self.tracer.is_some() || self.ticking || self.logs(Category::Bus, LogLevel::Trace)
+    }
+
+    fn update_bus_logging(&self) {
+        self.mem.borrow_mut().set_logging(self.watches_bus());
+    }
+
+    /// Runs `hook` before every instruction, after the other pre-hooks, with the
+    /// decoded instruction and the registers. If any hook returns `HookAction::Stop`,
+    /// the CPU halts with PC on the instruction instead of executing it.
+    ///
+    /// Like tracers, hooks are not run for interrupt entries and traps. The opcode is
+    /// read through the bus once more to decode it.
+    pub fn add_pre_hook(&mut self, hook: impl FnMut(&InstructionState) -> HookAction + 'static) {
+        self.pre_hooks.push(Rc::new(RefCell::new(hook)));
+    }
+
+    /// Runs `hook` after every instruction, after the other post-hooks, with the
+    /// instruction and the registers it left.
+    pub fn add_post_hook(&mut self, hook: impl FnMut(&InstructionState) + 'static) {
+        self.post_hooks.push(Rc::new(RefCell::new(hook)));
+    }
+
+    /// Removes the pre-hooks and post-hooks.
+    pub fn clear_hooks(&mut self) {
+        self.pre_hooks.clear();
+        self.post_hooks.clear();
+    }
+
+    /// # Returns
+    /// The registers with `op_code` at `pc`, for hooks.
+    fn instruction_state(&self, pc: u16, op_code: OpCode) -> InstructionState {
+        InstructionState {
+            pc,
+            op_code,
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            status: self.ps,
+        }
+    }
+
+    /// Sets the level of the IRQ line.
+    /// While asserted, an interrupt is taken before the next instruction unless the
+    /// interrupt disable flag is set, or was just cleared by `CLI` or `PLP`.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    /// Signals a non-maskable interrupt, taken before the next instruction.
+    pub fn nmi(&mut self) {
+        self.nmi_pending = true;
+        self.nmi_polled = true;
+    }
+
+    /// Calls the subroutine at `address` as `JSR` would and runs the CPU until it
+    /// returns, then puts PC back where it was. The routine runs on the current stack,
+    /// with the registers as they are, so arguments are passed by setting them first.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The entry point of the subroutine.
+    /// * `max_cycles` - How long the routine may run before giving up on it.
+    ///
+    /// # Returns
+    ///
+    /// The registers once the routine returned, or an error if it did not return
+    /// within `max_cycles` or the CPU halted. The CPU is then left where it stopped.
+    pub fn call(&mut self, address: u16, max_cycles: u64) -> Result<CallResult, String> {
+        if self.is_halted() {
+            return Err("the CPU is halted".to_string());
+        }
+        let caller_pc: u16 = self.pc;
+        let caller_sp: u8 = self.sp;
+        // Only the matching `RTS` gets back both the address and the stack pointer,
+        // which it pulls less 1 as `JSR` pushes it
+        let return_address: u16 = CALL_RETURN_ADDRESS.wrapping_sub(1);
+        self.stack_push((return_address >> 8) as u8);
+        self.stack_push(return_address as u8);
+        self.pc = address;
+
+        let mut cycles: u64 = 0;
+        while self.pc != CALL_RETURN_ADDRESS || self.sp != caller_sp {
+            if cycles >= max_cycles {
+                return Err(format!(
+                    "the routine at {:#06x} did not return within {} cycles, PC is {:#06x}",
+                    address, max_cycles, self.pc
+                ));
+            }
+            if self.is_halted() {
+                return Err(format!(
+                    "the CPU halted at {:#06x} in the routine at {:#06x}",
+                    self.pc, address
+                ));
+            }
+            cycles += self.step() as u64;
+        }
+        self.pc = caller_pc;
+
+        Ok(CallResult {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            status: self.ps,
+            cycles,
+        })
+    }
+
+    /// Executes one instruction, or enters the handler of a pending interrupt, then
+    /// advances the devices mapped in memory by the cycles it took.
+    ///
+    /// # Returns
+    /// The number of cycles consumed, including those stalled on RDY while devices
+    /// transferred memory, `0` if the CPU is halted.
+    pub fn step(&mut self) -> u32 {
+        if self.is_halted() {
+            return 0;
+        }
+
+        let start_cycles: u64 = self.cycles;
+        let interrupt_disable: bool = self.ps.interrupt_disable();
+        // Like interrupt entries, BRK does not poll, the handler runs an instruction first
+        let polls: bool = self.execute_next() && self.executed != Some(OpCode::Brk);
+
+        let mut cycles: u32 = (self.cycles - start_cycles) as u32;
+
+        // Like the hardware, the interrupt lines are polled at the end of the
+        // second-to-last cycle, so one raised in the last cycle waits an instruction
+        let poll_cycles: u32 = if polls {
+            self.poll_cycles(cycles)
+        } else {
+            cycles.min(HIJACK_CYCLES)
+        };
+        let irq_state: IrqState = self.tick_devices(poll_cycles);
+        if !polls && self.nmi_pending && self.pc == self.mem.borrow().get_interrupt_vector() {
+            // An NMI raised before BRK or an IRQ entry fetched the vector takes it over
+            self.nmi_pending = false;
+            self.nmi_polled = false;
+            self.pc = self.mem.borrow().get_nmi_vector();
+            self.log(Category::Irq, LogLevel::Info, || {
+                format!("NMI took the entry over, to {:#06x}", self.pc)
+            });
+        }
+        if polls {
+            // The flag changes in the last cycle of CLI, SEI and PLP, after the poll
+            let polled_disable: bool = match self.executed {
+                Some(OpCode::Cli | OpCode::Sei | OpCode::Plp) => interrupt_disable,
+                _ => self.ps.interrupt_disable(),
+            };
+            self.irq_polled = (self.irq_line || irq_state.irq) && !polled_disable;
+            self.nmi_polled |= self.nmi_pending;
+            self.irq_delayed = matches!(self.executed, Some(OpCode::Cli | OpCode::Plp))
+                && interrupt_disable
+                && !self.ps.interrupt_disable();
+        }
+        if cycles > poll_cycles {
+            self.tick_devices(cycles - poll_cycles);
+        }
+        // While a device transfers, RDY holds the CPU and the other devices keep running
+        loop {
+            let stall: u32 = self.mem.borrow_mut().take_stall_cycles();
+            if stall == 0 {
+                break;
+            }
+            self.cycles += stall as u64;
+            cycles += stall;
+            self.tick_devices(stall);
+        }
+
+        cycles
+    }
+
+    /// # Returns
+    /// After how many of the `cycles` of the last instruction it polled for
+    /// interrupts. Taken branches that stay in their page poll before their extra
+    /// cycle, delaying an interrupt raised during it.
+    fn poll_cycles(&self, cycles: u32) -> u32 {
+        match self.executed {
+            Some(op_code) if Self::is_jump(op_code) && op_code.cycles() == 2 && cycles == 3 => 1,
+            _ => cycles.saturating_sub(1),
+        }
+    }
+
+    /// Runs the devices for `cycles`, catching the edges of their NMI line.
+    ///
+    /// # Returns
+    /// The interrupt lines of the devices afterwards.
+    fn tick_devices(&mut self, cycles: u32) -> IrqState {
+        let irq_state: IrqState = self.mem.borrow_mut().tick_devices(cycles);
+        if irq_state.nmi && !self.device_nmi {
+            self.nmi_pending = true;
+        }
+        self.device_nmi = irq_state.nmi;
+        irq_state
+    }
+
+    /// # Returns
+    /// An iterator stepping the CPU until it halts, see `Steps`.
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps::new(self)
+    }
+
+    /// Runs the CPU for one cycle, for devices that need to see the bus cycle by cycle,
+    /// instead of a whole `step()`. Mixing both is fine, steps start at the next
+    /// instruction.
+    ///
+    /// The core still executes whole instructions: the first tick of a step runs it,
+    /// like `step()`, the devices and the registers included, and the reads and writes
+    /// it made are handed out one per tick, in order, followed by its internal cycles.
+    ///
+    /// # Returns
+    /// The cycle, `None` if the CPU is halted.
+    pub fn tick(&mut self) -> Option<Tick> {
+        if self.ticks.is_empty() && !self.is_halted() {
+            self.ticking = true;
+            // Drops what was logged since the last step
+            self.update_bus_logging();
+            let cycles: u32 = self.step();
+            let mut accesses: Vec<Access> = std::mem::take(&mut self.tick_accesses);
+            // Interrupt entries and device transfers
+            accesses.append(&mut self.mem.borrow_mut().take_accesses());
+            self.ticking = false;
+            self.update_bus_logging();
+            let sync: bool = self.executed.is_some();
+            self.ticks.extend(ticks::split(cycles, accesses, sync));
+        }
+        self.ticks.pop_front()
+    }
+
+    /// Enters a pending interrupt or executes the next instruction.
+    /// # Returns
+    /// `false` if it entered an interrupt, which does not poll for interrupts.
+    fn execute_next(&mut self) -> bool {
+        self.executed = None;
+        let irq: bool =
+            self.irq_polled || (self.irq_line && !self.ps.interrupt_disable() && !self.irq_delayed);
+        self.irq_delayed = false;
+        if self.nmi_polled {
+            self.nmi_polled = false;
+            self.nmi_pending = false;
+            let vector: u16 = self.mem.borrow().get_nmi_vector();
+            self.interrupt(vector);
+            return false;
+        }
+        if irq {
+            let vector: u16 = self.mem.borrow().get_interrupt_vector();
+            self.interrupt(vector);
+            return false;
+        }
+
+        if let Some(trap) = self.traps.get(&self.pc).cloned() {
+            self.log(Category::Exec, LogLevel::Info, || {
+                format!("Trap at {:#06x}", self.pc)
+            });
+            match (trap.borrow_mut())(self) {
+                TrapAction::Continue => {}
+                TrapAction::Return => {
+                    self.pull_return_address();
+                    self.cycles += OpCode::Rts.cycles() as u64;
+                    return true;
+                }
+                TrapAction::Wait => {
+                    self.cycles += OpCode::Nop.cycles() as u64;
+                    return true;
+                }
+            }
+        }
+
+        let instruction_pc: u16 = self.pc;
+        self.instruction_pc = instruction_pc;
+        if !self.pre_hooks.is_empty() {
+            let op_code: u8 = self.mem.borrow().read(self.pc);
+            if let Some(op_code) = OpCode::from_byte(op_code) {
+                let state: InstructionState = self.instruction_state(self.pc, op_code);
+                let mut stop: bool = false;
+                for hook in &self.pre_hooks {
+                    stop |= (hook.borrow_mut())(&state) == HookAction::Stop;
+                }
+                if stop {
+                    self.halt(HaltReason::Breakpoint);
+                    return true;
+                }
+            }
+        }
+        if self.watches_bus() {
+            // Left by a trap, the hooks or the devices
+            self.mem.borrow_mut().take_accesses();
+        }
+        let mut record: Option<TraceRecord> = self.tracer.is_some().then(|| TraceRecord {
+            pc: self.pc,
+            bytes: Vec::new(),
+            mnemonic: String::new(),
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            sp: self.sp,
+            status: self.ps,
+            cycles: self.cycles,
+            duration: 0,
+            accesses: Vec::new(),
+        });
+        self.fetched = 0;
+        let op_code: u8 = self.fetch();
+        if self.pc_history.len() == PC_HISTORY_DEPTH {
+            self.pc_history.pop_front();
+        }
+        self.pc_history.push_back((instruction_pc, op_code));
+        let op_code: OpCode = match OpCode::from_byte(op_code) {
+            Some(op_code) => op_code,
+            None => {
+                if self.variant == Variant::W65c816 {
+                    if let Some(cycles) = w65c816::execute(self, op_code) {
+                        self.cycles += cycles as u64;
+                        return true;
+                    }
+                }
+                self.log(Category::Exec, LogLevel::Info, || {
+                    format!("Jammed on {:#04x} at {:#06x}", op_code, instruction_pc)
+                });
+                self.pc = instruction_pc;
+                self.halt(HaltReason::Jam);
+                return true;
+            }
+        };
+        self.log(Category::Exec, LogLevel::Debug, || {
+            format!(
+                "Executing {} ({:#04x}) at {:#06x}",
+                op_code, op_code as u8, instruction_pc
+            )
+        });
+        self.executed = Some(op_code);
+        self.cycles += op_code.cycles() as u64;
+        self.execute(op_code);
+        if self.trap_detection && self.pc == instruction_pc && Self::is_jump(op_code) {
+            self.halt(HaltReason::Trap);
+            self.trap_pc = Some(instruction_pc);
+        }
+        if !self.post_hooks.is_empty() {
+            let state: InstructionState = self.instruction_state(instruction_pc, op_code);
+            for hook in &self.post_hooks {
+                (hook.borrow_mut())(&state);
             }
-            self.execute(op_code.into());
-            #[cfg(debug_assertions)]
-            {
-                println!("== Done ==\n");
+        }
+        let accesses: Vec<Access> = if self.watches_bus() {
+            self.mem.borrow_mut().take_accesses()
+        } else {
+            Vec::new()
+        };
+        if self.logs(Category::Bus, LogLevel::Trace) {
+            for access in &accesses {
+                self.log(Category::Bus, LogLevel::Trace, || {
+                    let verb: &str = if access.write { "Wrote" } else { "Read" };
+                    format!("{} {:#04x} at {:#06x}", verb, access.value, access.address)
+                });
             }
         }
+        self.log(Category::Exec, LogLevel::Trace, || {
+            format!(
+                "Done, a={:#04x} x={:#04x} y={:#04x} sp={:#04x} p={}",
+                self.a, self.x, self.y, self.sp, self.ps
+            )
+        });
+        if self.ticking {
+            self.tick_accesses.extend_from_slice(&accesses);
+        }
+        if let (Some(record), Some(tracer)) = (&mut record, &self.tracer) {
+            record.accesses = accesses;
+            // The opcode and operand are fetched before anything else
+            record.bytes = record
+                .accesses
+                .iter()
+                .take(self.fetched as usize)
+                .map(|access| access.value)
+                .collect();
+            record.mnemonic = op_code.mnemonic().to_string();
+            record.duration = (self.cycles - record.cycles) as u32;
+            (tracer.borrow_mut())(record);
+        }
+        true
+    }
+
+    /// # Returns
+    /// `true` for instructions that can leave PC pointing at themselves (`JMP` and branches).
+    fn is_jump(op_code: OpCode) -> bool {
+        matches!(
+            op_code,
+            OpCode::Jmp
+                | OpCode::JmpI
+                | OpCode::Bcc
+                | OpCode::Bcs
+                | OpCode::Beq
+                | OpCode::Bmi
+                | OpCode::Bne
+                | OpCode::Bpl
+                | OpCode::Bvc
+                | OpCode::Bvs
+        )
+    }
+
+    /// Pushes PC and PS, then jumps to `vector` with interrupts disabled.
+    fn interrupt(&mut self, vector: u16) {
+        self.irq_polled = false;
+        self.instruction_pc = self.pc;
+        self.enter_call(self.pc);
+        self.log(Category::Irq, LogLevel::Info, || {
+            format!("Interrupt at {:#06x}, to {:#06x}", self.pc, vector)
+        });
+        self.stack_push((self.pc >> 8) as u8);
+        self.stack_push(self.pc as u8);
+        self.stack_push(((self.ps & !StatusFlags::BREAK) | StatusFlags::UNUSED).bits());
+        self.mask_interrupts();
+        self.pc = vector;
+        self.cycles += 7;
+    }
+
+    /// Sets the interrupt disable flag on entering a handler, once the flags are
+    /// pushed. The 65C02 and the 65816 clear decimal mode too, the NMOS 6502 leaves it
+    /// to the handler.
+    fn mask_interrupts(&mut self) {
+        self.ps.set_interrupt_disable(true);
+        if self.variant != Variant::Nmos {
+            self.ps.set_decimal_mode(false);
+        }
     }
 
     fn execute(&mut self, op_code: opcodes::OpCode) {
         match op_code {
             OpCode::Nop => {}
             OpCode::Brk => {
-                self.set_flag(BREAK_FLAG);
-                self.stack_push((self.pc >> 8) as u8);
-                self.stack_push(self.pc as u8);
-                self.stack_push(self.ps);
+                // The byte after BRK is skipped, it is left for the handler to look at
+                let return_address: u16 = self.pc.wrapping_add(1);
+                self.stack_push((return_address >> 8) as u8);
+                self.stack_push(return_address as u8);
+                // Only the pushed copy tells BRK apart from IRQ
+                self.stack_push(self.pushed_status().bits());
+                self.mask_interrupts();
+                self.enter_call(self.instruction_pc);
 
                 self.pc = self.mem.borrow().get_interrupt_vector();
             }
             OpCode::Rti => {
-                self.ps = self.stack_pop();
+                self.ps = Self::pulled_status(self.stack_pop());
                 self.pc = self.stack_pop() as u16;
                 self.pc |= (self.stack_pop() as u16) << 8;
+                self.leave_call();
             }
             OpCode::Jmp => {
                 let address: u16 = self.fetch_word();
                 self.pc = address;
             }
             OpCode::JmpI => {
-                let address: u16 = self.fetch_word();
-                let address: u16 = self.read_word(address);
+                let pointer: u16 = self.fetch_word();
+                // The NMOS 6502 does not carry into the high byte of the pointer
+                let high_pointer: u16 = (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff);
+                let low_byte: u8 = self.mem.borrow().read(pointer);
+                let high_byte: u8 = self.mem.borrow().read(high_pointer);
+                let address: u16 = (high_byte as u16) << 8 | (low_byte as u16);
                 self.pc = address;
             }
             OpCode::Jsr => {
                 let address = self.fetch_word();
-                self.stack_push((self.pc >> 8) as u8);
-                self.stack_push(self.pc as u8);
+                // The address of the last byte of JSR, RTS adds the 1
+                let return_address: u16 = self.pc.wrapping_sub(1);
+                self.stack_push((return_address >> 8) as u8);
+                self.stack_push(return_address as u8);
+                self.enter_call(self.instruction_pc);
                 self.pc = address;
             }
-            OpCode::Rts => {
-                self.pc = self.stack_pop() as u16;
-                self.pc |= (self.stack_pop() as u16) << 8;
-            }
+            OpCode::Rts => self.pull_return_address(),
             OpCode::Clc => {
-                self.reset_flag(CARRY_FLAG);
+                self.ps.set_carry(false);
             }
             OpCode::Cld => {
-                self.reset_flag(DECIMAL_MODE_FLAG);
+                self.ps.set_decimal_mode(false);
             }
             OpCode::Cli => {
-                self.reset_flag(INTERRUPT_DISABLE_FLAG);
+                self.ps.set_interrupt_disable(false);
             }
             OpCode::Clv => {
-                self.reset_flag(OVERFLOW_FLAG);
+                self.ps.set_overflow(false);
             }
             OpCode::Sec => {
-                self.set_flag(CARRY_FLAG);
+                self.ps.set_carry(true);
             }
             OpCode::Sed => {
-                self.set_flag(DECIMAL_MODE_FLAG);
+                self.ps.set_decimal_mode(true);
             }
             OpCode::Sei => {
-                self.set_flag(INTERRUPT_DISABLE_FLAG);
+                self.ps.set_interrupt_disable(true);
             }
             OpCode::LdaI => {
                 self.a = self.fetch();
@@ -165,7 +1091,8 @@ impl Mos6502 {
             }
             OpCode::LdaZpX => {
                 let address: u8 = self.fetch();
-                self.a = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.a = self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
@@ -177,26 +1104,30 @@ impl Mos6502 {
             }
             OpCode::LdaAX => {
                 let address: u16 = self.fetch_word();
-                self.a = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                self.a = self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::LdaAY => {
                 let address = self.fetch_word();
-                self.a = self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                self.a = self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::LdaIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 self.a = self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::LdaIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 self.a = self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
@@ -214,7 +1145,8 @@ impl Mos6502 {
             }
             OpCode::LdxZpY => {
                 let address: u8 = self.fetch();
-                self.x = self.mem.borrow().read(address.wrapping_add(self.y) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.y);
+                self.x = self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.x);
                 self.update_negative_flag(self.x);
             }
@@ -226,7 +1158,8 @@ impl Mos6502 {
             }
             OpCode::LdxAY => {
                 let address: u16 = self.fetch_word();
-                self.x = self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                self.x = self.mem.borrow().read(address);
                 self.update_zero_flag(self.x);
                 self.update_negative_flag(self.x);
             }
@@ -243,7 +1176,8 @@ impl Mos6502 {
             }
             OpCode::LdyZpX => {
                 let address: u8 = self.fetch();
-                self.y = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.y = self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.y);
                 self.update_negative_flag(self.y);
             }
@@ -255,7 +1189,8 @@ impl Mos6502 {
             }
             OpCode::LdyAX => {
                 let address: u16 = self.fetch_word();
-                self.y = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                self.y = self.mem.borrow().read(address);
                 self.update_zero_flag(self.y);
                 self.update_negative_flag(self.y);
             }
@@ -265,9 +1200,8 @@ impl Mos6502 {
             }
             OpCode::StaZpX => {
                 let address: u8 = self.fetch();
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, self.a);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.mem.borrow_mut().write(address as u16, self.a);
             }
             OpCode::StaA => {
                 let address: u16 = self.fetch_word();
@@ -275,24 +1209,24 @@ impl Mos6502 {
             }
             OpCode::StaAX => {
                 let address: u16 = self.fetch_word();
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x as u16), self.a);
+                let address: u16 = self.indexed_write(address, self.x);
+                self.mem.borrow_mut().write(address, self.a);
             }
             OpCode::StaAY => {
                 let address: u16 = self.fetch_word();
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.y as u16), self.a);
+                let address: u16 = self.indexed_write(address, self.y);
+                self.mem.borrow_mut().write(address, self.a);
             }
             OpCode::StaIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 self.mem.borrow_mut().write(address, self.a);
             }
             OpCode::StaIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_write(pointer, self.y);
                 self.mem.borrow_mut().write(address, self.a);
             }
             OpCode::StxZp => {
@@ -301,9 +1235,8 @@ impl Mos6502 {
             }
             OpCode::StxZpY => {
                 let address: u8 = self.fetch();
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.y) as u16, self.x);
+                let address: u8 = self.indexed_zero_page(address, self.y);
+                self.mem.borrow_mut().write(address as u16, self.x);
             }
             OpCode::StxA => {
                 let address: u16 = self.fetch_word();
@@ -315,9 +1248,8 @@ impl Mos6502 {
             }
             OpCode::StyZpX => {
                 let address: u8 = self.fetch();
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, self.y);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.mem.borrow_mut().write(address as u16, self.y);
             }
             OpCode::StyA => {
                 let address: u16 = self.fetch_word();
@@ -325,7 +1257,7 @@ impl Mos6502 {
             }
             OpCode::IncZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
+                let mut value: u8 = self.read_modify(address as u16);
                 value = value.wrapping_add(0x01);
                 self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
@@ -333,17 +1265,16 @@ impl Mos6502 {
             }
             OpCode::IncZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
                 value = value.wrapping_add(0x01);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::IncA => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let mut value: u8 = self.read_modify(address);
                 value = value.wrapping_add(0x01);
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
@@ -351,17 +1282,16 @@ impl Mos6502 {
             }
             OpCode::IncAX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
                 value = value.wrapping_add(0x01);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x as u16), value);
+                self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::DecZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
+                let mut value: u8 = self.read_modify(address as u16);
                 value = value.wrapping_sub(0x01);
                 self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
@@ -369,17 +1299,16 @@ impl Mos6502 {
             }
             OpCode::DecZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
                 value = value.wrapping_sub(0x01);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::DecA => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let mut value: u8 = self.read_modify(address);
                 value = value.wrapping_sub(0x01);
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
@@ -387,11 +1316,10 @@ impl Mos6502 {
             }
             OpCode::DecAX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
                 value = value.wrapping_sub(0x01);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x as u16), value);
+                self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
@@ -416,11 +1344,10 @@ impl Mos6502 {
                 self.update_negative_flag(self.y);
             }
             OpCode::Pha => {
-                self.pc += 0x01;
                 self.stack_push(self.a);
             }
             OpCode::Php => {
-                self.stack_push(self.ps);
+                self.stack_push(self.pushed_status().bits());
             }
             OpCode::Pla => {
                 self.a = self.stack_pop();
@@ -428,7 +1355,7 @@ impl Mos6502 {
                 self.update_negative_flag(self.a);
             }
             OpCode::Plp => {
-                self.ps = self.stack_pop();
+                self.ps = Self::pulled_status(self.stack_pop());
             }
             OpCode::Tax => {
                 self.x = self.a;
@@ -459,108 +1386,28 @@ impl Mos6502 {
                 self.update_negative_flag(self.a);
             }
             OpCode::Bcc => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(CARRY_FLAG) == 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(!self.ps.carry());
             }
             OpCode::Bcs => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(CARRY_FLAG) != 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(self.ps.carry());
             }
             OpCode::Beq => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(ZERO_FLAG) != 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(self.ps.zero());
             }
             OpCode::Bmi => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(NEGATIVE_FLAG) != 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(self.ps.negative());
             }
             OpCode::Bne => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(ZERO_FLAG) == 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(!self.ps.zero());
             }
             OpCode::Bpl => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(NEGATIVE_FLAG) == 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(!self.ps.negative());
             }
             OpCode::Bvc => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(OVERFLOW_FLAG) == 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(!self.ps.overflow());
             }
             OpCode::Bvs => {
-                let offset: u8 = self.fetch();
-                if self.get_flag(OVERFLOW_FLAG) != 0 {
-                    let offset = if offset & 0x80 != 0 {
-                        // If the offset is negative, extend the sign bit to 16 bits
-                        (offset as u16) | 0xff00
-                    } else {
-                        // If the offset is positive, just cast it to u16
-                        offset as u16
-                    };
-                    self.pc = self.pc.wrapping_add(offset);
-                }
+                self.branch(self.ps.overflow());
             }
             OpCode::AdcI => {
                 let value: u8 = self.fetch();
@@ -573,7 +1420,8 @@ impl Mos6502 {
             }
             OpCode::AdcZpX => {
                 let address: u8 = self.fetch();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let value: u8 = self.mem.borrow().read(address as u16);
                 self.adc(value);
             }
             OpCode::AdcA => {
@@ -583,23 +1431,27 @@ impl Mos6502 {
             }
             OpCode::AdcAX => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                let value: u8 = self.mem.borrow().read(address);
                 self.adc(value);
             }
             OpCode::AdcAY => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                let value: u8 = self.mem.borrow().read(address);
                 self.adc(value);
             }
             OpCode::AdcIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 let value: u8 = self.mem.borrow().read(address);
                 self.adc(value);
             }
             OpCode::AdcIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 let value: u8 = self.mem.borrow().read(address);
                 self.adc(value);
             }
@@ -614,7 +1466,8 @@ impl Mos6502 {
             }
             OpCode::SbcZpX => {
                 let address: u8 = self.fetch();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let value: u8 = self.mem.borrow().read(address as u16);
                 self.sbc(value);
             }
             OpCode::SbcA => {
@@ -624,23 +1477,27 @@ impl Mos6502 {
             }
             OpCode::SbcAX => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                let value: u8 = self.mem.borrow().read(address);
                 self.sbc(value);
             }
             OpCode::SbcAY => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                let value: u8 = self.mem.borrow().read(address);
                 self.sbc(value);
             }
             OpCode::SbcIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 let value: u8 = self.mem.borrow().read(address);
                 self.sbc(value);
             }
             OpCode::SbcIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 let value: u8 = self.mem.borrow().read(address);
                 self.sbc(value);
             }
@@ -657,7 +1514,8 @@ impl Mos6502 {
             }
             OpCode::AndZpX => {
                 let address: u8 = self.fetch();
-                self.a &= self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.a &= self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
@@ -669,26 +1527,30 @@ impl Mos6502 {
             }
             OpCode::AndAX => {
                 let address: u16 = self.fetch_word();
-                self.a &= self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                self.a &= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::AndAY => {
                 let address: u16 = self.fetch_word();
-                self.a &= self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                self.a &= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::AndIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 self.a &= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::AndIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 self.a &= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
@@ -720,7 +1582,8 @@ impl Mos6502 {
             }
             OpCode::EorZpX => {
                 let address: u8 = self.fetch();
-                self.a ^= self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.a ^= self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
@@ -732,114 +1595,118 @@ impl Mos6502 {
             }
             OpCode::EorAX => {
                 let address: u16 = self.fetch_word();
-                self.a ^= self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                self.a ^= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::EorAY => {
                 let address: u16 = self.fetch_word();
-                self.a ^= self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                self.a ^= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::EorIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 self.a ^= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::EorIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 self.a ^= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::AslA => {
                 self.update_carry_flag(self.a);
-                self.a = self.a << 1;
+                self.a <<= 1;
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::AslZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
+                let mut value: u8 = self.read_modify(address as u16);
                 self.update_carry_flag(value);
-                value = value << 1;
+                value <<= 1;
                 self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::AslZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
                 self.update_carry_flag(value);
-                value = value << 1;
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                value <<= 1;
+                self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::AslAbs => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let mut value: u8 = self.read_modify(address);
                 self.update_carry_flag(value);
-                value = value << 1;
+                value <<= 1;
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::AslAbsX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
                 self.update_carry_flag(value);
-                value = value << 1;
+                value <<= 1;
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::LsrA => {
-                self.set_flag_to(CARRY_FLAG, self.a & 0b0000_0001);
-                self.a = self.a >> 1;
+                self.ps.set_carry(self.a & 0b0000_0001 != 0);
+                self.a >>= 1;
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::LsrZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
-                self.set_flag_to(CARRY_FLAG, value & 0b0000_0001);
-                value = value >> 1;
+                let mut value: u8 = self.read_modify(address as u16);
+                self.ps.set_carry(value & 0b0000_0001 != 0);
+                value >>= 1;
                 self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::LsrZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
-                self.set_flag_to(CARRY_FLAG, value & 0b0000_0001);
-                value = value >> 1;
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
+                self.ps.set_carry(value & 0b0000_0001 != 0);
+                value >>= 1;
+                self.mem.borrow_mut().write(address as u16, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::LsrAbs => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
-                self.set_flag_to(CARRY_FLAG, value & 0b0000_0001);
-                value = value >> 1;
+                let mut value: u8 = self.read_modify(address);
+                self.ps.set_carry(value & 0b0000_0001 != 0);
+                value >>= 1;
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
             }
             OpCode::LsrAbsX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
-                self.set_flag_to(CARRY_FLAG, value & 0b0000_0001);
-                value = value >> 1;
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
+                self.ps.set_carry(value & 0b0000_0001 != 0);
+                value >>= 1;
                 self.mem.borrow_mut().write(address, value);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
@@ -848,50 +1715,50 @@ impl Mos6502 {
                 let mut value: u8 = self.a;
                 let bit: u8 = (value & 0b1000_0000) >> 7;
                 // Sets bit 0 to the carry flag (works because Carry Flag is 0x1), another value would set another bit
-                value = (value << 1) | self.get_flag(CARRY_FLAG);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value << 1) | self.ps.carry() as u8;
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.a = value;
             }
             OpCode::RolZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
+                let mut value: u8 = self.read_modify(address as u16);
                 let bit: u8 = (value & 0b1000_0000) >> 7;
-                value = (value << 1) | self.get_flag(CARRY_FLAG);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value << 1) | self.ps.carry() as u8;
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address as u16, value);
             }
             OpCode::RolZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
                 let bit: u8 = (value & 0b1000_0000) >> 7;
-                value = (value << 1) | self.get_flag(CARRY_FLAG);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value << 1) | self.ps.carry() as u8;
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                self.mem.borrow_mut().write(address as u16, value);
             }
             OpCode::RolAbs => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let mut value: u8 = self.read_modify(address);
                 let bit: u8 = (value & 0b1000_0000) >> 7;
-                value = (value << 1) | self.get_flag(CARRY_FLAG);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value << 1) | self.ps.carry() as u8;
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address, value);
             }
             OpCode::RolAbsX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
                 let bit: u8 = (value & 0b1000_0000) >> 7;
-                value = (value << 1) | self.get_flag(CARRY_FLAG);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value << 1) | self.ps.carry() as u8;
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address, value);
@@ -899,50 +1766,50 @@ impl Mos6502 {
             OpCode::RorA => {
                 let mut value: u8 = self.a;
                 let bit: u8 = value & 0b0000_0001;
-                value = (value >> 1) | (self.get_flag(CARRY_FLAG) << 7);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value >> 1) | ((self.ps.carry() as u8) << 7);
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.a = value;
             }
             OpCode::RorZp => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address as u16);
+                let mut value: u8 = self.read_modify(address as u16);
                 let bit: u8 = value & 0b0000_0001;
-                value = (value >> 1) | (self.get_flag(CARRY_FLAG) << 7);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value >> 1) | ((self.ps.carry() as u8) << 7);
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address as u16, value);
             }
             OpCode::RorZpX => {
                 let address: u8 = self.fetch();
-                let mut value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let mut value: u8 = self.read_modify(address as u16);
                 let bit: u8 = value & 0b0000_0001;
-                value = (value >> 1) | (self.get_flag(CARRY_FLAG) << 7);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value >> 1) | ((self.ps.carry() as u8) << 7);
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
-                self.mem
-                    .borrow_mut()
-                    .write(address.wrapping_add(self.x) as u16, value);
+                self.mem.borrow_mut().write(address as u16, value);
             }
             OpCode::RorAbs => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let mut value: u8 = self.read_modify(address);
                 let bit: u8 = value & 0b0000_0001;
-                value = (value >> 1) | (self.get_flag(CARRY_FLAG) << 7);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value >> 1) | ((self.ps.carry() as u8) << 7);
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address, value);
             }
             OpCode::RorAbsX => {
                 let address: u16 = self.fetch_word();
-                let mut value: u8 = self.mem.borrow().read(address);
+                let address: u16 = self.indexed_write(address, self.x);
+                let mut value: u8 = self.read_modify(address);
                 let bit: u8 = value & 0b0000_0001;
-                value = (value >> 1) | (self.get_flag(CARRY_FLAG) << 7);
-                self.set_flag_to(CARRY_FLAG, bit);
+                value = (value >> 1) | ((self.ps.carry() as u8) << 7);
+                self.ps.set_carry(bit != 0);
                 self.update_zero_flag(value);
                 self.update_negative_flag(value);
                 self.mem.borrow_mut().write(address, value);
@@ -960,7 +1827,8 @@ impl Mos6502 {
             }
             OpCode::OraZpX => {
                 let address: u8 = self.fetch();
-                self.a |= self.mem.borrow().read(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                self.a |= self.mem.borrow().read(address as u16);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
@@ -972,282 +1840,546 @@ impl Mos6502 {
             }
             OpCode::OraAX => {
                 let address: u16 = self.fetch_word();
-                self.a |= self.mem.borrow().read(address.wrapping_add(self.x as u16));
+                let address: u16 = self.indexed_read(address, self.x);
+                self.a |= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::OraAY => {
                 let address: u16 = self.fetch_word();
-                self.a |= self.mem.borrow().read(address.wrapping_add(self.y as u16));
+                let address: u16 = self.indexed_read(address, self.y);
+                self.a |= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::OraIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 self.a |= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::OraIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 self.a |= self.mem.borrow().read(address);
                 self.update_zero_flag(self.a);
                 self.update_negative_flag(self.a);
             }
             OpCode::CmpI => {
                 let value: u8 = self.fetch();
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpZp => {
                 let address: u8 = self.fetch();
                 let value: u8 = self.mem.borrow().read(address as u16);
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpZpX => {
                 let address: u8 = self.fetch();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x) as u16);
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let value: u8 = self.mem.borrow().read(address as u16);
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpA => {
                 let address: u16 = self.fetch_word();
                 let value: u8 = self.mem.borrow().read(address);
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpAX => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.x as u16));
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                let address: u16 = self.indexed_read(address, self.x);
+                let value: u8 = self.mem.borrow().read(address);
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpAY => {
                 let address: u16 = self.fetch_word();
-                let value: u8 = self.mem.borrow().read(address.wrapping_add(self.y as u16));
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                let address: u16 = self.indexed_read(address, self.y);
+                let value: u8 = self.mem.borrow().read(address);
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpIX => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.x) as u16);
+                let address: u8 = self.indexed_zero_page(address, self.x);
+                let address: u16 = self.read_zero_page_word(address);
                 let value: u8 = self.mem.borrow().read(address);
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CmpIY => {
                 let address: u8 = self.fetch();
-                let address: u16 = self.read_word(address.wrapping_add(self.y) as u16);
+                let pointer: u16 = self.read_zero_page_word(address);
+                let address: u16 = self.indexed_read(pointer, self.y);
                 let value: u8 = self.mem.borrow().read(address);
-                self.update_carry_flag(self.a.wrapping_sub(value));
+                self.ps.set_carry(self.a >= value);
                 self.update_zero_flag(self.a.wrapping_sub(value));
                 self.update_negative_flag(self.a.wrapping_sub(value));
             }
             OpCode::CpxI => {
                 let value: u8 = self.fetch();
-                self.update_carry_flag(self.x.wrapping_sub(value));
+                self.ps.set_carry(self.x >= value);
                 self.update_zero_flag(self.x.wrapping_sub(value));
                 self.update_negative_flag(self.x.wrapping_sub(value));
             }
             OpCode::CpxZp => {
                 let address: u8 = self.fetch();
                 let value: u8 = self.mem.borrow().read(address as u16);
-                self.update_carry_flag(self.x.wrapping_sub(value));
+                self.ps.set_carry(self.x >= value);
                 self.update_zero_flag(self.x.wrapping_sub(value));
                 self.update_negative_flag(self.x.wrapping_sub(value));
             }
             OpCode::CpxA => {
                 let address: u16 = self.fetch_word();
                 let value: u8 = self.mem.borrow().read(address);
-                self.update_carry_flag(self.x.wrapping_sub(value));
+                self.ps.set_carry(self.x >= value);
                 self.update_zero_flag(self.x.wrapping_sub(value));
                 self.update_negative_flag(self.x.wrapping_sub(value));
             }
             OpCode::CpyI => {
                 let value: u8 = self.fetch();
-                self.update_carry_flag(self.y.wrapping_sub(value));
+                self.ps.set_carry(self.y >= value);
                 self.update_zero_flag(self.y.wrapping_sub(value));
                 self.update_negative_flag(self.y.wrapping_sub(value));
             }
             OpCode::CpyZp => {
                 let address: u8 = self.fetch();
                 let value: u8 = self.mem.borrow().read(address as u16);
-                self.update_carry_flag(self.y.wrapping_sub(value));
+                self.ps.set_carry(self.y >= value);
                 self.update_zero_flag(self.y.wrapping_sub(value));
                 self.update_negative_flag(self.y.wrapping_sub(value));
             }
             OpCode::CpyA => {
                 let address: u16 = self.fetch_word();
                 let value: u8 = self.mem.borrow().read(address);
-                self.update_carry_flag(self.y.wrapping_sub(value));
+                self.ps.set_carry(self.y >= value);
                 self.update_zero_flag(self.y.wrapping_sub(value));
                 self.update_negative_flag(self.y.wrapping_sub(value));
             }
         }
     }
 
+    /// # Returns
+    /// `base` indexed by `index`, for the instructions that only read there: they take
+    /// a cycle more when that crosses a page, to fix the high byte of the address.
+    fn indexed_read(&mut self, base: u16, index: u8) -> u16 {
+        let address: u16 = base.wrapping_add(index as u16);
+        if address & 0xff00 != base & 0xff00 {
+            self.cycles += 1;
+            self.dummy_read(base, address);
+        }
+        address
+    }
+
+    /// # Returns
+    /// `base` indexed by `index`, for the instructions that write there: they always
+    /// take the cycle fixing the high byte, so its dummy read is made even when the
+    /// page is the same.
+    fn indexed_write(&mut self, base: u16, index: u8) -> u16 {
+        let address: u16 = base.wrapping_add(index as u16);
+        self.dummy_read(base, address);
+        address
+    }
+
+    /// # Returns
+    /// The zero page address `base` indexed by `index`, wrapping in the zero page. Like
+    /// the hardware, `base` is read while the index is added, see `dummy_read()`.
+    fn indexed_zero_page(&self, base: u8, index: u8) -> u8 {
+        self.dummy_read(base as u16, base as u16);
+        base.wrapping_add(index)
+    }
+
+    /// Makes the read of the cycle an indexed instruction spends adding the index to
+    /// `base`, before `address` is known. The NMOS 6502 reads `address` with the high
+    /// byte of `base`, the 65C02 the last byte of the instruction, which hardware
+    /// registers with read side effects tell apart.
+    fn dummy_read(&self, base: u16, address: u16) {
+        let dummy: u16 = match self.variant {
+            Variant::Nmos | Variant::W65c816 => (base & 0xff00) | (address & 0x00ff),
+            Variant::Cmos => self.pc.wrapping_sub(1),
+        };
+        self.mem.borrow().read(dummy);
+    }
+
+    /// # Returns
+    /// The byte at `address`, for a read-modify-write instruction to change and write
+    /// back. The NMOS 6502 first writes it back unmodified while changing it, the 65C02
+    /// reads it again.
+    fn read_modify(&self, address: u16) -> u8 {
+        let value: u8 = self.mem.borrow().read(address);
+        match self.variant {
+            Variant::Nmos | Variant::W65c816 => self.mem.borrow_mut().write(address, value),
+            Variant::Cmos => {
+                self.mem.borrow().read(address);
+            }
+        }
+        value
+    }
+
     /// # Returns
     /// The instruction located at the current address stored in the PC register.
-    /// PC is incremented by 1.
+    /// PC is incremented by 1, wrapping from `0xffff` to `0x0000` like the hardware.
     fn fetch(&mut self) -> u8 {
         let value: u8 = self.mem.borrow().read(self.pc);
-        self.pc += 0x01;
+        self.log(Category::Fetch, LogLevel::Trace, || {
+            format!("Fetched {:#04x} at {:#06x}", value, self.pc)
+        });
+        self.pc = self.pc.wrapping_add(0x01);
+        self.fetched += 1;
         value
     }
 
+    /// # Returns
+    /// The little-endian word at PC, whose high byte is at `0x0000` when PC is
+    /// `0xffff`. PC is incremented by 2, wrapping like `fetch()`.
     fn fetch_word(&mut self) -> u16 {
         let low_byte: u8 = self.mem.borrow().read(self.pc);
         let high_byte: u8 = self.mem.borrow().read(self.pc.wrapping_add(0x01));
         let address: u16 = (high_byte as u16) << 8 | (low_byte as u16);
-        self.pc += 0x02;
+        self.log(Category::Fetch, LogLevel::Trace, || {
+            format!("Fetched {:#06x} at {:#06x}", address, self.pc)
+        });
+        self.pc = self.pc.wrapping_add(0x02);
+        self.fetched += 2;
         address
     }
 
-    fn read_word(&self, address: u16) -> u16 {
-        let low_byte: u8 = self.mem.borrow().read(address);
-        let high_byte: u8 = self.mem.borrow().read(address.wrapping_add(0x01));
+    /// # Returns
+    /// The pointer at `address` in the zero page, its high byte wrapping to `0x00`.
+    fn read_zero_page_word(&self, address: u8) -> u16 {
+        let low_byte: u8 = self.mem.borrow().read(address as u16);
+        let high_byte: u8 = self.mem.borrow().read(address.wrapping_add(0x01) as u16);
         (high_byte as u16) << 8 | (low_byte as u16)
     }
 
+    /// Fetches the branch offset and, if `condition` holds, adds it to PC.
+    /// A taken branch costs one extra cycle, two if it crosses a page.
+    fn branch(&mut self, condition: bool) {
+        let offset: u8 = self.fetch();
+        if condition {
+            let offset = if offset & 0x80 != 0 {
+                // If the offset is negative, extend the sign bit to 16 bits
+                (offset as u16) | 0xff00
+            } else {
+                // If the offset is positive, just cast it to u16
+                offset as u16
+            };
+            let target: u16 = self.pc.wrapping_add(offset);
+            self.cycles += if target & 0xff00 == self.pc & 0xff00 {
+                1
+            } else {
+                2
+            };
+            self.pc = target;
+        }
+    }
+
     fn stack_push(&mut self, value: u8) {
         self.mem.borrow_mut().write(0x0100 + self.sp as u16, value);
-        self.sp -= 1;
+        if self.sp == 0x00 {
+            self.report_stack_wrap(StackWrapKind::Overflow);
+        }
+        self.sp = self.sp.wrapping_sub(1);
+    }
+
+    /// Pulls the address pushed by `JSR` into PC, plus 1 as `RTS` does.
+    fn pull_return_address(&mut self) {
+        let low_byte: u8 = self.stack_pop();
+        let high_byte: u8 = self.stack_pop();
+        self.pc = u16::from_le_bytes([low_byte, high_byte]).wrapping_add(1);
+        self.leave_call();
+    }
+
+    /// # Returns
+    /// PS as `BRK` and `PHP` push it, with B and bit 5 set.
+    fn pushed_status(&self) -> StatusFlags {
+        self.ps | StatusFlags::BREAK | StatusFlags::UNUSED
+    }
+
+    /// # Returns
+    /// The `value` pulled by `PLP` and `RTI` as PS holds it: B only exists on the
+    /// stack, and bit 5 is always set.
+    fn pulled_status(value: u8) -> StatusFlags {
+        StatusFlags::from_bits((value & !StatusFlags::BREAK.bits()) | StatusFlags::UNUSED.bits())
     }
 
     fn stack_pop(&mut self) -> u8 {
-        self.sp += 1;
+        if self.sp == 0xff {
+            self.report_stack_wrap(StackWrapKind::Underflow);
+        }
+        self.sp = self.sp.wrapping_add(1);
         self.mem.borrow().read(0x0100 + self.sp as u16)
     }
 
-    fn update_zero_flag(&mut self, value: u8) {
-        if value == 0x00 {
-            self.set_flag(ZERO_FLAG);
-        } else {
-            self.reset_flag(ZERO_FLAG);
+    /// Records a call made at `pc` in the call trace, while the stack is watched.
+    fn enter_call(&mut self, pc: u16) {
+        if self.stack_watch.is_some() {
+            if self.call_trace.len() == CALL_TRACE_DEPTH {
+                self.call_trace.remove(0);
+            }
+            self.call_trace.push(pc);
         }
     }
 
-    fn update_negative_flag(&mut self, value: u8) {
-        if value & 0x80 == 0x80 {
-            self.set_flag(NEGATIVE_FLAG);
-        } else {
-            self.reset_flag(NEGATIVE_FLAG);
+    /// Removes the innermost call from the call trace, see `enter_call()`.
+    fn leave_call(&mut self) {
+        self.call_trace.pop();
+    }
+
+    fn report_stack_wrap(&self, kind: StackWrapKind) {
+        if let Some(watch) = &self.stack_watch {
+            (watch.borrow_mut())(&StackWrap {
+                kind,
+                pc: self.instruction_pc,
+                calls: self.call_trace.clone(),
+            });
         }
     }
 
+    fn update_zero_flag(&mut self, value: u8) {
+        self.ps.set_zero(value == 0x00);
+    }
+
+    fn update_negative_flag(&mut self, value: u8) {
+        self.ps.set_negative(value & 0x80 == 0x80);
+    }
+
     fn update_carry_flag(&mut self, value: u8) {
-        if value & 0x80 == 0x80 {
-            self.set_flag(CARRY_FLAG);
-        } else {
-            self.reset_flag(CARRY_FLAG);
-        }
+        self.ps.set_carry(value & 0x80 == 0x80);
     }
 
     fn update_overflow_flag(&mut self, value: u8) {
-        if value & 0x40 == 0x40 {
-            self.set_flag(OVERFLOW_FLAG);
-        } else {
-            self.reset_flag(OVERFLOW_FLAG);
-        }
+        self.ps.set_overflow(value & 0x40 == 0x40);
     }
 
     fn adc(&mut self, value: u8) {
-        let result: u8 = self
-            .a
-            .wrapping_add(value)
-            .wrapping_add(self.get_flag(CARRY_FLAG));
-        // Set OVERFLOW_FLAG if the sign of the result is different from the sign of both operands
-        if (self.a & 0x80) == 0 && (value & 0x80) == 0 && (result & 0x80) != 0
-            || (self.a & 0x80) != 0 && (value & 0x80) != 0 && (result & 0x80) == 0
-        {
-            self.set_flag(OVERFLOW_FLAG);
-        } else {
-            self.reset_flag(OVERFLOW_FLAG);
+        match self.ps.decimal_mode() {
+            true => {
+                self.adc_decimal(value);
+                self.decimal_cycle();
+            }
+            false => self.adc_binary(value),
         }
-        self.update_zero_flag(result as u8);
-        self.update_negative_flag(result as u8);
-        self.a = result as u8;
     }
 
-    fn sbc(&mut self, value: u8) {
-        let result: u8;
-        if self.get_flag(CARRY_FLAG) == 0 {
-            result = self.a.wrapping_sub(value).wrapping_sub(1);
-        } else {
-            result = self.a.wrapping_sub(value);
-        }
-
-        // Check for overflow
-        if (self.a ^ value) & (self.a ^ result) & 0x80 != 0 {
-            self.set_flag(OVERFLOW_FLAG);
-        } else {
-            self.reset_flag(OVERFLOW_FLAG);
+    /// The 65C02 takes a cycle more for `ADC` and `SBC` in decimal mode.
+    fn decimal_cycle(&mut self) {
+        if self.variant == Variant::Cmos {
+            self.cycles += 1;
         }
+    }
 
-        self.update_zero_flag(result as u8);
-        self.update_negative_flag(result as u8);
+    fn adc_binary(&mut self, value: u8) {
+        let sum: u16 = self.a as u16 + value as u16 + self.ps.carry() as u16;
+        let result: u8 = sum as u8;
+        // Overflow if the sign of the result is different from the sign of both operands
+        self.ps
+            .set_overflow((self.a ^ result) & (value ^ result) & 0x80 != 0);
+        self.ps.set_carry(sum > 0xff);
+        self.update_zero_flag(result);
+        self.update_negative_flag(result);
         self.a = result;
     }
 
-    fn set_flag(&mut self, flag: u8) {
-        self.ps |= flag;
+    /// Subtracts with borrow, the carry flag being clear when there is a borrow.
+    fn sbc(&mut self, value: u8) {
+        // A - M - (1 - C) is A + !M + C in two's complement, flags included
+        let (a, borrow): (u8, bool) = (self.a, !self.ps.carry());
+        self.adc_binary(!value);
+        if self.ps.decimal_mode() {
+            self.sbc_decimal(a, value, borrow);
+            self.decimal_cycle();
+        }
     }
 
-    fn set_flag_to(&mut self, flag: u8, value: u8) {
-        if value == 0 {
-            self.reset_flag(flag);
-        } else {
-            self.set_flag(flag);
+    /// Adds in binary-coded decimal, a digit at a time. The NMOS chip takes N and V
+    /// from the sum before its tens digit is adjusted and Z from the binary sum, the
+    /// 65C02 N and Z from the result.
+    fn adc_decimal(&mut self, value: u8) {
+        let carry: u16 = self.ps.carry() as u16;
+        let binary: u8 = self.a.wrapping_add(value).wrapping_add(carry as u8);
+        let mut units: u16 = (self.a & 0x0f) as u16 + (value & 0x0f) as u16 + carry;
+        let mut tens: u16 = (self.a >> 4) as u16 + (value >> 4) as u16;
+        if units > 0x09 {
+            units = (units + 0x06) & 0x0f;
+            tens += 1;
+        }
+        let unadjusted: u8 = (tens << 4 | units) as u8;
+        self.ps
+            .set_overflow((self.a ^ unadjusted) & (value ^ unadjusted) & 0x80 != 0);
+        if tens > 0x09 {
+            tens += 0x06;
         }
+        self.ps.set_carry(tens > 0x0f);
+        let result: u8 = (tens << 4 | units) as u8;
+        match self.variant {
+            Variant::Nmos => {
+                self.update_zero_flag(binary);
+                self.update_negative_flag(unadjusted);
+            }
+            Variant::Cmos | Variant::W65c816 => {
+                self.update_zero_flag(result);
+                self.update_negative_flag(result);
+            }
+        }
+        self.a = result;
+    }
+
+    /// Subtracts `value` and `borrow` from `a` in binary-coded decimal, once `sbc()`
+    /// set the flags of the binary difference, which stay but for N and Z on the
+    /// 65C02, those of the result.
+    fn sbc_decimal(&mut self, a: u8, value: u8, borrow: bool) {
+        let borrow: i16 = borrow as i16;
+        let units: i16 = (a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+        self.a = match self.variant {
+            Variant::Nmos => {
+                let mut units: i16 = units;
+                let mut tens: i16 = (a >> 4) as i16 - (value >> 4) as i16;
+                if units < 0 {
+                    units -= 0x06;
+                    tens -= 1;
+                }
+                if tens < 0 {
+                    tens -= 0x06;
+                }
+                (tens << 4 | (units & 0x0f)) as u8
+            }
+            Variant::Cmos | Variant::W65c816 => {
+                let mut difference: i16 = a as i16 - value as i16 - borrow;
+                if difference < 0 {
+                    difference -= 0x60;
+                }
+                if units < 0 {
+                    difference -= 0x06;
+                }
+                self.update_zero_flag(difference as u8);
+                self.update_negative_flag(difference as u8);
+                difference as u8
+            }
+        };
     }
 
-    fn reset_flag(&mut self, flag: u8) {
-        self.ps &= !flag;
+    /// # Returns
+    /// The instruction at PC as assemblers write it, see `disassemble()`.
+    pub fn next_instruction(&self) -> String {
+        self.disassemble(self.pc)
     }
 
     /// # Returns
-    /// 0 if the flag is not set.
-    /// Non 0 if set.
-    fn get_flag(&self, flag: u8) -> u8 {
-        self.ps & flag
+    /// The instruction at `address` as assemblers write it, e.g. `LDA ($10),Y`, or a
+    /// `.byte` directive if the opcode is not implemented.
+    ///
+    /// The bytes are read through the bus, like the CPU would fetch them.
+    pub fn disassemble(&self, address: u16) -> String {
+        let mem = self.mem.borrow();
+        let op_code: u8 = mem.read(address);
+        let op_code: OpCode = match OpCode::from_byte(op_code) {
+            Some(op_code) => op_code,
+            None => return format!(".byte ${:02X}", op_code),
+        };
+        let mode: AddressingMode = op_code.addressing_mode();
+        let operand: u16 = match mode.operand_bytes() {
+            0 => return op_code.mnemonic().to_string(),
+            1 => mem.read(address.wrapping_add(1)) as u16,
+            _ => u16::from_le_bytes([
+                mem.read(address.wrapping_add(1)),
+                mem.read(address.wrapping_add(2)),
+            ]),
+        };
+        format!(
+            "{} {}",
+            op_code.mnemonic(),
+            mode.format_operand(operand, address)
+        )
     }
 
-    /// Prints the current state of the CPU to stdout.
-    /// This method is only available when the `debug_assertions` feature is enabled.
-    #[cfg(debug_assertions)]
+    /// Prints the current state of the CPU to stdout, see `Display`.
     pub fn print_state(&self) {
-        println!("== Registers:");
-        println!("  A:  {:#04x}", self.a);
-        println!("  X:  {:#04x}", self.x);
-        println!("  Y:  {:#04x}", self.y);
-        println!("  SP: {:#04x}", self.sp);
-        println!("  PS: {:#04x}", self.ps);
-        println!("  PC: {:#06x}", self.pc);
-        println!("== Memory:");
-        println!(
-            "  {:#06x}: {:#04x}\n",
-            0x0000,
-            self.mem.borrow().read(0x0000)
-        );
+        println!("{}", self);
+    }
+}
+
+/// The registers, the flags and the next instruction, on one line.
+impl fmt::Display for Mos6502 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PC: {:#06x}  A: {:#04x}  X: {:#04x}  Y: {:#04x}  SP: {:#04x}  P: {}  next: {}",
+            self.pc,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.ps,
+            self.next_instruction()
+        )
+    }
+}
+
+impl fmt::Debug for Mos6502 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mos6502")
+            .field("pc", &format_args!("{:#06x}", self.pc))
+            .field("a", &format_args!("{:#04x}", self.a))
+            .field("x", &format_args!("{:#04x}", self.x))
+            .field("y", &format_args!("{:#04x}", self.y))
+            .field("sp", &format_args!("{:#04x}", self.sp))
+            .field("status", &format_args!("{}", self.ps))
+            .field("cycles", &self.cycles)
+            .field("halt_reason", &self.halt_reason)
+            .field("next_instruction", &self.next_instruction())
+            .finish()
     }
 }
 
 #[cfg(test)]
 mod tests_6510 {
     use super::*;
+    use asm6502_macros::m6502_asm;
+    use memory::device::{BusJob, Device, Transfer};
     use std::cell::RefCell;
     use std::rc::Rc;
 
+    /// Asserts IRQ or NMI once `countdown` cycles have passed, until any register is read.
+    struct IrqAfter {
+        countdown: u32,
+        state: IrqState,
+    }
+
+    impl Device for IrqAfter {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.state = IrqState::NONE;
+            0x00
+        }
+
+        fn write(&mut self, _offset: u16, _value: u8) {}
+
+        fn tick(&mut self, cycles: u32) -> IrqState {
+            if self.countdown > 0 {
+                self.countdown = self.countdown.saturating_sub(cycles);
+                if self.countdown == 0 {
+                    return self.state;
+                }
+                return IrqState::NONE;
+            }
+            self.state
+        }
+    }
+
     #[test]
     fn execute_dex() {
         let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
@@ -1258,8 +2390,8 @@ mod tests_6510 {
         cpu.execute(OpCode::Dex);
 
         assert_eq!(cpu.x, 0x00);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), ZERO_FLAG);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert!(cpu.ps.zero());
+        assert!(!cpu.ps.negative());
     }
 
     #[test]
@@ -1272,8 +2404,8 @@ mod tests_6510 {
         cpu.execute(OpCode::Dey);
 
         assert_eq!(cpu.y, 0x00);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), ZERO_FLAG);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert!(cpu.ps.zero());
+        assert!(!cpu.ps.negative());
     }
 
     #[test]
@@ -1283,22 +2415,22 @@ mod tests_6510 {
         cpu.reset();
 
         cpu.pc = 0x0000;
-        cpu.ps = 0x00;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Bcc.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x02);
+        cpu.ps = StatusFlags::empty();
+        cpu.poke(0x0000, OpCode::Bcc.into());
+        cpu.poke(0x0001, 0x02);
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0004);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), 0);
+        assert!(!cpu.ps.carry());
 
         cpu.pc = 0x0000;
-        cpu.ps = 0x01;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Bcc.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x02);
+        cpu.ps = StatusFlags::from_bits(0x01);
+        cpu.poke(0x0000, OpCode::Bcc.into());
+        cpu.poke(0x0001, 0x02);
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0002);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), CARRY_FLAG);
+        assert!(cpu.ps.carry());
     }
 
     #[test]
@@ -1307,15 +2439,15 @@ mod tests_6510 {
         let mut cpu: Mos6502 = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.ps = ZERO_FLAG;
+        cpu.ps = StatusFlags::ZERO;
 
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Beq.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x01);
+        cpu.poke(0x0000, OpCode::Beq.into());
+        cpu.poke(0x0001, 0x01);
 
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0003);
-        assert_eq!(cpu.ps, ZERO_FLAG);
+        assert_eq!(cpu.ps, StatusFlags::ZERO);
     }
 
     #[test]
@@ -1324,15 +2456,15 @@ mod tests_6510 {
         let mut cpu: Mos6502 = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.ps = ZERO_FLAG;
+        cpu.ps = StatusFlags::ZERO;
         cpu.pc = 0x0000;
 
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Nop.into());
-        cpu.mem.borrow_mut().write(0x0001, OpCode::Nop.into());
-        cpu.mem.borrow_mut().write(0x0002, OpCode::LdaI.into());
-        cpu.mem.borrow_mut().write(0x0003, 0x00);
-        cpu.mem.borrow_mut().write(0x0004, OpCode::Beq.into());
-        cpu.mem.borrow_mut().write(0x0005, 0xFC);
+        cpu.poke(0x0000, OpCode::Nop.into());
+        cpu.poke(0x0001, OpCode::Nop.into());
+        cpu.poke(0x0002, OpCode::LdaI.into());
+        cpu.poke(0x0003, 0x00);
+        cpu.poke(0x0004, OpCode::Beq.into());
+        cpu.poke(0x0005, 0xFC);
 
         cpu.step();
         cpu.step();
@@ -1340,7 +2472,7 @@ mod tests_6510 {
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0002);
-        assert_eq!(cpu.ps, ZERO_FLAG);
+        assert_eq!(cpu.ps, StatusFlags::ZERO);
     }
 
     #[test]
@@ -1350,22 +2482,22 @@ mod tests_6510 {
         cpu.reset();
 
         cpu.pc = 0x0000;
-        cpu.ps = 0x00;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Bmi.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x02);
+        cpu.ps = StatusFlags::empty();
+        cpu.poke(0x0000, OpCode::Bmi.into());
+        cpu.poke(0x0001, 0x02);
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0002);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert!(!cpu.ps.negative());
 
         cpu.pc = 0x0000;
-        cpu.ps = 0x80;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::Bmi.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x02);
+        cpu.ps = StatusFlags::from_bits(0x80);
+        cpu.poke(0x0000, OpCode::Bmi.into());
+        cpu.poke(0x0001, 0x02);
         cpu.step();
 
         assert_eq!(cpu.pc, 0x0004);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), NEGATIVE_FLAG);
+        assert!(cpu.ps.negative());
     }
 
     #[test]
@@ -1374,13 +2506,13 @@ mod tests_6510 {
         let mut cpu: Mos6502 = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.mem.borrow_mut().write(0x0000, OpCode::LdaI.into());
-        cpu.mem.borrow_mut().write(0x0001, 0xFA);
+        cpu.poke(0x0000, OpCode::LdaI.into());
+        cpu.poke(0x0001, 0xFA);
         cpu.step();
 
         assert_eq!(cpu.a, 0xFA);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), NEGATIVE_FLAG);
+        assert!(!cpu.ps.zero());
+        assert!(cpu.ps.negative());
     }
 
     #[test]
@@ -1389,13 +2521,13 @@ mod tests_6510 {
         let mut cpu: Mos6502 = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.mem.borrow_mut().write(0x0000, OpCode::LdxI.into());
-        cpu.mem.borrow_mut().write(0x0001, 0xFA);
+        cpu.poke(0x0000, OpCode::LdxI.into());
+        cpu.poke(0x0001, 0xFA);
         cpu.step();
 
         assert_eq!(cpu.x, 0xFA);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), NEGATIVE_FLAG);
+        assert!(!cpu.ps.zero());
+        assert!(cpu.ps.negative());
     }
 
     #[test]
@@ -1404,12 +2536,12 @@ mod tests_6510 {
         let mut cpu: Mos6502 = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.mem.borrow_mut().write(0x0000, OpCode::LdyI.into());
-        cpu.mem.borrow_mut().write(0x0001, 0xFA);
+        cpu.poke(0x0000, OpCode::LdyI.into());
+        cpu.poke(0x0001, 0xFA);
         cpu.step();
 
         assert_eq!(cpu.y, 0xFA);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
+        assert!(!cpu.ps.zero());
     }
 
     #[test]
@@ -1419,14 +2551,14 @@ mod tests_6510 {
         cpu.reset();
 
         cpu.a = 0;
-        cpu.ps = CARRY_FLAG | ZERO_FLAG | NEGATIVE_FLAG;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::RolA.into());
+        cpu.ps = StatusFlags::CARRY | StatusFlags::ZERO | StatusFlags::NEGATIVE;
+        cpu.poke(0x0000, OpCode::RolA.into());
         cpu.step();
 
         assert_eq!(cpu.a, 1);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), 0);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert!(!cpu.ps.carry());
+        assert!(!cpu.ps.zero());
+        assert!(!cpu.ps.negative());
     }
 
     #[test]
@@ -1435,17 +2567,17 @@ mod tests_6510 {
         let mut cpu = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.ps = CARRY_FLAG | ZERO_FLAG | NEGATIVE_FLAG;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::RolZp.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x42);
-        cpu.mem.borrow_mut().write(0x0042, 0);
+        cpu.ps = StatusFlags::CARRY | StatusFlags::ZERO | StatusFlags::NEGATIVE;
+        cpu.poke(0x0000, OpCode::RolZp.into());
+        cpu.poke(0x0001, 0x42);
+        cpu.poke(0x0042, 0);
 
         cpu.step();
 
-        assert_eq!(cpu.mem.borrow().read(0x0042), 1);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), 0);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert_eq!(cpu.peek(0x0042), 1);
+        assert!(!cpu.ps.carry());
+        assert!(!cpu.ps.zero());
+        assert!(!cpu.ps.negative());
     }
 
     #[test]
@@ -1454,17 +2586,17 @@ mod tests_6510 {
         let mut cpu = Mos6502::new(mem);
         cpu.reset();
 
-        cpu.ps = NEGATIVE_FLAG;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::RolZp.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x42);
-        cpu.mem.borrow_mut().write(0x0042, 0x80);
+        cpu.ps = StatusFlags::NEGATIVE;
+        cpu.poke(0x0000, OpCode::RolZp.into());
+        cpu.poke(0x0001, 0x42);
+        cpu.poke(0x0042, 0x80);
 
         cpu.step();
 
-        assert_eq!(cpu.mem.borrow().read(0x0042), 0);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), CARRY_FLAG);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), ZERO_FLAG);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
+        assert_eq!(cpu.peek(0x0042), 0);
+        assert!(cpu.ps.carry());
+        assert!(cpu.ps.zero());
+        assert!(!cpu.ps.negative());
     }
 
     #[test]
@@ -1474,15 +2606,15 @@ mod tests_6510 {
         cpu.reset();
 
         cpu.a = 0x01;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::AdcI.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x01);
+        cpu.poke(0x0000, OpCode::AdcI.into());
+        cpu.poke(0x0001, 0x01);
         cpu.step();
 
         assert_eq!(cpu.a, 0x02);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), 0);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), 0);
-        assert_eq!(cpu.get_flag(OVERFLOW_FLAG), 0);
+        assert!(!cpu.ps.carry());
+        assert!(!cpu.ps.zero());
+        assert!(!cpu.ps.negative());
+        assert!(!cpu.ps.overflow());
     }
 
     #[test]
@@ -1492,14 +2624,1070 @@ mod tests_6510 {
         cpu.reset();
 
         cpu.a = 0x7F;
-        cpu.mem.borrow_mut().write(0x0000, OpCode::AdcI.into());
-        cpu.mem.borrow_mut().write(0x0001, 0x01);
+        cpu.poke(0x0000, OpCode::AdcI.into());
+        cpu.poke(0x0001, 0x01);
         cpu.step();
 
         assert_eq!(cpu.a, 0x80);
-        assert_eq!(cpu.get_flag(CARRY_FLAG), 0);
-        assert_eq!(cpu.get_flag(ZERO_FLAG), 0);
-        assert_eq!(cpu.get_flag(NEGATIVE_FLAG), NEGATIVE_FLAG);
-        assert_eq!(cpu.get_flag(OVERFLOW_FLAG), OVERFLOW_FLAG);
+        assert!(!cpu.ps.carry());
+        assert!(!cpu.ps.zero());
+        assert!(cpu.ps.negative());
+        assert!(cpu.ps.overflow());
+    }
+
+    #[test]
+    fn indirect_indexed_subtract_and_compare() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        let program: [u8; 7] = m6502_asm! {
+            sec
+            lda ($fe),y     // the pointer wraps the zero page
+            sbc #$10
+            cmp #$30
+        };
+        cpu.load(0x0010, &program);
+        cpu.pc = 0x0010;
+        cpu.y = 0x04;
+        cpu.poke(0x00fe, 0x00);
+        cpu.poke(0x00ff, 0x20);
+        cpu.poke(0x2004, 0x40);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.a, 0x40);
+        cpu.step();
+        assert_eq!(cpu.a, 0x30);
+        // No borrow
+        assert!(cpu.ps.carry());
+        cpu.step();
+        assert!(cpu.ps.carry());
+        assert!(cpu.ps.zero());
+
+        // Borrowing clears carry
+        cpu.sbc(0x31);
+        assert_eq!(cpu.a, 0xff);
+        assert!(!cpu.ps.carry());
+    }
+
+    #[test]
+    fn trap_jmp_to_self() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+        cpu.set_trap_detection(true);
+
+        cpu.poke(0x0000, OpCode::Nop.into());
+        cpu.poke(0x0001, OpCode::Jmp.into());
+        cpu.poke(0x0002, 0x01);
+        cpu.poke(0x0003, 0x00);
+        cpu.step();
+
+        assert_eq!(cpu.trapped_at(), None);
+        assert!(!cpu.is_halted());
+
+        cpu.step();
+
+        assert_eq!(cpu.trapped_at(), Some(0x0001));
+        assert!(cpu.is_halted());
+
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x0001);
+    }
+
+    #[test]
+    fn waits_in_a_jump_to_itself_for_interrupts() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).sp(0xff).build();
+        // CLI, JMP $0201, with the handler at $0300
+        cpu.load(0x0200, &m6502_asm! { cli jmp $0201 });
+        cpu.load(0xfffe, &[0x00, 0x03]);
+        cpu.step();
+        for _ in 0..10 {
+            assert!(cpu.step() > 0);
+        }
+        cpu.set_irq(true);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0300);
+        assert_eq!(cpu.trapped_at(), None);
+    }
+
+    #[test]
+    fn trap_branch_to_self() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+        cpu.set_trap_detection(true);
+
+        cpu.ps = StatusFlags::ZERO;
+        cpu.poke(0x0000, OpCode::Bne.into());
+        cpu.poke(0x0001, 0xFE);
+        cpu.poke(0x0002, OpCode::Beq.into());
+        cpu.poke(0x0003, 0xFE);
+        cpu.step();
+
+        assert_eq!(cpu.trapped_at(), None);
+
+        cpu.step();
+
+        assert_eq!(cpu.trapped_at(), Some(0x0002));
+
+        cpu.reset();
+
+        assert_eq!(cpu.trapped_at(), None);
+        assert!(!cpu.is_halted());
+    }
+
+    #[test]
+    fn irq_respects_interrupt_disable() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.sp = 0xff;
+        cpu.ps = StatusFlags::INTERRUPT_DISABLE | StatusFlags::CARRY;
+        cpu.poke(0x0000, OpCode::Cli.into());
+        cpu.poke(0x0001, OpCode::Nop.into());
+        cpu.poke(0xfffe, 0x34);
+        cpu.poke(0xffff, 0x12);
+        cpu.set_irq(true);
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x0001);
+
+        // CLI only lets interrupts in after the next instruction
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0002);
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x1234);
+        assert_eq!(cpu.sp, 0xfc);
+        assert_eq!(cpu.peek(0x01ff), 0x00);
+        assert_eq!(cpu.peek(0x01fe), 0x02);
+        assert_eq!(
+            cpu.peek(0x01fd),
+            (StatusFlags::CARRY | StatusFlags::UNUSED).bits()
+        );
+        assert!(cpu.ps.interrupt_disable());
+        assert_eq!(cpu.cycles(), 11);
+    }
+
+    #[test]
+    fn device_irq_is_serviced() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        // Raised in the second-to-last cycle of the second NOP
+        let device = Rc::new(RefCell::new(IrqAfter {
+            countdown: 3,
+            state: IrqState::IRQ,
+        }));
+        mem.borrow_mut().map_device(0xd000, 0xd000, device);
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.sp = 0xff;
+        for address in 0x0000..0x0003 {
+            cpu.poke(address, OpCode::Nop.into());
+        }
+        cpu.poke(0x8000, OpCode::LdaA.into());
+        cpu.poke(0x8001, 0x00);
+        cpu.poke(0x8002, 0xd0);
+        cpu.poke(0x8003, OpCode::Rti.into());
+        cpu.poke(0xfffe, 0x00);
+        cpu.poke(0xffff, 0x80);
+
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(cpu.step(), 7);
+        assert_eq!(cpu.pc, 0x8000);
+
+        // Reading the device acknowledges the interrupt
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x0002);
+
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x0003);
+    }
+
+    /// Copies 4 bytes from 0x0010 to 0x0020 when written.
+    #[derive(Default)]
+    struct CopyOnWrite {
+        pending: Option<Transfer>,
+        ticked: u32,
+    }
+
+    impl Device for CopyOnWrite {
+        fn read(&mut self, _offset: u16) -> u8 {
+            0x00
+        }
+
+        fn write(&mut self, _offset: u16, _value: u8) {
+            self.pending = Some(Transfer {
+                source: 0x0010,
+                destination: 0x0020,
+                length: 4,
+            });
+        }
+
+        fn tick(&mut self, cycles: u32) -> IrqState {
+            self.ticked += cycles;
+            IrqState::NONE
+        }
+
+        fn take_bus_job(&mut self) -> Option<BusJob> {
+            self.pending.take().map(Transfer::job)
+        }
+    }
+
+    #[test]
+    fn device_transfer_stalls_cpu() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let device: Rc<RefCell<CopyOnWrite>> = Rc::new(RefCell::new(CopyOnWrite::default()));
+        mem.borrow_mut().map_device(0xd000, 0xd000, device.clone());
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.poke(0x0000, OpCode::StaA.into());
+        cpu.poke(0x0001, 0x00);
+        cpu.poke(0x0002, 0xd0);
+        cpu.poke(0x0013, 0x42);
+
+        // 4 cycles for the store, 2 per byte copied
+        assert_eq!(cpu.step(), 12);
+        assert_eq!(cpu.cycles(), 12);
+        assert_eq!(device.borrow().ticked, 12);
+        assert_eq!(cpu.peek(0x0023), 0x42);
+    }
+
+    #[test]
+    fn trap_replaces_subroutine() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.sp = 0xff;
+        // JSR $1234, with nothing but BRK at $1234
+        cpu.poke(0x0000, OpCode::Jsr.into());
+        cpu.poke(0x0001, 0x34);
+        cpu.poke(0x0002, 0x12);
+        cpu.poke(0x0003, OpCode::Nop.into());
+        let calls: Rc<RefCell<u32>> = Rc::new(RefCell::new(0));
+        let counter: Rc<RefCell<u32>> = calls.clone();
+        cpu.add_trap(0x1234, move |cpu: &mut Mos6502| {
+            *counter.borrow_mut() += 1;
+            cpu.set_a(cpu.x().wrapping_add(1));
+            TrapAction::Return
+        });
+        cpu.add_trap(0x0003, |_: &mut Mos6502| TrapAction::Continue);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0003);
+        assert_eq!(cpu.a(), 0x01);
+        assert_eq!(cpu.sp(), 0xff);
+        assert_eq!(*calls.borrow(), 1);
+
+        // A continuing trap runs the instruction under it
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0004);
+        cpu.remove_trap(0x1234);
+        assert_eq!(cpu.traps.len(), 1);
+    }
+
+    #[test]
+    fn pushes_as_the_chip_does() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).sp(0xff).build();
+        // JSR $0300; $0300: PHP, LDA #$FF, PHA, PLP, PLP, RTS
+        cpu.load(0x0200, &m6502_asm! { jsr $0300 });
+        cpu.load(0x0300, &m6502_asm! { php lda #$ff pha plp plp rts });
+        cpu.set_status(StatusFlags::empty());
+        cpu.step();
+        assert_eq!(cpu.peek_word(0x01fe), 0x0202);
+        cpu.step();
+        assert_eq!(cpu.peek(0x01fd), 0x30);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.status().bits(), 0xef);
+        cpu.step();
+        assert_eq!(cpu.status().bits(), 0x20);
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0203);
+    }
+
+    #[test]
+    fn call_runs_until_matching_rts() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+        cpu.sp = 0xff;
+        cpu.pc = 0x1000;
+
+        // $0200: JSR $0300, INX, RTS; $0300: LDA #$42, RTS
+        let program: [(u16, u8); 8] = [
+            (0x0200, OpCode::Jsr.into()),
+            (0x0201, 0x00),
+            (0x0202, 0x03),
+            (0x0203, OpCode::Inx.into()),
+            (0x0204, OpCode::Rts.into()),
+            (0x0300, OpCode::LdaI.into()),
+            (0x0301, 0x42),
+            (0x0302, OpCode::Rts.into()),
+        ];
+        for (address, byte) in program {
+            cpu.poke(address, byte);
+        }
+        cpu.x = 0x01;
+
+        let result: CallResult = cpu.call(0x0200, 100).unwrap();
+        assert_eq!((result.a, result.x, result.sp), (0x42, 0x02, 0xff));
+        assert_eq!(result.cycles, 6 + 2 + 6 + 2 + 6);
+        assert_eq!(cpu.pc(), 0x1000);
+
+        // JMP to itself halts the CPU
+        cpu.poke(0x0300, OpCode::Jmp.into());
+        cpu.poke(0x0301, 0x00);
+        cpu.poke(0x0302, 0x03);
+        assert!(cpu.call(0x0300, 100).is_err());
+        cpu.reset();
+        cpu.sp = 0xff;
+        // DEX, BNE -3 loops for longer than allowed
+        cpu.poke(0x0300, OpCode::Dex.into());
+        cpu.poke(0x0301, OpCode::Bne.into());
+        cpu.poke(0x0302, 0xfd);
+        assert!(cpu.call(0x0300, 100).is_err());
+    }
+
+    #[test]
+    fn taken_branch_polls_before_its_extra_cycle() {
+        for (op_code, irq_pc) in [(OpCode::LdaZp, 0x0002), (OpCode::Bne, 0x0003)] {
+            let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+            // Raised in the second cycle of the 3 cycle instruction
+            let device = Rc::new(RefCell::new(IrqAfter {
+                countdown: 2,
+                state: IrqState::IRQ,
+            }));
+            mem.borrow_mut().map_device(0xd000, 0xd000, device);
+            let mut cpu: Mos6502 = Mos6502::builder().memory(mem).sp(0xff).build();
+            // The instruction, to the next one if a branch, then a NOP
+            cpu.load(0x0000, &[op_code.into(), 0x00, OpCode::Nop.into()]);
+            cpu.poke(0xfffe, 0x00);
+            cpu.poke(0xffff, 0x80);
+
+            assert_eq!(cpu.step(), 3);
+            if irq_pc == 0x0003 {
+                cpu.step();
+            }
+            assert_eq!(cpu.step(), 7);
+            assert_eq!(cpu.peek(0x01fe), irq_pc);
+        }
+    }
+
+    #[test]
+    fn device_nmi_is_edge_triggered() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let device = Rc::new(RefCell::new(IrqAfter {
+            countdown: 1,
+            state: IrqState::NMI,
+        }));
+        mem.borrow_mut().map_device(0xd000, 0xd000, device);
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.sp = 0xff;
+        cpu.poke(0x0000, OpCode::Nop.into());
+        cpu.poke(0x8000, OpCode::Nop.into());
+        cpu.poke(0x8001, OpCode::Nop.into());
+        cpu.poke(0xfffa, 0x00);
+        cpu.poke(0xfffb, 0x80);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x8000);
+
+        // The line stays asserted, but there is no new edge
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.pc, 0x8002);
+    }
+
+    #[test]
+    fn displays_state_and_next_instruction() {
+        let mut cpu: Mos6502 = Mos6502::builder()
+            .a(0x2a)
+            .sp(0xfd)
+            .status(StatusFlags::ZERO | StatusFlags::CARRY)
+            .pc(0x0200)
+            .build();
+        cpu.poke(0x0200, OpCode::LdaIY.into());
+        cpu.poke(0x0201, 0x10);
+        assert_eq!(
+            cpu.to_string(),
+            "PC: 0x0200  A: 0x2a  X: 0x00  Y: 0x00  SP: 0xfd  P: ------ZC  next: LDA ($10),Y"
+        );
+
+        // A branch shows its target
+        cpu.pc = 0x0202;
+        cpu.poke(0x0202, OpCode::Bne.into());
+        cpu.poke(0x0203, 0xfc);
+        assert_eq!(cpu.next_instruction(), "BNE $0200");
+        assert!(format!("{:?}", cpu).contains("pc: 0x0202"));
+
+        cpu.poke(0x0202, 0x02);
+        assert_eq!(cpu.next_instruction(), ".byte $02");
+    }
+
+    #[test]
+    fn hooks_see_instructions_and_stop_them() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        let program: [u8; 6] = m6502_asm! {
+            .org $0200
+                    inx
+                    inx
+            loop:   inx
+                    jmp loop
+        };
+        cpu.load(0x0200, &program);
+        let seen: Rc<RefCell<Vec<(u16, u8)>>> = Rc::new(RefCell::new(Vec::new()));
+        let post_seen: Rc<RefCell<Vec<(u16, u8)>>> = seen.clone();
+        cpu.add_pre_hook(|state: &InstructionState| {
+            assert_eq!(state.op_code, OpCode::Inx);
+            if state.x == 2 {
+                HookAction::Stop
+            } else {
+                HookAction::Continue
+            }
+        });
+        cpu.add_post_hook(move |state: &InstructionState| {
+            post_seen.borrow_mut().push((state.pc, state.x));
+        });
+
+        while cpu.step() != 0 {}
+        assert!(cpu.is_halted());
+        assert_eq!((cpu.pc, cpu.x), (0x0202, 2));
+        assert_eq!(*seen.borrow(), [(0x0200, 1), (0x0201, 2)]);
+
+        // Resuming runs the instruction that was stopped
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::Breakpoint));
+        cpu.clear_hooks();
+        cpu.resume();
+        cpu.step();
+        assert_eq!(cpu.x, 3);
+    }
+
+    #[test]
+    fn restores_register_snapshot() {
+        let mut cpu: Mos6502 = Mos6502::builder().sp(0xff).pc(0x0200).build();
+        cpu.load(0x0200, &m6502_asm! { lda #$2a tax });
+        let before: Registers = cpu.snapshot();
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            cpu.snapshot(),
+            Registers {
+                a: 0x2a,
+                x: 0x2a,
+                pc: 0x0203,
+                ..before
+            }
+        );
+
+        cpu.restore(before);
+        assert_eq!(cpu.snapshot(), before);
+        assert_eq!(cpu.cycles(), 4);
+    }
+
+    #[test]
+    fn halts_for_a_reason() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        // NOP, then an undocumented opcode
+        cpu.load(0x0200, &m6502_asm! { nop .byte $02 });
+        cpu.halt(HaltReason::UserRequest);
+        assert_eq!(cpu.step(), 0);
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::UserRequest));
+
+        cpu.resume();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::Jam));
+        assert_eq!((cpu.pc(), cpu.trapped_at()), (0x0201, None));
+    }
+
+    #[test]
+    fn wraps_around_the_end_of_memory() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0xffff).build();
+        // NOP at the last address
+        cpu.load(0xffff, &[0xea]);
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0000);
+
+        // LDA $1234 with its operand split across 0xffff and 0x0000
+        cpu.load(0xfffe, &[0xad, 0x34]);
+        cpu.load(0x0000, &[0x12]);
+        cpu.load(0x1234, &[0x2a]);
+        cpu.set_pc(0xfffe);
+        cpu.step();
+        assert_eq!((cpu.a(), cpu.pc()), (0x2a, 0x0001));
+
+        // LDX #$02, LDA $FFFF,X, LDY #$10, LDA ($FF),Y with the pointer at $00FF and $0000,
+        // then LDA $FF,X wrapping in the zero page
+        cpu.load(
+            0x0300,
+            &m6502_asm! { ldx #$02 lda $ffff,x ldy #$10 lda ($ff),y lda $ff,x },
+        );
+        cpu.load(0x0000, &[0x04, 0x11]);
+        cpu.load(0x00ff, &[0x00]);
+        cpu.load(0x0410, &[0x55]);
+        cpu.set_pc(0x0300);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.a(), 0x11);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.a(), 0x55);
+        cpu.step();
+        assert_eq!(cpu.a(), 0x11);
+
+        // BNE +4 from 0xfffc, to 0x0002
+        cpu.load(0xfffc, &[0xd0, 0x04]);
+        cpu.set_pc(0xfffc);
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x0002);
+    }
+
+    #[test]
+    fn cmos_clears_decimal_mode_on_interrupts() {
+        for (variant, decimal, adc_cycles) in [(Variant::Nmos, true, 2), (Variant::Cmos, false, 3)]
+        {
+            let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).variant(variant).build();
+            cpu.set_sp(0xff);
+            cpu.load(0x0200, &m6502_asm! { sed adc #$01 brk });
+            cpu.step();
+            assert_eq!(cpu.step(), adc_cycles);
+            cpu.step();
+            assert_eq!(cpu.ps.decimal_mode(), decimal);
+            // The pushed copy keeps it
+            assert!(StatusFlags::from_bits(cpu.peek(0x01fd)).decimal_mode());
+        }
+    }
+
+    #[test]
+    fn makes_the_dummy_accesses() {
+        let accesses_of = |variant: Variant| {
+            let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).variant(variant).build();
+            // LDX #$01, INC $10,X, LDA $02FF,X
+            cpu.load(0x0200, &m6502_asm! { ldx #$01 inc $10,x lda $02ff,x });
+            cpu.load(0x0011, &[0x41]);
+            let accesses: Rc<RefCell<Vec<(u16, u8, bool)>>> = Rc::new(RefCell::new(Vec::new()));
+            let traced = Rc::clone(&accesses);
+            cpu.set_tracer(move |record| {
+                let made = record.accesses.iter().skip(record.bytes.len());
+                traced
+                    .borrow_mut()
+                    .extend(made.map(|access| (access.address, access.value, access.write)));
+            });
+            for _ in 0..3 {
+                cpu.step();
+            }
+            accesses.take()
+        };
+
+        assert_eq!(
+            accesses_of(Variant::Nmos),
+            [
+                (0x0010, 0x00, false),
+                (0x0011, 0x41, false),
+                (0x0011, 0x41, true),
+                (0x0011, 0x42, true),
+                (0x0200, 0xa2, false),
+                (0x0300, 0x00, false),
+            ]
+        );
+        assert_eq!(
+            accesses_of(Variant::Cmos),
+            [
+                (0x0203, 0x10, false),
+                (0x0011, 0x41, false),
+                (0x0011, 0x41, false),
+                (0x0011, 0x42, true),
+                (0x0206, 0x02, false),
+                (0x0300, 0x00, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn remembers_the_instructions_before_a_jam() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        // LDX #$28, then DEX until 0 and an undocumented opcode
+        cpu.load(0x0200, &[0xa2, 0x28, 0xca, 0xd0, 0xfd, 0x02]);
+        while !cpu.is_halted() {
+            cpu.step();
+        }
+
+        let history: Vec<(u16, u8)> = cpu.pc_history().collect();
+        assert_eq!(history.len(), PC_HISTORY_DEPTH);
+        assert_eq!(
+            history[PC_HISTORY_DEPTH - 3..],
+            [(0x0202, 0xca), (0x0203, 0xd0), (0x0205, 0x02)]
+        );
+        assert!(cpu
+            .format_pc_history()
+            .ends_with("0x0203  d0  BNE\n0x0205  02  ???"));
+    }
+
+    #[test]
+    fn stack_wraps_and_reports_it() {
+        let mut cpu: Mos6502 = Mos6502::builder().sp(0x01).pc(0x0200).build();
+        cpu.load(0x0200, &m6502_asm! { .org $0200 loop: jsr loop });
+        let wraps: Rc<RefCell<Vec<StackWrap>>> = Rc::new(RefCell::new(Vec::new()));
+        let reported: Rc<RefCell<Vec<StackWrap>>> = wraps.clone();
+        cpu.set_stack_watch(move |wrap: &StackWrap| reported.borrow_mut().push(wrap.clone()));
+
+        cpu.step();
+        assert_eq!(cpu.sp(), 0xff);
+        assert_eq!(
+            *wraps.borrow(),
+            [StackWrap {
+                kind: StackWrapKind::Overflow,
+                pc: 0x0200,
+                calls: Vec::new(),
+            }]
+        );
+        // The address of the last byte of JSR, as the chip pushes it
+        assert_eq!((cpu.peek(0x0101), cpu.peek(0x0100)), (0x02, 0x02));
+
+        // RTS, pulling $0202 and returning after the JSR
+        cpu.poke(0x0200, 0x60);
+        cpu.set_sp(0xff);
+        cpu.step();
+        assert_eq!((cpu.sp(), cpu.pc()), (0x01, 0x0203));
+        assert_eq!(wraps.borrow()[1].kind, StackWrapKind::Underflow);
+    }
+
+    /// The cycles of every instruction that is not a branch, and whether it takes one
+    /// more when indexing crosses a page, from the published timing tables.
+    const TIMING: [(u8, u8, bool); 143] = [
+        (0x69, 2, false),
+        (0x65, 3, false),
+        (0x75, 4, false),
+        (0x6d, 4, false),
+        (0x7d, 4, true),
+        (0x79, 4, true),
+        (0x61, 6, false),
+        (0x71, 5, true),
+        (0x29, 2, false),
+        (0x25, 3, false),
+        (0x35, 4, false),
+        (0x2d, 4, false),
+        (0x3d, 4, true),
+        (0x39, 4, true),
+        (0x21, 6, false),
+        (0x31, 5, true),
+        (0x0a, 2, false),
+        (0x06, 5, false),
+        (0x16, 6, false),
+        (0x0e, 6, false),
+        (0x1e, 7, false),
+        (0x24, 3, false),
+        (0x2c, 4, false),
+        (0x00, 7, false),
+        (0x18, 2, false),
+        (0xd8, 2, false),
+        (0x58, 2, false),
+        (0xb8, 2, false),
+        (0xc9, 2, false),
+        (0xc5, 3, false),
+        (0xd5, 4, false),
+        (0xcd, 4, false),
+        (0xdd, 4, true),
+        (0xd9, 4, true),
+        (0xc1, 6, false),
+        (0xd1, 5, true),
+        (0xe0, 2, false),
+        (0xe4, 3, false),
+        (0xec, 4, false),
+        (0xc0, 2, false),
+        (0xc4, 3, false),
+        (0xcc, 4, false),
+        (0xc6, 5, false),
+        (0xd6, 6, false),
+        (0xce, 6, false),
+        (0xde, 7, false),
+        (0xca, 2, false),
+        (0x88, 2, false),
+        (0x49, 2, false),
+        (0x45, 3, false),
+        (0x55, 4, false),
+        (0x4d, 4, false),
+        (0x5d, 4, true),
+        (0x59, 4, true),
+        (0x41, 6, false),
+        (0x51, 5, true),
+        (0xe6, 5, false),
+        (0xf6, 6, false),
+        (0xee, 6, false),
+        (0xfe, 7, false),
+        (0xe8, 2, false),
+        (0xc8, 2, false),
+        (0x4c, 3, false),
+        (0x6c, 5, false),
+        (0x20, 6, false),
+        (0xa9, 2, false),
+        (0xa5, 3, false),
+        (0xb5, 4, false),
+        (0xad, 4, false),
+        (0xbd, 4, true),
+        (0xb9, 4, true),
+        (0xa1, 6, false),
+        (0xb1, 5, true),
+        (0xa2, 2, false),
+        (0xa6, 3, false),
+        (0xb6, 4, false),
+        (0xae, 4, false),
+        (0xbe, 4, true),
+        (0xa0, 2, false),
+        (0xa4, 3, false),
+        (0xb4, 4, false),
+        (0xac, 4, false),
+        (0xbc, 4, true),
+        (0x4a, 2, false),
+        (0x46, 5, false),
+        (0x56, 6, false),
+        (0x4e, 6, false),
+        (0x5e, 7, false),
+        (0xea, 2, false),
+        (0x09, 2, false),
+        (0x05, 3, false),
+        (0x15, 4, false),
+        (0x0d, 4, false),
+        (0x1d, 4, true),
+        (0x19, 4, true),
+        (0x01, 6, false),
+        (0x11, 5, true),
+        (0x48, 3, false),
+        (0x08, 3, false),
+        (0x68, 4, false),
+        (0x28, 4, false),
+        (0x2a, 2, false),
+        (0x26, 5, false),
+        (0x36, 6, false),
+        (0x2e, 6, false),
+        (0x3e, 7, false),
+        (0x6a, 2, false),
+        (0x66, 5, false),
+        (0x76, 6, false),
+        (0x6e, 6, false),
+        (0x7e, 7, false),
+        (0x40, 6, false),
+        (0x60, 6, false),
+        (0xe9, 2, false),
+        (0xe5, 3, false),
+        (0xf5, 4, false),
+        (0xed, 4, false),
+        (0xfd, 4, true),
+        (0xf9, 4, true),
+        (0xe1, 6, false),
+        (0xf1, 5, true),
+        (0x38, 2, false),
+        (0xf8, 2, false),
+        (0x78, 2, false),
+        (0x85, 3, false),
+        (0x95, 4, false),
+        (0x8d, 4, false),
+        (0x9d, 5, false),
+        (0x99, 5, false),
+        (0x81, 6, false),
+        (0x91, 6, false),
+        (0x86, 3, false),
+        (0x96, 4, false),
+        (0x8e, 4, false),
+        (0x84, 3, false),
+        (0x94, 4, false),
+        (0x8c, 4, false),
+        (0xaa, 2, false),
+        (0xa8, 2, false),
+        (0xba, 2, false),
+        (0x8a, 2, false),
+        (0x9a, 2, false),
+        (0x98, 2, false),
+    ];
+
+    /// # Returns
+    /// The cycles `program`, loaded at `$0200` with the registers and status given,
+    /// takes to execute its first instruction.
+    fn cycles_of(program: &[u8], index: u8, status: StatusFlags, at: u16) -> u32 {
+        let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+        cpu.load(at, program);
+        // The pointer of (zp,X) and (zp),Y
+        cpu.load(0x0010, &[0xf0, 0x10]);
+        cpu.pc = at;
+        cpu.sp = 0xfd;
+        cpu.x = index;
+        cpu.y = index;
+        cpu.ps = status;
+        cpu.step()
+    }
+
+    #[test]
+    fn cycles_match_the_timing_tables() {
+        for (op_code, cycles, page_penalty) in TIMING {
+            let info: InstructionInfo = instruction_info(op_code).unwrap();
+            let operand: [u8; 2] = match info.addressing_mode {
+                AddressingMode::IndirectX | AddressingMode::IndirectY => [0x10, 0x00],
+                AddressingMode::Indirect => [0x00, 0x10],
+                _ => [0xf0, 0x10],
+            };
+            let program: Vec<u8> =
+                [op_code, operand[0], operand[1]][..info.length as usize].to_vec();
+            // Indexing $10F0, or the pointer at $0010 to it, by 1 stays in the page, by
+            // $20 crosses it, and the other modes mind neither
+            let within: u32 = cycles_of(&program, 0x01, StatusFlags::UNUSED, 0x0200);
+            let across: u32 = cycles_of(&program, 0x20, StatusFlags::UNUSED, 0x0200);
+            let across_expected: u32 = cycles as u32 + page_penalty as u32;
+            assert_eq!(within, cycles as u32, "{} {:#04x}", info.mnemonic, op_code);
+            assert_eq!(
+                across, across_expected,
+                "{} {:#04x} across a page",
+                info.mnemonic, op_code
+            );
+        }
+    }
+
+    #[test]
+    fn branch_cycles_match_the_timing_tables() {
+        let branches: [(u8, StatusFlags, bool); 8] = [
+            (0x10, StatusFlags::NEGATIVE, false),
+            (0x30, StatusFlags::NEGATIVE, true),
+            (0x50, StatusFlags::OVERFLOW, false),
+            (0x70, StatusFlags::OVERFLOW, true),
+            (0x90, StatusFlags::CARRY, false),
+            (0xb0, StatusFlags::CARRY, true),
+            (0xd0, StatusFlags::ZERO, false),
+            (0xf0, StatusFlags::ZERO, true),
+        ];
+        for (op_code, flag, set) in branches {
+            let (taken, untaken): (StatusFlags, StatusFlags) = match set {
+                true => (flag, StatusFlags::empty()),
+                false => (StatusFlags::empty(), flag),
+            };
+            // 2 cycles, 3 when taken, 4 when the target is in another page
+            assert_eq!(cycles_of(&[op_code, 0x10], 0, untaken, 0x0200), 2);
+            assert_eq!(cycles_of(&[op_code, 0x10], 0, taken, 0x0200), 3);
+            assert_eq!(cycles_of(&[op_code, 0x10], 0, taken, 0x02f0), 4);
+            assert_eq!(cycles_of(&[op_code, 0x80], 0, taken, 0x0200), 4);
+        }
+    }
+
+    /// Bruce Clark's program, which runs every `ADC` and `SBC` in decimal mode.
+    #[test]
+    fn passes_the_decimal_mode_test() {
+        let program: asm6502::Program =
+            asm6502::assemble(include_str!("../../roms/decimal_test.s")).unwrap();
+        let symbol = |name: &str| program.symbol(name).unwrap();
+        for (variant, chip) in [(Variant::Nmos, 0), (Variant::Cmos, 1)] {
+            let mut cpu: Mos6502 = Mos6502::builder()
+                .variant(variant)
+                .sp(0xff)
+                .pc(program.start())
+                .trap_detection(true)
+                .build();
+            program.load_into(cpu.memory().borrow_mut().as_mut_slice());
+            cpu.poke(symbol("chip"), chip);
+            while !cpu.is_halted() {
+                cpu.step();
+            }
+            assert_eq!(cpu.pc(), symbol("done"), "{:?}", variant);
+            let failed: [u8; 3] = [cpu.peek(symbol("n1")), cpu.peek(symbol("n2")), cpu.y()];
+            assert_eq!(
+                cpu.peek(symbol("error")),
+                0,
+                "{:?} failed on {:02x?}, N1, N2 and the carry",
+                variant,
+                failed
+            );
+        }
+    }
+
+    const IRQ_HANDLER: u16 = 0x8000;
+    const NMI_HANDLER: u16 = 0x9000;
+
+    /// # Returns
+    /// A CPU about to run `program` from `$0200` with `status`, next to a timer raising
+    /// IRQ in cycle `irq_in` and one raising NMI in cycle `nmi_in`, counting from 1,
+    /// if any. Both handlers are `NOP`s.
+    fn timed(
+        program: &[u8],
+        status: StatusFlags,
+        irq_in: Option<u8>,
+        nmi_in: Option<u8>,
+    ) -> Mos6502 {
+        use devices::timer::{Timer, CONTROL, CONTROL_ENABLE, CONTROL_NMI, PERIOD_LO};
+
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let timers = [
+            (0xd000, irq_in, CONTROL_ENABLE),
+            (0xd004, nmi_in, CONTROL_ENABLE | CONTROL_NMI),
+        ];
+        for (address, cycle, control) in timers {
+            let mut timer: Timer = Timer::new();
+            if let Some(cycle) = cycle {
+                timer.write(PERIOD_LO, cycle);
+                timer.write(CONTROL, control);
+            }
+            mem.borrow_mut()
+                .map_device(address, address + 3, Rc::new(RefCell::new(timer)));
+        }
+        let mut cpu: Mos6502 = Mos6502::builder()
+            .memory(mem)
+            .sp(0xff)
+            .status(status)
+            .pc(0x0200)
+            .build();
+        cpu.load(0x0200, program);
+        cpu.load(IRQ_HANDLER, &[OpCode::Nop.into(), OpCode::Nop.into()]);
+        cpu.load(NMI_HANDLER, &[OpCode::Nop.into(), OpCode::Nop.into()]);
+        cpu.load(0xfffa, &NMI_HANDLER.to_le_bytes());
+        cpu.load(0xfffe, &IRQ_HANDLER.to_le_bytes());
+        cpu
+    }
+
+    /// Steps `cpu` into the first interrupt handler.
+    ///
+    /// # Returns
+    /// The handler, the return address and status pushed, and the cycle the handler
+    /// starts at.
+    fn enter_handler(cpu: &mut Mos6502) -> (u16, u16, StatusFlags, u64) {
+        for _ in 0..100 {
+            if cpu.pc() == IRQ_HANDLER || cpu.pc() == NMI_HANDLER {
+                let pushed = |offset: u8| cpu.peek(0x0100 + cpu.sp().wrapping_add(offset) as u16);
+                let status: StatusFlags = StatusFlags::from_bits(pushed(1));
+                let return_address: u16 = u16::from_le_bytes([pushed(2), pushed(3)]);
+                return (cpu.pc(), return_address, status, cpu.cycles());
+            }
+            cpu.step();
+        }
+        panic!("no interrupt was taken, PC is {:#06x}", cpu.pc());
+    }
+
+    #[test]
+    fn interrupts_are_polled_before_the_last_cycle() {
+        // Polling at the end of cycles 1, 4, 10, 12 and 14, read-modify-write included
+        let program: [u8; 8] = m6502_asm! { nop lda $10 inc $0300 nop nop };
+        // Where the interrupted instruction ends, and what follows it
+        let interrupted = |cycle: u8| match cycle {
+            1 => (0x0201, 2),
+            2..=4 => (0x0203, 5),
+            5..=10 => (0x0206, 11),
+            11..=12 => (0x0207, 13),
+            _ => (0x0208, 15),
+        };
+        for cycle in 1..=14 {
+            for (handler, irq_in, nmi_in) in [
+                (IRQ_HANDLER, Some(cycle), None),
+                (NMI_HANDLER, None, Some(cycle)),
+            ] {
+                let mut cpu: Mos6502 = timed(&program, StatusFlags::empty(), irq_in, nmi_in);
+                let (entered, return_address, _, cycles) = enter_handler(&mut cpu);
+                let (expected, end): (u16, u64) = interrupted(cycle);
+                assert_eq!(
+                    (entered, return_address, cycles),
+                    (handler, expected, end + 7),
+                    "raised in cycle {}",
+                    cycle
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn interrupt_disable_changes_after_the_poll() {
+        let disabled: StatusFlags = StatusFlags::INTERRUPT_DISABLE;
+        // CLI and PLP let an IRQ in after the next instruction, SEI lets it in after
+        // itself, RTI right away
+        let cases: [(&[u8], StatusFlags, u16); 5] = [
+            (&m6502_asm! { cli nop nop }, disabled, 0x0202),
+            (&m6502_asm! { plp nop nop }, disabled, 0x0202),
+            (&m6502_asm! { sei nop }, StatusFlags::empty(), 0x0201),
+            (&m6502_asm! { cli sei nop }, disabled, 0x0202),
+            (&m6502_asm! { rti }, disabled, 0x0300),
+        ];
+        for (program, status, expected) in cases {
+            let mut cpu: Mos6502 = timed(program, status, Some(1), None);
+            // Pulled by PLP and RTI: no flags, and $0300 to return to
+            cpu.load(0x0100, &[0x00, 0x00, 0x03]);
+            cpu.load(0x0300, &[OpCode::Nop.into()]);
+            let (_, return_address, pushed, _) = enter_handler(&mut cpu);
+            assert_eq!(return_address, expected, "{:02x?}", program);
+            // Taken after SEI, the interrupt pushes the flag set
+            assert_eq!(pushed.interrupt_disable(), program.ends_with(&[0x78, 0xea]));
+        }
+    }
+
+    #[test]
+    fn taken_branches_poll_before_their_extra_cycle() {
+        let nops: [u8; 4] = [0xea; 4];
+        // Not taken, then taken within the page, polling at the end of cycle 1, and
+        // taken to $01F2, polling at the end of cycle 3
+        let cases = [
+            (StatusFlags::ZERO, [0xd0, 0x02], [(1, 0x0202), (2, 0x0203)]),
+            (
+                StatusFlags::empty(),
+                [0xd0, 0x02],
+                [(1, 0x0204), (2, 0x0205)],
+            ),
+            (
+                StatusFlags::empty(),
+                [0xd0, 0xf0],
+                [(3, 0x01f2), (4, 0x01f3)],
+            ),
+        ];
+        for (status, branch, raised) in cases {
+            for (cycle, expected) in raised {
+                for (irq_in, nmi_in) in [(Some(cycle), None), (None, Some(cycle))] {
+                    let mut cpu: Mos6502 =
+                        timed(&[&branch[..], &nops].concat(), status, irq_in, nmi_in);
+                    cpu.load(0x01f2, &nops);
+                    let (_, return_address, _, _) = enter_handler(&mut cpu);
+                    assert_eq!(
+                        return_address, expected,
+                        "{:02x?} with {}, raised in cycle {}",
+                        branch, status, cycle
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nmi_takes_over_brk_and_irq_entries() {
+        // BRK in cycles 1 to 7, the IRQ entry after the NOP in cycles 3 to 9
+        let cases = [
+            ([0x00, 0x00], None, 1, 0x0202, true),
+            ([0xea, 0xea], Some(1), 3, 0x0201, false),
+        ];
+        for (program, irq_in, entry, expected, brk) in cases {
+            for cycle in entry..entry + 7 {
+                let mut cpu: Mos6502 = timed(&program, StatusFlags::empty(), irq_in, Some(cycle));
+                let (handler, return_address, pushed, _) = enter_handler(&mut cpu);
+                assert_eq!(return_address, expected);
+                assert_eq!(pushed.bits() & StatusFlags::BREAK.bits() != 0, brk);
+                if cycle < entry + HIJACK_CYCLES as u8 {
+                    assert_eq!(handler, NMI_HANDLER, "raised in cycle {}", cycle);
+                    continue;
+                }
+                // Too late, the NMI waits for the first instruction of the handler
+                assert_eq!(handler, IRQ_HANDLER, "raised in cycle {}", cycle);
+                cpu.step();
+                assert_eq!(cpu.step(), 7);
+                assert_eq!((cpu.pc(), cpu.peek_word(0x01fb)), (NMI_HANDLER, 0x8001));
+            }
+        }
     }
 }
@@ -0,0 +1,144 @@
+use std::fmt;
+
+/// What a log message is about, each with its own level, see `Mos6502::set_log_level()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// The bytes of instructions, as they are fetched.
+    Fetch,
+    /// The instructions executed and the traps run.
+    Exec,
+    /// The reads and writes of each instruction.
+    Bus,
+    /// Interrupt entries.
+    Irq,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Fetch,
+        Category::Exec,
+        Category::Bus,
+        Category::Irq,
+    ];
+
+    /// # Returns
+    /// The category named `name`, as displayed, e.g. `exec`.
+    pub fn parse(name: &str) -> Result<Category, String> {
+        Category::ALL
+            .into_iter()
+            .find(|category| category.to_string() == name)
+            .ok_or_else(|| format!("Unknown log category `{}`", name))
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name: &str = match self {
+            Category::Fetch => "fetch",
+            Category::Exec => "exec",
+            Category::Bus => "bus",
+            Category::Irq => "irq",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How detailed the messages of a category are, each level including the ones below.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    #[default]
+    Off,
+    /// Rare events: interrupts and traps.
+    Info,
+    /// One message per instruction.
+    Debug,
+    /// Everything, down to every byte fetched or accessed.
+    Trace,
+}
+
+impl LogLevel {
+    /// # Returns
+    /// The level named `name`, as displayed, e.g. `debug`.
+    pub fn parse(name: &str) -> Result<LogLevel, String> {
+        [
+            LogLevel::Off,
+            LogLevel::Info,
+            LogLevel::Debug,
+            LogLevel::Trace,
+        ]
+        .into_iter()
+        .find(|level| level.to_string() == name)
+        .ok_or_else(|| format!("Unknown log level `{}`", name))
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name: &str = match self {
+            LogLevel::Off => "off",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where the CPU sends its log messages, see `Mos6502::set_log_sink()`.
+pub trait LogSink {
+    fn log(&mut self, category: Category, level: LogLevel, message: &str);
+}
+
+/// Prints every message on its own line, prefixed with its category.
+pub struct StdoutSink;
+
+impl LogSink for StdoutSink {
+    fn log(&mut self, category: Category, _level: LogLevel, message: &str) {
+        println!("[{}] {}", category, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Mos6502;
+
+    use asm6502_macros::m6502_asm;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Collect(Rc<RefCell<Vec<String>>>);
+
+    impl LogSink for Collect {
+        fn log(&mut self, category: Category, _level: LogLevel, message: &str) {
+            self.0
+                .borrow_mut()
+                .push(format!("{} {}", category, message));
+        }
+    }
+
+    #[test]
+    fn logs_enabled_categories_only() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        cpu.load(0x0200, &m6502_asm! { sta $10 });
+        let messages: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+        cpu.set_log_sink(Collect(messages.clone()));
+        cpu.set_log_level(Category::Exec, LogLevel::Debug);
+        cpu.set_log_level(Category::Bus, LogLevel::Trace);
+
+        cpu.step();
+        assert_eq!(
+            *messages.borrow(),
+            [
+                "exec Executing STA zp (0x85) at 0x0200",
+                "bus Read 0x85 at 0x0200",
+                "bus Read 0x10 at 0x0201",
+                "bus Wrote 0x00 at 0x0010",
+            ]
+        );
+        assert_eq!(Category::parse("irq"), Ok(Category::Irq));
+        assert!(LogLevel::parse("loud").is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use crate::opcodes::{AddressingMode, InstructionInfo};
+use crate::reference::normalized;
+use crate::{instruction_info, Mos6502, Registers, StatusFlags};
+
+use memory::Memory;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// The registers and the bytes of a micro-test, the rest of memory being zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MicroState {
+    pub registers: Registers,
+    /// Addresses and their values, in ascending order.
+    pub ram: Vec<(u16, u8)>,
+}
+
+/// A program of a single instruction, run from a random state, and the state it must
+/// end in, see `generate()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MicroTest {
+    pub op_code: u8,
+    /// The op code, the instruction and the number of the test, e.g. `7d ADC abs,X 3`.
+    pub name: String,
+    pub initial: MicroState,
+    pub expected: MicroState,
+    pub cycles: u64,
+}
+
+impl MicroTest {
+    /// Runs the instruction on a `Mos6502`, from the initial state over otherwise zeroed
+    /// memory, and compares the registers, the cycles and the whole memory with the
+    /// expected ones. The B bit and bit 5 of the status register are left out.
+    ///
+    /// # Returns
+    /// What differs, `name: expected, got`, if anything.
+    pub fn run(&self) -> Result<(), String> {
+        let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+        for &(address, value) in &self.initial.ram {
+            cpu.poke(address, value);
+        }
+        cpu.restore(self.initial.registers);
+        let cycles: u32 = cpu.step();
+
+        let (expected, actual): (Registers, Registers) = (self.expected.registers, cpu.snapshot());
+        let mut differences: Vec<String> = Vec::new();
+        let mut differ = |name: &str, left: u64, right: u64, width: usize| {
+            if left != right {
+                differences.push(format!(
+                    "{}: {:#0width$x}, got {:#0width$x}",
+                    name,
+                    left,
+                    right,
+                    width = width
+                ));
+            }
+        };
+        differ("pc", expected.pc as u64, actual.pc as u64, 6);
+        differ("a", expected.a as u64, actual.a as u64, 4);
+        differ("x", expected.x as u64, actual.x as u64, 4);
+        differ("y", expected.y as u64, actual.y as u64, 4);
+        differ("sp", expected.sp as u64, actual.sp as u64, 4);
+        differ(
+            "p",
+            normalized(expected.status).bits() as u64,
+            normalized(actual.status).bits() as u64,
+            4,
+        );
+        differ("cycles", self.cycles, cycles as u64, 0);
+
+        let mut ram: Vec<u8> = vec![0x00; 0x10000];
+        for &(address, value) in &self.expected.ram {
+            ram[address as usize] = value;
+        }
+        // Comparing whole slices first, which is much faster
+        let memory = cpu.memory().borrow();
+        let current: &[u8] = memory.as_slice();
+        let first = || (0..ram.len()).find(|&address| ram[address] != current[address]);
+        if let Some(address) = (ram != current).then(first).flatten() {
+            differences.push(format!(
+                "{:#06x}: {:#04x}, got {:#04x}",
+                address, ram[address], current[address]
+            ));
+        }
+
+        match differences.is_empty() {
+            true => Ok(()),
+            false => Err(format!("{}: {}", self, differences.join(", "))),
+        }
+    }
+}
+
+/// The name, then the initial registers and memory.
+impl fmt::Display for MicroTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Registers = self.initial.registers;
+        write!(
+            f,
+            "{} from pc={:#06x} a={:#04x} x={:#04x} y={:#04x} sp={:#04x} p={} ram={:02x?}",
+            self.name,
+            registers.pc,
+            registers.a,
+            registers.x,
+            registers.y,
+            registers.sp,
+            registers.status,
+            self.initial.ram
+        )
+    }
+}
+
+/// Generates `per_op_code` micro-tests of every op code `Mos6502` executes: the op code
+/// at a random address, with random registers and random bytes wherever it reads. The
+/// expected state comes from a model of the chip driven by the `InstructionInfo` of the
+/// op code: its addressing mode, length, cycles and affected flags, and an operation
+/// per mnemonic. Decimal mode is left to `roms/decimal_test.s`, `ADC` and `SBC` run
+/// with the D flag clear.
+///
+/// # Arguments
+/// * `seed` - Where the generator starts, the same seed giving the same tests.
+/// * `per_op_code` - How many tests to generate for each op code.
+///
+/// # Returns
+/// The tests, in the order of the op codes, or an error for an op code whose mnemonic
+/// the model does not know, e.g. of a new variant.
+pub fn generate(seed: u32, per_op_code: usize) -> Result<Vec<MicroTest>, String> {
+    let mut random: Random = Random(seed.max(1));
+    let mut tests: Vec<MicroTest> = Vec::new();
+    for op_code in 0..=0xff {
+        let Some(info) = instruction_info(op_code) else {
+            continue;
+        };
+        for index in 0..per_op_code {
+            let mut status: StatusFlags = normalized(StatusFlags::from_bits(random.byte()));
+            if matches!(info.mnemonic, "ADC" | "SBC") {
+                status.set_decimal_mode(false);
+            }
+            let registers: Registers = Registers {
+                pc: u16::from_le_bytes([random.byte(), random.byte()]),
+                a: random.byte(),
+                x: random.byte(),
+                y: random.byte(),
+                sp: random.byte(),
+                status,
+            };
+            let mut bus: Bus = Bus {
+                initial: BTreeMap::from([(registers.pc, op_code)]),
+                current: BTreeMap::from([(registers.pc, op_code)]),
+                random,
+            };
+            let (expected, cycles): (Registers, u64) = model(&info, registers, &mut bus)?;
+            random = bus.random;
+            tests.push(MicroTest {
+                op_code,
+                name: format!("{:02x} {} {}", op_code, info.op_code, index),
+                initial: MicroState {
+                    registers,
+                    ram: bus.initial.into_iter().collect(),
+                },
+                expected: MicroState {
+                    registers: expected,
+                    ram: bus.current.into_iter().collect(),
+                },
+                cycles,
+            });
+        }
+    }
+    Ok(tests)
+}
+
+/// A xorshift generator, for runs that are the same every time. The seed must not
+/// be 0. The fuzzer and the differential tests use it too.
+#[derive(Clone, Copy)]
+pub(crate) struct Random(pub(crate) u32);
+
+impl Random {
+    pub(crate) fn byte(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as u8
+    }
+}
+
+/// The memory of a test as the model sees it: a byte read before it is written is
+/// drawn at random, and becomes part of the initial state.
+struct Bus {
+    initial: BTreeMap<u16, u8>,
+    current: BTreeMap<u16, u8>,
+    random: Random,
+}
+
+impl Bus {
+    fn read(&mut self, address: u16) -> u8 {
+        if let Some(&value) = self.current.get(&address) {
+            return value;
+        }
+        let value: u8 = self.random.byte();
+        self.initial.insert(address, value);
+        self.current.insert(address, value);
+        value
+    }
+
+    fn read_word(&mut self, low: u16, high: u16) -> u16 {
+        u16::from_le_bytes([self.read(low), self.read(high)])
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.current.insert(address, value);
+    }
+
+    fn push(&mut self, registers: &mut Registers, value: u8) {
+        self.write(0x0100 | registers.sp as u16, value);
+        registers.sp = registers.sp.wrapping_sub(1);
+    }
+
+    fn pull(&mut self, registers: &mut Registers) -> u8 {
+        registers.sp = registers.sp.wrapping_add(1);
+        self.read(0x0100 | registers.sp as u16)
+    }
+}
+
+/// Mnemonics which take an extra cycle when indexing crosses a page.
+const READS: [&str; 11] = [
+    "LDA", "LDX", "LDY", "ADC", "SBC", "AND", "ORA", "EOR", "CMP", "BIT", "NOP",
+];
+
+/// Sets N and Z after `value`.
+///
+/// # Returns
+/// `value`.
+fn nz(status: &mut StatusFlags, value: u8) -> u8 {
+    status.set_zero(value == 0);
+    status.set_negative(value & 0x80 != 0);
+    value
+}
+
+/// Runs the instruction of `info` at PC as the chip does, see `generate()`.
+///
+/// # Returns
+/// The registers after it and the cycles it took, or an error for a mnemonic the
+/// model does not know.
+fn model(
+    info: &InstructionInfo,
+    registers: Registers,
+    bus: &mut Bus,
+) -> Result<(Registers, u64), String> {
+    let pc: u16 = registers.pc;
+    let operand: u16 = match info.length {
+        1 => 0,
+        2 => bus.read(pc.wrapping_add(1)) as u16,
+        _ => bus.read_word(pc.wrapping_add(1), pc.wrapping_add(2)),
+    };
+    let (x, y): (u8, u8) = (registers.x, registers.y);
+    let indexed = |base: u16, index: u8| {
+        let address: u16 = base.wrapping_add(index as u16);
+        (address, address & 0xff00 != base & 0xff00)
+    };
+    let (address, crossed): (u16, bool) = match info.addressing_mode {
+        AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Relative => {
+            (0, false)
+        }
+        AddressingMode::Immediate => (pc.wrapping_add(1), false),
+        AddressingMode::ZeroPage | AddressingMode::Absolute => (operand, false),
+        AddressingMode::ZeroPageX => ((operand as u8).wrapping_add(x) as u16, false),
+        AddressingMode::ZeroPageY => ((operand as u8).wrapping_add(y) as u16, false),
+        AddressingMode::AbsoluteX => indexed(operand, x),
+        AddressingMode::AbsoluteY => indexed(operand, y),
+        // The pointer does not carry into its high byte
+        AddressingMode::Indirect => {
+            let high: u16 = (operand & 0xff00) | (operand.wrapping_add(1) & 0x00ff);
+            (bus.read_word(operand, high), false)
+        }
+        AddressingMode::IndirectX => {
+            let pointer: u8 = (operand as u8).wrapping_add(x);
+            (
+                bus.read_word(pointer as u16, pointer.wrapping_add(1) as u16),
+                false,
+            )
+        }
+        AddressingMode::IndirectY => {
+            let pointer: u8 = operand as u8;
+            let base: u16 = bus.read_word(pointer as u16, pointer.wrapping_add(1) as u16);
+            indexed(base, y)
+        }
+    };
+
+    let mnemonic: &str = info.mnemonic;
+    let mut after: Registers = registers;
+    after.pc = pc.wrapping_add(info.length);
+    let mut status: StatusFlags = registers.status;
+    let mut cycles: u64 = info.cycles as u64;
+    if crossed && READS.contains(&mnemonic) {
+        cycles += 1;
+    }
+    match mnemonic {
+        "LDA" => after.a = nz(&mut status, bus.read(address)),
+        "LDX" => after.x = nz(&mut status, bus.read(address)),
+        "LDY" => after.y = nz(&mut status, bus.read(address)),
+        "STA" => bus.write(address, after.a),
+        "STX" => bus.write(address, after.x),
+        "STY" => bus.write(address, after.y),
+        "AND" => after.a = nz(&mut status, after.a & bus.read(address)),
+        "ORA" => after.a = nz(&mut status, after.a | bus.read(address)),
+        "EOR" => after.a = nz(&mut status, after.a ^ bus.read(address)),
+        // A - M - (1 - C) is A + !M + C
+        "ADC" | "SBC" => {
+            let value: u8 = match mnemonic {
+                "ADC" => bus.read(address),
+                _ => !bus.read(address),
+            };
+            let sum: u16 = after.a as u16 + value as u16 + status.carry() as u16;
+            let result: u8 = sum as u8;
+            status.set_overflow((after.a ^ result) & (value ^ result) & 0x80 != 0);
+            status.set_carry(sum > 0xff);
+            after.a = nz(&mut status, result);
+        }
+        "CMP" | "CPX" | "CPY" => {
+            let register: u8 = match mnemonic {
+                "CMP" => after.a,
+                "CPX" => after.x,
+                _ => after.y,
+            };
+            let value: u8 = bus.read(address);
+            status.set_carry(register >= value);
+            nz(&mut status, register.wrapping_sub(value));
+        }
+        "BIT" => {
+            let value: u8 = bus.read(address);
+            status.set_zero(after.a & value == 0);
+            status.set_negative(value & 0x80 != 0);
+            status.set_overflow(value & 0x40 != 0);
+        }
+        "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" => {
+            let value: u8 = match info.addressing_mode {
+                AddressingMode::Accumulator => after.a,
+                _ => bus.read(address),
+            };
+            let carry: u8 = status.carry() as u8;
+            let result: u8 = match mnemonic {
+                "ASL" => value << 1,
+                "LSR" => value >> 1,
+                "ROL" => value << 1 | carry,
+                "ROR" => value >> 1 | carry << 7,
+                "INC" => value.wrapping_add(1),
+                _ => value.wrapping_sub(1),
+            };
+            match mnemonic {
+                "ASL" | "ROL" => status.set_carry(value & 0x80 != 0),
+                "LSR" | "ROR" => status.set_carry(value & 0x01 != 0),
+                _ => {}
+            }
+            nz(&mut status, result);
+            match info.addressing_mode {
+                AddressingMode::Accumulator => after.a = result,
+                _ => bus.write(address, result),
+            }
+        }
+        "INX" => after.x = nz(&mut status, after.x.wrapping_add(1)),
+        "INY" => after.y = nz(&mut status, after.y.wrapping_add(1)),
+        "DEX" => after.x = nz(&mut status, after.x.wrapping_sub(1)),
+        "DEY" => after.y = nz(&mut status, after.y.wrapping_sub(1)),
+        "TAX" => after.x = nz(&mut status, after.a),
+        "TAY" => after.y = nz(&mut status, after.a),
+        "TXA" => after.a = nz(&mut status, after.x),
+        "TYA" => after.a = nz(&mut status, after.y),
+        "TSX" => after.x = nz(&mut status, after.sp),
+        "TXS" => after.sp = after.x,
+        "PHA" => {
+            let a: u8 = after.a;
+            bus.push(&mut after, a);
+        }
+        "PHP" => bus.push(
+            &mut after,
+            (status | StatusFlags::BREAK | StatusFlags::UNUSED).bits(),
+        ),
+        "PLA" => after.a = nz(&mut status, bus.pull(&mut after)),
+        "PLP" => status = StatusFlags::from_bits(bus.pull(&mut after)),
+        "CLC" => status.set_carry(false),
+        "SEC" => status.set_carry(true),
+        "CLD" => status.set_decimal_mode(false),
+        "SED" => status.set_decimal_mode(true),
+        "CLI" => status.set_interrupt_disable(false),
+        "SEI" => status.set_interrupt_disable(true),
+        "CLV" => status.set_overflow(false),
+        "NOP" => {}
+        "JMP" => after.pc = address,
+        // The address pushed is that of the last byte of JSR
+        "JSR" => {
+            let [low, high]: [u8; 2] = pc.wrapping_add(2).to_le_bytes();
+            bus.push(&mut after, high);
+            bus.push(&mut after, low);
+            after.pc = operand;
+        }
+        "RTS" => {
+            let low: u8 = bus.pull(&mut after);
+            let high: u8 = bus.pull(&mut after);
+            after.pc = u16::from_le_bytes([low, high]).wrapping_add(1);
+        }
+        // The byte after BRK is skipped
+        "BRK" => {
+            let [low, high]: [u8; 2] = pc.wrapping_add(2).to_le_bytes();
+            bus.push(&mut after, high);
+            bus.push(&mut after, low);
+            bus.push(
+                &mut after,
+                (status | StatusFlags::BREAK | StatusFlags::UNUSED).bits(),
+            );
+            status.set_interrupt_disable(true);
+            after.pc = bus.read_word(0xfffe, 0xffff);
+        }
+        "RTI" => {
+            status = StatusFlags::from_bits(bus.pull(&mut after));
+            let low: u8 = bus.pull(&mut after);
+            let high: u8 = bus.pull(&mut after);
+            after.pc = u16::from_le_bytes([low, high]);
+        }
+        "BCC" | "BCS" | "BEQ" | "BNE" | "BMI" | "BPL" | "BVC" | "BVS" => {
+            let taken: bool = match mnemonic {
+                "BCC" => !status.carry(),
+                "BCS" => status.carry(),
+                "BEQ" => status.zero(),
+                "BNE" => !status.zero(),
+                "BMI" => status.negative(),
+                "BPL" => !status.negative(),
+                "BVC" => !status.overflow(),
+                _ => status.overflow(),
+            };
+            if taken {
+                let target: u16 = after.pc.wrapping_add(operand as u8 as i8 as u16);
+                cycles += 1 + (target & 0xff00 != after.pc & 0xff00) as u64;
+                after.pc = target;
+            }
+        }
+        _ => return Err(format!("No model of {}", mnemonic)),
+    }
+
+    // Whatever the operation did, only the flags of the metadata may change
+    after.status =
+        normalized((registers.status & !info.affected_flags) | (status & info.affected_flags));
+    Ok((after, cycles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_the_generated_tests() {
+        let tests: Vec<MicroTest> = generate(0x6502, 20).unwrap();
+        assert!(tests.len() > 3000);
+        let failures: Vec<String> = tests.iter().filter_map(|test| test.run().err()).collect();
+        assert!(
+            failures.is_empty(),
+            "{} failed:\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+}
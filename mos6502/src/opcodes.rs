@@ -1,10 +1,13 @@
+use crate::StatusFlags;
+
 use std::fmt;
 
 /// Instruction codes from the 6510 instruction set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OpCode {
     // Misc
     Nop = 0xEA,
-    
+
     // Interrupts
     Brk = 0x00,
     Rti = 0x40,
@@ -200,9 +203,466 @@ impl From<OpCode> for u8 {
     }
 }
 
-impl From<u8> for OpCode {
-    fn from(value: u8) -> Self {
-        match value {
+/// An instruction as the CPU decodes it, for assemblers, disassemblers and analyzers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionInfo {
+    pub op_code: OpCode,
+    /// e.g. `LDA`.
+    pub mnemonic: &'static str,
+    pub addressing_mode: AddressingMode,
+    /// The size of the instruction in bytes, opcode included.
+    pub length: u16,
+    /// The cycles taken, not counting page crossings and taken branches.
+    pub cycles: u8,
+    /// The flags the instruction may change.
+    pub affected_flags: StatusFlags,
+}
+
+/// # Returns
+/// What the CPU decodes the opcode `op_code` to, or `None` if it is not implemented.
+pub fn instruction_info(op_code: u8) -> Option<InstructionInfo> {
+    OpCode::from_byte(op_code).map(|op_code| op_code.info())
+}
+
+/// How an instruction finds its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    /// Works on A, e.g. `ASL A`.
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    /// The address of the address, only for `JMP`.
+    Indirect,
+    /// `(zp,X)`.
+    IndirectX,
+    /// `(zp),Y`.
+    IndirectY,
+    /// A signed offset from the next instruction, for branches.
+    Relative,
+}
+
+impl AddressingMode {
+    /// # Returns
+    /// How many bytes of operand follow the opcode.
+    pub fn operand_bytes(&self) -> u16 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 0,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => 2,
+            _ => 1,
+        }
+    }
+
+    /// # Returns
+    /// The operand as written in references, e.g. `#imm` or `(zp),Y`, empty for
+    /// implied instructions.
+    pub fn syntax(&self) -> &'static str {
+        match self {
+            AddressingMode::Implied => "",
+            AddressingMode::Accumulator => "A",
+            AddressingMode::Immediate => "#imm",
+            AddressingMode::ZeroPage => "zp",
+            AddressingMode::ZeroPageX => "zp,X",
+            AddressingMode::ZeroPageY => "zp,Y",
+            AddressingMode::Absolute => "abs",
+            AddressingMode::AbsoluteX => "abs,X",
+            AddressingMode::AbsoluteY => "abs,Y",
+            AddressingMode::Indirect => "(abs)",
+            AddressingMode::IndirectX => "(zp,X)",
+            AddressingMode::IndirectY => "(zp),Y",
+            AddressingMode::Relative => "rel",
+        }
+    }
+
+    /// # Returns
+    /// `operand` the way assemblers write it, e.g. `#$2A` or `($10),Y`. Branches show
+    /// their target, computed from `pc`, the address of the instruction.
+    pub fn format_operand(&self, operand: u16, pc: u16) -> String {
+        match self {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", operand),
+            AddressingMode::ZeroPage => format!("${:02X}", operand),
+            AddressingMode::ZeroPageX => format!("${:02X},X", operand),
+            AddressingMode::ZeroPageY => format!("${:02X},Y", operand),
+            AddressingMode::Absolute => format!("${:04X}", operand),
+            AddressingMode::AbsoluteX => format!("${:04X},X", operand),
+            AddressingMode::AbsoluteY => format!("${:04X},Y", operand),
+            AddressingMode::Indirect => format!("(${:04X})", operand),
+            AddressingMode::IndirectX => format!("(${:02X},X)", operand),
+            AddressingMode::IndirectY => format!("(${:02X}),Y", operand),
+            AddressingMode::Relative => {
+                format!(
+                    "${:04X}",
+                    pc.wrapping_add(2).wrapping_add(operand as i8 as u16)
+                )
+            }
+        }
+    }
+}
+
+impl OpCode {
+    /// # Returns
+    /// The number of cycles the instruction takes, not counting the extra cycles
+    /// of a taken branch.
+    pub fn cycles(&self) -> u8 {
+        match self {
+            OpCode::Nop => 2,
+            OpCode::Brk => 7,
+            OpCode::Rti => 6,
+            OpCode::Jmp => 3,
+            OpCode::JmpI => 5,
+            OpCode::Jsr => 6,
+            OpCode::Rts => 6,
+            OpCode::Clc => 2,
+            OpCode::Cld => 2,
+            OpCode::Cli => 2,
+            OpCode::Clv => 2,
+            OpCode::Sec => 2,
+            OpCode::Sed => 2,
+            OpCode::Sei => 2,
+            OpCode::Dex => 2,
+            OpCode::Dey => 2,
+            OpCode::Tax => 2,
+            OpCode::Tay => 2,
+            OpCode::Tsx => 2,
+            OpCode::Txa => 2,
+            OpCode::Txs => 2,
+            OpCode::Tya => 2,
+            OpCode::Bcc => 2,
+            OpCode::Bcs => 2,
+            OpCode::Beq => 2,
+            OpCode::Bmi => 2,
+            OpCode::Bne => 2,
+            OpCode::Bpl => 2,
+            OpCode::Bvc => 2,
+            OpCode::Bvs => 2,
+            OpCode::LdxI => 2,
+            OpCode::LdxZp => 3,
+            OpCode::LdxZpY => 4,
+            OpCode::LdxA => 4,
+            OpCode::LdxAY => 4,
+            OpCode::LdaI => 2,
+            OpCode::LdaZp => 3,
+            OpCode::LdaZpX => 4,
+            OpCode::LdaA => 4,
+            OpCode::LdaAX => 4,
+            OpCode::LdaAY => 4,
+            OpCode::LdaIX => 6,
+            OpCode::LdaIY => 5,
+            OpCode::LdyI => 2,
+            OpCode::LdyZp => 3,
+            OpCode::LdyZpX => 4,
+            OpCode::LdyA => 4,
+            OpCode::LdyAX => 4,
+            OpCode::Inx => 2,
+            OpCode::Iny => 2,
+            OpCode::Pha => 3,
+            OpCode::Php => 3,
+            OpCode::Pla => 4,
+            OpCode::Plp => 4,
+            OpCode::AndI => 2,
+            OpCode::AndZp => 3,
+            OpCode::AndZpX => 4,
+            OpCode::AndA => 4,
+            OpCode::AndAX => 4,
+            OpCode::AndAY => 4,
+            OpCode::AndIX => 6,
+            OpCode::AndIY => 5,
+            OpCode::AslA => 2,
+            OpCode::AslZp => 5,
+            OpCode::AslZpX => 6,
+            OpCode::AslAbs => 6,
+            OpCode::AslAbsX => 7,
+            OpCode::CmpI => 2,
+            OpCode::CmpZp => 3,
+            OpCode::CmpZpX => 4,
+            OpCode::CmpA => 4,
+            OpCode::CmpAX => 4,
+            OpCode::CmpAY => 4,
+            OpCode::CmpIX => 6,
+            OpCode::CmpIY => 5,
+            OpCode::CpxI => 2,
+            OpCode::CpxZp => 3,
+            OpCode::CpxA => 4,
+            OpCode::CpyI => 2,
+            OpCode::CpyZp => 3,
+            OpCode::CpyA => 4,
+            OpCode::StaZp => 3,
+            OpCode::StaZpX => 4,
+            OpCode::StaA => 4,
+            OpCode::StaAX => 5,
+            OpCode::StaAY => 5,
+            OpCode::StaIX => 6,
+            OpCode::StaIY => 6,
+            OpCode::StxZp => 3,
+            OpCode::StxZpY => 4,
+            OpCode::StxA => 4,
+            OpCode::StyZp => 3,
+            OpCode::StyZpX => 4,
+            OpCode::StyA => 4,
+            OpCode::IncZp => 5,
+            OpCode::IncZpX => 6,
+            OpCode::IncA => 6,
+            OpCode::IncAX => 7,
+            OpCode::DecZp => 5,
+            OpCode::DecZpX => 6,
+            OpCode::DecA => 6,
+            OpCode::DecAX => 7,
+            OpCode::BitZp => 3,
+            OpCode::BitA => 4,
+            OpCode::EorI => 2,
+            OpCode::EorZp => 3,
+            OpCode::EorZpX => 4,
+            OpCode::EorA => 4,
+            OpCode::EorAX => 4,
+            OpCode::EorAY => 4,
+            OpCode::EorIX => 6,
+            OpCode::EorIY => 5,
+            OpCode::RolA => 2,
+            OpCode::RolZp => 5,
+            OpCode::RolZpX => 6,
+            OpCode::RolAbs => 6,
+            OpCode::RolAbsX => 7,
+            OpCode::RorA => 2,
+            OpCode::RorZp => 5,
+            OpCode::RorZpX => 6,
+            OpCode::RorAbs => 6,
+            OpCode::RorAbsX => 7,
+            OpCode::OraI => 2,
+            OpCode::OraZp => 3,
+            OpCode::OraZpX => 4,
+            OpCode::OraA => 4,
+            OpCode::OraAX => 4,
+            OpCode::OraAY => 4,
+            OpCode::OraIX => 6,
+            OpCode::OraIY => 5,
+            OpCode::LsrA => 2,
+            OpCode::LsrZp => 5,
+            OpCode::LsrZpX => 6,
+            OpCode::LsrAbs => 6,
+            OpCode::LsrAbsX => 7,
+            OpCode::AdcI => 2,
+            OpCode::AdcZp => 3,
+            OpCode::AdcZpX => 4,
+            OpCode::AdcA => 4,
+            OpCode::AdcAX => 4,
+            OpCode::AdcAY => 4,
+            OpCode::AdcIX => 6,
+            OpCode::AdcIY => 5,
+            OpCode::SbcI => 2,
+            OpCode::SbcZp => 3,
+            OpCode::SbcZpX => 4,
+            OpCode::SbcA => 4,
+            OpCode::SbcAX => 4,
+            OpCode::SbcAY => 4,
+            OpCode::SbcIX => 6,
+            OpCode::SbcIY => 5,
+        }
+    }
+
+    /// # Returns
+    /// How the instruction finds its operand.
+    pub fn addressing_mode(&self) -> AddressingMode {
+        match self {
+            OpCode::Nop
+            | OpCode::Brk
+            | OpCode::Rti
+            | OpCode::Rts
+            | OpCode::Clc
+            | OpCode::Cld
+            | OpCode::Cli
+            | OpCode::Clv
+            | OpCode::Sec
+            | OpCode::Sed
+            | OpCode::Sei
+            | OpCode::Inx
+            | OpCode::Iny
+            | OpCode::Dex
+            | OpCode::Dey
+            | OpCode::Pha
+            | OpCode::Php
+            | OpCode::Pla
+            | OpCode::Plp
+            | OpCode::Tax
+            | OpCode::Tay
+            | OpCode::Tsx
+            | OpCode::Txa
+            | OpCode::Txs
+            | OpCode::Tya => AddressingMode::Implied,
+            OpCode::AslA | OpCode::LsrA | OpCode::RolA | OpCode::RorA => {
+                AddressingMode::Accumulator
+            }
+            OpCode::LdaI
+            | OpCode::LdxI
+            | OpCode::LdyI
+            | OpCode::AdcI
+            | OpCode::SbcI
+            | OpCode::AndI
+            | OpCode::EorI
+            | OpCode::OraI
+            | OpCode::CmpI
+            | OpCode::CpxI
+            | OpCode::CpyI => AddressingMode::Immediate,
+            OpCode::LdaZp
+            | OpCode::LdxZp
+            | OpCode::LdyZp
+            | OpCode::StaZp
+            | OpCode::StxZp
+            | OpCode::StyZp
+            | OpCode::IncZp
+            | OpCode::DecZp
+            | OpCode::AdcZp
+            | OpCode::SbcZp
+            | OpCode::AndZp
+            | OpCode::BitZp
+            | OpCode::EorZp
+            | OpCode::AslZp
+            | OpCode::LsrZp
+            | OpCode::RolZp
+            | OpCode::RorZp
+            | OpCode::OraZp
+            | OpCode::CmpZp
+            | OpCode::CpxZp
+            | OpCode::CpyZp => AddressingMode::ZeroPage,
+            OpCode::LdaZpX
+            | OpCode::LdyZpX
+            | OpCode::StaZpX
+            | OpCode::StyZpX
+            | OpCode::IncZpX
+            | OpCode::DecZpX
+            | OpCode::AdcZpX
+            | OpCode::SbcZpX
+            | OpCode::AndZpX
+            | OpCode::EorZpX
+            | OpCode::AslZpX
+            | OpCode::LsrZpX
+            | OpCode::RolZpX
+            | OpCode::RorZpX
+            | OpCode::OraZpX
+            | OpCode::CmpZpX => AddressingMode::ZeroPageX,
+            OpCode::LdxZpY | OpCode::StxZpY => AddressingMode::ZeroPageY,
+            OpCode::Jmp
+            | OpCode::Jsr
+            | OpCode::LdaA
+            | OpCode::LdxA
+            | OpCode::LdyA
+            | OpCode::StaA
+            | OpCode::StxA
+            | OpCode::StyA
+            | OpCode::IncA
+            | OpCode::DecA
+            | OpCode::AdcA
+            | OpCode::SbcA
+            | OpCode::AndA
+            | OpCode::BitA
+            | OpCode::EorA
+            | OpCode::AslAbs
+            | OpCode::LsrAbs
+            | OpCode::RolAbs
+            | OpCode::RorAbs
+            | OpCode::OraA
+            | OpCode::CmpA
+            | OpCode::CpxA
+            | OpCode::CpyA => AddressingMode::Absolute,
+            OpCode::LdaAX
+            | OpCode::LdyAX
+            | OpCode::StaAX
+            | OpCode::IncAX
+            | OpCode::DecAX
+            | OpCode::AdcAX
+            | OpCode::SbcAX
+            | OpCode::AndAX
+            | OpCode::EorAX
+            | OpCode::AslAbsX
+            | OpCode::LsrAbsX
+            | OpCode::RolAbsX
+            | OpCode::RorAbsX
+            | OpCode::OraAX
+            | OpCode::CmpAX => AddressingMode::AbsoluteX,
+            OpCode::LdaAY
+            | OpCode::LdxAY
+            | OpCode::StaAY
+            | OpCode::AdcAY
+            | OpCode::SbcAY
+            | OpCode::AndAY
+            | OpCode::EorAY
+            | OpCode::OraAY
+            | OpCode::CmpAY => AddressingMode::AbsoluteY,
+            OpCode::JmpI => AddressingMode::Indirect,
+            OpCode::LdaIX
+            | OpCode::StaIX
+            | OpCode::AdcIX
+            | OpCode::SbcIX
+            | OpCode::AndIX
+            | OpCode::EorIX
+            | OpCode::OraIX
+            | OpCode::CmpIX => AddressingMode::IndirectX,
+            OpCode::LdaIY
+            | OpCode::StaIY
+            | OpCode::AdcIY
+            | OpCode::SbcIY
+            | OpCode::AndIY
+            | OpCode::EorIY
+            | OpCode::OraIY
+            | OpCode::CmpIY => AddressingMode::IndirectY,
+            OpCode::Bcc
+            | OpCode::Bcs
+            | OpCode::Beq
+            | OpCode::Bmi
+            | OpCode::Bne
+            | OpCode::Bpl
+            | OpCode::Bvc
+            | OpCode::Bvs => AddressingMode::Relative,
+        }
+    }
+
+    /// # Returns
+    /// The flags the instruction may change when it runs, besides pushing them.
+    pub fn affected_flags(&self) -> StatusFlags {
+        let nz: StatusFlags = StatusFlags::NEGATIVE | StatusFlags::ZERO;
+        match self.mnemonic() {
+            "ADC" | "SBC" => nz | StatusFlags::OVERFLOW | StatusFlags::CARRY,
+            "ASL" | "LSR" | "ROL" | "ROR" | "CMP" | "CPX" | "CPY" => nz | StatusFlags::CARRY,
+            "BIT" => nz | StatusFlags::OVERFLOW,
+            "LDA" | "LDX" | "LDY" | "AND" | "ORA" | "EOR" | "INC" | "DEC" | "INX" | "INY"
+            | "DEX" | "DEY" | "TAX" | "TAY" | "TSX" | "TXA" | "TYA" | "PLA" => nz,
+            "CLC" | "SEC" => StatusFlags::CARRY,
+            "CLD" | "SED" => StatusFlags::DECIMAL_MODE,
+            "CLI" | "SEI" | "BRK" => StatusFlags::INTERRUPT_DISABLE,
+            "CLV" => StatusFlags::OVERFLOW,
+            // The whole register comes from the stack
+            "PLP" | "RTI" => !(StatusFlags::BREAK | StatusFlags::UNUSED),
+            _ => StatusFlags::empty(),
+        }
+    }
+
+    /// # Returns
+    /// What is known of the instruction, see `instruction_info()`.
+    pub fn info(&self) -> InstructionInfo {
+        let addressing_mode: AddressingMode = self.addressing_mode();
+        InstructionInfo {
+            op_code: *self,
+            mnemonic: self.mnemonic(),
+            addressing_mode,
+            length: 1 + addressing_mode.operand_bytes(),
+            cycles: self.cycles(),
+            affected_flags: self.affected_flags(),
+        }
+    }
+
+    /// # Returns
+    /// The instruction with the opcode `value`, or `None` if it is not implemented.
+    pub fn from_byte(value: u8) -> Option<OpCode> {
+        Some(match value {
             // This is order of implementation
             // So not an specific order here
             0xEA => OpCode::Nop,
@@ -356,20 +816,28 @@ impl From<u8> for OpCode {
             0xF9 => OpCode::SbcAY,
             0xE1 => OpCode::SbcIX,
             0xF1 => OpCode::SbcIY,
-            _ => panic!("Unknown OpCode: {:02X}", value),
-        }
+            _ => return None,
+        })
+    }
+}
+
+impl From<u8> for OpCode {
+    fn from(value: u8) -> Self {
+        OpCode::from_byte(value).unwrap_or_else(|| panic!("Unknown OpCode: {:02X}", value))
     }
 }
 
 // Implementation for debug info
-impl fmt::Display for OpCode {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let op_str = match self {
+impl OpCode {
+    /// # Returns
+    /// The name of the instruction, shared by all its addressing modes, e.g. `LDA`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
             OpCode::Nop => "NOP",
             OpCode::Brk => "BRK",
             OpCode::Rti => "RTI",
             OpCode::Jmp => "JMP",
-            OpCode::JmpI => "JMPI",
+            OpCode::JmpI => "JMP",
             OpCode::Jsr => "JSR",
             OpCode::Rts => "RTS",
             OpCode::Clc => "CLC",
@@ -516,8 +984,48 @@ impl fmt::Display for OpCode {
             OpCode::SbcAY => "SBC",
             OpCode::SbcIX => "SBC",
             OpCode::SbcIY => "SBC",
-        };
+        }
+    }
+}
+
+/// The mnemonic and the addressing mode, e.g. `LDA (zp),Y` or `ASL A`.
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.addressing_mode().syntax() {
+            "" => write!(f, "{}", self.mnemonic()),
+            syntax => write!(f, "{} {}", self.mnemonic(), syntax),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_mnemonic_and_addressing_mode() {
+        assert_eq!(OpCode::LdaIY.to_string(), "LDA (zp),Y");
+        assert_eq!(OpCode::JmpI.to_string(), "JMP (abs)");
+        assert_eq!(OpCode::RolA.to_string(), "ROL A");
+        assert_eq!(OpCode::Nop.to_string(), "NOP");
+        assert_eq!(OpCode::StaAX.mnemonic(), "STA");
+        assert_eq!(OpCode::Bne.addressing_mode(), AddressingMode::Relative);
+    }
 
-        write!(f, "{}", op_str)
+    #[test]
+    fn describes_instructions() {
+        let info: InstructionInfo = instruction_info(0x7d).unwrap();
+        assert_eq!(info.op_code, OpCode::AdcAX);
+        assert_eq!(
+            (
+                info.mnemonic,
+                info.addressing_mode,
+                info.length,
+                info.cycles
+            ),
+            ("ADC", AddressingMode::AbsoluteX, 3, 4)
+        );
+        assert_eq!(info.affected_flags.to_string(), "NV----ZC");
+        assert_eq!(instruction_info(0x02), None);
     }
 }
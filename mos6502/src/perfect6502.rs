@@ -0,0 +1,260 @@
+//! The transistor-level simulation of perfect6502, https://github.com/mist64/perfect6502,
+//! as a `differential::Core`, behind the `perfect6502` feature. Link a static
+//! `libperfect6502.a` built from its `perfect6502.c` and `netlist_sim.c`, e.g. with
+//! `RUSTFLAGS=-L<dir> cargo test -p mos6502 --features perfect6502`.
+
+use crate::differential::{compare, Core, Divergence};
+use crate::{Mos6502, Registers, StatusFlags};
+
+use memory::Access;
+use std::cell::RefCell;
+use std::ffi::{c_uint, c_void};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The nodes of the netlist read here, as numbered by visual6502.
+const CLK0: c_uint = 1171;
+const SYNC: c_uint = 539;
+
+/// The memory of perfect6502 is a global, so only one chip can run at a time.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[link(name = "perfect6502")]
+extern "C" {
+    static mut memory: [u8; 0x10000];
+
+    fn initAndResetChip() -> *mut c_void;
+    fn destroyChip(state: *mut c_void);
+    /// A half cycle, the memory is read or written as the clock rises.
+    fn step(state: *mut c_void);
+    fn isNodeHigh(state: *mut c_void, node: c_uint) -> u8;
+    fn readA(state: *mut c_void) -> u8;
+    fn readX(state: *mut c_void) -> u8;
+    fn readY(state: *mut c_void) -> u8;
+    fn readSP(state: *mut c_void) -> u8;
+    fn readP(state: *mut c_void) -> u8;
+    fn readRW(state: *mut c_void) -> u8;
+    fn readAddressBus(state: *mut c_void) -> u16;
+    fn readDataBus(state: *mut c_void) -> u8;
+}
+
+/// A perfect6502 chip, stepped an instruction at a time.
+///
+/// On the chip the last cycle of an instruction overlaps the fetch of the next one, so
+/// an instruction is counted from its op code fetch up to the next, and the registers
+/// are read once that fetch ended, when the instruction before is done with them.
+pub struct Perfect6502 {
+    state: *mut c_void,
+    /// The reads and writes of the last instruction, its op code fetch first.
+    accesses: Vec<Access>,
+    /// The op code fetch of the next instruction.
+    fetch: Access,
+    cycles: u64,
+}
+
+impl Perfect6502 {
+    /// Resets a chip in `ram`, the whole 64 KiB, which runs up to the fetch of the
+    /// first op code, from the reset vector.
+    ///
+    /// # Panics
+    /// If another `Perfect6502` exists.
+    pub fn new(ram: &[u8]) -> Perfect6502 {
+        assert_eq!(ram.len(), 0x10000, "the memory of the chip is 64 KiB");
+        assert!(
+            !RUNNING.swap(true, Ordering::SeqCst),
+            "only one perfect6502 chip can run at a time"
+        );
+        // SAFETY: only this chip uses the memory and the simulation, see `RUNNING`
+        let state: *mut c_void = unsafe {
+            (*std::ptr::addr_of_mut!(memory)).copy_from_slice(ram);
+            initAndResetChip()
+        };
+        let mut chip: Perfect6502 = Perfect6502 {
+            state,
+            accesses: Vec::new(),
+            fetch: Access {
+                address: 0,
+                value: 0,
+                write: false,
+            },
+            cycles: 0,
+        };
+        chip.fetch = chip.run_to_fetch(|_| {});
+        chip.cycles = 0;
+        chip
+    }
+
+    /// # Returns
+    /// What the address and data buses held in the last cycle.
+    fn cycle(&mut self) -> (Access, bool) {
+        // SAFETY: `state` lives until `drop()`
+        unsafe {
+            loop {
+                step(self.state);
+                if isNodeHigh(self.state, CLK0) != 0 {
+                    break;
+                }
+            }
+            self.cycles += 1;
+            let access: Access = Access {
+                address: readAddressBus(self.state),
+                value: readDataBus(self.state),
+                write: readRW(self.state) == 0,
+            };
+            (access, isNodeHigh(self.state, SYNC) != 0)
+        }
+    }
+
+    /// Runs up to the next op code fetch, passing the cycles before it to `each`.
+    ///
+    /// # Returns
+    /// The fetch.
+    fn run_to_fetch(&mut self, mut each: impl FnMut(Access)) -> Access {
+        loop {
+            let (access, sync): (Access, bool) = self.cycle();
+            if sync {
+                return access;
+            }
+            each(access);
+        }
+    }
+
+    /// # Returns
+    /// The bus cycles of the last instruction, its op code fetch first.
+    pub fn accesses(&self) -> &[Access] {
+        &self.accesses
+    }
+}
+
+impl Drop for Perfect6502 {
+    fn drop(&mut self) {
+        // SAFETY: `state` came from `initAndResetChip()` and is not used after
+        unsafe { destroyChip(self.state) };
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Core for Perfect6502 {
+    fn name(&self) -> &str {
+        "perfect6502"
+    }
+
+    /// The simulation never stops, the chip jams on the op codes that do.
+    fn step(&mut self) -> bool {
+        let mut accesses: Vec<Access> = vec![self.fetch];
+        self.fetch = self.run_to_fetch(|access| accesses.push(access));
+        self.accesses = accesses;
+        true
+    }
+
+    fn registers(&self) -> Registers {
+        // SAFETY: `state` lives until `drop()`
+        unsafe {
+            Registers {
+                a: readA(self.state),
+                x: readX(self.state),
+                y: readY(self.state),
+                sp: readSP(self.state),
+                // PC has moved past the op code fetched
+                pc: self.fetch.address,
+                status: StatusFlags::from_bits(readP(self.state)),
+            }
+        }
+    }
+
+    /// The cycles since the first op code fetch.
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn memory(&self) -> Box<dyn Deref<Target = [u8]> + '_> {
+        // SAFETY: only this chip writes the memory, and not while it is borrowed
+        Box::new(unsafe { &*std::ptr::addr_of!(memory) }.as_slice())
+    }
+}
+
+/// Runs `chip` and `cpu` side by side like `differential::compare()`, and compares the
+/// reads and writes of every instruction as well, cycle by cycle.
+///
+/// # Returns
+/// How many instructions ran alike, up to `max_instructions`, or the first difference.
+pub fn compare_bus(
+    chip: &mut Perfect6502,
+    cpu: &mut Mos6502,
+    max_instructions: u64,
+) -> Result<u64, Divergence> {
+    let accesses: Rc<RefCell<Vec<Access>>> = Rc::new(RefCell::new(Vec::new()));
+    let traced: Rc<RefCell<Vec<Access>>> = accesses.clone();
+    cpu.set_tracer(move |record| *traced.borrow_mut() = record.accesses.clone());
+
+    for executed in 0..max_instructions {
+        let pc: u16 = Core::registers(chip).pc;
+        let op_code: u8 = Core::memory(chip)[pc as usize];
+        let mut differences: Vec<String> = match compare(chip, cpu, 1) {
+            Ok(0) => return Ok(executed),
+            Ok(_) => Vec::new(),
+            Err(divergence) => divergence.differences,
+        };
+        let actual: Vec<Access> = accesses.borrow_mut().drain(..).collect();
+        let expected: &[Access] = chip.accesses();
+        if let Some(cycle) = (0..expected.len().max(actual.len()))
+            .find(|&cycle| expected.get(cycle) != actual.get(cycle))
+        {
+            differences.push(format!(
+                "cycle {}: {}, got {}",
+                cycle + 1,
+                describe(expected.get(cycle)),
+                describe(actual.get(cycle))
+            ));
+        }
+        if !differences.is_empty() {
+            cpu.clear_tracer();
+            return Err(Divergence {
+                instructions: executed,
+                pc,
+                op_code,
+                differences,
+            });
+        }
+    }
+    cpu.clear_tracer();
+    Ok(max_instructions)
+}
+
+/// # Returns
+/// `access` as in `read 0x01 at 0x0200`, `nothing` without one.
+fn describe(access: Option<&Access>) -> String {
+    match access {
+        Some(access) => format!(
+            "{} {:#04x} at {:#06x}",
+            if access.write { "write" } else { "read" },
+            access.value,
+            access.address
+        ),
+        None => "nothing".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use memory::Memory;
+
+    #[test]
+    fn agrees_on_loads_and_stores() {
+        // LDA #$42, STA $10, LDX $10, INX, JMP $0207
+        let program: [u8; 10] = [0xa9, 0x42, 0x85, 0x10, 0xa6, 0x10, 0xe8, 0x4c, 0x07, 0x02];
+        let mut cpu: Mos6502 = Mos6502::new(Rc::new(RefCell::new(Memory::new())));
+        cpu.load(0x0200, &program);
+        cpu.load(0xfffc, &[0x00, 0x02]);
+        let mut chip: Perfect6502 = Perfect6502::new(cpu.memory().borrow().as_slice());
+        cpu.restore(Core::registers(&chip));
+
+        let executed: Result<u64, Divergence> = compare(&mut chip, &mut cpu, 4);
+        assert_eq!(executed, Ok(4));
+        assert_eq!(Core::registers(&chip).x, 0x43);
+        assert_eq!(Core::memory(&chip)[0x10], 0x42);
+    }
+}
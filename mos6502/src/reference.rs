@@ -0,0 +1,546 @@
+use crate::{Registers, StatusFlags};
+
+/// What an instruction does, apart from how it gets its operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Adc,
+    And,
+    Asl,
+    Bit,
+    Branch { flag: StatusFlags, set: bool },
+    Brk,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Flag { flag: StatusFlags, set: bool },
+    Inc,
+    Inx,
+    Iny,
+    Jmp,
+    Jsr,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rol,
+    Ror,
+    Rti,
+    Rts,
+    Sbc,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+}
+
+/// Where the operand of an instruction is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+/// A plain NMOS 6502 over 64 KiB of RAM, written apart from `Mos6502` after the
+/// documented behaviour of the chip, decimal mode and cycle counts included, for
+/// `differential::compare()` to check `Mos6502` against. It runs the official
+/// instructions only, and has no interrupt lines.
+pub struct Reference {
+    pub registers: Registers,
+    pub ram: Vec<u8>,
+    pub cycles: u64,
+}
+
+impl Reference {
+    /// # Returns
+    /// A reference CPU over `ram`, which must be 64 KiB, with the registers of
+    /// `registers`.
+    pub fn new(ram: Vec<u8>, registers: Registers) -> Self {
+        assert_eq!(ram.len(), 0x10000, "The reference CPU needs 64 KiB of RAM");
+        let mut registers: Registers = registers;
+        registers.status = normalized(registers.status);
+        Reference {
+            registers,
+            ram,
+            cycles: 0,
+        }
+    }
+
+    /// Executes the instruction at PC.
+    ///
+    /// # Returns
+    /// `false`, changing nothing, if the op code is not one of an official instruction.
+    pub fn step(&mut self) -> bool {
+        let pc: u16 = self.registers.pc;
+        let Some((op, mode)) = decode(self.ram[pc as usize]) else {
+            return false;
+        };
+        let operand_bytes: u16 = match mode {
+            Mode::Implied | Mode::Accumulator => 0,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 2,
+            _ => 1,
+        };
+        let next: u16 = pc.wrapping_add(1 + operand_bytes);
+        let byte: u8 = self.read(pc.wrapping_add(1));
+        let word: u16 = u16::from_le_bytes([byte, self.read(pc.wrapping_add(2))]);
+        let (x, y): (u16, u16) = (self.registers.x as u16, self.registers.y as u16);
+
+        // The effective address, and whether indexing crossed a page
+        let (address, crossed): (u16, bool) = match mode {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate => (pc.wrapping_add(1), false),
+            Mode::ZeroPage => (byte as u16, false),
+            Mode::ZeroPageX => (byte.wrapping_add(self.registers.x) as u16, false),
+            Mode::ZeroPageY => (byte.wrapping_add(self.registers.y) as u16, false),
+            Mode::Absolute => (word, false),
+            Mode::AbsoluteX => (word.wrapping_add(x), (word & 0xff) + x > 0xff),
+            Mode::AbsoluteY => (word.wrapping_add(y), (word & 0xff) + y > 0xff),
+            // The pointer does not carry into its high byte
+            Mode::Indirect => {
+                let high: u16 = (word & 0xff00) | (word.wrapping_add(1) & 0xff);
+                (
+                    u16::from_le_bytes([self.read(word), self.read(high)]),
+                    false,
+                )
+            }
+            Mode::IndirectX => {
+                let pointer: u8 = byte.wrapping_add(self.registers.x);
+                (self.zero_page_word(pointer), false)
+            }
+            Mode::IndirectY => {
+                let base: u16 = self.zero_page_word(byte);
+                (base.wrapping_add(y), (base & 0xff) + y > 0xff)
+            }
+            Mode::Relative => (next.wrapping_add(byte as i8 as u16), false),
+        };
+
+        let mut cycles: u64 = base_cycles(op, mode);
+        if crossed && is_read(op) {
+            cycles += 1;
+        }
+        self.registers.pc = next;
+        let value = |cpu: &Reference| match mode {
+            Mode::Accumulator => cpu.registers.a,
+            _ => cpu.read(address),
+        };
+
+        match op {
+            Op::Adc => {
+                let value: u8 = value(self);
+                self.add(value);
+            }
+            Op::Sbc => {
+                let value: u8 = value(self);
+                self.subtract(value);
+            }
+            Op::And => self.registers.a = self.set_nz(self.registers.a & value(self)),
+            Op::Ora => self.registers.a = self.set_nz(self.registers.a | value(self)),
+            Op::Eor => self.registers.a = self.set_nz(self.registers.a ^ value(self)),
+            Op::Bit => {
+                let value: u8 = value(self);
+                let status: &mut StatusFlags = &mut self.registers.status;
+                status.set(StatusFlags::ZERO, self.registers.a & value == 0);
+                status.set(StatusFlags::NEGATIVE, value & 0x80 != 0);
+                status.set(StatusFlags::OVERFLOW, value & 0x40 != 0);
+            }
+            Op::Cmp => self.compare(self.registers.a, value(self)),
+            Op::Cpx => self.compare(self.registers.x, value(self)),
+            Op::Cpy => self.compare(self.registers.y, value(self)),
+            Op::Asl | Op::Lsr | Op::Rol | Op::Ror | Op::Inc | Op::Dec => {
+                let value: u8 = value(self);
+                let carry: u8 = self.registers.status.carry() as u8;
+                let (result, carry_out): (u8, Option<bool>) = match op {
+                    Op::Asl => (value << 1, Some(value & 0x80 != 0)),
+                    Op::Lsr => (value >> 1, Some(value & 0x01 != 0)),
+                    Op::Rol => ((value << 1) | carry, Some(value & 0x80 != 0)),
+                    Op::Ror => ((value >> 1) | (carry << 7), Some(value & 0x01 != 0)),
+                    Op::Inc => (value.wrapping_add(1), None),
+                    _ => (value.wrapping_sub(1), None),
+                };
+                if let Some(carry) = carry_out {
+                    self.registers.status.set(StatusFlags::CARRY, carry);
+                }
+                self.set_nz(result);
+                match mode {
+                    Mode::Accumulator => self.registers.a = result,
+                    _ => self.ram[address as usize] = result,
+                }
+            }
+            Op::Lda => self.registers.a = self.set_nz(value(self)),
+            Op::Ldx => self.registers.x = self.set_nz(value(self)),
+            Op::Ldy => self.registers.y = self.set_nz(value(self)),
+            Op::Sta => self.ram[address as usize] = self.registers.a,
+            Op::Stx => self.ram[address as usize] = self.registers.x,
+            Op::Sty => self.ram[address as usize] = self.registers.y,
+            Op::Tax => self.registers.x = self.set_nz(self.registers.a),
+            Op::Tay => self.registers.y = self.set_nz(self.registers.a),
+            Op::Txa => self.registers.a = self.set_nz(self.registers.x),
+            Op::Tya => self.registers.a = self.set_nz(self.registers.y),
+            Op::Tsx => self.registers.x = self.set_nz(self.registers.sp),
+            Op::Txs => self.registers.sp = self.registers.x,
+            Op::Inx => self.registers.x = self.set_nz(self.registers.x.wrapping_add(1)),
+            Op::Iny => self.registers.y = self.set_nz(self.registers.y.wrapping_add(1)),
+            Op::Dex => self.registers.x = self.set_nz(self.registers.x.wrapping_sub(1)),
+            Op::Dey => self.registers.y = self.set_nz(self.registers.y.wrapping_sub(1)),
+            Op::Flag { flag, set } => self.registers.status.set(flag, set),
+            Op::Branch { flag, set } => {
+                if self.registers.status.contains(flag) == set {
+                    cycles += 1;
+                    if address & 0xff00 != next & 0xff00 {
+                        cycles += 1;
+                    }
+                    self.registers.pc = address;
+                }
+            }
+            Op::Jmp => self.registers.pc = address,
+            Op::Jsr => {
+                // The address of the last byte of JSR, RTS adds the 1
+                self.push_word(next.wrapping_sub(1));
+                self.registers.pc = address;
+            }
+            Op::Rts => self.registers.pc = self.pull_word().wrapping_add(1),
+            Op::Brk => {
+                // The byte after BRK is skipped
+                self.push_word(pc.wrapping_add(2));
+                self.push((self.registers.status | StatusFlags::BREAK).bits());
+                self.registers
+                    .status
+                    .set(StatusFlags::INTERRUPT_DISABLE, true);
+                self.registers.pc = u16::from_le_bytes([self.ram[0xfffe], self.ram[0xffff]]);
+            }
+            Op::Rti => {
+                self.registers.status = normalized(StatusFlags::from_bits(self.pull()));
+                self.registers.pc = self.pull_word();
+            }
+            Op::Pha => self.push(self.registers.a),
+            Op::Php => self.push((self.registers.status | StatusFlags::BREAK).bits()),
+            Op::Pla => {
+                let value: u8 = self.pull();
+                self.registers.a = self.set_nz(value);
+            }
+            Op::Plp => self.registers.status = normalized(StatusFlags::from_bits(self.pull())),
+            Op::Nop => {}
+        }
+        self.cycles += cycles;
+        true
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        self.ram[address as usize]
+    }
+
+    /// # Returns
+    /// The word at `pointer` in the zero page, its high byte wrapping to `$00`.
+    fn zero_page_word(&self, pointer: u8) -> u16 {
+        u16::from_le_bytes([
+            self.read(pointer as u16),
+            self.read(pointer.wrapping_add(1) as u16),
+        ])
+    }
+
+    /// Sets Z and N after `value`.
+    ///
+    /// # Returns
+    /// `value`.
+    fn set_nz(&mut self, value: u8) -> u8 {
+        let status: &mut StatusFlags = &mut self.registers.status;
+        status.set(StatusFlags::ZERO, value == 0);
+        status.set(StatusFlags::NEGATIVE, value & 0x80 != 0);
+        value
+    }
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.registers
+            .status
+            .set(StatusFlags::CARRY, register >= value);
+        self.set_nz(register.wrapping_sub(value));
+    }
+
+    fn add(&mut self, value: u8) {
+        let a: u8 = self.registers.a;
+        let carry: u16 = self.registers.status.carry() as u16;
+        let binary: u16 = a as u16 + value as u16 + carry;
+        if !self.registers.status.decimal_mode() {
+            let overflow: bool = (a ^ binary as u8) & (value ^ binary as u8) & 0x80 != 0;
+            self.registers.status.set(StatusFlags::OVERFLOW, overflow);
+            self.registers.status.set(StatusFlags::CARRY, binary > 0xff);
+            self.registers.a = self.set_nz(binary as u8);
+            return;
+        }
+
+        // The NMOS chip takes Z from the binary sum, N and V before the high digit is
+        // adjusted
+        let mut low: u16 = (a & 0x0f) as u16 + (value & 0x0f) as u16 + carry;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut sum: u16 = (a & 0xf0) as u16 + (value & 0xf0) as u16 + low;
+        let signed: i16 = (a & 0xf0) as i8 as i16 + (value & 0xf0) as i8 as i16 + low as i16;
+        let status: &mut StatusFlags = &mut self.registers.status;
+        status.set(StatusFlags::ZERO, binary & 0xff == 0);
+        status.set(StatusFlags::NEGATIVE, sum & 0x80 != 0);
+        status.set(StatusFlags::OVERFLOW, !(-128..=127).contains(&signed));
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        status.set(StatusFlags::CARRY, sum >= 0x100);
+        self.registers.a = sum as u8;
+    }
+
+    fn subtract(&mut self, value: u8) {
+        let a: u8 = self.registers.a;
+        let borrow: i16 = 1 - self.registers.status.carry() as i16;
+        let binary: i16 = a as i16 - value as i16 - borrow;
+        // The NMOS chip takes every flag from the binary difference
+        let overflow: bool = (a ^ value) & (a ^ binary as u8) & 0x80 != 0;
+        self.registers.status.set(StatusFlags::OVERFLOW, overflow);
+        self.registers.status.set(StatusFlags::CARRY, binary >= 0);
+        self.set_nz(binary as u8);
+        if !self.registers.status.decimal_mode() {
+            self.registers.a = binary as u8;
+            return;
+        }
+
+        let mut low: i16 = (a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0f) - 0x10;
+        }
+        let mut difference: i16 = (a & 0xf0) as i16 - (value & 0xf0) as i16 + low;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+        self.registers.a = difference as u8;
+    }
+
+    fn push(&mut self, value: u8) {
+        self.ram[0x0100 + self.registers.sp as usize] = value;
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+    }
+
+    fn push_word(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
+
+    fn pull(&mut self) -> u8 {
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        self.ram[0x0100 + self.registers.sp as usize]
+    }
+
+    fn pull_word(&mut self) -> u16 {
+        let low: u8 = self.pull();
+        u16::from_le_bytes([low, self.pull()])
+    }
+}
+
+/// # Returns
+/// `status` as the register holds it: the B bit only exists in pushed copies, and
+/// bit 5 always reads as set.
+pub fn normalized(status: StatusFlags) -> StatusFlags {
+    StatusFlags::from_bits(
+        (status.bits() & !StatusFlags::BREAK.bits()) | StatusFlags::UNUSED.bits(),
+    )
+}
+
+/// # Returns
+/// The instruction of `op_code`, from its bits `aaabbbcc`: `cc` the group, `aaa` the
+/// operation and `bbb` the addressing mode, with the exceptions of each group.
+fn decode(op_code: u8) -> Option<(Op, Mode)> {
+    let (aaa, bbb, cc): (u8, u8, u8) = (op_code >> 5, (op_code >> 2) & 0x07, op_code & 0x03);
+
+    // The instructions without an operand, and the branches
+    let single: Option<Op> = match op_code {
+        0x00 => Some(Op::Brk),
+        0x40 => Some(Op::Rti),
+        0x60 => Some(Op::Rts),
+        0x08 => Some(Op::Php),
+        0x28 => Some(Op::Plp),
+        0x48 => Some(Op::Pha),
+        0x68 => Some(Op::Pla),
+        0x88 => Some(Op::Dey),
+        0xa8 => Some(Op::Tay),
+        0xc8 => Some(Op::Iny),
+        0xe8 => Some(Op::Inx),
+        0x18 => Some(flag(StatusFlags::CARRY, false)),
+        0x38 => Some(flag(StatusFlags::CARRY, true)),
+        0x58 => Some(flag(StatusFlags::INTERRUPT_DISABLE, false)),
+        0x78 => Some(flag(StatusFlags::INTERRUPT_DISABLE, true)),
+        0xb8 => Some(flag(StatusFlags::OVERFLOW, false)),
+        0xd8 => Some(flag(StatusFlags::DECIMAL_MODE, false)),
+        0xf8 => Some(flag(StatusFlags::DECIMAL_MODE, true)),
+        0x98 => Some(Op::Tya),
+        0x8a => Some(Op::Txa),
+        0x9a => Some(Op::Txs),
+        0xaa => Some(Op::Tax),
+        0xba => Some(Op::Tsx),
+        0xca => Some(Op::Dex),
+        0xea => Some(Op::Nop),
+        _ => None,
+    };
+    if let Some(op) = single {
+        return Some((op, Mode::Implied));
+    }
+    if op_code & 0x1f == 0x10 {
+        // xxy10000: xx the flag, N V C Z, and y whether it is set
+        let flag: StatusFlags = [
+            StatusFlags::NEGATIVE,
+            StatusFlags::OVERFLOW,
+            StatusFlags::CARRY,
+            StatusFlags::ZERO,
+        ][(op_code >> 6) as usize];
+        let set: bool = op_code & 0x20 != 0;
+        return Some((Op::Branch { flag, set }, Mode::Relative));
+    }
+    if op_code == 0x20 {
+        return Some((Op::Jsr, Mode::Absolute));
+    }
+
+    match cc {
+        0b01 => {
+            let op: Op = [
+                Op::Ora,
+                Op::And,
+                Op::Eor,
+                Op::Adc,
+                Op::Sta,
+                Op::Lda,
+                Op::Cmp,
+                Op::Sbc,
+            ][aaa as usize];
+            let mode: Mode = [
+                Mode::IndirectX,
+                Mode::ZeroPage,
+                Mode::Immediate,
+                Mode::Absolute,
+                Mode::IndirectY,
+                Mode::ZeroPageX,
+                Mode::AbsoluteY,
+                Mode::AbsoluteX,
+            ][bbb as usize];
+            (op != Op::Sta || mode != Mode::Immediate).then_some((op, mode))
+        }
+        0b10 => {
+            let op: Op = [
+                Op::Asl,
+                Op::Rol,
+                Op::Lsr,
+                Op::Ror,
+                Op::Stx,
+                Op::Ldx,
+                Op::Dec,
+                Op::Inc,
+            ][aaa as usize];
+            // STX and LDX index with Y instead
+            let indexed_y: bool = matches!(op, Op::Stx | Op::Ldx);
+            let mode: Mode = match bbb {
+                0 if op == Op::Ldx => Mode::Immediate,
+                1 => Mode::ZeroPage,
+                2 if aaa < 4 => Mode::Accumulator,
+                3 => Mode::Absolute,
+                5 if indexed_y => Mode::ZeroPageY,
+                5 => Mode::ZeroPageX,
+                7 if op == Op::Ldx => Mode::AbsoluteY,
+                7 if op != Op::Stx => Mode::AbsoluteX,
+                _ => return None,
+            };
+            Some((op, mode))
+        }
+        0b00 => {
+            let op: Op = match aaa {
+                1 => Op::Bit,
+                2 | 3 => Op::Jmp,
+                4 => Op::Sty,
+                5 => Op::Ldy,
+                6 => Op::Cpy,
+                7 => Op::Cpx,
+                _ => return None,
+            };
+            let mode: Mode = match bbb {
+                0 if matches!(op, Op::Ldy | Op::Cpy | Op::Cpx) => Mode::Immediate,
+                1 if op != Op::Jmp => Mode::ZeroPage,
+                3 if aaa == 3 => Mode::Indirect,
+                3 => Mode::Absolute,
+                5 if matches!(op, Op::Sty | Op::Ldy) => Mode::ZeroPageX,
+                7 if op == Op::Ldy => Mode::AbsoluteX,
+                _ => return None,
+            };
+            Some((op, mode))
+        }
+        _ => None,
+    }
+}
+
+fn flag(flag: StatusFlags, set: bool) -> Op {
+    Op::Flag { flag, set }
+}
+
+/// # Returns
+/// `true` if `op` only reads its operand, taking a cycle more when indexing crosses
+/// a page.
+fn is_read(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Adc
+            | Op::And
+            | Op::Bit
+            | Op::Cmp
+            | Op::Cpx
+            | Op::Cpy
+            | Op::Eor
+            | Op::Lda
+            | Op::Ldx
+            | Op::Ldy
+            | Op::Ora
+            | Op::Sbc
+    )
+}
+
+/// # Returns
+/// The cycles `op` takes in `mode`, without those of crossing a page or taking a
+/// branch.
+fn base_cycles(op: Op, mode: Mode) -> u64 {
+    let modify: bool = matches!(
+        op,
+        Op::Asl | Op::Lsr | Op::Rol | Op::Ror | Op::Inc | Op::Dec
+    );
+    let write: bool = matches!(op, Op::Sta | Op::Stx | Op::Sty);
+    match (op, mode) {
+        (Op::Brk, _) => 7,
+        (Op::Jsr | Op::Rts | Op::Rti, _) => 6,
+        (Op::Pha | Op::Php, _) => 3,
+        (Op::Pla | Op::Plp, _) => 4,
+        (Op::Jmp, Mode::Absolute) => 3,
+        (Op::Jmp, _) => 5,
+        (_, Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative) => 2,
+        (_, Mode::ZeroPage) => 3 + 2 * modify as u64,
+        (_, Mode::ZeroPageX | Mode::ZeroPageY | Mode::Absolute) => 4 + 2 * modify as u64,
+        (_, Mode::AbsoluteX | Mode::AbsoluteY) => 4 + write as u64 + 3 * modify as u64,
+        (_, Mode::IndirectX) => 6,
+        (_, Mode::IndirectY) => 5 + write as u64,
+        (_, Mode::Indirect) => 5,
+    }
+}
@@ -0,0 +1,117 @@
+//! `memory::serialize` for the registers of the CPU, behind the `serialize` feature.
+
+use crate::{Registers, Registers65816, StatusFlags, Variant};
+use memory::serialize::{Deserialize, Serialize};
+
+impl Serialize for StatusFlags {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        self.bits().serialize(out);
+    }
+}
+
+impl Deserialize for StatusFlags {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(StatusFlags::from_bits(u8::deserialize(input)?))
+    }
+}
+
+/// A, X, Y, SP, PC and the status, in that order.
+impl Serialize for Registers {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        self.a.serialize(out);
+        self.x.serialize(out);
+        self.y.serialize(out);
+        self.sp.serialize(out);
+        self.pc.serialize(out);
+        self.status.serialize(out);
+    }
+}
+
+impl Deserialize for Registers {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(Registers {
+            a: u8::deserialize(input)?,
+            x: u8::deserialize(input)?,
+            y: u8::deserialize(input)?,
+            sp: u8::deserialize(input)?,
+            pc: u16::deserialize(input)?,
+            status: StatusFlags::deserialize(input)?,
+        })
+    }
+}
+
+/// B, DBR, PBR, D and the emulation flag, in that order.
+impl Serialize for Registers65816 {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        self.b.serialize(out);
+        self.dbr.serialize(out);
+        self.pbr.serialize(out);
+        self.d.serialize(out);
+        self.emulation.serialize(out);
+    }
+}
+
+impl Deserialize for Registers65816 {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        Ok(Registers65816 {
+            b: u8::deserialize(input)?,
+            dbr: u8::deserialize(input)?,
+            pbr: u8::deserialize(input)?,
+            d: u16::deserialize(input)?,
+            emulation: bool::deserialize(input)?,
+        })
+    }
+}
+
+/// A byte, `0` for the NMOS 6502, `1` for the 65C02 and `2` for the 65816.
+impl Serialize for Variant {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Variant::Nmos => 0,
+            Variant::Cmos => 1,
+            Variant::W65c816 => 2,
+        };
+        tag.serialize(out);
+    }
+}
+
+impl Deserialize for Variant {
+    fn deserialize(input: &mut &[u8]) -> Result<Self, String> {
+        match u8::deserialize(input)? {
+            0 => Ok(Variant::Nmos),
+            1 => Ok(Variant::Cmos),
+            2 => Ok(Variant::W65c816),
+            tag => Err(format!("{} is not a CPU variant", tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_read_back() {
+        let registers: Registers = Registers {
+            a: 0x01,
+            x: 0x02,
+            y: 0x03,
+            sp: 0xfd,
+            pc: 0x1234,
+            status: StatusFlags::CARRY | StatusFlags::UNUSED,
+        };
+        let bytes: Vec<u8> = registers.to_bytes();
+        assert_eq!(bytes, [0x01, 0x02, 0x03, 0xfd, 0x34, 0x12, 0x21]);
+        assert_eq!(Registers::from_bytes(&bytes), Ok(registers));
+
+        let mut bytes: Vec<u8> = Registers65816::RESET.to_bytes();
+        Variant::W65c816.serialize(&mut bytes);
+        let mut input: &[u8] = &bytes;
+        assert_eq!(
+            Registers65816::deserialize(&mut input),
+            Ok(Registers65816::RESET)
+        );
+        assert_eq!(Variant::from_bytes(input), Ok(Variant::W65c816));
+        assert!(Variant::from_bytes(&[0x03]).is_err());
+    }
+}
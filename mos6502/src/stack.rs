@@ -0,0 +1,24 @@
+/// Which way the stack pointer crossed the end of page one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackWrapKind {
+    /// A push at `$0100` moved SP to `$ff`.
+    Overflow,
+    /// A pull at `$01ff` moved SP to `$00`.
+    Underflow,
+}
+
+/// SP wrapping around page one, see `Mos6502::set_stack_watch()`. The CPU wraps like
+/// the hardware, this only tells the host it happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackWrap {
+    pub kind: StackWrapKind,
+    /// Where the instruction or interrupt entry that wrapped starts.
+    pub pc: u16,
+    /// The addresses of the `JSR` instructions and interrupted instructions the CPU
+    /// is in, outermost first, as far as the pushes and pulls of return addresses
+    /// tell. Only the last `CALL_TRACE_DEPTH` are kept.
+    pub calls: Vec<u16>,
+}
+
+/// How many calls `StackWrap::calls` keeps at most, more than a balanced stack can hold.
+pub const CALL_TRACE_DEPTH: usize = 128;
@@ -0,0 +1,87 @@
+use crate::Mos6502;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Incremental 64-bit FNV-1a hash of successive CPU states.
+/// Two runs of the same program produce the same digest only if every sampled state matched.
+pub struct StateHash {
+    hash: u64,
+    samples: u64,
+}
+
+impl Default for StateHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateHash {
+    pub fn new() -> Self {
+        StateHash {
+            hash: FNV_OFFSET_BASIS,
+            samples: 0,
+        }
+    }
+
+    /// Folds the current registers of `cpu` into the hash.
+    pub fn update(&mut self, cpu: &Mos6502) {
+        let pc: [u8; 2] = cpu.pc.to_le_bytes();
+        for byte in [cpu.a, cpu.x, cpu.y, cpu.sp, cpu.ps.bits(), pc[0], pc[1]] {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+        self.samples += 1;
+    }
+
+    /// # Returns
+    /// The hash of all states folded in so far.
+    pub fn digest(&self) -> u64 {
+        self.hash
+    }
+
+    /// # Returns
+    /// How many states have been folded in.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcodes::OpCode;
+    use memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn run(value: u8) -> u64 {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        let mut cpu = Mos6502::new(mem);
+        cpu.reset();
+
+        cpu.poke(0x0000, OpCode::LdaI.into());
+        cpu.poke(0x0001, value);
+        cpu.poke(0x0002, OpCode::Tax.into());
+
+        let mut hash = StateHash::new();
+        cpu.step();
+        hash.update(&cpu);
+        cpu.step();
+        hash.update(&cpu);
+
+        assert_eq!(hash.samples(), 2);
+        hash.digest()
+    }
+
+    #[test]
+    fn same_run_same_digest() {
+        assert_eq!(run(0x42), run(0x42));
+    }
+
+    #[test]
+    fn different_run_different_digest() {
+        assert_ne!(run(0x42), run(0x43));
+        assert_ne!(run(0x42), StateHash::new().digest());
+    }
+}
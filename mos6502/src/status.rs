@@ -0,0 +1,181 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+/// The letters of the status bits, bit 7 first, as displayed.
+const FLAG_NAMES: &[u8; 8] = b"NV-BDIZC";
+
+/// The processor status register, see `Mos6502::status()`.
+///
+/// Displayed the classic way, the letter of each set bit, bit 7 first, and `-` for the
+/// clear ones, e.g. `N-----ZC`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StatusFlags(u8);
+
+impl StatusFlags {
+    pub const CARRY: StatusFlags = StatusFlags(0b0000_0001);
+    pub const ZERO: StatusFlags = StatusFlags(0b0000_0010);
+    pub const INTERRUPT_DISABLE: StatusFlags = StatusFlags(0b0000_0100);
+    pub const DECIMAL_MODE: StatusFlags = StatusFlags(0b0000_1000);
+    /// Only exists in the copies of the register pushed by `BRK` and `PHP`.
+    pub const BREAK: StatusFlags = StatusFlags(0b0001_0000);
+    /// Unused, reads as set on real hardware.
+    pub const UNUSED: StatusFlags = StatusFlags(0b0010_0000);
+    pub const OVERFLOW: StatusFlags = StatusFlags(0b0100_0000);
+    pub const NEGATIVE: StatusFlags = StatusFlags(0b1000_0000);
+
+    /// # Returns
+    /// The register with every bit clear.
+    pub const fn empty() -> Self {
+        StatusFlags(0)
+    }
+
+    /// # Returns
+    /// The register holding `bits`, as pushed on the stack.
+    pub const fn from_bits(bits: u8) -> Self {
+        StatusFlags(bits)
+    }
+
+    /// # Returns
+    /// The register as a byte, as pushed on the stack.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// # Returns
+    /// `true` if all the bits of `flags` are set.
+    pub const fn contains(self, flags: StatusFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    /// Sets the bits of `flags` if `value`, clears them otherwise.
+    pub fn set(&mut self, flags: StatusFlags, value: bool) {
+        if value {
+            self.0 |= flags.0;
+        } else {
+            self.0 &= !flags.0;
+        }
+    }
+
+    pub fn carry(self) -> bool {
+        self.contains(Self::CARRY)
+    }
+
+    pub fn set_carry(&mut self, value: bool) {
+        self.set(Self::CARRY, value);
+    }
+
+    pub fn zero(self) -> bool {
+        self.contains(Self::ZERO)
+    }
+
+    pub fn set_zero(&mut self, value: bool) {
+        self.set(Self::ZERO, value);
+    }
+
+    pub fn interrupt_disable(self) -> bool {
+        self.contains(Self::INTERRUPT_DISABLE)
+    }
+
+    pub fn set_interrupt_disable(&mut self, value: bool) {
+        self.set(Self::INTERRUPT_DISABLE, value);
+    }
+
+    pub fn decimal_mode(self) -> bool {
+        self.contains(Self::DECIMAL_MODE)
+    }
+
+    pub fn set_decimal_mode(&mut self, value: bool) {
+        self.set(Self::DECIMAL_MODE, value);
+    }
+
+    /// The B bit, named so as `break` is a keyword.
+    pub fn break_flag(self) -> bool {
+        self.contains(Self::BREAK)
+    }
+
+    pub fn set_break_flag(&mut self, value: bool) {
+        self.set(Self::BREAK, value);
+    }
+
+    pub fn overflow(self) -> bool {
+        self.contains(Self::OVERFLOW)
+    }
+
+    pub fn set_overflow(&mut self, value: bool) {
+        self.set(Self::OVERFLOW, value);
+    }
+
+    pub fn negative(self) -> bool {
+        self.contains(Self::NEGATIVE)
+    }
+
+    pub fn set_negative(&mut self, value: bool) {
+        self.set(Self::NEGATIVE, value);
+    }
+}
+
+impl BitOr for StatusFlags {
+    type Output = StatusFlags;
+
+    fn bitor(self, other: StatusFlags) -> StatusFlags {
+        StatusFlags(self.0 | other.0)
+    }
+}
+
+impl BitAnd for StatusFlags {
+    type Output = StatusFlags;
+
+    fn bitand(self, other: StatusFlags) -> StatusFlags {
+        StatusFlags(self.0 & other.0)
+    }
+}
+
+impl Not for StatusFlags {
+    type Output = StatusFlags;
+
+    fn not(self) -> StatusFlags {
+        StatusFlags(!self.0)
+    }
+}
+
+impl From<u8> for StatusFlags {
+    fn from(bits: u8) -> Self {
+        StatusFlags(bits)
+    }
+}
+
+impl From<StatusFlags> for u8 {
+    fn from(flags: StatusFlags) -> Self {
+        flags.0
+    }
+}
+
+impl fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, &name) in FLAG_NAMES.iter().enumerate() {
+            let letter: char = if self.0 & (0x80 >> index) != 0 {
+                name as char
+            } else {
+                '-'
+            };
+            write!(f, "{}", letter)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sets_and_displays_flags() {
+        let mut status: StatusFlags = StatusFlags::CARRY | StatusFlags::NEGATIVE;
+        assert!(status.carry() && status.negative() && !status.zero());
+        status.set_zero(true);
+        status.set_carry(false);
+        assert_eq!(status.bits(), 0x82);
+        assert_eq!(status.to_string(), "N-----Z-");
+        assert_eq!(StatusFlags::from_bits(0xff).to_string(), "NV-BDIZC");
+    }
+}
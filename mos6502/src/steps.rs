@@ -0,0 +1,83 @@
+use crate::opcodes::OpCode;
+use crate::{Mos6502, StatusFlags};
+
+/// What one step of the CPU did, see `Mos6502::steps()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepInfo {
+    /// Where the step started.
+    pub pc: u16,
+    /// The instruction executed, `None` if the step entered an interrupt or returned
+    /// from a trap.
+    pub op_code: Option<OpCode>,
+    /// Cycles the step took, see `Mos6502::step()`.
+    pub cycles: u32,
+    // The registers once the step is over.
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: StatusFlags,
+    /// PC once the step is over.
+    pub next_pc: u16,
+}
+
+/// Steps the CPU at each `next()`, until it halts, so a run can be driven with the
+/// usual iterator adapters, e.g. `cpu.steps().take(1000).find(|step| step.a == 0)`.
+pub struct Steps<'a> {
+    cpu: &'a mut Mos6502,
+}
+
+impl<'a> Steps<'a> {
+    pub(crate) fn new(cpu: &'a mut Mos6502) -> Self {
+        Steps { cpu }
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<StepInfo> {
+        let pc: u16 = self.cpu.pc;
+        let cycles: u32 = self.cpu.step();
+        if cycles == 0 {
+            return None;
+        }
+        Some(StepInfo {
+            pc,
+            op_code: self.cpu.executed,
+            cycles,
+            a: self.cpu.a,
+            x: self.cpu.x,
+            y: self.cpu.y,
+            sp: self.cpu.sp,
+            status: self.cpu.ps,
+            next_pc: self.cpu.pc,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_until_halted() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).trap_detection(true).build();
+        // LDX #$03, DEX, BNE $0202, JMP $0205
+        let program: [u8; 8] = [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x05, 0x02];
+        cpu.memory().borrow_mut().as_mut_slice()[0x0200..0x0208].copy_from_slice(&program);
+
+        let steps: Vec<StepInfo> = cpu.steps().collect();
+        assert_eq!(steps.len(), 8);
+        assert_eq!(steps[0].op_code, Some(OpCode::LdxI));
+        assert_eq!((steps[0].x, steps[0].next_pc), (0x03, 0x0202));
+        assert_eq!(steps.last().map(|step| step.pc), Some(0x0205));
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.steps().next(), None);
+
+        cpu.set_pc(0x0200);
+        cpu.resume();
+        let zero: Option<StepInfo> = cpu.steps().find(|step| step.x == 0);
+        assert_eq!(zero.map(|step| step.pc), Some(0x0202));
+    }
+}
@@ -0,0 +1,76 @@
+use memory::Access;
+
+/// One cycle of the CPU, see `Mos6502::tick()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tick {
+    /// The read or write made on the bus, `None` for the cycles of an instruction
+    /// without an access in this core.
+    pub access: Option<Access>,
+    /// The opcode of an instruction is fetched, like the SYNC pin.
+    pub sync: bool,
+}
+
+/// How many ticks the `cycles` of a step take, handing out its `accesses` in order:
+/// one per cycle, then internal cycles. Device transfers can make more accesses than
+/// there are cycles, which each get a tick.
+///
+/// # Arguments
+///
+/// * `sync` - The step executed an instruction, whose opcode fetch is the first access.
+pub(crate) fn split(cycles: u32, accesses: Vec<Access>, sync: bool) -> Vec<Tick> {
+    let count: usize = (cycles as usize).max(accesses.len());
+    let mut accesses = accesses.into_iter();
+    (0..count)
+        .map(|cycle| Tick {
+            access: accesses.next(),
+            sync: sync && cycle == 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Mos6502;
+
+    use super::*;
+
+    #[test]
+    fn ticks_make_one_access_each() {
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        // LDA $0300, STA $0301, INX
+        cpu.load(0x0200, &[0xad, 0x00, 0x03, 0x8d, 0x01, 0x03, 0xe8]);
+        cpu.load(0x0300, &[0x2a]);
+        let read = |address: u16, value: u8| Access {
+            address,
+            value,
+            write: false,
+        };
+
+        let ticks: Vec<Tick> = (0..10).filter_map(|_| cpu.tick()).collect();
+        let accesses: Vec<Option<Access>> = ticks.iter().map(|tick| tick.access).collect();
+        assert_eq!(
+            accesses,
+            [
+                Some(read(0x0200, 0xad)),
+                Some(read(0x0201, 0x00)),
+                Some(read(0x0202, 0x03)),
+                Some(read(0x0300, 0x2a)),
+                Some(read(0x0203, 0x8d)),
+                Some(read(0x0204, 0x01)),
+                Some(read(0x0205, 0x03)),
+                Some(Access {
+                    address: 0x0301,
+                    value: 0x2a,
+                    write: true,
+                }),
+                Some(read(0x0206, 0xe8)),
+                None,
+            ]
+        );
+        let syncs: Vec<usize> = (0..ticks.len())
+            .filter(|&index| ticks[index].sync)
+            .collect();
+        assert_eq!(syncs, [0, 4, 8]);
+        assert_eq!((cpu.x(), cpu.cycles()), (0x01, 10));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::StatusFlags;
+
+use memory::Access;
+
+use std::fmt::Write;
+
+/// An executed instruction, see `Mos6502::set_tracer()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Where the instruction starts.
+    pub pc: u16,
+    /// The opcode and its operand.
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    // The registers before the instruction ran.
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub sp: u8,
+    pub status: StatusFlags,
+    /// The cycle count of the CPU when the instruction started.
+    pub cycles: u64,
+    /// Cycles the instruction took, not counting stalls on RDY.
+    pub duration: u32,
+    /// The reads and writes of the instruction, fetches included, in order.
+    pub accesses: Vec<Access>,
+}
+
+impl TraceRecord {
+    /// # Returns
+    /// The record as one line of JSON, without the newline, so a trace can be written
+    /// as newline-delimited JSON. Numbers are plain integers.
+    pub fn to_json(&self) -> String {
+        let mut json: String = String::new();
+        let bytes: Vec<String> = self.bytes.iter().map(u8::to_string).collect();
+        // Writing to a String cannot fail
+        let _ = write!(
+            json,
+            "{{\"pc\":{},\"bytes\":[{}],\"mnemonic\":\"{}\",\"a\":{},\"x\":{},\"y\":{},\"sp\":{},\"status\":{},\"flags\":\"{}\",\"cycles\":{},\"duration\":{},\"accesses\":[",
+            self.pc,
+            bytes.join(","),
+            self.mnemonic,
+            self.a,
+            self.x,
+            self.y,
+            self.sp,
+            self.status.bits(),
+            self.status,
+            self.cycles,
+            self.duration
+        );
+        for (index, access) in self.accesses.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"address\":{},\"value\":{},\"kind\":\"{}\"}}",
+                if index == 0 { "" } else { "," },
+                access.address,
+                access.value,
+                if access.write { "write" } else { "read" }
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Mos6502;
+
+    use memory::Memory;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn traces_instructions_with_accesses() {
+        let mem: Rc<RefCell<Memory>> = Rc::new(RefCell::new(Memory::new()));
+        // LDA #$80, STA $10
+        mem.borrow_mut().as_mut_slice()[..4].copy_from_slice(&[0xa9, 0x80, 0x85, 0x10]);
+        let mut cpu: Mos6502 = Mos6502::new(mem);
+        let records: Rc<RefCell<Vec<TraceRecord>>> = Rc::new(RefCell::new(Vec::new()));
+        let sink: Rc<RefCell<Vec<TraceRecord>>> = records.clone();
+        cpu.set_tracer(move |record: &TraceRecord| sink.borrow_mut().push(record.clone()));
+        cpu.step();
+        cpu.step();
+
+        let records = records.borrow();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].bytes, [0x85, 0x10]);
+        assert_eq!(
+            (records[1].a, records[1].status),
+            (0x80, StatusFlags::NEGATIVE)
+        );
+        assert_eq!(records[1].cycles, 2);
+        assert_eq!(
+            records[1].to_json(),
+            "{\"pc\":2,\"bytes\":[133,16],\"mnemonic\":\"STA\",\"a\":128,\"x\":0,\"y\":0,\"sp\":0,\"status\":128,\"flags\":\"N-------\",\"cycles\":2,\"duration\":3,\"accesses\":[{\"address\":2,\"value\":133,\"kind\":\"read\"},{\"address\":3,\"value\":16,\"kind\":\"read\"},{\"address\":16,\"value\":128,\"kind\":\"write\"}]}"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! The 65816 in emulation mode, see `Variant::W65c816`.
+//!
+//! The opcodes the base table lacks are executed here for that variant only: the ones
+//! of the 65816, that move its extra registers and reach the `COP` vector, and the
+//! common ones of the 65C02. Like undocumented opcodes on the other variants, the
+//! others jam for now: long addressing, block moves, the stack relative modes, `WAI`
+//! and `STP`. Switching to native mode with `XCE` jams too, so `E` stays set and the
+//! registers 8-bit until native mode is emulated.
+//!
+//! These instructions are not given to tracers and hooks, and the disassembler shows
+//! them as bytes. Direct page addressing uses page zero whatever `D` holds.
+
+use crate::logging::{Category, LogLevel};
+use crate::{HaltReason, Mos6502, StatusFlags};
+
+/// Where the 65816 finds the handler of `COP` in emulation mode.
+pub const COP_VECTOR: u16 = 0xfff4;
+
+/// The flags `REP` and `SEP` cannot change in emulation mode, where they stand for
+/// `B` and the unused bit instead of the register widths `X` and `M`.
+const WIDTH_FLAGS: u8 = 0b0011_0000;
+
+/// The registers the 65816 adds, see `Mos6502::registers_65816()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Registers65816 {
+    /// The high byte of the accumulator, swapped with A by `XBA`.
+    pub b: u8,
+    /// The data bank, pushed by `PHB` and pulled by `PLB`.
+    pub dbr: u8,
+    /// The program bank, pushed by `PHK`, zero on the 16-bit address bus.
+    pub pbr: u8,
+    /// The direct page, moved by `TCD`, `TDC`, `PHD` and `PLD`.
+    pub d: u16,
+    /// The emulation flag, swapped with carry by `XCE`.
+    pub emulation: bool,
+}
+
+impl Registers65816 {
+    /// The registers after a reset, in emulation mode.
+    pub const RESET: Registers65816 = Registers65816 {
+        b: 0x00,
+        dbr: 0x00,
+        pbr: 0x00,
+        d: 0x0000,
+        emulation: true,
+    };
+}
+
+/// Executes `op_code` as the 65816 does in emulation mode, its opcode fetched.
+///
+/// # Returns
+/// The cycles it takes before a branch is taken, `None` if `op_code` is not executed
+/// here, see the module documentation.
+pub(crate) fn execute(cpu: &mut Mos6502, op_code: u8) -> Option<u8> {
+    let cycles: u8 = match op_code {
+        // XBA
+        0xeb => {
+            std::mem::swap(&mut cpu.a, &mut cpu.registers_65816.b);
+            cpu.update_zero_flag(cpu.a);
+            cpu.update_negative_flag(cpu.a);
+            3
+        }
+        // XCE
+        0xfb => {
+            if !cpu.ps.carry() {
+                cpu.log(Category::Exec, LogLevel::Info, || {
+                    "Native mode is not emulated yet".to_string()
+                });
+                return None;
+            }
+            2
+        }
+        // PHB
+        0x8b => {
+            cpu.stack_push(cpu.registers_65816.dbr);
+            3
+        }
+        // PLB
+        0xab => {
+            cpu.registers_65816.dbr = cpu.stack_pop();
+            cpu.update_zero_flag(cpu.registers_65816.dbr);
+            cpu.update_negative_flag(cpu.registers_65816.dbr);
+            4
+        }
+        // PHK
+        0x4b => {
+            cpu.stack_push(cpu.registers_65816.pbr);
+            3
+        }
+        // PHD
+        0x0b => {
+            let d: u16 = cpu.registers_65816.d;
+            cpu.stack_push((d >> 8) as u8);
+            cpu.stack_push(d as u8);
+            4
+        }
+        // PLD
+        0x2b => {
+            let low_byte: u8 = cpu.stack_pop();
+            let high_byte: u8 = cpu.stack_pop();
+            cpu.registers_65816.d = u16::from_le_bytes([low_byte, high_byte]);
+            update_word_flags(cpu, cpu.registers_65816.d);
+            5
+        }
+        // TCD
+        0x5b => {
+            cpu.registers_65816.d = u16::from_le_bytes([cpu.a, cpu.registers_65816.b]);
+            update_word_flags(cpu, cpu.registers_65816.d);
+            2
+        }
+        // TDC
+        0x7b => {
+            let [low_byte, high_byte]: [u8; 2] = cpu.registers_65816.d.to_le_bytes();
+            (cpu.a, cpu.registers_65816.b) = (low_byte, high_byte);
+            update_word_flags(cpu, cpu.registers_65816.d);
+            2
+        }
+        // TCS, the stack stays in page one
+        0x1b => {
+            cpu.sp = cpu.a;
+            2
+        }
+        // TSC
+        0x3b => {
+            (cpu.a, cpu.registers_65816.b) = (cpu.sp, 0x01);
+            update_word_flags(cpu, 0x0100 | cpu.sp as u16);
+            2
+        }
+        // REP
+        0xc2 => {
+            let flags: u8 = cpu.fetch() & !WIDTH_FLAGS;
+            cpu.ps = StatusFlags::from_bits(cpu.ps.bits() & !flags);
+            3
+        }
+        // SEP
+        0xe2 => {
+            let flags: u8 = cpu.fetch() & !WIDTH_FLAGS;
+            cpu.ps = StatusFlags::from_bits(cpu.ps.bits() | flags);
+            3
+        }
+        // WDM, reserved for extensions, skips its operand
+        0x42 => {
+            cpu.fetch();
+            2
+        }
+        // COP, like BRK with its own vector
+        0x02 => {
+            let return_address: u16 = cpu.pc.wrapping_add(1);
+            cpu.stack_push((return_address >> 8) as u8);
+            cpu.stack_push(return_address as u8);
+            cpu.stack_push(cpu.pushed_status().bits());
+            cpu.mask_interrupts();
+            cpu.enter_call(cpu.instruction_pc);
+            cpu.pc = cpu.peek_word(COP_VECTOR);
+            7
+        }
+        // BRA
+        0x80 => {
+            cpu.branch(true);
+            if cpu.trap_detection && cpu.pc == cpu.instruction_pc {
+                cpu.halt(HaltReason::Trap);
+                cpu.trap_pc = Some(cpu.instruction_pc);
+            }
+            2
+        }
+        // PHX, PHY
+        0xda => {
+            cpu.stack_push(cpu.x);
+            3
+        }
+        0x5a => {
+            cpu.stack_push(cpu.y);
+            3
+        }
+        // PLX, PLY
+        0xfa => {
+            cpu.x = cpu.stack_pop();
+            cpu.update_zero_flag(cpu.x);
+            cpu.update_negative_flag(cpu.x);
+            4
+        }
+        0x7a => {
+            cpu.y = cpu.stack_pop();
+            cpu.update_zero_flag(cpu.y);
+            cpu.update_negative_flag(cpu.y);
+            4
+        }
+        // TXY, TYX
+        0x9b => {
+            cpu.y = cpu.x;
+            cpu.update_zero_flag(cpu.y);
+            cpu.update_negative_flag(cpu.y);
+            2
+        }
+        0xbb => {
+            cpu.x = cpu.y;
+            cpu.update_zero_flag(cpu.x);
+            cpu.update_negative_flag(cpu.x);
+            2
+        }
+        // INC A, DEC A
+        0x1a => {
+            cpu.a = cpu.a.wrapping_add(0x01);
+            cpu.update_zero_flag(cpu.a);
+            cpu.update_negative_flag(cpu.a);
+            2
+        }
+        0x3a => {
+            cpu.a = cpu.a.wrapping_sub(0x01);
+            cpu.update_zero_flag(cpu.a);
+            cpu.update_negative_flag(cpu.a);
+            2
+        }
+        // STZ zp, zp,X, abs, abs,X
+        0x64 => {
+            let address: u8 = cpu.fetch();
+            cpu.mem.borrow_mut().write(address as u16, 0x00);
+            3
+        }
+        0x74 => {
+            let address: u8 = cpu.fetch();
+            let address: u8 = cpu.indexed_zero_page(address, cpu.x);
+            cpu.mem.borrow_mut().write(address as u16, 0x00);
+            4
+        }
+        0x9c => {
+            let address: u16 = cpu.fetch_word();
+            cpu.mem.borrow_mut().write(address, 0x00);
+            4
+        }
+        0x9e => {
+            let address: u16 = cpu.fetch_word();
+            let address: u16 = cpu.indexed_write(address, cpu.x);
+            cpu.mem.borrow_mut().write(address, 0x00);
+            5
+        }
+        _ => return None,
+    };
+    Some(cycles)
+}
+
+/// Sets N and Z from the 16-bit `value` moved by `PLD`, `TCD`, `TDC` or `TSC`.
+fn update_word_flags(cpu: &mut Mos6502, value: u16) {
+    cpu.ps.set_zero(value == 0x0000);
+    cpu.ps.set_negative(value & 0x8000 != 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Variant;
+
+    fn cpu_with(program: &[u8]) -> Mos6502 {
+        let mut cpu: Mos6502 = Mos6502::builder()
+            .pc(0x0200)
+            .variant(Variant::W65c816)
+            .build();
+        cpu.set_sp(0xff);
+        cpu.load(0x0200, program);
+        cpu
+    }
+
+    #[test]
+    fn moves_the_extra_registers() {
+        // LDA #$12, XBA, LDA #$34, TCD, PHD, PHB, PLB, PLA, TDC, XBA
+        let mut cpu: Mos6502 = cpu_with(&[
+            0xa9, 0x12, 0xeb, 0xa9, 0x34, 0x5b, 0x0b, 0x8b, 0xab, 0x68, 0x7b, 0xeb,
+        ]);
+        for _ in 0..6 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers_65816().d, 0x1234);
+        assert_eq!(cpu.peek_word(0x01fe), 0x1234);
+
+        assert_eq!(
+            cpu.steps().take(4).map(|step| step.cycles).sum::<u32>(),
+            4 + 4 + 2 + 3
+        );
+        assert_eq!((cpu.a(), cpu.registers_65816().b), (0x12, 0x34));
+    }
+
+    #[test]
+    fn keeps_emulation_mode() {
+        // SEP #$FF, REP #$FF, then SEC, XCE, CLC, XCE
+        let mut cpu: Mos6502 = cpu_with(&[0xe2, 0xff, 0xc2, 0xff, 0x38, 0xfb, 0x18, 0xfb]);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.ps.bits() & !WIDTH_FLAGS, 0x00);
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::Jam));
+        assert_eq!((cpu.pc(), cpu.registers_65816().emulation), (0x0207, true));
+    }
+
+    #[test]
+    fn enters_the_cop_handler() {
+        // COP #$01
+        let mut cpu: Mos6502 = cpu_with(&[0x02, 0x01]);
+        cpu.load(COP_VECTOR, &[0x00, 0x80]);
+        assert_eq!(cpu.step(), 7);
+        assert_eq!((cpu.pc(), cpu.peek_word(0x01fe)), (0x8000, 0x0202));
+
+        let mut cpu: Mos6502 = Mos6502::builder().pc(0x0200).build();
+        cpu.load(0x0200, &[0x02, 0x01]);
+        cpu.step();
+        assert_eq!(cpu.halt_reason(), Some(HaltReason::Jam));
+    }
+}
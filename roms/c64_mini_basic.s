@@ -0,0 +1,187 @@
+; A miniature BASIC for `c64_mini_kernal.s`, tested in machine/src/preset.rs.
+;
+; It prints READY. and reads lines typed on the keyboard. A line starting with a
+; digit is the program, which holds one statement: PRINT with two numbers added or
+; multiplied, e.g. `10 PRINT 6*7`. RUN runs it, printing the result with a space in
+; front as BASIC does. Commands are dispatched by pushing the address of their
+; handler less 1 and returning to it, as the real interpreter does.
+
+chrout  = $ffd2
+getin   = $ffe4
+length  = $fb           ; the length of the line typed
+value   = $fc
+left    = $fd
+right   = $fe
+printed = $ff           ; a digit of the result was printed
+input   = $0200         ; the line typed
+program = $0300         ; the statement of the program, zero-terminated
+
+        .org $a000
+        .word cold      ; the cold start vector, and the warm start one
+        .word ready
+
+cold:   lda #$00
+        sta program
+ready:  ldx #$00
+rmsg:   lda readym,x
+        beq lines
+        jsr chrout
+        inx
+        bne rmsg
+
+lines:  lda #$00
+        sta length
+wait:   jsr getin
+        beq wait
+        jsr chrout      ; echoes it, the return too
+        cmp #$0d
+        beq enter
+        ldx length
+        sta input,x
+        inc length
+        jmp wait
+
+enter:  ldx length
+        lda #$00
+        sta input,x
+        ldx #$00        ; the program line
+        lda input
+        cmp #'0'
+        bcc other
+        cmp #'9'+1
+        bcc dispatch
+other:  ldx #$01        ; RUN
+        ldy #$00
+cmd:    lda runcmd,y
+        beq dispatch
+        cmp input,y
+        bne error
+        iny
+        bne cmd
+dispatch:
+        lda high,x
+        pha
+        lda low,x
+        pha
+        rts
+
+low:    .byte <(store-1), <(run-1)
+high:   .byte >(store-1), >(run-1)
+
+error:  ldx #$00
+emsg:   lda errorm,x
+        beq ready
+        jsr chrout
+        inx
+        bne emsg
+
+; Keeps the line typed after its number as the program.
+store:  ldx #$00
+snum:   lda input,x
+        cmp #'9'+1      ; digits and spaces
+        bcs scopy
+        inx
+        bne snum
+scopy:  ldy #$00
+sbyte:  lda input,x
+        sta program,y
+        beq lines
+        inx
+        iny
+        bne sbyte
+
+run:    ldx #$00
+rword:  lda printw,x
+        beq rleft
+        cmp program,x
+        bne error
+        inx
+        bne rword
+rleft:  jsr number
+        sta left
+        lda program,x
+        inx
+        pha
+        jsr number
+        sta right
+        pla
+        cmp #'+'
+        beq add
+        cmp #'*'
+        bne error
+
+        lda #$00        ; left * right, a bit of left at a time
+        ldy #$08
+mul:    asl a
+        asl left
+        bcc mnext
+        clc
+        adc right
+mnext:  dey
+        bne mul
+        jmp result
+add:    lda left
+        clc
+        adc right
+
+result: sta value
+        lda #$20        ; space
+        jsr chrout
+        lda #$00
+        sta printed
+        ldx #$00
+place:  ldy #'0'-1
+        lda value
+        sec
+psub:   iny
+        sbc powers,x
+        bcs psub
+        adc powers,x    ; the carry is clear
+        sta value
+        tya
+        cmp #'0'
+        bne pshow
+        ldy printed
+        bne pshow
+        cpx #$02        ; the ones are always printed
+        bne pnext
+pshow:  sta printed
+        jsr chrout
+pnext:  inx
+        cpx #$03
+        bne place
+        lda #$0d
+        jsr chrout
+        jmp ready
+
+; Reads the decimal number at X in the program into A, moving X after it.
+number: lda #$00
+        sta value
+ndigit: lda program,x
+        sec
+        sbc #'0'
+        cmp #$0a
+        bcs ndone
+        pha
+        lda value       ; value * 10, as value * 8 + value * 2
+        asl a
+        sta right
+        asl a
+        asl a
+        clc
+        adc right
+        sta value
+        pla
+        clc
+        adc value
+        sta value
+        inx
+        bne ndigit
+ndone:  lda value
+        rts
+
+powers: .byte 100, 10, 1
+readym: .byte $0d, "READY.", $0d, $00
+errorm: .byte $0d, "?SYNTAX  ERROR", $00
+runcmd: .byte "RUN", $00
+printw: .byte "PRINT ", $00
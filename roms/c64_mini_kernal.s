@@ -0,0 +1,185 @@
+; A miniature KERNAL for the c64-lite preset, tested in machine/src/preset.rs with
+; `c64_mini_basic.s`, as the real ROMs cannot be shipped.
+;
+; At reset it sets up the I/O port of the 6510, clears the RAM below the BASIC ROM
+; with BASIC banked out, clears the screen and starts the timer A interrupt of CIA1,
+; which scans the keyboard matrix 60 times a second into the keyboard buffer. Then
+; it starts BASIC through the cold start vector at $A000. CHROUT at $FFD2 prints on
+; the screen, without scrolling, and GETIN at $FFE4 takes a key from the buffer.
+
+screen  = $0400
+cia1    = $dc00
+keys    = $c6           ; how many keys are in the buffer
+last    = $cb           ; the key held at the last scan, $40 for none
+line    = $d1           ; 2 bytes, the start of the cursor line on the screen
+column  = $d3           ; the column of the cursor
+buffer  = $0277         ; 10 bytes
+
+        .org $e000
+reset:  sei
+        cld
+        ldx #$ff
+        txs
+        lda #$36        ; BASIC out, to clear the RAM below it
+        sta $01
+        lda #$2f        ; the direction of the port, once its output is set
+        sta $00
+        lda #$00
+        sta line
+        lda #$a0
+        sta line+1
+        ldx #$20        ; pages
+        ldy #$00
+        tya
+ram:    sta (line),y
+        iny
+        bne ram
+        inc line+1
+        dex
+        bne ram
+        lda #$37        ; BASIC and the KERNAL in
+        sta $01
+
+        lda #$20        ; space
+        ldx #$00
+clear:  sta screen,x
+        sta screen+$0100,x
+        sta screen+$0200,x
+        sta screen+$02e8,x
+        inx
+        bne clear
+        lda #<screen
+        sta line
+        lda #>screen
+        sta line+1
+        lda #$00
+        sta column
+        sta keys
+        lda #$40
+        sta last
+
+        lda #$ff        ; port A drives the columns, port B reads the rows
+        sta cia1+$02
+        lda #$00
+        sta cia1+$03
+        lda #<16421     ; 1/60 s
+        sta cia1+$04
+        lda #>16421
+        sta cia1+$05
+        lda #$81        ; timer A interrupts
+        sta cia1+$0d
+        lda #$11        ; load and start
+        sta cia1+$0e
+        cli
+        jmp ($a000)
+
+irq:    pha
+        txa
+        pha
+        tya
+        pha
+        lda cia1+$0d    ; acknowledges the interrupt
+        jsr scan
+        pla
+        tay
+        pla
+        tax
+        pla
+nmi:    rti
+
+; Puts the first key pressed in the buffer, once, when it goes down. Y counts the
+; keys, 8 per column.
+scan:   ldy #$00
+        lda #$fe
+scol:   sta cia1
+        pha
+        lda cia1+$01
+        ldx #$08
+srow:   lsr a
+        bcc found
+        iny
+        dex
+        bne srow
+        pla
+        sec
+        rol a
+        bcs scol
+        lda #$40
+        sta last
+        rts
+found:  pla
+        cpy last
+        beq sdone
+        sty last
+        lda layout,y
+        beq sdone       ; the modifiers type nothing
+        ldx keys
+        cpx #10
+        bcs sdone
+        sta buffer,x
+        inc keys
+sdone:  rts
+
+; The PETSCII code of each key, by column then row.
+layout: .byte $14, $0d, $1d, $88, $85, $86, $87, $11
+        .byte "3WA4ZSE", $00
+        .byte "5RD6CFTX"
+        .byte "7YG8BHUV"
+        .byte "9IJ0MKON"
+        .byte "+PL-.:@,"
+        .byte $5c, "*", $3b, $13, $00, "=", $5e, "/"
+        .byte "1", $5f, $00, "2 ", $00, "Q", $03
+
+; Prints the character in A, keeping the registers.
+chrout: pha
+        txa
+        pha
+        tya
+        pha
+        tsx
+        lda $0103,x
+        cmp #$0d
+        beq return
+        and #$3f        ; the screen code, in the uppercase set
+        ldy column
+        sta (line),y
+        inc column
+        bne cdone
+return: lda #$00
+        sta column
+        lda line
+        clc
+        adc #40
+        sta line
+        bcc cdone
+        inc line+1
+cdone:  pla
+        tay
+        pla
+        tax
+        pla
+        rts
+
+; Takes the first key of the buffer into A, 0 if there is none. X is lost.
+getin:  lda keys
+        beq gdone
+        sei
+        lda buffer
+        pha
+        ldx #$00
+gshift: lda buffer+1,x
+        sta buffer,x
+        inx
+        cpx keys
+        bne gshift
+        dec keys
+        pla
+        cli
+gdone:  rts
+
+        .org $ffd2
+        jmp chrout
+        .org $ffe4
+        jmp getin
+        .org $fffa
+        .word nmi, reset, irq
@@ -0,0 +1,254 @@
+; Verify decimal mode behavior
+; Written by Bruce Clark.  This code is public domain.
+; From "Decimal Mode", http://www.6502.org/tutorials/decimal_mode.html, Appendix B
+;
+; Every ADC and SBC of two bytes, with the carry clear and set, is run in decimal
+; mode, and the accumulator and the N, V, Z and C flags compared with what the chip
+; is documented to give, those of the NMOS 6502 if `chip` is 0, of the 65C02 if it
+; is 1. `error` holds 0 once the test passed, 1 if it failed, in which case N1, N2
+; and Y, the carry, tell which operation it failed on. It ends at `done`, jumping to
+; itself.
+;
+; Adapted to this assembler: the byte `chip` picks the routines of the predictions
+; instead of assembling them in, and the test is called from `start`.
+
+chip    = $0f
+error   = $10
+ar      = $11   ; predicted accumulator result
+cf      = $12   ; predicted carry
+da      = $13   ; actual accumulator result in decimal mode
+dnvzc   = $14   ; actual flags in decimal mode
+ha      = $15   ; accumulator result in binary mode
+hnvzc   = $16   ; flags in binary mode
+n1      = $17
+n1h     = $18
+n1l     = $19
+n2      = $1a
+n2l     = $1b
+nf      = $1c   ; predicted N
+vf      = $1d   ; predicted V
+zf      = $1e   ; predicted Z
+n2h     = $1f   ; 2 bytes
+
+        .org $0200
+start:  jsr test
+done:   jmp done
+
+test:   ldy #1          ; initialize Y (used to loop through carry flag values)
+        sty error       ; store 1 in error until the test passes
+        lda #0          ; initialize N1 and N2
+        sta n1
+        sta n2
+loop1:  lda n2          ; N2L = N2 & $0F
+        and #$0f
+        sta n2l
+        lda n2          ; N2H = N2 & $F0
+        and #$f0
+        sta n2h
+        ora #$0f        ; N2H+1 = (N2 & $F0) + $0F
+        sta n2h+1
+loop2:  lda n1          ; N1L = N1 & $0F
+        and #$0f
+        sta n1l
+        lda n1          ; N1H = N1 & $F0
+        and #$f0
+        sta n1h
+        jsr add
+        jsr predict_add
+        jsr compare
+        bne exit
+        jsr sub
+        jsr predict_sub
+        jsr compare
+        bne exit
+        inc n1
+        bne loop2       ; loop through all 256 values of N1
+        inc n2
+        bne loop1       ; loop through all 256 values of N2
+        dey
+        bpl loop1       ; loop through both values of the carry flag
+        lda #0          ; test passed, so store 0 in error
+        sta error
+exit:   rts
+
+; Calculate the actual decimal mode accumulator and flags, the accumulator
+; and flag results when N1 is added to N2 using binary arithmetic, the
+; predicted accumulator result, the predicted carry flag, and the predicted
+; V flag
+add:    sed             ; decimal mode
+        cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1
+        adc n2
+        sta da          ; actual accumulator result in decimal mode
+        php
+        pla
+        sta dnvzc       ; actual flags result in decimal mode
+        cld             ; binary mode
+        cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1
+        adc n2
+        sta ha          ; accumulator result of N1+N2 using binary arithmetic
+        php
+        pla
+        sta hnvzc       ; flags result of N1+N2 using binary arithmetic
+        cpy #1
+        lda n1l
+        adc n2l
+        cmp #$0a
+        ldx #0
+        bcc a1
+        inx
+        adc #5          ; add 6 (carry is set)
+        and #$0f
+        sec
+a1:     ora n1h
+; if N1L + N2L <  $0A, then add N2 & $F0
+; if N1L + N2L >= $0A, then add (N2 & $F0) + $0F + 1 (carry is set)
+        adc n2h,x
+        php
+        bcs a2
+        cmp #$a0
+        bcc a3
+a2:     adc #$5f        ; add $60 (carry is set)
+        sec
+a3:     sta ar          ; predicted accumulator result
+        php
+        pla
+        sta cf          ; predicted carry result
+        pla
+; note that all 8 bits of the P register are stored in VF
+        sta vf          ; predicted V flags
+        rts
+
+; Calculate the actual decimal mode accumulator and flags, and the
+; accumulator and flag results when N2 is subtracted from N1 using binary
+; arithmetic
+sub:    sed             ; decimal mode
+        cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1
+        sbc n2
+        sta da          ; actual accumulator result in decimal mode
+        php
+        pla
+        sta dnvzc       ; actual flags result in decimal mode
+        cld             ; binary mode
+        cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1
+        sbc n2
+        sta ha          ; accumulator result of N1-N2 using binary arithmetic
+        php
+        pla
+        sta hnvzc       ; flags result of N1-N2 using binary arithmetic
+        rts
+
+; Calculate the predicted SBC accumulator result for the 6502
+sub1:   cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1l
+        sbc n2l
+        ldx #0
+        bcs s11
+        inx
+        sbc #5          ; subtract 6 (carry is clear)
+        and #$0f
+        clc
+s11:    ora n1h
+; if N1L - N2L >= 0, then subtract N2 & $F0
+; if N1L - N2L <  0, then subtract (N2 & $F0) + $0F + 1 (carry is clear)
+        sbc n2h,x
+        bcs s12
+        sbc #$5f        ; subtract $60 (carry is clear)
+s12:    sta ar
+        rts
+
+; Calculate the predicted SBC accumulator result for the 65C02
+sub2:   cpy #1          ; set carry if Y = 1, clear carry if Y = 0
+        lda n1l
+        sbc n2l
+        ldx #0
+        bcs s21
+        inx
+        and #$0f
+        clc
+s21:    ora n1h
+; if N1L - N2L >= 0, then subtract N2 & $F0
+; if N1L - N2L <  0, then subtract (N2 & $F0) + $0F + 1 (carry is clear)
+        sbc n2h,x
+        bcs s22
+        sbc #$5f        ; subtract $60 (carry is clear)
+s22:    cpx #0
+        beq s23
+        sbc #6
+s23:    sta ar          ; predicted accumulator result
+        rts
+
+; Compare accumulator actual results to predicted results
+;
+; Return:
+;   Z flag = 1 (BEQ branch) if same
+;   Z flag = 0 (BNE branch) if different
+compare:
+        lda da
+        cmp ar
+        bne c1
+        lda dnvzc
+        eor nf
+        and #$80        ; mask off N flag
+        bne c1
+        lda dnvzc
+        eor vf
+        and #$40        ; mask off V flag
+        bne c1
+        lda dnvzc
+        eor zf          ; mask off Z flag
+        and #2
+        bne c1
+        lda dnvzc
+        eor cf
+        and #1          ; mask off C flag
+c1:     rts
+
+; The predictions of the chip `chip` selects
+predict_add:
+        lda chip
+        bne a65c02
+        beq a6502
+predict_sub:
+        lda chip
+        bne s65c02
+        beq s6502
+
+; These routines store the predicted values for ADC and SBC for the 6502
+; and 65C02 in AR, CF, NF, VF, and ZF
+a6502:  lda vf
+; since all 8 bits of the P register were stored in VF, bit 7 of VF contains
+; the N flag for NF
+        sta nf
+        lda hnvzc
+        sta zf
+        rts
+
+s6502:  jsr sub1
+        lda hnvzc
+        sta nf
+        sta vf
+        sta zf
+        sta cf
+        rts
+
+a65c02: lda ar
+        php
+        pla
+        sta nf
+        sta zf
+        rts
+
+s65c02: jsr sub2
+        lda ar
+        php
+        pla
+        sta nf
+        sta zf
+        lda hnvzc
+        sta vf
+        sta cf
+        rts
@@ -0,0 +1,32 @@
+; The 16-bit Fibonacci numbers below $ffff, stored from $0300
+        .org $0400
+        ldx #$00
+        lda #$01
+        sta $10
+        lda #$00
+        sta $11
+        sta $12
+        sta $13
+loop:   lda $10
+        sta $0300,x
+        lda $11
+        sta $0301,x
+        clc
+        lda $10
+        adc $12
+        tay
+        lda $11
+        adc $13
+        bcs done
+        pha
+        lda $10
+        sta $12
+        lda $11
+        sta $13
+        sty $10
+        pla
+        sta $11
+        inx
+        inx
+        bne loop
+done:   jmp done
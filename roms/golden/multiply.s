@@ -0,0 +1,42 @@
+; 8 by 8 bit shift-and-add multiplication of every pair in a table, through a
+; subroutine, products stored from $0300
+        .org $0400
+        ldx #$00
+        ldy #$00
+loop:   lda factors,x
+        sta $20
+        lda factors+1,x
+        sta $21
+        jsr multiply
+        lda $22
+        sta $0300,y
+        lda $23
+        sta $0301,y
+        iny
+        iny
+        inx
+        inx
+        cpx #16
+        bne loop
+done:   jmp done
+
+; $23:$22 = $20 * $21, $21 is lost
+multiply:
+        stx $24
+        lda #$00
+        sta $23
+        ldx #$08
+        lsr $21
+bit:    bcc skip
+        clc
+        adc $20
+skip:   ror a
+        ror $22
+        lsr $21
+        dex
+        bne bit
+        sta $23
+        ldx $24
+        rts
+
+factors: .byte 0, 0, 1, 1, 3, 7, 15, 17, 255, 255, 128, 2, 200, 100, 13, 19
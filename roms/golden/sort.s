@@ -0,0 +1,28 @@
+; Bubble sort of 32 bytes in place
+        .org $0400
+        ldx #$00
+copy:   lda data,x
+        sta $0300,x
+        inx
+        cpx #32
+        bne copy
+pass:   ldy #$00
+        ldx #$00
+inner:  lda $0300,x
+        cmp $0301,x
+        bcc next
+        beq next
+        pha
+        lda $0301,x
+        sta $0300,x
+        pla
+        sta $0301,x
+        ldy #$01
+next:   inx
+        cpx #31
+        bne inner
+        cpy #$00
+        bne pass
+done:   jmp done
+data:   .byte $5a, $12, $ff, $00, $80, $7f, $33, $33, $01, $fe, $9c, $44, $21, $6d, $c0, $08
+        .byte $e1, $3b, $90, $0f, $aa, $55, $72, $19, $b4, $2e, $d7, $63, $04, $88, $ca, $10
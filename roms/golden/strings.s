@@ -0,0 +1,52 @@
+; Copies a string through (zp),Y pointers, upper-casing it, then counts its letters;
+; the interrupt handler counts BRKs
+        .org $0400
+        ldx #$ff
+        txs
+        lda #<irq
+        sta $fffe
+        lda #>irq
+        sta $ffff
+        lda #<text
+        sta $10
+        lda #>text
+        sta $11
+        lda #$00
+        sta $12
+        lda #$03
+        sta $13
+        ldy #$00
+copy:   lda ($10),y
+        beq copied
+        cmp #$61
+        bcc store
+        cmp #$7b
+        bcs store
+        and #$df
+store:  sta ($12),y
+        iny
+        bne copy
+copied: sta ($12),y
+        ldx #$00
+        ldy #$00
+        lda #$20
+count:  cmp $0300,y
+        beq space
+        inx
+skip:   iny
+        lda $0300,y
+        bne more
+        stx $20
+        brk
+        .byte $00
+        brk
+        .byte $00
+done:   jmp done
+more:   lda #$20
+        jmp count
+space:  jmp skip
+
+irq:    inc $21
+        rti
+
+text:   .asciiz "the quick brown fox jumps over the lazy dog"
@@ -0,0 +1,236 @@
+//! The emulator for the web: a bare machine behind functions exported from the
+//! WebAssembly module, called from JavaScript through `instance.exports`.
+//!
+//! Build with `cargo build -p emulator-wasm --target wasm32-unknown-unknown --release`,
+//! see `www/index.html` for a page using it. The exports only take and return
+//! numbers, so no bindings are generated: a machine is passed around as the address
+//! of its state in the memory of the module, and programs are copied into a buffer
+//! from `emu_alloc()` before `emu_load()`.
+//!
+//! The JavaScript API with wasm-bindgen classes is blocked on that crate, which
+//! cannot be fetched in this offline tree.
+
+use machine::Machine;
+
+/// The registers `emu_register()` reads, by their number.
+pub const REGISTER_A: u32 = 0;
+pub const REGISTER_X: u32 = 1;
+pub const REGISTER_Y: u32 = 2;
+pub const REGISTER_SP: u32 = 3;
+pub const REGISTER_STATUS: u32 = 4;
+pub const REGISTER_PC: u32 = 5;
+
+/// # Returns
+/// A new machine with 64 KiB of RAM and no devices, to pass to the other functions
+/// and free with `emu_destroy()`. Like the CPU, it must be reset before running.
+#[no_mangle]
+pub extern "C" fn emu_create() -> *mut Machine {
+    Box::into_raw(Box::new(Machine::new()))
+}
+
+/// Frees `emu`.
+///
+/// # Safety
+///
+/// `emu` must come from `emu_create()` and is not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn emu_destroy(emu: *mut Machine) {
+    drop(Box::from_raw(emu));
+}
+
+/// Resets the machine, which starts at its reset vector.
+///
+/// # Safety
+///
+/// `emu` must come from `emu_create()`, here and in every function taking it.
+#[no_mangle]
+pub unsafe extern "C" fn emu_reset(emu: *mut Machine) {
+    (*emu).reset();
+}
+
+/// Halts the CPU whenever a `JMP` or branch jumps to itself if `enabled` is not `0`,
+/// see `Mos6502::set_trap_detection()`. It is off after `emu_create()`.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_trap_detection(emu: *mut Machine, enabled: u32) {
+    (*emu).cpu_mut().set_trap_detection(enabled != 0);
+}
+
+/// Executes one instruction.
+///
+/// # Returns
+/// The cycles it took, `0` if the CPU is halted.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_step(emu: *mut Machine) -> u32 {
+    (*emu).step()
+}
+
+/// Executes instructions until at least `cycles` cycles passed or the CPU halted.
+///
+/// # Returns
+/// The cycles run.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_run(emu: *mut Machine, cycles: u32) -> u32 {
+    let mut run: u32 = 0;
+    while run < cycles {
+        let step_cycles: u32 = (*emu).step();
+        if step_cycles == 0 {
+            break;
+        }
+        run += step_cycles;
+    }
+    run
+}
+
+/// # Returns
+/// `1` if the CPU is halted, after jumping to itself with trap detection on or on an
+/// opcode it cannot execute, `0` if it runs.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_halted(emu: *mut Machine) -> u32 {
+    (*emu).cpu().is_halted() as u32
+}
+
+/// # Returns
+/// The byte at `address`, as the CPU reads it.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_read(emu: *mut Machine, address: u16) -> u8 {
+    (*emu).memory().borrow().read(address)
+}
+
+/// Writes `value` at `address`, as the CPU does.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_write(emu: *mut Machine, address: u16, value: u8) {
+    (*emu).memory().borrow_mut().write(address, value);
+}
+
+/// # Returns
+/// The register numbered `register`, see `REGISTER_A` and the others, or `0` for an
+/// unknown number.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_register(emu: *mut Machine, register: u32) -> u32 {
+    let machine: &Machine = &*emu;
+    match register {
+        REGISTER_A => machine.cpu().a() as u32,
+        REGISTER_X => machine.cpu().x() as u32,
+        REGISTER_Y => machine.cpu().y() as u32,
+        REGISTER_SP => machine.cpu().sp() as u32,
+        REGISTER_STATUS => machine.cpu().status().bits() as u32,
+        REGISTER_PC => machine.cpu().pc() as u32,
+        _ => 0,
+    }
+}
+
+/// Sets PC, to start a program without going through the reset vector.
+///
+/// # Safety
+///
+/// See `emu_reset()`.
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_pc(emu: *mut Machine, pc: u16) {
+    (*emu).cpu_mut().set_pc(pc);
+}
+
+/// # Returns
+/// A buffer of `length` bytes in the memory of the module, for JavaScript to copy a
+/// program into. Free it with `emu_free()`.
+#[no_mangle]
+pub extern "C" fn emu_alloc(length: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; length].into_boxed_slice()) as *mut u8
+}
+
+/// Frees a buffer from `emu_alloc()`.
+///
+/// # Safety
+///
+/// `buffer` and `length` must be those of a call to `emu_alloc()`, and the buffer is
+/// not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn emu_free(buffer: *mut u8, length: usize) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        buffer, length,
+    )));
+}
+
+/// Copies the `length` bytes of `program` into RAM from `address` on, without
+/// touching devices.
+///
+/// # Returns
+/// `0`, or `-1` if the program does not fit in memory.
+///
+/// # Safety
+///
+/// See `emu_reset()`. `program` must point to `length` readable bytes, as a buffer
+/// from `emu_alloc()` does.
+#[no_mangle]
+pub unsafe extern "C" fn emu_load(
+    emu: *mut Machine,
+    address: u16,
+    program: *const u8,
+    length: usize,
+) -> i32 {
+    let start: usize = address as usize;
+    if start + length > memory::MEMORY_SIZE {
+        return -1;
+    }
+    let program: &[u8] = std::slice::from_raw_parts(program, length);
+    (*emu).memory().borrow_mut().as_mut_slice()[start..start + length].copy_from_slice(program);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_program_through_exports() {
+        // LDA #$2A, STA $0200, JMP $0605
+        let program: [u8; 8] = [0xa9, 0x2a, 0x8d, 0x00, 0x02, 0x4c, 0x05, 0x06];
+        unsafe {
+            let emu: *mut Machine = emu_create();
+            let buffer: *mut u8 = emu_alloc(program.len());
+            std::ptr::copy_nonoverlapping(program.as_ptr(), buffer, program.len());
+            assert_eq!(emu_load(emu, 0x0600, buffer, program.len()), 0);
+            assert_eq!(emu_load(emu, 0xfffe, buffer, program.len()), -1);
+            emu_free(buffer, program.len());
+
+            emu_write(emu, 0xfffc, 0x00);
+            emu_write(emu, 0xfffd, 0x06);
+            emu_reset(emu);
+            emu_set_trap_detection(emu, 1);
+            assert_eq!(emu_register(emu, REGISTER_PC), 0x0600);
+            emu_run(emu, 1000);
+
+            assert_eq!(emu_halted(emu), 1);
+            assert_eq!(emu_read(emu, 0x0200), 0x2a);
+            assert_eq!(emu_register(emu, REGISTER_A), 0x2a);
+            emu_destroy(emu);
+        }
+    }
+}